@@ -1,3 +1,3 @@
+pub mod dump;
 pub mod node;
-pub mod statements;
-pub mod expressions;
+pub mod visitor;
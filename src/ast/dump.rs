@@ -0,0 +1,452 @@
+use crate::ast::node::{ Ast, NodeId, NodeKind, AssignTarget, Parameters, BodyStatement };
+use crate::lexer::token::{ Span, Trivia };
+
+/// A node as it appears in a dump: a type name, the span of source it came from if it has one,
+/// and its fields in order. Both output formats are printed from this, so they always agree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dump {
+    pub kind:   &'static str,
+    pub span:   Option<Span>,
+    pub fields: Vec<(&'static str, Value)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An integer too big for `Int`, written out as its digits
+    Digits(String),
+    Float(f64),
+    /// A method, variable or constant name, printed as a symbol in S-expressions
+    Name(String),
+    Str(String),
+    Node(Box<Dump>),
+    List(Vec<Value>),
+}
+
+impl Dump {
+    fn new(kind: &'static str, span: Option<Span>) -> Self {
+        Self { kind, span, fields: vec!() }
+    }
+
+    fn field(mut self, name: &'static str, value: Value) -> Self {
+        self.fields.push((name, value));
+        self
+    }
+}
+
+fn name(name: &str) -> Value {
+    Value::Name(name.to_string())
+}
+
+fn optional_name(name: &Option<String>) -> Value {
+    name.as_ref().map_or(Value::Null, |name| Value::Name(name.clone()))
+}
+
+/// Builds the dump of every node below `statements`.
+pub struct Dumper<'a> {
+    ast: &'a Ast,
+}
+
+impl<'a> Dumper<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Self { ast }
+    }
+
+    pub fn program(&self, statements: &[NodeId]) -> Dump {
+        Dump::new("program", None).field("statements", self.list(statements))
+    }
+
+    pub fn node(&self, id: NodeId) -> Dump {
+        let node = &self.ast[id];
+        let dump = |kind| Dump::new(kind, Some(node.span));
+        match &node.kind {
+            NodeKind::Nil => dump("nil"),
+            NodeKind::Boolean(true) => dump("true"),
+            NodeKind::Boolean(false) => dump("false"),
+            NodeKind::Integer(value) => dump("int").field("value", Value::Int(*value)),
+            NodeKind::BigInteger(digits) => dump("int").field("value", Value::Digits(digits.clone())),
+            NodeKind::Float(value) => dump("float").field("value", Value::Float(*value)),
+            NodeKind::Str(value) => dump("str").field("value", Value::Str(String::from_utf8_lossy(value).into_owned())),
+            NodeKind::InterpolatedStr(parts) => dump("dstr").field("parts", self.list(parts)),
+            NodeKind::InterpolatedRegex(parts, flags) => dump("dregex").field("parts", self.list(parts)).field("flags", Value::Str(flags.clone())),
+            NodeKind::Symbol(value) => dump("sym").field("name", name(value)),
+            NodeKind::Regex(source, flags) => dump("regex").field("source", Value::Str(source.clone())).field("flags", Value::Str(flags.clone())),
+            NodeKind::Array(elements) => dump("array").field("elements", self.list(elements)),
+            NodeKind::Hash(pairs) => {
+                let pairs = pairs.iter().map(|(key, value)| {
+                    Value::Node(Box::new(Dump::new("pair", None).field("key", self.value(*key)).field("value", self.value(*value))))
+                }).collect();
+                dump("hash").field("pairs", Value::List(pairs))
+            },
+            NodeKind::Range(start, end, exclusive) => {
+                dump(if *exclusive { "erange" } else { "irange" }).field("start", self.optional(*start)).field("end", self.optional(*end))
+            },
+            NodeKind::SelfRef => dump("self"),
+            NodeKind::Identifier(value) => dump("ident").field("name", name(value)),
+            NodeKind::Constant(scope, value) => dump("const").field("scope", self.optional(*scope)).field("name", name(value)),
+            NodeKind::InstanceVariable(value) => dump("ivar").field("name", name(value)),
+            NodeKind::ClassVariable(value) => dump("cvar").field("name", name(value)),
+            NodeKind::GlobalVariable(value) => dump("gvar").field("name", name(value)),
+            NodeKind::Assign(target, value) => dump("asgn").field("target", self.target(target)).field("value", self.value(*value)),
+            NodeKind::MultipleAssign(targets, value) => dump("masgn").field("targets", self.targets(targets)).field("value", self.value(*value)),
+            NodeKind::OpAssign(target, op, value) => {
+                dump("op-asgn").field("target", self.target(target)).field("operator", name(op)).field("value", self.value(*value))
+            },
+            NodeKind::Mlhs(targets) => dump("mlhs").field("targets", self.targets(targets)),
+            NodeKind::Splat(value) => dump("splat").field("value", self.optional(*value)),
+            NodeKind::BlockPass(value) => dump("block-pass").field("value", self.optional(*value)),
+            NodeKind::Call(call) => {
+                dump("send")
+                    .field("receiver", self.optional(call.receiver))
+                    .field("method", name(&call.method))
+                    .field("arguments", self.list(&call.arguments))
+                    .field("block", self.optional(call.block))
+            },
+            NodeKind::Block(params, body) => dump("block").field("params", self.params(params)).field("body", self.body(body)),
+            NodeKind::Lambda(params, body) => dump("lambda").field("params", self.params(params)).field("body", self.body(body)),
+            NodeKind::Def(method, params, body) => {
+                dump("def").field("name", name(method)).field("params", self.params(params)).field("body", self.body(body))
+            },
+            NodeKind::SingletonDef(receiver, method, params, body) => {
+                dump("defs")
+                    .field("receiver", self.value(*receiver))
+                    .field("name", name(method))
+                    .field("params", self.params(params))
+                    .field("body", self.body(body))
+            },
+            NodeKind::Class(path, superclass, body) => {
+                dump("class").field("name", self.value(*path)).field("superclass", self.optional(*superclass)).field("body", self.body(body))
+            },
+            NodeKind::SingletonClass(object, body) => dump("sclass").field("object", self.value(*object)).field("body", self.body(body)),
+            NodeKind::Module(path, body) => dump("module").field("name", self.value(*path)).field("body", self.body(body)),
+            NodeKind::Super(Some(arguments), block) => {
+                dump("super").field("arguments", self.list(arguments)).field("block", self.optional(*block))
+            },
+            NodeKind::Super(None, block) => dump("zsuper").field("block", self.optional(*block)),
+            NodeKind::Yield(arguments) => dump("yield").field("arguments", self.list(arguments)),
+            NodeKind::Begin(body) => dump("kwbegin").field("body", self.body(body)),
+            NodeKind::RescueModifier(value, rescue) => dump("rescue-mod").field("value", self.value(*value)).field("rescue", self.value(*rescue)),
+            NodeKind::Retry => dump("retry"),
+            NodeKind::Return(value) => dump("return").field("value", self.optional(*value)),
+            NodeKind::Break(value) => dump("break").field("value", self.optional(*value)),
+            NodeKind::Next(value) => dump("next").field("value", self.optional(*value)),
+            NodeKind::And(left, right) => dump("and").field("left", self.value(*left)).field("right", self.value(*right)),
+            NodeKind::Or(left, right) => dump("or").field("left", self.value(*left)).field("right", self.value(*right)),
+            NodeKind::If(condition, consequence, alternative) => {
+                dump("if").field("condition", self.value(*condition)).field("then", self.list(consequence)).field("else", self.clause(alternative))
+            },
+            NodeKind::Unless(condition, consequence, alternative) => {
+                dump("unless").field("condition", self.value(*condition)).field("then", self.list(consequence)).field("else", self.clause(alternative))
+            },
+            NodeKind::While(condition, body) => dump("while").field("condition", self.value(*condition)).field("body", self.list(body)),
+            NodeKind::Until(condition, body) => dump("until").field("condition", self.value(*condition)).field("body", self.list(body)),
+            NodeKind::Case(case) => {
+                let clauses = case.when_clauses.iter().map(|clause| {
+                    Value::Node(Box::new(Dump::new("when", Some(clause.span)).field("values", self.list(&clause.values)).field("body", self.list(&clause.body))))
+                }).collect();
+                dump("case").field("subject", self.optional(case.subject)).field("when", Value::List(clauses)).field("else", self.clause(&case.else_clause))
+            },
+        }
+    }
+
+    fn value(&self, id: NodeId) -> Value {
+        Value::Node(Box::new(self.node(id)))
+    }
+
+    fn optional(&self, id: Option<NodeId>) -> Value {
+        id.map_or(Value::Null, |id| self.value(id))
+    }
+
+    fn list(&self, ids: &[NodeId]) -> Value {
+        Value::List(ids.iter().map(|id| self.value(*id)).collect())
+    }
+
+    /// An `else` branch, which is `null` when missing rather than an empty list.
+    fn clause(&self, ids: &Option<Vec<NodeId>>) -> Value {
+        ids.as_ref().map_or(Value::Null, |ids| Value::Node(Box::new(Dump::new("else", None).field("body", self.list(ids)))))
+    }
+
+    fn target(&self, target: &AssignTarget) -> Value {
+        let dump = match target {
+            AssignTarget::Local(value) => Dump::new("lvasgn", None).field("name", name(value)),
+            AssignTarget::InstanceVariable(value) => Dump::new("ivasgn", None).field("name", name(value)),
+            AssignTarget::ClassVariable(value) => Dump::new("cvasgn", None).field("name", name(value)),
+            AssignTarget::GlobalVariable(value) => Dump::new("gvasgn", None).field("name", name(value)),
+            AssignTarget::Constant(scope, value) => Dump::new("casgn", None).field("scope", self.optional(*scope)).field("name", name(value)),
+            AssignTarget::Attribute(receiver, value) => Dump::new("attrasgn", None).field("receiver", self.value(*receiver)).field("name", name(value)),
+            AssignTarget::Index(receiver, arguments) => {
+                Dump::new("indexasgn", None).field("receiver", self.value(*receiver)).field("arguments", self.list(arguments))
+            },
+            AssignTarget::Splat(target) => {
+                Dump::new("splat", None).field("target", target.as_ref().map_or(Value::Null, |target| self.target(target)))
+            },
+            AssignTarget::Nested(targets) => Dump::new("mlhs", None).field("targets", self.targets(targets)),
+        };
+
+        Value::Node(Box::new(dump))
+    }
+
+    fn targets(&self, targets: &[AssignTarget]) -> Value {
+        Value::List(targets.iter().map(|target| self.target(target)).collect())
+    }
+
+    fn params(&self, params: &Parameters) -> Value {
+        let mut list = vec!();
+        for required in params.required.iter() {
+            list.push(self.param(required));
+        }
+        for (optional, default) in params.optional.iter() {
+            list.push(Dump::new("optarg", None).field("name", name(optional)).field("default", self.value(*default)));
+        }
+        if let Some(rest) = &params.rest {
+            list.push(Dump::new("restarg", None).field("name", name(rest)));
+        }
+        for post in params.post.iter() {
+            list.push(self.param(post));
+        }
+        if let Some(block) = &params.block {
+            list.push(Dump::new("blockarg", None).field("name", name(block)));
+        }
+        if params.numbered > 0 {
+            list.push(Dump::new("numargs", None).field("count", Value::Int(params.numbered as i64)));
+        }
+        if params.it {
+            list.push(Dump::new("itarg", None));
+        }
+        let list = list.into_iter().map(|dump| Value::Node(Box::new(dump))).collect();
+
+        Value::Node(Box::new(Dump::new("args", None).field("params", Value::List(list))))
+    }
+
+    /// A required parameter: a name, or a pattern destructuring its argument.
+    fn param(&self, target: &AssignTarget) -> Dump {
+        match target {
+            AssignTarget::Local(value) => Dump::new("arg", None).field("name", name(value)),
+            AssignTarget::Nested(targets) => Dump::new("mlhs", None).field("targets", self.targets(targets)),
+            target => unreachable!("{:?} is not a parameter", target),
+        }
+    }
+
+    fn body(&self, body: &BodyStatement) -> Value {
+        let clauses = body.rescue_clauses.iter().map(|clause| {
+            Value::Node(Box::new(Dump::new("resbody", Some(clause.span))
+                .field("classes", self.list(&clause.exception_classes))
+                .field("variable", optional_name(&clause.variable))
+                .field("body", self.list(&clause.body))))
+        }).collect();
+        let clause = |kind, span: Option<Span>, statements: &Option<Vec<NodeId>>| match statements {
+            Some(statements) => Value::Node(Box::new(Dump::new(kind, span).field("body", self.list(statements)))),
+            None => Value::Null,
+        };
+        let dump = Dump::new("body", None)
+            .field("statements", self.list(&body.statements))
+            .field("rescue", Value::List(clauses))
+            .field("else", clause("else", body.else_span, &body.else_clause))
+            .field("ensure", clause("ensure", body.ensure_span, &body.ensure_clause));
+
+        Value::Node(Box::new(dump))
+    }
+}
+
+/// Prints a dump in the style of the `parser` gem: `(type@start..end field...)`, with each
+/// child node on its own indented line. Lists are spliced into their parent and trailing empty
+/// fields are left out.
+pub fn sexp(dump: &Dump) -> String {
+    let mut out = String::new();
+    write_sexp(&mut out, dump, 0);
+    out
+}
+
+fn write_sexp(out: &mut String, dump: &Dump, level: usize) {
+    out.push('(');
+    out.push_str(dump.kind);
+    if let Some(span) = dump.span { *out = format!("{}@{}..{}", out, span.start, span.end); }
+
+    let mut items = vec!();
+    for (_, value) in dump.fields.iter() {
+        match value {
+            Value::List(values) => items.extend(values.iter()),
+            value => items.push(value),
+        }
+    }
+    while let Some(Value::Null) = items.last() { items.pop(); }
+
+    for item in items {
+        match item {
+            Value::Node(child) => {
+                *out = format!("{}\n{}", out, "  ".repeat(level + 1));
+                write_sexp(out, child, level + 1);
+            },
+            Value::Null => out.push_str(" nil"),
+            Value::Bool(value) => *out = format!("{} {}", out, value),
+            Value::Int(value) => *out = format!("{} {}", out, value),
+            Value::Digits(digits) => *out = format!("{} {}", out, digits),
+            Value::Float(value) => *out = format!("{} {:?}", out, value),
+            Value::Name(name) => *out = format!("{} :{}", out, name),
+            Value::Str(value) => *out = format!("{} {:?}", out, value),
+            Value::List(_) => (),
+        }
+    }
+    out.push(')');
+}
+
+/// Prints a dump as JSON. Every node is an object with its `type`, its `span` as
+/// `[start, end]` byte offsets when it has one, and its fields by name, in a fixed order.
+pub fn json(dump: &Dump) -> String {
+    let mut out = String::new();
+    write_json_node(&mut out, dump, 0);
+    out
+}
+
+/// Prints the program dump as JSON with its comments in a `comments` field.
+pub fn json_with_comments(dump: &Dump, trivia: &[Trivia]) -> String {
+    let comments = trivia.iter().filter_map(|trivia| match trivia {
+        Trivia::Comment { span, text, .. } => Some(Value::Node(Box::new(Dump::new("comment", Some(*span)).field("text", Value::Str(text.clone()))))),
+        Trivia::BlankLine(_) => None,
+    }).collect();
+
+    json(&dump.clone().field("comments", Value::List(comments)))
+}
+
+fn write_json_node(out: &mut String, dump: &Dump, level: usize) {
+    let inner = "  ".repeat(level + 1);
+    *out = format!("{}{{\n{}\"type\": {}", out, inner, json_string(dump.kind));
+    if let Some(span) = dump.span {
+        *out = format!("{},\n{}\"span\": [{}, {}]", out, inner, span.start, span.end);
+    }
+    for (name, value) in dump.fields.iter() {
+        *out = format!("{},\n{}{}: ", out, inner, json_string(name));
+        write_json_value(out, value, level + 1);
+    }
+    *out = format!("{}\n{}}}", out, "  ".repeat(level));
+}
+
+fn write_json_value(out: &mut String, value: &Value, level: usize) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(&value.to_string()),
+        Value::Int(value) => out.push_str(&value.to_string()),
+        Value::Digits(digits) => out.push_str(digits),
+        Value::Float(value) if value.is_finite() => out.push_str(&format!("{:?}", value)),
+        Value::Float(_) => out.push_str("null"),
+        Value::Name(value) | Value::Str(value) => out.push_str(&json_string(value)),
+        Value::Node(dump) => write_json_node(out, dump, level),
+        Value::List(values) if values.is_empty() => out.push_str("[]"),
+        Value::List(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 { out.push(','); }
+                *out = format!("{}\n{}", out, "  ".repeat(level + 1));
+                write_json_value(out, value, level + 1);
+            }
+            *out = format!("{}\n{}]", out, "  ".repeat(level));
+        },
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"'  => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::program::Program;
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let mut lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+        assert!(errors.is_empty(), "parser errors: {:?}", errors);
+
+        program
+    }
+
+    #[test]
+    fn should_dump_s_expressions_with_spans() {
+        let program = parse("x = 1 + y\nputs(x)");
+
+        assert_eq!(program.to_sexp(), "(program
+  (asgn@0..9
+    (lvasgn :x)
+    (send@4..9
+      (int@4..5 1) :+
+      (ident@8..9 :y)))
+  (send@10..17 nil :puts
+    (ident@15..16 :x)))");
+    }
+
+    #[test]
+    fn should_dump_bodies_params_and_targets() {
+        let program = parse("def go(a, b = 2, *r, &blk)\n  retry\nrescue Err => e\nensure\nend");
+
+        assert_eq!(program.to_sexp(), "(program
+  (def@0..61 :go
+    (args
+      (arg :a)
+      (optarg :b
+        (int@14..15 2))
+      (restarg :r)
+      (blockarg :blk))
+    (body
+      (retry@29..34)
+      (resbody@35..50
+        (const@42..45 nil :Err) :e) nil
+      (ensure@51..57))))");
+    }
+
+    #[test]
+    fn should_dump_json() {
+        let program = parse("say 'hi' # greet");
+
+        assert_eq!(program.to_json(), r##"{
+  "type": "program",
+  "statements": [
+    {
+      "type": "send",
+      "span": [0, 8],
+      "receiver": null,
+      "method": "say",
+      "arguments": [
+        {
+          "type": "str",
+          "span": [4, 8],
+          "value": "hi"
+        }
+      ],
+      "block": null
+    }
+  ],
+  "comments": [
+    {
+      "type": "comment",
+      "span": [9, 16],
+      "text": "# greet"
+    }
+  ]
+}"##);
+    }
+
+    #[test]
+    fn should_escape_json_strings() {
+        assert_eq!(json_string("a \"b\"\n\\\u{1}"), "\"a \\\"b\\\"\\n\\\\\\u0001\"");
+    }
+}
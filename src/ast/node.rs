@@ -1,16 +1,590 @@
-use crate::lexer::token::Token;
+use std::ops::{ Index, IndexMut };
 
-#[derive(Clone, Debug)]
-pub enum Node<'a> {
-    Identifier(Token, String),
-    NumberNode(Token),
-    BinOpNode(&'a Node<'a>, &'a Node<'a>, Token)
+use crate::lexer::token::Span;
+
+/// Identifies a node within the `Ast` that owns it. Ids are dense, so later passes can keep
+/// their own per-node annotations in a `Vec` indexed by `NodeId::index`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub id:   NodeId,
+    pub span: Span,
+    pub kind: NodeKind,
+}
+
+/// Every kind of expression and statement. Children are referenced by id and live in the same
+/// `Ast`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeKind {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    /// An integer literal too big for `i64`, as its decimal digits
+    BigInteger(String),
+    Float(f64),
+    /// A string literal's contents, which needn't be valid UTF-8
+    Str(Vec<u8>),
+    /// `"text #{code} text"`: `Str` nodes for the text, and a `Begin` node for each `#{}`
+    InterpolatedStr(Vec<NodeId>),
+    Symbol(String),
+    /// `/source/flags`
+    Regex(String, String),
+    /// `/source #{code}/flags`, with its parts like an `InterpolatedStr`
+    InterpolatedRegex(Vec<NodeId>, String),
+    Array(Vec<NodeId>),
+    Hash(Vec<(NodeId, NodeId)>),
+    /// `start..end`, or `start...end` when exclusive. Either end may be left out.
+    Range(Option<NodeId>, Option<NodeId>, bool),
+    SelfRef,
+    /// A local variable, or a call to a method with no receiver and no arguments
+    Identifier(String),
+    /// `Name` or `scope::Name`
+    Constant(Option<NodeId>, String),
+    InstanceVariable(String),
+    ClassVariable(String),
+    GlobalVariable(String),
+    Assign(AssignTarget, NodeId),
+    MultipleAssign(Vec<AssignTarget>, NodeId),
+    /// `target op= value`, where `op` is a binary operator like `+` or one of `||` and `&&`
+    OpAssign(AssignTarget, String, NodeId),
+    /// A parenthesized `(a, b)` list of targets, only valid on the left of an assignment
+    Mlhs(Vec<AssignTarget>),
+    /// `*expr` in an argument list, array or assignment, or a bare `*`
+    Splat(Option<NodeId>),
+    /// `&blk` or `&:sym` passed in place of a literal block, or a bare `&` forwarding one
+    BlockPass(Option<NodeId>),
+    Call(Call),
+    Block(Parameters, BodyStatement),
+    /// `->(params) { body }`
+    Lambda(Parameters, BodyStatement),
+    Def(String, Parameters, BodyStatement),
+    /// `def self.name` or `def object.name`, defining a method on the receiver's singleton class
+    SingletonDef(NodeId, String, Parameters, BodyStatement),
+    /// `class Name < Superclass`, where the name is a constant node
+    Class(NodeId, Option<NodeId>, BodyStatement),
+    /// `class << object`
+    SingletonClass(NodeId, BodyStatement),
+    Module(NodeId, BodyStatement),
+    /// `super(args)`, or a bare `super` passing on the method's own arguments when there's no
+    /// argument list, with an optional block
+    Super(Option<Vec<NodeId>>, Option<NodeId>),
+    /// `yield args`, calling the block of the current method
+    Yield(Vec<NodeId>),
+    Begin(BodyStatement),
+    RescueModifier(NodeId, NodeId),
+    Retry,
+    Return(Option<NodeId>),
+    Break(Option<NodeId>),
+    Next(Option<NodeId>),
+    /// `a && b` or `a and b`
+    And(NodeId, NodeId),
+    /// `a || b` or `a or b`
+    Or(NodeId, NodeId),
+    /// `if`, a modifier `if` or a ternary: the condition, then and else branches. An `elsif` is a
+    /// nested `If` making up the whole else branch.
+    If(NodeId, Vec<NodeId>, Option<Vec<NodeId>>),
+    Unless(NodeId, Vec<NodeId>, Option<Vec<NodeId>>),
+    While(NodeId, Vec<NodeId>),
+    Until(NodeId, Vec<NodeId>),
+    Case(Case),
+}
+
+/// The left-hand side of an assignment. Attribute and index targets become calls to `name=`
+/// and `[]=` when evaluated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssignTarget {
+    Local(String),
+    InstanceVariable(String),
+    ClassVariable(String),
+    GlobalVariable(String),
+    Constant(Option<NodeId>, String),
+    Attribute(NodeId, String),
+    Index(NodeId, Vec<NodeId>),
+    /// `*rest` in a multiple assignment, or a bare `*` / trailing comma that discards values
+    Splat(Option<Box<AssignTarget>>),
+    /// A parenthesized `(a, b)` destructuring target
+    Nested(Vec<AssignTarget>),
+}
+
+/// A method call: `receiver.method(arguments) { block }`. Operators are calls too, so `a + b`
+/// is a call to `+` on `a`. The block is either a `Block` or a `BlockPass` node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+    pub receiver:  Option<NodeId>,
+    pub method:    String,
+    pub arguments: Vec<NodeId>,
+    pub block:     Option<NodeId>,
+}
+
+/// A parameter list, which Ruby only allows in the order of these fields: `a, b = 1, *r, c, &blk`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parameters {
+    /// Names, or in a block also `Nested` patterns destructuring the argument
+    pub required: Vec<AssignTarget>,
+    pub optional: Vec<(String, NodeId)>,
+    pub rest:     Option<String>,
+    /// The required parameters after the optional ones or the rest, filled from the end
+    pub post:     Vec<AssignTarget>,
+    pub block:    Option<String>,
+    /// The highest numbered parameter (`_1` to `_9`) used by a block without explicit parameters
+    pub numbered: usize,
+    /// Whether a block without explicit parameters refers to its argument as `it`
+    pub it:       bool,
+}
+
+/// The body of a `begin`, `def` or `do` block, which may carry its own `rescue`, `else` and
+/// `ensure` clauses without an explicit `begin`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BodyStatement {
+    pub statements:     Vec<NodeId>,
+    pub rescue_clauses: Vec<RescueClause>,
+    pub else_clause:    Option<Vec<NodeId>>,
+    pub ensure_clause:  Option<Vec<NodeId>>,
+    /// Where the `else` and `ensure` keywords are, when the clauses are present
+    pub else_span:      Option<Span>,
+    pub ensure_span:    Option<Span>,
+}
+
+/// `rescue TypeA, TypeB => e`. An empty list of exception classes rescues `StandardError`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RescueClause {
+    pub span:              Span,
+    pub exception_classes: Vec<NodeId>,
+    pub variable:          Option<String>,
+    pub body:              Vec<NodeId>,
+}
+
+/// `case subject when ... else ... end`. Without a subject each `when` value is tested for
+/// truthiness instead of with `===`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub subject:      Option<NodeId>,
+    pub when_clauses: Vec<WhenClause>,
+    pub else_clause:  Option<Vec<NodeId>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhenClause {
+    pub span:   Span,
+    pub values: Vec<NodeId>,
+    pub body:   Vec<NodeId>,
+}
+
+impl Parameters {
+    /// Whether the parameters were declared without any explicit names.
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.optional.is_empty() && self.rest.is_none() && self.block.is_none()
+    }
+}
+
+/// Owns every node of a parsed program, along with where the program came from so spans
+/// can be turned into `file:line` positions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ast {
+    nodes:    Vec<Node>,
+    file:     String,
+    /// The byte offset of every newline in the source
+    newlines: Vec<usize>,
+    /// Set by a `# frozen_string_literal: true` comment before the code
+    frozen_string_literal: bool,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Self { nodes: vec!(), file: String::new(), newlines: vec!(), frozen_string_literal: false }
+    }
+
+    /// The file the program was read from, `-` when it wasn't given a name.
+    pub fn file(&self) -> &str {
+        if self.file.is_empty() { "-" } else { &self.file }
+    }
+
+    pub fn set_file(&mut self, file: &str) {
+        self.file = file.to_string();
+    }
+
+    /// Whether string literals make frozen strings.
+    pub fn frozen_string_literal(&self) -> bool {
+        self.frozen_string_literal
+    }
+
+    pub fn set_frozen_string_literal(&mut self, frozen: bool) {
+        self.frozen_string_literal = frozen;
+    }
+
+    /// Remembers where the lines of `source` start, for `line`.
+    pub fn index_lines(&mut self, source: &str) {
+        self.newlines = source.bytes().enumerate().filter(|(_, byte)| *byte == b'\n').map(|(offset, _)| offset).collect();
+    }
+
+    /// The 1-based line of a byte offset in the source.
+    pub fn line(&self, offset: usize) -> usize {
+        self.newlines.partition_point(|newline| *newline < offset) + 1
+    }
+
+    pub fn push(&mut self, kind: NodeKind, span: Span) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(Node { id, span, kind });
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter()
+    }
+
+    pub fn to_s(&self, id: NodeId) -> String {
+        match &self[id].kind {
+            NodeKind::Nil => String::from("nil"),
+            NodeKind::Boolean(value) => value.to_string(),
+            NodeKind::Integer(value) => value.to_string(),
+            NodeKind::BigInteger(digits) => digits.clone(),
+            NodeKind::Float(value) => format!("{:?}", value),
+            NodeKind::Str(value) => format!("{:?}", String::from_utf8_lossy(value)),
+            NodeKind::InterpolatedStr(parts) => {
+                format!("\"{}\"", self.parts_to_s(parts, |text| String::from_utf8_lossy(text).escape_debug().to_string()))
+            },
+            NodeKind::Symbol(name) => format!(":{}", name),
+            NodeKind::Regex(source, flags) => format!("/{}/{}", source, flags),
+            NodeKind::InterpolatedRegex(parts, flags) => {
+                format!("/{}/{}", self.parts_to_s(parts, |text| String::from_utf8_lossy(text).to_string()), flags)
+            },
+            NodeKind::Array(elements) => format!("[{}]", self.list_to_s(elements)),
+            NodeKind::Hash(pairs) => {
+                let pairs: Vec<String> = pairs.iter().map(|(key, value)| format!("{} => {}", self.to_s(*key), self.to_s(*value))).collect();
+                format!("{{{}}}", pairs.join(", "))
+            },
+            NodeKind::Range(start, end, exclusive) => format!("({}{}{})",
+                start.map_or(String::new(), |start| self.to_s(start)),
+                if *exclusive { "..." } else { ".." },
+                end.map_or(String::new(), |end| self.to_s(end))),
+            NodeKind::SelfRef => String::from("self"),
+            NodeKind::Identifier(name) => name.to_string(),
+            NodeKind::Constant(Some(scope), name) => format!("{}::{}", self.to_s(*scope), name),
+            NodeKind::Constant(None, name) => name.to_string(),
+            NodeKind::InstanceVariable(name) => name.to_string(),
+            NodeKind::ClassVariable(name) => name.to_string(),
+            NodeKind::GlobalVariable(name) => name.to_string(),
+            NodeKind::Assign(target, value) => format!("{} = {}\n", self.target_to_s(target), self.to_s(*value)),
+            NodeKind::MultipleAssign(targets, value) => format!("{} = {}\n", self.targets_to_s(targets), self.to_s(*value)),
+            NodeKind::OpAssign(target, op, value) => format!("{} {}= {}\n", self.target_to_s(target), op, self.to_s(*value)),
+            NodeKind::Mlhs(targets) => format!("({})", self.targets_to_s(targets)),
+            NodeKind::Splat(Some(value)) => format!("*{}", self.to_s(*value)),
+            NodeKind::Splat(None) => String::from("*"),
+            NodeKind::BlockPass(Some(value)) => format!("&{}", self.to_s(*value)),
+            NodeKind::BlockPass(None) => String::from("&"),
+            NodeKind::Call(call) => self.call_to_s(call),
+            NodeKind::Block(params, body) => {
+                if params.is_empty() {
+                    format!("do\n{}end", self.body_to_s(body))
+                } else {
+                    format!("do |{}|\n{}end", self.params_to_s(params), self.body_to_s(body))
+                }
+            },
+            NodeKind::Lambda(params, body) => format!("->({}) {{\n{}}}", self.params_to_s(params), self.body_to_s(body)),
+            NodeKind::Def(name, params, body) => format!("def {}({})\n{}end\n", name, self.params_to_s(params), self.body_to_s(body)),
+            NodeKind::SingletonDef(receiver, name, params, body) => {
+                format!("def {}.{}({})\n{}end\n", self.to_s(*receiver), name, self.params_to_s(params), self.body_to_s(body))
+            },
+            NodeKind::Class(name, Some(superclass), body) => {
+                format!("class {} < {}\n{}end\n", self.to_s(*name), self.to_s(*superclass), self.body_to_s(body))
+            },
+            NodeKind::Class(name, None, body) => format!("class {}\n{}end\n", self.to_s(*name), self.body_to_s(body)),
+            NodeKind::SingletonClass(object, body) => format!("class << {}\n{}end\n", self.to_s(*object), self.body_to_s(body)),
+            NodeKind::Module(name, body) => format!("module {}\n{}end\n", self.to_s(*name), self.body_to_s(body)),
+            NodeKind::Super(Some(arguments), block) => {
+                let block = block.map_or(String::new(), |block| format!(" {}", self.to_s(block)));
+                format!("super({}){}", self.list_to_s(arguments), block)
+            },
+            NodeKind::Super(None, Some(block)) => format!("super {}", self.to_s(*block)),
+            NodeKind::Super(None, None) => String::from("super"),
+            NodeKind::Yield(arguments) => format!("yield({})", self.list_to_s(arguments)),
+            NodeKind::Begin(body) => format!("begin\n{}end\n", self.body_to_s(body)),
+            NodeKind::RescueModifier(value, rescue) => format!("{} rescue {}", self.to_s(*value).trim_end(), self.to_s(*rescue)),
+            NodeKind::Retry => String::from("retry\n"),
+            NodeKind::Return(Some(value)) => format!("return {}\n", self.to_s(*value)),
+            NodeKind::Return(None) => String::from("return\n"),
+            NodeKind::Break(Some(value)) => format!("break {}\n", self.to_s(*value)),
+            NodeKind::Break(None) => String::from("break\n"),
+            NodeKind::Next(Some(value)) => format!("next {}\n", self.to_s(*value)),
+            NodeKind::Next(None) => String::from("next\n"),
+            NodeKind::And(left, right) => format!("({} && {})", self.to_s(*left), self.to_s(*right)),
+            NodeKind::Or(left, right) => format!("({} || {})", self.to_s(*left), self.to_s(*right)),
+            NodeKind::If(condition, consequence, alternative) => self.conditional_to_s("if", *condition, consequence, alternative),
+            NodeKind::Unless(condition, consequence, alternative) => self.conditional_to_s("unless", *condition, consequence, alternative),
+            NodeKind::While(condition, body) => format!("while {}\n{}end\n", self.to_s(*condition), self.statements_to_s(body)),
+            NodeKind::Until(condition, body) => format!("until {}\n{}end\n", self.to_s(*condition), self.statements_to_s(body)),
+            NodeKind::Case(case) => {
+                let mut string = match case.subject {
+                    Some(subject) => format!("case {}\n", self.to_s(subject)),
+                    None => String::from("case\n"),
+                };
+                for clause in case.when_clauses.iter() {
+                    string += &format!("when {}\n{}", self.list_to_s(&clause.values), self.statements_to_s(&clause.body));
+                }
+                if let Some(else_clause) = &case.else_clause {
+                    string += &format!("else\n{}", self.statements_to_s(else_clause));
+                }
+
+                string + "end\n"
+            },
+        }
+    }
+
+    /// Whether the tree under `id` and the tree under `other_id` in `other` are the same,
+    /// ignoring spans and ids.
+    pub fn same_shape(&self, id: NodeId, other: &Ast, other_id: NodeId) -> bool {
+        let same = |a: &NodeId, b: &NodeId| self.same_shape(*a, other, *b);
+        let same_clause = |a: &Option<Vec<NodeId>>, b: &Option<Vec<NodeId>>| match (a, b) {
+            (Some(a), Some(b)) => self.same_list(a, other, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        match (&self[id].kind, &other[other_id].kind) {
+            (NodeKind::Array(a), NodeKind::Array(b)) |
+            (NodeKind::InterpolatedStr(a), NodeKind::InterpolatedStr(b)) => self.same_list(a, other, b),
+            (NodeKind::InterpolatedRegex(a, f), NodeKind::InterpolatedRegex(b, g)) => f == g && self.same_list(a, other, b),
+            (NodeKind::Hash(a), NodeKind::Hash(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|((k, v), (l, w))| same(k, l) && same(v, w))
+            },
+            (NodeKind::Range(a, x, e), NodeKind::Range(b, y, f)) => {
+                e == f && self.same_optional(*a, other, *b) && self.same_optional(*x, other, *y)
+            },
+            (NodeKind::Constant(a, x), NodeKind::Constant(b, y)) => x == y && self.same_optional(*a, other, *b),
+            (NodeKind::Assign(t, a), NodeKind::Assign(u, b)) => self.same_target(t, other, u) && same(a, b),
+            (NodeKind::MultipleAssign(t, a), NodeKind::MultipleAssign(u, b)) => self.same_targets(t, other, u) && same(a, b),
+            (NodeKind::OpAssign(t, x, a), NodeKind::OpAssign(u, y, b)) => x == y && self.same_target(t, other, u) && same(a, b),
+            (NodeKind::Mlhs(t), NodeKind::Mlhs(u)) => self.same_targets(t, other, u),
+            (NodeKind::Splat(a), NodeKind::Splat(b)) |
+            (NodeKind::BlockPass(a), NodeKind::BlockPass(b)) |
+            (NodeKind::Return(a), NodeKind::Return(b)) |
+            (NodeKind::Break(a), NodeKind::Break(b)) |
+            (NodeKind::Next(a), NodeKind::Next(b)) => self.same_optional(*a, other, *b),
+            (NodeKind::And(a, x), NodeKind::And(b, y)) |
+            (NodeKind::Or(a, x), NodeKind::Or(b, y)) => same(a, b) && same(x, y),
+            (NodeKind::If(a, x, e), NodeKind::If(b, y, f)) |
+            (NodeKind::Unless(a, x, e), NodeKind::Unless(b, y, f)) => {
+                same(a, b) && self.same_list(x, other, y) && same_clause(e, f)
+            },
+            (NodeKind::While(a, x), NodeKind::While(b, y)) |
+            (NodeKind::Until(a, x), NodeKind::Until(b, y)) => same(a, b) && self.same_list(x, other, y),
+            (NodeKind::Case(a), NodeKind::Case(b)) => {
+                self.same_optional(a.subject, other, b.subject) && same_clause(&a.else_clause, &b.else_clause) &&
+                    a.when_clauses.len() == b.when_clauses.len() &&
+                    a.when_clauses.iter().zip(b.when_clauses.iter())
+                        .all(|(a, b)| self.same_list(&a.values, other, &b.values) && self.same_list(&a.body, other, &b.body))
+            },
+            (NodeKind::Call(a), NodeKind::Call(b)) => {
+                a.method == b.method && self.same_optional(a.receiver, other, b.receiver) &&
+                    self.same_list(&a.arguments, other, &b.arguments) && self.same_optional(a.block, other, b.block)
+            },
+            (NodeKind::Block(p, a), NodeKind::Block(q, b)) |
+            (NodeKind::Lambda(p, a), NodeKind::Lambda(q, b)) => self.same_params(p, other, q) && self.same_body(a, other, b),
+            (NodeKind::Def(x, p, a), NodeKind::Def(y, q, b)) => x == y && self.same_params(p, other, q) && self.same_body(a, other, b),
+            (NodeKind::SingletonDef(r, x, p, a), NodeKind::SingletonDef(s, y, q, b)) => {
+                x == y && same(r, s) && self.same_params(p, other, q) && self.same_body(a, other, b)
+            },
+            (NodeKind::Class(x, c, a), NodeKind::Class(y, d, b)) => {
+                same(x, y) && self.same_optional(*c, other, *d) && self.same_body(a, other, b)
+            },
+            (NodeKind::SingletonClass(x, a), NodeKind::SingletonClass(y, b)) |
+            (NodeKind::Module(x, a), NodeKind::Module(y, b)) => same(x, y) && self.same_body(a, other, b),
+            (NodeKind::Super(x, a), NodeKind::Super(y, b)) => same_clause(x, y) && self.same_optional(*a, other, *b),
+            (NodeKind::Yield(a), NodeKind::Yield(b)) => self.same_list(a, other, b),
+            (NodeKind::Begin(a), NodeKind::Begin(b)) => self.same_body(a, other, b),
+            (NodeKind::RescueModifier(a, x), NodeKind::RescueModifier(b, y)) => same(a, b) && same(x, y),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Whether two statement or argument lists are the same, ignoring spans and ids.
+    pub fn same_list(&self, ids: &[NodeId], other: &Ast, other_ids: &[NodeId]) -> bool {
+        ids.len() == other_ids.len() && ids.iter().zip(other_ids).all(|(a, b)| self.same_shape(*a, other, *b))
+    }
+
+    fn same_optional(&self, id: Option<NodeId>, other: &Ast, other_id: Option<NodeId>) -> bool {
+        match (id, other_id) {
+            (Some(a), Some(b)) => self.same_shape(a, other, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+
+    fn same_target(&self, target: &AssignTarget, other: &Ast, other_target: &AssignTarget) -> bool {
+        match (target, other_target) {
+            (AssignTarget::Constant(a, x), AssignTarget::Constant(b, y)) => x == y && self.same_optional(*a, other, *b),
+            (AssignTarget::Attribute(a, x), AssignTarget::Attribute(b, y)) => x == y && self.same_shape(*a, other, *b),
+            (AssignTarget::Index(a, x), AssignTarget::Index(b, y)) => self.same_shape(*a, other, *b) && self.same_list(x, other, y),
+            (AssignTarget::Splat(Some(a)), AssignTarget::Splat(Some(b))) => self.same_target(a, other, b),
+            (AssignTarget::Nested(a), AssignTarget::Nested(b)) => self.same_targets(a, other, b),
+            (a, b) => a == b,
+        }
+    }
+
+    fn same_targets(&self, targets: &[AssignTarget], other: &Ast, other_targets: &[AssignTarget]) -> bool {
+        targets.len() == other_targets.len() && targets.iter().zip(other_targets).all(|(a, b)| self.same_target(a, other, b))
+    }
+
+    fn same_params(&self, params: &Parameters, other: &Ast, other_params: &Parameters) -> bool {
+        params.required == other_params.required && params.rest == other_params.rest &&
+            params.block == other_params.block && params.numbered == other_params.numbered &&
+            params.it == other_params.it && params.optional.len() == other_params.optional.len() &&
+            params.optional.iter().zip(other_params.optional.iter())
+                .all(|((x, a), (y, b))| x == y && self.same_shape(*a, other, *b))
+    }
+
+    fn same_body(&self, body: &BodyStatement, other: &Ast, other_body: &BodyStatement) -> bool {
+        let same_clause = |a: &Option<Vec<NodeId>>, b: &Option<Vec<NodeId>>| match (a, b) {
+            (Some(a), Some(b)) => self.same_list(a, other, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+
+        self.same_list(&body.statements, other, &other_body.statements) &&
+            body.rescue_clauses.len() == other_body.rescue_clauses.len() &&
+            body.rescue_clauses.iter().zip(other_body.rescue_clauses.iter()).all(|(a, b)| {
+                a.variable == b.variable && self.same_list(&a.exception_classes, other, &b.exception_classes) &&
+                    self.same_list(&a.body, other, &b.body)
+            }) &&
+            same_clause(&body.else_clause, &other_body.else_clause) &&
+            same_clause(&body.ensure_clause, &other_body.ensure_clause)
+    }
+
+    pub fn target_to_s(&self, target: &AssignTarget) -> String {
+        match target {
+            AssignTarget::Local(name) => name.to_string(),
+            AssignTarget::InstanceVariable(name) => name.to_string(),
+            AssignTarget::ClassVariable(name) => name.to_string(),
+            AssignTarget::GlobalVariable(name) => name.to_string(),
+            AssignTarget::Constant(Some(scope), name) => format!("{}::{}", self.to_s(*scope), name),
+            AssignTarget::Constant(None, name) => name.to_string(),
+            AssignTarget::Attribute(receiver, name) => format!("{}.{}", self.to_s(*receiver), name),
+            AssignTarget::Index(receiver, args) => format!("{}[{}]", self.to_s(*receiver), self.list_to_s(args)),
+            AssignTarget::Splat(Some(target)) => format!("*{}", self.target_to_s(target)),
+            AssignTarget::Splat(None) => String::from("*"),
+            AssignTarget::Nested(targets) => format!("({})", self.targets_to_s(targets)),
+        }
+    }
+
+    /// The parts of an interpolated string or regexp, with each piece of text as `text` prints it.
+    fn parts_to_s(&self, parts: &[NodeId], text: fn(&[u8]) -> String) -> String {
+        let parts: Vec<String> = parts.iter().map(|part| match &self[*part].kind {
+            NodeKind::Str(value) => text(value),
+            NodeKind::Begin(body) => {
+                let statements: Vec<String> = body.statements.iter().map(|id| self.to_s(*id).trim_end().to_string()).collect();
+                format!("#{{{}}}", statements.join("; "))
+            },
+            _ => self.to_s(*part),
+        }).collect();
+        parts.concat()
+    }
+
+    pub fn params_to_s(&self, params: &Parameters) -> String {
+        let mut strings: Vec<String> = params.required.iter().map(|target| self.target_to_s(target)).collect();
+        for (name, default) in params.optional.iter() {
+            strings.push(format!("{} = {}", name, self.to_s(*default)));
+        }
+        match params.rest.as_deref() {
+            Some("*") => strings.push(String::from("*")),
+            Some(rest) => strings.push(format!("*{}", rest)),
+            None => ()
+        }
+        strings.extend(params.post.iter().map(|target| self.target_to_s(target)));
+        match params.block.as_deref() {
+            Some("&") => strings.push(String::from("&")),
+            Some(block) => strings.push(format!("&{}", block)),
+            None => ()
+        }
+
+        strings.join(", ")
+    }
+
+    pub fn body_to_s(&self, body: &BodyStatement) -> String {
+        let mut string = self.statements_to_s(&body.statements);
+        for clause in body.rescue_clauses.iter() {
+            string += "rescue";
+            if !clause.exception_classes.is_empty() {
+                string = format!("{} {}", string, self.list_to_s(&clause.exception_classes));
+            }
+            if let Some(variable) = &clause.variable { string = format!("{} => {}", string, variable); }
+            string = format!("{}\n{}", string, self.statements_to_s(&clause.body));
+        }
+        if let Some(else_clause) = &body.else_clause {
+            string = format!("{}else\n{}", string, self.statements_to_s(else_clause));
+        }
+        if let Some(ensure_clause) = &body.ensure_clause {
+            string = format!("{}ensure\n{}", string, self.statements_to_s(ensure_clause));
+        }
+
+        string
+    }
+
+    fn conditional_to_s(&self, keyword: &str, condition: NodeId, consequence: &[NodeId], alternative: &Option<Vec<NodeId>>) -> String {
+        let mut string = format!("{} {}\n{}", keyword, self.to_s(condition), self.statements_to_s(consequence));
+        if let Some(alternative) = alternative {
+            string += &format!("else\n{}", self.statements_to_s(alternative));
+        }
+
+        string + "end\n"
+    }
+
+    fn call_to_s(&self, call: &Call) -> String {
+        let args = self.list_to_s(&call.arguments);
+        let mut string = match call.receiver {
+            Some(receiver) if call.method == "[]" => format!("{}[{}]", self.to_s(receiver), args),
+            Some(receiver) => format!("{}.{}", self.to_s(receiver), call.method),
+            None => call.method.clone(),
+        };
+        if !args.is_empty() && call.method != "[]" { string = format!("{}({})", string, args); }
+        if let Some(block) = call.block { string = format!("{} {}", string, self.to_s(block)); }
+
+        string
+    }
+
+    fn list_to_s(&self, ids: &[NodeId]) -> String {
+        let strings: Vec<String> = ids.iter().map(|id| self.to_s(*id)).collect();
+        strings.join(", ")
+    }
+
+    fn targets_to_s(&self, targets: &[AssignTarget]) -> String {
+        let strings: Vec<String> = targets.iter().map(|target| self.target_to_s(target)).collect();
+        strings.join(", ")
+    }
+
+    pub fn statements_to_s(&self, statements: &[NodeId]) -> String {
+        let mut string = String::from("");
+        for statement in statements.iter() {
+            let line = self.to_s(*statement);
+            string += &line;
+            if !line.ends_with('\n') { string.push('\n'); }
+        }
+
+        string
+    }
+}
+
+impl Index<NodeId> for Ast {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
 }
 
-pub fn token_literal(node: &Node) -> String {
-    match node {
-        Node::Identifier(token, _) => token.literal.clone(),
-        Node::NumberNode(token) => token.literal.clone(),
-        Node::BinOpNode(_, _, token) => token.literal.clone(),
+impl IndexMut<NodeId> for Ast {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.index()]
     }
 }
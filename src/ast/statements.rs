@@ -1,4 +0,0 @@
-use crate::lexer::token::Token;
-
-pub enum Statement {
-}
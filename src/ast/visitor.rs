@@ -0,0 +1,304 @@
+use crate::ast::node::{ Ast, NodeId, NodeKind, AssignTarget, Call, Parameters, BodyStatement, RescueClause };
+
+/// Walks an `Ast` read-only. Every method defaults to visiting the children of what it is
+/// given, so a pass only overrides the methods for the nodes it cares about and calls the
+/// matching `walk_*` function when it still wants to descend into them.
+pub trait Visitor {
+    fn visit_node(&mut self, ast: &Ast, id: NodeId) {
+        walk_node(self, ast, id);
+    }
+
+    fn visit_call(&mut self, ast: &Ast, call: &Call) {
+        walk_call(self, ast, call);
+    }
+
+    fn visit_target(&mut self, ast: &Ast, target: &AssignTarget) {
+        walk_target(self, ast, target);
+    }
+
+    fn visit_parameters(&mut self, ast: &Ast, params: &Parameters) {
+        walk_parameters(self, ast, params);
+    }
+
+    fn visit_body(&mut self, ast: &Ast, body: &BodyStatement) {
+        walk_body(self, ast, body);
+    }
+
+    fn visit_rescue_clause(&mut self, ast: &Ast, clause: &RescueClause) {
+        walk_rescue_clause(self, ast, clause);
+    }
+}
+
+/// Visits the children of a node in source order.
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, id: NodeId) {
+    match &ast[id].kind {
+        NodeKind::Nil | NodeKind::Boolean(_) | NodeKind::Integer(_) | NodeKind::BigInteger(_) | NodeKind::Float(_) | NodeKind::Str(_) |
+        NodeKind::Symbol(_) | NodeKind::Regex(..) | NodeKind::SelfRef | NodeKind::Identifier(_) | NodeKind::InstanceVariable(_) |
+        NodeKind::ClassVariable(_) | NodeKind::GlobalVariable(_) | NodeKind::Retry => (),
+        NodeKind::Array(elements) | NodeKind::InterpolatedStr(elements) | NodeKind::InterpolatedRegex(elements, _) => walk_list(visitor, ast, elements),
+        NodeKind::Hash(pairs) => {
+            for (key, value) in pairs.iter() {
+                visitor.visit_node(ast, *key);
+                visitor.visit_node(ast, *value);
+            }
+        },
+        NodeKind::Range(start, end, _) => {
+            walk_optional(visitor, ast, *start);
+            walk_optional(visitor, ast, *end);
+        },
+        NodeKind::Constant(scope, _) => walk_optional(visitor, ast, *scope),
+        NodeKind::Assign(target, value) | NodeKind::OpAssign(target, _, value) => {
+            visitor.visit_target(ast, target);
+            visitor.visit_node(ast, *value);
+        },
+        NodeKind::MultipleAssign(targets, value) => {
+            for target in targets.iter() { visitor.visit_target(ast, target); }
+            visitor.visit_node(ast, *value);
+        },
+        NodeKind::Mlhs(targets) => {
+            for target in targets.iter() { visitor.visit_target(ast, target); }
+        },
+        NodeKind::Splat(value) | NodeKind::BlockPass(value) | NodeKind::Return(value) |
+        NodeKind::Break(value) | NodeKind::Next(value) => walk_optional(visitor, ast, *value),
+        NodeKind::Call(call) => visitor.visit_call(ast, call),
+        NodeKind::Block(params, body) | NodeKind::Lambda(params, body) | NodeKind::Def(_, params, body) => {
+            visitor.visit_parameters(ast, params);
+            visitor.visit_body(ast, body);
+        },
+        NodeKind::SingletonDef(receiver, _, params, body) => {
+            visitor.visit_node(ast, *receiver);
+            visitor.visit_parameters(ast, params);
+            visitor.visit_body(ast, body);
+        },
+        NodeKind::Class(name, superclass, body) => {
+            visitor.visit_node(ast, *name);
+            walk_optional(visitor, ast, *superclass);
+            visitor.visit_body(ast, body);
+        },
+        NodeKind::SingletonClass(name, body) | NodeKind::Module(name, body) => {
+            visitor.visit_node(ast, *name);
+            visitor.visit_body(ast, body);
+        },
+        NodeKind::Super(arguments, block) => {
+            if let Some(arguments) = arguments { walk_list(visitor, ast, arguments); }
+            walk_optional(visitor, ast, *block);
+        },
+        NodeKind::Yield(arguments) => walk_list(visitor, ast, arguments),
+        NodeKind::Begin(body) => visitor.visit_body(ast, body),
+        NodeKind::RescueModifier(value, rescue) | NodeKind::And(value, rescue) | NodeKind::Or(value, rescue) => {
+            visitor.visit_node(ast, *value);
+            visitor.visit_node(ast, *rescue);
+        },
+        NodeKind::If(condition, body, alternative) | NodeKind::Unless(condition, body, alternative) => {
+            walk_conditional(visitor, ast, *condition, body);
+            if let Some(statements) = alternative { walk_list(visitor, ast, statements); }
+        },
+        NodeKind::While(condition, body) | NodeKind::Until(condition, body) => walk_conditional(visitor, ast, *condition, body),
+        NodeKind::Case(case) => {
+            walk_optional(visitor, ast, case.subject);
+            for clause in case.when_clauses.iter() {
+                walk_list(visitor, ast, &clause.values);
+                walk_list(visitor, ast, &clause.body);
+            }
+            if let Some(statements) = &case.else_clause { walk_list(visitor, ast, statements); }
+        },
+    }
+}
+
+/// Visits a condition and the statements it guards, which come first for a modifier like
+/// `body if condition`.
+fn walk_conditional<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, condition: NodeId, body: &[NodeId]) {
+    let modifier = body.first().is_some_and(|first| ast[*first].span.start < ast[condition].span.start);
+    if !modifier { visitor.visit_node(ast, condition); }
+    walk_list(visitor, ast, body);
+    if modifier { visitor.visit_node(ast, condition); }
+}
+
+pub fn walk_call<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, call: &Call) {
+    walk_optional(visitor, ast, call.receiver);
+    walk_list(visitor, ast, &call.arguments);
+    walk_optional(visitor, ast, call.block);
+}
+
+pub fn walk_target<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, target: &AssignTarget) {
+    match target {
+        AssignTarget::Local(_) | AssignTarget::InstanceVariable(_) | AssignTarget::ClassVariable(_) |
+        AssignTarget::GlobalVariable(_) | AssignTarget::Splat(None) => (),
+        AssignTarget::Constant(scope, _) => walk_optional(visitor, ast, *scope),
+        AssignTarget::Attribute(receiver, _) => visitor.visit_node(ast, *receiver),
+        AssignTarget::Index(receiver, arguments) => {
+            visitor.visit_node(ast, *receiver);
+            walk_list(visitor, ast, arguments);
+        },
+        AssignTarget::Splat(Some(target)) => visitor.visit_target(ast, target),
+        AssignTarget::Nested(targets) => {
+            for target in targets.iter() { visitor.visit_target(ast, target); }
+        },
+    }
+}
+
+pub fn walk_parameters<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, params: &Parameters) {
+    for (_, default) in params.optional.iter() {
+        visitor.visit_node(ast, *default);
+    }
+}
+
+pub fn walk_body<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, body: &BodyStatement) {
+    walk_list(visitor, ast, &body.statements);
+    for clause in body.rescue_clauses.iter() {
+        visitor.visit_rescue_clause(ast, clause);
+    }
+    if let Some(statements) = &body.else_clause { walk_list(visitor, ast, statements); }
+    if let Some(statements) = &body.ensure_clause { walk_list(visitor, ast, statements); }
+}
+
+pub fn walk_rescue_clause<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, clause: &RescueClause) {
+    walk_list(visitor, ast, &clause.exception_classes);
+    walk_list(visitor, ast, &clause.body);
+}
+
+fn walk_list<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, ids: &[NodeId]) {
+    for id in ids.iter() {
+        visitor.visit_node(ast, *id);
+    }
+}
+
+fn walk_optional<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, id: Option<NodeId>) {
+    if let Some(id) = id { visitor.visit_node(ast, id); }
+}
+
+/// Collects the direct children of a node without descending any further.
+struct Children(Vec<NodeId>);
+
+impl Visitor for Children {
+    fn visit_node(&mut self, _ast: &Ast, id: NodeId) {
+        self.0.push(id);
+    }
+}
+
+/// The ids of the nodes directly below `id`, in source order.
+pub fn children(ast: &Ast, id: NodeId) -> Vec<NodeId> {
+    let mut collector = Children(vec!());
+    walk_node(&mut collector, ast, id);
+    collector.0
+}
+
+/// Walks an `Ast` and may rewrite it in place. A pass replaces a node by assigning to
+/// `ast[id].kind`, and may push new nodes for the replacement to refer to. Calling
+/// `walk_node_mut` before rewriting a node folds its children first.
+pub trait VisitorMut {
+    fn visit_node_mut(&mut self, ast: &mut Ast, id: NodeId) {
+        walk_node_mut(self, ast, id);
+    }
+}
+
+/// Visits the children of a node in source order. The children are looked up before any of
+/// them are visited, so a pass may replace them freely.
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, id: NodeId) {
+    for child in children(ast, id) {
+        visitor.visit_node_mut(ast, child);
+    }
+}
+
+/// Runs `visitor` over each of `statements`.
+pub fn visit_statements<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, statements: &[NodeId]) {
+    walk_list(visitor, ast, statements);
+}
+
+/// Runs `visitor` over each of `statements`, letting it rewrite them.
+pub fn visit_statements_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, statements: &[NodeId]) {
+    for id in statements.iter() {
+        visitor.visit_node_mut(ast, *id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::program::Program;
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let mut lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+        assert!(errors.is_empty(), "parser errors: {:?}", errors);
+
+        program
+    }
+
+    /// Records every identifier and method call in the order it is reached.
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_node(&mut self, ast: &Ast, id: NodeId) {
+            if let NodeKind::Identifier(name) = &ast[id].kind { self.0.push(name.clone()); }
+            walk_node(self, ast, id);
+        }
+
+        fn visit_call(&mut self, ast: &Ast, call: &Call) {
+            self.0.push(call.method.clone());
+            walk_call(self, ast, call);
+        }
+    }
+
+    #[test]
+    fn should_reach_nested_nodes_in_source_order() {
+        let program = parse("def run(limit = default_limit)
+  items.each { |item| process(item) }
+rescue Timeout => e
+  report e
+ensure
+  cleanup
+end
+h[key] += compute rescue fallback");
+
+        let mut names = Names(vec!());
+        visit_statements(&mut names, &program.ast, &program.statements);
+        assert_eq!(names.0, vec!(
+            "default_limit", "each", "items", "process", "item", "report", "e", "cleanup", "h", "key", "compute", "fallback",
+        ));
+    }
+
+    #[test]
+    fn should_list_direct_children() {
+        let program = parse("obj.call(1, *rest) { 2 }");
+        let kids: Vec<String> = children(&program.ast, program.statements[0]).iter()
+            .map(|id| program.ast.to_s(*id))
+            .collect();
+
+        assert_eq!(kids, vec!("obj", "1", "*rest", "do\n2\nend"));
+    }
+
+    /// Replaces `+` and `*` on two integer literals with their result.
+    struct ConstantFolder;
+
+    impl VisitorMut for ConstantFolder {
+        fn visit_node_mut(&mut self, ast: &mut Ast, id: NodeId) {
+            walk_node_mut(self, ast, id);
+            let folded = match &ast[id].kind {
+                NodeKind::Call(Call { receiver: Some(left), method, arguments, block: None }) if arguments.len() == 1 => {
+                    match (&ast[*left].kind, &ast[arguments[0]].kind, method.as_str()) {
+                        (NodeKind::Integer(a), NodeKind::Integer(b), "+") => Some(a + b),
+                        (NodeKind::Integer(a), NodeKind::Integer(b), "*") => Some(a * b),
+                        _ => None
+                    }
+                },
+                _ => None
+            };
+            if let Some(value) = folded {
+                ast[id].kind = NodeKind::Integer(value);
+            }
+        }
+    }
+
+    #[test]
+    fn should_rewrite_nodes_bottom_up() {
+        let mut program = parse("x = 1 + 2 * 3\nputs x + 4, [5 * 5]");
+        let statements = program.statements.clone();
+        visit_statements_mut(&mut ConstantFolder, &mut program.ast, &statements);
+
+        assert_eq!(program.to_s(), "x = 7\nputs(x.+(4), [25])");
+    }
+}
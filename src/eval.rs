@@ -0,0 +1,2791 @@
+pub mod bigint;
+pub mod class;
+pub mod environment;
+pub mod regexp;
+pub mod value;
+mod builtins;
+mod coroutine;
+mod random;
+
+use std::collections::HashMap;
+use std::io::{ self, BufRead, BufReader, Write };
+use std::rc::Rc;
+use std::slice;
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+use crate::ast::node::{ Ast, NodeId, NodeKind, AssignTarget, Call, Case, Parameters, BodyStatement };
+use crate::program::Program;
+use bigint::BigInt;
+use class::{ Method, MethodBody, RClass, Visibility };
+use environment::{ Context, MethodCall, Nesting, Scope };
+use random::Random;
+use regexp::{ Options, Regex };
+use value::{ Encoding, ProcBody, RProc, Value };
+
+/// Deeper calls raise `SystemStackError` instead of overflowing the Rust stack, as long as the
+/// interpreter runs on a thread with a few hundred megabytes of stack.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The ways evaluation can leave an expression other than by producing a value.
+#[derive(Debug)]
+pub enum Control {
+    /// An exception object on its way to a `rescue`
+    Raise(Value),
+    /// Leaves the innermost loop, or with the tag of a block, the call the block was given to
+    Break(Value, Option<usize>),
+    Next(Value),
+    /// Leaves the method or lambda call with this id
+    Return(Value, usize),
+    Retry,
+    /// `throw` on its way to the `catch` with this tag, carrying the value it returns
+    Throw(Value, Value),
+}
+
+pub type EvalResult = Result<Value, Control>;
+
+/// The classes the interpreter creates values of directly.
+pub struct CoreClasses {
+    pub basic_object: Rc<RClass>,
+    pub object:       Rc<RClass>,
+    pub module:       Rc<RClass>,
+    pub class:        Rc<RClass>,
+    pub kernel:       Rc<RClass>,
+    pub nil:          Rc<RClass>,
+    pub true_class:   Rc<RClass>,
+    pub false_class:  Rc<RClass>,
+    pub numeric:      Rc<RClass>,
+    pub integer:      Rc<RClass>,
+    pub float:        Rc<RClass>,
+    pub string:       Rc<RClass>,
+    pub symbol:       Rc<RClass>,
+    pub array:        Rc<RClass>,
+    pub hash:         Rc<RClass>,
+    pub range:        Rc<RClass>,
+    pub proc_class:   Rc<RClass>,
+}
+
+/// What a method body, class body, block or the top level is running with.
+struct Frame {
+    context:         Rc<Context>,
+    scope:           Rc<Scope>,
+    /// What a `def` makes the method, changed by a bare `private`, `protected` or `public`
+    visibility:      Visibility,
+    /// Set by a bare `module_function`, after which a `def` also defines a singleton method
+    module_function: bool,
+    /// The tag of the proc running in this frame, which a `break` outside a loop leaves
+    block:           Option<usize>,
+    /// The number of `while` and `until` loops running in this frame
+    loops:           usize,
+    /// Whether this is the body of a method or lambda, which `return` leaves
+    returns:         bool,
+    /// The program the frame runs code from, and the offset of the statement or call it is at
+    ast:             Option<Rc<Ast>>,
+    offset:          usize,
+}
+
+impl Frame {
+    fn new(context: Rc<Context>, scope: Rc<Scope>, ast: Option<Rc<Ast>>) -> Frame {
+        Frame {
+            context,
+            scope,
+            visibility: Visibility::Public,
+            module_function: false,
+            block: None,
+            loops: 0,
+            returns: false,
+            ast,
+            offset: 0,
+        }
+    }
+
+    /// How the frame shows up in a backtrace: `file:line:in 'label'`.
+    fn position(&self) -> String {
+        let label = match &self.context.method {
+            Some(call) => call.name.clone(),
+            None if self.context.returns_to == 0 => String::from("<main>"),
+            None => match &self.context.self_value {
+                Value::Class(module) if module.is_module => format!("<module:{}>", module.inspect()),
+                Value::Class(class) => format!("<class:{}>", class.inspect()),
+                _ => String::from("<main>"),
+            },
+        };
+        let label = if self.block.is_some() { format!("block in {}", label) } else { label };
+        match &self.ast {
+            Some(ast) => format!("{}:{}:in '{}'", ast.file(), ast.line(self.offset), label),
+            None => format!("-:in '{}'", label),
+        }
+    }
+}
+
+/// Set when the user presses Ctrl-C, so the program raises `Interrupt` at its next statement.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The stacks of one thread of execution. A coroutine has its own, which the interpreter
+/// switches to while it runs.
+struct Stacks {
+    frames:    Vec<Frame>,
+    blocks:    Vec<usize>,
+    recursion: Vec<(&'static str, usize)>,
+}
+
+/// Evaluates programs by walking their syntax trees. The top level binding, globals and every
+/// class defined so far persist from one `eval_program` to the next.
+pub struct Interpreter {
+    pub classes: CoreClasses,
+    globals:     HashMap<String, Value>,
+    frames:      Vec<Frame>,
+    main:        Value,
+    /// The tags of the literal blocks whose calls are running, which `break` can still leave
+    blocks:      Vec<usize>,
+    /// The id of the most recent method or lambda call
+    calls:       usize,
+    /// Every pattern compiled so far, by source and options
+    regexps:     HashMap<(String, i64), Rc<Regex>>,
+    /// The objects `guard_recursion` is running something for, with what it runs
+    recursion:   Vec<(&'static str, usize)>,
+    /// The tags of the `catch` blocks running, innermost last
+    catches:     Vec<Value>,
+    /// Where `puts`, `print` and `p` write
+    output:      Box<dyn Write>,
+    /// Where `warn`, `$stderr` and `abort` write
+    error_output: Box<dyn Write>,
+    /// Where `gets` reads from
+    input:       Box<dyn BufRead>,
+    /// The generator `rand` draws from, and the seed `srand` last gave it
+    random:      Random,
+    seed:        Value,
+    /// The absolute paths of the files `require` is running, innermost last
+    loading:     Vec<String>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let basic_object = RClass::new("BasicObject", None);
+        let object = RClass::new("Object", Some(basic_object.clone()));
+        let kernel = RClass::module("Kernel");
+        object.includes.borrow_mut().push(kernel.clone());
+        let module = RClass::new("Module", Some(object.clone()));
+        let numeric = RClass::new("Numeric", Some(object.clone()));
+        let classes = CoreClasses {
+            class:        RClass::new("Class", Some(module.clone())),
+            nil:          RClass::new("NilClass", Some(object.clone())),
+            true_class:   RClass::new("TrueClass", Some(object.clone())),
+            false_class:  RClass::new("FalseClass", Some(object.clone())),
+            integer:      RClass::new("Integer", Some(numeric.clone())),
+            float:        RClass::new("Float", Some(numeric.clone())),
+            string:       RClass::new("String", Some(object.clone())),
+            symbol:       RClass::new("Symbol", Some(object.clone())),
+            array:        RClass::new("Array", Some(object.clone())),
+            hash:         RClass::new("Hash", Some(object.clone())),
+            range:        RClass::new("Range", Some(object.clone())),
+            proc_class:   RClass::new("Proc", Some(object.clone())),
+            basic_object,
+            object,
+            module,
+            kernel,
+            numeric,
+        };
+        let main = Value::object(classes.object.clone());
+        let context = Context {
+            self_value: main.clone(),
+            definee:    classes.object.clone(),
+            nesting:    Nesting::new(classes.object.clone(), None),
+            method:     None,
+            returns_to: 0,
+        };
+        // methods defined at the top level are private methods of Object
+        let top = Frame { visibility: Visibility::Private, returns: true, ..Frame::new(Rc::new(context), Scope::new(), None) };
+        let seed = random::new_seed();
+        let mut interpreter = Self {
+            classes,
+            globals:   HashMap::new(),
+            frames:    vec!(top),
+            main,
+            blocks:    vec!(),
+            calls:     0,
+            regexps:   HashMap::new(),
+            recursion: vec!(),
+            catches:   vec!(),
+            output:    Box::new(io::stdout()),
+            error_output: Box::new(io::stderr()),
+            input:     Box::new(BufReader::new(io::stdin())),
+            random:    Random::new(&random::seed_words(&seed)),
+            seed,
+            loading:   vec!(),
+        };
+
+        let core = [
+            &interpreter.classes.basic_object, &interpreter.classes.object, &interpreter.classes.module,
+            &interpreter.classes.class, &interpreter.classes.kernel, &interpreter.classes.nil, &interpreter.classes.true_class,
+            &interpreter.classes.false_class, &interpreter.classes.numeric, &interpreter.classes.integer,
+            &interpreter.classes.float, &interpreter.classes.string, &interpreter.classes.symbol,
+            &interpreter.classes.array, &interpreter.classes.hash, &interpreter.classes.range,
+            &interpreter.classes.proc_class,
+        ].iter().map(|class| (*class).clone()).collect::<Vec<Rc<RClass>>>();
+        for class in core {
+            interpreter.set_constant(&class.name(), Value::Class(class.clone()));
+        }
+        builtins::define(&mut interpreter);
+
+        interpreter
+    }
+
+    /// Runs a parsed program at the top level, returning the value of its last statement.
+    pub fn eval_program(&mut self, program: Program) -> EvalResult {
+        let ast = Rc::new(program.ast);
+        self.frames[0].ast = Some(ast.clone());
+        match self.eval_statements(&ast, &program.statements) {
+            Err(Control::Return(value, _)) => Ok(value),
+            Err(Control::Break(_, _)) => Err(self.error("LocalJumpError", "break from proc-closure")),
+            Err(Control::Next(_)) => Err(self.error("LocalJumpError", "next used outside of a block")),
+            Err(Control::Retry) => Err(self.error("LocalJumpError", "retry used outside of rescue")),
+            Err(Control::Throw(tag, _)) => {
+                let message = format!("uncaught throw {}", self.inspect(&tag)?);
+                Err(self.error("UncaughtThrowError", &message))
+            },
+            result => result,
+        }
+    }
+
+    /// Runs the program of a file that `require` or `load` read, at the top level but with a
+    /// scope of its own. A `return` outside any method stops just this file.
+    pub fn eval_file(&mut self, program: Program) -> EvalResult {
+        let ast = Rc::new(program.ast);
+        let object = self.classes.object.clone();
+        let id = self.next_call();
+        let context = Context {
+            self_value: self.main.clone(),
+            definee:    object.clone(),
+            nesting:    Nesting::new(object, None),
+            method:     None,
+            returns_to: id,
+        };
+        let frame = Frame { visibility: Visibility::Private, returns: true, ..Frame::new(Rc::new(context), Scope::new(), Some(ast.clone())) };
+        self.frames.push(frame);
+        let result = self.eval_statements(&ast, &program.statements);
+        self.frames.pop();
+
+        match result {
+            Err(Control::Return(value, target)) if target == id => Ok(value),
+            result => result,
+        }
+    }
+
+    /// Puts directories in front of `$LOAD_PATH`, in the order given, as `-I` does.
+    pub fn prepend_load_path(&mut self, directories: &[String]) {
+        if let Value::Array(paths) = self.global("$LOAD_PATH") {
+            let mut paths = paths.elements.borrow_mut();
+            for (i, directory) in directories.iter().enumerate() {
+                paths.insert(i, Value::string(directory));
+            }
+        }
+    }
+
+    /// Makes the running program raise `Interrupt`. Only sets a flag, so a signal handler can
+    /// call it.
+    pub fn interrupt() {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    /// Forgets an interrupt nothing has raised yet, returning whether there was one.
+    pub fn clear_interrupt() -> bool {
+        INTERRUPTED.swap(false, Ordering::Relaxed)
+    }
+
+    /// The names of the local variables at the top level.
+    pub fn top_level_locals(&self) -> Vec<String> {
+        self.frames[0].scope.names()
+    }
+
+    pub fn set_top_level_local(&mut self, name: &str, value: Value) {
+        self.frames[0].scope.set(name, value);
+    }
+
+    /// The names of the methods `value` responds to, singleton ones included, and its private
+    /// methods too when asked for.
+    pub fn method_names(&self, value: &Value, private: bool) -> Vec<String> {
+        let filter: fn(Visibility) -> bool = match private {
+            true => |_| true,
+            false => |visibility| visibility != Visibility::Private,
+        };
+        builtins::module::method_names(&self.lookup_class(value), true, filter)
+    }
+
+    pub fn main(&self) -> &Value {
+        &self.main
+    }
+
+    /// Sends what the program prints somewhere other than standard output.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Sends the warnings and errors the program writes somewhere other than standard error.
+    pub fn set_error_output(&mut self, error_output: Box<dyn Write>) {
+        self.error_output = error_output;
+    }
+
+    /// Has `gets` read from somewhere other than standard input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("the top level frame is never popped")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("the top level frame is never popped")
+    }
+
+    fn next_call(&mut self) -> usize {
+        self.calls += 1;
+        self.calls
+    }
+
+    fn self_value(&self) -> Value {
+        self.frame().context.self_value.clone()
+    }
+
+    fn eval_statements(&mut self, ast: &Rc<Ast>, statements: &[NodeId]) -> EvalResult {
+        if INTERRUPTED.swap(false, Ordering::Relaxed) {
+            let interrupt = self.error("Interrupt", "");
+            if let Control::Raise(Value::Object(exception)) = &interrupt { exception.set("message", Value::Nil); }
+            return Err(interrupt);
+        }
+
+        let mut value = Value::Nil;
+        for statement in statements.iter() {
+            self.frame_mut().offset = ast[*statement].span.start;
+            value = self.eval(ast, *statement)?;
+        }
+
+        Ok(value)
+    }
+
+    fn eval(&mut self, ast: &Rc<Ast>, id: NodeId) -> EvalResult {
+        match &ast[id].kind {
+            NodeKind::Nil => Ok(Value::Nil),
+            NodeKind::Boolean(value) => Ok(Value::Bool(*value)),
+            NodeKind::Integer(value) => Ok(Value::Integer(*value)),
+            NodeKind::BigInteger(digits) => Ok(Value::integer(BigInt::parse(digits, 10).expect("the parser only keeps digits"))),
+            NodeKind::Float(value) => Ok(Value::Float(*value)),
+            NodeKind::Str(value) => {
+                let string = Value::bytes(value.clone(), Encoding::Utf8);
+                if let Value::String(string) = &string { string.frozen.set(ast.frozen_string_literal()); }
+                Ok(string)
+            },
+            NodeKind::InterpolatedStr(parts) => Ok(Value::bytes(self.interpolate(ast, parts)?, Encoding::Utf8)),
+            NodeKind::Regex(source, flags) => {
+                let options = Options::from_flags(flags).unwrap_or_default();
+                builtins::regexp::regexp(self, source, options)
+            },
+            NodeKind::InterpolatedRegex(parts, flags) => {
+                let source = String::from_utf8_lossy(&self.interpolate(ast, parts)?).to_string();
+                let options = Options::from_flags(flags).unwrap_or_default();
+                builtins::regexp::regexp(self, &source, options)
+            },
+            NodeKind::Symbol(name) => Ok(Value::symbol(name)),
+            NodeKind::Array(elements) => Ok(Value::array(self.eval_list(ast, elements)?)),
+            NodeKind::Hash(pairs) => {
+                let hash = Value::hash();
+                for (key, value) in pairs.iter() {
+                    let key = self.eval(ast, *key)?;
+                    let value = self.eval(ast, *value)?;
+                    builtins::hash::insert(self, &hash, key, value)?;
+                }
+                Ok(hash)
+            },
+            NodeKind::Range(start, end, exclusive) => {
+                let start = self.eval_optional(ast, *start)?;
+                let end = self.eval_optional(ast, *end)?;
+                let valid = match (&start, &end) {
+                    (Value::Integer(_), Value::Integer(_)) | (Value::Nil, _) | (_, Value::Nil) => true,
+                    (start, end) => self.call_method(start, "<=>", slice::from_ref(end), None)?.is_truthy(),
+                };
+                if !valid { return Err(self.error("ArgumentError", "bad value for range")); }
+                Ok(Value::range(start, end, *exclusive))
+            },
+            NodeKind::SelfRef => Ok(self.self_value()),
+            NodeKind::Identifier(name) => match self.frame().scope.get(name) {
+                Some(value) => Ok(value),
+                None => self.call_function(name, &[], None, true),
+            },
+            NodeKind::Constant(scope, name) => self.eval_constant(ast, *scope, name),
+            NodeKind::InstanceVariable(name) => Ok(self.instance_variable(&self.self_value(), name)),
+            NodeKind::ClassVariable(name) => {
+                let module = self.class_variable_scope()?;
+                match self.class_variable(&module, name) {
+                    Some(value) => Ok(value),
+                    None => Err(self.error("NameError", &format!("uninitialized class variable {} in {}", name, module.inspect()))),
+                }
+            },
+            NodeKind::GlobalVariable(name) => Ok(self.global(name)),
+            NodeKind::Assign(target, value) => {
+                let value = self.eval(ast, *value)?;
+                self.assign(ast, target, value.clone())?;
+                Ok(value)
+            },
+            NodeKind::MultipleAssign(targets, value) => {
+                let value = self.eval(ast, *value)?;
+                self.destructure(ast, targets, &value)?;
+                Ok(value)
+            },
+            NodeKind::OpAssign(target, op, value) => self.eval_op_assign(ast, target, op, *value),
+            NodeKind::Mlhs(_) | NodeKind::BlockPass(_) | NodeKind::Block(_, _) => {
+                Err(self.error("SyntaxError", &format!("unexpected {}", ast.to_s(id).trim_end())))
+            },
+            NodeKind::Splat(_) => Ok(Value::array(self.eval_list(ast, &[id])?)),
+            NodeKind::Call(call) => self.eval_call(ast, call, ast[id].span.start),
+            NodeKind::Lambda(_, _) => Ok(self.make_proc(ast, id, true)),
+            NodeKind::Def(name, _, _) => {
+                self.define_method(ast, id, name);
+                Ok(Value::symbol(name))
+            },
+            NodeKind::SingletonDef(receiver, name, _, _) => {
+                let object = self.eval(ast, *receiver)?;
+                let body = MethodBody::Defined(ast.clone(), id, self.frame().context.nesting.clone());
+                self.singleton_class(&object)?.define(name, Method { body, visibility: Visibility::Public });
+                Ok(Value::symbol(name))
+            },
+            NodeKind::Class(name, superclass, body) => self.eval_class(ast, *name, *superclass, body),
+            NodeKind::SingletonClass(object, body) => {
+                let object = self.eval(ast, *object)?;
+                let singleton = self.singleton_class(&object)?;
+                self.eval_module_body(ast, singleton, body)
+            },
+            NodeKind::Module(name, body) => self.eval_module(ast, *name, body),
+            NodeKind::Super(arguments, block) => self.eval_super(ast, arguments.as_deref(), *block),
+            NodeKind::Yield(arguments) => {
+                let arguments = self.eval_list(ast, arguments)?;
+                match self.frame().context.method.as_ref().and_then(|call| call.block.clone()) {
+                    Some(block) => self.call_proc(&block, &arguments),
+                    None => Err(self.error("LocalJumpError", "no block given (yield)")),
+                }
+            },
+            NodeKind::Begin(body) => self.eval_body(ast, body),
+            NodeKind::RescueModifier(value, rescue) => match self.eval(ast, *value) {
+                Err(Control::Raise(exception)) if self.is_a(&exception, "StandardError") => {
+                    // as in a `rescue` clause, `$!` is the exception while rescuing it
+                    let previous = self.globals.insert(String::from("$!"), exception);
+                    let result = self.eval(ast, *rescue);
+                    self.globals.insert(String::from("$!"), previous.unwrap_or(Value::Nil));
+                    result
+                },
+                result => result,
+            },
+            NodeKind::Retry => Err(Control::Retry),
+            NodeKind::Return(value) => {
+                let value = self.eval_optional(ast, *value)?;
+                let target = self.frame().context.returns_to;
+                if !self.frames.iter().rev().any(|frame| frame.returns && frame.context.returns_to == target) {
+                    return Err(self.error("LocalJumpError", "unexpected return"));
+                }
+                Err(Control::Return(value, target))
+            },
+            NodeKind::Break(value) => {
+                let value = self.eval_optional(ast, *value)?;
+                match self.frame() {
+                    frame if frame.loops > 0 => Err(Control::Break(value, None)),
+                    Frame { block: Some(tag), .. } => Err(Control::Break(value, Some(*tag))),
+                    _ => Err(self.error("SyntaxError", "Invalid break")),
+                }
+            },
+            NodeKind::Next(value) => Err(Control::Next(self.eval_optional(ast, *value)?)),
+            NodeKind::And(left, right) => {
+                let left = self.eval(ast, *left)?;
+                if left.is_truthy() { self.eval(ast, *right) } else { Ok(left) }
+            },
+            NodeKind::Or(left, right) => {
+                let left = self.eval(ast, *left)?;
+                if left.is_truthy() { Ok(left) } else { self.eval(ast, *right) }
+            },
+            NodeKind::If(condition, consequence, alternative) => {
+                let condition = self.eval(ast, *condition)?.is_truthy();
+                self.eval_branch(ast, condition, consequence, alternative.as_deref())
+            },
+            NodeKind::Unless(condition, consequence, alternative) => {
+                let condition = self.eval(ast, *condition)?.is_truthy();
+                self.eval_branch(ast, !condition, consequence, alternative.as_deref())
+            },
+            NodeKind::While(condition, body) => self.eval_loop(ast, *condition, body, true),
+            NodeKind::Until(condition, body) => self.eval_loop(ast, *condition, body, false),
+            NodeKind::Case(case) => self.eval_case(ast, case),
+        }
+    }
+
+    fn eval_optional(&mut self, ast: &Rc<Ast>, id: Option<NodeId>) -> EvalResult {
+        match id {
+            Some(id) => self.eval(ast, id),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    /// Evaluates the items of an array literal or argument list, expanding `*splat`s.
+    fn eval_list(&mut self, ast: &Rc<Ast>, ids: &[NodeId]) -> Result<Vec<Value>, Control> {
+        let mut values = vec!();
+        for id in ids.iter() {
+            match &ast[*id].kind {
+                NodeKind::Splat(Some(inner)) => {
+                    let value = self.eval(ast, *inner)?;
+                    values.extend(self.splat(value)?);
+                },
+                NodeKind::Splat(None) => {
+                    let rest = self.frame().scope.get("*").unwrap_or(Value::Nil);
+                    values.extend(self.splat(rest)?);
+                },
+                _ => values.push(self.eval(ast, *id)?),
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// The values `*value` expands to: the elements of an array, nothing for `nil`, or the
+    /// value itself.
+    fn splat(&mut self, value: Value) -> Result<Vec<Value>, Control> {
+        match value {
+            Value::Array(array) => Ok(array.elements.borrow().clone()),
+            Value::Nil => Ok(vec!()),
+            Value::Hash(hash) => Ok(hash.entries.borrow().iter().map(|(k, v)| Value::array(vec!(k.clone(), v.clone()))).collect()),
+            Value::Range(_) => match self.call_method(&value, "to_a", &[], None)? {
+                Value::Array(array) => Ok(array.elements.borrow().clone()),
+                _ => Ok(vec!(value)),
+            },
+            value => Ok(vec!(value)),
+        }
+    }
+
+    fn eval_branch(&mut self, ast: &Rc<Ast>, condition: bool, consequence: &[NodeId], alternative: Option<&[NodeId]>) -> EvalResult {
+        match (condition, alternative) {
+            (true, _) => self.eval_statements(ast, consequence),
+            (false, Some(alternative)) => self.eval_statements(ast, alternative),
+            (false, None) => Ok(Value::Nil),
+        }
+    }
+
+    /// Runs a `while` loop, or an `until` loop when `expected` is false. `break` ends the loop
+    /// with its value and `next` skips to the next test of the condition.
+    fn eval_loop(&mut self, ast: &Rc<Ast>, condition: NodeId, body: &[NodeId], expected: bool) -> EvalResult {
+        self.frame_mut().loops += 1;
+        let result = self.run_loop(ast, condition, body, expected);
+        self.frame_mut().loops -= 1;
+
+        result
+    }
+
+    fn run_loop(&mut self, ast: &Rc<Ast>, condition: NodeId, body: &[NodeId], expected: bool) -> EvalResult {
+        while self.eval(ast, condition)?.is_truthy() == expected {
+            match self.eval_statements(ast, body) {
+                Ok(_) | Err(Control::Next(_)) => (),
+                Err(Control::Break(value, None)) => return Ok(value),
+                Err(control) => return Err(control),
+            }
+        }
+
+        Ok(Value::Nil)
+    }
+
+    /// Compares the subject to each `when` value with `value === subject`, or tests the values
+    /// themselves when there is no subject.
+    fn eval_case(&mut self, ast: &Rc<Ast>, case: &Case) -> EvalResult {
+        let subject = match case.subject {
+            Some(subject) => Some(self.eval(ast, subject)?),
+            None => None,
+        };
+        for clause in case.when_clauses.iter() {
+            for pattern in self.eval_list(ast, &clause.values)? {
+                let matched = match &subject {
+                    Some(subject) => self.call_method(&pattern, "===", slice::from_ref(subject), None)?.is_truthy(),
+                    None => pattern.is_truthy(),
+                };
+                if matched { return self.eval_statements(ast, &clause.body); }
+            }
+        }
+
+        match &case.else_clause {
+            Some(statements) => self.eval_statements(ast, statements),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    /// Runs the statements of a body, then its `else` clause if nothing was raised. A raised
+    /// `StandardError` is handed to the first matching `rescue` clause, which may `retry` the
+    /// body. The `ensure` clause always runs last; the body's result stands unless the
+    /// `ensure` clause itself jumps away.
+    fn eval_body(&mut self, ast: &Rc<Ast>, body: &BodyStatement) -> EvalResult {
+        let mut result = loop {
+            let result = match self.eval_statements(ast, &body.statements) {
+                Err(Control::Raise(exception)) => self.rescue(ast, body, exception),
+                Ok(_) if body.else_clause.is_some() => self.eval_statements(ast, body.else_clause.as_ref().unwrap()),
+                result => result,
+            };
+            match result {
+                Err(Control::Retry) if !body.rescue_clauses.is_empty() => continue,
+                result => break result,
+            }
+        };
+
+        if let Some(ensure) = &body.ensure_clause {
+            self.eval_statements(ast, ensure)?;
+        }
+        if let Err(Control::Retry) = result {
+            result = Err(self.error("SyntaxError", "Invalid retry"));
+        }
+
+        result
+    }
+
+    fn rescue(&mut self, ast: &Rc<Ast>, body: &BodyStatement, exception: Value) -> EvalResult {
+        for clause in body.rescue_clauses.iter() {
+            let classes = if clause.exception_classes.is_empty() {
+                vec!(self.constant("StandardError").unwrap_or(Value::Nil))
+            } else {
+                self.eval_list(ast, &clause.exception_classes)?
+            };
+            let mut matched = false;
+            for class in classes.iter() {
+                match class {
+                    Value::Class(class) => matched |= self.lookup_class(&exception).inherits(class),
+                    _ => return Err(self.error("TypeError", "class or module required for rescue clause")),
+                }
+            }
+            if matched {
+                if let Some(variable) = &clause.variable { self.frame().scope.set(variable, exception.clone()); }
+                // `$!` is the exception being handled until the clause is done with it
+                let previous = self.globals.insert(String::from("$!"), exception);
+                let result = self.eval_statements(ast, &clause.body);
+                self.globals.insert(String::from("$!"), previous.unwrap_or(Value::Nil));
+                return result;
+            }
+        }
+
+        Err(Control::Raise(exception))
+    }
+
+    fn eval_constant(&mut self, ast: &Rc<Ast>, scope: Option<NodeId>, name: &str) -> EvalResult {
+        let (value, module) = match scope {
+            Some(scope) => match self.eval(ast, scope)? {
+                Value::Class(class) => (self.scoped_constant(&class, name), class),
+                value => {
+                    let message = format!("{} is not a class/module", self.inspect(&value)?);
+                    return Err(self.error("TypeError", &message));
+                }
+            },
+            None => (self.lexical_constant(name), self.frame().context.nesting.module.clone()),
+        };
+
+        value.ok_or_else(|| {
+            let message = format!("uninitialized constant {}", self.qualified_name(&module, name));
+            self.name_error("NameError", &message, &Value::Class(module.clone()), name)
+        })
+    }
+
+    /// Looks a constant up in the modules lexically enclosing the code, innermost first, then
+    /// in the ancestors of the innermost one and finally at the top level.
+    fn lexical_constant(&self, name: &str) -> Option<Value> {
+        let nesting = self.frame().context.nesting.clone();
+        let mut current = Some(nesting.clone());
+        while let Some(enclosing) = current {
+            if let Some(value) = enclosing.module.constants.borrow().get(name) {
+                return Some(value.clone());
+            }
+            current = enclosing.parent.clone();
+        }
+
+        nesting.module.ancestors().iter()
+            .find_map(|module| module.constants.borrow().get(name).cloned())
+            .or_else(|| self.constant(name))
+    }
+
+    /// Looks up `Module::name` in the module and its ancestors. Top level constants are only
+    /// found through `Object` itself.
+    fn scoped_constant(&self, module: &Rc<RClass>, name: &str) -> Option<Value> {
+        for ancestor in module.ancestors() {
+            if Rc::ptr_eq(&ancestor, &self.classes.object) && !Rc::ptr_eq(module, &self.classes.object) { break; }
+            if let Some(value) = ancestor.constants.borrow().get(name) {
+                return Some(value.clone());
+            }
+        }
+
+        None
+    }
+
+    /// Sets a constant in `module`, which also names a class or module that didn't have a
+    /// name yet.
+    pub fn assign_constant(&self, module: &Rc<RClass>, name: &str, value: Value) {
+        if let Value::Class(class) = &value {
+            if class.name().is_empty() { *class.name.borrow_mut() = self.qualified_name(module, name); }
+        }
+        module.set_constant(name, value);
+    }
+
+    /// `Outer::name`, or just `name` for a constant at the top level.
+    fn qualified_name(&self, module: &Rc<RClass>, name: &str) -> String {
+        if Rc::ptr_eq(module, &self.classes.object) { name.to_string() } else { format!("{}::{}", module.inspect(), name) }
+    }
+
+    /// The class whose class variables `@@name` refers to: the innermost class or module
+    /// around the code, or the class a singleton class belongs to.
+    fn class_variable_scope(&self) -> Result<Rc<RClass>, Control> {
+        let module = self.frame().context.nesting.module.clone();
+        let module = match &module.attached {
+            Some(Value::Class(attached)) => attached.clone(),
+            _ => module,
+        };
+        if Rc::ptr_eq(&module, &self.classes.object) {
+            return Err(self.error("RuntimeError", "class variable access from toplevel"));
+        }
+
+        Ok(module)
+    }
+
+    /// Looks a class variable up in the module and its ancestors.
+    pub fn class_variable(&self, module: &Rc<RClass>, name: &str) -> Option<Value> {
+        module.ancestors().iter().find_map(|ancestor| ancestor.class_variables.borrow().get(name).cloned())
+    }
+
+    /// Assigns to the class variable in the ancestor that already has it, or creates it in
+    /// `module`.
+    pub fn set_class_variable(&self, module: &Rc<RClass>, name: &str, value: Value) {
+        let owner = module.ancestors().into_iter()
+            .find(|ancestor| ancestor.class_variables.borrow().contains_key(name))
+            .unwrap_or_else(|| module.clone());
+        owner.class_variables.borrow_mut().insert(name.to_string(), value);
+    }
+
+    /// Looks up a constant defined at the top level.
+    pub fn constant(&self, name: &str) -> Option<Value> {
+        self.classes.object.constants.borrow().get(name).cloned()
+    }
+
+    pub fn set_constant(&mut self, name: &str, value: Value) {
+        self.classes.object.set_constant(name, value);
+    }
+
+    pub fn global(&self, name: &str) -> Value {
+        if let Some(value) = builtins::regexp::special_global(self, name) { return value; }
+        self.globals.get(name).cloned().unwrap_or(Value::Nil)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    pub fn instance_variable(&self, object: &Value, name: &str) -> Value {
+        match object {
+            Value::Object(object) => object.get(name).unwrap_or(Value::Nil),
+            Value::Class(class) => {
+                class.ivars.borrow().iter().find(|(ivar, _)| ivar == name).map_or(Value::Nil, |(_, value)| value.clone())
+            },
+            _ => Value::Nil,
+        }
+    }
+
+    pub fn set_instance_variable(&mut self, object: &Value, name: &str, value: Value) -> Result<(), Control> {
+        match object {
+            Value::Object(_) if object.is_frozen() => builtins::check_frozen(self, object),
+            Value::Object(object) => {
+                object.set(name, value);
+                Ok(())
+            },
+            Value::Class(class) => {
+                let mut ivars = class.ivars.borrow_mut();
+                match ivars.iter_mut().find(|(ivar, _)| ivar == name) {
+                    Some(slot) => slot.1 = value,
+                    None => ivars.push((name.to_string(), value)),
+                }
+                Ok(())
+            },
+            _ => {
+                let message = format!("can't modify frozen {}: {}", self.class_of(object).name(), self.inspect(object)?);
+                Err(self.error("FrozenError", &message))
+            }
+        }
+    }
+
+    /// The bytes of an interpolated string or regexp: the text, and what each `#{}` gives
+    /// as a string.
+    fn interpolate(&mut self, ast: &Rc<Ast>, parts: &[NodeId]) -> Result<Vec<u8>, Control> {
+        let mut bytes = vec!();
+        for part in parts.iter() {
+            let value = self.eval(ast, *part)?;
+            let string = match value {
+                Value::String(_) => value.clone(),
+                _ => self.call_method(&value, "to_s", &[], None)?,
+            };
+            match &string {
+                Value::String(string) => bytes.extend_from_slice(&string.bytes.borrow()),
+                // a `to_s` that doesn't return a string is passed over for `#<Class>`
+                _ => bytes.extend_from_slice(self.string_of(&value).as_bytes()),
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn assign(&mut self, ast: &Rc<Ast>, target: &AssignTarget, value: Value) -> Result<(), Control> {
+        match target {
+            AssignTarget::Local(name) => self.frame().scope.set(name, value),
+            AssignTarget::InstanceVariable(name) => self.set_instance_variable(&self.self_value(), name, value)?,
+            AssignTarget::ClassVariable(name) => {
+                let module = self.class_variable_scope()?;
+                self.set_class_variable(&module, name, value);
+            },
+            AssignTarget::GlobalVariable(name) => self.set_global(name, value),
+            AssignTarget::Constant(None, name) => {
+                let module = self.frame().context.nesting.module.clone();
+                self.assign_constant(&module, name, value);
+            },
+            AssignTarget::Constant(Some(scope), name) => match self.eval(ast, *scope)? {
+                Value::Class(class) => self.assign_constant(&class, name, value),
+                scope => {
+                    let message = format!("{} is not a class/module", self.inspect(&scope)?);
+                    return Err(self.error("TypeError", &message));
+                }
+            },
+            AssignTarget::Attribute(receiver, name) => {
+                let self_call = matches!(ast[*receiver].kind, NodeKind::SelfRef);
+                let receiver = self.eval(ast, *receiver)?;
+                self.call_public(&receiver, &format!("{}=", name), &[value], None, self_call)?;
+            },
+            AssignTarget::Index(receiver, arguments) => {
+                let receiver = self.eval(ast, *receiver)?;
+                let mut arguments = self.eval_list(ast, arguments)?;
+                arguments.push(value);
+                self.call_method(&receiver, "[]=", &arguments, None)?;
+            },
+            AssignTarget::Splat(Some(target)) => {
+                let values = self.splat(value)?;
+                self.assign(ast, target, Value::array(values))?;
+            },
+            AssignTarget::Splat(None) => (),
+            AssignTarget::Nested(targets) => self.destructure(ast, targets, &value)?,
+        }
+
+        Ok(())
+    }
+
+    /// Spreads a value over several targets: an array's elements in order, with a splat taking
+    /// whatever the targets around it leave over. Missing values are `nil`.
+    fn destructure(&mut self, ast: &Rc<Ast>, targets: &[AssignTarget], value: &Value) -> Result<(), Control> {
+        let values = match value {
+            Value::Array(array) => array.elements.borrow().clone(),
+            value => vec!(value.clone()),
+        };
+        let splat = targets.iter().position(|target| matches!(target, AssignTarget::Splat(_)));
+        let after = splat.map_or(0, |splat| targets.len() - splat - 1);
+        for (i, target) in targets.iter().enumerate() {
+            let value = match splat {
+                Some(splat) if i == splat => {
+                    let end = values.len().saturating_sub(after).max(splat);
+                    Value::array(values.get(splat..end).unwrap_or(&[]).to_vec())
+                },
+                Some(splat) if i > splat => {
+                    let index = values.len().saturating_sub(after).max(splat) + (i - splat - 1);
+                    values.get(index).cloned().unwrap_or(Value::Nil)
+                },
+                _ => values.get(i).cloned().unwrap_or(Value::Nil),
+            };
+            match target {
+                AssignTarget::Splat(Some(target)) => self.assign(ast, target, value)?,
+                target => self.assign(ast, target, value)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates `target op= value`. The receiver and index arguments of the target are
+    /// evaluated once, and `||=` and `&&=` only evaluate and assign the value when needed.
+    fn eval_op_assign(&mut self, ast: &Rc<Ast>, target: &AssignTarget, op: &str, value: NodeId) -> EvalResult {
+        match target {
+            AssignTarget::Attribute(receiver, name) => {
+                let receiver = self.eval(ast, *receiver)?;
+                let current = self.call_method(&receiver, name, &[], None)?;
+                match self.apply_op(ast, current, op, value)? {
+                    (result, true) => self.call_method(&receiver, &format!("{}=", name), slice::from_ref(&result), None).map(|_| result),
+                    (result, false) => Ok(result),
+                }
+            },
+            AssignTarget::Index(receiver, arguments) => {
+                let receiver = self.eval(ast, *receiver)?;
+                let mut arguments = self.eval_list(ast, arguments)?;
+                let current = self.call_method(&receiver, "[]", &arguments, None)?;
+                match self.apply_op(ast, current, op, value)? {
+                    (result, true) => {
+                        arguments.push(result.clone());
+                        self.call_method(&receiver, "[]=", &arguments, None).map(|_| result)
+                    },
+                    (result, false) => Ok(result),
+                }
+            },
+            target => {
+                let current = match target {
+                    AssignTarget::Local(name) => self.frame().scope.get(name).unwrap_or(Value::Nil),
+                    AssignTarget::InstanceVariable(name) => self.instance_variable(&self.self_value(), name),
+                    AssignTarget::GlobalVariable(name) => self.global(name),
+                    AssignTarget::ClassVariable(name) => {
+                        let module = self.class_variable_scope()?;
+                        match self.class_variable(&module, name) {
+                            Some(value) => value,
+                            None if op == "||" => Value::Nil,
+                            None => {
+                                let message = format!("uninitialized class variable {} in {}", name, module.inspect());
+                                return Err(self.error("NameError", &message));
+                            }
+                        }
+                    },
+                    AssignTarget::Constant(None, name) if op == "||" => self.lexical_constant(name).unwrap_or(Value::Nil),
+                    AssignTarget::Constant(scope, name) => self.eval_constant(ast, *scope, name)?,
+                    _ => unreachable!("attributes and indexes are handled above"),
+                };
+                match self.apply_op(ast, current, op, value)? {
+                    (result, true) => self.assign(ast, target, result.clone()).map(|_| result),
+                    (result, false) => Ok(result),
+                }
+            },
+        }
+    }
+
+    /// The new value for `current op= value`, and whether it needs assigning.
+    fn apply_op(&mut self, ast: &Rc<Ast>, current: Value, op: &str, value: NodeId) -> Result<(Value, bool), Control> {
+        match op {
+            "||" if current.is_truthy() => Ok((current, false)),
+            "&&" if !current.is_truthy() => Ok((current, false)),
+            "||" | "&&" => Ok((self.eval(ast, value)?, true)),
+            op => {
+                let value = self.eval(ast, value)?;
+                Ok((self.call_method(&current, op, &[value], None)?, true))
+            }
+        }
+    }
+
+    fn eval_call(&mut self, ast: &Rc<Ast>, call: &Call, offset: usize) -> EvalResult {
+        let receiver = match call.receiver {
+            Some(receiver) => Some((self.eval(ast, receiver)?, matches!(ast[receiver].kind, NodeKind::SelfRef))),
+            None => None,
+        };
+        let arguments = self.eval_list(ast, &call.arguments)?;
+        let block = match call.block {
+            Some(block) => self.eval_block(ast, block)?,
+            None => None,
+        };
+
+        self.frame_mut().offset = offset;
+        let tag = self.enter_block(ast, call.block, block.as_ref());
+        let result = match receiver {
+            Some((receiver, self_call)) => self.call_public(&receiver, &call.method, &arguments, block.as_ref(), self_call),
+            None => self.call_function(&call.method, &arguments, block.as_ref(), false),
+        };
+        self.leave_block(tag, result)
+    }
+
+    /// Marks a literal block as running for the duration of the call it is given to, so a
+    /// `break` in it can end that call. Returns the tag of the block, if it was literal.
+    fn enter_block(&mut self, ast: &Rc<Ast>, id: Option<NodeId>, block: Option<&Rc<RProc>>) -> Option<usize> {
+        match (id, block) {
+            (Some(id), Some(block)) if matches!(ast[id].kind, NodeKind::Block(_, _)) => {
+                let tag = Rc::as_ptr(block) as usize;
+                self.blocks.push(tag);
+                Some(tag)
+            },
+            _ => None,
+        }
+    }
+
+    /// Ends the call started by `enter_block`, taking the value of a `break` from its block.
+    fn leave_block(&mut self, tag: Option<usize>, result: EvalResult) -> EvalResult {
+        let tag = match tag {
+            Some(tag) => tag,
+            None => return result,
+        };
+        self.blocks.pop();
+        match result {
+            Err(Control::Break(value, Some(target))) if target == tag => Ok(value),
+            result => result,
+        }
+    }
+
+    /// Turns the block of a call into a proc: a literal block closes over the current scope,
+    /// and `&value` passes on a proc or `nil`, or what `value.to_proc` gives.
+    fn eval_block(&mut self, ast: &Rc<Ast>, id: NodeId) -> Result<Option<Rc<RProc>>, Control> {
+        let value = match &ast[id].kind {
+            NodeKind::BlockPass(Some(value)) => self.eval(ast, *value)?,
+            NodeKind::BlockPass(None) => self.frame().scope.get("&").unwrap_or(Value::Nil),
+            _ => self.make_proc(ast, id, false),
+        };
+        let value = match value {
+            Value::Proc(_) | Value::Nil => value,
+            value if self.lookup_class(&value).find_method("to_proc").is_some() => self.call_method(&value, "to_proc", &[], None)?,
+            value => value,
+        };
+        match value {
+            Value::Proc(proc) => Ok(Some(proc)),
+            Value::Nil => Ok(None),
+            value => {
+                let message = format!("wrong argument type {} (expected Proc)", self.class_of(&value).inspect());
+                Err(self.error("TypeError", &message))
+            }
+        }
+    }
+
+    fn make_proc(&self, ast: &Rc<Ast>, id: NodeId, lambda: bool) -> Value {
+        Value::Proc(Rc::new(RProc {
+            body: ProcBody::Block(ast.clone(), id),
+            scope: self.frame().scope.clone(),
+            context: self.frame().context.clone(),
+            lambda,
+        }))
+    }
+
+    /// Adds the method a `def` node defines to the class the current frame defines methods
+    /// in, with the visibility the frame is set to.
+    fn define_method(&mut self, ast: &Rc<Ast>, id: NodeId, name: &str) {
+        let frame = self.frame();
+        let definee = frame.context.definee.clone();
+        let body = MethodBody::Defined(ast.clone(), id, frame.context.nesting.clone());
+        let visibility = match name {
+            "initialize" | "initialize_copy" | "respond_to_missing?" => Visibility::Private,
+            _ if frame.module_function => Visibility::Private,
+            _ => frame.visibility,
+        };
+        if frame.module_function {
+            self.metaclass(&definee).define(name, Method { body: body.clone(), visibility: Visibility::Public });
+        }
+        definee.define(name, Method { body, visibility });
+    }
+
+    /// Opens the class `name` for its body to run in, creating it first if the constant isn't
+    /// defined yet.
+    fn eval_class(&mut self, ast: &Rc<Ast>, name: NodeId, superclass: Option<NodeId>, body: &BodyStatement) -> EvalResult {
+        let (container, name) = self.definition_target(ast, name)?;
+        let superclass = match superclass {
+            Some(superclass) => match self.eval(ast, superclass)? {
+                Value::Class(class) if !class.is_module => Some(class),
+                value => {
+                    let message = format!("superclass must be an instance of Class (given an instance of {})", self.class_of(&value).inspect());
+                    return Err(self.error("TypeError", &message));
+                }
+            },
+            None => None,
+        };
+
+        let existing = container.constants.borrow().get(&name).cloned();
+        let class = match existing {
+            Some(Value::Class(class)) if !class.is_module => {
+                let mismatch = superclass.as_ref().is_some_and(|superclass| {
+                    !class.superclass().is_some_and(|current| Rc::ptr_eq(&current, superclass))
+                });
+                if mismatch {
+                    return Err(self.error("TypeError", &format!("superclass mismatch for class {}", name)));
+                }
+                class
+            },
+            Some(_) => return Err(self.error("TypeError", &format!("{} is not a class", name))),
+            None => {
+                let superclass = superclass.unwrap_or_else(|| self.classes.object.clone());
+                self.define_class(Some(&container), &name, superclass)?
+            }
+        };
+
+        self.eval_module_body(ast, class, body)
+    }
+
+    fn eval_module(&mut self, ast: &Rc<Ast>, name: NodeId, body: &BodyStatement) -> EvalResult {
+        let (container, name) = self.definition_target(ast, name)?;
+        let existing = container.constants.borrow().get(&name).cloned();
+        let module = match existing {
+            Some(Value::Class(module)) if module.is_module => module,
+            Some(_) => return Err(self.error("TypeError", &format!("{} is not a module", name))),
+            None => {
+                let module = RClass::module("");
+                self.assign_constant(&container, &name, Value::Class(module.clone()));
+                module
+            }
+        };
+
+        self.eval_module_body(ast, module, body)
+    }
+
+    /// The module a `class` or `module` keyword defines its constant in, and the name of the
+    /// constant.
+    fn definition_target(&mut self, ast: &Rc<Ast>, name: NodeId) -> Result<(Rc<RClass>, String), Control> {
+        match &ast[name].kind {
+            NodeKind::Constant(None, name) => Ok((self.frame().context.nesting.module.clone(), name.clone())),
+            NodeKind::Constant(Some(scope), name) => match self.eval(ast, *scope)? {
+                Value::Class(module) => Ok((module, name.clone())),
+                value => {
+                    let message = format!("{} is not a class/module", self.inspect(&value)?);
+                    Err(self.error("TypeError", &message))
+                }
+            },
+            _ => unreachable!("the parser only accepts constants as class names"),
+        }
+    }
+
+    /// Creates a subclass of `superclass`, as the constant `name` in `container` or as an
+    /// anonymous class, and tells the superclass through its `inherited` hook.
+    pub fn define_class(&mut self, container: Option<&Rc<RClass>>, name: &str, superclass: Rc<RClass>) -> Result<Rc<RClass>, Control> {
+        if superclass.is_singleton() {
+            return Err(self.error("TypeError", "can't make subclass of singleton class"));
+        }
+        if Rc::ptr_eq(&superclass, &self.classes.class) {
+            return Err(self.error("TypeError", "can't make subclass of Class"));
+        }
+
+        let class = RClass::new("", Some(superclass.clone()));
+        if let Some(container) = container {
+            self.assign_constant(container, name, Value::Class(class.clone()));
+        }
+        self.call_method(&Value::Class(superclass), "inherited", &[Value::Class(class.clone())], None)?;
+        Ok(class)
+    }
+
+    /// Runs the body of a class or module with the module as `self`, as the class `def`
+    /// defines methods in and as the innermost module for constants.
+    fn eval_module_body(&mut self, ast: &Rc<Ast>, module: Rc<RClass>, body: &BodyStatement) -> EvalResult {
+        let context = Context {
+            self_value: Value::Class(module.clone()),
+            nesting:    Nesting::new(module.clone(), Some(self.frame().context.nesting.clone())),
+            definee:    module,
+            method:     None,
+            returns_to: self.next_call(),
+        };
+        self.frames.push(Frame::new(Rc::new(context), Scope::new(), Some(ast.clone())));
+        let result = self.eval_body(ast, body);
+        self.frames.pop();
+
+        result
+    }
+
+    /// Calls the method the current one overrides: the next one with the same name in the
+    /// ancestors after the class it was found in. A bare `super` passes on the current
+    /// values of the method's parameters, and the method's block unless given another one.
+    fn eval_super(&mut self, ast: &Rc<Ast>, arguments: Option<&[NodeId]>, block: Option<NodeId>) -> EvalResult {
+        let call = match self.frame().context.method.clone() {
+            Some(call) => call,
+            None => return Err(self.error("RuntimeError", "super called outside of method")),
+        };
+        let arguments = match arguments {
+            Some(arguments) => self.eval_list(ast, arguments)?,
+            None => self.forwarded_arguments(&call)?,
+        };
+        let literal = block;
+        let block = match block {
+            Some(block) => self.eval_block(ast, block)?,
+            None => call.block.clone(),
+        };
+
+        let receiver = self.self_value();
+        let next = self.lookup_class(&receiver).ancestors().into_iter()
+            .skip_while(|class| !Rc::ptr_eq(class, &call.owner))
+            .skip(1)
+            .find_map(|class| {
+                let method = class.methods.borrow().get(&call.name).cloned();
+                method.map(|method| (method, class))
+            });
+        match next {
+            Some((method, owner)) => {
+                let tag = self.enter_block(ast, literal, block.as_ref());
+                let result = self.invoke(&receiver, &call.name, method, owner, &arguments, block.as_ref());
+                self.leave_block(tag, result)
+            },
+            None => {
+                let message = format!("super: no superclass method '{}' for {}", call.name, self.describe_receiver(&receiver));
+                Err(self.name_error("NoMethodError", &message, &receiver, &call.name))
+            }
+        }
+    }
+
+    fn forwarded_arguments(&mut self, call: &MethodCall) -> Result<Vec<Value>, Control> {
+        let (ast, id) = match &call.definition {
+            Some(definition) => definition,
+            None => {
+                let message = "implicit argument passing of super from method defined by define_method() is not supported. Specify all arguments explicitly.";
+                return Err(self.error("RuntimeError", message));
+            }
+        };
+        let params = match &ast[*id].kind {
+            NodeKind::Def(_, params, _) | NodeKind::SingletonDef(_, _, params, _) => params,
+            _ => unreachable!("method `{}` is not a def", call.name),
+        };
+
+        let scope = self.frame().scope.clone();
+        let value_of = |target: &AssignTarget| match target {
+            AssignTarget::Local(name) => scope.get(name).unwrap_or(Value::Nil),
+            target => unreachable!("a method's parameter is a name, not {:?}", target),
+        };
+        let mut arguments: Vec<Value> = params.required.iter().map(value_of)
+            .chain(params.optional.iter().map(|(name, _)| scope.get(name).unwrap_or(Value::Nil)))
+            .collect();
+        if let Some(rest) = &params.rest {
+            arguments.extend(self.splat(scope.get(rest).unwrap_or(Value::Nil))?);
+        }
+        arguments.extend(params.post.iter().map(value_of));
+
+        Ok(arguments)
+    }
+
+    /// Calls a method on `self` without an explicit receiver, like `foo(1)`. `variable` is set
+    /// when the call was a bare name that could also have been a local variable.
+    fn call_function(&mut self, name: &str, arguments: &[Value], block: Option<&Rc<RProc>>, variable: bool) -> EvalResult {
+        let receiver = self.self_value();
+        match self.lookup_class(&receiver).find_method(name) {
+            Some((method, owner)) => self.invoke(&receiver, name, method, owner, arguments, block),
+            None => self.method_missing(&receiver, name, arguments, block, variable),
+        }
+    }
+
+    /// Calls a method with an explicit receiver, as `receiver.name(arguments)` does. Private
+    /// methods can only be called this way on `self`, and protected ones from methods of
+    /// the class that defined them.
+    pub fn call_public(&mut self, receiver: &Value, name: &str, arguments: &[Value], block: Option<&Rc<RProc>>, self_call: bool) -> EvalResult {
+        let (method, owner) = match self.lookup_class(receiver).find_method(name) {
+            Some(found) => found,
+            None => return self.method_missing(receiver, name, arguments, block, false),
+        };
+        let allowed = match method.visibility {
+            Visibility::Public => true,
+            Visibility::Private => self_call,
+            Visibility::Protected => self_call || self.lookup_class(&self.self_value()).inherits(&owner),
+        };
+        if !allowed {
+            let visibility = if method.visibility == Visibility::Private { "private" } else { "protected" };
+            let message = format!("{} method '{}' called for {}", visibility, name, self.describe_receiver(receiver));
+            return Err(self.name_error("NoMethodError", &message, receiver, name));
+        }
+
+        self.invoke(receiver, name, method, owner, arguments, block)
+    }
+
+    /// Calls the method `name` on `receiver` whatever its visibility, as `send` does.
+    pub fn call_method(&mut self, receiver: &Value, name: &str, arguments: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+        match self.lookup_class(receiver).find_method(name) {
+            Some((method, owner)) => self.invoke(receiver, name, method, owner, arguments, block),
+            None => self.method_missing(receiver, name, arguments, block, false),
+        }
+    }
+
+    /// Hands a call to an undefined method to the receiver's `method_missing`, with the name
+    /// as a symbol before the arguments, or raises the error the builtin one would.
+    fn method_missing(&mut self, receiver: &Value, name: &str, arguments: &[Value], block: Option<&Rc<RProc>>, variable: bool) -> EvalResult {
+        if let Some((method, owner)) = self.lookup_class(receiver).find_method("method_missing") {
+            if !Rc::ptr_eq(&owner, &self.classes.basic_object) {
+                let mut missing = vec!(Value::symbol(name));
+                missing.extend(arguments.iter().cloned());
+                return self.invoke(receiver, "method_missing", method, owner, &missing, block);
+            }
+        }
+
+        if variable {
+            let message = format!("undefined local variable or method '{}' for {}", name, self.describe_receiver(receiver));
+            return Err(self.name_error("NameError", &message, receiver, name));
+        }
+        Err(self.no_method_error(receiver, name))
+    }
+
+    fn invoke(&mut self, receiver: &Value, name: &str, method: Method, owner: Rc<RClass>, arguments: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(self.error("SystemStackError", "stack level too deep"));
+        }
+        let (ast, id, nesting) = match method.body {
+            MethodBody::Builtin(builtin) => return builtin(self, receiver, arguments, block),
+            MethodBody::AttrReader(ivar) => {
+                builtins::check_arity(self, arguments, 0, 0)?;
+                return Ok(self.instance_variable(receiver, &ivar));
+            },
+            MethodBody::AttrWriter(ivar) => {
+                builtins::check_arity(self, arguments, 1, 1)?;
+                self.set_instance_variable(receiver, &ivar, arguments[0].clone())?;
+                return Ok(arguments[0].clone());
+            },
+            MethodBody::Proc(proc) => {
+                let call = MethodCall { name: name.to_string(), owner, definition: None, block: block.cloned() };
+                let context = Context {
+                    self_value: receiver.clone(),
+                    definee:    proc.context.definee.clone(),
+                    nesting:    proc.context.nesting.clone(),
+                    method:     Some(Rc::new(call)),
+                    returns_to: proc.context.returns_to,
+                };
+                return self.call_proc_in(&proc, Rc::new(context), arguments, block, true);
+            },
+            MethodBody::Defined(ast, id, nesting) => (ast, id, nesting),
+        };
+        let (params, body) = match &ast[id].kind {
+            NodeKind::Def(_, params, body) | NodeKind::SingletonDef(_, _, params, body) => (params, body),
+            _ => unreachable!("method `{}` is not a def", name),
+        };
+
+        let call = MethodCall { name: name.to_string(), owner, definition: Some((ast.clone(), id)), block: block.cloned() };
+        let id = self.next_call();
+        let context = Context {
+            self_value: receiver.clone(),
+            definee:    nesting.module.clone(),
+            nesting,
+            method:     Some(Rc::new(call)),
+            returns_to: id,
+        };
+        self.frames.push(Frame { returns: true, ..Frame::new(Rc::new(context), Scope::new(), Some(ast.clone())) });
+        let result = self.bind_parameters(&ast, params, arguments, block, true).and_then(|_| self.eval_body(&ast, body));
+        self.frames.pop();
+
+        match result {
+            Err(Control::Return(value, target)) if target == id => Ok(value),
+            result => result,
+        }
+    }
+
+    /// Calls a proc with `arguments`, in a new scope inside the one it was created in. `next`
+    /// ends the call with its value, as does `return` in a lambda.
+    pub fn call_proc(&mut self, proc: &Rc<RProc>, arguments: &[Value]) -> EvalResult {
+        self.call_proc_in(proc, proc.context.clone(), arguments, None, proc.lambda)
+    }
+
+    /// A block running `f`, for builtins to pass to methods that take one.
+    pub fn native_proc(&self, f: impl Fn(&mut Interpreter, &[Value]) -> EvalResult + 'static) -> Rc<RProc> {
+        Rc::new(RProc {
+            body:    ProcBody::Native(Rc::new(f)),
+            scope:   self.frame().scope.clone(),
+            context: self.frame().context.clone(),
+            lambda:  false,
+        })
+    }
+
+    /// Calls a proc passing it a block of its own, as `Proc#call` does.
+    pub fn call_proc_with_block(&mut self, proc: &Rc<RProc>, arguments: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+        self.call_proc_in(proc, proc.context.clone(), arguments, block, proc.lambda)
+    }
+
+    /// Calls a proc with `self_value` as `self` and `definee` as the class `def` defines
+    /// methods in, as `instance_eval` and `class_eval` do.
+    pub fn call_proc_as(&mut self, proc: &Rc<RProc>, self_value: Value, definee: Rc<RClass>, arguments: &[Value]) -> EvalResult {
+        let context = Context {
+            self_value,
+            definee,
+            nesting: proc.context.nesting.clone(),
+            method:  proc.context.method.clone(),
+            returns_to: proc.context.returns_to,
+        };
+        self.call_proc_in(proc, Rc::new(context), arguments, None, proc.lambda)
+    }
+
+    /// Runs a proc in `context`. A lambda checks its arguments like a method and is what its
+    /// `return` and `break` leave; a proc's `break` leaves the call it was given to, which
+    /// must still be running.
+    fn call_proc_in(&mut self, proc: &Rc<RProc>, context: Rc<Context>, arguments: &[Value], block: Option<&Rc<RProc>>, lambda: bool) -> EvalResult {
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(self.error("SystemStackError", "stack level too deep"));
+        }
+        let (ast, node) = match &proc.body {
+            ProcBody::Block(ast, node) => (ast.clone(), *node),
+            ProcBody::Native(native) => return native.clone()(self, arguments),
+        };
+        let (params, body) = match &ast[node].kind {
+            NodeKind::Block(params, body) | NodeKind::Lambda(params, body) => (params, body),
+            _ => unreachable!("a proc is made from a block or a lambda"),
+        };
+
+        let tag = Rc::as_ptr(proc) as usize;
+        let context = match lambda {
+            true => Rc::new(Context { returns_to: self.next_call(), ..(*context).clone() }),
+            false => context,
+        };
+        let id = context.returns_to;
+        self.frames.push(Frame { block: Some(tag), returns: lambda, ..Frame::new(context, Scope::child(&proc.scope), Some(ast.clone())) });
+        let result = self.bind_parameters(&ast, params, arguments, block, lambda).and_then(|_| self.eval_body(&ast, body));
+        self.frames.pop();
+
+        match result {
+            Err(Control::Next(value)) => Ok(value),
+            Err(Control::Return(value, target)) if lambda && target == id => Ok(value),
+            Err(Control::Break(value, Some(target))) if lambda && target == tag => Ok(value),
+            Err(Control::Break(_, Some(target))) if target == tag && !self.blocks.contains(&tag) => {
+                Err(self.error("LocalJumpError", "break from proc-closure"))
+            },
+            result => result,
+        }
+    }
+
+    /// Binds arguments to the parameters of the method or proc in the current frame, and the
+    /// block to a `&block` parameter. Required parameters take arguments from both ends,
+    /// then optional ones take what is left in order, and the rest gets the remainder. A
+    /// method or lambda raises `ArgumentError` when there are too few or too many arguments;
+    /// a proc fills in `nil` for missing ones, drops the extra ones and spreads a lone array
+    /// over several parameters. Defaults are evaluated in the new scope, so they can refer
+    /// to the parameters before them.
+    fn bind_parameters(
+        &mut self,
+        ast: &Rc<Ast>,
+        params: &Parameters,
+        arguments: &[Value],
+        block: Option<&Rc<RProc>>,
+        strict: bool,
+    ) -> Result<(), Control> {
+        let leading = params.required.len() + params.numbered + usize::from(params.it);
+        let required = leading + params.post.len();
+        let optional = params.optional.len();
+        let spread;
+        let arguments = match arguments {
+            [Value::Array(array)] if !strict && (required + optional > 1 || (required + optional > 0 && params.rest.is_some())) => {
+                spread = array.elements.borrow().clone();
+                &spread[..]
+            },
+            arguments => arguments,
+        };
+        if strict && (arguments.len() < required || (params.rest.is_none() && arguments.len() > required + optional)) {
+            let expected = match (&params.rest, optional) {
+                (Some(_), _) => format!("{}+", required),
+                (None, 0) => required.to_string(),
+                (None, optional) => format!("{}..{}", required, required + optional),
+            };
+            let message = format!("wrong number of arguments (given {}, expected {})", arguments.len(), expected);
+            return Err(self.error("ArgumentError", &message));
+        }
+        let arguments = match params.rest {
+            None => &arguments[..arguments.len().min(required + optional)],
+            Some(_) => arguments,
+        };
+        let (first, others) = arguments.split_at(arguments.len().min(leading));
+        let (middle, last) = others.split_at(others.len() - others.len().min(params.post.len()));
+
+        let scope = self.frame().scope.clone();
+        let mut first = first.iter().cloned();
+        for target in params.required.iter() {
+            self.bind_parameter(ast, target, first.next().unwrap_or(Value::Nil))?;
+        }
+        for n in 1..=params.numbered {
+            scope.declare(&format!("_{}", n), first.next().unwrap_or(Value::Nil));
+        }
+        if params.it {
+            scope.declare("it", first.next().unwrap_or(Value::Nil));
+        }
+        let mut middle = middle.iter().cloned();
+        for (name, default) in params.optional.iter() {
+            let value = match middle.next() {
+                Some(value) => value,
+                None => self.eval(ast, *default)?,
+            };
+            scope.declare(name, value);
+        }
+        // an anonymous `*` is kept too, for `super` and `f(*)` to pass on
+        if let Some(rest) = &params.rest {
+            scope.declare(rest, Value::array(middle.collect()));
+        }
+        let mut last = last.iter().cloned();
+        for target in params.post.iter() {
+            self.bind_parameter(ast, target, last.next().unwrap_or(Value::Nil))?;
+        }
+        if let Some(name) = &params.block {
+            scope.declare(name, block.map_or(Value::Nil, |block| Value::Proc(block.clone())));
+        }
+
+        Ok(())
+    }
+
+    /// Binds a required parameter, destructuring the value over a pattern. The names in the
+    /// pattern are declared first, so they shadow any outside the block as parameters do.
+    fn bind_parameter(&mut self, ast: &Rc<Ast>, target: &AssignTarget, value: Value) -> Result<(), Control> {
+        fn declare(scope: &Scope, target: &AssignTarget) {
+            match target {
+                AssignTarget::Local(name) => scope.declare(name, Value::Nil),
+                AssignTarget::Splat(Some(target)) => declare(scope, target),
+                AssignTarget::Nested(targets) => targets.iter().for_each(|target| declare(scope, target)),
+                _ => (),
+            }
+        }
+
+        let scope = self.frame().scope.clone();
+        declare(&scope, target);
+        self.assign(ast, target, value)
+    }
+
+    pub fn class_of(&self, value: &Value) -> Rc<RClass> {
+        let classes = &self.classes;
+        match value {
+            Value::Nil => classes.nil.clone(),
+            Value::Bool(true) => classes.true_class.clone(),
+            Value::Bool(false) => classes.false_class.clone(),
+            Value::Integer(_) | Value::Bignum(_) => classes.integer.clone(),
+            Value::Float(_) => classes.float.clone(),
+            Value::String(_) => classes.string.clone(),
+            Value::Symbol(_) => classes.symbol.clone(),
+            Value::Array(_) => classes.array.clone(),
+            Value::Hash(_) => classes.hash.clone(),
+            Value::Range(_) => classes.range.clone(),
+            Value::Proc(_) => classes.proc_class.clone(),
+            Value::Object(object) => object.class.clone(),
+            Value::Class(class) if class.is_module => classes.module.clone(),
+            Value::Class(_) => classes.class.clone(),
+        }
+    }
+
+    /// Where method lookup for `value` starts: its singleton class if it has one, and always
+    /// the metaclass for a class, so class methods are inherited.
+    pub fn lookup_class(&self, value: &Value) -> Rc<RClass> {
+        match value {
+            Value::Object(object) => object.singleton.borrow().clone().unwrap_or_else(|| object.class.clone()),
+            Value::Class(class) => self.metaclass(class),
+            value => self.class_of(value),
+        }
+    }
+
+    /// The singleton class of a class or module, created on first use. A class's metaclass
+    /// inherits from its superclass's metaclass, ending in `Class`.
+    pub fn metaclass(&self, class: &Rc<RClass>) -> Rc<RClass> {
+        if let Some(metaclass) = class.singleton.borrow().clone() { return metaclass; }
+
+        let superclass = match class.superclass() {
+            _ if class.is_singleton() => self.classes.class.clone(),
+            _ if class.is_module => self.classes.module.clone(),
+            Some(superclass) => self.metaclass(&superclass),
+            None => self.classes.class.clone(),
+        };
+        let metaclass = RClass::singleton(superclass, Value::Class(class.clone()));
+        *class.singleton.borrow_mut() = Some(metaclass.clone());
+        metaclass
+    }
+
+    /// The singleton class of an object, where `def object.name` and `extend` put methods.
+    pub fn singleton_class(&self, value: &Value) -> Result<Rc<RClass>, Control> {
+        match value {
+            Value::Object(object) => {
+                if let Some(singleton) = object.singleton.borrow().clone() { return Ok(singleton); }
+                let singleton = RClass::singleton(object.class.clone(), value.clone());
+                *object.singleton.borrow_mut() = Some(singleton.clone());
+                Ok(singleton)
+            },
+            Value::Class(class) => Ok(self.metaclass(class)),
+            Value::Nil | Value::Bool(_) => Ok(self.class_of(value)),
+            _ => Err(self.error("TypeError", "can't define singleton")),
+        }
+    }
+
+    /// Whether `value` is an instance of the top level class `class_name` or one of its
+    /// subclasses.
+    pub fn is_a(&self, value: &Value, class_name: &str) -> bool {
+        match self.constant(class_name) {
+            Some(Value::Class(class)) => self.lookup_class(value).inherits(&class),
+            _ => false,
+        }
+    }
+
+    /// Creates an exception of the builtin class `class_name`, like `KeyError` or
+    /// `Encoding::CompatibilityError`, to be raised.
+    pub fn error(&self, class_name: &str, message: &str) -> Control {
+        let class = class_name.split("::").try_fold(self.classes.object.clone(), |scope, name| {
+            match scope.constants.borrow().get(name) {
+                Some(Value::Class(class)) => Some(class.clone()),
+                _ => None,
+            }
+        });
+        let class = class.unwrap_or_else(|| panic!("{} is not a builtin exception class", class_name));
+        let exception = Value::object(class);
+        if let Value::Object(object) = &exception {
+            object.set("message", Value::string(message));
+            object.set("backtrace", self.backtrace());
+        }
+
+        Control::Raise(exception)
+    }
+
+    /// Where each running frame is, innermost first, as `file:line:in 'method'` strings.
+    pub fn backtrace(&self) -> Value {
+        Value::array(self.frames.iter().rev().map(|frame| Value::string(&frame.position())).collect())
+    }
+
+    /// Describes an exception nobody rescued the way Ruby does: where it was raised, its
+    /// message and class, then the rest of the backtrace.
+    pub fn report(&mut self, exception: &Value) -> String {
+        builtins::exception::full_message(self, exception)
+    }
+
+    /// The status a `SystemExit` asks the process to exit with, or None for any other
+    /// exception.
+    pub fn exit_status(&self, exception: &Value) -> Option<i32> {
+        if !self.is_a(exception, "SystemExit") { return None; }
+        match self.instance_variable(exception, "status") {
+            Value::Integer(status) => Some(status as i32),
+            _ => Some(0),
+        }
+    }
+
+    /// Writes out whatever the program printed that is still buffered.
+    pub fn flush_output(&mut self) -> io::Result<()> {
+        self.output.flush()?;
+        self.error_output.flush()
+    }
+
+    /// Sets `ARGV` and `$0`, which is also `$PROGRAM_NAME`, for the script being run.
+    pub fn set_arguments(&mut self, program: &str, arguments: &[String]) {
+        let arguments = arguments.iter().map(|argument| Value::string(argument)).collect();
+        self.set_constant("ARGV", Value::array(arguments));
+        self.set_global("$0", Value::string(program));
+        self.set_global("$PROGRAM_NAME", Value::string(program));
+    }
+
+    pub fn no_method_error(&self, receiver: &Value, name: &str) -> Control {
+        let message = format!("undefined method '{}' for {}", name, self.describe_receiver(receiver));
+        self.name_error("NoMethodError", &message, receiver, name)
+    }
+
+    /// A `NameError` or `NoMethodError` for looking `name` up on `receiver`, which it answers
+    /// `name` and `receiver` with.
+    pub fn name_error(&self, class: &str, message: &str, receiver: &Value, name: &str) -> Control {
+        let error = self.error(class, message);
+        if let Control::Raise(Value::Object(exception)) = &error {
+            exception.set("name", Value::symbol(name));
+            exception.set("receiver", receiver.clone());
+        }
+        error
+    }
+
+    /// How error messages refer to the receiver of a missing method.
+    fn describe_receiver(&self, receiver: &Value) -> String {
+        match receiver {
+            Value::Nil => String::from("nil"),
+            Value::Bool(value) => value.to_string(),
+            Value::Class(class) if class.is_module => format!("module {}", class.inspect()),
+            Value::Class(class) => format!("class {}", class.inspect()),
+            value if value.is_identical(&self.main) => String::from("main"),
+            value => format!("an instance of {}", self.class_of(value).inspect()),
+        }
+    }
+
+    /// Stacks for a coroutine, starting from a frame like the top level.
+    fn new_stacks(&self) -> Stacks {
+        let top = &self.frames[0];
+        let frame = Frame { visibility: Visibility::Private, returns: true, ..Frame::new(top.context.clone(), top.scope.clone(), top.ast.clone()) };
+        Stacks { frames: vec!(frame), blocks: vec!(), recursion: vec!() }
+    }
+
+    /// Goes on with `stacks`, giving back the ones that were in use.
+    fn switch_stacks(&mut self, stacks: Stacks) -> Stacks {
+        Stacks {
+            frames:    std::mem::replace(&mut self.frames, stacks.frames),
+            blocks:    std::mem::replace(&mut self.blocks, stacks.blocks),
+            recursion: std::mem::replace(&mut self.recursion, stacks.recursion),
+        }
+    }
+
+    /// Runs `f` unless it is already running for `value` under the same `tag`, which is how
+    /// an array that contains itself is inspected as `[...]`. Gives `None` in that case.
+    pub fn guard_recursion<T>(&mut self, tag: &'static str, value: &Value, f: impl FnOnce(&mut Self) -> Result<T, Control>) -> Result<Option<T>, Control> {
+        let key = (tag, value.address());
+        if self.recursion.contains(&key) { return Ok(None); }
+
+        self.recursion.push(key);
+        let result = f(self);
+        self.recursion.pop();
+        result.map(Some)
+    }
+
+    /// The result of calling `inspect` on `value`.
+    pub fn inspect(&mut self, value: &Value) -> Result<String, Control> {
+        let inspected = self.call_method(value, "inspect", &[], None)?;
+        Ok(self.string_of(&inspected))
+    }
+
+    /// The result of calling `to_s` on `value`.
+    pub fn to_s(&mut self, value: &Value) -> Result<String, Control> {
+        if let Value::String(string) = value { return Ok(string.text()); }
+        let string = self.call_method(value, "to_s", &[], None)?;
+        Ok(self.string_of(&string))
+    }
+
+    /// The contents of a string value, or Ruby's fallback `#<Class>` when a `to_s` or
+    /// `inspect` returned something else.
+    fn string_of(&self, value: &Value) -> String {
+        match value {
+            Value::String(string) => string.text(),
+            value => format!("#<{}>", self.class_of(value).inspect()),
+        }
+    }
+
+    /// The message of an exception object.
+    pub fn exception_message(&self, exception: &Value) -> String {
+        match self.instance_variable(exception, "message") {
+            Value::String(message) => message.text(),
+            _ => self.class_of(exception).name(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::path::{ Path, PathBuf };
+    use std::process;
+
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use super::*;
+
+    fn run(interpreter: &mut Interpreter, input: &str) -> EvalResult {
+        let mut lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+        assert!(errors.is_empty(), "parser errors: {:?}", errors);
+
+        interpreter.eval_program(program)
+    }
+
+    /// Evaluates `input` and returns the `inspect` of its value, or `Class: message` for an
+    /// exception.
+    fn eval(input: &str) -> String {
+        let mut interpreter = Interpreter::new();
+        match run(&mut interpreter, input) {
+            Ok(value) => interpreter.inspect(&value).unwrap(),
+            Err(Control::Raise(exception)) => {
+                format!("{}: {}", interpreter.class_of(&exception).name(), interpreter.exception_message(&exception))
+            },
+            Err(control) => panic!("unexpected {:?}", control),
+        }
+    }
+
+    #[test]
+    fn should_evaluate_literals() {
+        assert_eq!(eval("nil"), "nil");
+        assert_eq!(eval("true; false"), "false");
+        assert_eq!(eval("-42"), "-42");
+        assert_eq!(eval("1.5"), "1.5");
+        assert_eq!(eval("'it\\'s'"), "\"it's\"");
+        assert_eq!(eval(":sym"), ":sym");
+        assert_eq!(eval("[1, [2.0, nil], :a, 'b']"), "[1, [2.0, nil], :a, \"b\"]");
+        assert_eq!(eval("{ a: 1, 'b' => [2], 3 => nil }"), "{a: 1, \"b\" => [2], 3 => nil}");
+        assert_eq!(eval("[1..2, (1...), (..'z')]"), "[1..2, 1..., ..\"z\"]");
+        assert_eq!(eval("->(x) { x }.lambda?"), "true");
+    }
+
+    #[test]
+    fn should_evaluate_operators_as_method_calls() {
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), "5");
+        assert_eq!(eval("-7 / 2"), "-4");
+        assert_eq!(eval("-7 % 3"), "2");
+        assert_eq!(eval("2 ** 10"), "1024");
+        assert_eq!(eval("1 + 2.5"), "3.5");
+        assert_eq!(eval("7.0 / 2"), "3.5");
+        assert_eq!(eval("'ab' + 'c' * 2"), "\"abcc\"");
+        assert_eq!(eval("[1, 2] == [1, 2] && 1 != 2"), "true");
+        assert_eq!(eval("1 <=> 2"), "-1");
+        assert_eq!(eval("5 & 3 | 8 ^ 1"), "8");
+        assert_eq!(eval("1 << 4 >> 2"), "4");
+        assert_eq!(eval("!nil"), "true");
+        assert_eq!(eval("1 / 0"), "ZeroDivisionError: divided by 0");
+        assert_eq!(eval("1 + 'a'"), "TypeError: String can't be coerced into Integer");
+        assert_eq!(eval("nil + 1"), "NoMethodError: undefined method '+' for nil");
+    }
+
+    #[test]
+    fn should_promote_integers_to_bignums() {
+        assert_eq!(eval("2 ** 100"), "1267650600228229401496703205376");
+        assert_eq!(eval("9223372036854775807 + 1"), "9223372036854775808");
+        assert_eq!(eval("-9223372036854775808 - 1"), "-9223372036854775809");
+        assert_eq!(eval("x = 123456789012345678901234567890\n[x.class, x * x / x == x, (x + 1 - x).class]"), "[Integer, true, Integer]");
+        assert_eq!(eval("f = 1\ni = 1\nwhile i <= 25\n  f *= i\n  i += 1\nend\nf"), "15511210043330985984000000");
+        assert_eq!(eval("[-(2 ** 64) / 3, -(2 ** 64) % 3, (2 ** 64).divmod(-7), 7.divmod(-2), -7.remainder(2)]"),
+            "[-6148914691236517206, 2, [-2635249153387078803, -5], [-4, -1], -1]");
+        assert_eq!(eval("[3.pow(1000, 7), 2.pow(10 ** 20, 1_000_000_007), 5.pow(3, -7)]"), "[4, 855473248, -1]");
+        assert_eq!(eval("[-(2 ** 70) & 255, -(2 ** 70) | 1, (2 ** 70) ^ -1, ~(2 ** 70), -(2 ** 70) >> 68, -1 >> 100, (2 ** 70)[70]]"),
+            "[0, -1180591620717411303423, -1180591620717411303425, -1180591620717411303425, -4, -1, 1]");
+        assert_eq!(eval("[255.to_s(2), (2 ** 64).to_s(16), -255.to_s(36), 255.to_s(1)]"), "ArgumentError: invalid radix 1");
+        assert_eq!(eval("[255.to_s(2), (2 ** 64).to_s(16), -255.to_s(36), (2 ** 64).to_f == 2.0 ** 64, (2 ** 64) > 1.5, 1e20.to_i]"),
+            "[\"11111111\", \"10000000000000000\", \"-73\", true, true, 100000000000000000000]");
+        assert_eq!(eval("[(2 ** 64) == 2 ** 64, (2 ** 64).eql?(2 ** 64), (2 ** 64).hash == (2 ** 64).hash, 12.gcd(18), 4.lcm(6), 1234.digits]"),
+            "[true, true, true, 6, 12, [4, 3, 2, 1]]");
+    }
+
+    #[test]
+    fn should_print_and_round_floats_like_ruby() {
+        assert_eq!(eval("[1.0, 2.5, 100.0, 1e15, 1e16, 1e20, 1.5e-5, 0.0001, -0.0, 0.1 + 0.2, 2.0 ** 64]"),
+            "[1.0, 2.5, 100.0, 1000000000000000.0, 1.0e+16, 1.0e+20, 1.5e-05, 0.0001, -0.0, 0.30000000000000004, 1.8446744073709552e+19]");
+        assert_eq!(eval("[Float::INFINITY, -Float::INFINITY, Float::NAN.nan?, Float::NAN == Float::NAN, 1 / 0.0, Float::MAX > 10 ** 300]"),
+            "[Infinity, -Infinity, true, false, Infinity, true]");
+        assert_eq!(eval("[2.5.round, 3.5.round, -2.5.round, 2.5.round(half: :even), 2.5.round(half: :down), 2.675.round(2), 1.23456.round(3)]"),
+            "[3, 4, -3, 2, 2, 2.68, 1.235]");
+        assert_eq!(eval("[0.125.round(2, half: :even), 1234.5678.round(-2), 1234.5678.floor(2), 1234.5678.ceil(-2), -1.55.floor(1), 0.29.floor(2)]"),
+            "[0.12, 1200, 1234.56, 1300, -1.6, 0.29]");
+        assert_eq!(eval("[15.round(-1), 25.round(-1, half: :even), -15.round(-1), 1234.floor(-2), -1234.ceil(-2), 1234.truncate(-3), 5.round(2)]"),
+            "[20, 20, -20, 1200, -1200, 1000, 5]");
+        assert_eq!(eval("2.5.round(half: :sideways)"), "ArgumentError: invalid rounding mode: sideways");
+        assert_eq!(eval("Float::NAN.round"), "FloatDomainError: NaN");
+        assert_eq!(eval("[(2 ** 64 + 1) > 2.0 ** 64, 2 ** 64 == 2.0 ** 64, 1 == 1.0, 1.eql?(1.0), 3 <=> 2.5, 1 < Float::INFINITY]"),
+            "[true, true, true, false, 1, true]");
+    }
+
+    #[test]
+    fn should_coerce_mixed_numeric_operands() {
+        assert_eq!(eval("[1 + 2.5, 2.5 * 2, 7 / 2.0, 1.coerce(2), 1.coerce(2.5), 1.5.coerce(2)]"),
+            "[3.5, 5.0, 3.5, [2, 1], [2.5, 1.0], [2.0, 1.5]]");
+        let money = "class Money\n  attr_reader :cents\n  def initialize(cents)\n    @cents = cents\n  end\n  \
+            def coerce(other)\n    [Money.new(other * 100), self]\n  end\n  def +(other)\n    Money.new(cents + other.cents)\n  end\n  \
+            def <=>(other)\n    cents <=> other.cents\n  end\n  def <(other)\n    cents < other.cents\n  end\nend\n";
+        assert_eq!(eval(&format!("{}(2 + Money.new(50)).cents", money)), "250");
+        assert_eq!(eval(&format!("{}[3 < Money.new(500), 3 <=> Money.new(300)]", money)), "[true, 0]");
+        assert_eq!(eval("1 + 'a'"), "TypeError: String can't be coerced into Integer");
+        assert_eq!(eval("1 < nil"), "ArgumentError: comparison of Integer with nil failed");
+    }
+
+    #[test]
+    fn should_parse_integers_with_kernel_integer() {
+        assert_eq!(eval("[Integer('0x1f'), Integer('-0b101'), Integer('0o17'), Integer('017'), Integer(' 1_000 '), Integer('ff', 16), Integer('z', 36)]"),
+            "[31, -5, 15, 15, 1000, 255, 35]");
+        assert_eq!(eval("[Integer('123456789012345678901234567890'), Integer(3.99), Integer(-2 ** 70)]"),
+            "[123456789012345678901234567890, 3, -1180591620717411303424]");
+        assert_eq!(eval("Integer('1__0')"), "ArgumentError: invalid value for Integer(): \"1__0\"");
+        assert_eq!(eval("Integer('0x1g')"), "ArgumentError: invalid value for Integer(): \"0x1g\"");
+        assert_eq!(eval("Integer(nil)"), "TypeError: can't convert nil into Integer");
+        assert_eq!(eval("Integer(12, 2)"), "ArgumentError: base specified for non string value");
+    }
+
+    #[test]
+    fn should_treat_only_nil_and_false_as_falsy() {
+        assert_eq!(eval("[0 ? 1 : 2, '' ? 1 : 2, nil ? 1 : 2, false ? 1 : 2]"), "[1, 1, 2, 2]");
+        assert_eq!(eval("[nil || 0, false && 1, 1 && 2, (x = 3 unless nil)]"), "[0, false, 2, 3]");
+    }
+
+    #[test]
+    fn should_keep_locals_in_scope() {
+        assert_eq!(eval("x = 1\nx += 2\ny ||= x * 2\nx, y = y, x\n[x, y]"), "[6, 3]");
+        assert_eq!(eval("a, (b, *c), d = 1, [2, 3, 4], 5\n[a, b, c, d]"), "[1, 2, [3, 4], 5]");
+        assert_eq!(eval("*a, b = 1, 2, 3\n[a, b]"), "[[1, 2], 3]");
+        assert_eq!(eval("h = {}\nh[:k] ||= []\nh[:k] += [1]\nh"), "{k: [1]}");
+        assert_eq!(eval("undefined_thing"), "NameError: undefined local variable or method 'undefined_thing' for main");
+    }
+
+    #[test]
+    fn should_run_conditionals_and_loops() {
+        assert_eq!(eval("x = 5\nif x > 10 then :big elsif x > 3 then :mid else :small end"), ":mid");
+        assert_eq!(eval("i = 0\ntotal = 0\nwhile i < 10\n  i += 1\n  next if i.odd?\n  total += i\nend\ntotal"), "30");
+        assert_eq!(eval("i = 0\ni += 1 until i * i > 50\ni"), "8");
+        assert_eq!(eval("while true do break 42 end"), "42");
+        assert_eq!(eval("def kind(x)\n  case x\n  when Integer, Float then :number\n  when 'a'..'m' then :early\n  when String then :string\n  else :other\n  end\nend\n[kind(1), kind(2.0), kind('c'), kind('z'), kind(nil)]"),
+            "[:number, :number, :early, :string, :other]");
+        assert_eq!(eval("x = 7\ncase\nwhen x < 5 then :low\nwhen x < 10 then :high\nend"), ":high");
+    }
+
+    #[test]
+    fn should_define_and_invoke_methods() {
+        assert_eq!(eval("def fib(n)\n  return n if n < 2\n  fib(n - 1) + fib(n - 2)\nend\nfib(15)"), "610");
+        assert_eq!(eval("def greet(name, greeting = 'Hello', *rest)\n  [greeting + ', ' + name, rest]\nend\n[greet('Ann'), greet('Bo', 'Hi', 1, 2)]"),
+            "[[\"Hello, Ann\", []], [\"Hi, Bo\", [1, 2]]]");
+        assert_eq!(eval("def two(a, b)\nend\ntwo(1)"), "ArgumentError: wrong number of arguments (given 1, expected 2)");
+        assert_eq!(eval("def opt(a, b = 1)\nend\nopt"), "ArgumentError: wrong number of arguments (given 0, expected 1..2)");
+        assert_eq!(eval("x = 1\ndef no_outer_locals\n  x\nend\nno_outer_locals"), "NameError: undefined local variable or method 'x' for main");
+        assert_eq!(eval("add = ->(a, b) { return a + b }\nadd.call(1, 2) + add[3, 4]"), "10");
+        assert_eq!(eval("counter = 0\ninc = -> { counter += 1 }\ninc.call\ninc.call\ncounter"), "2");
+        assert_eq!(eval("o = Object.new\no.class"), "Object");
+    }
+
+    #[test]
+    fn should_destructure_patterns_in_block_parameters() {
+        assert_eq!(eval("{ a: 1, b: 2 }.each_with_index.map { |(k, v), i| [k, v, i] }"), "[[:a, 1, 0], [:b, 2, 1]]");
+        assert_eq!(eval("{ a: 1, b: 2 }.each_with_object({}) { |(k, v), acc| acc[v] = k }"), "{1 => :a, 2 => :b}");
+        assert_eq!(eval("[[1, [2, 3, 4]]].map { |a, (b, *c)| [a, b, c] }"), "[[1, 2, [3, 4]]]");
+        assert_eq!(eval("k = :outer\n[[1, 2]].each { |(k, v)| v }\nk"), ":outer");
+        assert_eq!(eval("[[1, 2], 3].map { |(a, b)| [a, b] }"), "[[1, 2], [3, nil]]");
+    }
+
+    #[test]
+    fn should_bind_required_parameters_after_the_rest_from_the_end() {
+        assert_eq!(eval("def f(a, *r, b)\n  [a, r, b]\nend\n[f(1, 2, 3), f(1, 2), f(1, 2, 3, 4)]"), "[[1, [2], 3], [1, [], 2], [1, [2, 3], 4]]");
+        assert_eq!(eval("def g(a = 1, b)\n  [a, b]\nend\n[g(5, 6), g(6)]"), "[[5, 6], [1, 6]]");
+        assert_eq!(eval("def h(a, b = 2, *r, c, d)\n  [a, b, r, c, d]\nend\n[h(1, 3, 4), h(1, 2, 3, 4, 5, 6)]"), "[[1, 2, [], 3, 4], [1, 2, [3, 4], 5, 6]]");
+        assert_eq!(eval("def f(a, *r, b)\nend\nf(1)"), "ArgumentError: wrong number of arguments (given 1, expected 2+)");
+        assert_eq!(eval("proc { |a, *r, b| [a, r, b] }.call(1, 2, 3, 4)"), "[1, [2, 3], 4]");
+        assert_eq!(eval("pr = proc { |a, b = 5, c| [a, b, c] }\n[pr.call(1, 2), pr.call([1, 2, 3, 4]), pr.call(1), pr.arity]"), "[[1, 5, 2], [1, 2, 3], [1, 5, nil], 2]");
+        assert_eq!(eval("class A\n  def go(*args)\n    args\n  end\nend\nclass B < A\n  def go(a, *r, b)\n    super\n  end\nend\nB.new.go(1, 2, 3)"), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn should_bootstrap_the_core_class_hierarchy() {
+        assert_eq!(eval("[BasicObject.superclass, Object.superclass, Module.superclass, Class.superclass]"), "[nil, BasicObject, Object, Module]");
+        assert_eq!(eval("[Class.class, Module.class, Object.class, Kernel.class]"), "[Class, Class, Class, Module]");
+        assert_eq!(eval("Object.ancestors"), "[Object, Kernel, BasicObject]");
+        assert_eq!(eval("[Object.singleton_class, Object.singleton_class.superclass, BasicObject.singleton_class.superclass]"),
+            "[#<Class:Object>, #<Class:BasicObject>, Class]");
+        assert_eq!(eval("[Class.is_a?(Module), Object.is_a?(Class), 1.is_a?(Kernel), Integer < Numeric, Numeric < Integer]"),
+            "[true, true, true, true, false]");
+    }
+
+    #[test]
+    fn should_define_classes_with_instance_variables() {
+        let point = "class Point
+  attr_reader :x, :y
+
+  def initialize(x, y)
+    @x = x
+    @y = y
+  end
+
+  def +(other)
+    Point.new(x + other.x, y + other.y)
+  end
+
+  def to_s
+    '(' + x.to_s + ', ' + y.to_s + ')'
+  end
+end
+";
+        assert_eq!(eval(&format!("{}(Point.new(1, 2) + Point.new(3, 4)).to_s", point)), "\"(4, 6)\"");
+        assert_eq!(eval(&format!("{}Point.new(1, 2).x = 5", point)), "NoMethodError: undefined method 'x=' for an instance of Point");
+        assert_eq!(eval(&format!("{}class Point\n  attr_writer :x\nend\np = Point.new(1, 2)\np.x = 5\np.x", point)), "5");
+        assert_eq!(eval("class Foo < Bar\nend"), "NameError: uninitialized constant Bar");
+        assert_eq!(eval("class A\nend\nclass B\nend\nclass A < B\nend"), "TypeError: superclass mismatch for class A");
+        assert_eq!(eval("module M\nend\nclass M\nend"), "TypeError: M is not a class");
+        assert_eq!(eval("class Counter\n  @count = 0\n  def self.increment\n    @count += 1\n  end\nend\nCounter.increment\nCounter.increment"), "2");
+    }
+
+    #[test]
+    fn should_look_up_constants_and_class_variables_lexically() {
+        assert_eq!(eval("module Outer\n  LIMIT = 3\n  class Inner\n    def limit\n      LIMIT\n    end\n  end\nend\n[Outer::Inner.new.limit, Outer::Inner.name]"), "[3, \"Outer::Inner\"]");
+        assert_eq!(eval("class Base\n  SIZE = 1\nend\nclass Derived < Base\n  def size\n    SIZE\n  end\nend\n[Derived.new.size, Derived::SIZE]"), "[1, 1]");
+        assert_eq!(eval("module M\nend\nM::String"), "NameError: uninitialized constant M::String");
+        assert_eq!(eval("class Tally\n  @@total = 0\n  def self.add(n)\n    @@total += n\n  end\nend\nclass SubTally < Tally\n  def total\n    @@total\n  end\nend\nTally.add(2)\nSubTally.add(3)\nSubTally.new.total"), "5");
+        assert_eq!(eval("class Empty\n  def read\n    @@missing\n  end\nend\nEmpty.new.read"), "NameError: uninitialized class variable @@missing in Empty");
+        assert_eq!(eval("@@top = 1"), "RuntimeError: class variable access from toplevel");
+    }
+
+    #[test]
+    fn should_resolve_methods_through_mixins_and_super() {
+        let program = "module Loud
+  def speak
+    super.upcase + '!'
+  end
+end
+
+module Polite
+  def speak
+    'please, ' + super
+  end
+end
+
+class Animal
+  def speak
+    'hi from ' + name
+  end
+
+  def name
+    'animal'
+  end
+end
+
+class Dog < Animal
+  include Polite
+  prepend Loud
+
+  def name
+    'dog'
+  end
+end
+";
+        assert_eq!(eval(&format!("{}Dog.ancestors", program)), "[Loud, Dog, Polite, Animal, Object, Kernel, BasicObject]");
+        assert_eq!(eval(&format!("{}Dog.new.speak", program)), "\"PLEASE, HI FROM DOG!\"");
+        assert_eq!(eval("class A\n  def initialize(a, b = 2)\n    @sum = a + b\n  end\n  attr_reader :sum\nend\nclass B < A\n  def initialize(a, b = 10)\n    b += 1\n    super\n  end\nend\nB.new(1).sum"), "12");
+        assert_eq!(eval("class A\n  def go(*args)\n    args\n  end\nend\nclass B < A\n  def go(x)\n    super(x, x)\n  end\nend\nB.new.go(3)"), "[3, 3]");
+        assert_eq!(eval("class A\n  def go\n    super\n  end\nend\nA.new.go"), "NoMethodError: super: no superclass method 'go' for an instance of A");
+        assert_eq!(eval("super"), "RuntimeError: super called outside of method");
+        assert_eq!(eval("module Greeting\n  def greet\n    'hello'\n  end\nend\no = Object.new\no.extend(Greeting)\n[o.greet, o.singleton_class.include?(Greeting), o.is_a?(Greeting)]"), "[\"hello\", true, true]");
+    }
+
+    #[test]
+    fn should_define_singleton_methods_and_class_methods() {
+        assert_eq!(eval("o = Object.new\ndef o.hi\n  :hi\nend\n[o.hi, o.singleton_methods]"), "[:hi, [:hi]]");
+        assert_eq!(eval("o = Object.new\ndef o.hi\nend\nObject.new.hi"), "NoMethodError: undefined method 'hi' for an instance of Object");
+        assert_eq!(eval("class Shape\n  class << self\n    def create\n      new\n    end\n  end\n  def self.kind\n    :shape\n  end\nend\nclass Circle < Shape\nend\n[Circle.create.class, Circle.kind]"), "[Circle, :shape]");
+        assert_eq!(eval("module Util\n  module_function\n  def double(x)\n    x * 2\n  end\nend\nUtil.double(4)"), "8");
+        assert_eq!(eval("x = 1\ndef x.foo\nend"), "TypeError: can't define singleton");
+        assert_eq!(eval("Named = Class.new(StandardError) do |klass|\n  def extra\n    :extra\n  end\nend\n[Named.name, Named.superclass, Named.new.extra]"), "[\"Named\", StandardError, :extra]");
+        assert_eq!(eval("class Base\n  def self.inherited(subclass)\n    (@subclasses ||= []) << subclass.name\n  end\n  def self.subclasses\n    @subclasses\n  end\nend\nclass One < Base\nend\nclass Two < Base\nend\nBase.subclasses"), "[\"One\", \"Two\"]");
+    }
+
+    #[test]
+    fn should_enforce_method_visibility() {
+        let account = "class Account
+  def initialize(balance)
+    @balance = balance
+  end
+
+  def >(other)
+    balance > other.balance
+  end
+
+  def audit
+    self.secret
+  end
+
+  protected
+
+  def balance
+    @balance
+  end
+
+  private
+
+  def secret
+    :secret
+  end
+end
+";
+        assert_eq!(eval(&format!("{}Account.new(2) > Account.new(1)", account)), "true");
+        assert_eq!(eval(&format!("{}Account.new(2).audit", account)), ":secret");
+        assert_eq!(eval(&format!("{}Account.new(2).balance", account)), "NoMethodError: protected method 'balance' called for an instance of Account");
+        assert_eq!(eval(&format!("{}Account.new(2).secret", account)), "NoMethodError: private method 'secret' called for an instance of Account");
+        assert_eq!(eval(&format!("{}Account.new(1).initialize(2)", account)), "NoMethodError: private method 'initialize' called for an instance of Account");
+        assert_eq!(eval(&format!("{}[Account.private_instance_methods(false), Account.public_method_defined?(:audit)]", account)), "[[:initialize, :secret], true]");
+        assert_eq!(eval("class Thing\n  def a\n  end\n  def b\n  end\n  private :a, :b\nend\nThing.new.b"), "NoMethodError: private method 'b' called for an instance of Thing");
+        assert_eq!(eval("class Single\n  private_class_method :new\n  def self.instance\n    @instance ||= new\n  end\nend\n[Single.instance.equal?(Single.instance), (Single.new rescue :private)]"), "[true, :private]");
+        assert_eq!(eval("def helper\nend\nObject.new.helper"), "NoMethodError: private method 'helper' called for an instance of Object");
+    }
+
+    #[test]
+    fn should_dispatch_unknown_methods_to_method_missing() {
+        let ghost = "class Ghost
+  def method_missing(name, *args)
+    if name == :get_name
+      [name, args]
+    else
+      super
+    end
+  end
+
+  def respond_to_missing?(name, include_all = false)
+    name == :get_x || super
+  end
+end
+";
+        assert_eq!(eval(&format!("{}Ghost.new.get_name(1)", ghost)), "[:get_name, [1]]");
+        assert_eq!(eval(&format!("{}Ghost.new.other", ghost)), "NoMethodError: undefined method 'other' for an instance of Ghost");
+        assert_eq!(eval(&format!("{}g = Ghost.new\n[g.respond_to?(:get_x), g.respond_to?(:other), g.respond_to?(:to_s)]", ghost)), "[true, false, true]");
+        assert_eq!(eval("class Dyn\n  define_method(:a) { :a }\n  define_method(:b, -> { self.class })\n  alias_method :c, :a\nend\nd = Dyn.new\n[d.a, d.b, d.c]"), "[:a, Dyn, :a]");
+    }
+
+    #[test]
+    fn should_yield_to_blocks_closing_over_their_scope() {
+        let times = "def times(n)\n  i = 0\n  while i < n\n    yield i\n    i += 1\n  end\n  n\nend\n";
+        assert_eq!(eval(&format!("{}sum = 0\ntimes(4) {{ |i| sum += i }}\nsum", times)), "6");
+        assert_eq!(eval("def twice\n  [yield(1), yield(2)]\nend\ntwice { |x| x * 10 }"), "[10, 20]");
+        assert_eq!(eval("def given?\n  block_given?\nend\n[given?, given? {}]"), "[false, true]");
+        assert_eq!(eval("def bare\n  yield\nend\nbare"), "LocalJumpError: no block given (yield)");
+        assert_eq!(eval("def pass(&blk)\n  [blk.class, relay(&blk)]\nend\ndef relay\n  yield 5\nend\npass { |x| x + 1 }"), "[Proc, 6]");
+        assert_eq!(eval("def anon(&)\n  relay(&)\nend\ndef relay\n  yield 2\nend\nanon { |x| x * 3 }"), "6");
+        assert_eq!(eval("def make\n  n = 0\n  [-> { n += 1 }, -> { n }]\nend\ninc, get = make\ninc.call\ninc[]\nget.call"), "2");
+        assert_eq!(eval("def pairs\n  yield [1, 2]\nend\npairs { |a, b| [b, a] }"), "[2, 1]");
+        assert_eq!(eval(&format!("{}out = []\ntimes(2) {{ |i| times(2) {{ |j| out << [i, j] }} }}\nout", times)), "[[0, 0], [0, 1], [1, 0], [1, 1]]");
+    }
+
+    #[test]
+    fn should_distinguish_procs_from_lambdas() {
+        assert_eq!(eval("pr = proc { |a, b| [a, b] }\n[pr.call(1), pr.call(1, 2, 3), pr.call([4, 5])]"), "[[1, nil], [1, 2], [4, 5]]");
+        assert_eq!(eval("l = lambda { |a, b| [a, b] }\nl.call(1)"), "ArgumentError: wrong number of arguments (given 1, expected 2)");
+        assert_eq!(eval("[proc {}.lambda?, lambda {}.lambda?, Proc.new { 1 }.call]"), "[false, true, 1]");
+        assert_eq!(eval("Proc.new"), "ArgumentError: tried to create Proc object without a block");
+        assert_eq!(eval("def run\n  l = -> { return 1 }\n  l.call\n  pr = proc { return 2 }\n  pr.call\n  3\nend\nrun"), "2");
+        assert_eq!(eval("def make\n  proc { return 1 }\nend\nmake.call"), "LocalJumpError: unexpected return");
+        assert_eq!(eval("pr = proc { next 5; 6 }\npr.call"), "5");
+        assert_eq!(eval("def pair\n  return 1, 2\nend\na, b = pair\n[pair, b]"), "[[1, 2], 2]");
+        assert_eq!(eval("[[1].each { break 3, 4 }, [1, 2].map { |x| next x, -x }]"), "[[3, 4], [[1, -1], [2, -2]]]");
+    }
+
+    #[test]
+    fn should_break_out_of_the_yielding_method() {
+        let each = "def each_of(list)\n  i = 0\n  while i < list.length\n    yield list[i]\n    i += 1\n  end\n  :done\nend\n";
+        assert_eq!(eval(&format!("{}each_of([1, 2, 3]) {{ |x| break x * 10 if x == 2 }}", each)), "20");
+        assert_eq!(eval(&format!("{}each_of([1, 2]) {{ |x| next }}", each)), ":done");
+        assert_eq!(eval(&format!("{}seen = []\neach_of([1, 2, 3]) {{ |x| next if x == 2\n seen << x }}\nseen", each)), "[1, 3]");
+        assert_eq!(eval(&format!("{}log = []\ndef guarded(log)\n  yield\nensure\n  log << :ensured\nend\nr = guarded(log) {{ break :broke }}\n[r, log]", each)),
+            "[:broke, [:ensured]]");
+        assert_eq!(eval(&format!("{}each_of([1, 2]) {{ |x| each_of([3, 4]) {{ |y| break y }} ; break x }}", each)), "1");
+        assert_eq!(eval(&format!("{}r = each_of([1]) {{ |x| i = 0\n while true\n i += 1\n break if i > 3\n end\n i }}\nr", each)), ":done");
+        assert_eq!(eval("def keep(&b)\n  b\nend\nkeep { break 1 }.call"), "LocalJumpError: break from proc-closure");
+        assert_eq!(eval("l = -> { break 7; 8 }\nl.call"), "7");
+        assert_eq!(eval("def m\n  break\nend\nm"), "SyntaxError: Invalid break");
+    }
+
+    #[test]
+    fn should_raise_and_rescue_exception_hierarchies() {
+        let errors = "class AppError < StandardError\n  def initialize(message = 'app failed')\n    super\n  end\nend\nclass DbError < AppError\nend\n";
+        assert_eq!(eval(&format!("{}begin\n  raise DbError\nrescue AppError => e\n  [e.class, e.message, e.is_a?(StandardError)]\nend", errors)), "[DbError, \"app failed\", true]");
+        assert_eq!(eval("begin\n  raise ArgumentError, 'bad'\nrescue TypeError\n  :type\nrescue ArgumentError, NameError => e\n  e.inspect\nend"), "\"#<ArgumentError: bad>\"");
+        assert_eq!(eval("raise 'boom'"), "RuntimeError: boom");
+        assert_eq!(eval("raise"), "RuntimeError: unhandled exception");
+        assert_eq!(eval("raise 42"), "TypeError: exception class/object expected");
+        assert_eq!(eval("begin\n  raise Exception, 'low'\nrescue => e\n  :standard\nend"), "Exception: low");
+        assert_eq!(eval("e = KeyError.new('k')\n[e.message, e.backtrace, KeyError.superclass, StopIteration.superclass]"), "[\"k\", nil, IndexError, IndexError]");
+        assert_eq!(eval("begin\n  begin\n    raise 'inner'\n  rescue\n    raise TypeError, 'outer'\n  end\nrescue => e\n  [e.message, e.cause.message, $!.message]\nend"), "[\"outer\", \"inner\", \"outer\"]");
+        assert_eq!(eval("begin\n  raise 'x'\nrescue\nend\n$!"), "nil");
+        assert_eq!(eval("begin\n  begin\n    1 / 0\n  rescue => e\n    raise\n  end\nrescue => again\n  again.equal?(e)\nend"), "true");
+        assert_eq!(eval("class Custom < StandardError\n  def message\n    'custom'\n  end\nend\nbegin\n  raise Custom\nrescue => e\n  [e.message, e.inspect]\nend"), "[\"custom\", \"#<Custom: custom>\"]");
+        assert_eq!(eval("e = RuntimeError.new('a')\n[e.exception.equal?(e), e.exception('b').message, e == RuntimeError.new('a')]"), "[true, \"b\", true]");
+        assert_eq!(eval("begin\n  nil.shout\nrescue NoMethodError => e\n  [e.name, e.receiver]\nend"), "[:shout, nil]");
+        assert_eq!(eval("class Vault\n  private def code\n  end\nend\nv = Vault.new\nbegin\n  v.code\nrescue => e\n  [e.name, e.receiver.equal?(v)]\nend"), "[:code, true]");
+        assert_eq!(eval("begin\n  xyz\nrescue NameError => e\n  [e.name, e.receiver]\nend"), "[:xyz, main]");
+        assert_eq!(eval("module M\nend\n[-> { Nope }, -> { M::Gone }, -> { Object.const_get('M::Lost') }].map do |f|\n  f.call\nrescue NameError => e\n  [e.name, e.receiver]\nend"), "[[:Nope, Object], [:Gone, M], [:Lost, M]]");
+        assert_eq!(eval("NoMethodError.new('x').receiver"), "ArgumentError: no receiver is available");
+    }
+
+    #[test]
+    fn should_record_backtraces_and_report_uncaught_exceptions() {
+        let source = "def inner\n  raise ArgumentError, \"bad\\nthings\"\nend\n\ndef outer\n  [1].first\n  inner\nend\nouter\n";
+        let mut interpreter = Interpreter::new();
+        let mut lexer = Lexer::new(source.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (mut program, _) = parser.parse_program();
+        program.ast.set_file("app.rb");
+        let exception = match interpreter.eval_program(program) {
+            Err(Control::Raise(exception)) => exception,
+            _ => panic!("expected an exception"),
+        };
+        assert_eq!(interpreter.report(&exception), "app.rb:2:in 'inner': bad (ArgumentError)\nthings\n\tfrom app.rb:7:in 'outer'\n\tfrom app.rb:9:in '<main>'");
+
+        assert_eq!(eval("def f\n  1 / 0\nend\nbegin\n  f\nrescue => e\n  e.backtrace\nend"), "[\"-:2:in 'f'\", \"-:5:in '<main>'\"]");
+        assert_eq!(eval("def each_one\n  yield 1\nend\nbegin\n  each_one { |x| raise 'no' }\nrescue => e\n  e.backtrace\nend"),
+            "[\"-:5:in 'block in <main>'\", \"-:2:in 'each_one'\", \"-:5:in '<main>'\"]");
+        assert_eq!(eval("class Config\n  raise 'in body'\nend"), "RuntimeError: in body");
+        assert_eq!(eval("begin\n  raise 'x'\nrescue => e\n  e.set_backtrace(['a:1'])\n  e.backtrace\nend"), "[\"a:1\"]");
+    }
+
+    #[test]
+    fn should_always_run_ensure_clauses() {
+        assert_eq!(eval("log = []\nbegin\n  begin\n    raise 'x'\n  ensure\n    log << :inner\n  end\nrescue\n  log << :rescued\nensure\n  log << :outer\nend\nlog"), "[:inner, :rescued, :outer]");
+        assert_eq!(eval("def f(log)\n  i = 0\n  while true\n    begin\n      i += 1\n      break if i == 2\n    ensure\n      log << i\n    end\n  end\n  log\nend\nf([])"), "[1, 2]");
+        assert_eq!(eval("def f\n  yield\nensure\n  @cleaned = true\nend\nf { raise 'in block' } rescue nil\n@cleaned"), "true");
+        assert_eq!(eval("def f\n  return :body\nensure\n  return :ensure\nend\nf"), ":ensure");
+    }
+
+    #[test]
+    fn should_raise_on_runaway_recursion() {
+        let deep = std::thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(|| eval("def deep(n)\n  deep(n + 1)\nend\ndeep(0)"))
+            .unwrap();
+        assert_eq!(deep.join().unwrap(), "SystemStackError: stack level too deep");
+    }
+
+    #[test]
+    fn should_rescue_and_ensure() {
+        assert_eq!(eval("begin\n  1 / 0\nrescue ZeroDivisionError => e\n  e.message\nend"), "\"divided by 0\"");
+        assert_eq!(eval("log = []\nbegin\n  log << 1\nensure\n  log << 2\nend\nlog"), "[1, 2]");
+        assert_eq!(eval("tries = 0\nbegin\n  tries += 1\n  10 / (tries - 1)\nrescue\n  retry\nend"), "10");
+        assert_eq!(eval("(1 / 0 rescue :failed)"), ":failed");
+        assert_eq!(eval("x = (raise 'q' rescue $!)\n[x.class, x.message, $!]"), "[RuntimeError, \"q\", nil]");
+        assert_eq!(eval("def f\n  return 1\nensure\n  @seen = true\nend\n[f, @seen]"), "[1, true]");
+    }
+
+    #[test]
+    fn should_treat_strings_as_encoded_bytes() {
+        assert_eq!(eval("'héllo'.length"), "5");
+        assert_eq!(eval("'héllo'.bytesize"), "6");
+        assert_eq!(eval("'é'.bytes"), "[195, 169]");
+        assert_eq!(eval("'é'.b"), "\"\\xC3\\xA9\"");
+        assert_eq!(eval("'é'.b.length"), "2");
+        assert_eq!(eval("'é'.encoding"), "#<Encoding:UTF-8>");
+        assert_eq!(eval("'é'.b.encoding"), "#<Encoding:BINARY (ASCII-8BIT)>");
+        assert_eq!(eval("'é'.force_encoding('ASCII-8BIT').force_encoding(Encoding::UTF_8) == 'é'"), "true");
+        assert_eq!(eval("'é' == 'é'.b"), "false");
+        assert_eq!(eval("'abc' == 'abc'.b"), "true");
+        assert_eq!(eval("255.chr.valid_encoding?"), "true");
+        assert_eq!(eval("255.chr.force_encoding('UTF-8').valid_encoding?"), "false");
+        assert_eq!(eval("s = 255.chr.force_encoding('UTF-8')\n[s.length, s.inspect]"), "[1, \"\\\"\\\\xFF\\\"\"]");
+        assert_eq!(eval("\"caf\\u00e9\""), "\"café\"");
+        assert_eq!(eval("\"\\x41\\u{1F600}\".chars"), "[\"A\", \"😀\"]");
+        assert_eq!(eval("\"\\xff\".bytes"), "[255]");
+        assert_eq!(eval("\"\\xff\".valid_encoding?"), "false");
+        assert_eq!(eval("[\"\\101\\0612\", \"\\1\".ord]"), "[\"A12\", 1]");
+        assert_eq!(eval("'é'.encode('US-ASCII')"), "Encoding::UndefinedConversionError: U+00E9 from UTF-8 to US-ASCII");
+        assert_eq!(eval("'é'.encode('US-ASCII', undef: :replace)"), "\"?\"");
+        assert_eq!(eval("'é'.b.encode('UTF-8')"), "Encoding::UndefinedConversionError: \"\\xC3\" from ASCII-8BIT to UTF-8");
+        assert_eq!(eval("s = 255.chr.force_encoding('UTF-8')\n(s + 'a').encode('UTF-8', invalid: :replace, replace: '?')"), "\"?a\"");
+        assert_eq!(eval("'a'.encode('ASCII').encoding"), "#<Encoding:US-ASCII>");
+        assert_eq!(eval("'é' + 'é'.b"), "Encoding::CompatibilityError: incompatible character encodings: UTF-8 and BINARY (ASCII-8BIT)");
+        assert_eq!(eval("('a'.b + 'é').encoding"), "#<Encoding:UTF-8>");
+        assert_eq!(eval("Encoding.find('binary')"), "#<Encoding:BINARY (ASCII-8BIT)>");
+        assert_eq!(eval("'x'.force_encoding('EBCDIC')"), "ArgumentError: unknown encoding name - EBCDIC");
+    }
+
+    #[test]
+    fn should_interpolate_code_into_strings() {
+        assert_eq!(eval("x = 5\n\"x=#{x}, #{x += 1; x * 2}, #{}#{nil}#{:sym}\""), "\"x=5, 12, sym\"");
+        assert_eq!(eval("\"#{[1, 'a']} #{ {a: 1}[:a] } #{\"in#{'ner'}\"}\""), "\"[1, \\\"a\\\"] 1 inner\"");
+        assert_eq!(eval("class Foo\n  def to_s\n    'foo'\n  end\nend\nclass Bar\n  def to_s\n    1\n  end\nend\n\"#{Foo.new} #{Bar.new}\""), "\"foo #<Bar>\"");
+        assert_eq!(eval("\"#{\"\\xff\"}!\".bytes"), "[255, 33]");
+        assert_eq!(eval("['\\#{x}', \"\\#{x}\".length]"), "[\"\\\\\\#{x}\", 4]");
+    }
+
+    #[test]
+    fn should_freeze_strings() {
+        assert_eq!(eval("s = 'abc'.freeze\ns << 'd'"), "FrozenError: can't modify frozen String: \"abc\"");
+        assert_eq!(eval("s = 'abc'.freeze\n[s.frozen?, s.dup.frozen?, (+s).frozen?, (-'x').frozen?]"), "[true, false, false, true]");
+        assert_eq!(eval("s = 'abc'.freeze\ns.upcase!"), "FrozenError: can't modify frozen String: \"abc\"");
+        assert_eq!(eval("s = 'abc'.freeze\ns.upcase"), "\"ABC\"");
+        assert_eq!(eval("# frozen_string_literal: true\n'abc'.frozen?"), "true");
+        assert_eq!(eval("# frozen_string_literal: true\ns = 'abc'\ns << 'd'"), "FrozenError: can't modify frozen String: \"abc\"");
+        assert_eq!(eval("# frozen_string_literal: true\ns = +'abc'\ns << 'd'"), "\"abcd\"");
+        assert_eq!(eval("x = 1\n# frozen_string_literal: true\n'abc'.frozen?"), "false");
+        assert_eq!(eval("'abc'.frozen?"), "false");
+        assert_eq!(eval("k = 'key'\nh = {k => 1}\nk << '!'\n[h.keys[0], h.keys[0].frozen?]"), "[\"key\", true]");
+    }
+
+    #[test]
+    fn should_index_and_change_strings() {
+        assert_eq!(eval("s = 'hello world'\n[s[0], s[-1], s[20], s[0, 5], s[6..], s[-5..-2], s[11], s[11, 2], s[12, 1]]"), "[\"h\", \"d\", nil, \"hello\", \"world\", \"worl\", nil, \"\", nil]");
+        assert_eq!(eval("'hello'['ell']"), "\"ell\"");
+        assert_eq!(eval("'hello'[/l+/]"), "\"ll\"");
+        assert_eq!(eval("'key=value'[/(\\w+)=(\\w+)/, 2]"), "\"value\"");
+        assert_eq!(eval("'key=value'[/(?<k>\\w+)=/, 'k']"), "\"key\"");
+        assert_eq!(eval("s = 'hello'\ns[0] = 'J'\ns"), "\"Jello\"");
+        assert_eq!(eval("s = 'hello'\ns[1..3] = 'ipp'\ns"), "\"hippo\"");
+        assert_eq!(eval("s = 'hello'\ns[/l+/] = 'L'\ns"), "\"heLo\"");
+        assert_eq!(eval("s = 'hello'\ns['x'] = 'y'"), "IndexError: string not matched");
+        assert_eq!(eval("s = 'hi'\ns[5] = 'x'"), "IndexError: index 5 out of string");
+        assert_eq!(eval("'ab' + 'cd' * 2"), "\"abcdcd\"");
+        assert_eq!(eval("s = 'a'\ns << 'b' << 99\ns.concat('d', 'e')"), "\"abcde\"");
+        assert_eq!(eval("'a' + 1"), "TypeError: no implicit conversion of Integer into String");
+        assert_eq!(eval("'x' * -1"), "ArgumentError: negative argument");
+        assert_eq!(eval("'%05.1f|%-4s|%x' % [3.14159, 'ab', 255]"), "\"003.1|ab  |ff\"");
+        assert_eq!(eval("'%s' % :sym"), "\"sym\"");
+        assert_eq!(eval("'%{a}-%<b>03d' % {a: 'x', b: 7}"), "\"x-007\"");
+        assert_eq!(eval("'%d' % []"), "ArgumentError: too few arguments");
+    }
+
+    #[test]
+    fn should_substitute_and_split_strings() {
+        assert_eq!(eval("'hello'.sub('l', 'L')"), "\"heLlo\"");
+        assert_eq!(eval("'hello'.gsub('l', 'L')"), "\"heLLo\"");
+        assert_eq!(eval("'a.b.c'.gsub('.', '-')"), "\"a-b-c\"");
+        assert_eq!(eval("'john smith'.gsub(/(\\w)(\\w*)/) { $1.upcase + $2 }"), "\"John Smith\"");
+        assert_eq!(eval("'john smith'.sub(/(?<first>\\w+) (?<last>\\w+)/, '\\k<last>, \\k<first>')"), "\"smith, john\"");
+        assert_eq!(eval("'2024-01-15'.gsub(/(\\d+)-(\\d+)-(\\d+)/, '\\3/\\2/\\1')"), "\"15/01/2024\"");
+        assert_eq!(eval("'cat hat'.gsub(/[ch]at/, 'cat' => 'dog', 'hat' => 'cap')"), "\"dog cap\"");
+        assert_eq!(eval("'abc'.gsub(/x*/, '-')"), "\"-a-b-c-\"");
+        assert_eq!(eval("'abc'.gsub(/b/) { |m| m.upcase }"), "\"aBc\"");
+        assert_eq!(eval("s = 'abc'\n[s.sub!(/x/, 'y'), s.sub!(/b/, 'B'), s]"), "[nil, \"aBc\", \"aBc\"]");
+        assert_eq!(eval("'a,b,,c,,'.split(',')"), "[\"a\", \"b\", \"\", \"c\"]");
+        assert_eq!(eval("'a,b,,c,,'.split(',', -1)"), "[\"a\", \"b\", \"\", \"c\", \"\", \"\"]");
+        assert_eq!(eval("'  one two\tthree\n'.split"), "[\"one\", \"two\", \"three\"]");
+        assert_eq!(eval("'a b c d'.split(' ', 2)"), "[\"a\", \"b c d\"]");
+        assert_eq!(eval("'a1b22c'.split(/\\d+/)"), "[\"a\", \"b\", \"c\"]");
+        assert_eq!(eval("'a1b2c'.split(/(\\d)/)"), "[\"a\", \"1\", \"b\", \"2\", \"c\"]");
+        assert_eq!(eval("'héllo'.split('')"), "[\"h\", \"é\", \"l\", \"l\", \"o\"]");
+        assert_eq!(eval("''.split(',')"), "[]");
+        assert_eq!(eval("'a-b_c'.scan(/[a-z]/)"), "[\"a\", \"b\", \"c\"]");
+        assert_eq!(eval("'a=1, b=2'.scan(/(\\w)=(\\d)/)"), "[[\"a\", \"1\"], [\"b\", \"2\"]]");
+        assert_eq!(eval("'hello' =~ /ll/"), "2");
+        assert_eq!(eval("if 'x=42' =~ /(\\d+)/ then $1.to_i + $~[0].length end"), "44");
+        assert_eq!(eval("'abc'.match(/(?<mid>b)/)[:mid]"), "\"b\"");
+        assert_eq!(eval("'abc'.match?('b.')"), "true");
+        assert_eq!(eval("'abc' =~ 'b'"), "TypeError: wrong argument type String (expected Regexp)");
+    }
+
+    #[test]
+    fn should_transform_strings() {
+        assert_eq!(eval("['  hi  '.strip, '  hi  '.lstrip, '  hi  '.rstrip, \"hi\\n\".chomp, 'hi'.chop, 'hi!'.chomp('!')]"), "[\"hi\", \"hi  \", \"  hi\", \"hi\", \"h\", \"hi\"]");
+        assert_eq!(eval("s = 'hi'\n[s.strip!, s]"), "[nil, \"hi\"]");
+        assert_eq!(eval("['straße'.upcase, 'ÉCOLE'.downcase, 'hELLO wORLD'.capitalize, 'Hello'.swapcase]"), "[\"STRASSE\", \"école\", \"Hello world\", \"hELLO\"]");
+        assert_eq!(eval("['hello'.start_with?('he'), 'hello'.start_with?('x', 'hel'), 'hello'.start_with?(/h.l/), 'hello'.end_with?('lo')]"), "[true, true, true, true]");
+        assert_eq!(eval("['hello'.include?('ell'), 'hello'.include?('z')]"), "[true, false]");
+        assert_eq!(eval("'hello'.include?(1)"), "TypeError: no implicit conversion of Integer into String");
+        assert_eq!(eval("out = []\n'héy'.each_char { |c| out << c }\nout"), "[\"h\", \"é\", \"y\"]");
+        assert_eq!(eval("['hello'.index('l'), 'hello'.rindex('l'), 'hello'.index(/l+o/), 'héllo'.index('l', 3)]"), "[2, 3, 2, 3]");
+        assert_eq!(eval("['héllo'.reverse, 'ab'.center(6, '*'), 'ab'.ljust(4, '.'), 'ab'.rjust(4)]"), "[\"olléh\", \"**ab**\", \"ab..\", \"  ab\"]");
+        assert_eq!(eval("['hello'.tr('el', 'ip'), 'hello'.tr('a-y', 'b-z'), 'hello'.delete('l'), 'aaabbb'.squeeze, 'hello'.count('lo')]"), "[\"hippo\", \"ifmmp\", \"heo\", \"ab\", 3]");
+        assert_eq!(eval("['az'.succ, 'zz'.succ, 'a9'.succ, 'Zz'.succ, '1.9'.succ]"), "[\"ba\", \"aaa\", \"b0\", \"AAa\", \"2.0\"]");
+        assert_eq!(eval("['12abc'.to_i, ' -3'.to_i, 'ff'.to_i(16), '0x1f'.hex, '1_000'.to_i, 'abc'.to_i, '3.7e2x'.to_f, '.5'.to_f]"), "[12, -3, 255, 31, 1000, 0, 370.0, 0.5]");
+        assert_eq!(eval("['a'.ord, 'é'.ord, 97.chr, 233.chr(Encoding::UTF_8)]"), "[97, 233, \"a\", \"é\"]");
+        assert_eq!(eval("\"a\\nb\\n\".lines"), "[\"a\\n\", \"b\\n\"]");
+        assert_eq!(eval("'a\"b#{1}'.inspect"), "\"\\\"a\\\\\\\"b\\\\\\#{1}\\\"\"");
+        assert_eq!(eval("s = 'world'\ns.insert(0, 'hello ')\ns.prepend('>> ')"), "\">> hello world\"");
+        assert_eq!(eval("['b' <=> 'a', 'a' < 'b', 'abc'.casecmp?('ABC')]"), "[1, true, true]");
+    }
+
+    #[test]
+    fn should_escape_strings_by_their_encoding_when_inspecting() {
+        assert_eq!(eval("[\"\\0\", \"\\x01\", \"\\x7F\", \"\\u0085\"]"), "[\"\\u0000\", \"\\u0001\", \"\\u007F\", \"\\u0085\"]");
+        assert_eq!(eval("\"\\a\\b\\t\\n\\v\\f\\r\\e\""), "\"\\a\\b\\t\\n\\v\\f\\r\\e\"");
+        assert_eq!(eval("[[1].pack('N'), \"\\0\\x01\\a\".b, \"\\x01\".force_encoding('US-ASCII')]"), "[\"\\x00\\x00\\x00\\x01\", \"\\x00\\x01\\a\", \"\\x01\"]");
+        assert_eq!(eval(":\"a\\0\""), ":\"a\\u0000\"");
+    }
+
+    #[test]
+    fn should_match_regexps() {
+        assert_eq!(eval("/(\\d+)-(\\d+)/.match('tel: 555-1234')"), "#<MatchData \"555-1234\" 1:\"555\" 2:\"1234\">");
+        assert_eq!(eval("m = /(?<year>\\d{4})-(?<month>\\d\\d)/.match('on 2024-06')\n[m[:year], m['month'], m.pre_match, m.begin(0)]"), "[\"2024\", \"06\", \"on \", 3]");
+        assert_eq!(eval("/hello/i =~ 'Say HELLO'"), "4");
+        assert_eq!(eval("/a.c/m.match?(\"a\\nc\")"), "true");
+        assert_eq!(eval("/a.c/.match?(\"a\\nc\")"), "false");
+        assert_eq!(eval("/x/ix"), "/x/ix");
+        assert_eq!(eval("[/a\\/b/.source, /a\\/b/]"), "[\"a/b\", /a\\/b/]");
+        assert_eq!(eval("Regexp.new('a+b').match?('caab')"), "true");
+        assert_eq!(eval("Regexp.new('(')"), "RegexpError: end pattern with unmatched parenthesis: /(/");
+        assert_eq!(eval("Regexp.escape('a.b*c')"), "\"a\\\\.b\\\\*c\"");
+        assert_eq!(eval("Regexp.union('a.b', /c/)"), "/a\\.b|(?-mix:c)/");
+        assert_eq!(eval("case 'hello'\nwhen /^h/ then :h\nelse :other\nend"), ":h");
+        assert_eq!(eval("x = 10\ny = 2\nx / y / 1"), "5");
+        assert_eq!(eval("x = 4\nx /2"), "2");
+        assert_eq!(eval("'a,b'.split /,/"), "[\"a\", \"b\"]");
+        assert_eq!(eval("n = 2\n[/a{#{n}}b/ =~ 'xaab', /a#{n}/.source, /x#{/b/i}y/]"), "[1, \"a2\", /x(?i-mx:b)y/]");
+        assert_eq!(eval("['aÉb' =~ /\\p{Lu}/, 'ab1' =~ /\\p{^Alpha}/, 'é' =~ /[\\p{L}]/, '1' =~ /\\P{N}/]"), "[1, 2, 0, nil]");
+        assert_eq!(eval("/\\p{Foo}/"), "RegexpError: invalid character property name {Foo}: /\\p{Foo}/");
+    }
+
+    #[test]
+    fn should_index_and_change_arrays() {
+        assert_eq!(eval("a = [1, 2, 3, 4, 5]\n[a[-1], a[1, 2], a[1..], a[5], a[5, 1], a[6, 1], a[-7]]"), "[5, [2, 3], [2, 3, 4, 5], nil, [], nil, nil]");
+        assert_eq!(eval("a = [1, 2, 3]\na[5] = 6\na"), "[1, 2, 3, nil, nil, 6]");
+        assert_eq!(eval("a = [1, 2, 3, 4]\na[1, 2] = [:x]\na[0..0] = 9\na"), "[9, :x, 4]");
+        assert_eq!(eval("a = [1, 2, 3]\na[-5] = 0"), "IndexError: index -5 too small for array; minimum: -3");
+        assert_eq!(eval("a = [1, 2]\n[a.push(3, 4), a.pop, a.shift, a.unshift(0), a.pop(2), a]"), "[[0], 4, 1, [0], [2, 3], [0]]");
+        assert_eq!(eval("a = [1, 2, 3]\na.insert(-2, :x)\n[a, a.delete(:x), a.delete_at(0), a.concat([7], [8])]"), "[[2, 3, 7, 8], :x, 1, [2, 3, 7, 8]]");
+        assert_eq!(eval("[[1, [2, [3]]].dig(1, 1, 0), [1, 2].fetch(-1), [1].fetch(5, :none), [1, 2, 3].values_at(0, 2, 4)]"), "[3, 2, :none, [1, 3, nil]]");
+        assert_eq!(eval("[[1, 2]].dig(0, 1, 0)"), "TypeError: Integer does not have #dig method");
+        assert_eq!(eval("[1, 2, 3].fetch(10)"), "IndexError: index 10 outside of array bounds: -3...3");
+        assert_eq!(eval("[Array.new(2), Array.new(2, 0), Array.new(3) { |i| i * i }, [1, 2, 3].first(2), [1, 2, 3].last(2)]"), "[[nil, nil], [0, 0], [0, 1, 4], [1, 2], [2, 3]]");
+        assert_eq!(eval("a = [3, nil, 1, nil]\n[a.compact, a.compact!, a.compact!, a.reverse, a.rotate, a.slice!(0), a]"), "[[3, 1], [1], nil, [1, 3], [1, 3], 3, [1]]");
+    }
+
+    #[test]
+    fn should_iterate_arrays_with_enumerable() {
+        assert_eq!(eval("s = 0\n[1, 2, 3].each { |x| s += x }\ns"), "6");
+        assert_eq!(eval("[[1, 2, 3].map { |x| x * 2 }, [1, 2, 3, 4].select { |x| x.even? }, [1, 2, 3, 4].reject { |x| x > 2 }]"), "[[2, 4, 6], [2, 4], [1, 2]]");
+        assert_eq!(eval("[[1, 2, 3].reduce(:+), [1, 2, 3].inject(10) { |s, x| s + x }, [].reduce(:+), [1, 2].sum, [0.1, 0.2, 0.3].sum, [1, 2].sum(0.5)]"), "[6, 16, nil, 3, 0.6, 3.5]");
+        assert_eq!(eval("r = []\n[:a, :b].each_with_index { |x, i| r << [x, i] }\n[r, [1, 2].each_with_object([]) { |x, memo| memo << x * 3 }]"), "[[[:a, 0], [:b, 1]], [3, 6]]");
+        assert_eq!(eval("[(1..6).to_a.group_by { |x| x % 3 }, [1, 2, 3, 4].partition { |x| x.odd? }, ['a', 'b', 'a'].tally]"), "[{1 => [1, 4], 2 => [2, 5], 0 => [3, 6]}, [[1, 3], [2, 4]], {\"a\" => 2, \"b\" => 1}]");
+        assert_eq!(eval("[[[1, 2], [3]].flat_map { |x| x }, ['apple', 'fig', 'kiwi'].min_by { |s| s.size }, ['apple', 'fig', 'kiwi'].max_by { |s| s.size }, [1, 2, 3, 1].take_while { |x| x < 3 }]"), "[[1, 2, 3], \"fig\", \"apple\", [1, 2]]");
+        assert_eq!(eval("r = []\n[1, 2, 3, 4, 5].each_slice(2) { |s| r << s }\n[1, 2, 3].each_cons(2) { |c| r << c }\nr"), "[[1, 2], [3, 4], [5], [1, 2], [2, 3]]");
+        assert_eq!(eval("[[1, 2, 3].find { |x| x > 1 }, [1, 2, 3].find_index(3), [1, 2, 3].all?(Integer), [nil, 1].any?, [1, 1].one?(1), [].none?, [1, 2, 3].count { |x| x.odd? }]"), "[2, 2, true, true, false, true, 2]");
+        assert_eq!(eval("[[1, 2].zip([3, 4], [5]), [[1, :a], [2, :b]].to_h, [3, 1, 2].minmax, [1, 2, 3, 4].filter_map { |x| x * 2 if x.even? }]"), "[[[1, 3, 5], [2, 4, nil]], {1 => :a, 2 => :b}, [1, 3], [4, 8]]");
+        assert_eq!(eval("[1, 2].inject"), "LocalJumpError: no block given (yield)");
+    }
+
+    #[test]
+    fn should_sort_and_combine_arrays() {
+        assert_eq!(eval("[[3, 1, 2].sort, ['pear', 'fig', 'apple'].sort, [3, 1, 2].sort { |a, b| b <=> a }, [3, 1, 2].max(2), [1, 2, 3].min]"), "[[1, 2, 3], [\"apple\", \"fig\", \"pear\"], [3, 2, 1], [3, 2], 1]");
+        assert_eq!(eval("['bb', 'a', 'cc', 'd'].sort_by { |s| s.size }"), "[\"a\", \"d\", \"bb\", \"cc\"]");
+        assert_eq!(eval("[3, 'a'].sort"), "ArgumentError: comparison of String with 3 failed");
+        assert_eq!(eval("[[1, 1, 2, 1.0].uniq, ['a', 'B', 'b'].uniq { |s| s.downcase }, [1, [2, [3, [4]]]].flatten, [1, [2, [3, [4]]]].flatten(1)]"), "[[1, 2, 1.0], [\"a\", \"B\"], [1, 2, 3, 4], [1, 2, [3, [4]]]]");
+        assert_eq!(eval("[[1, 2, 2, 3] - [2], [1, 2] & [2, 3], [1, 2] | [2, 3], [1, 2] * 2, [1, 2] * '-', [1, [2, [3]]].join(',')]"), "[[1, 3], [2], [1, 2, 3], [1, 2, 1, 2], \"1-2\", \"1,2,3\"]");
+        assert_eq!(eval("[[1, 2] <=> [1, 3], [1, 2] <=> [1], [1] <=> ['a'], [1, 2].product([3, 4]), [[1, 2], [3, 4]].transpose]"), "[-1, 1, nil, [[1, 3], [1, 4], [2, 3], [2, 4]], [[1, 3], [2, 4]]]");
+        assert_eq!(eval("[[1, 2] == [1, 2], [1, 2].eql?([1.0, 2]), [1, [2]].hash == [1, [2]].hash, [1, 2].include?(2)]"), "[true, false, true, true]");
+    }
+
+    #[test]
+    fn should_inspect_arrays_like_ruby() {
+        assert_eq!(eval("[1, 'two', :three, nil, 4.0, [5]].inspect"), "\"[1, \\\"two\\\", :three, nil, 4.0, [5]]\"");
+        assert_eq!(eval("a = [1]\na << a\n[a.inspect, a.to_s, a == a]"), "[\"[1, [...]]\", \"[1, [...]]\", true]");
+        assert_eq!(eval("a = [1]\na << a\na.flatten"), "ArgumentError: tried to flatten recursive array");
+        assert_eq!(eval("a = []\na << a\na.join"), "ArgumentError: recursive array join");
+    }
+
+    #[test]
+    fn should_pack_arrays() {
+        assert_eq!(eval("[65, 66].pack('C*')"), "\"AB\"");
+        assert_eq!(eval("[1, 2].pack('nV').bytes"), "[0, 1, 2, 0, 0, 0]");
+        assert_eq!(eval("[-1].pack('s>').bytes"), "[255, 255]");
+        assert_eq!(eval("['abc', 'de'].pack('a5A3').bytes"), "[97, 98, 99, 0, 0, 100, 101, 32]");
+        assert_eq!(eval("[['hello'].pack('m'), ['hello'].pack('m0'), ['616263'].pack('H*'), ['0100'].pack('B*').bytes]"), "[\"aGVsbG8=\\n\", \"aGVsbG8=\", \"abc\", [64]]");
+        assert_eq!(eval("[[233, 9786].pack('U*'), [233, 9786].pack('U*').encoding, [1].pack('C').encoding, ['a'].pack('m').encoding]"), "[\"é☺\", #<Encoding:UTF-8>, #<Encoding:BINARY (ASCII-8BIT)>, #<Encoding:US-ASCII>]");
+        assert_eq!(eval("[300].pack('w').bytes"), "[130, 44]");
+        assert_eq!(eval("[1].pack('CC')"), "ArgumentError: too few arguments");
+        assert_eq!(eval("[1].pack('y')"), "ArgumentError: unknown pack directive 'y' in 'y'");
+    }
+
+    #[test]
+    fn should_keep_hash_entries_in_insertion_order() {
+        assert_eq!(eval("h = { b: 1, a: 2 }\nh[:c] = 3\nh[:b] = 4\nh.delete(:a)\nh[:a] = 5\n[h, h.keys, h.length, h[:missing]]"), "[{b: 4, c: 3, a: 5}, [:b, :c, :a], 3, nil]");
+        assert_eq!(eval("h = { 1 => :int, 1.0 => :float, [1, 2] => :array, 'k' => :string }\n[h[1], h[1.0], h[[1, 2]], h['k'], h.size]"), "[:int, :float, :array, :string, 4]");
+        assert_eq!(eval("k = 'key'\nh = { k => 1 }\nk << 's'\n[h['key'], h.keys[0].frozen?]"), "[1, true]");
+        let point = "class Point\n  attr_reader :x, :y\n  def initialize(x, y)\n    @x = x\n    @y = y\n  end\n  def hash\n    [x, y].hash\n  end\n  def eql?(other)\n    other.is_a?(Point) && x == other.x && y == other.y\n  end\nend\n";
+        assert_eq!(eval(&format!("{}h = {{ Point.new(1, 2) => :a }}\nh[Point.new(1, 2)] = :b\n[h[Point.new(1, 2)], h.size, h.key?(Point.new(2, 1))]", point)), "[:b, 1, false]");
+        assert_eq!(eval("class Plain; end\na = Plain.new\nh = { a => 1 }\n[h[a], h[Plain.new]]"), "[1, nil]");
+    }
+
+    #[test]
+    fn should_give_hash_defaults() {
+        assert_eq!(eval("h = Hash.new(0)\n'hello'.each_char { |c| h[c] += 1 }\n[h, h['z'], h.default]"), "[{\"h\" => 1, \"e\" => 1, \"l\" => 2, \"o\" => 1}, 0, 0]");
+        assert_eq!(eval("h = Hash.new { |hash, key| hash[key] = [] }\nh[:a] << 1\nh[:a] << 2\nh[:b]\n[h, h.fetch(:c, :none), h.default_proc.class]"), "[{a: [1, 2], b: []}, :none, Proc]");
+        assert_eq!(eval("{ a: 1 }.fetch(:b)"), "KeyError: key not found: :b");
+        assert_eq!(eval("{ a: 1 }.fetch('b') { |k| k * 2 }"), "\"bb\"");
+        assert_eq!(eval("{ a: { b: [10, 20] } }.dig(:a, :b, 1)"), "20");
+        assert_eq!(eval("{ a: 1 }.dig(:a, :b)"), "TypeError: Integer does not have #dig method");
+        assert_eq!(eval("Hash.new(1) { }"), "ArgumentError: wrong number of arguments (given 1, expected 0)");
+    }
+
+    #[test]
+    fn should_iterate_and_transform_hashes() {
+        assert_eq!(eval("r = []\n{ a: 1, b: 2 }.each { |k, v| r << k << v }\n{ c: 3 }.each { |pair| r << pair }\nr"), "[:a, 1, :b, 2, [:c, 3]]");
+        assert_eq!(eval("h = { a: 1, b: 2, c: 3 }\n[h.map { |k, v| v * 2 }, h.select { |k, v| v > 1 }, h.reject { |k| k == :a }, h.to_a]"), "[[2, 4, 6], {b: 2, c: 3}, {b: 2, c: 3}, [[:a, 1], [:b, 2], [:c, 3]]]");
+        assert_eq!(eval("h = { a: 1, b: 2 }\n[h.transform_values { |v| v * 10 }, h.transform_keys { |k| k.to_s }, h.transform_keys({ a: :x }), h.invert, h.min_by { |k, v| -v }]"), "[{a: 10, b: 20}, {\"a\" => 1, \"b\" => 2}, {x: 1, b: 2}, {1 => :a, 2 => :b}, [:b, 2]]");
+        assert_eq!(eval("[1, 2, 3, 4].group_by { |n| n.odd? }"), "{true => [1, 3], false => [2, 4]}");
+        assert_eq!(eval("{ a: 1, b: 2 }.merge({ b: 3, c: 4 }) { |key, old, new| old + new }"), "{a: 1, b: 5, c: 4}");
+        assert_eq!(eval("h = { a: 1, b: nil }\n[h.compact, h.slice(:b), h.except(:a), h.sum { |k, v| v || 0 }, h.count, h.find { |k, v| v.nil? }]"), "[{a: 1}, {b: nil}, {b: nil}, 1, 2, [:b, nil]]");
+        assert_eq!(eval("h = { a: 1, b: 2 }\n[h.delete_if { |k, v| v > 1 }.to_a, h.reject! { false }, h.shift, h]"), "[[[:a, 1]], nil, [:a, 1], {}]");
+    }
+
+    #[test]
+    fn should_guard_hashes_changed_while_iterating() {
+        assert_eq!(eval("h = { a: 1 }\nh.each { h[:b] = 2 }"), "RuntimeError: can't add a new key into hash during iteration");
+        assert_eq!(eval("h = { a: 1, b: 2, c: 3 }\nr = []\nh.each { |k, v| h.delete(:b) if k == :a\n  h[k] = v * 10\n  r << k }\n[r, h]"), "[[:a, :c], {a: 10, c: 30}]");
+        assert_eq!(eval("h = { a: 1 }\nbegin\n  h.each { h[:new] = 1 }\nrescue RuntimeError\nend\nh[:new] = 2\nh"), "{a: 1, new: 2}");
+    }
+
+    #[test]
+    fn should_compare_hashes() {
+        assert_eq!(eval("a = 'x'\nh = {}.compare_by_identity\nh[a] = 1\nh['x'.dup] = 2\n[h.size, h[a], h['x'], h.compare_by_identity?]"), "[2, 1, nil, true]");
+        assert_eq!(eval("[{ a: 1, b: 2 } == { b: 2, a: 1 }, { a: 1 } == { a: 1.0 }, { a: 1 }.eql?({ a: 1.0 }), { a: 1, b: 2 }.hash == { b: 2, a: 1 }.hash]"), "[true, true, false, true]");
+        assert_eq!(eval("h = { a: 1 }\nh[:self] = h\nh.inspect"), "\"{a: 1, self: {...}}\"");
+        assert_eq!(eval("Hash[[[:a, 1], [:b, 2]]]"), "{a: 1, b: 2}");
+    }
+
+    #[test]
+    fn should_compare_with_comparable() {
+        let version = "class Version\n  include Comparable\n  attr_reader :n\n  def initialize(n)\n    @n = n\n  end\n  def <=>(other)\n    n <=> other.n\n  end\nend\n";
+        assert_eq!(eval(&format!("{}[Version.new(1) < Version.new(2), Version.new(2) == Version.new(2), Version.new(3).between?(Version.new(1), Version.new(2))]", version)), "[true, true, false]");
+        assert_eq!(eval(&format!("{}[Version.new(5).clamp(Version.new(1), Version.new(3)).n, 7.clamp(1, 5), 0.clamp(1..), 'b'.clamp('a', 'c')]", version)), "[3, 5, 1, \"b\"]");
+        assert_eq!(eval("1.clamp(3, 1)"), "ArgumentError: min argument must be less than or equal to max argument");
+        assert_eq!(eval(&format!("{}Version.new(1) < 2", version)), "NoMethodError: undefined method 'n' for an instance of Integer");
+    }
+
+    #[test]
+    fn should_make_enumerators_without_a_block() {
+        assert_eq!(eval("[[1, 2].each, [3, 4].map.with_index { |x, i| x * i }, [:a, :b].each.with_index(1).to_a]"), "[#<Enumerator: [1, 2]:each>, [0, 4], [[:a, 1], [:b, 2]]]");
+        assert_eq!(eval("[[1, 2, 3].select.with_index { |x, i| i > 0 }, { a: 1 }.each.to_a, 3.times.to_a, 3.downto(1).map { |x| x * 2 }]"), "[[2, 3], [[:a, 1]], [0, 1, 2], [6, 4, 2]]");
+        assert_eq!(eval("[[4, 5].each_with_index.to_a, 'ab'.each_char.to_a, [1, 2].each.size, 4.times.size]"), "[[[4, 0], [5, 1]], [\"a\", \"b\"], 2, 4]");
+        assert_eq!(eval("[1, 2].each_with_object([]).each { |x, memo| memo << x * 3 }"), "[3, 6]");
+    }
+
+    #[test]
+    fn should_iterate_enumerators_externally() {
+        assert_eq!(eval("e = [1, 2].each\n[e.next, e.peek, e.next]"), "[1, 2, 2]");
+        assert_eq!(eval("e = [1].each\ne.next\ne.next"), "StopIteration: iteration reached an end");
+        assert_eq!(eval("e = [1, 2].each\ne.next\ne.rewind\ne.next"), "1");
+        let fib = "fib = Enumerator.new do |y|\n  a, b = 0, 1\n  while true\n    y << a\n    a, b = b, a + b\n  end\nend\n";
+        assert_eq!(eval(&format!("{}[fib.take(6), fib.next, fib.next, fib.next, fib.first]", fib)), "[[0, 1, 1, 2, 3, 5], 0, 1, 1, 0]");
+        assert_eq!(eval("e = Enumerator.new { |y| y.yield 1, 2\ny << 3 }\n[e.next_values, e.next, e.to_a]"), "[[1, 2], 3, [[1, 2], 3]]");
+    }
+
+    #[test]
+    fn should_let_go_of_half_consumed_enumerators() {
+        let mut interpreter = Interpreter::new();
+        let list = Value::array(vec!(Value::Integer(1), Value::Integer(2), Value::Integer(3)));
+        interpreter.set_top_level_local("list", list.clone());
+        let references = |list: &Value| match list {
+            Value::Array(array) => Rc::strong_count(array),
+            _ => unreachable!(),
+        };
+        let before = references(&list);
+
+        let value = run(&mut interpreter, "3.times.map { e = list.each\ne.next }").unwrap();
+        assert_eq!(interpreter.inspect(&value).unwrap(), "[1, 1, 1]");
+        assert_eq!(references(&list), before);
+        run(&mut interpreter, "e = list.each\ne.next\ne = nil").unwrap();
+        assert_eq!(references(&list), before);
+    }
+
+    #[test]
+    fn should_evaluate_lazily() {
+        let naturals = "naturals = Enumerator.new do |y|\n  n = 0\n  while true\n    y << n\n    n += 1\n  end\nend\n";
+        assert_eq!(eval(&format!("{}naturals.lazy.map {{ |x| x * x }}.select {{ |x| x.even? }}.first(3)", naturals)), "[0, 4, 16]");
+        assert_eq!(eval(&format!("{}naturals.lazy.reject {{ |x| x.odd? }}.drop(1).take(2).to_a", naturals)), "[2, 4]");
+        assert_eq!(eval("[1, 2, 3].lazy.map { |x| x + 1 }.take_while { |x| x < 4 }.force"), "[2, 3]");
+        assert_eq!(eval("[1, 2].lazy.map { |x| x }.inspect"), "\"#<Enumerator::Lazy: #<Enumerator::Lazy: [1, 2]>:map>\"");
+        assert_eq!(eval("[1, 2].lazy.map"), "ArgumentError: tried to call lazy map without a block");
+    }
+
+    #[test]
+    fn should_intern_symbols() {
+        assert_eq!(eval("[:a.equal?(:a), :a.object_id == 'a'.to_sym.object_id, :a <=> :b, :b.between?(:a, :c), :abc.upcase, :a.succ, :abc[1], :abc.size]"), "[true, true, -1, true, :ABC, :b, \"b\", 3]");
+        assert_eq!(eval("[['a', 'b'].map(&:upcase), [1, 2, 3].inject(&:+), [[1, 2], [3]].map(&:size), :upcase.to_proc.call('x')]"), "[[\"A\", \"B\"], 6, [2, 1], \"X\"]");
+        assert_eq!(eval(":upcase.to_proc.call"), "ArgumentError: no receiver given");
+        assert_eq!(eval("class Thing\n  private def secret\n  end\nend\n[Thing.new].each(&:secret)"), "NoMethodError: private method 'secret' called for an instance of Thing");
+    }
+
+    #[test]
+    fn should_iterate_ranges() {
+        assert_eq!(eval("out = []\n(1..3).each { |x| out << x }\n(1...3).each { |x| out << x * 10 }\nout"), "[1, 2, 3, 10, 20]");
+        assert_eq!(eval("[('a'..'e').to_a, ('az'..'bc').to_a, ('08'..'11').to_a, ('a'...'c').to_a, ('b'..'a').to_a]"), "[[\"a\", \"b\", \"c\", \"d\", \"e\"], [\"az\", \"ba\", \"bb\", \"bc\"], [\"08\", \"09\", \"10\", \"11\"], [\"a\", \"b\"], []]");
+        assert_eq!(eval("[(1..10).step(3).to_a, (1.0..2.0).step(0.5).to_a, ('a'..'e').step(2).to_a, (1..).step(5).first(3), (1.0...2.0).step(0.5).to_a]"), "[[1, 4, 7, 10], [1.0, 1.5, 2.0], [\"a\", \"c\", \"e\"], [1, 6, 11], [1.0, 1.5]]");
+        assert_eq!(eval("[(1..).each.first(3), (1..).lazy.map { |x| x * 2 }.select { |x| x % 3 == 0 }.first(2), (1..Float::INFINITY).first(2)]"), "[[1, 2, 3], [6, 12], [1, 2]]");
+        assert_eq!(eval("class Day\n  attr_reader :n\n  def initialize(n)\n    @n = n\n  end\n  def succ\n    Day.new(n + 1)\n  end\n  def <=>(other)\n    n <=> other.n\n  end\nend\n(Day.new(1)..Day.new(3)).map(&:n)"), "[1, 2, 3]");
+        assert_eq!(eval("(1.5..2).each { }"), "TypeError: can't iterate from Float");
+        assert_eq!(eval("(..3).each { }"), "TypeError: can't iterate from NilClass");
+        assert_eq!(eval("(1..).to_a"), "RangeError: cannot convert endless range to an array");
+        assert_eq!(eval("(1..3).step(0) { }"), "ArgumentError: step can't be 0");
+    }
+
+    #[test]
+    fn should_test_range_membership() {
+        assert_eq!(eval("[(1..5).include?(3), (1...5).include?(5), (1..5).cover?(2.5), (1..).include?(10 ** 20), (..0).cover?(-1), (1..5) === 5]"), "[true, false, true, true, true, true]");
+        assert_eq!(eval("[('a'..'z').include?('bb'), ('a'..'z').cover?('bb'), ('a'..'z') === 'c', (1..10).cover?(2..5), (1..10).cover?(5..11), (1...10).cover?(2...10)]"), "[false, true, true, true, false, true]");
+        assert_eq!(eval("('a'..).include?('b')"), "TypeError: cannot determine inclusion in beginless/endless ranges");
+    }
+
+    #[test]
+    fn should_compare_and_hash_ranges_by_their_ends() {
+        assert_eq!(eval("[(1..2) == (1..2), (1..2) == (1...2), (1..2) == (1.0..2.0), (1..) == (1..nil), (1..2) == [1, 2]]"), "[true, false, true, true, false]");
+        assert_eq!(eval("[(1..2).eql?(1..2), (1..2).eql?(1.0..2.0), ('a'..'b').eql?('a'..'b'), (1..2).eql?(1...2)]"), "[true, false, true, false]");
+        assert_eq!(eval("[(1..2).hash == (1..2).hash, ('a'..'b').hash == ('a'..'b').hash, (1..2).hash == (1...2).hash]"), "[true, true, false]");
+        assert_eq!(eval("h = { (1..2) => :a }\n[h[1..2], h[1...2], [1..2, 1..2, 1...2].uniq]"), "[:a, nil, [1..2, 1...2]]");
+    }
+
+    #[test]
+    fn should_summarize_ranges() {
+        assert_eq!(eval("[(1..100).sum, (1...1).sum, (1..4).sum(10), (1..3).sum { |x| x * x }, (1..4).sum(0.0), (1..3_000_000_000).sum]"), "[5050, 0, 20, 14, 10.0, 4500000001500000000]");
+        assert_eq!(eval("[(0...-9223372036854775808).sum, (1..2**64).sum, (2**64..2**64 + 2).sum, (9223372036854775806..9223372036854775807).to_a]"),
+            "[0, 170141183460469231740910675752738881536, 55340232221128654851, [9223372036854775806, 9223372036854775807]]");
+        assert_eq!(eval("[(1..10).size, (1...10).size, (1..).size, ('a'..'z').size, (1..3.5).size, (5..1).size, (1..10).count, ('a'..'c').count]"), "[10, 9, Infinity, nil, 3, 0, 10, 3]");
+        assert_eq!(eval("[(1..5).first, (1..5).first(2), (1..5).last(2), (1...5).max, (1..5).min, (5..1).max, ('a'..'c').max, (1..5).max { |a, b| b <=> a }]"), "[1, [1, 2], [4, 5], 4, 1, nil, \"c\", 1]");
+        assert_eq!(eval("(1..).last"), "RangeError: cannot get the last element of endless range");
+        assert_eq!(eval("(..1).first"), "RangeError: cannot get the first element of beginless range");
+        assert_eq!(eval("(1...2.5).max"), "TypeError: cannot exclude non Integer end value");
+    }
+
+    /// Collects what a program writes, so tests can look at it after the interpreter is done.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs `input` reading `stdin`, and returns what it printed.
+    fn output(input: &str, stdin: &str) -> String {
+        let captured = Captured::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(captured.clone()));
+        interpreter.set_input(Box::new(io::Cursor::new(stdin.as_bytes().to_vec())));
+        if let Err(Control::Raise(exception)) = run(&mut interpreter, input) {
+            panic!("{}", interpreter.exception_message(&exception));
+        }
+        let bytes = captured.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn should_write_output() {
+        assert_eq!(output("puts 1, 'a', nil, [2, [3]], []\nputs\nputs \"b\\n\"", ""), "1\na\n\n2\n3\n\n\nb\n");
+        assert_eq!(output("a = [1]\na << a\nputs a", ""), "1\n[...]\n");
+        assert_eq!(output("print 'a', 1, :b\nprint nil", ""), "a1b");
+        assert_eq!(output("x = p 1, 'a'\np x\np", ""), "1\n\"a\"\n[1, \"a\"]\n");
+        assert_eq!(output("pp({ a: 1 })", ""), "{a: 1}\n");
+        assert_eq!(output("printf('%05.1f|%-3s|%x', 3.14159, 'a', 255)", ""), "003.1|a  |ff");
+        assert_eq!(eval("[format('%+d', 5), sprintf('%s and %p', 'a', 'a'), p(1), puts]"), "[\"+5\", \"a and \\\"a\\\"\", 1, nil]");
+    }
+
+    /// Runs `input`, and returns what it printed and what it wrote to the error output.
+    fn both_outputs(input: &str) -> (String, String) {
+        let (captured, errors) = (Captured::default(), Captured::default());
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(captured.clone()));
+        interpreter.set_error_output(Box::new(errors.clone()));
+        if let Err(Control::Raise(exception)) = run(&mut interpreter, input) {
+            assert!(interpreter.is_a(&exception, "SystemExit"), "{}", interpreter.exception_message(&exception));
+        }
+        let text = |captured: Captured| String::from_utf8(captured.0.borrow().clone()).unwrap();
+        (text(captured), text(errors))
+    }
+
+    #[test]
+    fn should_write_to_stdout_and_stderr() {
+        assert_eq!(both_outputs("puts 1\n$stderr.puts 'e', [2]\nwarn 'w', 'x'\nwarn\nSTDERR.print 'p'\n"), (String::from("1\n"), String::from("e\n2\nw\nx\np")));
+        assert_eq!(both_outputs("$stdout.puts 1\nSTDOUT.print 'a', :b\nx = $stdout.write('cd', 5)\n$stdout << x << \"\\n\"\n$stdout.printf('%03d', 7)"), (String::from("1\nabcd53\n007"), String::new()));
+        assert_eq!(both_outputs("$VERBOSE = nil\nwarn 'quiet'\nabort 'bye'\nputs 'not reached'"), (String::new(), String::from("bye\n")));
+        assert_eq!(eval("[STDOUT, $stderr, $stdout.equal?(STDOUT), STDERR.fileno, warn('x', uplevel: 0)]"), "[#<IO:<STDOUT>>, #<IO:<STDERR>>, true, 2, nil]");
+    }
+
+    #[test]
+    fn should_pretty_print_what_is_too_wide() {
+        assert_eq!(output("x = pp({ a: 1 }, [2])\np x", ""), "{a: 1}\n[2]\n[{a: 1}, [2]]\n");
+        assert_eq!(output("pp (1..12).map { |i| 'item' * i }.first(5)", ""), "[\"item\", \"itemitem\", \"itemitemitem\", \"itemitemitemitem\", \"itemitemitemitemitem\"]\n");
+        assert_eq!(output("pp ['a' * 30, ['b' * 30, 'c' * 30]]", ""), "[\"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\",\n [\"bbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\", \"cccccccccccccccccccccccccccccc\"]]\n");
+        assert_eq!(output("pp({ name: 'a' * 40, list: ['b' * 30, 'c' * 30] })", ""), "{name: \"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\",\n list: [\"bbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\", \"cccccccccccccccccccccccccccccc\"]}\n");
+        assert_eq!(output("a = ['x' * 40]\na << a\npp a", ""), "[\"xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx\", [...]]\n");
+    }
+
+    #[test]
+    fn should_read_input() {
+        assert_eq!(output("while line = gets\n  print line.upcase\nend\np $_", "ab\ncd"), "AB\nCDnil\n");
+        assert_eq!(output("p gets", ""), "nil\n");
+        assert_eq!(eval("sleep(0)"), "0");
+        assert_eq!(eval("sleep(-1)"), "ArgumentError: time interval must not be negative");
+    }
+
+    #[test]
+    fn should_generate_random_numbers() {
+        assert_eq!(eval("srand(42)\n[rand(100), rand(100), rand(100)]"), "[51, 92, 14]");
+        assert_eq!(eval("srand(42)\nrand"), "0.3745401188473625");
+        assert_eq!(eval("srand(1)\nsrand(2)"), "1");
+        assert_eq!(eval("srand(5)\na = [rand, rand(10), rand(1..6)]\nsrand(5)\na == [rand, rand(10), rand(1..6)]"), "true");
+        assert_eq!(eval("srand(2**40)\nx = rand(1000)\nsrand(2**40)\nx == rand(1000)"), "true");
+        assert_eq!(eval("100.times.map { rand(-3) }.uniq.sort"), "[0, 1, 2]");
+        assert_eq!(eval("100.times.all? { (1...3).cover?(rand(1...3)) && (1.5..2.0).cover?(rand(1.5..2.0)) }"), "true");
+        assert_eq!(eval("[rand(1), rand(3..1), rand.class]"), "[0, nil, Float]");
+    }
+
+    #[test]
+    fn should_loop_catch_and_throw() {
+        assert_eq!(eval("i = 0\nloop do\n  i += 1\n  break i * 10 if i == 3\nend"), "30");
+        assert_eq!(eval("e = [1, 2].each\n[loop { e.next }, loop { raise StopIteration }, begin\n  e.next\nrescue StopIteration => error\n  error.result\nend]"), "[[1, 2], nil, [1, 2]]");
+        assert_eq!(eval("e = [1, 2].each\na = []\nr = loop { a << e.next }\n[a, r]"), "[[1, 2], [1, 2]]");
+        assert_eq!(eval("loop.class"), "Enumerator");
+        assert_eq!(eval("catch(:done) do\n  10.times { |i| 10.times { |j| throw :done, [i, j] if i * j == 6 } }\n  :never\nend"), "[1, 6]");
+        assert_eq!(eval("catch(:x) { 1 }"), "1");
+        assert_eq!(eval("catch(:x) { throw :x }"), "nil");
+        assert_eq!(eval("catch { |tag| catch(:inner) { throw tag, 2 }\n3 }"), "2");
+        assert_eq!(eval("def f\n  throw :out, 5\nensure\n  $ran = true\nend\n[catch(:out) { f }, $ran]"), "[5, true]");
+        assert_eq!(eval("begin\n  catch(:a) { throw :b }\nrescue UncaughtThrowError => e\n  e.message\nend"), "\"uncaught throw :b\"");
+    }
+
+    #[test]
+    fn should_convert_with_kernel_functions() {
+        assert_eq!(eval("[Integer('0x1f'), Integer('12', 8), Integer(3.9)]"), "[31, 10, 3]");
+        assert_eq!(eval("[Integer('z', exception: false), Float('1e3'), Float('x', exception: false), Float(2)]"), "[nil, 1000.0, nil, 2.0]");
+        assert_eq!(eval("Float('1.5x')"), "ArgumentError: invalid value for Float(): \"1.5x\"");
+        assert_eq!(eval("[String(1), String(nil), String(:a), String([1])]"), "[\"1\", \"\", \"a\", \"[1]\"]");
+        assert_eq!(eval("[Array(nil), Array([1]), Array(1..3), Array({ a: 1 }), Array('a')]"), "[[], [1], [1, 2, 3], [[:a, 1]], [\"a\"]]");
+    }
+
+    #[test]
+    fn should_freeze_objects() {
+        assert_eq!(eval("a = [1].freeze\na << 2"), "FrozenError: can't modify frozen Array: [1]");
+        assert_eq!(eval("h = { a: 1 }.freeze\nh[:b] = 2"), "FrozenError: can't modify frozen Hash: {a: 1}");
+        assert_eq!(eval("class C; def initialize; @a = 1; end; def set; @a = 2; end; end\nbegin
+  C.new.freeze.set
+rescue FrozenError => e
+  e.message.start_with?(\"can't modify frozen C: #<C:0x\")
+end"), "true");
+        assert_eq!(eval("a = [1].freeze\n[a.frozen?, a.dup.frozen?, a.clone.frozen?, a.clone(freeze: false).frozen?, 1.frozen?, :a.frozen?, nil.frozen?]"), "[true, false, true, false, true, true, true]");
+        assert_eq!(eval("o = Object.new\no.instance_variable_set(:@a, [1])\nc = o.freeze.clone\n[c.frozen?, c.instance_variable_get(:@a), c.dup.frozen?]"), "[true, [1], false]");
+        assert_eq!(eval("s = 'a'.freeze\n[s.clone.frozen?, s.clone.equal?(s)]"), "[true, false]");
+    }
+
+    #[test]
+    fn should_reflect_on_objects() {
+        assert_eq!(eval("class C; private def secret(x); x * 2; end; end\n[C.new.send(:secret, 2), C.new.__send__('secret', 3)]"), "[4, 6]");
+        assert_eq!(eval("class C; private def secret; end; end\nC.new.public_send(:secret)"), "NoMethodError: private method 'secret' called for an instance of C");
+        assert_eq!(eval("[1, 2].public_send(:map) { |x| x + 1 }"), "[2, 3]");
+        assert_eq!(eval("1.send"), "ArgumentError: no method name given");
+        assert_eq!(eval("o = Object.new\no.instance_variable_set('@a', 1)\n[o.instance_variable_get(:@a), o.instance_variable_get(:@b), o.instance_variables, o.instance_variable_defined?(:@a)]"), "[1, nil, [:@a], true]");
+        assert_eq!(eval("Object.new.instance_variable_get(:a)"), "NameError: 'a' is not allowed as an instance variable name");
+        assert_eq!(eval("[1.respond_to?(:+), 1.respond_to?(:nope)]"), "[true, false]");
+    }
+
+    /// A fresh directory under the temporary one, holding `files`.
+    fn scratch(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("rri-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        for (file, source) in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        directory
+    }
+
+    /// Runs `main.rb` in the directory with its `lib` on the load path, and returns what it
+    /// printed, followed by the exception that ended it if one did.
+    fn run_main(directory: &Path) -> String {
+        let file = directory.join("main.rb");
+        let mut lexer = Lexer::new(fs::read_to_string(&file).unwrap());
+        let mut parser = Parser::new(&mut lexer);
+        let (mut program, errors) = parser.parse_program();
+        assert!(errors.is_empty(), "parser errors: {:?}", errors);
+        program.ast.set_file(&file.to_string_lossy());
+
+        let captured = Captured::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(captured.clone()));
+        interpreter.prepend_load_path(&[directory.join("lib").to_string_lossy().into_owned()]);
+        let ending = match interpreter.eval_program(program) {
+            Err(Control::Raise(exception)) => {
+                format!("{}: {}", interpreter.class_of(&exception).name(), interpreter.exception_message(&exception))
+            },
+            _ => String::new(),
+        };
+        let printed = String::from_utf8(captured.0.borrow().clone()).unwrap();
+        let _ = fs::remove_dir_all(directory);
+        printed + &ending
+    }
+
+    #[test]
+    fn should_require_files_once() {
+        let directory = scratch("require", &[
+            ("lib/shapes.rb", "puts 'loading shapes'\nclass Square; def area; 4; end; end\nx = 1"),
+            ("lib/shapes/circle.rb", "class Circle; end\nreturn\nraise 'not reached'"),
+            ("main.rb", concat!(
+                "p require('shapes'), require('shapes.rb'), require('shapes/circle')\n",
+                "p Square.new.area, (x rescue :none)\n",
+                "p $LOADED_FEATURES.map { |feature| feature.split('/').last(2).join('/') }, $:.equal?($LOAD_PATH)\n",
+                "p load('shapes.rb')\n",
+                "begin\n  require 'missing'\nrescue LoadError => e\n  p e.message\nend\n",
+                "load 'missing.rb'",
+            )),
+        ]);
+        assert_eq!(run_main(&directory), concat!(
+            "loading shapes\n",
+            "true\nfalse\ntrue\n",
+            "4\n:none\n",
+            "[\"lib/shapes.rb\", \"shapes/circle.rb\"]\ntrue\n",
+            "loading shapes\ntrue\n",
+            "\"cannot load such file -- missing\"\n",
+            "LoadError: cannot load such file -- missing.rb",
+        ));
+    }
+
+    #[test]
+    fn should_require_relative_files() {
+        let directory = scratch("require-relative", &[
+            ("models/a.rb", "require_relative 'b'\nA = :a\np __FILE__.end_with?('models/a.rb'), __dir__.end_with?('models')"),
+            ("models/b.rb", "p require_relative('a')\nB = :b"),
+            ("broken.rb", "def f(1)\n"),
+            ("failing.rb", "$tries = ($tries || 0) + 1\nraise 'boom' if $tries == 1"),
+            ("main.rb", concat!(
+                "p require_relative('models/a'), require_relative('./models/b')\n",
+                "p [A, B]\n",
+                "3.times { begin\n  p require_relative('failing')\nrescue => e\n  p e.message\nend }\n",
+                "require_relative 'broken'",
+            )),
+        ]);
+        let output = run_main(&directory);
+        assert!(output.starts_with("false\ntrue\ntrue\ntrue\nfalse\n[:a, :b]\n\"boom\"\ntrue\nfalse\nSyntaxError: "), "{}", output);
+        assert!(output.contains("broken.rb:1:7: "), "{}", output);
+    }
+
+    #[test]
+    fn should_exit_with_a_status() {
+        let mut interpreter = Interpreter::new();
+        for (program, status) in [("exit", 0), ("exit(3)", 3), ("exit false", 1), ("abort", 1), ("raise 'x'", -1)] {
+            let exception = match run(&mut interpreter, program) {
+                Err(Control::Raise(exception)) => exception,
+                _ => panic!("{} should raise", program),
+            };
+            assert_eq!(interpreter.exit_status(&exception).unwrap_or(-1), status, "{}", program);
+        }
+        assert_eq!(eval("begin\n  exit 2\nrescue => e\n  :rescued\nrescue SystemExit => e\n  [e.status, e.success?, e.message]\nend"), "[2, false, \"exit\"]");
+        assert_eq!(eval("e = SystemExit.new(true, 'done')\n[e.status, e.success?, e.message, SystemExit.new('bye').status]"), "[0, true, \"done\", 0]");
+        assert_eq!(eval("begin\n  begin\n    exit\n  ensure\n    $ran = true\n  end\nrescue SystemExit\nend\n$ran"), "true");
+    }
+
+    #[test]
+    fn should_set_arguments() {
+        assert_eq!(eval("[ARGV, $0, $PROGRAM_NAME, $VERBOSE]"), "[[], \"-\", \"-\", false]");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_arguments("script.rb", &[String::from("a"), String::from("-x")]);
+        let value = run(&mut interpreter, "'b' =~ /(b)/\n[ARGV, $0, $1]").unwrap();
+        assert_eq!(interpreter.inspect(&value).unwrap(), "[[\"a\", \"-x\"], \"script.rb\", \"b\"]");
+    }
+}
@@ -0,0 +1,466 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// An integer of any size: a sign and a magnitude in base 2^32 limbs, least significant
+/// first and without zero limbs at the top. Zero has no limbs and is never negative.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs:    Vec<u32>,
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+        BigInt { negative, limbs }
+    }
+
+    pub fn from_i64(value: i64) -> BigInt {
+        let magnitude = value.unsigned_abs();
+        BigInt::from_parts(value < 0, vec!(magnitude as u32, (magnitude >> 32) as u32))
+    }
+
+    /// The integer part of a finite float.
+    pub fn from_f64(value: f64) -> BigInt {
+        let bits = value.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        if exponent < 1023 { return BigInt::from_i64(0); }
+
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let magnitude = BigInt::from_parts(false, vec!(mantissa as u32, (mantissa >> 32) as u32));
+        let shift = exponent - 1075;
+        let magnitude = if shift >= 0 { magnitude.shl(shift as usize) } else { magnitude.shr((-shift) as usize) };
+        if value < 0.0 { magnitude.neg() } else { magnitude }
+    }
+
+    /// The value as an `i64`, if it fits in one.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 { return None; }
+        let magnitude = self.limbs.iter().rev().fold(0_u64, |value, limb| (value << 32) | u64::from(*limb));
+        match self.negative {
+            true if magnitude <= 1 << 63 => Some((magnitude as i64).wrapping_neg()),
+            true => None,
+            false => i64::try_from(magnitude).ok(),
+        }
+    }
+
+    /// The magnitude in 32-bit words, least significant first.
+    pub fn magnitude_limbs(&self) -> &[u32] {
+        &self.limbs
+    }
+
+    /// The nearest float, or an infinity when the value is out of range.
+    pub fn to_f64(&self) -> f64 {
+        // the top 64 bits are enough to round correctly almost always
+        let length = self.magnitude_bits();
+        let shift = length.saturating_sub(64);
+        let top = self.abs().shr(shift);
+        let top = top.limbs.iter().rev().fold(0_u64, |value, limb| (value << 32) | u64::from(*limb));
+        let magnitude = top as f64 * 2_f64.powi(i32::try_from(shift).unwrap_or(i32::MAX));
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.limbs.first().is_none_or(|limb| limb & 1 == 0)
+    }
+
+    /// The number of bits needed to write the magnitude.
+    pub fn magnitude_bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => self.limbs.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    /// The number of bits of the value in two's complement, not counting the sign bit, as
+    /// `Integer#bit_length` gives it.
+    pub fn bit_length(&self) -> usize {
+        if self.negative { self.not().magnitude_bits() } else { self.magnitude_bits() }
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::from_parts(false, self.limbs.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitudes(&self.limbs, &other.limbs));
+        }
+        match compare_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::from_parts(other.negative, subtract_magnitudes(&other.limbs, &self.limbs)),
+            _ => BigInt::from_parts(self.negative, subtract_magnitudes(&self.limbs, &other.limbs)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != other.negative, multiply_magnitudes(&self.limbs, &other.limbs))
+    }
+
+    /// The quotient rounded towards zero and the remainder with the sign of `self`. `other`
+    /// must not be zero.
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        let (quotient, remainder) = divide_magnitudes(&self.limbs, &other.limbs);
+        (BigInt::from_parts(self.negative != other.negative, quotient), BigInt::from_parts(self.negative, remainder))
+    }
+
+    /// The quotient rounded towards negative infinity and the modulo with the sign of
+    /// `other`, as Ruby's `/` and `%` give them. `other` must not be zero.
+    pub fn div_mod_floor(&self, other: &BigInt) -> (BigInt, BigInt) {
+        let (quotient, remainder) = self.div_rem(other);
+        if !remainder.is_zero() && remainder.negative != other.negative {
+            return (quotient.sub(&BigInt::from_i64(1)), remainder.add(other));
+        }
+        (quotient, remainder)
+    }
+
+    pub fn pow(&self, mut exponent: u64) -> BigInt {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 { result = result.mul(&base); }
+            exponent >>= 1;
+            if exponent > 0 { base = base.mul(&base); }
+        }
+        result
+    }
+
+    /// `self ** exponent % modulus` without building the full power. `exponent` must not be
+    /// negative and `modulus` must not be zero.
+    pub fn pow_mod(&self, exponent: &BigInt, modulus: &BigInt) -> BigInt {
+        let mut result = BigInt::from_i64(1).div_mod_floor(modulus).1;
+        let mut base = self.div_mod_floor(modulus).1;
+        for bit in 0..exponent.magnitude_bits() {
+            if exponent.limbs[bit / 32] >> (bit % 32) & 1 == 1 {
+                result = result.mul(&base).div_mod_floor(modulus).1;
+            }
+            base = base.mul(&base).div_mod_floor(modulus).1;
+        }
+        result
+    }
+
+    pub fn shl(&self, bits: usize) -> BigInt {
+        if self.is_zero() { return self.clone(); }
+        let (limbs, bits) = (bits / 32, bits % 32);
+        let mut shifted = vec!(0; limbs);
+        let mut carry = 0;
+        for limb in self.limbs.iter() {
+            shifted.push((limb << bits) | carry);
+            carry = if bits == 0 { 0 } else { limb >> (32 - bits) };
+        }
+        shifted.push(carry);
+        BigInt::from_parts(self.negative, shifted)
+    }
+
+    /// Shifts right rounding towards negative infinity, so `-1 >> 1` stays `-1`.
+    pub fn shr(&self, bits: usize) -> BigInt {
+        if self.negative {
+            return self.not().shr(bits).not();
+        }
+        let (limbs, bits) = (bits / 32, bits % 32);
+        if limbs >= self.limbs.len() { return BigInt::from_i64(0); }
+        let mut shifted = Vec::with_capacity(self.limbs.len() - limbs);
+        for index in limbs..self.limbs.len() {
+            let high = if bits == 0 { 0 } else { self.limbs.get(index + 1).map_or(0, |limb| limb << (32 - bits)) };
+            shifted.push((self.limbs[index] >> bits) | high);
+        }
+        BigInt::from_parts(false, shifted)
+    }
+
+    /// `~self`, which is `-self - 1` in two's complement.
+    pub fn not(&self) -> BigInt {
+        self.neg().sub(&BigInt::from_i64(1))
+    }
+
+    pub fn and(&self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a | b)
+    }
+
+    pub fn xor(&self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+
+    /// Applies `op` to the two's complement forms of both numbers, as if negative numbers
+    /// had infinitely many one bits at the top.
+    fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> BigInt {
+        let length = self.limbs.len().max(other.limbs.len()) + 1;
+        let (a, b) = (self.twos_complement(length), other.twos_complement(length));
+        let limbs: Vec<u32> = a.iter().zip(b.iter()).map(|(a, b)| op(*a, *b)).collect();
+        if limbs[length - 1] >> 31 == 0 {
+            return BigInt::from_parts(false, limbs);
+        }
+        let inverted = BigInt::from_parts(false, limbs.iter().map(|limb| !limb).collect());
+        inverted.add(&BigInt::from_i64(1)).neg()
+    }
+
+    fn twos_complement(&self, length: usize) -> Vec<u32> {
+        if !self.negative {
+            let mut limbs = self.limbs.clone();
+            limbs.resize(length, 0);
+            return limbs;
+        }
+        let mut limbs = self.abs().sub(&BigInt::from_i64(1)).limbs;
+        limbs.resize(length, 0);
+        limbs.iter().map(|limb| !limb).collect()
+    }
+
+    /// Parses digits in `radix`, with an optional leading `-`.
+    pub fn parse(text: &str, radix: u32) -> Option<BigInt> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        if digits.is_empty() { return None; }
+
+        let mut limbs: Vec<u32> = vec!();
+        for digit in digits.chars() {
+            let digit = digit.to_digit(radix)?;
+            let mut carry = u64::from(digit);
+            for limb in limbs.iter_mut() {
+                let product = u64::from(*limb) * u64::from(radix) + carry;
+                *limb = product as u32;
+                carry = product >> 32;
+            }
+            if carry > 0 { limbs.push(carry as u32); }
+        }
+        Some(BigInt::from_parts(negative, limbs))
+    }
+
+    /// The digits in `radix`, lowercase, with a leading `-` when negative.
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        if self.is_zero() { return String::from("0"); }
+
+        // peel off as many digits at a time as fit in a limb
+        let (mut chunk, mut width) = (radix, 1);
+        while let Some(next) = chunk.checked_mul(radix) {
+            chunk = next;
+            width += 1;
+        }
+        let mut chunks = vec!();
+        let mut limbs = self.limbs.clone();
+        while !limbs.is_empty() {
+            let (quotient, remainder) = divide_by_limb(&limbs, chunk);
+            chunks.push(remainder);
+            limbs = quotient;
+        }
+
+        let mut text = String::from(if self.negative { "-" } else { "" });
+        for (index, chunk) in chunks.iter().rev().enumerate() {
+            let digits = radix_digits(*chunk, radix);
+            if index > 0 {
+                text.extend(std::iter::repeat_n('0', width - digits.len()));
+            }
+            text += &digits;
+        }
+        text
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.limbs, &other.limbs),
+            (true, true) => compare_magnitudes(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_string_radix(10))
+    }
+}
+
+fn radix_digits(mut value: u32, radix: u32) -> String {
+    let mut digits = vec!();
+    loop {
+        digits.push(std::char::from_digit(value % radix, radix).expect("a digit below the radix"));
+        value /= radix;
+        if value == 0 { break; }
+    }
+    digits.iter().rev().collect()
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0_u64;
+    for (index, limb) in long.iter().enumerate() {
+        let total = u64::from(*limb) + u64::from(short.get(index).copied().unwrap_or(0)) + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b` for `a >= b`.
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0_i64;
+    for (index, limb) in a.iter().enumerate() {
+        let mut total = i64::from(*limb) - i64::from(b.get(index).copied().unwrap_or(0)) - borrow;
+        borrow = 0;
+        if total < 0 {
+            total += 1 << 32;
+            borrow = 1;
+        }
+        difference.push(total as u32);
+    }
+    difference
+}
+
+fn multiply_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() { return vec!(); }
+    let mut product = vec!(0_u32; a.len() + b.len());
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0_u64;
+        for (j, y) in b.iter().enumerate() {
+            let total = u64::from(*x) * u64::from(*y) + u64::from(product[i + j]) + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    product
+}
+
+fn divide_by_limb(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec!(0; a.len());
+    let mut remainder = 0_u64;
+    for index in (0..a.len()).rev() {
+        let current = (remainder << 32) | u64::from(a[index]);
+        quotient[index] = (current / u64::from(divisor)) as u32;
+        remainder = current % u64::from(divisor);
+    }
+    while quotient.last() == Some(&0) {
+        quotient.pop();
+    }
+    (quotient, remainder as u32)
+}
+
+/// Long division of magnitudes, one bit at a time unless the divisor fits in a limb.
+fn divide_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (quotient, remainder) = divide_by_limb(a, b[0]);
+        return (quotient, vec!(remainder));
+    }
+    if compare_magnitudes(a, b) == Ordering::Less {
+        return (vec!(), a.to_vec());
+    }
+
+    let mut quotient = vec!(0; a.len());
+    let mut remainder: Vec<u32> = vec!();
+    for bit in (0..a.len() * 32).rev() {
+        // remainder = remainder * 2 + the next bit of a
+        let mut carry = a[bit / 32] >> (bit % 32) & 1;
+        for limb in remainder.iter_mut() {
+            let next = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = next;
+        }
+        if carry > 0 { remainder.push(carry); }
+
+        if compare_magnitudes(&remainder, b) != Ordering::Less {
+            remainder = subtract_magnitudes(&remainder, b);
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text, 10).unwrap()
+    }
+
+    #[test]
+    fn should_do_arithmetic_beyond_a_machine_word() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(a.add(&b).to_string(), "-864197532086419753208641975320");
+        assert_eq!(a.sub(&b).to_string(), "1111111110111111111011111111100");
+        assert_eq!(a.mul(&b).to_string(), "-121932631137021795226185032733622923332237463801111263526900");
+        assert_eq!(BigInt::from_i64(2).pow(100).to_string(), "1267650600228229401496703205376");
+        assert_eq!(BigInt::from_i64(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from_i64(i64::MAX).add(&BigInt::from_i64(1)).to_i64(), None);
+    }
+
+    #[test]
+    fn should_divide_rounding_towards_negative_infinity() {
+        let a = big("-1000000000000000000000000000007");
+        let b = big("10000000000000000000000");
+        let (quotient, modulo) = a.div_mod_floor(&b);
+        assert_eq!((quotient.to_string(), modulo.to_string()), (String::from("-100000001"), String::from("9999999999999999999993")));
+        let (quotient, remainder) = a.div_rem(&b);
+        assert_eq!((quotient.to_string(), remainder.to_string()), (String::from("-100000000"), String::from("-7")));
+        assert_eq!(big("7").pow_mod(&big("560"), &big("561")).to_string(), "1");
+        assert_eq!(big("3").pow_mod(&big("1000"), &big("-7")).to_string(), "-3");
+    }
+
+    #[test]
+    fn should_use_twos_complement_for_bit_operations() {
+        let a = big("-36893488147419103232");
+        assert_eq!(a.and(&big("-1")).to_string(), a.to_string());
+        assert_eq!(a.or(&big("5")).to_string(), "-36893488147419103227");
+        assert_eq!(a.xor(&big("-1")).to_string(), "36893488147419103231");
+        assert_eq!(a.not().to_string(), "36893488147419103231");
+        assert_eq!(big("-5").shr(1).to_string(), "-3");
+        assert_eq!(big("-1").shl(70).to_string(), "-1180591620717411303424");
+        assert_eq!(big("255").bit_length(), 8);
+        assert_eq!(big("-256").bit_length(), 8);
+    }
+
+    #[test]
+    fn should_convert_to_and_from_text_and_floats() {
+        assert_eq!(BigInt::parse("-ff", 16).unwrap().to_string(), "-255");
+        assert_eq!(big("18446744073709551616").to_string_radix(16), "10000000000000000");
+        assert_eq!(big("-1267650600228229401496703205376").to_string_radix(2).len(), 102);
+        assert_eq!(big("1000000000000000000000000").to_string(), "1000000000000000000000000");
+        assert_eq!(BigInt::from_f64(1e20).to_string(), "100000000000000000000");
+        assert_eq!(BigInt::from_f64(-2.5).to_string(), "-2");
+        assert_eq!(big("100000000000000000000").to_f64(), 1e20);
+        assert!(BigInt::parse("12a", 10).is_none());
+    }
+}
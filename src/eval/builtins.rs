@@ -0,0 +1,132 @@
+pub mod array;
+pub mod comparable;
+pub mod encoding;
+pub mod enumerable;
+pub mod enumerator;
+pub mod exception;
+pub mod format;
+pub mod hash;
+pub mod io;
+pub mod kernel;
+pub mod load;
+pub mod module;
+pub mod numeric;
+pub mod object;
+pub mod pack;
+pub mod proc;
+pub mod range;
+pub mod regexp;
+pub mod string;
+pub mod symbol;
+
+use std::slice;
+
+use crate::eval::{ Control, Interpreter };
+use crate::eval::value::Value;
+
+/// Defines the methods of every core class.
+pub fn define(interpreter: &mut Interpreter) {
+    object::define(interpreter);
+    module::define(interpreter);
+    exception::define(interpreter);
+    encoding::define(interpreter);
+    numeric::define(interpreter);
+    string::define(interpreter);
+    regexp::define(interpreter);
+    symbol::define(interpreter);
+    array::define(interpreter);
+    enumerable::define(interpreter);
+    enumerator::define(interpreter);
+    comparable::define(interpreter);
+    hash::define(interpreter);
+    range::define(interpreter);
+    proc::define(interpreter);
+    kernel::define(interpreter);
+    io::define(interpreter);
+    load::define(interpreter);
+}
+
+/// Raises `ArgumentError` unless between `min` and `max` arguments were given.
+pub fn check_arity(interpreter: &Interpreter, args: &[Value], min: usize, max: usize) -> Result<(), Control> {
+    if args.len() >= min && args.len() <= max { return Ok(()); }
+
+    let expected = if min == max { min.to_string() } else { format!("{}..{}", min, max) };
+    let message = format!("wrong number of arguments (given {}, expected {})", args.len(), expected);
+    Err(interpreter.error("ArgumentError", &message))
+}
+
+/// Raises `FrozenError` if the value can't be changed.
+pub fn check_frozen(interpreter: &mut Interpreter, value: &Value) -> Result<(), Control> {
+    if !value.is_frozen() { return Ok(()); }
+
+    let message = format!("can't modify frozen {}: {}", interpreter.class_of(value).name(), interpreter.inspect(value)?);
+    Err(interpreter.error("FrozenError", &message))
+}
+
+/// The argument as an `Integer`, or a `TypeError`.
+pub fn expect_integer(interpreter: &Interpreter, value: &Value) -> Result<i64, Control> {
+    match value {
+        Value::Integer(value) => Ok(*value),
+        Value::Bignum(_) => Err(interpreter.error("RangeError", "bignum too big to convert into 'long'")),
+        Value::Float(value) if value.is_finite() => Ok(*value as i64),
+        value => Err(conversion_error(interpreter, value, "Integer")),
+    }
+}
+
+/// The argument as a `String`, or a `TypeError`.
+pub fn expect_string(interpreter: &Interpreter, value: &Value) -> Result<String, Control> {
+    match value {
+        Value::String(string) => Ok(string.text()),
+        value => Err(conversion_error(interpreter, value, "String")),
+    }
+}
+
+/// A method, variable or constant name given as a symbol or a string.
+pub fn expect_name(interpreter: &mut Interpreter, value: &Value) -> Result<String, Control> {
+    match value {
+        Value::Symbol(name) => Ok(name.to_string()),
+        Value::String(name) => Ok(name.text()),
+        value => {
+            let message = format!("{} is not a symbol nor a string", interpreter.inspect(value)?);
+            Err(interpreter.error("TypeError", &message))
+        }
+    }
+}
+
+/// The `TypeError` for a value of the wrong type, like `no implicit conversion of nil into
+/// String`.
+pub fn conversion_error(interpreter: &Interpreter, value: &Value, into: &str) -> Control {
+    let from = match value {
+        Value::Nil => String::from("nil"),
+        Value::Bool(value) => value.to_string(),
+        value => interpreter.class_of(value).inspect(),
+    };
+    interpreter.error("TypeError", &format!("no implicit conversion of {} into {}", from, into))
+}
+
+/// `eql?` for the builtin types: equal numbers of the same class, strings with the same
+/// contents, and arrays of `eql?` elements. Any other objects have to be the same object.
+pub fn eql(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => string::same_contents(a, b),
+        (Value::Float(a), Value::Float(b)) => a == b,
+        (Value::Bignum(a), Value::Bignum(b)) => a == b,
+        (Value::Array(a), Value::Array(b)) => {
+            let (a, b) = (a.elements.borrow(), b.elements.borrow());
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| eql(a, b))
+        },
+        (Value::Range(a), Value::Range(b)) => a.exclusive == b.exclusive && eql(&a.start, &b.start) && eql(&a.end, &b.end),
+        (a, b) => a.is_identical(b),
+    }
+}
+
+/// Calls `==`, comparing the builtin types without going through method dispatch.
+pub fn equals(interpreter: &mut Interpreter, a: &Value, b: &Value) -> Result<bool, Control> {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(a == b),
+        (Value::String(a), Value::String(b)) => Ok(string::same_contents(a, b)),
+        (Value::Symbol(a), Value::Symbol(b)) => Ok(a == b),
+        (Value::Nil, _) | (Value::Bool(_), _) => Ok(a.is_identical(b)),
+        (a, b) => Ok(interpreter.call_method(a, "==", slice::from_ref(b), None)?.is_truthy()),
+    }
+}
@@ -0,0 +1,890 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{ Hash, Hasher };
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, check_frozen, conversion_error, eql, equals, expect_integer, pack, range };
+use crate::eval::builtins::enumerable::{ sort_values, sort_values_by, unique };
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::value::{ RArray, RProc, RRange, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let array = interpreter.classes.array.clone();
+    let metaclass = interpreter.metaclass(&array);
+    metaclass.define_builtin("new", new);
+    metaclass.define_builtin("[]", |_, _, a, _| Ok(Value::array(a.to_vec())));
+
+    array.define_builtin("[]", index);
+    array.define_builtin("slice", index);
+    array.define_builtin("[]=", set_index);
+    array.define_builtin("at", at);
+    array.define_builtin("dig", dig);
+    array.define_builtin("fetch", fetch);
+    array.define_builtin("values_at", values_at);
+    array.define_builtin("first", |i, r, a, _| end(i, r, a, true));
+    array.define_builtin("last", |i, r, a, _| end(i, r, a, false));
+    array.define_builtin("length", length);
+    array.define_builtin("size", length);
+    array.define_builtin("empty?", |_, r, _, _| Ok(Value::Bool(elements(r).is_empty())));
+    array.define_builtin("push", push);
+    array.define_builtin("append", push);
+    array.define_builtin("<<", |i, r, a, b| {
+        check_arity(i, a, 1, 1)?;
+        push(i, r, a, b)
+    });
+    array.define_builtin("pop", |i, r, a, _| remove_end(i, r, a, false));
+    array.define_builtin("shift", |i, r, a, _| remove_end(i, r, a, true));
+    array.define_builtin("unshift", unshift);
+    array.define_builtin("prepend", unshift);
+    array.define_builtin("insert", insert);
+    array.define_builtin("concat", concat);
+    array.define_builtin("delete", delete);
+    array.define_builtin("delete_at", delete_at);
+    array.define_builtin("slice!", slice_in_place);
+    array.define_builtin("delete_if", |i, r, a, b| remove_if(i, r, a, b, true, false));
+    array.define_builtin("reject!", |i, r, a, b| remove_if(i, r, a, b, true, true));
+    array.define_builtin("keep_if", |i, r, a, b| remove_if(i, r, a, b, false, false));
+    array.define_builtin("select!", |i, r, a, b| remove_if(i, r, a, b, false, true));
+    array.define_builtin("filter!", |i, r, a, b| remove_if(i, r, a, b, false, true));
+    array.define_builtin("compact", |_, r, _, _| Ok(Value::array(elements(r).into_iter().filter(|e| !e.is_nil()).collect())));
+    array.define_builtin("compact!", |i, r, _, _| {
+        check_frozen(i, r)?;
+        let compacted: Vec<Value> = elements(r).into_iter().filter(|e| !e.is_nil()).collect();
+        Ok(changed(r, compacted))
+    });
+    array.define_builtin("clear", |i, r, _, _| {
+        check_frozen(i, r)?;
+        Ok(replace_elements(r, vec!()))
+    });
+    array.define_builtin("replace", replace);
+    array.define_builtin("fill", fill);
+    array.define_builtin("each", each);
+    array.define_builtin("each_index", each_index);
+    array.define_builtin("map!", map_in_place);
+    array.define_builtin("collect!", map_in_place);
+    array.define_builtin("index", |i, r, a, b| position(i, r, a, b, false));
+    array.define_builtin("find_index", |i, r, a, b| position(i, r, a, b, false));
+    array.define_builtin("rindex", |i, r, a, b| position(i, r, a, b, true));
+    array.define_builtin("reverse", |_, r, _, _| Ok(Value::array(elements(r).into_iter().rev().collect())));
+    array.define_builtin("reverse!", |i, r, _, _| {
+        check_frozen(i, r)?;
+        Ok(replace_elements(r, elements(r).into_iter().rev().collect()))
+    });
+    array.define_builtin("rotate", |i, r, a, _| Ok(Value::array(rotated(i, r, a)?)));
+    array.define_builtin("rotate!", |i, r, a, _| {
+        check_frozen(i, r)?;
+        let rotated = rotated(i, r, a)?;
+        Ok(replace_elements(r, rotated))
+    });
+    array.define_builtin("sort!", |i, r, a, b| {
+        check_arity(i, a, 0, 0)?;
+        check_frozen(i, r)?;
+        let sorted = sort_values(i, elements(r), b)?;
+        Ok(replace_elements(r, sorted))
+    });
+    array.define_builtin("sort_by!", |i, r, a, b| {
+        check_arity(i, a, 0, 0)?;
+        check_frozen(i, r)?;
+        let block = match b {
+            Some(block) => block,
+            None => return Ok(enumerator_for(i, r, "sort_by!", a)),
+        };
+        let sorted = sort_values_by(i, elements(r), block)?;
+        Ok(replace_elements(r, sorted))
+    });
+    array.define_builtin("uniq!", |i, r, a, b| {
+        check_arity(i, a, 0, 0)?;
+        check_frozen(i, r)?;
+        let unique = unique(i, elements(r), b)?;
+        Ok(changed(r, unique))
+    });
+    array.define_builtin("flatten", |i, r, a, _| Ok(Value::array(flattened(i, r, a)?.0)));
+    array.define_builtin("flatten!", |i, r, a, _| {
+        check_frozen(i, r)?;
+        let (flat, flattened) = flattened(i, r, a)?;
+        Ok(if flattened { replace_elements(r, flat) } else { Value::Nil })
+    });
+    array.define_builtin("product", product);
+    array.define_builtin("transpose", transpose);
+    array.define_builtin("assoc", |i, r, a, _| associated(i, r, a, 0));
+    array.define_builtin("rassoc", |i, r, a, _| associated(i, r, a, 1));
+    array.define_builtin("join", join);
+    array.define_builtin("pack", pack::pack);
+    array.define_builtin("+", add);
+    array.define_builtin("-", |i, r, a, _| set_operation(i, r, a, SetOperation::Difference));
+    array.define_builtin("difference", |i, r, a, _| set_operation(i, r, a, SetOperation::Difference));
+    array.define_builtin("&", |i, r, a, _| set_operation(i, r, a, SetOperation::Intersection));
+    array.define_builtin("intersection", |i, r, a, _| set_operation(i, r, a, SetOperation::Intersection));
+    array.define_builtin("|", |i, r, a, _| set_operation(i, r, a, SetOperation::Union));
+    array.define_builtin("union", |i, r, a, _| set_operation(i, r, a, SetOperation::Union));
+    array.define_builtin("*", multiply);
+    array.define_builtin("==", equal);
+    array.define_builtin("eql?", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        Ok(Value::Bool(eql(r, &a[0])))
+    });
+    array.define_builtin("hash", hash);
+    array.define_builtin("<=>", compare_arrays);
+    array.define_builtin("to_a", |_, r, _, _| Ok(r.clone()));
+    array.define_builtin("to_ary", |_, r, _, _| Ok(r.clone()));
+    array.define_builtin("dup", |_, r, _, _| Ok(Value::array(elements(r))));
+    array.define_builtin("inspect", inspect);
+    array.define_builtin("to_s", inspect);
+}
+
+fn with<T>(value: &Value, f: impl FnOnce(&RArray) -> T) -> T {
+    match value {
+        Value::Array(array) => f(array),
+        _ => unreachable!("an Array method called on {:?}", value),
+    }
+}
+
+/// A copy of the elements, so calls made while going through them can't change them.
+pub fn elements(value: &Value) -> Vec<Value> {
+    with(value, |array| array.elements.borrow().clone())
+}
+
+fn replace_elements(receiver: &Value, elements: Vec<Value>) -> Value {
+    with(receiver, |array| *array.elements.borrow_mut() = elements);
+    receiver.clone()
+}
+
+/// Replaces the elements for the bang methods that return `nil` when nothing changed.
+fn changed(receiver: &Value, elements: Vec<Value>) -> Value {
+    if with(receiver, |array| array.elements.borrow().len()) == elements.len() { return Value::Nil; }
+    replace_elements(receiver, elements)
+}
+
+/// The argument as an array, or a `TypeError`.
+fn expect_array(interpreter: &Interpreter, value: &Value) -> Result<Vec<Value>, Control> {
+    match value {
+        Value::Array(_) => Ok(elements(value)),
+        value => Err(conversion_error(interpreter, value, "Array")),
+    }
+}
+
+/// Resolves a possibly negative index against `length`.
+fn resolve(index: i64, length: usize) -> Option<usize> {
+    let index = if index < 0 { index + length as i64 } else { index };
+    if index < 0 { None } else { Some(index as usize) }
+}
+
+fn new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 2)?;
+    let size = match args.first() {
+        None => 0,
+        Some(copied @ Value::Array(_)) if args.len() == 1 => return Ok(Value::array(elements(copied))),
+        Some(size) => expect_integer(interpreter, size)?,
+    };
+    if size < 0 { return Err(interpreter.error("ArgumentError", "negative array size")); }
+
+    let mut elements = vec!();
+    for index in 0..size {
+        elements.push(match block {
+            Some(block) => interpreter.call_proc(block, &[Value::Integer(index)])?,
+            None => args.get(1).cloned().unwrap_or(Value::Nil),
+        });
+    }
+
+    Ok(Value::array(elements))
+}
+
+/// The start and length `[start, length]` or `[range]` arguments pick out, with the start
+/// allowed to be just past the end. `None` when the start is out of bounds.
+fn span(interpreter: &Interpreter, args: &[Value], length: usize) -> Result<Option<(usize, usize)>, Control> {
+    let length = length as i64;
+    let (start, count) = match args {
+        [Value::Range(range)] => match range::span(interpreter, range, length)? {
+            Some(span) => span,
+            None => return Ok(None),
+        },
+        [start, count] => {
+            let (start, count) = (expect_integer(interpreter, start)?, expect_integer(interpreter, count)?);
+            let start = if start < 0 { start + length } else { start };
+            if start < 0 || start > length || count < 0 { return Ok(None); }
+            (start, count.min(length - start))
+        },
+        _ => unreachable!("a span is a range or a start and a length"),
+    };
+
+    Ok(Some((start as usize, count as usize)))
+}
+
+fn index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let elements = elements(receiver);
+    if args.len() == 1 && !matches!(args[0], Value::Range(_)) {
+        let index = expect_integer(interpreter, &args[0])?;
+        return Ok(resolve(index, elements.len()).and_then(|index| elements.get(index).cloned()).unwrap_or(Value::Nil));
+    }
+
+    Ok(match span(interpreter, args, elements.len())? {
+        Some((start, count)) => Value::array(elements[start..start + count].to_vec()),
+        None => Value::Nil,
+    })
+}
+
+/// Where `[start, length] =` or `[range] =` replaces elements. The start may be past the end,
+/// which pads with `nil`, but not before the beginning.
+fn assignment_span(interpreter: &mut Interpreter, args: &[Value], length: usize) -> Result<(usize, usize), Control> {
+    let length = length as i64;
+    match args {
+        [Value::Range(range)] => {
+            let RRange { start, end, exclusive } = &**range;
+            let from = if start.is_nil() { 0 } else { expect_integer(interpreter, start)? };
+            let from = if from < 0 { from + length } else { from };
+            if from < 0 {
+                let message = format!("{} out of range", interpreter.inspect(&args[0])?);
+                return Err(interpreter.error("RangeError", &message));
+            }
+            let to = if end.is_nil() { length } else { expect_integer(interpreter, end)? };
+            let to = if to < 0 { to + length } else { to };
+            let to = if *exclusive || end.is_nil() { to } else { to + 1 };
+            Ok((from as usize, (to - from).max(0) as usize))
+        },
+        [start, count] => {
+            let (start, count) = (expect_integer(interpreter, start)?, expect_integer(interpreter, count)?);
+            let from = if start < 0 { start + length } else { start };
+            if from < 0 {
+                let message = format!("index {} too small for array; minimum: -{}", start, length);
+                return Err(interpreter.error("IndexError", &message));
+            }
+            if count < 0 { return Err(interpreter.error("IndexError", &format!("negative length ({})", count))); }
+            Ok((from as usize, count as usize))
+        },
+        _ => unreachable!("a span is a range or a start and a length"),
+    }
+}
+
+/// Replaces `count` elements from `start` with `replacement`, padding with `nil` up to the
+/// start.
+fn splice(receiver: &Value, start: usize, count: usize, replacement: Vec<Value>) {
+    with(receiver, |array| {
+        let mut elements = array.elements.borrow_mut();
+        if start > elements.len() { elements.resize(start, Value::Nil); }
+        let end = (start + count).min(elements.len());
+        elements.splice(start..end, replacement);
+    })
+}
+
+/// `array[index] = value`, padding with `nil` when assigning past the end, or
+/// `array[start, length] = values` and `array[range] = values` replacing part of it.
+fn set_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 2, 3)?;
+    let length = with(receiver, |array| array.elements.borrow().len());
+    let value = args[args.len() - 1].clone();
+    let span = &args[..args.len() - 1];
+    if let [index] = span {
+        if !matches!(index, Value::Range(_)) {
+            let index = expect_integer(interpreter, index)?;
+            return match resolve(index, length) {
+                Some(index) => {
+                    splice(receiver, index, 1, vec!(value.clone()));
+                    Ok(value)
+                },
+                None => {
+                    let message = format!("index {} too small for array; minimum: -{}", index, length);
+                    Err(interpreter.error("IndexError", &message))
+                }
+            };
+        }
+    }
+
+    let (start, count) = assignment_span(interpreter, span, length)?;
+    let replacement = match &value {
+        Value::Array(_) => elements(&value),
+        value => vec!(value.clone()),
+    };
+    splice(receiver, start, count, replacement);
+    Ok(value)
+}
+
+fn at(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let index = expect_integer(interpreter, &args[0])?;
+    let elements = elements(receiver);
+    Ok(resolve(index, elements.len()).and_then(|index| elements.get(index).cloned()).unwrap_or(Value::Nil))
+}
+
+/// `[[1, [2]]].dig(0, 1, 0)`: indexes one level per argument, stopping at `nil`.
+fn dig(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if args.is_empty() { return Err(interpreter.error("ArgumentError", "wrong number of arguments (given 0, expected 1+)")); }
+    let found = at(interpreter, receiver, &args[..1], None)?;
+    if args.len() == 1 || found.is_nil() { return Ok(found); }
+    if interpreter.lookup_class(&found).find_method("dig").is_none() {
+        let message = format!("{} does not have #dig method", interpreter.class_of(&found).inspect());
+        return Err(interpreter.error("TypeError", &message));
+    }
+
+    interpreter.call_method(&found, "dig", &args[1..], None)
+}
+
+/// The element at the index, else the block's result for the index, else the default, else
+/// an `IndexError`.
+fn fetch(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let index = expect_integer(interpreter, &args[0])?;
+    let elements = elements(receiver);
+    if let Some(element) = resolve(index, elements.len()).and_then(|index| elements.get(index)) {
+        return Ok(element.clone());
+    }
+
+    match (block, args.get(1)) {
+        (Some(block), _) => interpreter.call_proc(block, slice::from_ref(&args[0])),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => {
+            let length = elements.len();
+            let message = format!("index {} outside of array bounds: -{}...{}", index, length, length);
+            Err(interpreter.error("IndexError", &message))
+        }
+    }
+}
+
+fn values_at(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let elements = elements(receiver);
+    let element = |index: Option<usize>| index.and_then(|index| elements.get(index).cloned()).unwrap_or(Value::Nil);
+    let mut values = vec!();
+    for arg in args.iter() {
+        match arg {
+            Value::Range(_) => {
+                let (start, count) = assignment_span(interpreter, slice::from_ref(arg), elements.len())?;
+                values.extend((start..start + count).map(|index| element(Some(index))));
+            },
+            index => {
+                let index = expect_integer(interpreter, index)?;
+                values.push(element(resolve(index, elements.len())));
+            }
+        }
+    }
+
+    Ok(Value::array(values))
+}
+
+/// `first` and `last`: the element at that end, or an array of up to `count` of them.
+fn end(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], front: bool) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let elements = elements(receiver);
+    let count = match args.first() {
+        Some(count) => expect_integer(interpreter, count)?,
+        None => {
+            let element = if front { elements.first() } else { elements.last() };
+            return Ok(element.cloned().unwrap_or(Value::Nil));
+        }
+    };
+    if count < 0 { return Err(interpreter.error("ArgumentError", "negative array size")); }
+
+    let count = (count as usize).min(elements.len());
+    let taken = if front { &elements[..count] } else { &elements[elements.len() - count..] };
+    Ok(Value::array(taken.to_vec()))
+}
+
+fn length(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Integer(with(receiver, |array| array.elements.borrow().len()) as i64))
+}
+
+fn push(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    with(receiver, |array| array.elements.borrow_mut().extend(args.iter().cloned()));
+    Ok(receiver.clone())
+}
+
+/// `pop` and `shift`: one element or `nil`, or with a count an array of up to that many.
+fn remove_end(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], front: bool) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 0, 1)?;
+    let count = match args.first() {
+        Some(count) => Some(expect_integer(interpreter, count)?),
+        None => None,
+    };
+    if count.is_some_and(|count| count < 0) { return Err(interpreter.error("ArgumentError", "negative array size")); }
+
+    with(receiver, |array| {
+        let mut elements = array.elements.borrow_mut();
+        let length = elements.len();
+        let taken = (count.unwrap_or(1) as usize).min(length);
+        let removed: Vec<Value> = if front { elements.drain(..taken).collect() } else { elements.drain(length - taken..).collect() };
+        Ok(match count {
+            Some(_) => Value::array(removed),
+            None => removed.into_iter().next().unwrap_or(Value::Nil),
+        })
+    })
+}
+
+fn unshift(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    with(receiver, |array| array.elements.borrow_mut().splice(0..0, args.iter().cloned()).for_each(drop));
+    Ok(receiver.clone())
+}
+
+/// Inserts before the element at the index. A negative index counts from the end, inserting
+/// after the element there, so `insert(-1, x)` appends.
+fn insert(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    if args.is_empty() { return Err(interpreter.error("ArgumentError", "wrong number of arguments (given 0, expected 1+)")); }
+    let index = expect_integer(interpreter, &args[0])?;
+    if args.len() == 1 { return Ok(receiver.clone()); }
+
+    let length = with(receiver, |array| array.elements.borrow().len()) as i64;
+    let position = if index < 0 { index + length + 1 } else { index };
+    if position < 0 {
+        let message = format!("index {} too small for array; minimum: -{}", index, length + 1);
+        return Err(interpreter.error("IndexError", &message));
+    }
+    splice(receiver, position as usize, 0, args[1..].to_vec());
+    Ok(receiver.clone())
+}
+
+fn concat(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    let mut added = vec!();
+    for arg in args.iter() {
+        added.extend(expect_array(interpreter, arg)?);
+    }
+    with(receiver, |array| array.elements.borrow_mut().extend(added));
+    Ok(receiver.clone())
+}
+
+/// Removes every element `==` to the argument, returning it, or else `nil` or the block's
+/// result.
+fn delete(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 1)?;
+    let mut kept = vec!();
+    let mut found = None;
+    for element in elements(receiver).into_iter() {
+        if equals(interpreter, &element, &args[0])? {
+            found = Some(element);
+        } else {
+            kept.push(element);
+        }
+    }
+
+    match found {
+        Some(found) => {
+            replace_elements(receiver, kept);
+            Ok(found)
+        },
+        None => block.map_or(Ok(Value::Nil), |block| interpreter.call_proc(block, args)),
+    }
+}
+
+fn delete_at(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 1)?;
+    let index = expect_integer(interpreter, &args[0])?;
+    with(receiver, |array| {
+        let mut elements = array.elements.borrow_mut();
+        Ok(match resolve(index, elements.len()) {
+            Some(index) if index < elements.len() => elements.remove(index),
+            _ => Value::Nil,
+        })
+    })
+}
+
+/// Removes and returns what `[]` with the same arguments would.
+fn slice_in_place(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 2)?;
+    if args.len() == 1 && !matches!(args[0], Value::Range(_)) {
+        return delete_at(interpreter, receiver, args, None);
+    }
+
+    let length = with(receiver, |array| array.elements.borrow().len());
+    match span(interpreter, args, length)? {
+        Some((start, count)) => {
+            let removed = with(receiver, |array| array.elements.borrow_mut().drain(start..start + count).collect());
+            Ok(Value::array(removed))
+        },
+        None => Ok(Value::Nil),
+    }
+}
+
+/// `delete_if`, `reject!`, `keep_if` and `select!`. The bang methods return `nil` when
+/// nothing was removed.
+fn remove_if(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, remove: bool, bang: bool) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => {
+            let method = match (remove, bang) {
+                (true, false) => "delete_if",
+                (true, true) => "reject!",
+                (false, false) => "keep_if",
+                (false, true) => "select!",
+            };
+            return Ok(enumerator_for(interpreter, receiver, method, args));
+        },
+    };
+    let mut kept = vec!();
+    for element in elements(receiver).into_iter() {
+        if interpreter.call_proc(block, slice::from_ref(&element))?.is_truthy() != remove {
+            kept.push(element);
+        }
+    }
+
+    Ok(if bang { changed(receiver, kept) } else { replace_elements(receiver, kept) })
+}
+
+fn replace(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 1)?;
+    let elements = expect_array(interpreter, &args[0])?;
+    Ok(replace_elements(receiver, elements))
+}
+
+/// `fill(value)`, `fill(value, start, length)`, `fill(value, range)`, or the same without the
+/// value and with a block giving the value for each index.
+fn fill(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    let (value, span) = match block {
+        Some(_) => (None, args),
+        None if args.is_empty() => return Err(interpreter.error("ArgumentError", "wrong number of arguments (given 0, expected 1..3)")),
+        None => (Some(args[0].clone()), &args[1..]),
+    };
+    if span.len() > 2 { return check_arity(interpreter, args, 0, 3).map(|_| Value::Nil); }
+
+    let length = with(receiver, |array| array.elements.borrow().len());
+    let (start, count) = match span {
+        [] => (0, length),
+        [Value::Range(_)] => assignment_span(interpreter, span, length)?,
+        [start] => {
+            let start = expect_integer(interpreter, start)?;
+            let start = resolve(start, length).unwrap_or(0);
+            (start, length.saturating_sub(start))
+        },
+        [start, count] => {
+            let start = resolve(expect_integer(interpreter, start)?, length).unwrap_or(0);
+            (start, expect_integer(interpreter, count)?.max(0) as usize)
+        },
+        _ => unreachable!("at most a start and a length"),
+    };
+
+    let mut filled = vec!();
+    for index in start..start + count {
+        filled.push(match (&value, block) {
+            (Some(value), _) => value.clone(),
+            (None, Some(block)) => interpreter.call_proc(block, &[Value::Integer(index as i64)])?,
+            (None, None) => unreachable!("fill needs a value or a block"),
+        });
+    }
+    splice(receiver, start, count, filled);
+    Ok(receiver.clone())
+}
+
+/// Yields each element, reading the array afresh every time so changes made by the block
+/// are seen.
+fn each(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each", args)),
+    };
+    let mut index = 0;
+    while let Some(element) = with(receiver, |array| array.elements.borrow().get(index).cloned()) {
+        interpreter.call_proc(block, &[element])?;
+        index += 1;
+    }
+
+    Ok(receiver.clone())
+}
+
+fn each_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each_index", args)),
+    };
+    let mut index = 0;
+    while index < with(receiver, |array| array.elements.borrow().len()) {
+        interpreter.call_proc(block, &[Value::Integer(index as i64)])?;
+        index += 1;
+    }
+
+    Ok(receiver.clone())
+}
+
+fn map_in_place(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "map!", args)),
+    };
+    let mut mapped = vec!();
+    for element in elements(receiver).into_iter() {
+        mapped.push(interpreter.call_proc(block, &[element])?);
+    }
+
+    Ok(replace_elements(receiver, mapped))
+}
+
+/// `index` and `rindex`: the first or last position of an element `==` to the argument, or
+/// that the block is true for.
+fn position(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, last: bool) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    if args.is_empty() && block.is_none() {
+        return Ok(enumerator_for(interpreter, receiver, if last { "rindex" } else { "index" }, args));
+    }
+    let elements = elements(receiver);
+    let mut indices: Vec<usize> = (0..elements.len()).collect();
+    if last { indices.reverse(); }
+    for index in indices.into_iter() {
+        let found = match (args.first(), block) {
+            (Some(target), _) => equals(interpreter, &elements[index], target)?,
+            (None, Some(block)) => interpreter.call_proc(block, slice::from_ref(&elements[index]))?.is_truthy(),
+            (None, None) => false,
+        };
+        if found { return Ok(Value::Integer(index as i64)); }
+    }
+
+    Ok(Value::Nil)
+}
+
+fn rotated(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<Value>, Control> {
+    check_arity(interpreter, args, 0, 1)?;
+    let count = match args.first() {
+        Some(count) => expect_integer(interpreter, count)?,
+        None => 1,
+    };
+    let mut elements = elements(receiver);
+    if !elements.is_empty() {
+        let shift = count.rem_euclid(elements.len() as i64) as usize;
+        elements.rotate_left(shift);
+    }
+
+    Ok(elements)
+}
+
+/// The elements with nested arrays spread out, to the given depth or all the way. Also says
+/// whether there was anything to flatten.
+fn flattened(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<(Vec<Value>, bool), Control> {
+    check_arity(interpreter, args, 0, 1)?;
+    let depth = match args.first() {
+        Some(Value::Nil) | None => -1,
+        Some(depth) => expect_integer(interpreter, depth)?,
+    };
+
+    fn flatten(interpreter: &Interpreter, array: &Value, depth: i64, seen: &mut Vec<usize>, into: &mut Vec<Value>) -> Result<bool, Control> {
+        if seen.contains(&array.address()) {
+            return Err(interpreter.error("ArgumentError", "tried to flatten recursive array"));
+        }
+        seen.push(array.address());
+        let mut flattened = false;
+        for element in elements(array).into_iter() {
+            match element {
+                Value::Array(_) if depth != 0 => {
+                    flatten(interpreter, &element, depth - 1, seen, into)?;
+                    flattened = true;
+                },
+                element => into.push(element),
+            }
+        }
+        seen.pop();
+        Ok(flattened)
+    }
+
+    let mut flat = vec!();
+    let flattened = flatten(interpreter, receiver, depth, &mut vec!(), &mut flat)?;
+    Ok((flat, flattened))
+}
+
+/// Every combination of one element from the receiver and each argument.
+fn product(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut combinations = elements(receiver).into_iter().map(|element| vec!(element)).collect::<Vec<Vec<Value>>>();
+    for arg in args.iter() {
+        let other = expect_array(interpreter, arg)?;
+        combinations = combinations.into_iter().flat_map(|combination| {
+            other.iter().map(move |element| [combination.clone(), vec!(element.clone())].concat())
+        }).collect();
+    }
+
+    Ok(Value::array(combinations.into_iter().map(Value::array).collect()))
+}
+
+fn transpose(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let mut rows = vec!();
+    for row in elements(receiver).iter() {
+        rows.push(expect_array(interpreter, row)?);
+    }
+    let width = rows.first().map_or(0, |row| row.len());
+    if let Some(row) = rows.iter().find(|row| row.len() != width) {
+        let message = format!("element size differs ({} should be {})", row.len(), width);
+        return Err(interpreter.error("IndexError", &message));
+    }
+
+    Ok(Value::array((0..width).map(|column| Value::array(rows.iter().map(|row| row[column].clone()).collect())).collect()))
+}
+
+/// `assoc` and `rassoc`: the first element that is an array with the argument at `position`.
+fn associated(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], position: usize) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    for element in elements(receiver).into_iter() {
+        if let Value::Array(pair) = &element {
+            let candidate = pair.elements.borrow().get(position).cloned();
+            if let Some(candidate) = candidate {
+                if equals(interpreter, &candidate, &args[0])? { return Ok(element); }
+            }
+        }
+    }
+
+    Ok(Value::Nil)
+}
+
+/// The elements as strings with the separator between them, nested arrays joined the same
+/// way.
+fn join(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let separator = match args.first() {
+        Some(Value::String(separator)) => separator.text(),
+        Some(Value::Nil) | None => String::new(),
+        Some(other) => return Err(conversion_error(interpreter, other, "String")),
+    };
+
+    fn join_into(interpreter: &mut Interpreter, array: &Value, parts: &mut Vec<String>) -> Result<(), Control> {
+        let joined = interpreter.guard_recursion("join", array, |interpreter| {
+            for element in elements(array).iter() {
+                match element {
+                    Value::Array(_) => join_into(interpreter, element, parts)?,
+                    element => parts.push(interpreter.to_s(element)?),
+                }
+            }
+            Ok(())
+        })?;
+        joined.ok_or_else(|| interpreter.error("ArgumentError", "recursive array join"))
+    }
+
+    let mut parts = vec!();
+    join_into(interpreter, receiver, &mut parts)?;
+    Ok(Value::string(&parts.join(&separator)))
+}
+
+fn add(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let other = expect_array(interpreter, &args[0])?;
+    Ok(Value::array([elements(receiver), other].concat()))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SetOperation {
+    Difference,
+    Intersection,
+    Union,
+}
+
+/// `-`, `&` and `|`, comparing elements with `eql?`. Intersection and union leave out
+/// duplicates; difference keeps them.
+fn set_operation(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], operation: SetOperation) -> EvalResult {
+    let mut others = vec!();
+    for arg in args.iter() {
+        others.push(expect_array(interpreter, arg)?);
+    }
+    let contains = |array: &[Value], value: &Value| array.iter().any(|element| eql(element, value));
+
+    let result = match operation {
+        SetOperation::Difference => {
+            elements(receiver).into_iter().filter(|element| !others.iter().any(|other| contains(other, element))).collect()
+        },
+        SetOperation::Intersection => {
+            let mut result: Vec<Value> = vec!();
+            for element in elements(receiver).into_iter() {
+                if others.iter().all(|other| contains(other, &element)) && !contains(&result, &element) {
+                    result.push(element);
+                }
+            }
+            result
+        },
+        SetOperation::Union => {
+            let mut result: Vec<Value> = vec!();
+            for element in elements(receiver).into_iter().chain(others.into_iter().flatten()) {
+                if !contains(&result, &element) { result.push(element); }
+            }
+            result
+        },
+    };
+
+    Ok(Value::array(result))
+}
+
+/// `[1, 2] * 2` repeats the elements; `[1, 2] * ","` joins them.
+fn multiply(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if let Value::String(_) = &args[0] {
+        return join(interpreter, receiver, args, None);
+    }
+
+    let times = expect_integer(interpreter, &args[0])?;
+    if times < 0 { return Err(interpreter.error("ArgumentError", "negative argument")); }
+    let elements = elements(receiver);
+    Ok(Value::array((0..times).flat_map(|_| elements.iter().cloned()).collect()))
+}
+
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if receiver.is_identical(&args[0]) { return Ok(Value::Bool(true)); }
+    if !matches!(args[0], Value::Array(_)) { return Ok(Value::Bool(false)); }
+
+    let (a, b) = (elements(receiver), elements(&args[0]));
+    if a.len() != b.len() { return Ok(Value::Bool(false)); }
+    // an array that contains itself is equal where the recursion starts again
+    let equal = interpreter.guard_recursion("==", receiver, |interpreter| {
+        for (a, b) in a.iter().zip(b.iter()) {
+            if !equals(interpreter, a, b)? { return Ok(false); }
+        }
+        Ok(true)
+    })?;
+
+    Ok(Value::Bool(equal.unwrap_or(true)))
+}
+
+/// Combines the hashes of the elements, so arrays that are `eql?` hash the same.
+fn hash(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let mut hasher = DefaultHasher::new();
+    let elements = elements(receiver);
+    elements.len().hash(&mut hasher);
+    interpreter.guard_recursion("hash", receiver, |interpreter| {
+        for element in elements.iter() {
+            match interpreter.call_method(element, "hash", &[], None)? {
+                Value::Integer(hash) => hash.hash(&mut hasher),
+                other => other.address().hash(&mut hasher),
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(Value::Integer(hasher.finish() as i64))
+}
+
+/// Compares element by element with `<=>`, then by length. `nil` when an element pair or the
+/// argument can't be compared.
+fn compare_arrays(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if !matches!(args[0], Value::Array(_)) { return Ok(Value::Nil); }
+
+    let (a, b) = (elements(receiver), elements(&args[0]));
+    for (a, b) in a.iter().zip(b.iter()) {
+        match interpreter.call_method(a, "<=>", slice::from_ref(b), None)? {
+            Value::Integer(0) => (),
+            Value::Nil => return Ok(Value::Nil),
+            result => return Ok(result),
+        }
+    }
+
+    Ok(Value::Integer(a.len().cmp(&b.len()) as i64))
+}
+
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let inspected = interpreter.guard_recursion("inspect", receiver, |interpreter| {
+        let mut inspected = vec!();
+        for element in elements(receiver).iter() {
+            inspected.push(interpreter.inspect(element)?);
+        }
+        Ok(format!("[{}]", inspected.join(", ")))
+    })?;
+
+    Ok(Value::string(&inspected.unwrap_or_else(|| String::from("[...]"))))
+}
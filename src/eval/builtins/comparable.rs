@@ -0,0 +1,72 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ EvalResult, Interpreter };
+use crate::eval::builtins::check_arity;
+use crate::eval::builtins::enumerable::compare;
+use crate::eval::class::RClass;
+use crate::eval::value::{ RProc, Value };
+
+/// The comparisons a class gets from `<=>` by including `Comparable`.
+pub fn define(interpreter: &mut Interpreter) {
+    let comparable = RClass::module("Comparable");
+    interpreter.set_constant("Comparable", Value::Class(comparable.clone()));
+    interpreter.classes.numeric.includes.borrow_mut().push(comparable.clone());
+    interpreter.classes.string.includes.borrow_mut().push(comparable.clone());
+    interpreter.classes.symbol.includes.borrow_mut().push(comparable.clone());
+
+    comparable.define_builtin("==", equal);
+    comparable.define_builtin("<", |i, r, a, _| comparison(i, r, a, |o| o == Ordering::Less));
+    comparable.define_builtin("<=", |i, r, a, _| comparison(i, r, a, |o| o != Ordering::Greater));
+    comparable.define_builtin(">", |i, r, a, _| comparison(i, r, a, |o| o == Ordering::Greater));
+    comparable.define_builtin(">=", |i, r, a, _| comparison(i, r, a, |o| o != Ordering::Less));
+    comparable.define_builtin("between?", between);
+    comparable.define_builtin("clamp", clamp);
+}
+
+/// `<=>` giving 0, and `false` rather than an error when it gives `nil`.
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if receiver.is_identical(&args[0]) { return Ok(Value::Bool(true)); }
+
+    match interpreter.call_method(receiver, "<=>", slice::from_ref(&args[0]), None)? {
+        Value::Nil => Ok(Value::Bool(false)),
+        result => Ok(Value::Bool(matches!(result, Value::Integer(0)))),
+    }
+}
+
+fn comparison(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], test: fn(Ordering) -> bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(test(compare(interpreter, receiver, &args[0])?)))
+}
+
+fn between(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 2)?;
+    let above = compare(interpreter, receiver, &args[0])? != Ordering::Less;
+    Ok(Value::Bool(above && compare(interpreter, receiver, &args[1])? != Ordering::Greater))
+}
+
+/// `clamp(min, max)` or `clamp(min..max)`, where either end of the range can be left open.
+fn clamp(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let (min, max) = match args {
+        [Value::Range(range)] if range.exclusive && !range.end.is_nil() => {
+            return Err(interpreter.error("ArgumentError", "cannot clamp with an exclusive range"));
+        },
+        [Value::Range(range)] => (range.start.clone(), range.end.clone()),
+        [min, max] => (min.clone(), max.clone()),
+        [other] => {
+            let message = format!("wrong argument type {} (expected Range)", interpreter.class_of(other).inspect());
+            return Err(interpreter.error("TypeError", &message));
+        },
+        _ => unreachable!("the arity was checked"),
+    };
+    if !min.is_nil() && !max.is_nil() && compare(interpreter, &min, &max)? == Ordering::Greater {
+        return Err(interpreter.error("ArgumentError", "min argument must be less than or equal to max argument"));
+    }
+
+    if !min.is_nil() && compare(interpreter, receiver, &min)? == Ordering::Less { return Ok(min); }
+    if !max.is_nil() && compare(interpreter, receiver, &max)? == Ordering::Greater { return Ok(max); }
+    Ok(receiver.clone())
+}
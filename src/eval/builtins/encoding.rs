@@ -0,0 +1,76 @@
+use std::rc::Rc;
+
+use crate::eval::{ Control, Interpreter };
+use crate::eval::builtins::conversion_error;
+use crate::eval::class::RClass;
+use crate::eval::value::{ Encoding, RProc, Value };
+
+const ENCODINGS: &[(Encoding, &[&str])] = &[
+    (Encoding::Utf8, &["UTF_8"]),
+    (Encoding::Binary, &["ASCII_8BIT", "BINARY"]),
+    (Encoding::UsAscii, &["US_ASCII", "ASCII"]),
+];
+
+pub fn define(interpreter: &mut Interpreter) {
+    let object = interpreter.classes.object.clone();
+    let class = RClass::new("Encoding", Some(object));
+    interpreter.set_constant("Encoding", Value::Class(class.clone()));
+    for (encoding, constants) in ENCODINGS.iter() {
+        let value = Value::object(class.clone());
+        if let Value::Object(object) = &value {
+            object.set("name", Value::string(encoding.name()));
+        }
+        for constant in constants.iter() {
+            class.set_constant(constant, value.clone());
+        }
+    }
+
+    let encoding_error = match interpreter.constant("EncodingError") {
+        Some(Value::Class(error)) => error,
+        _ => unreachable!("EncodingError is defined with the other exceptions"),
+    };
+    for name in ["CompatibilityError", "UndefinedConversionError", "InvalidByteSequenceError"] {
+        let error = RClass::new(&format!("Encoding::{}", name), Some(encoding_error.clone()));
+        class.set_constant(name, Value::Class(error));
+    }
+
+    class.define_builtin("name", name);
+    class.define_builtin("to_s", name);
+    class.define_builtin("inspect", inspect);
+    class.define_builtin("ascii_compatible?", |_, _, _, _| Ok(Value::Bool(true)));
+    interpreter.metaclass(&class).define_builtin("find", |interpreter, _, args, _| {
+        super::check_arity(interpreter, args, 1, 1)?;
+        let encoding = expect_encoding(interpreter, &args[0])?;
+        Ok(value_of(interpreter, encoding))
+    });
+}
+
+fn name(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> Result<Value, Control> {
+    Ok(interpreter.instance_variable(receiver, "name"))
+}
+
+/// `#<Encoding:UTF-8>`, with the `BINARY` alias shown as Ruby does.
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> Result<Value, Control> {
+    let name = interpreter.to_s(&interpreter.instance_variable(receiver, "name"))?;
+    let name = if name == Encoding::Binary.name() { format!("BINARY ({})", name) } else { name };
+    Ok(Value::string(&format!("#<Encoding:{}>", name)))
+}
+
+/// The `Encoding` object for an encoding, the same one each time.
+pub fn value_of(interpreter: &Interpreter, encoding: Encoding) -> Value {
+    let constant = ENCODINGS.iter().find(|(known, _)| *known == encoding).map(|(_, constants)| constants[0]).expect("every encoding has a constant");
+    match interpreter.constant("Encoding") {
+        Some(Value::Class(class)) => class.constants.borrow().get(constant).cloned().expect("the encoding constants are defined"),
+        _ => unreachable!("Encoding is a builtin class"),
+    }
+}
+
+/// An `Encoding` object or the name of one.
+pub fn expect_encoding(interpreter: &mut Interpreter, value: &Value) -> Result<Encoding, Control> {
+    let name = match value {
+        Value::String(name) => name.text(),
+        Value::Object(object) if object.class.name() == "Encoding" => interpreter.to_s(&interpreter.instance_variable(value, "name"))?,
+        value => return Err(conversion_error(interpreter, value, "String")),
+    };
+    Encoding::find(&name).ok_or_else(|| interpreter.error("ArgumentError", &format!("unknown encoding name - {}", name)))
+}
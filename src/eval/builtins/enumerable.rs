@@ -0,0 +1,867 @@
+use std::cell::{ Cell, RefCell };
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ array, check_arity, eql, equals, expect_integer, hash, numeric };
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::class::RClass;
+use crate::eval::value::{ RProc, Value };
+
+/// The methods every class with an `each` gets by including `Enumerable`. They work on what
+/// `each` yields, going through an array directly.
+pub fn define(interpreter: &mut Interpreter) {
+    let enumerable = RClass::module("Enumerable");
+    interpreter.set_constant("Enumerable", Value::Class(enumerable.clone()));
+    interpreter.classes.array.includes.borrow_mut().push(enumerable.clone());
+    interpreter.classes.hash.includes.borrow_mut().push(enumerable.clone());
+    interpreter.classes.range.includes.borrow_mut().push(enumerable.clone());
+
+    enumerable.define_builtin("to_a", |i, r, _, _| Ok(Value::array(elements(i, r)?)));
+    enumerable.define_builtin("entries", |i, r, _, _| Ok(Value::array(elements(i, r)?)));
+    enumerable.define_builtin("map", map);
+    enumerable.define_builtin("collect", map);
+    enumerable.define_builtin("flat_map", flat_map);
+    enumerable.define_builtin("collect_concat", flat_map);
+    enumerable.define_builtin("select", |i, r, a, b| filter(i, r, a, b, true));
+    enumerable.define_builtin("filter", |i, r, a, b| filter(i, r, a, b, true));
+    enumerable.define_builtin("find_all", |i, r, a, b| filter(i, r, a, b, true));
+    enumerable.define_builtin("reject", |i, r, a, b| filter(i, r, a, b, false));
+    enumerable.define_builtin("filter_map", filter_map);
+    enumerable.define_builtin("partition", partition);
+    enumerable.define_builtin("group_by", group_by);
+    enumerable.define_builtin("tally", tally);
+    enumerable.define_builtin("find", find);
+    enumerable.define_builtin("detect", find);
+    enumerable.define_builtin("find_index", find_index);
+    enumerable.define_builtin("each_with_index", each_with_index);
+    enumerable.define_builtin("each_with_object", each_with_object);
+    enumerable.define_builtin("reverse_each", reverse_each);
+    enumerable.define_builtin("each_slice", each_slice);
+    enumerable.define_builtin("each_cons", each_cons);
+    enumerable.define_builtin("inject", inject);
+    enumerable.define_builtin("reduce", inject);
+    enumerable.define_builtin("sum", sum);
+    enumerable.define_builtin("count", count);
+    enumerable.define_builtin("min", |i, r, a, b| extreme(i, r, a, b, Ordering::Less));
+    enumerable.define_builtin("max", |i, r, a, b| extreme(i, r, a, b, Ordering::Greater));
+    enumerable.define_builtin("min_by", |i, r, a, b| extreme_by(i, r, a, b, Ordering::Less));
+    enumerable.define_builtin("max_by", |i, r, a, b| extreme_by(i, r, a, b, Ordering::Greater));
+    enumerable.define_builtin("minmax", minmax);
+    enumerable.define_builtin("sort", sort);
+    enumerable.define_builtin("sort_by", sort_by);
+    enumerable.define_builtin("uniq", uniq);
+    enumerable.define_builtin("zip", zip);
+    enumerable.define_builtin("to_h", to_h);
+    enumerable.define_builtin("first", first);
+    enumerable.define_builtin("take", take);
+    enumerable.define_builtin("take_while", take_while);
+    enumerable.define_builtin("drop", drop);
+    enumerable.define_builtin("drop_while", drop_while);
+    enumerable.define_builtin("include?", include);
+    enumerable.define_builtin("member?", include);
+    enumerable.define_builtin("all?", |i, r, a, b| quantify(i, r, a, b, Quantifier::All));
+    enumerable.define_builtin("any?", |i, r, a, b| quantify(i, r, a, b, Quantifier::Any));
+    enumerable.define_builtin("none?", |i, r, a, b| quantify(i, r, a, b, Quantifier::None));
+    enumerable.define_builtin("one?", |i, r, a, b| quantify(i, r, a, b, Quantifier::One));
+}
+
+/// The block, which these methods can't do without.
+pub fn need_block<'a>(interpreter: &Interpreter, block: Option<&'a Rc<RProc>>) -> Result<&'a Rc<RProc>, Control> {
+    block.ok_or_else(|| interpreter.error("LocalJumpError", "no block given (yield)"))
+}
+
+/// What a block sees when it is yielded `values`: nothing is `nil`, and several are an array.
+pub fn packed(values: &[Value]) -> Value {
+    match values {
+        [] => Value::Nil,
+        [value] => value.clone(),
+        values => Value::array(values.to_vec()),
+    }
+}
+
+/// Calls `each` on `receiver`, handing what each iteration yields to `f` until it returns
+/// `false`. An array is gone through by index, so elements added on the way are seen too.
+pub fn each_element(
+    interpreter: &mut Interpreter,
+    receiver: &Value,
+    f: impl FnMut(&mut Interpreter, Value) -> Result<bool, Control> + 'static,
+) -> Result<(), Control> {
+    let mut f = f;
+    if let Value::Array(array) = receiver {
+        let mut index = 0;
+        loop {
+            let element = match array.elements.borrow().get(index) {
+                Some(element) => element.clone(),
+                None => return Ok(()),
+            };
+            if !f(interpreter, element)? { return Ok(()); }
+            index += 1;
+        }
+    }
+
+    // stopping breaks out of `each` with a tag no block has, the address of `marker`
+    let marker = Rc::new(());
+    let tag = Rc::as_ptr(&marker) as usize;
+    let f = RefCell::new(f);
+    let block = interpreter.native_proc(move |interpreter, values| {
+        if (f.borrow_mut())(interpreter, packed(values))? { return Ok(Value::Nil); }
+        Err(Control::Break(Value::Nil, Some(tag)))
+    });
+    match interpreter.call_method(receiver, "each", &[], Some(&block)) {
+        Err(Control::Break(_, Some(target))) if target == tag => Ok(()),
+        result => result.map(|_| ()),
+    }
+}
+
+/// Everything `each` yields.
+pub fn elements(interpreter: &mut Interpreter, receiver: &Value) -> Result<Vec<Value>, Control> {
+    if let Value::Array(_) = receiver { return Ok(array::elements(receiver)); }
+
+    let collected = Rc::new(RefCell::new(vec!()));
+    let into = collected.clone();
+    each_element(interpreter, receiver, move |_, value| {
+        into.borrow_mut().push(value);
+        Ok(true)
+    })?;
+    let elements = collected.borrow().clone();
+    Ok(elements)
+}
+
+fn call(interpreter: &mut Interpreter, block: &Rc<RProc>, value: &Value) -> EvalResult {
+    interpreter.call_proc(block, slice::from_ref(value))
+}
+
+/// `a <=> b` as an ordering, or the `ArgumentError` for values that can't be compared.
+pub fn compare(interpreter: &mut Interpreter, a: &Value, b: &Value) -> Result<Ordering, Control> {
+    if let Some(ordering) = numeric::ordering(a, b) { return Ok(ordering); }
+    if let (Value::String(x), Value::String(y)) = (a, b) {
+        return Ok(x.bytes.borrow().cmp(&y.bytes.borrow()));
+    }
+
+    let result = interpreter.call_method(a, "<=>", slice::from_ref(b), None)?;
+    ordering_of(interpreter, &result, a, b)
+}
+
+/// The ordering a `<=>` result stands for: its sign, with `nil` meaning `a` and `b` can't
+/// be compared.
+pub fn ordering_of(interpreter: &mut Interpreter, result: &Value, a: &Value, b: &Value) -> Result<Ordering, Control> {
+    match result {
+        Value::Integer(result) => Ok(result.cmp(&0)),
+        Value::Bignum(result) => Ok(if result.is_negative() { Ordering::Less } else { Ordering::Greater }),
+        Value::Float(result) if !result.is_nan() => Ok(result.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+        _ => Err(comparison_failed(interpreter, a, b)),
+    }
+}
+
+/// `comparison of Integer with String failed`. Like Ruby, values without a class of their
+/// own to speak of, such as `nil` and numbers, are shown as themselves on the right.
+pub fn comparison_failed(interpreter: &mut Interpreter, a: &Value, b: &Value) -> Control {
+    let right = match b {
+        Value::Nil | Value::Bool(_) | Value::Integer(_) | Value::Float(_) | Value::Symbol(_) => {
+            interpreter.inspect(b).unwrap_or_default()
+        },
+        b => interpreter.class_of(b).inspect(),
+    };
+    let message = format!("comparison of {} with {} failed", interpreter.class_of(a).inspect(), right);
+    interpreter.error("ArgumentError", &message)
+}
+
+/// A stable merge sort, since the comparison calls into Ruby and can fail or be inconsistent.
+pub fn merge_sort<T: Clone>(
+    interpreter: &mut Interpreter,
+    items: Vec<T>,
+    compare: &mut impl FnMut(&mut Interpreter, &T, &T) -> Result<Ordering, Control>,
+) -> Result<Vec<T>, Control> {
+    let mut items = items;
+    let mut width = 1;
+    while width < items.len() {
+        let mut merged = Vec::with_capacity(items.len());
+        for start in (0..items.len()).step_by(2 * width) {
+            let middle = (start + width).min(items.len());
+            let end = (start + 2 * width).min(items.len());
+            let (mut left, mut right) = (start, middle);
+            while left < middle && right < end {
+                if compare(interpreter, &items[right], &items[left])? == Ordering::Less {
+                    merged.push(items[right].clone());
+                    right += 1;
+                } else {
+                    merged.push(items[left].clone());
+                    left += 1;
+                }
+            }
+            merged.extend_from_slice(&items[left..middle]);
+            merged.extend_from_slice(&items[right..end]);
+        }
+        items = merged;
+        width *= 2;
+    }
+
+    Ok(items)
+}
+
+/// Sorts with `<=>`, or with the block when there is one.
+pub fn sort_values(interpreter: &mut Interpreter, values: Vec<Value>, block: Option<&Rc<RProc>>) -> Result<Vec<Value>, Control> {
+    match block {
+        Some(block) => merge_sort(interpreter, values, &mut |interpreter, a, b| {
+            let result = interpreter.call_proc(block, &[a.clone(), b.clone()])?;
+            ordering_of(interpreter, &result, a, b)
+        }),
+        None => merge_sort(interpreter, values, &mut compare),
+    }
+}
+
+/// Sorts by what the block gives for each value.
+pub fn sort_values_by(interpreter: &mut Interpreter, values: Vec<Value>, block: &Rc<RProc>) -> Result<Vec<Value>, Control> {
+    let mut keyed = vec!();
+    for value in values.into_iter() {
+        keyed.push((call(interpreter, block, &value)?, value));
+    }
+    let sorted = merge_sort(interpreter, keyed, &mut |interpreter, a, b| compare(interpreter, &a.0, &b.0))?;
+    Ok(sorted.into_iter().map(|(_, value)| value).collect())
+}
+
+/// The values with later duplicates left out, comparing them or what the block gives for
+/// them with `eql?`.
+pub fn unique(interpreter: &mut Interpreter, values: Vec<Value>, block: Option<&Rc<RProc>>) -> Result<Vec<Value>, Control> {
+    let mut seen: Vec<Value> = vec!();
+    let mut unique = vec!();
+    for value in values.into_iter() {
+        let key = match block {
+            Some(block) => call(interpreter, block, &value)?,
+            None => value.clone(),
+        };
+        if !seen.iter().any(|seen| eql(seen, &key)) {
+            seen.push(key);
+            unique.push(value);
+        }
+    }
+
+    Ok(unique)
+}
+
+/// A count argument, which can't be negative.
+fn expect_size(interpreter: &Interpreter, value: &Value, message: &str) -> Result<usize, Control> {
+    let size = expect_integer(interpreter, value)?;
+    if size < 0 { return Err(interpreter.error("ArgumentError", message)); }
+    Ok(size as usize)
+}
+
+fn map(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "map", args)),
+    };
+    let mut mapped = vec!();
+    for element in elements(interpreter, receiver)?.iter() {
+        mapped.push(call(interpreter, block, element)?);
+    }
+
+    Ok(Value::array(mapped))
+}
+
+fn flat_map(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "flat_map", args)),
+    };
+    let mut mapped = vec!();
+    for element in elements(interpreter, receiver)?.iter() {
+        match call(interpreter, block, element)? {
+            result @ Value::Array(_) => mapped.extend(array::elements(&result)),
+            result => mapped.push(result),
+        }
+    }
+
+    Ok(Value::array(mapped))
+}
+
+fn filter(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, keep: bool) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, if keep { "select" } else { "reject" }, args)),
+    };
+    let mut kept = vec!();
+    for element in elements(interpreter, receiver)?.into_iter() {
+        if call(interpreter, block, &element)?.is_truthy() == keep {
+            kept.push(element);
+        }
+    }
+
+    Ok(Value::array(kept))
+}
+
+fn filter_map(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "filter_map", args)),
+    };
+    let mut kept = vec!();
+    for element in elements(interpreter, receiver)?.iter() {
+        let result = call(interpreter, block, element)?;
+        if result.is_truthy() { kept.push(result); }
+    }
+
+    Ok(Value::array(kept))
+}
+
+fn partition(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "partition", args)),
+    };
+    let (mut selected, mut rejected) = (vec!(), vec!());
+    for element in elements(interpreter, receiver)?.into_iter() {
+        if call(interpreter, block, &element)?.is_truthy() {
+            selected.push(element);
+        } else {
+            rejected.push(element);
+        }
+    }
+
+    Ok(Value::array(vec!(Value::array(selected), Value::array(rejected))))
+}
+
+fn group_by(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "group_by", args)),
+    };
+    let groups = Value::hash();
+    for element in elements(interpreter, receiver)?.into_iter() {
+        let key = call(interpreter, block, &element)?;
+        match hash::lookup(interpreter, &groups, &key)? {
+            Some(Value::Array(group)) => group.elements.borrow_mut().push(element),
+            Some(_) => unreachable!("the groups are arrays"),
+            None => hash::insert(interpreter, &groups, key, Value::array(vec!(element)))?,
+        }
+    }
+
+    Ok(groups)
+}
+
+fn tally(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let counts = Value::hash();
+    for element in elements(interpreter, receiver)?.into_iter() {
+        let count = match hash::lookup(interpreter, &counts, &element)? {
+            Some(Value::Integer(count)) => count + 1,
+            _ => 1,
+        };
+        hash::insert(interpreter, &counts, element, Value::Integer(count))?;
+    }
+
+    Ok(counts)
+}
+
+fn find(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(enumerator_for(interpreter, receiver, "find", args)),
+    };
+    let found = Rc::new(RefCell::new(Value::Nil));
+    let slot = found.clone();
+    each_element(interpreter, receiver, move |interpreter, element| {
+        if !call(interpreter, &block, &element)?.is_truthy() { return Ok(true); }
+        *slot.borrow_mut() = element;
+        Ok(false)
+    })?;
+
+    let found = found.borrow().clone();
+    Ok(found)
+}
+
+/// The index of the first element `==` to the argument, or the first the block is true for.
+fn find_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (target, block) = (args.first().cloned(), block.cloned());
+    if target.is_none() && block.is_none() { return Ok(enumerator_for(interpreter, receiver, "find_index", args)); }
+    let (index, found) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(false)));
+    let (at, hit) = (index.clone(), found.clone());
+    each_element(interpreter, receiver, move |interpreter, element| {
+        let matched = match (&target, &block) {
+            (Some(target), _) => equals(interpreter, &element, target)?,
+            (None, Some(block)) => call(interpreter, block, &element)?.is_truthy(),
+            (None, None) => false,
+        };
+        if matched {
+            hit.set(true);
+            return Ok(false);
+        }
+        at.set(at.get() + 1);
+        Ok(true)
+    })?;
+
+    Ok(if found.get() { Value::Integer(index.get()) } else { Value::Nil })
+}
+
+fn each_with_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(enumerator_for(interpreter, receiver, "each_with_index", args)),
+    };
+    let index = Rc::new(Cell::new(0));
+    each_element(interpreter, receiver, move |interpreter, element| {
+        interpreter.call_proc(&block, &[element, Value::Integer(index.get())])?;
+        index.set(index.get() + 1);
+        Ok(true)
+    })?;
+
+    Ok(receiver.clone())
+}
+
+fn each_with_object(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(enumerator_for(interpreter, receiver, "each_with_object", args)),
+    };
+    let memo = args[0].clone();
+    let object = memo.clone();
+    each_element(interpreter, receiver, move |interpreter, element| {
+        interpreter.call_proc(&block, &[element, object.clone()])?;
+        Ok(true)
+    })?;
+
+    Ok(memo)
+}
+
+fn reverse_each(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "reverse_each", args)),
+    };
+    for element in elements(interpreter, receiver)?.iter().rev() {
+        call(interpreter, block, element)?;
+    }
+
+    Ok(receiver.clone())
+}
+
+fn each_slice(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let size = expect_integer(interpreter, &args[0])?;
+    if size <= 0 { return Err(interpreter.error("ArgumentError", "invalid slice size")); }
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each_slice", args)),
+    };
+    for slice in elements(interpreter, receiver)?.chunks(size as usize) {
+        call(interpreter, block, &Value::array(slice.to_vec()))?;
+    }
+
+    Ok(receiver.clone())
+}
+
+fn each_cons(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let size = expect_integer(interpreter, &args[0])?;
+    if size <= 0 { return Err(interpreter.error("ArgumentError", "invalid size")); }
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each_cons", args)),
+    };
+    for window in elements(interpreter, receiver)?.windows(size as usize) {
+        call(interpreter, block, &Value::array(window.to_vec()))?;
+    }
+
+    Ok(receiver.clone())
+}
+
+/// `inject(initial, :+)`, `inject(:+)`, `inject(initial) { }` or `inject { }`. Without an
+/// initial value the first element starts it off, and nothing at all gives `nil`.
+fn inject(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 2)?;
+    let (initial, operator) = match (args, block) {
+        ([initial, operator], _) => (Some(initial.clone()), Some(operator.clone())),
+        ([operator], None) => (None, Some(operator.clone())),
+        ([initial], Some(_)) => (Some(initial.clone()), None),
+        _ => (None, None),
+    };
+    let operator = match operator {
+        Some(operator) => Some(super::expect_name(interpreter, &operator)?),
+        None => {
+            need_block(interpreter, block)?;
+            None
+        },
+    };
+
+    let mut elements = elements(interpreter, receiver)?.into_iter();
+    let mut memo = match initial.or_else(|| elements.next()) {
+        Some(memo) => memo,
+        None => return Ok(Value::Nil),
+    };
+    for element in elements {
+        memo = match (&operator, block) {
+            (Some(operator), _) => interpreter.call_method(&memo, operator, slice::from_ref(&element), None)?,
+            (None, Some(block)) => interpreter.call_proc(block, &[memo, element])?,
+            (None, None) => unreachable!("inject needs an operator or a block"),
+        };
+    }
+
+    Ok(memo)
+}
+
+/// Adds with `+` starting from the argument or `0`. Runs of floats are added with
+/// Kahan-Babuska compensation like Ruby does, so `[0.1, 0.2, 0.3].sum` is `0.6`.
+pub fn sum(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let mut values = vec!();
+    for element in elements(interpreter, receiver)?.into_iter() {
+        values.push(match block {
+            Some(block) => call(interpreter, block, &element)?,
+            None => element,
+        });
+    }
+
+    let mut total = args.first().cloned().unwrap_or(Value::Integer(0));
+    let mut index = 0;
+    while index < values.len() {
+        total = match (&total, &values[index]) {
+            (Value::Integer(a), Value::Integer(b)) if a.checked_add(*b).is_some() => {
+                index += 1;
+                Value::Integer(a + b)
+            },
+            (Value::Float(_), Value::Float(_) | Value::Integer(_)) | (Value::Integer(_), Value::Float(_)) => {
+                let mut sum = numeric::float_of(&total);
+                let mut compensation = 0.0;
+                while let Some(x) = values.get(index).and_then(|value| match value {
+                    Value::Float(x) => Some(*x),
+                    Value::Integer(x) => Some(*x as f64),
+                    _ => None,
+                }) {
+                    sum = kahan_babuska(sum, x, &mut compensation);
+                    index += 1;
+                }
+                Value::Float(sum + compensation)
+            },
+            _ => {
+                index += 1;
+                interpreter.call_method(&total, "+", slice::from_ref(&values[index - 1]), None)?
+            },
+        };
+    }
+
+    Ok(total)
+}
+
+/// One step of compensated summation, carrying the lost low-order bits in `c`.
+fn kahan_babuska(sum: f64, x: f64, c: &mut f64) -> f64 {
+    if sum.is_nan() { return sum; }
+    if x.is_nan() { return x; }
+    if x.is_infinite() {
+        return if sum.is_infinite() && x.is_sign_negative() != sum.is_sign_negative() { f64::NAN } else { x };
+    }
+    if sum.is_infinite() { return sum; }
+
+    let t = sum + x;
+    if sum.abs() >= x.abs() {
+        *c += (sum - t) + x;
+    } else {
+        *c += (x - t) + sum;
+    }
+    t
+}
+
+pub fn count(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let mut count = 0;
+    for element in elements(interpreter, receiver)?.iter() {
+        let counted = match (args.first(), block) {
+            (Some(target), _) => equals(interpreter, element, target)?,
+            (None, Some(block)) => call(interpreter, block, element)?.is_truthy(),
+            (None, None) => true,
+        };
+        if counted { count += 1; }
+    }
+
+    Ok(Value::Integer(count))
+}
+
+/// `min` and `max`, comparing with `<=>` or the block. With a count, that many of the
+/// smallest or largest in order.
+pub fn extreme(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, wanted: Ordering) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let elements = elements(interpreter, receiver)?;
+    if let Some(count) = args.first() {
+        let message = format!("negative size ({})", interpreter.inspect(count)?);
+        let count = expect_size(interpreter, count, &message)?;
+        let mut sorted = sort_values(interpreter, elements, block)?;
+        if wanted == Ordering::Greater { sorted.reverse(); }
+        sorted.truncate(count);
+        return Ok(Value::array(sorted));
+    }
+
+    let mut best: Option<Value> = None;
+    for element in elements.into_iter() {
+        let better = match &best {
+            None => true,
+            Some(current) => {
+                let ordering = match block {
+                    Some(block) => {
+                        let result = interpreter.call_proc(block, &[element.clone(), current.clone()])?;
+                        ordering_of(interpreter, &result, &element, current)?
+                    },
+                    None => compare(interpreter, &element, current)?,
+                };
+                ordering == wanted
+            },
+        };
+        if better { best = Some(element); }
+    }
+
+    Ok(best.unwrap_or(Value::Nil))
+}
+
+/// `min_by` and `max_by`: the element the block gives the smallest or largest value for.
+fn extreme_by(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, wanted: Ordering) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, if wanted == Ordering::Less { "min_by" } else { "max_by" }, args)),
+    };
+    let elements = elements(interpreter, receiver)?;
+    if let Some(count) = args.first() {
+        let message = format!("negative size ({})", interpreter.inspect(count)?);
+        let count = expect_size(interpreter, count, &message)?;
+        let mut sorted = sort_values_by(interpreter, elements, block)?;
+        if wanted == Ordering::Greater { sorted.reverse(); }
+        sorted.truncate(count);
+        return Ok(Value::array(sorted));
+    }
+
+    let mut best: Option<(Value, Value)> = None;
+    for element in elements.into_iter() {
+        let key = call(interpreter, block, &element)?;
+        let better = match &best {
+            None => true,
+            Some((current, _)) => compare(interpreter, &key, current)? == wanted,
+        };
+        if better { best = Some((key, element)); }
+    }
+
+    Ok(best.map_or(Value::Nil, |(_, element)| element))
+}
+
+fn minmax(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let min = extreme(interpreter, receiver, &[], block, Ordering::Less)?;
+    let max = extreme(interpreter, receiver, &[], block, Ordering::Greater)?;
+    Ok(Value::array(vec!(min, max)))
+}
+
+fn sort(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let elements = elements(interpreter, receiver)?;
+    Ok(Value::array(sort_values(interpreter, elements, block)?))
+}
+
+fn sort_by(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "sort_by", args)),
+    };
+    let elements = elements(interpreter, receiver)?;
+    Ok(Value::array(sort_values_by(interpreter, elements, block)?))
+}
+
+fn uniq(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let elements = elements(interpreter, receiver)?;
+    Ok(Value::array(unique(interpreter, elements, block)?))
+}
+
+/// Pairs each element with the ones at the same position in the arguments, padding with
+/// `nil`. With a block each tuple is yielded and the result is `nil`.
+fn zip(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut others = vec!();
+    for other in args.iter() {
+        if !matches!(other, Value::Array(_)) && interpreter.lookup_class(other).find_method("each").is_none() {
+            let message = format!("wrong argument type {} (must respond to :each)", interpreter.class_of(other).inspect());
+            return Err(interpreter.error("TypeError", &message));
+        }
+        others.push(elements(interpreter, other)?);
+    }
+
+    let mut zipped = vec!();
+    for (index, element) in elements(interpreter, receiver)?.into_iter().enumerate() {
+        let mut tuple = vec!(element);
+        tuple.extend(others.iter().map(|other| other.get(index).cloned().unwrap_or(Value::Nil)));
+        match block {
+            Some(block) => { call(interpreter, block, &Value::array(tuple))?; },
+            None => zipped.push(Value::array(tuple)),
+        }
+    }
+
+    Ok(if block.is_some() { Value::Nil } else { Value::array(zipped) })
+}
+
+/// A hash from `[key, value]` pairs, or from the pairs the block makes of the elements.
+fn to_h(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let hash = Value::hash();
+    for (index, element) in elements(interpreter, receiver)?.iter().enumerate() {
+        let pair = match block {
+            Some(block) => call(interpreter, block, element)?,
+            None => element.clone(),
+        };
+        let pair = match &pair {
+            Value::Array(_) => array::elements(&pair),
+            other => {
+                let message = format!("wrong element type {} at {} (expected array)", interpreter.class_of(other).inspect(), index);
+                return Err(interpreter.error("TypeError", &message));
+            }
+        };
+        match <[Value; 2]>::try_from(pair) {
+            Ok([key, value]) => hash::insert(interpreter, &hash, key, value)?,
+            Err(pair) => {
+                let message = format!("wrong array length at {} (expected 2, was {})", index, pair.len());
+                return Err(interpreter.error("ArgumentError", &message));
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Up to `count` elements from the start, stopping `each` as soon as there are enough.
+fn leading(interpreter: &mut Interpreter, receiver: &Value, count: usize) -> Result<Vec<Value>, Control> {
+    if count == 0 { return Ok(vec!()); }
+    let taken = Rc::new(RefCell::new(vec!()));
+    let into = taken.clone();
+    each_element(interpreter, receiver, move |_, element| {
+        into.borrow_mut().push(element);
+        Ok(into.borrow().len() < count)
+    })?;
+
+    let taken = taken.borrow().clone();
+    Ok(taken)
+}
+
+pub fn first(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    match args.first() {
+        Some(count) => {
+            let count = expect_size(interpreter, count, "attempt to take negative size")?;
+            Ok(Value::array(leading(interpreter, receiver, count)?))
+        },
+        None => Ok(leading(interpreter, receiver, 1)?.pop().unwrap_or(Value::Nil)),
+    }
+}
+
+fn take(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let count = expect_size(interpreter, &args[0], "attempt to take negative size")?;
+    Ok(Value::array(leading(interpreter, receiver, count)?))
+}
+
+fn take_while(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(enumerator_for(interpreter, receiver, "take_while", args)),
+    };
+    let taken = Rc::new(RefCell::new(vec!()));
+    let into = taken.clone();
+    each_element(interpreter, receiver, move |interpreter, element| {
+        if !call(interpreter, &block, &element)?.is_truthy() { return Ok(false); }
+        into.borrow_mut().push(element);
+        Ok(true)
+    })?;
+
+    let taken = taken.borrow().clone();
+    Ok(Value::array(taken))
+}
+
+fn drop(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let count = expect_size(interpreter, &args[0], "attempt to drop negative size")?;
+    Ok(Value::array(elements(interpreter, receiver)?.into_iter().skip(count).collect()))
+}
+
+fn drop_while(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "drop_while", args)),
+    };
+    let mut kept = vec!();
+    for element in elements(interpreter, receiver)?.into_iter() {
+        if kept.is_empty() && call(interpreter, block, &element)?.is_truthy() { continue; }
+        kept.push(element);
+    }
+
+    Ok(Value::array(kept))
+}
+
+pub fn include(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let target = args[0].clone();
+    let found = Rc::new(Cell::new(false));
+    let hit = found.clone();
+    each_element(interpreter, receiver, move |interpreter, element| {
+        if equals(interpreter, &element, &target)? {
+            hit.set(true);
+            return Ok(false);
+        }
+        Ok(true)
+    })?;
+
+    Ok(Value::Bool(found.get()))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Quantifier {
+    All,
+    Any,
+    None,
+    One,
+}
+
+/// `all?`, `any?`, `none?` and `one?`, testing elements with the block, a pattern's `===`,
+/// or their truthiness. They stop as soon as the answer is known.
+fn quantify(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, quantifier: Quantifier) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (pattern, block) = (args.first().cloned(), block.cloned());
+    let matches = Rc::new(Cell::new(0));
+    let (count, settled) = (matches.clone(), Rc::new(Cell::new(None)));
+    let answer = settled.clone();
+    each_element(interpreter, receiver, move |interpreter, element| {
+        let matched = match (&pattern, &block) {
+            (Some(pattern), _) => interpreter.call_method(pattern, "===", slice::from_ref(&element), None)?.is_truthy(),
+            (None, Some(block)) => call(interpreter, block, &element)?.is_truthy(),
+            (None, None) => element.is_truthy(),
+        };
+        if matched { count.set(count.get() + 1); }
+        let result = match quantifier {
+            Quantifier::All if !matched => Some(false),
+            Quantifier::Any if matched => Some(true),
+            Quantifier::None if matched => Some(false),
+            Quantifier::One if count.get() > 1 => Some(false),
+            _ => None,
+        };
+        answer.set(result);
+        Ok(result.is_none())
+    })?;
+
+    Ok(Value::Bool(match settled.get() {
+        Some(result) => result,
+        None => match quantifier {
+            Quantifier::All | Quantifier::None => true,
+            Quantifier::Any => false,
+            Quantifier::One => matches.get() == 1,
+        },
+    }))
+}
@@ -0,0 +1,531 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ array, check_arity, expect_integer, expect_name, hash };
+use crate::eval::builtins::enumerable::{ each_element, elements, packed };
+use crate::eval::class::RClass;
+use crate::eval::coroutine::Coroutine;
+use crate::eval::environment::Scope;
+use crate::eval::value::{ ProcBody, RProc, Value };
+
+/// Methods that yield once for each element of their receiver, so an enumerator for one of
+/// them has the size of the receiver.
+const SIZED: &[&str] = &[
+    "each", "each_pair", "each_key", "each_value", "each_index", "each_char", "each_with_index", "with_index", "map",
+    "collect", "flat_map", "select", "filter", "reject", "filter_map", "find_index", "group_by", "partition",
+    "min_by", "max_by", "sort_by", "each_with_object", "reverse_each", "map!", "select!", "reject!", "delete_if",
+    "keep_if", "transform_values", "times",
+];
+
+/// `Enumerator`, what methods that iterate return when called without a block, and its lazy
+/// variant. An enumerator is either a method to call on an object with a block, or a
+/// generator block that yields values by calling an `Enumerator::Yielder`.
+pub fn define(interpreter: &mut Interpreter) {
+    let object = interpreter.classes.object.clone();
+    let enumerable = match interpreter.constant("Enumerable") {
+        Some(Value::Class(enumerable)) => enumerable,
+        _ => unreachable!("Enumerable is defined first"),
+    };
+    let enumerator = RClass::new("Enumerator", Some(object.clone()));
+    enumerator.includes.borrow_mut().push(enumerable.clone());
+    interpreter.set_constant("Enumerator", Value::Class(enumerator.clone()));
+    let lazy = RClass::new("Enumerator::Lazy", Some(enumerator.clone()));
+    enumerator.set_constant("Lazy", Value::Class(lazy.clone()));
+    let yielder = RClass::new("Enumerator::Yielder", Some(object));
+    enumerator.set_constant("Yielder", Value::Class(yielder.clone()));
+
+    interpreter.metaclass(&enumerator).define_builtin("new", new);
+    enumerator.define_builtin("each", each);
+    enumerator.define_builtin("size", size);
+    enumerator.define_builtin("with_index", |i, r, a, b| with_index(i, r, a, b, "with_index"));
+    enumerator.define_builtin("each_with_index", |i, r, a, b| {
+        check_arity(i, a, 0, 0)?;
+        with_index(i, r, a, b, "each_with_index")
+    });
+    enumerator.define_builtin("with_object", |i, r, a, b| with_object(i, r, a, b, "with_object"));
+    enumerator.define_builtin("each_with_object", |i, r, a, b| with_object(i, r, a, b, "each_with_object"));
+    enumerator.define_builtin("next", |i, r, _, _| Ok(packed(&next_values(i, r)?)));
+    enumerator.define_builtin("next_values", |i, r, _, _| Ok(Value::array(next_values(i, r)?)));
+    enumerator.define_builtin("peek", |i, r, _, _| Ok(packed(&peek_values(i, r)?)));
+    enumerator.define_builtin("peek_values", |i, r, _, _| Ok(Value::array(peek_values(i, r)?)));
+    enumerator.define_builtin("rewind", rewind);
+    enumerator.define_builtin("inspect", inspect);
+    enumerator.define_builtin("to_s", inspect);
+
+    yielder.define_builtin("<<", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        i.call_proc(&yielder_block(i, r), a)?;
+        Ok(r.clone())
+    });
+    yielder.define_builtin("yield", |i, r, a, _| i.call_proc(&yielder_block(i, r), a));
+    yielder.define_builtin("call", |i, r, a, _| i.call_proc(&yielder_block(i, r), a));
+
+    lazy.define_builtin("each", lazy_each);
+    lazy.define_builtin("map", |i, r, a, b| lazy_with_block(i, r, a, b, "map"));
+    lazy.define_builtin("collect", |i, r, a, b| lazy_with_block(i, r, a, b, "collect"));
+    lazy.define_builtin("select", |i, r, a, b| lazy_with_block(i, r, a, b, "select"));
+    lazy.define_builtin("filter", |i, r, a, b| lazy_with_block(i, r, a, b, "filter"));
+    lazy.define_builtin("reject", |i, r, a, b| lazy_with_block(i, r, a, b, "reject"));
+    lazy.define_builtin("filter_map", |i, r, a, b| lazy_with_block(i, r, a, b, "filter_map"));
+    lazy.define_builtin("flat_map", |i, r, a, b| lazy_with_block(i, r, a, b, "flat_map"));
+    lazy.define_builtin("collect_concat", |i, r, a, b| lazy_with_block(i, r, a, b, "collect_concat"));
+    lazy.define_builtin("take_while", |i, r, a, b| lazy_with_block(i, r, a, b, "take_while"));
+    lazy.define_builtin("drop_while", |i, r, a, b| lazy_with_block(i, r, a, b, "drop_while"));
+    lazy.define_builtin("take", |i, r, a, _| lazy_with_count(i, r, a, "take"));
+    lazy.define_builtin("drop", |i, r, a, _| lazy_with_count(i, r, a, "drop"));
+    lazy.define_builtin("with_index", lazy_with_index);
+    lazy.define_builtin("each_with_index", lazy_with_index);
+    lazy.define_builtin("compact", |i, r, _, _| Ok(chain(i, r, "compact", vec!())));
+    lazy.define_builtin("uniq", |i, r, _, _| Ok(chain(i, r, "uniq", vec!())));
+    lazy.define_builtin("force", |i, r, _, _| Ok(Value::array(elements(i, r)?)));
+    lazy.define_builtin("eager", |i, r, _, _| Ok(enumerator_for(i, r, "each", &[])));
+    lazy.define_builtin("lazy", |_, r, _, _| Ok(r.clone()));
+    lazy.define_builtin("size", |_, _, _, _| Ok(Value::Nil));
+    lazy.define_builtin("inspect", lazy_inspect);
+    lazy.define_builtin("to_s", lazy_inspect);
+
+    enumerable.define_builtin("lazy", |i, r, _, _| {
+        let lazy = build(&class(i, "Lazy"), &[("source", r.clone()), ("steps", Value::array(vec!()))]);
+        Ok(lazy)
+    });
+
+    let kernel = interpreter.classes.kernel.clone();
+    kernel.define_builtin("to_enum", to_enum);
+    kernel.define_builtin("enum_for", to_enum);
+}
+
+/// `Enumerator` or one of the classes nested in it.
+fn class(interpreter: &Interpreter, name: &str) -> Rc<RClass> {
+    let enumerator = match interpreter.constant("Enumerator") {
+        Some(Value::Class(enumerator)) => enumerator,
+        _ => unreachable!("Enumerator is defined at startup"),
+    };
+    if name == "Enumerator" { return enumerator; }
+
+    let nested = enumerator.constants.borrow().get(name).cloned();
+    match nested {
+        Some(Value::Class(class)) => class,
+        _ => unreachable!("Enumerator::{} is defined at startup", name),
+    }
+}
+
+/// An object of a builtin class with its state in hidden instance variables.
+fn build(class: &Rc<RClass>, state: &[(&str, Value)]) -> Value {
+    let value = Value::object(class.clone());
+    if let Value::Object(object) = &value {
+        for (name, state) in state.iter() {
+            object.set(name, state.clone());
+        }
+    }
+    value
+}
+
+fn set(value: &Value, name: &str, state: Value) {
+    if let Value::Object(object) = value { object.set(name, state); }
+}
+
+/// An enumerator that calls `method` on `receiver` with `args` and the block it is given,
+/// which is what a method that iterates returns when it is called without a block.
+pub fn enumerator_for(interpreter: &Interpreter, receiver: &Value, method: &str, args: &[Value]) -> Value {
+    let state = [("receiver", receiver.clone()), ("method", Value::symbol(method)), ("arguments", Value::array(args.to_vec()))];
+    build(&class(interpreter, "Enumerator"), &state)
+}
+
+/// `Enumerator.new { |yielder| ... }`, optionally with a size.
+fn new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let block = super::proc::expect_block(interpreter, block, "no block given")?;
+    let size = args.first().cloned().unwrap_or(Value::Nil);
+    Ok(build(&class(interpreter, "Enumerator"), &[("generator", Value::Proc(block.clone())), ("size", size)]))
+}
+
+/// `to_enum(method = :each, *args)`
+fn to_enum(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let method = match args.first() {
+        Some(method) => expect_name(interpreter, method)?,
+        None => String::from("each"),
+    };
+    Ok(enumerator_for(interpreter, receiver, &method, args.get(1..).unwrap_or(&[])))
+}
+
+/// What an enumerator iterates over: a generator block, or a method called with a block.
+enum Source {
+    Generator(Rc<RProc>),
+    Method(Value, String, Vec<Value>),
+}
+
+fn source(interpreter: &mut Interpreter, enumerator: &Value) -> Result<Source, Control> {
+    if let Value::Proc(generator) = interpreter.instance_variable(enumerator, "generator") {
+        return Ok(Source::Generator(generator));
+    }
+
+    let receiver = interpreter.instance_variable(enumerator, "receiver");
+    let method = expect_name(interpreter, &interpreter.instance_variable(enumerator, "method"))?;
+    let args = array::elements(&interpreter.instance_variable(enumerator, "arguments"));
+    Ok(Source::Method(receiver, method, args))
+}
+
+fn run_source(interpreter: &mut Interpreter, source: &Source, block: &Rc<RProc>) -> EvalResult {
+    match source {
+        Source::Generator(generator) => {
+            let yielder = build(&class(interpreter, "Yielder"), &[("block", Value::Proc(block.clone()))]);
+            interpreter.call_proc(generator, &[yielder])
+        },
+        Source::Method(receiver, method, args) => interpreter.call_method(receiver, method, args, Some(block)),
+    }
+}
+
+/// Runs what the enumerator stands for, with `block` getting the values.
+fn run(interpreter: &mut Interpreter, enumerator: &Value, block: &Rc<RProc>) -> EvalResult {
+    let source = source(interpreter, enumerator)?;
+    run_source(interpreter, &source, block)
+}
+
+fn each(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    match block {
+        Some(block) => run(interpreter, receiver, block),
+        None => Ok(receiver.clone()),
+    }
+}
+
+fn size(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    match interpreter.instance_variable(receiver, "size") {
+        Value::Proc(size) => return interpreter.call_proc(&size, &[]),
+        Value::Nil => (),
+        size => return Ok(size),
+    }
+
+    let (source, method) = (interpreter.instance_variable(receiver, "receiver"), interpreter.instance_variable(receiver, "method"));
+    match &method {
+        Value::Symbol(method) if &**method == "times" => match source {
+            Value::Integer(count) => Ok(Value::Integer(count.max(0))),
+            _ => Ok(Value::Nil),
+        },
+        Value::Symbol(method) if SIZED.contains(&&**method) && interpreter.lookup_class(&source).find_method("size").is_some() => {
+            interpreter.call_method(&source, "size", &[], None)
+        },
+        _ => Ok(Value::Nil),
+    }
+}
+
+/// Calls the block with each value and its index, counting from `offset`, and hands what the
+/// block returns back to the method, so `map.with_index` maps.
+fn with_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, method: &str) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let offset = match args.first() {
+        None | Some(Value::Nil) => 0,
+        Some(offset) => expect_integer(interpreter, offset)?,
+    };
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(enumerator_for(interpreter, receiver, method, args)),
+    };
+
+    let index = Cell::new(offset);
+    let indexed = interpreter.native_proc(move |interpreter, values| {
+        let current = index.get();
+        index.set(current + 1);
+        interpreter.call_proc(&block, &[packed(values), Value::Integer(current)])
+    });
+    run(interpreter, receiver, &indexed)
+}
+
+fn with_object(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, method: &str) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(enumerator_for(interpreter, receiver, method, args)),
+    };
+
+    let memo = args[0].clone();
+    let given = memo.clone();
+    let with_memo = interpreter.native_proc(move |interpreter, values| interpreter.call_proc(&block, &[packed(values), given.clone()]));
+    run(interpreter, receiver, &with_memo)?;
+    Ok(memo)
+}
+
+/// The values of the next iteration. The enumerator runs in a coroutine that is suspended
+/// after each one, until `rewind` starts it over.
+fn next_values(interpreter: &mut Interpreter, receiver: &Value) -> Result<Vec<Value>, Control> {
+    if let Value::Array(_) = interpreter.instance_variable(receiver, "peeked") {
+        let peeked = interpreter.instance_variable(receiver, "peeked");
+        set(receiver, "peeked", Value::Nil);
+        return Ok(array::elements(&peeked));
+    }
+
+    let cursor = match interpreter.instance_variable(receiver, "cursor") {
+        Value::Proc(cursor) => cursor,
+        _ => {
+            // not the enumerator itself, which holds the coroutine, so that it can be dropped
+            let source = source(interpreter, receiver)?;
+            let coroutine = Coroutine::new(move |interpreter, suspender| {
+                let block = interpreter.native_proc(move |_, values| {
+                    suspender.suspend(values.to_vec());
+                    Ok(Value::Nil)
+                });
+                run_source(interpreter, &source, &block)
+            });
+            let next = move |interpreter: &mut Interpreter, _: &[Value]| match coroutine.resume(interpreter)? {
+                Some(values) => Ok(Value::array(values)),
+                None => {
+                    // its `result` is what the iteration returned, which `loop` returns
+                    let error = interpreter.error("StopIteration", "iteration reached an end");
+                    if let Control::Raise(Value::Object(exception)) = &error { exception.set("result", coroutine.result()); }
+                    Err(error)
+                },
+            };
+            // with the scope and self of the caller, which may hold the enumerator, the cursor
+            // would keep itself alive
+            let cursor = Rc::new(RProc {
+                body:    ProcBody::Native(Rc::new(next)),
+                scope:   Scope::new(),
+                context: interpreter.frames[0].context.clone(),
+                lambda:  false,
+            });
+            set(receiver, "cursor", Value::Proc(cursor.clone()));
+            cursor
+        }
+    };
+
+    let values = interpreter.call_proc(&cursor, &[])?;
+    Ok(array::elements(&values))
+}
+
+fn peek_values(interpreter: &mut Interpreter, receiver: &Value) -> Result<Vec<Value>, Control> {
+    let values = next_values(interpreter, receiver)?;
+    set(receiver, "peeked", Value::array(values.clone()));
+    Ok(values)
+}
+
+/// Starts over, leaving the coroutine of the previous run suspended where it was.
+fn rewind(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    set(receiver, "cursor", Value::Nil);
+    set(receiver, "peeked", Value::Nil);
+    Ok(receiver.clone())
+}
+
+/// `#<Enumerator: [1, 2]:each_slice(2)>`
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if let Value::Proc(generator) = interpreter.instance_variable(receiver, "generator") {
+        let inspected = format!("#<Enumerator: #<Enumerator::Generator:0x{:016x}>:each>", Rc::as_ptr(&generator) as usize);
+        return Ok(Value::string(&inspected));
+    }
+
+    let source = interpreter.instance_variable(receiver, "receiver");
+    let method = expect_name(interpreter, &interpreter.instance_variable(receiver, "method"))?;
+    let args = array::elements(&interpreter.instance_variable(receiver, "arguments"));
+    let inspected = format!("#<Enumerator: {}:{}{}>", interpreter.inspect(&source)?, method, inspect_arguments(interpreter, &args)?);
+    Ok(Value::string(&inspected))
+}
+
+fn inspect_arguments(interpreter: &mut Interpreter, args: &[Value]) -> Result<String, Control> {
+    if args.is_empty() { return Ok(String::new()); }
+    let mut inspected = vec!();
+    for arg in args.iter() {
+        inspected.push(interpreter.inspect(arg)?);
+    }
+    Ok(format!("({})", inspected.join(", ")))
+}
+
+fn yielder_block(interpreter: &Interpreter, yielder: &Value) -> Rc<RProc> {
+    match interpreter.instance_variable(yielder, "block") {
+        Value::Proc(block) => block,
+        _ => unreachable!("a yielder is made with a block"),
+    }
+}
+
+/// A lazy enumerator with one more step after the receiver's. A step is its name and its
+/// arguments, ending with the block if it has one.
+fn chain(interpreter: &Interpreter, receiver: &Value, step: &str, args: Vec<Value>) -> Value {
+    let mut steps = array::elements(&interpreter.instance_variable(receiver, "steps"));
+    let mut step = vec!(Value::symbol(step));
+    step.extend(args);
+    steps.push(Value::array(step));
+    build(&class(interpreter, "Lazy"), &[("source", interpreter.instance_variable(receiver, "source")), ("steps", Value::array(steps))])
+}
+
+fn lazy_with_block(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, step: &str) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Err(interpreter.error("ArgumentError", &format!("tried to call lazy {} without a block", step))),
+    };
+    Ok(chain(interpreter, receiver, step, vec!(Value::Proc(block.clone()))))
+}
+
+fn lazy_with_count(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], step: &str) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let count = expect_integer(interpreter, &args[0])?;
+    if count < 0 { return Err(interpreter.error("ArgumentError", &format!("attempt to {} negative size", step))); }
+    Ok(chain(interpreter, receiver, step, vec!(Value::Integer(count))))
+}
+
+fn lazy_with_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let offset = match args.first() {
+        None | Some(Value::Nil) => 0,
+        Some(offset) => expect_integer(interpreter, offset)?,
+    };
+    let block = block.map_or(Value::Nil, |block| Value::Proc(block.clone()));
+    Ok(chain(interpreter, receiver, "with_index", vec!(Value::Integer(offset), block)))
+}
+
+/// A step of a lazy enumerator, with the state it keeps while the enumerator runs.
+enum Stage {
+    Map(Rc<RProc>),
+    Select(Rc<RProc>, bool),
+    FilterMap(Rc<RProc>),
+    FlatMap(Rc<RProc>),
+    TakeWhile(Rc<RProc>),
+    DropWhile(Rc<RProc>, Cell<bool>),
+    Take(i64, Cell<i64>),
+    Drop(i64, Cell<i64>),
+    WithIndex(Option<Rc<RProc>>, Cell<i64>),
+    Compact,
+    /// The values seen so far, as the keys of a hash
+    Uniq(Value),
+}
+
+fn stages(interpreter: &Interpreter, receiver: &Value) -> Vec<Stage> {
+    let steps = array::elements(&interpreter.instance_variable(receiver, "steps"));
+    steps.iter().map(|step| {
+        let step = array::elements(step);
+        let block = || match step.last() {
+            Some(Value::Proc(block)) => block.clone(),
+            _ => unreachable!("the step is made with a block"),
+        };
+        let count = || match step.get(1) {
+            Some(Value::Integer(count)) => *count,
+            _ => 0,
+        };
+        match &step[0] {
+            Value::Symbol(name) => match &**name {
+                "map" | "collect" => Stage::Map(block()),
+                "select" | "filter" => Stage::Select(block(), true),
+                "reject" => Stage::Select(block(), false),
+                "filter_map" => Stage::FilterMap(block()),
+                "flat_map" | "collect_concat" => Stage::FlatMap(block()),
+                "take_while" => Stage::TakeWhile(block()),
+                "drop_while" => Stage::DropWhile(block(), Cell::new(true)),
+                "take" => Stage::Take(count(), Cell::new(0)),
+                "drop" => Stage::Drop(count(), Cell::new(0)),
+                "with_index" => Stage::WithIndex(step.get(2).and_then(|block| match block {
+                    Value::Proc(block) => Some(block.clone()),
+                    _ => None,
+                }), Cell::new(count())),
+                "compact" => Stage::Compact,
+                "uniq" => Stage::Uniq(Value::hash()),
+                name => unreachable!("no lazy step {}", name),
+            },
+            _ => unreachable!("a step starts with its name"),
+        }
+    }).collect()
+}
+
+/// Takes each value of the source through the steps, calling the block with what comes out
+/// at the end.
+fn lazy_each(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block.clone(),
+        None => return Ok(receiver.clone()),
+    };
+
+    let stages = stages(interpreter, receiver);
+    let source = interpreter.instance_variable(receiver, "source");
+    // `take(0)` doesn't even look at the source
+    if stages.iter().any(|stage| matches!(stage, Stage::Take(0, _))) { return Ok(receiver.clone()); }
+    each_element(interpreter, &source, move |interpreter, value| feed(interpreter, &stages, value, &block))?;
+    Ok(receiver.clone())
+}
+
+/// Runs one value through the stages. `false` once no more values are wanted.
+fn feed(interpreter: &mut Interpreter, stages: &[Stage], value: Value, block: &Rc<RProc>) -> Result<bool, Control> {
+    let (stage, rest) = match stages.split_first() {
+        Some(split) => split,
+        None => {
+            interpreter.call_proc(block, slice::from_ref(&value))?;
+            return Ok(true);
+        }
+    };
+
+    match stage {
+        Stage::Map(map) => {
+            let mapped = interpreter.call_proc(map, slice::from_ref(&value))?;
+            feed(interpreter, rest, mapped, block)
+        },
+        Stage::Select(test, keep) => match interpreter.call_proc(test, slice::from_ref(&value))?.is_truthy() == *keep {
+            true => feed(interpreter, rest, value, block),
+            false => Ok(true),
+        },
+        Stage::FilterMap(map) => match interpreter.call_proc(map, slice::from_ref(&value))? {
+            mapped if mapped.is_truthy() => feed(interpreter, rest, mapped, block),
+            _ => Ok(true),
+        },
+        Stage::FlatMap(map) => match interpreter.call_proc(map, slice::from_ref(&value))? {
+            mapped @ Value::Array(_) => {
+                for value in array::elements(&mapped).into_iter() {
+                    if !feed(interpreter, rest, value, block)? { return Ok(false); }
+                }
+                Ok(true)
+            },
+            mapped => feed(interpreter, rest, mapped, block),
+        },
+        Stage::TakeWhile(test) => match interpreter.call_proc(test, slice::from_ref(&value))?.is_truthy() {
+            true => feed(interpreter, rest, value, block),
+            false => Ok(false),
+        },
+        Stage::DropWhile(test, dropping) => {
+            if dropping.get() && interpreter.call_proc(test, slice::from_ref(&value))?.is_truthy() { return Ok(true); }
+            dropping.set(false);
+            feed(interpreter, rest, value, block)
+        },
+        Stage::Take(count, taken) => {
+            taken.set(taken.get() + 1);
+            // stop right after the last value wanted, without fetching another one
+            let more = feed(interpreter, rest, value, block)?;
+            Ok(more && taken.get() < *count)
+        },
+        Stage::Drop(count, dropped) => {
+            if dropped.get() < *count {
+                dropped.set(dropped.get() + 1);
+                return Ok(true);
+            }
+            feed(interpreter, rest, value, block)
+        },
+        Stage::WithIndex(with, index) => {
+            let current = Value::Integer(index.get());
+            index.set(index.get() + 1);
+            match with {
+                Some(with) => {
+                    interpreter.call_proc(with, &[value.clone(), current])?;
+                    feed(interpreter, rest, value, block)
+                },
+                None => feed(interpreter, rest, Value::array(vec!(value, current)), block),
+            }
+        },
+        Stage::Compact if value.is_nil() => Ok(true),
+        Stage::Compact => feed(interpreter, rest, value, block),
+        Stage::Uniq(seen) => {
+            if hash::lookup(interpreter, seen, &value)?.is_some() { return Ok(true); }
+            hash::insert(interpreter, seen, value.clone(), Value::Bool(true))?;
+            feed(interpreter, rest, value, block)
+        },
+    }
+}
+
+/// `#<Enumerator::Lazy: #<Enumerator::Lazy: 1..3>:map>`, one level for each step.
+fn lazy_inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let source = interpreter.instance_variable(receiver, "source");
+    let mut inspected = format!("#<Enumerator::Lazy: {}>", interpreter.inspect(&source)?);
+    for step in array::elements(&interpreter.instance_variable(receiver, "steps")).iter() {
+        let step = array::elements(step);
+        let name = expect_name(interpreter, &step[0])?;
+        let args: Vec<Value> = step[1..].iter().filter(|arg| matches!(arg, Value::Integer(_))).cloned().collect();
+        inspected = format!("#<Enumerator::Lazy: {}:{}{}>", inspected, name, inspect_arguments(interpreter, &args)?);
+    }
+    Ok(Value::string(&inspected))
+}
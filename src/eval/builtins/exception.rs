@@ -0,0 +1,293 @@
+use std::rc::Rc;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, equals, expect_string, kernel };
+use crate::eval::builtins::kernel::Stream;
+use crate::eval::class::RClass;
+use crate::eval::value::{ RProc, Value };
+
+/// Each exception class and its superclass, superclasses first.
+const HIERARCHY: &[(&str, &str)] = &[
+    ("Exception", "Object"),
+    ("NoMemoryError", "Exception"),
+    ("ScriptError", "Exception"),
+    ("LoadError", "ScriptError"),
+    ("NotImplementedError", "ScriptError"),
+    ("SyntaxError", "ScriptError"),
+    ("SecurityError", "Exception"),
+    ("SignalException", "Exception"),
+    ("Interrupt", "SignalException"),
+    ("StandardError", "Exception"),
+    ("ArgumentError", "StandardError"),
+    ("UncaughtThrowError", "ArgumentError"),
+    ("EncodingError", "StandardError"),
+    ("FiberError", "StandardError"),
+    ("IOError", "StandardError"),
+    ("EOFError", "IOError"),
+    ("IndexError", "StandardError"),
+    ("KeyError", "IndexError"),
+    ("StopIteration", "IndexError"),
+    ("LocalJumpError", "StandardError"),
+    ("NameError", "StandardError"),
+    ("NoMethodError", "NameError"),
+    ("RangeError", "StandardError"),
+    ("FloatDomainError", "RangeError"),
+    ("RegexpError", "StandardError"),
+    ("RuntimeError", "StandardError"),
+    ("FrozenError", "RuntimeError"),
+    ("ThreadError", "StandardError"),
+    ("TypeError", "StandardError"),
+    ("ZeroDivisionError", "StandardError"),
+    ("SystemExit", "Exception"),
+    ("SystemStackError", "Exception"),
+];
+
+pub fn define(interpreter: &mut Interpreter) {
+    for (name, superclass) in HIERARCHY.iter() {
+        let superclass = match interpreter.constant(superclass) {
+            Some(Value::Class(superclass)) => superclass,
+            _ => panic!("{} is defined before its subclasses", superclass),
+        };
+        interpreter.set_constant(name, Value::Class(RClass::new(name, Some(superclass))));
+    }
+
+    let exception = match interpreter.constant("Exception") {
+        Some(Value::Class(exception)) => exception,
+        _ => unreachable!(),
+    };
+    interpreter.metaclass(&exception).define_builtin("exception", |interpreter, receiver, args, block| {
+        interpreter.call_method(receiver, "new", args, block)
+    });
+    exception.define_private_builtin("initialize", initialize);
+    exception.define_builtin("exception", exception_with_message);
+    exception.define_builtin("message", |interpreter, receiver, _, _| interpreter.call_method(receiver, "to_s", &[], None));
+    exception.define_builtin("to_s", to_s);
+    exception.define_builtin("inspect", inspect);
+    exception.define_builtin("==", equal);
+    exception.define_builtin("backtrace", |interpreter, receiver, _, _| Ok(interpreter.instance_variable(receiver, "backtrace")));
+    exception.define_builtin("set_backtrace", set_backtrace);
+    exception.define_builtin("cause", |interpreter, receiver, _, _| Ok(interpreter.instance_variable(receiver, "cause")));
+    exception.define_builtin("full_message", |interpreter, receiver, _, _| Ok(Value::string(&full_message(interpreter, receiver))));
+
+    let system_exit = match interpreter.constant("SystemExit") {
+        Some(Value::Class(system_exit)) => system_exit,
+        _ => unreachable!(),
+    };
+    system_exit.define_private_builtin("initialize", system_exit_initialize);
+    system_exit.define_builtin("status", |interpreter, receiver, _, _| Ok(interpreter.instance_variable(receiver, "status")));
+    system_exit.define_builtin("success?", |interpreter, receiver, _, _| {
+        Ok(Value::Bool(matches!(interpreter.instance_variable(receiver, "status"), Value::Integer(0))))
+    });
+
+    let name_error = match interpreter.constant("NameError") {
+        Some(Value::Class(name_error)) => name_error,
+        _ => unreachable!(),
+    };
+    name_error.define_builtin("name", |interpreter, receiver, _, _| Ok(interpreter.instance_variable(receiver, "name")));
+    name_error.define_builtin("receiver", receiver);
+
+    let stop_iteration = match interpreter.constant("StopIteration") {
+        Some(Value::Class(stop_iteration)) => stop_iteration,
+        _ => unreachable!(),
+    };
+    stop_iteration.define_builtin("result", |interpreter, receiver, _, _| Ok(interpreter.instance_variable(receiver, "result")));
+
+    let kernel = interpreter.classes.kernel.clone();
+    kernel.define_private_builtin("raise", raise);
+    kernel.define_private_builtin("fail", raise);
+    kernel.define_private_builtin("exit", exit);
+    kernel.define_private_builtin("abort", abort);
+}
+
+fn initialize(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    if let (Value::Object(object), Some(message)) = (receiver, args.first()) {
+        object.set("message", message.clone());
+    }
+
+    Ok(Value::Nil)
+}
+
+/// The object a method was missing from, which only errors raised for a call know.
+fn receiver(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    match receiver {
+        Value::Object(object) => object.get("receiver").ok_or_else(|| interpreter.error("ArgumentError", "no receiver is available")),
+        _ => Ok(Value::Nil),
+    }
+}
+
+/// `SystemExit.new(status = 0, message = nil)`, where the status can also be true for 0 or
+/// false for 1, and can be left out before the message.
+fn system_exit_initialize(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 2)?;
+    let (status, message) = match args.first() {
+        Some(Value::Bool(true)) => (0, args.get(1)),
+        Some(Value::Bool(false)) => (1, args.get(1)),
+        Some(Value::Integer(status)) => (*status, args.get(1)),
+        _ if args.len() == 2 => return Err(super::conversion_error(interpreter, &args[0], "Integer")),
+        message => (0, message),
+    };
+    interpreter.set_instance_variable(receiver, "status", Value::Integer(status))?;
+    if let Some(message) = message {
+        interpreter.set_instance_variable(receiver, "message", message.clone())?;
+    }
+
+    Ok(Value::Nil)
+}
+
+/// `exit(status = true)`: raises the `SystemExit` that ends the program, running `ensure`
+/// clauses on the way out.
+fn exit(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let status = args.first().cloned().unwrap_or(Value::Bool(true));
+    let system_exit = interpreter.constant("SystemExit").expect("SystemExit is defined");
+    let exception = interpreter.call_method(&system_exit, "new", &[status, Value::string("exit")], None)?;
+    raise(interpreter, receiver, &[exception], None)
+}
+
+/// `abort(message = nil)`: prints the message on the error output and exits with status 1.
+fn abort(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let message = match args.first() {
+        Some(message) => expect_string(interpreter, message)?,
+        None => String::from("exit"),
+    };
+    if !args.is_empty() {
+        kernel::write_to(interpreter, Stream::Error, format!("{}\n", message).as_bytes())?;
+    }
+
+    let system_exit = interpreter.constant("SystemExit").expect("SystemExit is defined");
+    let exception = interpreter.call_method(&system_exit, "new", &[Value::Bool(false), Value::string(&message)], None)?;
+    raise(interpreter, receiver, &[exception], None)
+}
+
+/// The exception itself, or a copy of it with another message.
+fn exception_with_message(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (object, message) = match (receiver, args.first()) {
+        (Value::Object(object), Some(message)) => (object, message),
+        _ => return Ok(receiver.clone()),
+    };
+    let copy = Value::object(object.class.clone());
+    if let Value::Object(copy) = &copy {
+        *copy.ivars.borrow_mut() = object.ivars.borrow().clone();
+        copy.set("message", message.clone());
+    }
+
+    Ok(copy)
+}
+
+fn to_s(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    match interpreter.instance_variable(receiver, "message") {
+        Value::Nil => Ok(Value::string(&interpreter.class_of(receiver).inspect())),
+        message => Ok(Value::string(&interpreter.to_s(&message)?)),
+    }
+}
+
+/// `#<ArgumentError: bad value>`, or just the class name when there's no message.
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let class = interpreter.class_of(receiver).inspect();
+    let message = interpreter.call_method(receiver, "message", &[], None)?;
+    let message = interpreter.to_s(&message)?;
+    if message.is_empty() || message == class {
+        return Ok(Value::string(&class));
+    }
+
+    Ok(Value::string(&format!("#<{}: {}>", class, message)))
+}
+
+/// Exceptions are equal when they have the same class, message and backtrace.
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let other = &args[0];
+    if !Rc::ptr_eq(&interpreter.class_of(receiver), &interpreter.class_of(other)) {
+        return Ok(Value::Bool(false));
+    }
+    for name in ["message", "backtrace"] {
+        let (mine, theirs) = (interpreter.call_method(receiver, name, &[], None)?, interpreter.call_method(other, name, &[], None)?);
+        if !equals(interpreter, &mine, &theirs)? { return Ok(Value::Bool(false)); }
+    }
+
+    Ok(Value::Bool(true))
+}
+
+fn set_backtrace(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let backtrace = match &args[0] {
+        Value::String(_) => Value::array(vec!(args[0].clone())),
+        Value::Array(_) | Value::Nil => args[0].clone(),
+        _ => return Err(interpreter.error("TypeError", "backtrace must be an Array of String or an Array of Thread::Backtrace::Location")),
+    };
+    interpreter.set_instance_variable(receiver, "backtrace", backtrace.clone())?;
+
+    Ok(backtrace)
+}
+
+/// `raise` re-raises `$!`; `raise "message"` raises a `RuntimeError`; `raise Class` and
+/// `raise Class, "message"` raise what `Class.exception` makes. A third argument replaces
+/// the backtrace, which is otherwise where `raise` was called.
+fn raise(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 3)?;
+    let current = interpreter.global("$!");
+    let exception = match args {
+        [] if current.is_truthy() => return Err(Control::Raise(current)),
+        [] => return Err(interpreter.error("RuntimeError", "unhandled exception")),
+        [Value::String(_)] => {
+            let runtime_error = interpreter.constant("RuntimeError").expect("RuntimeError is defined");
+            interpreter.call_method(&runtime_error, "new", args, None)?
+        },
+        [value, rest @ ..] => {
+            if interpreter.lookup_class(value).find_method("exception").is_none() {
+                return Err(interpreter.error("TypeError", "exception class/object expected"));
+            }
+            interpreter.call_method(value, "exception", &rest[..rest.len().min(1)], None)?
+        },
+    };
+    if !interpreter.is_a(&exception, "Exception") {
+        return Err(interpreter.error("TypeError", "exception object expected"));
+    }
+
+    if let Some(backtrace) = args.get(2) {
+        set_backtrace(interpreter, &exception, std::slice::from_ref(backtrace), None)?;
+    } else if interpreter.instance_variable(&exception, "backtrace").is_nil() {
+        let backtrace = interpreter.backtrace();
+        interpreter.set_instance_variable(&exception, "backtrace", backtrace)?;
+    }
+    if current.is_truthy() && !current.is_identical(&exception) && interpreter.instance_variable(&exception, "cause").is_nil() {
+        interpreter.set_instance_variable(&exception, "cause", current)?;
+    }
+
+    Err(Control::Raise(exception))
+}
+
+/// `file:line:in 'method': message (Class)` followed by a `from` line for each outer frame.
+/// A message spanning several lines has the class after its first line.
+pub fn full_message(interpreter: &mut Interpreter, exception: &Value) -> String {
+    let class = interpreter.class_of(exception).inspect();
+    let message = match interpreter.call_method(exception, "message", &[], None) {
+        Ok(message) => interpreter.to_s(&message).unwrap_or_else(|_| class.clone()),
+        Err(_) => class.clone(),
+    };
+    let backtrace: Vec<String> = match interpreter.instance_variable(exception, "backtrace") {
+        Value::Array(lines) => lines.elements.borrow().iter().map(|line| interpreter.string_of(line)).collect(),
+        _ => vec!(),
+    };
+
+    let mut lines = message.lines();
+    let first = match lines.next() {
+        Some(first) if !first.is_empty() => format!("{} ({})", first, class),
+        _ => String::from("unhandled exception"),
+    };
+    let mut report = match backtrace.first() {
+        Some(position) => format!("{}: {}", position, first),
+        None => first,
+    };
+    for line in lines {
+        report += &format!("\n{}", line);
+    }
+    for position in backtrace.iter().skip(1) {
+        report += &format!("\n\tfrom {}", position);
+    }
+
+    report
+}
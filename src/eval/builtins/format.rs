@@ -0,0 +1,323 @@
+use std::convert::TryFrom;
+use std::slice;
+
+use crate::eval::{ Control, Interpreter };
+use crate::eval::bigint::BigInt;
+use crate::eval::builtins::{ expect_integer, hash, numeric };
+use crate::eval::value::Value;
+
+/// One `%` directive: its flags, width and precision.
+#[derive(Clone, Copy, Default)]
+struct Spec {
+    minus:     bool,
+    plus:      bool,
+    space:     bool,
+    zero:      bool,
+    alternate: bool,
+    width:     Option<usize>,
+    precision: Option<usize>,
+}
+
+/// The arguments a format string consumes: in order, by `N$` position, or from a hash by
+/// `%<name>` and `%{name}`.
+struct Arguments<'a> {
+    values: &'a [Value],
+    next:   usize,
+}
+
+impl<'a> Arguments<'a> {
+    fn next(&mut self, interpreter: &Interpreter) -> Result<Value, Control> {
+        let value = self.values.get(self.next).cloned().ok_or_else(|| interpreter.error("ArgumentError", "too few arguments"))?;
+        self.next += 1;
+        Ok(value)
+    }
+
+    fn at(&self, interpreter: &Interpreter, position: usize) -> Result<Value, Control> {
+        match position.checked_sub(1).and_then(|index| self.values.get(index)) {
+            Some(value) => Ok(value.clone()),
+            None => Err(interpreter.error("ArgumentError", &format!("invalid index - {}$", position))),
+        }
+    }
+
+    fn named(&self, interpreter: &Interpreter, name: &str) -> Result<Value, Control> {
+        let hash = match self.values {
+            [hash @ Value::Hash(_)] => hash,
+            _ => return Err(interpreter.error("ArgumentError", "one hash required")),
+        };
+        hash::get(hash, &Value::symbol(name)).ok_or_else(|| interpreter.error("KeyError", &format!("key<{}> not found", name)))
+    }
+}
+
+/// `format` as in `Kernel#format` and `String#%`.
+pub fn format(interpreter: &mut Interpreter, format: &str, values: &[Value]) -> Result<String, Control> {
+    let mut arguments = Arguments { values, next: 0 };
+    let chars: Vec<char> = format.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '%' {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        let mut spec = Spec::default();
+        let mut value = None;
+        let mut substituted = false;
+        loop {
+            match chars.get(i) {
+                Some('-') => spec.minus = true,
+                Some('+') => spec.plus = true,
+                Some(' ') => spec.space = true,
+                Some('0') => spec.zero = true,
+                Some('#') => spec.alternate = true,
+                Some('<') | Some('{') => {
+                    let close = if chars[i] == '<' { '>' } else { '}' };
+                    let end = chars[i..].iter().position(|c| *c == close).map(|end| end + i)
+                        .ok_or_else(|| interpreter.error("ArgumentError", "malformed name - unmatched parenthesis"))?;
+                    let name: String = chars[i + 1..end].iter().collect();
+                    let named = arguments.named(interpreter, &name)?;
+                    i = end;
+                    if close == '}' {
+                        // `%{name}` is replaced by the value's to_s, with no conversion after it
+                        let text = interpreter.to_s(&named)?;
+                        out += &pad(&text, &spec, false);
+                        substituted = true;
+                        break;
+                    }
+                    value = Some(named);
+                },
+                Some('*') => {
+                    let width = expect_integer(interpreter, &arguments.next(interpreter)?)?;
+                    if width < 0 { spec.minus = true; }
+                    spec.width = Some(width.unsigned_abs() as usize);
+                },
+                Some(c) if c.is_ascii_digit() => {
+                    let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                    i += digits.len();
+                    let number: usize = digits.parse().unwrap_or(usize::MAX);
+                    if chars.get(i) == Some(&'$') {
+                        value = Some(arguments.at(interpreter, number)?);
+                    } else {
+                        spec.width = Some(number);
+                        continue;
+                    }
+                },
+                Some('.') => {
+                    i += 1;
+                    if chars.get(i) == Some(&'*') {
+                        let precision = expect_integer(interpreter, &arguments.next(interpreter)?)?;
+                        spec.precision = usize::try_from(precision).ok();
+                    } else {
+                        let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                        i += digits.len();
+                        spec.precision = Some(digits.parse().unwrap_or(0));
+                        continue;
+                    }
+                },
+                _ => break,
+            }
+            i += 1;
+        }
+        if substituted {
+            i += 1;
+            continue;
+        }
+        let directive = match chars.get(i) {
+            Some(directive) => *directive,
+            None if i == start + 1 => return Err(interpreter.error("ArgumentError", "incomplete format specifier; use %% (double %) instead")),
+            None => return Err(interpreter.error("ArgumentError", "malformed format string - %")),
+        };
+        i += 1;
+        if directive == '%' {
+            out.push('%');
+            continue;
+        }
+        if directive == '\n' || directive == '\0' {
+            out.push('%');
+            out.push(directive);
+            continue;
+        }
+        let value = match value {
+            Some(value) => value,
+            None if "bBdiuoxXeEfgGaAcsp".contains(directive) => arguments.next(interpreter)?,
+            None => return Err(interpreter.error("ArgumentError", &format!("malformed format string - %{}", directive))),
+        };
+        out += &directive_text(interpreter, directive, &value, &spec)?;
+    }
+
+    Ok(out)
+}
+
+fn directive_text(interpreter: &mut Interpreter, directive: char, value: &Value, spec: &Spec) -> Result<String, Control> {
+    Ok(match directive {
+        'd' | 'i' | 'u' => integer_text(&integer_of(interpreter, value)?, 10, spec, ""),
+        'x' => integer_text(&integer_of(interpreter, value)?, 16, spec, "0x"),
+        'X' => integer_text(&integer_of(interpreter, value)?, 16, spec, "0X").to_uppercase(),
+        'o' => integer_text(&integer_of(interpreter, value)?, 8, spec, "0"),
+        'b' => integer_text(&integer_of(interpreter, value)?, 2, spec, "0b"),
+        'B' => integer_text(&integer_of(interpreter, value)?, 2, spec, "0B"),
+        'f' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => float_text(numeric::to_float(interpreter, value)?, directive, spec),
+        'c' => {
+            let c = match value {
+                Value::String(string) => string.text().chars().next().map(String::from).unwrap_or_default(),
+                value => {
+                    let code = expect_integer(interpreter, value)?;
+                    match u32::try_from(code).ok().and_then(char::from_u32) {
+                        Some(c) => c.to_string(),
+                        None => return Err(interpreter.error("RangeError", &format!("{} out of char range", code))),
+                    }
+                },
+            };
+            pad(&c, spec, false)
+        },
+        's' | 'p' => {
+            let text = if directive == 's' { interpreter.to_s(value)? } else { interpreter.inspect(value)? };
+            let text = match spec.precision {
+                Some(precision) => text.chars().take(precision).collect(),
+                None => text,
+            };
+            pad(&text, spec, false)
+        },
+        _ => unreachable!("directives are checked before the argument is taken"),
+    })
+}
+
+fn integer_of(interpreter: &mut Interpreter, value: &Value) -> Result<BigInt, Control> {
+    let integer = numeric::kernel_integer(interpreter, &Value::Nil, slice::from_ref(value), None)?;
+    match integer {
+        Value::Integer(integer) => Ok(BigInt::from_i64(integer)),
+        Value::Bignum(integer) => Ok((*integer).clone()),
+        other => Err(super::conversion_error(interpreter, &other, "Integer")),
+    }
+}
+
+/// Pads `text` to the width, on the left unless `-` was given. Numbers padded with zeros get
+/// them after their sign and prefix, which is `prefix_length` characters long.
+fn pad(text: &str, spec: &Spec, numeric: bool) -> String {
+    pad_after(text, spec, numeric, 0)
+}
+
+fn pad_after(text: &str, spec: &Spec, numeric: bool, prefix_length: usize) -> String {
+    let width = spec.width.unwrap_or(0);
+    let length = text.chars().count();
+    if length >= width { return text.to_string(); }
+    let padding = width - length;
+    if spec.minus {
+        format!("{}{}", text, " ".repeat(padding))
+    } else if spec.zero && numeric {
+        let (prefix, rest) = text.split_at(prefix_length);
+        format!("{}{}{}", prefix, "0".repeat(padding), rest)
+    } else {
+        format!("{}{}", " ".repeat(padding), text)
+    }
+}
+
+/// An integer in `radix`. Without `+` or space, negative numbers in bases other than ten are
+/// shown in two's complement as Ruby does, like `..f01` for `-255` in hex.
+fn integer_text(value: &BigInt, radix: u32, spec: &Spec, prefix: &str) -> String {
+    let complement = value.is_negative() && radix != 10 && !spec.plus && !spec.space;
+    let mut digits = if complement {
+        let base = BigInt::from_i64(i64::from(radix));
+        let width = value.abs().to_string_radix(radix).len() as u64 + 1;
+        let digits = base.pow(width).add(value).to_string_radix(radix);
+        let top = std::char::from_digit(radix - 1, radix).expect("radix is at most 16");
+        format!("{}{}", top, digits.trim_start_matches(top))
+    } else {
+        value.abs().to_string_radix(radix)
+    };
+    if let Some(precision) = spec.precision {
+        if digits.len() < precision {
+            let fill = if complement { std::char::from_digit(radix - 1, radix).unwrap() } else { '0' };
+            digits = format!("{}{}", fill.to_string().repeat(precision - digits.len()), digits);
+        }
+    }
+    let sign = match (value.is_negative(), complement) {
+        (_, true) => "..",
+        (true, false) => "-",
+        (false, _) if spec.plus => "+",
+        (false, _) if spec.space => " ",
+        _ => "",
+    };
+    let prefix = if spec.alternate && !(radix == 8 && digits.starts_with('0')) && !value.is_zero() { prefix } else { "" };
+    let text = format!("{}{}{}", sign, prefix, digits);
+    // a precision turns off zero padding
+    let spec = Spec { zero: spec.zero && spec.precision.is_none(), ..*spec };
+    pad_after(&text, &spec, true, sign.len() + prefix.len())
+}
+
+fn float_text(value: f64, directive: char, spec: &Spec) -> String {
+    let sign = if value.is_sign_negative() && !value.is_nan() {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    };
+    if !value.is_finite() {
+        let text = format!("{}{}", sign, if value.is_nan() { "NaN" } else { "Inf" });
+        return pad(&text, spec, false);
+    }
+    let magnitude = value.abs();
+    let precision = spec.precision.unwrap_or(6);
+    let body = match directive {
+        'f' => {
+            let body = format!("{:.*}", precision, magnitude);
+            if spec.alternate && precision == 0 { body + "." } else { body }
+        },
+        'e' | 'E' => exponent_form(magnitude, precision, spec.alternate),
+        'a' | 'A' => hex_float(magnitude),
+        _ => {
+            // %g picks whichever of %e and %f is shorter for the number of significant digits
+            let precision = precision.max(1);
+            let exponent = if magnitude == 0.0 { 0 } else { exponent_of(magnitude, precision) };
+            let body = if exponent < -4 || exponent >= precision as i64 {
+                exponent_form(magnitude, precision - 1, spec.alternate)
+            } else {
+                format!("{:.*}", (precision as i64 - 1 - exponent).max(0) as usize, magnitude)
+            };
+            if spec.alternate { body } else { strip_fraction_zeros(&body) }
+        },
+    };
+    let body = if directive.is_ascii_uppercase() { body.to_uppercase() } else { body };
+    pad_after(&format!("{}{}", sign, body), spec, true, sign.len())
+}
+
+/// The decimal exponent of `value` once rounded to `precision` significant digits.
+fn exponent_of(value: f64, precision: usize) -> i64 {
+    let scientific = format!("{:.*e}", precision - 1, value);
+    scientific.split_once('e').and_then(|(_, exponent)| exponent.parse().ok()).unwrap_or(0)
+}
+
+/// `1.500000e+02`: C's exponent form, with a sign and at least two exponent digits.
+fn exponent_form(value: f64, precision: usize, alternate: bool) -> String {
+    let scientific = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = scientific.split_once('e').expect("LowerExp always has an exponent");
+    let exponent: i64 = exponent.parse().expect("LowerExp exponents are integers");
+    let point = if alternate && precision == 0 { "." } else { "" };
+    format!("{}{}e{}{:02}", mantissa, point, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+fn strip_fraction_zeros(body: &str) -> String {
+    let (number, exponent) = match body.find('e') {
+        Some(index) => body.split_at(index),
+        None => (body, ""),
+    };
+    let number = if number.contains('.') { number.trim_end_matches('0').trim_end_matches('.') } else { number };
+    format!("{}{}", number, exponent)
+}
+
+/// `%a`: the significand in hex and a binary exponent, like `0x1.8p+1` for 3.0.
+fn hex_float(value: f64) -> String {
+    if value == 0.0 { return String::from("0x0p+0"); }
+    let bits = value.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+    let (lead, exponent) = if biased == 0 { (0, -1022) } else { (1, biased - 1023) };
+    let fraction = format!("{:013x}", bits & ((1 << 52) - 1));
+    let fraction = fraction.trim_end_matches('0');
+    let point = if fraction.is_empty() { "" } else { "." };
+    format!("0x{}{}{}p{}{}", lead, point, fraction, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
@@ -0,0 +1,846 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{ Hash, Hasher };
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, check_frozen, conversion_error, eql, equals, string, symbol };
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::value::{ RHash, RProc, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let hash = interpreter.classes.hash.clone();
+    let metaclass = interpreter.metaclass(&hash);
+    metaclass.define_builtin("new", new);
+    metaclass.define_builtin("[]", from_pairs);
+
+    hash.define_builtin("[]", index);
+    hash.define_builtin("[]=", set_index);
+    hash.define_builtin("store", set_index);
+    hash.define_builtin("fetch", fetch);
+    hash.define_builtin("dig", dig);
+    hash.define_builtin("values_at", values_at);
+    hash.define_builtin("fetch_values", fetch_values);
+    hash.define_builtin("default", default);
+    hash.define_builtin("default=", set_default);
+    hash.define_builtin("default_proc", |_, r, _, _| Ok(rhash(r).default_proc.borrow().clone().map_or(Value::Nil, Value::Proc)));
+    hash.define_builtin("length", length);
+    hash.define_builtin("size", length);
+    hash.define_builtin("empty?", |_, r, _, _| Ok(Value::Bool(rhash(r).entries.borrow().is_empty())));
+    hash.define_builtin("key?", has_key);
+    hash.define_builtin("has_key?", has_key);
+    hash.define_builtin("include?", has_key);
+    hash.define_builtin("member?", has_key);
+    hash.define_builtin("value?", has_value);
+    hash.define_builtin("has_value?", has_value);
+    hash.define_builtin("key", key);
+    hash.define_builtin("keys", |_, r, _, _| Ok(Value::array(entries(r).into_iter().map(|(k, _)| k).collect())));
+    hash.define_builtin("values", |_, r, _, _| Ok(Value::array(entries(r).into_iter().map(|(_, v)| v).collect())));
+    hash.define_builtin("assoc", assoc);
+    hash.define_builtin("rassoc", rassoc);
+    hash.define_builtin("each", each);
+    hash.define_builtin("each_pair", each);
+    hash.define_builtin("each_key", |i, r, a, b| each_one(i, r, a, b, true));
+    hash.define_builtin("each_value", |i, r, a, b| each_one(i, r, a, b, false));
+    hash.define_builtin("delete", delete);
+    hash.define_builtin("delete_if", |i, r, a, b| remove_if(i, r, a, b, true, false));
+    hash.define_builtin("reject!", |i, r, a, b| remove_if(i, r, a, b, true, true));
+    hash.define_builtin("keep_if", |i, r, a, b| remove_if(i, r, a, b, false, false));
+    hash.define_builtin("select!", |i, r, a, b| remove_if(i, r, a, b, false, true));
+    hash.define_builtin("filter!", |i, r, a, b| remove_if(i, r, a, b, false, true));
+    hash.define_builtin("select", |i, r, a, b| filter(i, r, a, b, true));
+    hash.define_builtin("filter", |i, r, a, b| filter(i, r, a, b, true));
+    hash.define_builtin("reject", |i, r, a, b| filter(i, r, a, b, false));
+    hash.define_builtin("compact", compact);
+    hash.define_builtin("slice", slice);
+    hash.define_builtin("except", except);
+    hash.define_builtin("transform_values", |i, r, a, b| transform_values(i, r, a, b, false));
+    hash.define_builtin("transform_values!", |i, r, a, b| transform_values(i, r, a, b, true));
+    hash.define_builtin("transform_keys", |i, r, a, b| transform_keys(i, r, a, b, false));
+    hash.define_builtin("transform_keys!", |i, r, a, b| transform_keys(i, r, a, b, true));
+    hash.define_builtin("invert", invert);
+    hash.define_builtin("merge", merge);
+    hash.define_builtin("update", update);
+    hash.define_builtin("merge!", update);
+    hash.define_builtin("replace", replace);
+    hash.define_builtin("clear", clear);
+    hash.define_builtin("shift", shift);
+    hash.define_builtin("to_a", |_, r, _, _| Ok(Value::array(pairs(r))));
+    hash.define_builtin("to_h", to_h);
+    hash.define_builtin("compare_by_identity", compare_by_identity);
+    hash.define_builtin("compare_by_identity?", |_, r, _, _| Ok(Value::Bool(rhash(r).identity.get())));
+    hash.define_builtin("rehash", rehash);
+    hash.define_builtin("==", |i, r, a, _| equal(i, r, a, false));
+    hash.define_builtin("eql?", |i, r, a, _| equal(i, r, a, true));
+    hash.define_builtin("hash", hash_code);
+    hash.define_builtin("dup", |_, r, _, _| Ok(copy(r)));
+    hash.define_builtin("inspect", inspect);
+    hash.define_builtin("to_s", inspect);
+}
+
+fn rhash(value: &Value) -> Rc<RHash> {
+    match value {
+        Value::Hash(hash) => hash.clone(),
+        _ => unreachable!("a Hash method called on {:?}", value),
+    }
+}
+
+/// A copy of the entries, so calls made while going through them can't change them.
+pub fn entries(value: &Value) -> Vec<(Value, Value)> {
+    rhash(value).entries.borrow().clone()
+}
+
+/// The entries as `[key, value]` arrays, which is what `each` yields.
+fn pairs(value: &Value) -> Vec<Value> {
+    entries(value).into_iter().map(|(k, v)| Value::array(vec!(k, v))).collect()
+}
+
+/// The code `key` is filed under. The builtin types are hashed here, other objects by their
+/// `hash` method, and every key by its identity after `compare_by_identity`.
+fn key_hash(interpreter: &mut Interpreter, identity: bool, key: &Value) -> Result<i64, Control> {
+    let mut hasher = DefaultHasher::new();
+    match key {
+        _ if identity => return Ok(key.address() as i64),
+        Value::Integer(value) => value.hash(&mut hasher),
+        // `0.0.eql?(-0.0)`, so both have to hash the same
+        Value::Float(value) => (if *value == 0.0 { 0.0f64 } else { *value }).to_bits().hash(&mut hasher),
+        Value::Bignum(value) => value.hash(&mut hasher),
+        Value::String(string) => string.bytes.borrow().hash(&mut hasher),
+        Value::Symbol(name) => name.hash(&mut hasher),
+        Value::Array(array) => {
+            let elements = array.elements.borrow().clone();
+            elements.len().hash(&mut hasher);
+            interpreter.guard_recursion("hash", key, |interpreter| {
+                for element in elements.iter() {
+                    key_hash(interpreter, false, element)?.hash(&mut hasher);
+                }
+                Ok(())
+            })?;
+        },
+        Value::Range(range) => {
+            key_hash(interpreter, false, &range.start)?.hash(&mut hasher);
+            key_hash(interpreter, false, &range.end)?.hash(&mut hasher);
+            range.exclusive.hash(&mut hasher);
+        },
+        Value::Object(_) | Value::Hash(_) => return method_hash(interpreter, key),
+        _ => return Ok(key.address() as i64),
+    }
+
+    Ok(hasher.finish() as i64)
+}
+
+/// What the object's `hash` method returns, which has to be an `Integer`.
+fn method_hash(interpreter: &mut Interpreter, value: &Value) -> Result<i64, Control> {
+    match interpreter.call_method(value, "hash", &[], None)? {
+        Value::Integer(code) => Ok(code),
+        Value::Bignum(code) => {
+            let mut hasher = DefaultHasher::new();
+            code.hash(&mut hasher);
+            Ok(hasher.finish() as i64)
+        },
+        other => Err(conversion_error(interpreter, &other, "Integer")),
+    }
+}
+
+/// Whether a stored key and the key looked up are the same key: `eql?`, calling the method for
+/// objects of classes defined in Ruby.
+fn same_key(interpreter: &mut Interpreter, identity: bool, stored: &Value, key: &Value) -> Result<bool, Control> {
+    if identity || stored.is_identical(key) { return Ok(stored.is_identical(key)); }
+
+    match (stored, key) {
+        (Value::Array(a), Value::Array(b)) => {
+            let (a, b) = (a.elements.borrow().clone(), b.elements.borrow().clone());
+            if a.len() != b.len() { return Ok(false); }
+            for (a, b) in a.iter().zip(b.iter()) {
+                if !same_key(interpreter, false, a, b)? { return Ok(false); }
+            }
+            Ok(true)
+        },
+        (Value::Object(_), _) | (Value::Hash(_), _) => Ok(interpreter.call_method(stored, "eql?", slice::from_ref(key), None)?.is_truthy()),
+        (stored, key) => Ok(eql(stored, key)),
+    }
+}
+
+/// The code `key` is filed under, and the position of its entry if it has one.
+fn locate(interpreter: &mut Interpreter, hash: &RHash, key: &Value) -> Result<(i64, Option<usize>), Control> {
+    let identity = hash.identity.get();
+    let code = key_hash(interpreter, identity, key)?;
+    let positions = hash.buckets.borrow().get(&code).cloned().unwrap_or_default();
+    for position in positions.into_iter() {
+        let stored = match hash.entries.borrow().get(position) {
+            Some((stored, _)) => stored.clone(),
+            None => continue,
+        };
+        if same_key(interpreter, identity, &stored, key)? { return Ok((code, Some(position))); }
+    }
+
+    Ok((code, None))
+}
+
+/// The value stored under `key`, without falling back on the default.
+pub fn lookup(interpreter: &mut Interpreter, receiver: &Value, key: &Value) -> Result<Option<Value>, Control> {
+    let hash = rhash(receiver);
+    let (_, position) = locate(interpreter, &hash, key)?;
+    let value = position.and_then(|position| hash.entries.borrow().get(position).map(|(_, value)| value.clone()));
+    Ok(value)
+}
+
+/// The value under a key of a builtin type, like the symbol of a keyword option, found
+/// without calling any methods.
+pub fn get(receiver: &Value, key: &Value) -> Option<Value> {
+    rhash(receiver).entries.borrow().iter().find(|(k, _)| eql(k, key)).map(|(_, v)| v.clone())
+}
+
+/// `hash[key]`: the stored value, else what the default proc makes of the key, else the
+/// default value.
+pub fn value_of(interpreter: &mut Interpreter, receiver: &Value, key: &Value) -> EvalResult {
+    match lookup(interpreter, receiver, key)? {
+        Some(value) => Ok(value),
+        None => default_for(interpreter, receiver, key),
+    }
+}
+
+fn default_for(interpreter: &mut Interpreter, receiver: &Value, key: &Value) -> EvalResult {
+    let hash = rhash(receiver);
+    let default_proc = hash.default_proc.borrow().clone();
+    match default_proc {
+        Some(default_proc) => interpreter.call_proc(&default_proc, &[receiver.clone(), key.clone()]),
+        None => Ok(hash.default.borrow().clone()),
+    }
+}
+
+/// Stores `value` under `key`, keeping the position of an existing key. An unfrozen string
+/// key is copied and frozen, so changing the original string later doesn't change the key.
+pub fn insert(interpreter: &mut Interpreter, receiver: &Value, key: Value, value: Value) -> Result<(), Control> {
+    let hash = rhash(receiver);
+    let (code, position) = locate(interpreter, &hash, &key)?;
+    if let Some(position) = position {
+        hash.entries.borrow_mut()[position].1 = value;
+        return Ok(());
+    }
+    if hash.iterating.get() > 0 {
+        return Err(interpreter.error("RuntimeError", "can't add a new key into hash during iteration"));
+    }
+
+    let key = match key {
+        Value::String(string) if !string.frozen.get() && !hash.identity.get() => {
+            let copy = Value::bytes(string.bytes.borrow().clone(), string.encoding.get());
+            if let Value::String(copy) = &copy { copy.frozen.set(true); }
+            copy
+        },
+        key => key,
+    };
+    let mut entries = hash.entries.borrow_mut();
+    hash.buckets.borrow_mut().entry(code).or_default().push(entries.len());
+    entries.push((key, value));
+    Ok(())
+}
+
+/// Takes the entry for `key` out of the hash, giving its value.
+fn remove(interpreter: &mut Interpreter, receiver: &Value, key: &Value) -> Result<Option<Value>, Control> {
+    let hash = rhash(receiver);
+    let (code, position) = locate(interpreter, &hash, key)?;
+    Ok(position.map(|position| remove_at(&hash, code, position).1))
+}
+
+fn remove_at(hash: &RHash, code: i64, position: usize) -> (Value, Value) {
+    let entry = hash.entries.borrow_mut().remove(position);
+    let mut buckets = hash.buckets.borrow_mut();
+    if let Some(bucket) = buckets.get_mut(&code) {
+        bucket.retain(|stored| *stored != position);
+    }
+    buckets.retain(|_, bucket| !bucket.is_empty());
+    for stored in buckets.values_mut().flat_map(|bucket| bucket.iter_mut()) {
+        if *stored > position { *stored -= 1; }
+    }
+
+    entry
+}
+
+/// Files every entry again, after its key changed or the way keys are compared did.
+fn rebuild(interpreter: &mut Interpreter, receiver: &Value) -> Result<(), Control> {
+    let hash = rhash(receiver);
+    let entries = hash.entries.replace(vec!());
+    hash.buckets.borrow_mut().clear();
+    for (key, value) in entries.into_iter() {
+        insert(interpreter, receiver, key, value)?;
+    }
+    Ok(())
+}
+
+/// Calls `f` with each key and value until it returns `false`. No key can be added meanwhile,
+/// but keys can be deleted, and an entry deleted before its turn is skipped.
+pub fn each_pair(
+    interpreter: &mut Interpreter,
+    receiver: &Value,
+    mut f: impl FnMut(&mut Interpreter, Value, Value) -> Result<bool, Control>,
+) -> Result<(), Control> {
+    let hash = rhash(receiver);
+    hash.iterating.set(hash.iterating.get() + 1);
+    let result = walk(interpreter, &hash, &mut f);
+    hash.iterating.set(hash.iterating.get() - 1);
+    result
+}
+
+fn walk(
+    interpreter: &mut Interpreter,
+    hash: &RHash,
+    f: &mut impl FnMut(&mut Interpreter, Value, Value) -> Result<bool, Control>,
+) -> Result<(), Control> {
+    let mut position = 0;
+    loop {
+        let (key, value) = match hash.entries.borrow().get(position) {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+        if !f(interpreter, key.clone(), value)? { return Ok(()); }
+
+        // deleting entries moves the later ones down, so go on after wherever the key is now
+        let entries = hash.entries.borrow();
+        position = match entries.get(position) {
+            Some((stored, _)) if stored.is_identical(&key) => position + 1,
+            _ => entries.iter().position(|(stored, _)| stored.is_identical(&key)).map_or(position, |found| found + 1),
+        };
+    }
+}
+
+/// A new hash with the same entries, defaults and way of comparing keys.
+fn copy(receiver: &Value) -> Value {
+    let (hash, copy) = (rhash(receiver), empty_like(receiver));
+    let table = rhash(&copy);
+    *table.entries.borrow_mut() = hash.entries.borrow().clone();
+    *table.buckets.borrow_mut() = hash.buckets.borrow().clone();
+    *table.default.borrow_mut() = hash.default.borrow().clone();
+    *table.default_proc.borrow_mut() = hash.default_proc.borrow().clone();
+    copy
+}
+
+/// A new empty hash comparing keys the way the receiver does.
+fn empty_like(receiver: &Value) -> Value {
+    let empty = Value::hash();
+    rhash(&empty).identity.set(rhash(receiver).identity.get());
+    empty
+}
+
+fn expect_hash(interpreter: &Interpreter, value: &Value) -> Result<(), Control> {
+    match value {
+        Value::Hash(_) => Ok(()),
+        value => Err(conversion_error(interpreter, value, "Hash")),
+    }
+}
+
+/// The `KeyError` for a key that isn't there.
+fn missing_key(interpreter: &mut Interpreter, key: &Value) -> EvalResult {
+    let message = format!("key not found: {}", interpreter.inspect(key)?);
+    Err(interpreter.error("KeyError", &message))
+}
+
+/// `Hash.new`, `Hash.new(default)` or `Hash.new { |hash, key| ... }`.
+fn new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, if block.is_some() { 0 } else { 1 })?;
+    let hash = Value::hash();
+    let table = rhash(&hash);
+    if let Some(default) = args.first() { *table.default.borrow_mut() = default.clone(); }
+    *table.default_proc.borrow_mut() = block.cloned();
+    Ok(hash)
+}
+
+/// `Hash[other_hash]`, `Hash[[[key, value], ...]]` or `Hash[key, value, ...]`.
+fn from_pairs(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let hash = Value::hash();
+    match args {
+        [other @ Value::Hash(_)] => {
+            for (key, value) in entries(other).into_iter() {
+                insert(interpreter, &hash, key, value)?;
+            }
+        },
+        [Value::Array(pairs)] => {
+            let pairs = pairs.elements.borrow().clone();
+            for (index, pair) in pairs.iter().enumerate() {
+                match pair {
+                    Value::Array(pair) if matches!(pair.elements.borrow().len(), 1 | 2) => {
+                        let pair = pair.elements.borrow().clone();
+                        insert(interpreter, &hash, pair[0].clone(), pair.get(1).cloned().unwrap_or(Value::Nil))?;
+                    },
+                    Value::Array(pair) => {
+                        let message = format!("invalid number of elements ({} for 1..2)", pair.elements.borrow().len());
+                        return Err(interpreter.error("ArgumentError", &message));
+                    },
+                    other => {
+                        let message = format!("wrong element type {} at {} (expected array)", interpreter.class_of(other).inspect(), index);
+                        return Err(interpreter.error("ArgumentError", &message));
+                    }
+                }
+            }
+        },
+        args if args.len() % 2 == 1 => return Err(interpreter.error("ArgumentError", "odd number of arguments for Hash")),
+        args => {
+            for pair in args.chunks(2) {
+                insert(interpreter, &hash, pair[0].clone(), pair[1].clone())?;
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+fn index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    value_of(interpreter, receiver, &args[0])
+}
+
+fn set_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 2, 2)?;
+    insert(interpreter, receiver, args[0].clone(), args[1].clone())?;
+    Ok(args[1].clone())
+}
+
+/// The value for the key, else the block's result for the key, else the default given, else
+/// a `KeyError`. The hash's own default isn't used.
+fn fetch(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    if let Some(value) = lookup(interpreter, receiver, &args[0])? { return Ok(value); }
+
+    match (block, args.get(1)) {
+        (Some(block), _) => interpreter.call_proc(block, &args[..1]),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => missing_key(interpreter, &args[0]),
+    }
+}
+
+fn dig(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if args.is_empty() { return Err(interpreter.error("ArgumentError", "wrong number of arguments (given 0, expected 1+)")); }
+    let found = value_of(interpreter, receiver, &args[0])?;
+    if args.len() == 1 || found.is_nil() { return Ok(found); }
+    if interpreter.lookup_class(&found).find_method("dig").is_none() {
+        let message = format!("{} does not have #dig method", interpreter.class_of(&found).inspect());
+        return Err(interpreter.error("TypeError", &message));
+    }
+
+    interpreter.call_method(&found, "dig", &args[1..], None)
+}
+
+fn values_at(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut values = vec!();
+    for key in args.iter() {
+        values.push(value_of(interpreter, receiver, key)?);
+    }
+    Ok(Value::array(values))
+}
+
+fn fetch_values(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut values = vec!();
+    for key in args.iter() {
+        values.push(match (lookup(interpreter, receiver, key)?, block) {
+            (Some(value), _) => value,
+            (None, Some(block)) => interpreter.call_proc(block, slice::from_ref(key))?,
+            (None, None) => return missing_key(interpreter, key),
+        });
+    }
+    Ok(Value::array(values))
+}
+
+/// The default value, or with a key, what `hash[key]` gives when the key isn't there.
+fn default(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    match args.first() {
+        Some(key) => default_for(interpreter, receiver, key),
+        None => Ok(rhash(receiver).default.borrow().clone()),
+    }
+}
+
+fn set_default(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 1)?;
+    let hash = rhash(receiver);
+    *hash.default.borrow_mut() = args[0].clone();
+    *hash.default_proc.borrow_mut() = None;
+    Ok(args[0].clone())
+}
+
+fn length(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Integer(rhash(receiver).entries.borrow().len() as i64))
+}
+
+fn has_key(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(lookup(interpreter, receiver, &args[0])?.is_some()))
+}
+
+fn has_value(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    for (_, value) in entries(receiver).iter() {
+        if equals(interpreter, value, &args[0])? { return Ok(Value::Bool(true)); }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// The first key whose value is `==` to the argument.
+fn key(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    for (key, value) in entries(receiver).into_iter() {
+        if equals(interpreter, &value, &args[0])? { return Ok(key); }
+    }
+    Ok(Value::Nil)
+}
+
+fn assoc(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let hash = rhash(receiver);
+    let (_, position) = locate(interpreter, &hash, &args[0])?;
+    let entry = position.and_then(|position| hash.entries.borrow().get(position).cloned());
+    Ok(entry.map_or(Value::Nil, |(key, value)| Value::array(vec!(key, value))))
+}
+
+fn rassoc(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    for (key, value) in entries(receiver).into_iter() {
+        if equals(interpreter, &value, &args[0])? { return Ok(Value::array(vec!(key, value))); }
+    }
+    Ok(Value::Nil)
+}
+
+/// Yields each entry as a `[key, value]` pair, which a block with two parameters takes apart.
+fn each(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each", args)),
+    };
+    each_pair(interpreter, receiver, |interpreter, key, value| {
+        interpreter.call_proc(block, &[Value::array(vec!(key, value))])?;
+        Ok(true)
+    })?;
+    Ok(receiver.clone())
+}
+
+fn each_one(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, keys: bool) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, if keys { "each_key" } else { "each_value" }, args)),
+    };
+    each_pair(interpreter, receiver, |interpreter, key, value| {
+        interpreter.call_proc(block, &[if keys { key } else { value }])?;
+        Ok(true)
+    })?;
+    Ok(receiver.clone())
+}
+
+/// Removes the entry for the key and gives its value, or gives the block's result for the key
+/// when there isn't one.
+fn delete(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 1)?;
+    match (remove(interpreter, receiver, &args[0])?, block) {
+        (Some(value), _) => Ok(value),
+        (None, Some(block)) => interpreter.call_proc(block, &args[..1]),
+        (None, None) => Ok(Value::Nil),
+    }
+}
+
+/// Deletes the entries for which the block is `remove`, yielding keys and values as two
+/// values. The bang methods give `nil` when nothing was deleted.
+fn remove_if(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, remove: bool, bang: bool) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => {
+            let method = match (remove, bang) {
+                (true, false) => "delete_if",
+                (true, true) => "reject!",
+                (false, false) => "keep_if",
+                (false, true) => "select!",
+            };
+            return Ok(enumerator_for(interpreter, receiver, method, args));
+        },
+    };
+    let mut changed = false;
+    each_pair(interpreter, receiver, |interpreter, key, value| {
+        if interpreter.call_proc(block, &[key.clone(), value])?.is_truthy() == remove {
+            self::remove(interpreter, receiver, &key)?;
+            changed = true;
+        }
+        Ok(true)
+    })?;
+
+    Ok(if bang && !changed { Value::Nil } else { receiver.clone() })
+}
+
+/// `select` and `reject`, which make a hash rather than an array of pairs.
+fn filter(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, keep: bool) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, if keep { "select" } else { "reject" }, args)),
+    };
+    let filtered = empty_like(receiver);
+    let mut kept = vec!();
+    each_pair(interpreter, receiver, |interpreter, key, value| {
+        if interpreter.call_proc(block, &[key.clone(), value.clone()])?.is_truthy() == keep {
+            kept.push((key, value));
+        }
+        Ok(true)
+    })?;
+    for (key, value) in kept.into_iter() {
+        insert(interpreter, &filtered, key, value)?;
+    }
+
+    Ok(filtered)
+}
+
+fn compact(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let compacted = empty_like(receiver);
+    for (key, value) in entries(receiver).into_iter().filter(|(_, value)| !value.is_nil()) {
+        insert(interpreter, &compacted, key, value)?;
+    }
+    Ok(compacted)
+}
+
+/// The entries for the given keys that are there, in the order of the keys.
+fn slice(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let sliced = empty_like(receiver);
+    for key in args.iter() {
+        if let Some(value) = lookup(interpreter, receiver, key)? {
+            insert(interpreter, &sliced, key.clone(), value)?;
+        }
+    }
+    Ok(sliced)
+}
+
+fn except(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let rest = copy(receiver);
+    for key in args.iter() {
+        remove(interpreter, &rest, key)?;
+    }
+    Ok(rest)
+}
+
+fn transform_values(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, bang: bool) -> EvalResult {
+    if bang { check_frozen(interpreter, receiver)?; }
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, if bang { "transform_values!" } else { "transform_values" }, args)),
+    };
+    let transformed = if bang { receiver.clone() } else { empty_like(receiver) };
+    for (key, value) in entries(receiver).into_iter() {
+        let value = interpreter.call_proc(block, &[value])?;
+        insert(interpreter, &transformed, key, value)?;
+    }
+    Ok(transformed)
+}
+
+/// New keys from a hash mapping old keys to new ones, else from the block, else the old key.
+fn transform_keys(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, bang: bool) -> EvalResult {
+    if bang { check_frozen(interpreter, receiver)?; }
+    check_arity(interpreter, args, 0, 1)?;
+    if let Some(mapping) = args.first() { expect_hash(interpreter, mapping)?; }
+    if args.is_empty() && block.is_none() {
+        return Ok(enumerator_for(interpreter, receiver, if bang { "transform_keys!" } else { "transform_keys" }, args));
+    }
+
+    let transformed = empty_like(receiver);
+    for (key, value) in entries(receiver).into_iter() {
+        let mapped = match args.first() {
+            Some(mapping) => lookup(interpreter, mapping, &key)?,
+            None => None,
+        };
+        let key = match (mapped, block) {
+            (Some(mapped), _) => mapped,
+            (None, Some(block)) => interpreter.call_proc(block, &[key])?,
+            (None, None) => key,
+        };
+        insert(interpreter, &transformed, key, value)?;
+    }
+    if !bang { return Ok(transformed); }
+
+    let hash = rhash(receiver);
+    *hash.entries.borrow_mut() = entries(&transformed);
+    *hash.buckets.borrow_mut() = rhash(&transformed).buckets.borrow().clone();
+    Ok(receiver.clone())
+}
+
+fn invert(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let inverted = Value::hash();
+    for (key, value) in entries(receiver).into_iter() {
+        insert(interpreter, &inverted, value, key)?;
+    }
+    Ok(inverted)
+}
+
+fn merge(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    update(interpreter, &copy(receiver), args, block)
+}
+
+/// Stores the entries of each argument, calling the block with the key and both values to
+/// settle a key that is already there.
+fn update(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    for other in args.iter() {
+        expect_hash(interpreter, other)?;
+        for (key, value) in entries(other).into_iter() {
+            let value = match (block, lookup(interpreter, receiver, &key)?) {
+                (Some(block), Some(old)) => interpreter.call_proc(block, &[key.clone(), old, value])?,
+                _ => value,
+            };
+            insert(interpreter, receiver, key, value)?;
+        }
+    }
+    Ok(receiver.clone())
+}
+
+fn replace(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    check_arity(interpreter, args, 1, 1)?;
+    expect_hash(interpreter, &args[0])?;
+    let (hash, other) = (rhash(receiver), rhash(&args[0]));
+    let entries = entries(&args[0]);
+    clear(interpreter, receiver, &[], None)?;
+    for (key, value) in entries.into_iter() {
+        insert(interpreter, receiver, key, value)?;
+    }
+    *hash.default.borrow_mut() = other.default.borrow().clone();
+    *hash.default_proc.borrow_mut() = other.default_proc.borrow().clone();
+    Ok(receiver.clone())
+}
+
+fn clear(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    let hash = rhash(receiver);
+    hash.entries.borrow_mut().clear();
+    hash.buckets.borrow_mut().clear();
+    Ok(receiver.clone())
+}
+
+/// Removes the first entry and gives it as a `[key, value]` pair.
+fn shift(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    let first = rhash(receiver).entries.borrow().first().map(|(key, _)| key.clone());
+    let key = match first {
+        Some(key) => key,
+        None => return Ok(Value::Nil),
+    };
+    let value = remove(interpreter, receiver, &key)?.unwrap_or(Value::Nil);
+    Ok(Value::array(vec!(key, value)))
+}
+
+/// The hash itself, or a new one from the `[key, value]` pairs the block makes of the entries.
+fn to_h(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(receiver.clone()),
+    };
+
+    let hash = Value::hash();
+    for (key, value) in entries(receiver).into_iter() {
+        let pair = interpreter.call_proc(block, &[key, value])?;
+        let pair = match &pair {
+            Value::Array(pair) => pair.elements.borrow().clone(),
+            other => {
+                let message = format!("wrong element type {} (expected array)", interpreter.class_of(other).inspect());
+                return Err(interpreter.error("TypeError", &message));
+            }
+        };
+        if pair.len() != 2 {
+            let message = format!("element has wrong array length (expected 2, was {})", pair.len());
+            return Err(interpreter.error("ArgumentError", &message));
+        }
+        insert(interpreter, &hash, pair[0].clone(), pair[1].clone())?;
+    }
+
+    Ok(hash)
+}
+
+fn compare_by_identity(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    let hash = rhash(receiver);
+    if hash.iterating.get() > 0 { return Err(interpreter.error("RuntimeError", "compare_by_identity during iteration")); }
+    hash.identity.set(true);
+    rebuild(interpreter, receiver)?;
+    Ok(receiver.clone())
+}
+
+fn rehash(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_frozen(interpreter, receiver)?;
+    if rhash(receiver).iterating.get() > 0 { return Err(interpreter.error("RuntimeError", "rehash during iteration")); }
+    rebuild(interpreter, receiver)?;
+    Ok(receiver.clone())
+}
+
+/// Hashes are equal when they have the same keys with `==` values, in any order. `eql?`
+/// wants `eql?` values.
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], strict: bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if receiver.is_identical(&args[0]) { return Ok(Value::Bool(true)); }
+    if !matches!(args[0], Value::Hash(_)) { return Ok(Value::Bool(false)); }
+
+    let (a, b) = (entries(receiver), entries(&args[0]));
+    if a.len() != b.len() { return Ok(Value::Bool(false)); }
+    let equal = interpreter.guard_recursion("==", receiver, |interpreter| {
+        for (key, value) in a.iter() {
+            let other = match lookup(interpreter, &args[0], key)? {
+                Some(other) => other,
+                None => return Ok(false),
+            };
+            let same = match strict {
+                true => interpreter.call_method(value, "eql?", slice::from_ref(&other), None)?.is_truthy(),
+                false => equals(interpreter, value, &other)?,
+            };
+            if !same { return Ok(false); }
+        }
+        Ok(true)
+    })?;
+
+    Ok(Value::Bool(equal.unwrap_or(true)))
+}
+
+/// Adds up a code for each entry, so hashes with the same entries in another order hash the
+/// same.
+fn hash_code(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let entries = entries(receiver);
+    let mut code = entries.len() as i64;
+    interpreter.guard_recursion("hash", receiver, |interpreter| {
+        for (key, value) in entries.iter() {
+            let mut hasher = DefaultHasher::new();
+            method_hash(interpreter, key)?.hash(&mut hasher);
+            method_hash(interpreter, value)?.hash(&mut hasher);
+            code = code.wrapping_add(hasher.finish() as i64);
+        }
+        Ok(())
+    })?;
+
+    Ok(Value::Integer(code))
+}
+
+/// `{name: "rri", "key" => 1}`: symbol keys that can be labels are written as labels. A hash
+/// inside itself is `{...}`.
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let inspected = interpreter.guard_recursion("inspect", receiver, |interpreter| {
+        let mut pairs = vec!();
+        for (key, value) in entries(receiver).iter() {
+            let key = key_prefix(interpreter, key)?;
+            pairs.push(format!("{}{}", key, interpreter.inspect(value)?));
+        }
+        Ok(format!("{{{}}}", pairs.join(", ")))
+    })?;
+
+    Ok(Value::string(&inspected.unwrap_or_else(|| String::from("{...}"))))
+}
+
+/// What an inspected hash writes before the value of a key: `name: `, or `key => `.
+pub fn key_prefix(interpreter: &mut Interpreter, key: &Value) -> Result<String, Control> {
+    match key {
+        Value::Symbol(name) if is_label(name) => Ok(format!("{}: ", name)),
+        Value::Symbol(name) => Ok(format!("{}: ", string::inspect(name))),
+        key => Ok(format!("{} => ", interpreter.inspect(key)?)),
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    symbol::is_plain(name) && !name.ends_with('=') && name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+}
@@ -0,0 +1,88 @@
+use std::rc::Rc;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, expect_string, format };
+use crate::eval::builtins::kernel::{ self, Stream };
+use crate::eval::class::RClass;
+use crate::eval::value::{ RProc, Value };
+
+const STREAMS: &[(Stream, &str, &str)] = &[
+    (Stream::Output, "STDOUT", "$stdout"),
+    (Stream::Error, "STDERR", "$stderr"),
+];
+
+/// `IO`, with an object for each stream the interpreter writes to: `STDOUT`, which is also
+/// `$stdout`, and `STDERR`, which is also `$stderr`.
+pub fn define(interpreter: &mut Interpreter) {
+    let object = interpreter.classes.object.clone();
+    let class = RClass::new("IO", Some(object));
+    interpreter.set_constant("IO", Value::Class(class.clone()));
+    for (stream, constant, global) in STREAMS.iter() {
+        let io = Value::object(class.clone());
+        if let Value::Object(object) = &io {
+            object.set("fileno", Value::Integer(stream.fileno()));
+        }
+        interpreter.set_constant(constant, io.clone());
+        interpreter.set_global(global, io);
+    }
+
+    class.define_builtin("puts", |i, r, a, _| {
+        let out = kernel::puts_bytes(i, a)?;
+        kernel::write_to(i, stream(i, r), &out)?;
+        Ok(Value::Nil)
+    });
+    class.define_builtin("print", |i, r, a, _| {
+        let out = kernel::print_bytes(i, a)?;
+        kernel::write_to(i, stream(i, r), &out)?;
+        Ok(Value::Nil)
+    });
+    class.define_builtin("write", write);
+    class.define_builtin("<<", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let out = kernel::bytes_of(i, &a[0])?;
+        kernel::write_to(i, stream(i, r), &out)?;
+        Ok(r.clone())
+    });
+    class.define_builtin("printf", printf);
+    class.define_builtin("flush", |i, r, _, _| {
+        kernel::flush(i)?;
+        Ok(r.clone())
+    });
+    class.define_builtin("sync", |_, _, _, _| Ok(Value::Bool(true)));
+    class.define_builtin("sync=", |i, _, a, _| {
+        check_arity(i, a, 1, 1)?;
+        Ok(a[0].clone())
+    });
+    class.define_builtin("fileno", |i, r, _, _| Ok(i.instance_variable(r, "fileno")));
+    class.define_builtin("inspect", inspect);
+}
+
+/// The stream an `IO` writes to.
+fn stream(interpreter: &Interpreter, io: &Value) -> Stream {
+    match interpreter.instance_variable(io, "fileno") {
+        Value::Integer(2) => Stream::Error,
+        _ => Stream::Output,
+    }
+}
+
+/// `write(*objects)`: writes each object like `print`, returning how many bytes were written.
+fn write(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let out = kernel::print_bytes(interpreter, args)?;
+    kernel::write_to(interpreter, stream(interpreter, receiver), &out)?;
+    Ok(Value::Integer(out.len() as i64))
+}
+
+fn printf(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if args.is_empty() { return Ok(Value::Nil); }
+    let spec = expect_string(interpreter, &args[0])?;
+    let text = format::format(interpreter, &spec, &args[1..])?;
+    kernel::write_to(interpreter, stream(interpreter, receiver), text.as_bytes())?;
+    Ok(Value::Nil)
+}
+
+/// `#<IO:<STDOUT>>`, named after the constant for the stream.
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> Result<Value, Control> {
+    let stream = stream(interpreter, receiver);
+    let name = STREAMS.iter().find(|(other, _, _)| *other == stream).map_or("STDOUT", |(_, constant, _)| constant);
+    Ok(Value::string(&format!("#<IO:<{}>>", name)))
+}
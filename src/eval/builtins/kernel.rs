@@ -0,0 +1,582 @@
+use std::io::{ BufRead, Write };
+use std::rc::Rc;
+use std::slice;
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, expect_integer, expect_name, expect_string, format, hash, numeric };
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::random::{ self, Random };
+use crate::eval::value::{ Encoding, RProc, Value };
+
+/// The functions every object has privately through `Kernel`: writing to the output and
+/// reading the input, random numbers, `loop` and `catch`, and the conversion functions. Also
+/// the public methods for freezing, copying and reflecting on objects.
+pub fn define(interpreter: &mut Interpreter) {
+    interpreter.set_arguments("-", &[]);
+    interpreter.set_global("$VERBOSE", Value::Bool(false));
+
+    let kernel = interpreter.classes.kernel.clone();
+    kernel.define_private_builtin("puts", puts);
+    kernel.define_private_builtin("print", print);
+    kernel.define_private_builtin("p", p);
+    kernel.define_private_builtin("pp", pp);
+    kernel.define_private_builtin("warn", warn);
+    kernel.define_private_builtin("printf", printf);
+    kernel.define_private_builtin("format", sprintf);
+    kernel.define_private_builtin("sprintf", sprintf);
+    kernel.define_private_builtin("gets", gets);
+    kernel.define_private_builtin("sleep", sleep);
+    kernel.define_private_builtin("rand", rand);
+    kernel.define_private_builtin("srand", srand);
+    kernel.define_private_builtin("loop", kernel_loop);
+    kernel.define_private_builtin("catch", catch);
+    kernel.define_private_builtin("throw", throw);
+
+    kernel.define_private_builtin("Integer", |i, r, a, b| {
+        let (args, exception) = exception_option(i, a)?;
+        rescue_conversion(i, exception, |i| numeric::kernel_integer(i, r, args, b))
+    });
+    kernel.define_private_builtin("Float", |i, _, a, _| {
+        let (args, exception) = exception_option(i, a)?;
+        check_arity(i, args, 1, 1)?;
+        rescue_conversion(i, exception, |i| Ok(Value::Float(numeric::to_float(i, &args[0])?)))
+    });
+    kernel.define_private_builtin("String", kernel_string);
+    kernel.define_private_builtin("Array", kernel_array);
+
+    kernel.define_builtin("freeze", |_, r, _, _| {
+        r.freeze();
+        Ok(r.clone())
+    });
+    kernel.define_builtin("frozen?", |_, r, _, _| Ok(Value::Bool(r.is_frozen())));
+    kernel.define_builtin("dup", |_, r, _, _| Ok(copy(r)));
+    kernel.define_builtin("clone", clone);
+    kernel.define_builtin("send", send);
+    kernel.define_builtin("__send__", send);
+    kernel.define_builtin("public_send", public_send);
+    kernel.define_builtin("instance_variable_get", instance_variable_get);
+    kernel.define_builtin("instance_variable_set", instance_variable_set);
+    kernel.define_builtin("instance_variable_defined?", instance_variable_defined);
+    kernel.define_builtin("instance_variables", instance_variables);
+}
+
+/// The streams a program writes to, which `STDOUT` and `STDERR` stand for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Output,
+    Error,
+}
+
+impl Stream {
+    pub fn fileno(self) -> i64 {
+        match self {
+            Stream::Output => 1,
+            Stream::Error => 2,
+        }
+    }
+}
+
+/// Writes to the interpreter's output, turning a failed write into an `IOError`.
+pub fn write(interpreter: &mut Interpreter, bytes: &[u8]) -> Result<(), Control> {
+    write_to(interpreter, Stream::Output, bytes)
+}
+
+/// Writes to one of the streams. The error output isn't buffered, and what was printed before
+/// is flushed first, so the two come out in the order they were written.
+pub fn write_to(interpreter: &mut Interpreter, stream: Stream, bytes: &[u8]) -> Result<(), Control> {
+    let result = match stream {
+        Stream::Output => interpreter.output.write_all(bytes),
+        Stream::Error => {
+            interpreter.output.flush()
+                .and_then(|_| interpreter.error_output.write_all(bytes))
+                .and_then(|_| interpreter.error_output.flush())
+        },
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(error) => Err(interpreter.error("IOError", &error.to_string())),
+    }
+}
+
+/// Flushes the output, which matters before reading input or pausing.
+pub fn flush(interpreter: &mut Interpreter) -> Result<(), Control> {
+    match interpreter.output.flush() {
+        Ok(()) => Ok(()),
+        Err(error) => Err(interpreter.error("IOError", &error.to_string())),
+    }
+}
+
+/// The bytes `print` writes for a value: a string's own, anything else's `to_s`.
+pub fn bytes_of(interpreter: &mut Interpreter, value: &Value) -> Result<Vec<u8>, Control> {
+    match value {
+        Value::String(string) => Ok(string.bytes.borrow().clone()),
+        value => Ok(interpreter.to_s(value)?.into_bytes()),
+    }
+}
+
+/// `puts(*objects)`: each object on its own line, the elements of arrays each on theirs, and
+/// just a newline with nothing to print.
+fn puts(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let out = puts_bytes(interpreter, args)?;
+    write(interpreter, &out)?;
+    Ok(Value::Nil)
+}
+
+/// What `puts` writes for its arguments.
+pub fn puts_bytes(interpreter: &mut Interpreter, args: &[Value]) -> Result<Vec<u8>, Control> {
+    let mut out = vec!();
+    if args.is_empty() { out.push(b'\n'); }
+    for arg in args {
+        puts_lines(interpreter, arg, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn puts_lines(interpreter: &mut Interpreter, value: &Value, out: &mut Vec<u8>) -> Result<(), Control> {
+    let elements = match value {
+        Value::Array(array) => array.elements.borrow().clone(),
+        value => {
+            let line = bytes_of(interpreter, value)?;
+            out.extend_from_slice(&line);
+            if line.last() != Some(&b'\n') { out.push(b'\n'); }
+            return Ok(());
+        },
+    };
+    if elements.is_empty() {
+        out.push(b'\n');
+        return Ok(());
+    }
+
+    let nested = interpreter.guard_recursion("puts", value, |interpreter| {
+        let mut lines = vec!();
+        for element in elements.iter() {
+            puts_lines(interpreter, element, &mut lines)?;
+        }
+        Ok(lines)
+    })?;
+    out.extend(nested.unwrap_or_else(|| b"[...]\n".to_vec()));
+    Ok(())
+}
+
+fn print(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let out = print_bytes(interpreter, args)?;
+    write(interpreter, &out)?;
+    Ok(Value::Nil)
+}
+
+/// What `print` writes for its arguments: each one's bytes, one after another.
+pub fn print_bytes(interpreter: &mut Interpreter, args: &[Value]) -> Result<Vec<u8>, Control> {
+    let mut out = vec!();
+    for arg in args {
+        out.extend(bytes_of(interpreter, arg)?);
+    }
+    Ok(out)
+}
+
+/// `p(*objects)`: writes each object's `inspect` on a line, returning what it was given.
+fn p(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut out = String::new();
+    for arg in args {
+        out.push_str(&interpreter.inspect(arg)?);
+        out.push('\n');
+    }
+    write(interpreter, out.as_bytes())?;
+    flush(interpreter)?;
+    Ok(given(args))
+}
+
+/// `pp(*objects)`: like `p`, but an array or hash too wide for the line has its elements
+/// written one to a line.
+fn pp(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut out = String::new();
+    for arg in args {
+        out.push_str(&pretty(interpreter, arg, 0, 0)?);
+        out.push('\n');
+    }
+    write(interpreter, out.as_bytes())?;
+    flush(interpreter)?;
+    Ok(given(args))
+}
+
+/// What `p` and `pp` return: nil, their one argument, or an array of them.
+fn given(args: &[Value]) -> Value {
+    match args {
+        [] => Value::Nil,
+        [arg] => arg.clone(),
+        args => Value::array(args.to_vec()),
+    }
+}
+
+/// The width of the lines `pp` writes.
+const PP_WIDTH: usize = 80;
+
+/// A value's `inspect` laid out for `pp`, starting at `column` and followed by `trailing`
+/// closing characters. An array or hash that doesn't fit has each element on its own line,
+/// lined up after the opening bracket.
+fn pretty(interpreter: &mut Interpreter, value: &Value, column: usize, trailing: usize) -> Result<String, Control> {
+    let inspected = interpreter.inspect(value)?;
+    if column + inspected.chars().count() + trailing <= PP_WIDTH { return Ok(inspected); }
+
+    let separator = format!(",\n{}", " ".repeat(column + 1));
+    let laid_out = interpreter.guard_recursion("pp", value, |interpreter| {
+        let mut lines = vec!();
+        match value {
+            Value::Array(array) => {
+                let elements = array.elements.borrow().clone();
+                for (index, element) in elements.iter().enumerate() {
+                    let after = if index + 1 == elements.len() { trailing + 1 } else { 1 };
+                    lines.push(pretty(interpreter, element, column + 1, after)?);
+                }
+                Ok(Some(format!("[{}]", lines.join(&separator))))
+            },
+            Value::Hash(_) => {
+                let entries = hash::entries(value);
+                for (index, (key, element)) in entries.iter().enumerate() {
+                    let after = if index + 1 == entries.len() { trailing + 1 } else { 1 };
+                    let key = hash::key_prefix(interpreter, key)?;
+                    let element = pretty(interpreter, element, column + 1 + key.chars().count(), after)?;
+                    lines.push(format!("{}{}", key, element));
+                }
+                Ok(Some(format!("{{{}}}", lines.join(&separator))))
+            },
+            _ => Ok(None),
+        }
+    })?;
+
+    Ok(laid_out.flatten().unwrap_or(inspected))
+}
+
+/// `warn(*messages)`: writes the messages to `$stderr` as `puts` would, unless `$VERBOSE` is
+/// nil. Options such as `uplevel:` are accepted and ignored.
+fn warn(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let messages = match args.split_last() {
+        Some((Value::Hash(_), messages)) => messages,
+        _ => args,
+    };
+    if messages.is_empty() || matches!(interpreter.global("$VERBOSE"), Value::Nil) { return Ok(Value::Nil); }
+
+    let out = puts_bytes(interpreter, messages)?;
+    let stderr = interpreter.global("$stderr");
+    interpreter.call_method(&stderr, "write", &[Value::bytes(out, Encoding::Utf8)], None)?;
+    Ok(Value::Nil)
+}
+
+fn printf(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if args.is_empty() { return Ok(Value::Nil); }
+    let spec = expect_string(interpreter, &args[0])?;
+    let text = format::format(interpreter, &spec, &args[1..])?;
+    write(interpreter, text.as_bytes())?;
+    Ok(Value::Nil)
+}
+
+fn sprintf(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if args.is_empty() { return Err(interpreter.error("ArgumentError", "too few arguments")); }
+    let spec = expect_string(interpreter, &args[0])?;
+    Ok(Value::string(&format::format(interpreter, &spec, &args[1..])?))
+}
+
+/// `gets`: the next line of input including its newline, also stored in `$_`, or nil at the
+/// end of the input.
+fn gets(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    flush(interpreter)?;
+    let mut line = vec!();
+    if let Err(error) = interpreter.input.read_until(b'\n', &mut line) {
+        return Err(interpreter.error("IOError", &error.to_string()));
+    }
+
+    let line = if line.is_empty() { Value::Nil } else { Value::bytes(line, Encoding::Utf8) };
+    interpreter.set_global("$_", line.clone());
+    Ok(line)
+}
+
+/// `sleep(seconds = nil)`: pauses for that long, or forever, and returns the whole number of
+/// seconds it slept.
+fn sleep(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let seconds = match args.first() {
+        None | Some(Value::Nil) => None,
+        Some(value @ (Value::Integer(_) | Value::Float(_))) => Some(numeric::float_of(value)),
+        Some(value) => {
+            let message = format!("can't convert {} into time interval", interpreter.class_of(value).inspect());
+            return Err(interpreter.error("TypeError", &message));
+        },
+    };
+    if seconds.is_some_and(|seconds| seconds < 0.0) {
+        return Err(interpreter.error("ArgumentError", "time interval must not be negative"));
+    }
+
+    flush(interpreter)?;
+    let start = Instant::now();
+    match seconds {
+        Some(seconds) => thread::sleep(Duration::from_secs_f64(seconds)),
+        None => loop { thread::park(); },
+    }
+    Ok(Value::Integer(start.elapsed().as_secs_f64().round() as i64))
+}
+
+/// `rand(max = 0)`: a float in `[0, 1)` for a zero or missing `max`, an integer below its
+/// absolute value otherwise, or a number in a range.
+fn rand(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let max = match args.first() {
+        None | Some(Value::Nil) => 0,
+        Some(Value::Range(range)) => {
+            return match (&range.start, &range.end) {
+                (Value::Integer(start), Value::Integer(end)) => {
+                    let last = if range.exclusive { end - 1 } else { *end };
+                    if last < *start { return Ok(Value::Nil); }
+                    let offset = interpreter.random.up_to(last.wrapping_sub(*start) as u64);
+                    Ok(Value::Integer(start.wrapping_add(offset as i64)))
+                },
+                (start @ (Value::Integer(_) | Value::Float(_)), end @ (Value::Integer(_) | Value::Float(_))) => {
+                    let (start, end) = (numeric::float_of(start), numeric::float_of(end));
+                    if end < start || (range.exclusive && end == start) { return Ok(Value::Nil); }
+                    Ok(Value::Float(interpreter.random.next_f64() * (end - start) + start))
+                },
+                _ => {
+                    let message = format!("invalid argument - {}", interpreter.inspect(&args[0])?);
+                    Err(interpreter.error("ArgumentError", &message))
+                },
+            };
+        },
+        Some(max) => expect_integer(interpreter, max)?,
+    };
+
+    match max.unsigned_abs() {
+        0 => Ok(Value::Float(interpreter.random.next_f64())),
+        max => Ok(Value::Integer(interpreter.random.up_to(max - 1) as i64)),
+    }
+}
+
+/// `srand(seed = nil)`: reseeds the generator `rand` uses, with a fresh seed if none is given,
+/// and returns the previous seed.
+fn srand(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let seed = match args.first() {
+        None | Some(Value::Nil) => random::new_seed(),
+        Some(seed @ (Value::Integer(_) | Value::Bignum(_))) => seed.clone(),
+        Some(seed) => Value::Integer(expect_integer(interpreter, seed)?),
+    };
+    interpreter.random = Random::new(&random::seed_words(&seed));
+    Ok(std::mem::replace(&mut interpreter.seed, seed))
+}
+
+/// `loop { ... }`: runs the block until it breaks, or until it raises `StopIteration`, which
+/// ends the loop with the exception's `result`.
+fn kernel_loop(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "loop", args)),
+    };
+    loop {
+        match interpreter.call_proc(block, &[]) {
+            Ok(_) => {},
+            Err(Control::Raise(exception)) if interpreter.is_a(&exception, "StopIteration") => {
+                return Ok(interpreter.instance_variable(&exception, "result"));
+            },
+            Err(control) => return Err(control),
+        }
+    }
+}
+
+/// `catch(tag = Object.new) { |tag| ... }`: the block's value, or the value a `throw` of the
+/// same tag unwound it with.
+fn catch(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let tag = args.first().cloned().unwrap_or_else(|| Value::object(interpreter.classes.object.clone()));
+    let block = match block {
+        Some(block) => block,
+        None => return Err(interpreter.error("LocalJumpError", "no block given (yield)")),
+    };
+
+    interpreter.catches.push(tag.clone());
+    let result = interpreter.call_proc(block, slice::from_ref(&tag));
+    interpreter.catches.pop();
+    match result {
+        Err(Control::Throw(thrown, value)) if thrown.is_identical(&tag) => Ok(value),
+        result => result,
+    }
+}
+
+fn throw(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let tag = args[0].clone();
+    if !interpreter.catches.iter().any(|catching| catching.is_identical(&tag)) {
+        let message = format!("uncaught throw {}", interpreter.inspect(&tag)?);
+        return Err(interpreter.error("UncaughtThrowError", &message));
+    }
+
+    Err(Control::Throw(tag, args.get(1).cloned().unwrap_or(Value::Nil)))
+}
+
+/// Splits off the `exception:` option `Integer()` and `Float()` take.
+fn exception_option<'a>(interpreter: &mut Interpreter, args: &'a [Value]) -> Result<(&'a [Value], bool), Control> {
+    let (options, rest) = match args.split_last() {
+        Some((options @ Value::Hash(_), rest)) if !rest.is_empty() => (options, rest),
+        _ => return Ok((args, true)),
+    };
+    let entries = hash::entries(options);
+    for (key, _) in entries.iter() {
+        if !matches!(key, Value::Symbol(key) if *key == "exception") {
+            let message = format!("unknown keyword: {}", interpreter.inspect(key)?);
+            return Err(interpreter.error("ArgumentError", &message));
+        }
+    }
+
+    let exception = entries.first().is_none_or(|(_, value)| value.is_truthy());
+    Ok((rest, exception))
+}
+
+/// Runs a conversion, turning the errors of a value that can't be converted into nil when
+/// `exception: false` was given.
+fn rescue_conversion(interpreter: &mut Interpreter, exception: bool, convert: impl FnOnce(&mut Interpreter) -> EvalResult) -> EvalResult {
+    match convert(interpreter) {
+        Err(Control::Raise(error)) if !exception && (interpreter.is_a(&error, "ArgumentError") || interpreter.is_a(&error, "TypeError")) => {
+            Ok(Value::Nil)
+        },
+        result => result,
+    }
+}
+
+/// `String(value)`: `to_str` if the value has it, else `to_s`.
+fn kernel_string(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let value = &args[0];
+    if let Value::String(_) = value { return Ok(value.clone()); }
+
+    for conversion in ["to_str", "to_s"] {
+        if interpreter.lookup_class(value).find_method(conversion).is_none() { continue; }
+        let converted = interpreter.call_method(value, conversion, &[], None)?;
+        if let Value::String(_) = converted { return Ok(converted); }
+        let message = format!(
+            "can't convert {} to String ({}#{} gives {})",
+            interpreter.class_of(value).inspect(), interpreter.class_of(value).inspect(), conversion, interpreter.class_of(&converted).inspect(),
+        );
+        return Err(interpreter.error("TypeError", &message));
+    }
+    let message = format!("can't convert {} into String", interpreter.class_of(value).inspect());
+    Err(interpreter.error("TypeError", &message))
+}
+
+/// `Array(value)`: `[]` for nil, the array itself, what `to_ary` or `to_a` make of the value,
+/// or else the value alone in an array.
+fn kernel_array(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let value = &args[0];
+    match value {
+        Value::Nil => return Ok(Value::array(vec!())),
+        Value::Array(_) => return Ok(value.clone()),
+        _ => {},
+    }
+
+    for conversion in ["to_ary", "to_a"] {
+        if interpreter.lookup_class(value).find_method(conversion).is_none() { continue; }
+        match interpreter.call_method(value, conversion, &[], None)? {
+            converted @ Value::Array(_) => return Ok(converted),
+            Value::Nil => continue,
+            converted => {
+                let message = format!(
+                    "can't convert {} to Array ({}#{} gives {})",
+                    interpreter.class_of(value).inspect(), interpreter.class_of(value).inspect(), conversion, interpreter.class_of(&converted).inspect(),
+                );
+                return Err(interpreter.error("TypeError", &message));
+            },
+        }
+    }
+    Ok(Value::array(vec!(value.clone())))
+}
+
+/// A shallow copy of an object with its instance variables. Values without state of their
+/// own are their own copies.
+fn copy(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let copy = Value::object(object.class.clone());
+            if let Value::Object(copied) = &copy {
+                *copied.ivars.borrow_mut() = object.ivars.borrow().clone();
+            }
+            copy
+        },
+        value => value.clone(),
+    }
+}
+
+/// `clone(freeze: nil)`: what `dup` makes, frozen if the receiver is, unless `freeze:` says
+/// otherwise.
+fn clone(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let freeze = match args.first() {
+        Some(options @ Value::Hash(_)) => hash::get(options, &Value::symbol("freeze")).unwrap_or(Value::Nil),
+        Some(_) => return Err(interpreter.error("ArgumentError", "wrong number of arguments (given 1, expected 0)")),
+        None => Value::Nil,
+    };
+    let copy = interpreter.call_method(receiver, "dup", &[], None)?;
+    if matches!(freeze, Value::Bool(true)) || (matches!(freeze, Value::Nil) && receiver.is_frozen()) { copy.freeze(); }
+    Ok(copy)
+}
+
+fn method_name(interpreter: &mut Interpreter, args: &[Value]) -> Result<String, Control> {
+    match args.first() {
+        Some(name) => expect_name(interpreter, name),
+        None => Err(interpreter.error("ArgumentError", "no method name given")),
+    }
+}
+
+/// `send(name, *args)`: calls a method, private or not.
+fn send(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let name = method_name(interpreter, args)?;
+    interpreter.call_method(receiver, &name, &args[1..], block)
+}
+
+fn public_send(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let name = method_name(interpreter, args)?;
+    interpreter.call_public(receiver, &name, &args[1..], block, false)
+}
+
+fn instance_variable_name(interpreter: &mut Interpreter, value: &Value) -> Result<String, Control> {
+    let name = expect_name(interpreter, value)?;
+    let identifier = name.strip_prefix('@').filter(|rest| !rest.starts_with(|c: char| c.is_ascii_digit() || c == '@'));
+    if !identifier.is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_alphanumeric() || c == '_')) {
+        let message = format!("'{}' is not allowed as an instance variable name", name);
+        return Err(interpreter.error("NameError", &message));
+    }
+
+    Ok(name)
+}
+
+fn instance_variable_get(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let name = instance_variable_name(interpreter, &args[0])?;
+    Ok(interpreter.instance_variable(receiver, &name))
+}
+
+fn instance_variable_set(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 2)?;
+    let name = instance_variable_name(interpreter, &args[0])?;
+    interpreter.set_instance_variable(receiver, &name, args[1].clone())?;
+    Ok(args[1].clone())
+}
+
+fn instance_variable_defined(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let name = instance_variable_name(interpreter, &args[0])?;
+    let defined = match receiver {
+        Value::Object(object) => object.get(&name).is_some(),
+        Value::Class(class) => class.ivars.borrow().iter().any(|(ivar, _)| *ivar == name),
+        _ => false,
+    };
+    Ok(Value::Bool(defined))
+}
+
+fn instance_variables(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let names = match receiver {
+        Value::Object(object) => object.ivars.borrow().iter().map(|(name, _)| Value::symbol(name)).collect(),
+        Value::Class(class) => class.ivars.borrow().iter().map(|(name, _)| Value::symbol(name)).collect(),
+        _ => vec!(),
+    };
+    Ok(Value::array(names))
+}
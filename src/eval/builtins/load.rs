@@ -0,0 +1,187 @@
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, expect_string };
+use crate::eval::builtins::kernel::{ self, Stream };
+use crate::eval::value::{ RProc, Value };
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+/// `require`, `require_relative` and `load`, which run other files, with the globals they
+/// keep: `$LOAD_PATH` (also `$:`), the directories `require` searches, and
+/// `$LOADED_FEATURES` (also `$"`), the absolute paths of the files already required.
+pub fn define(interpreter: &mut Interpreter) {
+    let load_path = Value::array(vec!());
+    interpreter.set_global("$LOAD_PATH", load_path.clone());
+    interpreter.set_global("$:", load_path);
+    let features = Value::array(vec!());
+    interpreter.set_global("$LOADED_FEATURES", features.clone());
+    interpreter.set_global("$\"", features);
+
+    let kernel = interpreter.classes.kernel.clone();
+    kernel.define_private_builtin("require", require);
+    kernel.define_private_builtin("require_relative", require_relative);
+    kernel.define_private_builtin("load", load);
+    kernel.define_private_builtin("__FILE__", |i, _, _, _| Ok(Value::string(&current_file(i))));
+    kernel.define_private_builtin("__dir__", |i, _, _, _| {
+        let file = current_file(i);
+        if !is_real_file(&file) { return Ok(Value::Nil); }
+        let directory = absolute(Path::new(&file)).parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(Value::string(&directory.to_string_lossy()))
+    });
+}
+
+/// The file the running code was read from, `-` for a program without one.
+fn current_file(interpreter: &Interpreter) -> String {
+    match &interpreter.frame().ast {
+        Some(ast) => ast.file().to_string(),
+        None => String::from("-"),
+    }
+}
+
+/// Whether code came from a file, rather than from `-e` or standard input.
+fn is_real_file(file: &str) -> bool {
+    file != "-" && file != "-e"
+}
+
+/// The path made absolute against the working directory, with links resolved if it exists.
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| env::current_dir().unwrap_or_default().join(path))
+}
+
+/// The file a feature name means: the name itself if it ends in `.rb`, else with `.rb` added.
+fn with_extension(path: PathBuf) -> PathBuf {
+    if path.extension().is_some_and(|extension| extension == "rb") { return path; }
+
+    let mut file = path.into_os_string();
+    file.push(".rb");
+    PathBuf::from(file)
+}
+
+/// Whether a name is a path to use as it is rather than one to look for in `$LOAD_PATH`.
+fn is_explicit(name: &str) -> bool {
+    Path::new(name).is_absolute() || name.starts_with("./") || name.starts_with("../") || name.starts_with('~')
+}
+
+fn load_path(interpreter: &Interpreter) -> Vec<Value> {
+    match interpreter.global("$LOAD_PATH") {
+        Value::Array(paths) => paths.elements.borrow().clone(),
+        _ => vec!(),
+    }
+}
+
+/// The first directory of `$LOAD_PATH` holding the file.
+fn search_load_path(interpreter: &mut Interpreter, file: &Path) -> Result<Option<PathBuf>, Control> {
+    for directory in load_path(interpreter) {
+        let directory = interpreter.to_s(&directory)?;
+        let path = Path::new(&directory).join(file);
+        if path.is_file() { return Ok(Some(path)); }
+    }
+    Ok(None)
+}
+
+fn load_error(interpreter: &Interpreter, name: &str) -> Control {
+    interpreter.error("LoadError", &format!("cannot load such file -- {}", name))
+}
+
+/// `require(name)`: runs the file `name.rb` from `$LOAD_PATH`, or from the path given, unless
+/// it has already been required. True if it ran the file.
+fn require(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let name = expect_string(interpreter, &args[0])?;
+    let file = with_extension(PathBuf::from(&name));
+    let path = if is_explicit(&name) {
+        Some(file).filter(|file| file.is_file())
+    } else {
+        search_load_path(interpreter, &file)?
+    };
+    match path {
+        Some(path) => require_file(interpreter, &path),
+        None => Err(load_error(interpreter, &name)),
+    }
+}
+
+/// `require_relative(name)`: requires a file by its path from the directory of the file
+/// calling it.
+fn require_relative(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let name = expect_string(interpreter, &args[0])?;
+    let base = current_file(interpreter);
+    if !is_real_file(&base) { return Err(interpreter.error("LoadError", "cannot infer basepath")); }
+
+    let directory = absolute(Path::new(&base)).parent().map(Path::to_path_buf).unwrap_or_default();
+    let path = with_extension(directory.join(&name));
+    if !path.is_file() {
+        return Err(load_error(interpreter, &directory.join(&name).to_string_lossy()));
+    }
+    require_file(interpreter, &path)
+}
+
+/// Runs a file unless `$LOADED_FEATURES` has it, adding it there once it ran without raising.
+/// A file that is still being required when it is required again is skipped, with a warning
+/// under `-w`.
+fn require_file(interpreter: &mut Interpreter, path: &Path) -> EvalResult {
+    let feature = absolute(path).to_string_lossy().into_owned();
+    let features = match interpreter.global("$LOADED_FEATURES") {
+        Value::Array(features) => features,
+        _ => return Err(interpreter.error("TypeError", "$LOADED_FEATURES is not an array")),
+    };
+    for loaded in features.elements.borrow().iter() {
+        if matches!(loaded, Value::String(loaded) if loaded.text() == feature) { return Ok(Value::Bool(false)); }
+    }
+    if interpreter.loading.contains(&feature) {
+        if interpreter.global("$VERBOSE").is_truthy() {
+            let frame = interpreter.frame();
+            let line = frame.ast.as_ref().map_or(0, |ast| ast.line(frame.offset));
+            let warning = format!("{}:{}: warning: loading in progress, circular require considered harmful - {}\n", current_file(interpreter), line, feature);
+            kernel::write_to(interpreter, Stream::Error, warning.as_bytes())?;
+        }
+        return Ok(Value::Bool(false));
+    }
+
+    interpreter.loading.push(feature.clone());
+    let result = run_file(interpreter, &feature);
+    interpreter.loading.pop();
+    result?;
+
+    features.elements.borrow_mut().push(Value::string(&feature));
+    Ok(Value::Bool(true))
+}
+
+/// `load(file)`: runs a file every time, looking for it in `$LOAD_PATH` and then in the
+/// working directory.
+fn load(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let name = expect_string(interpreter, &args[0])?;
+    let path = match is_explicit(&name) {
+        true => None,
+        false => search_load_path(interpreter, Path::new(&name))?,
+    };
+    let path = path.unwrap_or_else(|| PathBuf::from(&name));
+    if !path.is_file() { return Err(load_error(interpreter, &name)); }
+
+    run_file(interpreter, &path.to_string_lossy())?;
+    Ok(Value::Bool(true))
+}
+
+/// Reads, parses and runs a file, raising `SyntaxError` with every parse error in it.
+fn run_file(interpreter: &mut Interpreter, file: &str) -> EvalResult {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(error) => return Err(interpreter.error("LoadError", &format!("{} -- {}", error, file))),
+    };
+
+    let mut lexer = Lexer::new(source.clone());
+    let mut parser = Parser::new(&mut lexer);
+    let (mut program, errors) = parser.parse_program();
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|error| format!("{}:{}", file, error.describe(&source))).collect();
+        return Err(interpreter.error("SyntaxError", &messages.join("\n")));
+    }
+    program.ast.set_file(file);
+
+    interpreter.eval_file(program)
+}
@@ -0,0 +1,518 @@
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ EvalResult, Interpreter, Control };
+use crate::eval::builtins::{ check_arity, expect_name };
+use crate::eval::class::{ Method, MethodBody, RClass, Visibility };
+use crate::eval::value::{ RProc, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let module = interpreter.classes.module.clone();
+    module.define_builtin("name", name);
+    module.define_builtin("to_s", inspect);
+    module.define_builtin("inspect", inspect);
+    module.define_builtin("===", case_equal);
+    module.define_builtin("<", |i, r, a, _| compare(i, r, a, false));
+    module.define_builtin("<=", |i, r, a, _| compare(i, r, a, true));
+    module.define_builtin("ancestors", ancestors);
+    module.define_builtin("include", include);
+    module.define_builtin("prepend", prepend);
+    module.define_builtin("include?", includes);
+    module.define_private_builtin("included", |_, _, _, _| Ok(Value::Nil));
+    module.define_private_builtin("extended", |_, _, _, _| Ok(Value::Nil));
+    module.define_private_builtin("prepended", |_, _, _, _| Ok(Value::Nil));
+    module.define_builtin("instance_methods", |i, r, a, _| instance_methods(i, r, a, |visibility| visibility != Visibility::Private));
+    module.define_builtin("public_instance_methods", |i, r, a, _| instance_methods(i, r, a, |visibility| visibility == Visibility::Public));
+    module.define_builtin("private_instance_methods", |i, r, a, _| instance_methods(i, r, a, |visibility| visibility == Visibility::Private));
+    module.define_builtin("method_defined?", |i, r, a, _| method_defined(i, r, a, |visibility| visibility != Visibility::Private));
+    module.define_builtin("public_method_defined?", |i, r, a, _| method_defined(i, r, a, |visibility| visibility == Visibility::Public));
+    module.define_builtin("private_method_defined?", |i, r, a, _| method_defined(i, r, a, |visibility| visibility == Visibility::Private));
+    module.define_builtin("protected_method_defined?", |i, r, a, _| method_defined(i, r, a, |visibility| visibility == Visibility::Protected));
+    module.define_builtin("attr_reader", |i, r, a, _| define_attributes(i, r, a, true, false));
+    module.define_builtin("attr_writer", |i, r, a, _| define_attributes(i, r, a, false, true));
+    module.define_builtin("attr_accessor", |i, r, a, _| define_attributes(i, r, a, true, true));
+    module.define_private_builtin("public", |i, r, a, _| set_visibility(i, &module_of(r), a, Visibility::Public));
+    module.define_private_builtin("protected", |i, r, a, _| set_visibility(i, &module_of(r), a, Visibility::Protected));
+    module.define_private_builtin("private", |i, r, a, _| set_visibility(i, &module_of(r), a, Visibility::Private));
+    module.define_private_builtin("module_function", module_function);
+    module.define_builtin("public_class_method", |i, r, a, _| class_method_visibility(i, r, a, Visibility::Public));
+    module.define_builtin("private_class_method", |i, r, a, _| class_method_visibility(i, r, a, Visibility::Private));
+    module.define_builtin("define_method", define_method);
+    module.define_builtin("alias_method", alias_method);
+    module.define_builtin("const_get", const_get);
+    module.define_builtin("const_set", const_set);
+    module.define_builtin("const_defined?", const_defined);
+    module.define_builtin("constants", constants);
+    module.define_builtin("class_variable_get", class_variable_get);
+    module.define_builtin("class_variable_set", class_variable_set);
+    module.define_builtin("class_variable_defined?", class_variable_defined);
+    module.define_builtin("class_variables", class_variables);
+    module.define_builtin("class_eval", class_eval);
+    module.define_builtin("module_eval", class_eval);
+
+    let class = interpreter.classes.class.clone();
+    class.define_builtin("new", new);
+    class.define_builtin("allocate", allocate);
+    class.define_builtin("superclass", superclass);
+    class.define_private_builtin("inherited", |_, _, _, _| Ok(Value::Nil));
+
+    // `Class.new` and `Module.new` create classes and modules rather than instances of them
+    interpreter.metaclass(&class).define_builtin("new", class_new);
+    interpreter.metaclass(&module).define_builtin("new", module_new);
+}
+
+fn module_of(value: &Value) -> Rc<RClass> {
+    match value {
+        Value::Class(module) => module.clone(),
+        _ => unreachable!("a Module method called on {:?}", value),
+    }
+}
+
+/// The argument as a module, or the `TypeError` `include` and `extend` raise for anything
+/// else.
+pub fn expect_module(interpreter: &Interpreter, value: &Value) -> Result<Rc<RClass>, Control> {
+    match value {
+        Value::Class(module) if module.is_module => Ok(module.clone()),
+        value => {
+            let message = format!("wrong argument type {} (expected Module)", interpreter.class_of(value).inspect());
+            Err(interpreter.error("TypeError", &message))
+        }
+    }
+}
+
+/// Adds `module` in front of the modules `target` includes, unless it's already among its
+/// ancestors.
+pub fn include_module(target: &Rc<RClass>, module: &Rc<RClass>) {
+    if !target.inherits(module) {
+        target.includes.borrow_mut().insert(0, module.clone());
+    }
+}
+
+/// The names of the methods defined in the class, and in its ancestors with `inherit`, whose
+/// visibility passes `filter`. Each name is listed once, with the visibility of the
+/// definition a call would find.
+pub fn method_names(class: &Rc<RClass>, inherit: bool, filter: fn(Visibility) -> bool) -> Vec<String> {
+    let ancestors = if inherit { class.ancestors() } else { vec!(class.clone()) };
+    let mut seen: Vec<String> = vec!();
+    let mut names = vec!();
+    for ancestor in ancestors.iter() {
+        let mut methods: Vec<(String, Visibility)> = ancestor.methods.borrow().iter()
+            .map(|(name, method)| (name.clone(), method.visibility))
+            .collect();
+        methods.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, visibility) in methods {
+            if seen.contains(&name) { continue; }
+            if filter(visibility) { names.push(name.clone()); }
+            seen.push(name);
+        }
+    }
+
+    names
+}
+
+/// Changes the visibility of the named methods, copying inherited ones into `module`. With
+/// no names it sets what later `def`s in the class body get instead.
+pub fn set_visibility(interpreter: &mut Interpreter, module: &Rc<RClass>, args: &[Value], visibility: Visibility) -> EvalResult {
+    if args.is_empty() {
+        let frame = interpreter.frames.last_mut().expect("the top level frame is never popped");
+        frame.visibility = visibility;
+        frame.module_function = false;
+        return Ok(Value::Nil);
+    }
+
+    for name in names(interpreter, args)? {
+        let mut method = find_method(interpreter, module, &name)?;
+        method.visibility = visibility;
+        module.define(&name, method);
+    }
+
+    Ok(if args.len() == 1 { args[0].clone() } else { Value::array(args.to_vec()) })
+}
+
+/// The method names given to `private` and friends, which may also come as an array, like
+/// the one `attr_reader` returns.
+fn names(interpreter: &mut Interpreter, args: &[Value]) -> Result<Vec<String>, Control> {
+    let mut names = vec!();
+    for arg in args.iter() {
+        match arg {
+            Value::Array(array) => {
+                let elements = array.elements.borrow().clone();
+                for element in elements.iter() { names.push(expect_name(interpreter, element)?); }
+            },
+            arg => names.push(expect_name(interpreter, arg)?),
+        }
+    }
+
+    Ok(names)
+}
+
+fn find_method(interpreter: &Interpreter, module: &Rc<RClass>, name: &str) -> Result<Method, Control> {
+    match module.find_method(name) {
+        Some((method, _)) => Ok(method),
+        None => {
+            let kind = if module.is_module { "module" } else { "class" };
+            let message = format!("undefined method '{}' for {} '{}'", name, kind, module.inspect());
+            Err(interpreter.error("NameError", &message))
+        }
+    }
+}
+
+/// The visibility a method defined by a builtin like `attr_reader` gets: what the class
+/// body calling it is set to, or public when called from anywhere else.
+fn default_visibility(interpreter: &Interpreter, module: &Rc<RClass>) -> Visibility {
+    let frame = interpreter.frame();
+    match &frame.context.self_value {
+        Value::Class(class) if Rc::ptr_eq(class, module) && !frame.module_function => frame.visibility,
+        _ => Visibility::Public,
+    }
+}
+
+fn name(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let name = module_of(receiver).name();
+    Ok(if name.is_empty() { Value::Nil } else { Value::string(&name) })
+}
+
+fn inspect(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::string(&module_of(receiver).inspect()))
+}
+
+/// `Integer === 1` tests whether the argument is an instance, which is how `case` matches
+/// on classes.
+fn case_equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(interpreter.lookup_class(&args[0]).inherits(&module_of(receiver))))
+}
+
+/// `A < B` is true when `A` inherits from or includes `B`, false when `B` is below `A`, and
+/// nil when the two aren't related.
+fn compare(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], or_equal: bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let (module, other) = match &args[0] {
+        Value::Class(other) => (module_of(receiver), other.clone()),
+        _ => return Err(interpreter.error("TypeError", "compared with non class/module")),
+    };
+    if Rc::ptr_eq(&module, &other) { return Ok(Value::Bool(or_equal)); }
+
+    if module.inherits(&other) {
+        Ok(Value::Bool(true))
+    } else if other.inherits(&module) {
+        Ok(Value::Bool(false))
+    } else {
+        Ok(Value::Nil)
+    }
+}
+
+fn ancestors(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::array(module_of(receiver).ancestors().into_iter().map(Value::Class).collect()))
+}
+
+/// Includes the modules, the last argument first so they end up in the ancestors in the
+/// order given, and calls their `included` hooks.
+fn include(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, usize::MAX)?;
+    let target = module_of(receiver);
+    for module in args.iter().rev() {
+        let module = expect_module(interpreter, module)?;
+        if module.inherits(&target) {
+            return Err(interpreter.error("ArgumentError", "cyclic include detected"));
+        }
+        include_module(&target, &module);
+        interpreter.call_method(&Value::Class(module), "included", slice::from_ref(receiver), None)?;
+    }
+
+    Ok(receiver.clone())
+}
+
+/// Puts the modules in front of the class in its ancestors, so their methods override its
+/// own and can call them with `super`.
+fn prepend(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, usize::MAX)?;
+    let target = module_of(receiver);
+    for module in args.iter().rev() {
+        let module = expect_module(interpreter, module)?;
+        if module.inherits(&target) {
+            return Err(interpreter.error("ArgumentError", "cyclic prepend detected"));
+        }
+        if !target.prepends.borrow().iter().any(|prepended| Rc::ptr_eq(prepended, &module)) {
+            target.prepends.borrow_mut().insert(0, module.clone());
+        }
+        interpreter.call_method(&Value::Class(module), "prepended", slice::from_ref(receiver), None)?;
+    }
+
+    Ok(receiver.clone())
+}
+
+fn includes(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let module = expect_module(interpreter, &args[0])?;
+    let target = module_of(receiver);
+    Ok(Value::Bool(!Rc::ptr_eq(&target, &module) && target.inherits(&module)))
+}
+
+fn instance_methods(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], filter: fn(Visibility) -> bool) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let inherit = args.first().is_none_or(Value::is_truthy);
+    let names = method_names(&module_of(receiver), inherit, filter);
+    Ok(Value::array(names.iter().map(|name| Value::symbol(name)).collect()))
+}
+
+fn method_defined(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], filter: fn(Visibility) -> bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let name = expect_name(interpreter, &args[0])?;
+    Ok(Value::Bool(module_of(receiver).find_method(&name).is_some_and(|(method, _)| filter(method.visibility))))
+}
+
+/// Defines a reader, a writer or both for each name, returning the names of the methods.
+fn define_attributes(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], reader: bool, writer: bool) -> EvalResult {
+    let module = module_of(receiver);
+    let visibility = default_visibility(interpreter, &module);
+    let mut defined = vec!();
+    for arg in args.iter() {
+        let name = expect_name(interpreter, arg)?;
+        let ivar = format!("@{}", name);
+        if reader {
+            module.define(&name, Method { body: MethodBody::AttrReader(ivar.clone()), visibility });
+            defined.push(Value::symbol(&name));
+        }
+        if writer {
+            let setter = format!("{}=", name);
+            module.define(&setter, Method { body: MethodBody::AttrWriter(ivar), visibility });
+            defined.push(Value::symbol(&setter));
+        }
+    }
+
+    Ok(Value::array(defined))
+}
+
+/// With names, copies the methods to the module's singleton class and makes the instance
+/// methods private. Without, does the same for every method defined after it.
+fn module_function(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let module = module_of(receiver);
+    if args.is_empty() {
+        let frame = interpreter.frames.last_mut().expect("the top level frame is never popped");
+        frame.module_function = true;
+        return Ok(Value::Nil);
+    }
+
+    let metaclass = interpreter.metaclass(&module);
+    for name in names(interpreter, args)? {
+        let method = find_method(interpreter, &module, &name)?;
+        metaclass.define(&name, Method { visibility: Visibility::Public, ..method.clone() });
+        module.define(&name, Method { visibility: Visibility::Private, ..method });
+    }
+
+    Ok(if args.len() == 1 { args[0].clone() } else { Value::array(args.to_vec()) })
+}
+
+fn class_method_visibility(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], visibility: Visibility) -> EvalResult {
+    let metaclass = interpreter.metaclass(&module_of(receiver));
+    if args.is_empty() { return Ok(Value::Nil); }
+    set_visibility(interpreter, &metaclass, args, visibility)?;
+    Ok(Value::Nil)
+}
+
+/// Defines a method whose body is the block, or a proc given as the second argument.
+fn define_method(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let module = module_of(receiver);
+    let name = expect_name(interpreter, &args[0])?;
+    let body = match (args.get(1), block) {
+        (Some(Value::Proc(proc)), _) | (None, Some(proc)) => proc.clone(),
+        (Some(value), _) => {
+            let message = format!("wrong argument type {} (expected Proc/Method/UnboundMethod)", interpreter.class_of(value).inspect());
+            return Err(interpreter.error("TypeError", &message));
+        },
+        (None, None) => return Err(interpreter.error("ArgumentError", "tried to create Proc object without a block")),
+    };
+    let visibility = default_visibility(interpreter, &module);
+    module.define(&name, Method { body: MethodBody::Proc(body), visibility });
+
+    Ok(Value::symbol(&name))
+}
+
+fn alias_method(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 2)?;
+    let module = module_of(receiver);
+    let new_name = expect_name(interpreter, &args[0])?;
+    let old_name = expect_name(interpreter, &args[1])?;
+    let method = find_method(interpreter, &module, &old_name)?;
+    module.define(&new_name, method);
+
+    Ok(Value::symbol(&new_name))
+}
+
+/// A constant of the module or its ancestors, or a path like `"Outer::Inner"`.
+fn const_get(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let path = expect_name(interpreter, &args[0])?;
+    let mut value = receiver.clone();
+    for name in path.split("::") {
+        let module = match &value {
+            Value::Class(module) => module.clone(),
+            value => {
+                let message = format!("{} is not a class/module", interpreter.inspect(value)?);
+                return Err(interpreter.error("TypeError", &message));
+            }
+        };
+        value = match lookup_constant(interpreter, &module, name)? {
+            Some(value) => value,
+            None => {
+                let message = format!("uninitialized constant {}", interpreter.qualified_name(&module, name));
+                return Err(interpreter.name_error("NameError", &message, &Value::Class(module), name));
+            }
+        };
+    }
+
+    Ok(value)
+}
+
+fn lookup_constant(interpreter: &Interpreter, module: &Rc<RClass>, name: &str) -> Result<Option<Value>, Control> {
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err(interpreter.error("NameError", &format!("wrong constant name {}", name)));
+    }
+
+    Ok(module.ancestors().iter()
+        .find_map(|ancestor| ancestor.constants.borrow().get(name).cloned())
+        .or_else(|| interpreter.constant(name)))
+}
+
+fn const_set(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 2)?;
+    let name = expect_name(interpreter, &args[0])?;
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err(interpreter.error("NameError", &format!("wrong constant name {}", name)));
+    }
+    interpreter.assign_constant(&module_of(receiver), &name, args[1].clone());
+
+    Ok(args[1].clone())
+}
+
+fn const_defined(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let name = expect_name(interpreter, &args[0])?;
+    Ok(Value::Bool(lookup_constant(interpreter, &module_of(receiver), &name)?.is_some()))
+}
+
+fn constants(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut names: Vec<String> = module_of(receiver).constants.borrow().keys().cloned().collect();
+    names.sort();
+    Ok(Value::array(names.iter().map(|name| Value::symbol(name)).collect()))
+}
+
+fn class_variable_name(interpreter: &mut Interpreter, value: &Value) -> Result<String, Control> {
+    let name = expect_name(interpreter, value)?;
+    if !name.starts_with("@@") {
+        let message = format!("'{}' is not allowed as a class variable name", name);
+        return Err(interpreter.error("NameError", &message));
+    }
+
+    Ok(name)
+}
+
+fn class_variable_get(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let name = class_variable_name(interpreter, &args[0])?;
+    let module = module_of(receiver);
+    match interpreter.class_variable(&module, &name) {
+        Some(value) => Ok(value),
+        None => {
+            let message = format!("uninitialized class variable {} in {}", name, module.inspect());
+            Err(interpreter.error("NameError", &message))
+        }
+    }
+}
+
+fn class_variable_set(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 2)?;
+    let name = class_variable_name(interpreter, &args[0])?;
+    interpreter.set_class_variable(&module_of(receiver), &name, args[1].clone());
+    Ok(args[1].clone())
+}
+
+fn class_variable_defined(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let name = class_variable_name(interpreter, &args[0])?;
+    Ok(Value::Bool(interpreter.class_variable(&module_of(receiver), &name).is_some()))
+}
+
+fn class_variables(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut names: Vec<String> = vec!();
+    for ancestor in module_of(receiver).ancestors() {
+        let mut own: Vec<String> = ancestor.class_variables.borrow().keys().cloned().collect();
+        own.sort();
+        names.extend(own.into_iter().filter(|name| !names.contains(name)).collect::<Vec<String>>());
+    }
+
+    Ok(Value::array(names.iter().map(|name| Value::symbol(name)).collect()))
+}
+
+/// Runs the block with the module as `self` and as where `def` defines methods.
+fn class_eval(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    match block {
+        Some(block) => interpreter.call_proc_as(block, receiver.clone(), module_of(receiver), slice::from_ref(receiver)),
+        None => Err(interpreter.error("ArgumentError", "wrong number of arguments (given 0, expected 1..3)")),
+    }
+}
+
+/// Allocates an object and passes the arguments to its `initialize`. Classes whose instances
+/// have a native representation can't be instantiated this way.
+fn new(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let object = allocate(interpreter, receiver, &[], None)?;
+    interpreter.call_method(&object, "initialize", args, block)?;
+    Ok(object)
+}
+
+fn allocate(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let class = module_of(receiver);
+    let classes = &interpreter.classes;
+    let native = [
+        &classes.nil, &classes.true_class, &classes.false_class, &classes.numeric, &classes.string, &classes.symbol,
+        &classes.array, &classes.hash, &classes.range, &classes.proc_class, &classes.module,
+    ];
+    if class.is_singleton() {
+        return Err(interpreter.error("TypeError", "can't create instance of singleton class"));
+    }
+    if native.iter().any(|native| class.inherits(native)) {
+        let message = format!("undefined method 'new' for class {}", class.inspect());
+        return Err(interpreter.error("NoMethodError", &message));
+    }
+
+    Ok(Value::object(class))
+}
+
+fn superclass(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(module_of(receiver).superclass().map_or(Value::Nil, Value::Class))
+}
+
+/// `Class.new(superclass) { body }` creates an anonymous class, named by the first constant
+/// it is assigned to.
+fn class_new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let superclass = match args.first() {
+        None => interpreter.classes.object.clone(),
+        Some(Value::Class(class)) if !class.is_module => class.clone(),
+        Some(value) => {
+            let message = format!("superclass must be an instance of Class (given an instance of {})", interpreter.class_of(value).inspect());
+            return Err(interpreter.error("TypeError", &message));
+        }
+    };
+    let class = interpreter.define_class(None, "", superclass)?;
+    let value = Value::Class(class.clone());
+    if let Some(block) = block {
+        interpreter.call_proc_as(block, value.clone(), class, slice::from_ref(&value))?;
+    }
+
+    Ok(value)
+}
+
+fn module_new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let module = RClass::module("");
+    let value = Value::Class(module.clone());
+    if let Some(block) = block {
+        interpreter.call_proc_as(block, value.clone(), module, slice::from_ref(&value))?;
+    }
+
+    Ok(value)
+}
@@ -0,0 +1,933 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
+use std::hash::{ Hash, Hasher };
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::bigint::BigInt;
+use crate::eval::builtins::{ check_arity, expect_integer };
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::value::{ Encoding, RProc, Value };
+
+/// The largest power `**` computes exactly, in bits; anything bigger is `Infinity`.
+const MAX_POWER_BITS: usize = 1 << 26;
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+}
+
+impl Op {
+    fn name(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Subtract => "-",
+            Op::Multiply => "*",
+            Op::Divide => "/",
+            Op::Modulo => "%",
+            Op::Power => "**",
+        }
+    }
+}
+
+/// Which way `round`, `floor`, `ceil` and `truncate` go, the `Half` modes being the choices
+/// `round(half:)` has for a value exactly between two results.
+#[derive(Clone, Copy, PartialEq)]
+enum Rounding {
+    Floor,
+    Ceil,
+    Truncate,
+    HalfUp,
+    HalfEven,
+    HalfDown,
+}
+
+pub fn define(interpreter: &mut Interpreter) {
+    for class in [interpreter.classes.integer.clone(), interpreter.classes.float.clone()].iter() {
+        class.define_builtin("+", |i, r, a, _| arithmetic(i, r, a, Op::Add));
+        class.define_builtin("-", |i, r, a, _| arithmetic(i, r, a, Op::Subtract));
+        class.define_builtin("*", |i, r, a, _| arithmetic(i, r, a, Op::Multiply));
+        class.define_builtin("/", |i, r, a, _| arithmetic(i, r, a, Op::Divide));
+        class.define_builtin("%", |i, r, a, _| arithmetic(i, r, a, Op::Modulo));
+        class.define_builtin("modulo", |i, r, a, _| arithmetic(i, r, a, Op::Modulo));
+        class.define_builtin("**", |i, r, a, _| arithmetic(i, r, a, Op::Power));
+        class.define_builtin("div", div);
+        class.define_builtin("divmod", divmod);
+        class.define_builtin("remainder", remainder);
+        class.define_builtin("fdiv", |i, r, a, _| {
+            check_arity(i, a, 1, 1)?;
+            if !is_number(&a[0]) { return Err(coercion_error(i, r, &a[0])); }
+            Ok(Value::Float(float_of(r) / float_of(&a[0])))
+        });
+        class.define_builtin("==", equal);
+        class.define_builtin("===", equal);
+        class.define_builtin("<=>", compare);
+        class.define_builtin("<", |i, r, a, _| comparison(i, r, a, "<", |o| o == Ordering::Less));
+        class.define_builtin("<=", |i, r, a, _| comparison(i, r, a, "<=", |o| o != Ordering::Greater));
+        class.define_builtin(">", |i, r, a, _| comparison(i, r, a, ">", |o| o == Ordering::Greater));
+        class.define_builtin(">=", |i, r, a, _| comparison(i, r, a, ">=", |o| o != Ordering::Less));
+        class.define_builtin("coerce", coerce);
+        class.define_builtin("eql?", eql);
+        class.define_builtin("+@", |_, r, _, _| Ok(r.clone()));
+        class.define_builtin("-@", negate);
+        class.define_builtin("abs", abs);
+        class.define_builtin("zero?", zero);
+        class.define_builtin("to_s", to_s);
+        class.define_builtin("inspect", to_s);
+        class.define_builtin("round", round);
+        class.define_builtin("floor", |i, r, a, _| round_with(i, r, a, Rounding::Floor));
+        class.define_builtin("ceil", |i, r, a, _| round_with(i, r, a, Rounding::Ceil));
+        class.define_builtin("truncate", |i, r, a, _| round_with(i, r, a, Rounding::Truncate));
+    }
+
+    let integer = interpreter.classes.integer.clone();
+    integer.define_builtin("&", |i, r, a, _| bitwise(i, r, a, |a, b| a & b, BigInt::and));
+    integer.define_builtin("|", |i, r, a, _| bitwise(i, r, a, |a, b| a | b, BigInt::or));
+    integer.define_builtin("^", |i, r, a, _| bitwise(i, r, a, |a, b| a ^ b, BigInt::xor));
+    integer.define_builtin("<<", |i, r, a, _| shift(i, r, a, false));
+    integer.define_builtin(">>", |i, r, a, _| shift(i, r, a, true));
+    integer.define_builtin("[]", bit);
+    integer.define_builtin("~", |_, r, _, _| Ok(Value::integer(big_of(r).not())));
+    integer.define_builtin("to_i", |_, r, _, _| Ok(r.clone()));
+    integer.define_builtin("to_int", |_, r, _, _| Ok(r.clone()));
+    integer.define_builtin("to_f", |_, r, _, _| Ok(Value::Float(float_of(r))));
+    integer.define_builtin("to_s", integer_to_s);
+    integer.define_builtin("inspect", integer_to_s);
+    integer.define_builtin("hash", integer_hash);
+    integer.define_builtin("chr", chr);
+    integer.define_builtin("even?", |_, r, _, _| Ok(Value::Bool(big_of(r).is_even())));
+    integer.define_builtin("odd?", |_, r, _, _| Ok(Value::Bool(!big_of(r).is_even())));
+    integer.define_builtin("succ", |i, r, _, _| integer_arithmetic(i, r, &Value::Integer(1), Op::Add));
+    integer.define_builtin("next", |i, r, _, _| integer_arithmetic(i, r, &Value::Integer(1), Op::Add));
+    integer.define_builtin("pred", |i, r, _, _| integer_arithmetic(i, r, &Value::Integer(1), Op::Subtract));
+    integer.define_builtin("pow", pow);
+    integer.define_builtin("bit_length", |_, r, _, _| Ok(Value::Integer(big_of(r).bit_length() as i64)));
+    integer.define_builtin("digits", digits);
+    integer.define_builtin("gcd", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let other = expect_big(i, &a[0])?;
+        Ok(Value::integer(gcd(big_of(r), other)))
+    });
+    integer.define_builtin("lcm", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let (a, b) = (big_of(r), expect_big(i, &a[0])?);
+        if a.is_zero() || b.is_zero() { return Ok(Value::Integer(0)); }
+        let divisor = gcd(a.clone(), b.clone());
+        Ok(Value::integer(a.mul(&b).abs().div_rem(&divisor).0))
+    });
+    integer.define_builtin("times", times);
+    integer.define_builtin("upto", |i, r, a, b| count_to(i, r, a, b, false));
+    integer.define_builtin("downto", |i, r, a, b| count_to(i, r, a, b, true));
+
+    let float = interpreter.classes.float.clone();
+    float.define_builtin("to_f", |_, r, _, _| Ok(r.clone()));
+    float.define_builtin("to_i", |i, r, _, _| to_integer(i, float_of(r).trunc()));
+    float.define_builtin("nan?", |_, r, _, _| Ok(Value::Bool(float_of(r).is_nan())));
+    float.define_builtin("finite?", |_, r, _, _| Ok(Value::Bool(float_of(r).is_finite())));
+    float.define_builtin("infinite?", |_, r, _, _| {
+        let value = float_of(r);
+        Ok(if value.is_infinite() { Value::Integer(value.signum() as i64) } else { Value::Nil })
+    });
+    float.set_constant("INFINITY", Value::Float(f64::INFINITY));
+    float.set_constant("NAN", Value::Float(f64::NAN));
+    float.set_constant("EPSILON", Value::Float(f64::EPSILON));
+    float.set_constant("MAX", Value::Float(f64::MAX));
+    float.set_constant("MIN", Value::Float(f64::MIN_POSITIVE));
+    float.set_constant("DIG", Value::Integer(f64::DIGITS as i64));
+    float.set_constant("MANT_DIG", Value::Integer(f64::MANTISSA_DIGITS as i64));
+}
+
+fn is_integer(value: &Value) -> bool {
+    matches!(value, Value::Integer(_) | Value::Bignum(_))
+}
+
+fn is_number(value: &Value) -> bool {
+    matches!(value, Value::Integer(_) | Value::Bignum(_) | Value::Float(_))
+}
+
+pub fn big_of(value: &Value) -> BigInt {
+    match value {
+        Value::Integer(value) => BigInt::from_i64(*value),
+        Value::Bignum(value) => (**value).clone(),
+        _ => unreachable!("an Integer method called on {:?}", value),
+    }
+}
+
+fn expect_big(interpreter: &Interpreter, value: &Value) -> Result<BigInt, Control> {
+    match value {
+        Value::Integer(_) | Value::Bignum(_) => Ok(big_of(value)),
+        value => Err(super::conversion_error(interpreter, value, "Integer")),
+    }
+}
+
+pub fn float_of(value: &Value) -> f64 {
+    match value {
+        Value::Integer(value) => *value as f64,
+        Value::Bignum(value) => value.to_f64(),
+        Value::Float(value) => *value,
+        _ => unreachable!("a Float method called on {:?}", value),
+    }
+}
+
+/// The integer part of a float, which must be finite.
+fn to_integer(interpreter: &Interpreter, value: f64) -> EvalResult {
+    if value.is_finite() && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        return Ok(Value::Integer(value as i64));
+    }
+    if value.is_finite() {
+        return Ok(Value::integer(BigInt::from_f64(value)));
+    }
+
+    Err(interpreter.error("FloatDomainError", &float_to_s(value)))
+}
+
+/// A number, or a string holding one, as a float; what `Float()` accepts.
+pub fn to_float(interpreter: &mut Interpreter, value: &Value) -> Result<f64, Control> {
+    match value {
+        value if is_number(value) => Ok(float_of(value)),
+        Value::String(text) => {
+            let text = text.text();
+            match parse_float(&text) {
+                Some(value) => Ok(value),
+                None => Err(interpreter.error("ArgumentError", &format!("invalid value for Float(): {}", super::string::inspect(&text)))),
+            }
+        },
+        Value::Nil => Err(interpreter.error("TypeError", "can't convert nil into Float")),
+        value => {
+            if interpreter.lookup_class(value).find_method("to_f").is_none() {
+                return Err(super::conversion_error(interpreter, value, "Float"));
+            }
+            match interpreter.call_method(value, "to_f", &[], None)? {
+                Value::Float(value) => Ok(value),
+                other => Err(super::conversion_error(interpreter, &other, "Float")),
+            }
+        },
+    }
+}
+
+/// A float literal with optional surrounding whitespace and underscores between digits, or
+/// an integer in any of the forms `Integer()` takes.
+pub fn parse_float(text: &str) -> Option<f64> {
+    let trimmed = text.trim();
+    let bytes = trimmed.as_bytes();
+    let underscores_ok = bytes.iter().enumerate().all(|(i, byte)| {
+        *byte != b'_' || (i > 0 && bytes[i - 1].is_ascii_digit() && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+    });
+    let digits = trimmed.replace('_', "");
+    let unsigned = digits.trim_start_matches(['+', '-']);
+    if !underscores_ok || unsigned.is_empty() || !unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.') || unsigned.ends_with('.') {
+        return None;
+    }
+    if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+        return parse_integer(trimmed, None).map(|value| float_of(&value));
+    }
+    if !unsigned.bytes().all(|byte| byte.is_ascii_digit() || matches!(byte, b'.' | b'e' | b'E' | b'+' | b'-')) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// How Ruby prints a `Float`: the shortest digits that read back as the same value, always
+/// with a fractional part, and in exponent form like `1.0e+20` or `1.0e-05` when the point
+/// would be more than 16 places right or 4 places left of the first digit.
+pub fn float_to_s(value: f64) -> String {
+    if value.is_nan() { return String::from("NaN"); }
+    if value.is_infinite() { return String::from(if value > 0.0 { "Infinity" } else { "-Infinity" }); }
+    if value == 0.0 { return String::from(if value.is_sign_negative() { "-0.0" } else { "0.0" }); }
+
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').expect("LowerExp always has an exponent");
+    let digits = mantissa.replace('.', "");
+    let exponent: i64 = exponent.parse().expect("LowerExp exponents are integers");
+    // how many digits come before the point
+    let point = exponent + 1;
+    let body = if !(-3..=16).contains(&point) {
+        let (first, rest) = digits.split_at(1);
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}.{}e{}{:02}", first, if rest.is_empty() { "0" } else { rest }, sign, exponent.abs())
+    } else if point <= 0 {
+        format!("0.{}{}", "0".repeat(point.unsigned_abs() as usize), digits)
+    } else if point as usize >= digits.len() {
+        format!("{}{}.0", digits, "0".repeat(point as usize - digits.len()))
+    } else {
+        let (whole, fraction) = digits.split_at(point as usize);
+        format!("{}.{}", whole, fraction)
+    };
+
+    if value < 0.0 { format!("-{}", body) } else { body }
+}
+
+/// The `TypeError` for a numeric operation with something that isn't a number.
+fn coercion_error(interpreter: &Interpreter, receiver: &Value, other: &Value) -> Control {
+    let other = match other {
+        Value::Nil => String::from("nil"),
+        Value::Bool(value) => value.to_string(),
+        other => interpreter.class_of(other).name(),
+    };
+    let message = format!("{} can't be coerced into {}", other, interpreter.class_of(receiver).name());
+    interpreter.error("TypeError", &message)
+}
+
+/// Retries `receiver.method(other)` with the pair `other.coerce(receiver)` returns, or gives
+/// `None` when `other` is a builtin number or doesn't know how to coerce.
+fn coerced(interpreter: &mut Interpreter, receiver: &Value, other: &Value, method: &str) -> Option<EvalResult> {
+    if is_number(other) || interpreter.lookup_class(other).find_method("coerce").is_none() {
+        return None;
+    }
+    let pair = match interpreter.call_method(other, "coerce", slice::from_ref(receiver), None) {
+        Ok(Value::Array(pair)) if pair.elements.borrow().len() == 2 => pair.elements.borrow().clone(),
+        Ok(_) => return Some(Err(interpreter.error("TypeError", "coerce must return [x, y]"))),
+        Err(control) => return Some(Err(control)),
+    };
+
+    Some(interpreter.call_method(&pair[0], method, &pair[1..], None))
+}
+
+/// `coerced`, or the `TypeError` when there's nothing to coerce with.
+fn coerce_or_fail(interpreter: &mut Interpreter, receiver: &Value, other: &Value, method: &str) -> EvalResult {
+    match coerced(interpreter, receiver, other, method) {
+        Some(result) => result,
+        None => Err(coercion_error(interpreter, receiver, other)),
+    }
+}
+
+fn arithmetic(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], op: Op) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match (receiver, &args[0]) {
+        (a, b) if is_integer(a) && is_integer(b) => integer_arithmetic(interpreter, a, b, op),
+        (a, b) if is_number(a) && is_number(b) => Ok(Value::Float(float_arithmetic(float_of(a), float_of(b), op))),
+        (receiver, other) => coerce_or_fail(interpreter, receiver, other, op.name()),
+    }
+}
+
+/// Integer operations stay in machine words until they would overflow, then continue as
+/// bignums. Division and modulo round towards negative infinity, so `-7 / 2` is `-4` and
+/// `-7 % 3` is `2`.
+fn integer_arithmetic(interpreter: &Interpreter, a: &Value, b: &Value, op: Op) -> EvalResult {
+    if let (Value::Integer(a), Value::Integer(b)) = (a, b) {
+        let (a, b) = (*a, *b);
+        let result = match op {
+            _ if b == 0 && matches!(op, Op::Divide | Op::Modulo) => return Err(interpreter.error("ZeroDivisionError", "divided by 0")),
+            Op::Add => a.checked_add(b),
+            Op::Subtract => a.checked_sub(b),
+            Op::Multiply => a.checked_mul(b),
+            Op::Divide => a.checked_div(b).map(|q| if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q }),
+            Op::Modulo => a.checked_rem(b).map(|r| if r != 0 && (r < 0) != (b < 0) { r + b } else { r }),
+            Op::Power if b < 0 => return Ok(Value::Float((a as f64).powf(b as f64))),
+            Op::Power => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+        };
+        if let Some(result) = result { return Ok(Value::Integer(result)); }
+    }
+
+    let (a, b) = (big_of(a), big_of(b));
+    if b.is_zero() && matches!(op, Op::Divide | Op::Modulo) {
+        return Err(interpreter.error("ZeroDivisionError", "divided by 0"));
+    }
+    let result = match op {
+        Op::Add => a.add(&b),
+        Op::Subtract => a.sub(&b),
+        Op::Multiply => a.mul(&b),
+        Op::Divide => a.div_mod_floor(&b).0,
+        Op::Modulo => a.div_mod_floor(&b).1,
+        Op::Power => return Ok(power(&a, &b)),
+    };
+
+    Ok(Value::integer(result))
+}
+
+/// `a ** b`, which is a float for a negative exponent and `Infinity` when the result would
+/// be unreasonably big.
+fn power(a: &BigInt, b: &BigInt) -> Value {
+    if b.is_negative() {
+        return Value::Float(a.to_f64().powf(b.to_f64()));
+    }
+    // 0, 1 and -1 stay small whatever the exponent
+    if a.magnitude_bits() <= 1 {
+        let negative = a.is_negative() && !b.is_even();
+        let value = if a.is_zero() && !b.is_zero() { 0 } else if negative { -1 } else { 1 };
+        return Value::Integer(value);
+    }
+    match b.to_i64() {
+        Some(exponent) if (exponent as u128) * (a.magnitude_bits() as u128) <= MAX_POWER_BITS as u128 => {
+            Value::integer(a.pow(exponent as u64))
+        },
+        _ => Value::Float(if a.is_negative() && !b.is_even() { f64::NEG_INFINITY } else { f64::INFINITY }),
+    }
+}
+
+fn float_arithmetic(a: f64, b: f64, op: Op) -> f64 {
+    match op {
+        Op::Add => a + b,
+        Op::Subtract => a - b,
+        Op::Multiply => a * b,
+        Op::Divide => a / b,
+        Op::Modulo => {
+            let r = a % b;
+            if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r }
+        },
+        Op::Power => a.powf(b),
+    }
+}
+
+/// `div` is floor division with an `Integer` result, even for floats.
+fn div(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match (receiver, &args[0]) {
+        (a, b) if is_integer(a) && is_integer(b) => integer_arithmetic(interpreter, a, b, Op::Divide),
+        (a, b) if is_number(a) && is_number(b) => {
+            if float_of(b) == 0.0 { return Err(interpreter.error("ZeroDivisionError", "divided by 0")); }
+            to_integer(interpreter, (float_of(a) / float_of(b)).floor())
+        },
+        (receiver, other) => coerce_or_fail(interpreter, receiver, other, "div"),
+    }
+}
+
+/// `[a.div(b), a % b]`.
+fn divmod(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let quotient = div(interpreter, receiver, args, block)?;
+    let modulo = arithmetic(interpreter, receiver, args, Op::Modulo)?;
+    Ok(Value::array(vec!(quotient, modulo)))
+}
+
+/// Like `%`, but with the sign of the receiver, as truncating division leaves it.
+fn remainder(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match (receiver, &args[0]) {
+        (a, b) if is_integer(a) && is_integer(b) => {
+            let (a, b) = (big_of(a), big_of(b));
+            if b.is_zero() { return Err(interpreter.error("ZeroDivisionError", "divided by 0")); }
+            Ok(Value::integer(a.div_rem(&b).1))
+        },
+        (a, b) if is_number(a) && is_number(b) => Ok(Value::Float(float_of(a) % float_of(b))),
+        (receiver, other) => coerce_or_fail(interpreter, receiver, other, "remainder"),
+    }
+}
+
+/// `pow(b)` is `**`; `pow(b, m)` is `(self ** b) % m` computed without the huge power.
+fn pow(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    if args.len() == 1 {
+        return arithmetic(interpreter, receiver, args, Op::Power);
+    }
+    if !is_integer(&args[0]) || !is_integer(&args[1]) {
+        return Err(interpreter.error("TypeError", "Integer#pow() 2nd argument not allowed unless all arguments are integers"));
+    }
+    let (exponent, modulus) = (big_of(&args[0]), big_of(&args[1]));
+    if exponent.is_negative() {
+        return Err(interpreter.error("RangeError", "Integer#pow() 1st argument cannot be negative when 2nd argument specified"));
+    }
+    if modulus.is_zero() {
+        return Err(interpreter.error("ZeroDivisionError", "divided by 0"));
+    }
+
+    Ok(Value::integer(big_of(receiver).pow_mod(&exponent, &modulus)))
+}
+
+fn gcd(mut a: BigInt, mut b: BigInt) -> BigInt {
+    while !b.is_zero() {
+        let remainder = a.div_rem(&b).1;
+        a = b;
+        b = remainder;
+    }
+    a.abs()
+}
+
+/// The digits from least significant up, in base 10 or the given base.
+fn digits(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let base = match args.first() {
+        Some(base) => expect_big(interpreter, base)?,
+        None => BigInt::from_i64(10),
+    };
+    if base.is_negative() { return Err(interpreter.error("ArgumentError", "negative radix")); }
+    if base.magnitude_bits() <= 1 { return Err(interpreter.error("ArgumentError", &format!("invalid radix {}", base))); }
+    let mut value = big_of(receiver);
+    if value.is_negative() { return Err(interpreter.error("ArgumentError", "out of domain")); }
+
+    let mut digits = vec!();
+    loop {
+        let (quotient, digit) = value.div_rem(&base);
+        digits.push(Value::integer(digit));
+        value = quotient;
+        if value.is_zero() { break; }
+    }
+    Ok(Value::array(digits))
+}
+
+/// Compares two numbers, or gives `None` when either isn't one or is NaN. Integers and
+/// floats compare exactly, so `2 ** 64 + 1` is more than `2.0 ** 64`.
+fn times(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "times", args)),
+    };
+    let count = expect_integer(interpreter, receiver)?;
+    for index in 0..count {
+        interpreter.call_proc(block, &[Value::Integer(index)])?;
+    }
+    Ok(receiver.clone())
+}
+
+/// `upto` and `downto`, yielding every integer from the receiver to the limit inclusive.
+fn count_to(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, down: bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, if down { "downto" } else { "upto" }, args)),
+    };
+    let (from, to) = (expect_integer(interpreter, receiver)?, expect_integer(interpreter, &args[0])?);
+    let steps: Box<dyn Iterator<Item = i64>> = if down { Box::new((to..=from).rev()) } else { Box::new(from..=to) };
+    for index in steps {
+        interpreter.call_proc(block, &[Value::Integer(index)])?;
+    }
+    Ok(receiver.clone())
+}
+
+pub fn ordering(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (a, b) if is_integer(a) && is_integer(b) => Some(big_of(a).cmp(&big_of(b))),
+        (a, Value::Float(b)) if is_integer(a) => integer_float_ordering(&big_of(a), *b),
+        (Value::Float(a), b) if is_integer(b) => integer_float_ordering(&big_of(b), *a).map(Ordering::reverse),
+        (a, b) if is_number(a) && is_number(b) => float_of(a).partial_cmp(&float_of(b)),
+        _ => None,
+    }
+}
+
+fn integer_float_ordering(integer: &BigInt, float: f64) -> Option<Ordering> {
+    if float.is_nan() { return None; }
+    if float.is_infinite() { return Some(if float > 0.0 { Ordering::Less } else { Ordering::Greater }); }
+
+    let whole = float.floor();
+    match integer.cmp(&BigInt::from_f64(whole)) {
+        Ordering::Equal if whole != float => Some(Ordering::Less),
+        ordering => Some(ordering),
+    }
+}
+
+/// Numbers are equal by value; anything else decides for itself with `other == self`.
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if !is_number(&args[0]) {
+        let equal = interpreter.call_method(&args[0], "==", slice::from_ref(receiver), None)?;
+        return Ok(Value::Bool(equal.is_truthy()));
+    }
+
+    Ok(Value::Bool(ordering(receiver, &args[0]) == Some(Ordering::Equal)))
+}
+
+fn eql(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let same_class = Rc::ptr_eq(&interpreter.class_of(receiver), &interpreter.class_of(&args[0]));
+    Ok(Value::Bool(same_class && ordering(receiver, &args[0]) == Some(Ordering::Equal)))
+}
+
+fn compare(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if let Some(result) = coerced(interpreter, receiver, &args[0], "<=>") {
+        return result;
+    }
+
+    Ok(ordering(receiver, &args[0]).map_or(Value::Nil, |ordering| Value::Integer(ordering as i64)))
+}
+
+fn comparison(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], method: &str, test: fn(Ordering) -> bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match ordering(receiver, &args[0]) {
+        Some(ordering) => Ok(Value::Bool(test(ordering))),
+        None if is_number(&args[0]) => Ok(Value::Bool(false)),
+        None => {
+            if let Some(result) = coerced(interpreter, receiver, &args[0], method) {
+                return result;
+            }
+            let other = match &args[0] {
+                Value::Nil => String::from("nil"),
+                other => interpreter.class_of(other).name(),
+            };
+            let message = format!("comparison of {} with {} failed", interpreter.class_of(receiver).name(), other);
+            Err(interpreter.error("ArgumentError", &message))
+        }
+    }
+}
+
+/// `[other, self]` converted to a common class: both integers, or else both floats.
+fn coerce(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match (receiver, &args[0]) {
+        (a, b) if is_integer(a) && is_integer(b) => Ok(Value::array(vec!(b.clone(), a.clone()))),
+        (a, b) if is_number(b) => Ok(Value::array(vec!(Value::Float(float_of(b)), Value::Float(float_of(a))))),
+        (_, other) => {
+            let into = if is_integer(receiver) { Value::Integer(0) } else { Value::Float(0.0) };
+            Err(coercion_error(interpreter, &into, other))
+        },
+    }
+}
+
+/// `round(digits = 0, half: :up)`, where `half` is `:up`, `:even` or `:down`.
+fn round(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let (args, options) = match args.split_last() {
+        Some((Value::Hash(options), rest)) => (rest, options.entries.borrow().clone()),
+        _ => (args, vec!()),
+    };
+    let mut rounding = Rounding::HalfUp;
+    for (key, value) in options.iter() {
+        match key {
+            Value::Symbol(key) if &**key == "half" => {},
+            key => {
+                let message = format!("unknown keyword: {}", interpreter.inspect(key)?);
+                return Err(interpreter.error("ArgumentError", &message));
+            },
+        }
+        rounding = match value {
+            Value::Nil => Rounding::HalfUp,
+            Value::Symbol(mode) if &**mode == "up" => Rounding::HalfUp,
+            Value::Symbol(mode) if &**mode == "even" => Rounding::HalfEven,
+            Value::Symbol(mode) if &**mode == "down" => Rounding::HalfDown,
+            mode => {
+                let message = format!("invalid rounding mode: {}", interpreter.to_s(mode)?);
+                return Err(interpreter.error("ArgumentError", &message));
+            },
+        };
+    }
+
+    round_with(interpreter, receiver, args, rounding)
+}
+
+/// Rounds to `digits` places after the point, or to a multiple of `10 ** -digits` when it's
+/// negative. Floats stay floats only for positive digits.
+fn round_with(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], rounding: Rounding) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let digits = match args.first() {
+        Some(digits) => expect_integer(interpreter, digits)?,
+        None => 0,
+    };
+    let value = match receiver {
+        Value::Float(value) => *value,
+        integer => return Ok(round_integer(integer, digits, rounding)),
+    };
+
+    if digits > 0 {
+        return Ok(Value::Float(round_float(value, digits, rounding)));
+    }
+    if digits == 0 {
+        return to_integer(interpreter, round_scaled(value, 1.0, rounding));
+    }
+    // like Ruby, the fraction goes first, so 15.5.round(-1) is 20 but 14.9.round(-1) is 10
+    let whole = match rounding {
+        Rounding::Floor => value.floor(),
+        Rounding::Ceil => value.ceil(),
+        _ => value.trunc(),
+    };
+    let integer = to_integer(interpreter, whole)?;
+
+    Ok(round_integer(&integer, digits, rounding))
+}
+
+/// The whole number `value * scale` rounds to. Ties in the half modes are decided on the decimal
+/// value written, so `2.675.round(2)` is `2.68` though the closest double is a little less.
+fn round_scaled(value: f64, scale: f64, rounding: Rounding) -> f64 {
+    let scaled = value * scale;
+    let rounded = scaled.round();
+    match rounding {
+        Rounding::Floor => scaled.floor(),
+        Rounding::Ceil => scaled.ceil(),
+        Rounding::Truncate => scaled.trunc(),
+        _ if scale == 1.0 || rounded == scaled => match rounding {
+            Rounding::HalfEven if (scaled - scaled.trunc()).abs() == 0.5 => 2.0 * (scaled / 2.0).round(),
+            Rounding::HalfDown if (scaled - scaled.trunc()).abs() == 0.5 => scaled.trunc(),
+            _ => rounded,
+        },
+        _ => {
+            let (below, above) = if scaled < rounded { (rounded - 1.0, rounded) } else { (rounded, rounded + 1.0) };
+            let halfway = (below + 0.5) / scale;
+            if value < halfway {
+                below
+            } else if value > halfway {
+                above
+            } else {
+                match rounding {
+                    Rounding::HalfEven => if below % 2.0 == 0.0 { below } else { above },
+                    Rounding::HalfDown => if value > 0.0 { below } else { above },
+                    _ => if value > 0.0 { above } else { below },
+                }
+            }
+        },
+    }
+}
+
+/// A float rounded to `digits` places after the point. Values already that precise are
+/// returned as they are.
+fn round_float(value: f64, digits: i64, rounding: Rounding) -> f64 {
+    if !value.is_finite() || value == 0.0 { return value; }
+    let exponent = binary_exponent(value);
+    if digits >= f64::DIGITS as i64 + 2 - (if exponent > 0 { exponent / 4 } else { exponent / 3 - 1 }) {
+        return value;
+    }
+    let rounding = match rounding {
+        Rounding::Truncate if value > 0.0 => Rounding::Floor,
+        Rounding::Truncate => Rounding::Ceil,
+        rounding => rounding,
+    };
+    let away = (rounding == Rounding::Floor && value < 0.0) || (rounding == Rounding::Ceil && value > 0.0);
+    if !away && digits < -(if exponent > 0 { exponent / 3 + 1 } else { exponent / 4 }) {
+        return 0.0;
+    }
+    if digits > 14 && !matches!(rounding, Rounding::Floor | Rounding::Ceil) {
+        // 10 ** digits isn't exact any more, so round the exact decimal expansion instead
+        return format!("{:.*}", digits as usize, value).parse().unwrap_or(value);
+    }
+
+    let scale = 10f64.powi(digits as i32);
+    // the product can fall just short of a whole number, as 0.29 * 100 does, so floor and
+    // ceil take the next one along when it's still on the right side of the value
+    match rounding {
+        Rounding::Floor => {
+            let whole = (value * scale).floor();
+            let next = (whole + 1.0) / scale;
+            if next > value { whole / scale } else { next }
+        },
+        Rounding::Ceil => {
+            let whole = (value * scale).ceil();
+            let next = (whole - 1.0) / scale;
+            if next < value { whole / scale } else { next }
+        },
+        _ => round_scaled(value, scale, rounding) / scale,
+    }
+}
+
+/// The exponent `e` with `2 ** (e - 1) <= |value| < 2 ** e`, like C's `frexp`.
+fn binary_exponent(value: f64) -> i64 {
+    let biased = ((value.to_bits() >> 52) & 0x7ff) as i64;
+    if biased == 0 {
+        return value.abs().log2().floor() as i64 + 1;
+    }
+    biased - 1022
+}
+
+/// An integer rounded to a multiple of `10 ** -digits`; other digits leave it alone.
+fn round_integer(value: &Value, digits: i64, rounding: Rounding) -> Value {
+    if digits >= 0 { return value.clone(); }
+    let value = big_of(value);
+    // a unit with more decimal digits than the value has bits is bigger than the value
+    if digits.unsigned_abs() > value.magnitude_bits() as u64 + 1 && !matches!(rounding, Rounding::Floor | Rounding::Ceil) {
+        return Value::Integer(0);
+    }
+
+    let unit = BigInt::from_i64(10).pow(digits.unsigned_abs());
+    let (quotient, remainder) = value.div_mod_floor(&unit);
+    let below = quotient.mul(&unit);
+    if remainder.is_zero() { return Value::integer(below); }
+    let above = below.add(&unit);
+    let result = match rounding {
+        Rounding::Floor => below,
+        Rounding::Ceil => above,
+        Rounding::Truncate => if value.is_negative() { above } else { below },
+        _ => match remainder.add(&remainder).cmp(&unit) {
+            Ordering::Less => below,
+            Ordering::Greater => above,
+            Ordering::Equal => match rounding {
+                Rounding::HalfEven => if quotient.is_even() { below } else { above },
+                Rounding::HalfDown => if value.is_negative() { above } else { below },
+                _ => if value.is_negative() { below } else { above },
+            },
+        },
+    };
+
+    Value::integer(result)
+}
+
+/// `&`, `|` and `^`, which treat negative numbers as two's complement with infinitely many
+/// leading ones, bignums included.
+fn bitwise(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], small: fn(i64, i64) -> i64, big: fn(&BigInt, &BigInt) -> BigInt) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match (receiver, &args[0]) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(small(*a, *b))),
+        (a, b) if is_integer(b) => Ok(Value::integer(big(&big_of(a), &big_of(b)))),
+        (receiver, other) => Err(coercion_error(interpreter, receiver, other)),
+    }
+}
+
+/// `<<`, or `>>` when `right`. A negative count shifts the other way, and shifting right
+/// rounds towards negative infinity.
+fn shift(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], right: bool) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let count = match &args[0] {
+        Value::Integer(count) => *count,
+        // a bignum count shifts everything out, or is more than memory allows
+        Value::Bignum(count) if count.is_negative() != right => return Err(interpreter.error("RangeError", "shift width too big")),
+        Value::Bignum(_) => return Ok(Value::Integer(if big_of(receiver).is_negative() { -1 } else { 0 })),
+        other => return Err(coercion_error(interpreter, receiver, other)),
+    };
+    let count = if right { count.saturating_neg() } else { count };
+    let value = big_of(receiver);
+    if count < 0 {
+        return Ok(Value::integer(value.shr(usize::try_from(count.unsigned_abs()).unwrap_or(usize::MAX))));
+    }
+    if !value.is_zero() && value.magnitude_bits() as u64 + count as u64 > MAX_POWER_BITS as u64 {
+        return Err(interpreter.error("RangeError", "shift width too big"));
+    }
+
+    Ok(Value::integer(value.shl(count as usize)))
+}
+
+/// `n[i]`, the bit at position `i` in two's complement.
+fn bit(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let value = big_of(receiver);
+    let index = match &args[0] {
+        Value::Integer(index) if *index < 0 => return Ok(Value::Integer(0)),
+        Value::Integer(index) => *index as usize,
+        Value::Bignum(index) if index.is_negative() => return Ok(Value::Integer(0)),
+        Value::Bignum(_) => return Ok(Value::Integer(i64::from(value.is_negative()))),
+        other => return Err(coercion_error(interpreter, receiver, other)),
+    };
+
+    Ok(Value::Integer(i64::from(!value.shr(index).is_even())))
+}
+
+fn negate(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    match receiver {
+        Value::Integer(_) | Value::Bignum(_) => integer_arithmetic(interpreter, &Value::Integer(0), receiver, Op::Subtract),
+        receiver => Ok(Value::Float(-float_of(receiver))),
+    }
+}
+
+fn abs(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    match receiver {
+        Value::Integer(value) if *value != i64::MIN => Ok(Value::Integer(value.abs())),
+        Value::Integer(_) | Value::Bignum(_) => Ok(Value::integer(big_of(receiver).abs())),
+        receiver => Ok(Value::Float(float_of(receiver).abs())),
+    }
+}
+
+fn zero(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Bool(float_of(receiver) == 0.0))
+}
+
+fn to_s(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::string(&float_to_s(float_of(receiver))))
+}
+
+/// The digits in base 10, or in the base given, from 2 to 36.
+fn integer_to_s(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let base = match args.first() {
+        Some(base) => expect_integer(interpreter, base)?,
+        None => 10,
+    };
+    if !(2..=36).contains(&base) {
+        return Err(interpreter.error("ArgumentError", &format!("invalid radix {}", base)));
+    }
+
+    match receiver {
+        Value::Integer(value) if base == 10 => Ok(Value::string(&value.to_string())),
+        receiver => Ok(Value::string(&big_of(receiver).to_string_radix(base as u32))),
+    }
+}
+
+fn integer_hash(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut hasher = DefaultHasher::new();
+    match receiver {
+        Value::Integer(value) => value.hash(&mut hasher),
+        receiver => big_of(receiver).hash(&mut hasher),
+    }
+    Ok(Value::Integer(hasher.finish() as i64))
+}
+
+/// `Integer(value, base = nil)`: parses a string strictly, with an optional sign, a `0x`,
+/// `0b`, `0o` or `0` prefix and underscores between digits, or truncates a number.
+pub fn kernel_integer(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let base = match args.get(1) {
+        Some(Value::Nil) | None => None,
+        Some(base) => Some(expect_integer(interpreter, base)?),
+    };
+    let text = match (&args[0], base) {
+        (Value::String(text), _) => text.text(),
+        (_, Some(_)) => return Err(interpreter.error("ArgumentError", "base specified for non string value")),
+        (value, None) if is_integer(value) => return Ok(value.clone()),
+        (Value::Float(value), None) => return to_integer(interpreter, value.trunc()),
+        (Value::Nil, None) => return Err(interpreter.error("TypeError", "can't convert nil into Integer")),
+        (value, None) => {
+            for conversion in ["to_int", "to_i"] {
+                if interpreter.lookup_class(value).find_method(conversion).is_some() {
+                    return interpreter.call_method(value, conversion, &[], None);
+                }
+            }
+            let message = format!("can't convert {} into Integer", interpreter.class_of(value).inspect());
+            return Err(interpreter.error("TypeError", &message));
+        },
+    };
+    if let Some(base) = base {
+        if base == 1 || !(0..=36).contains(&base.abs()) {
+            return Err(interpreter.error("ArgumentError", &format!("invalid radix {}", base)));
+        }
+    }
+
+    match parse_integer(&text, base.map(|base| base.unsigned_abs() as u32).filter(|base| *base != 0)) {
+        Some(value) => Ok(value),
+        None => {
+            let message = format!("invalid value for Integer(): {}", interpreter.inspect(&args[0])?);
+            Err(interpreter.error("ArgumentError", &message))
+        }
+    }
+}
+
+/// `chr(encoding = nil)`: the character with this code, US-ASCII below 128 and binary up to
+/// 255 unless an encoding is given.
+fn chr(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let code = expect_integer(interpreter, receiver)?;
+    let encoding = match args.first() {
+        Some(encoding) => Some(super::encoding::expect_encoding(interpreter, encoding)?),
+        None => None,
+    };
+    let out_of_range = || interpreter.error("RangeError", &format!("{} out of char range", code));
+    match encoding {
+        Some(Encoding::Utf8) => match u32::try_from(code).ok().and_then(char::from_u32) {
+            Some(c) => Ok(Value::string(&c.to_string())),
+            None => Err(interpreter.error("RangeError", &format!("invalid codepoint 0x{:X} in UTF-8", code))),
+        },
+        None | Some(Encoding::UsAscii) if (0..128).contains(&code) => Ok(Value::bytes(vec!(code as u8), Encoding::UsAscii)),
+        None | Some(Encoding::Binary) if (0..256).contains(&code) => Ok(Value::bytes(vec!(code as u8), Encoding::Binary)),
+        _ => Err(out_of_range()),
+    }
+}
+
+/// Parses an integer the way `Integer()` does, giving `None` for anything malformed.
+pub fn parse_integer(text: &str, base: Option<u32>) -> Option<Value> {
+    let text = text.trim();
+    let (negative, text) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let lower = text.to_ascii_lowercase();
+    let prefixed = |prefix: &str, radix: u32| {
+        let matches = lower.starts_with(prefix) && base.is_none_or(|base| base == radix);
+        if matches { Some((radix, &text[prefix.len()..])) } else { None }
+    };
+    let (radix, digits) = prefixed("0x", 16)
+        .or_else(|| prefixed("0b", 2))
+        .or_else(|| prefixed("0o", 8))
+        .or_else(|| prefixed("0d", 10))
+        .or_else(|| if text.len() > 1 { prefixed("0", 8) } else { None })
+        .unwrap_or((base.unwrap_or(10), text));
+
+    // underscores may only separate digits
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return None;
+    }
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    let value = BigInt::parse(&digits, radix)?;
+
+    Some(Value::integer(if negative { value.neg() } else { value }))
+}
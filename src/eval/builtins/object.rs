@@ -0,0 +1,238 @@
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, expect_name, module };
+use crate::eval::class::Visibility;
+use crate::eval::value::{ RProc, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let basic_object = interpreter.classes.basic_object.clone();
+    basic_object.define_builtin("==", equal);
+    basic_object.define_builtin("equal?", equal);
+    basic_object.define_builtin("!", not);
+    basic_object.define_builtin("!=", not_equal);
+    basic_object.define_builtin("__id__", object_id);
+    basic_object.define_builtin("instance_eval", instance_eval);
+    basic_object.define_private_builtin("initialize", initialize);
+    basic_object.define_private_builtin("method_missing", method_missing);
+
+    let kernel = interpreter.classes.kernel.clone();
+    kernel.define_builtin("class", class);
+    kernel.define_builtin("singleton_class", singleton_class);
+    kernel.define_builtin("inspect", inspect);
+    kernel.define_builtin("to_s", to_s);
+    kernel.define_builtin("nil?", |_, _, _, _| Ok(Value::Bool(false)));
+    kernel.define_builtin("is_a?", is_a);
+    kernel.define_builtin("kind_of?", is_a);
+    kernel.define_builtin("instance_of?", instance_of);
+    kernel.define_builtin("===", case_equal);
+    kernel.define_builtin("eql?", equal);
+    kernel.define_builtin("hash", object_id);
+    kernel.define_builtin("object_id", object_id);
+    kernel.define_builtin("extend", extend);
+    kernel.define_builtin("respond_to?", respond_to);
+    kernel.define_private_builtin("respond_to_missing?", |_, _, _, _| Ok(Value::Bool(false)));
+    kernel.define_builtin("singleton_methods", singleton_methods);
+    kernel.define_builtin("define_singleton_method", define_singleton_method);
+
+    // `include`, `public` and `private` at the top level apply to Object
+    let main = interpreter.singleton_class(&interpreter.main().clone()).expect("main is an object");
+    main.define_private_builtin("include", |interpreter, _, args, block| {
+        let object = Value::Class(interpreter.classes.object.clone());
+        interpreter.call_method(&object, "include", args, block)
+    });
+    main.define_private_builtin("public", |interpreter, _, args, _| main_visibility(interpreter, args, Visibility::Public));
+    main.define_private_builtin("private", |interpreter, _, args, _| main_visibility(interpreter, args, Visibility::Private));
+
+    let nil = interpreter.classes.nil.clone();
+    nil.define_builtin("to_s", |_, _, _, _| Ok(Value::string("")));
+    nil.define_builtin("to_a", |_, _, _, _| Ok(Value::array(vec!())));
+    nil.define_builtin("inspect", |_, _, _, _| Ok(Value::string("nil")));
+    nil.define_builtin("nil?", |_, _, _, _| Ok(Value::Bool(true)));
+    nil.define_builtin("&", |_, _, _, _| Ok(Value::Bool(false)));
+    nil.define_builtin("|", or);
+
+    for class in [interpreter.classes.true_class.clone(), interpreter.classes.false_class.clone()].iter() {
+        class.define_builtin("to_s", boolean_to_s);
+        class.define_builtin("inspect", boolean_to_s);
+        class.define_builtin("&", and);
+        class.define_builtin("|", or);
+        class.define_builtin("^", xor);
+    }
+}
+
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(receiver.is_identical(&args[0])))
+}
+
+fn not(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Bool(!receiver.is_truthy()))
+}
+
+fn not_equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let equal = interpreter.call_method(receiver, "==", args, None)?;
+    Ok(Value::Bool(!equal.is_truthy()))
+}
+
+fn initialize(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    Ok(Value::Nil)
+}
+
+/// Raises the `NoMethodError` for a call nothing handled, which is what a `method_missing`
+/// override gets by calling `super`.
+fn method_missing(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    match args.first() {
+        Some(Value::Symbol(name)) => Err(interpreter.no_method_error(receiver, name)),
+        _ => Err(interpreter.error("ArgumentError", "no method name given")),
+    }
+}
+
+/// Runs the block with the receiver as `self`, so `def` in it defines singleton methods.
+fn instance_eval(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Err(interpreter.error("ArgumentError", "wrong number of arguments (given 0, expected 1..3)")),
+    };
+    let definee = interpreter.singleton_class(receiver).unwrap_or_else(|_| interpreter.class_of(receiver));
+    interpreter.call_proc_as(block, receiver.clone(), definee, slice::from_ref(receiver))
+}
+
+fn object_id(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Integer(receiver.address() as i64 >> 3))
+}
+
+fn class(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Class(interpreter.class_of(receiver)))
+}
+
+fn singleton_class(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Class(interpreter.singleton_class(receiver)?))
+}
+
+/// `#<Point:0x000055d0c1a2b3c8 @x=1, @y=2>`
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if receiver.is_identical(interpreter.main()) { return Ok(Value::string("main")); }
+
+    let mut string = format!("#<{}:0x{:016x}", interpreter.class_of(receiver).inspect(), receiver.address());
+    if let Value::Object(object) = receiver {
+        let ivars: Vec<(String, Value)> = object.ivars.borrow().iter()
+            .filter(|(name, _)| name.starts_with('@'))
+            .cloned()
+            .collect();
+        for (i, (name, value)) in ivars.iter().enumerate() {
+            string += if i == 0 { " " } else { ", " };
+            string += &format!("{}={}", name, interpreter.inspect(value)?);
+        }
+    }
+    string.push('>');
+
+    Ok(Value::string(&string))
+}
+
+fn to_s(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    if receiver.is_identical(interpreter.main()) { return Ok(Value::string("main")); }
+
+    let string = format!("#<{}:0x{:016x}>", interpreter.class_of(receiver).inspect(), receiver.address());
+    Ok(Value::string(&string))
+}
+
+fn is_a(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match &args[0] {
+        Value::Class(class) => Ok(Value::Bool(interpreter.lookup_class(receiver).inherits(class))),
+        _ => Err(interpreter.error("TypeError", "class or module required")),
+    }
+}
+
+fn instance_of(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match &args[0] {
+        Value::Class(class) => Ok(Value::Bool(Rc::ptr_eq(&interpreter.class_of(receiver), class))),
+        _ => Err(interpreter.error("TypeError", "class or module required")),
+    }
+}
+
+fn case_equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if receiver.is_identical(&args[0]) { return Ok(Value::Bool(true)); }
+    interpreter.call_method(receiver, "==", args, None)
+}
+
+/// Mixes modules into the receiver's singleton class, last argument first.
+fn extend(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, usize::MAX)?;
+    let singleton = interpreter.singleton_class(receiver)?;
+    for module in args.iter().rev() {
+        let module = module::expect_module(interpreter, module)?;
+        module::include_module(&singleton, &module);
+        interpreter.call_method(&Value::Class(module), "extended", slice::from_ref(receiver), None)?;
+    }
+
+    Ok(receiver.clone())
+}
+
+/// Whether the receiver has a public method `name`, or any method with `include_all`, asking
+/// `respond_to_missing?` about methods it doesn't have.
+fn respond_to(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let name = expect_name(interpreter, &args[0])?;
+    let include_all = args.get(1).is_some_and(Value::is_truthy);
+    match interpreter.lookup_class(receiver).find_method(&name) {
+        Some((method, _)) => Ok(Value::Bool(include_all || method.visibility == Visibility::Public)),
+        None => {
+            let args = [Value::symbol(&name), Value::Bool(include_all)];
+            let missing = interpreter.call_method(receiver, "respond_to_missing?", &args, None)?;
+            Ok(Value::Bool(missing.is_truthy()))
+        }
+    }
+}
+
+fn singleton_methods(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let names = match receiver {
+        Value::Object(object) => match object.singleton.borrow().as_ref() {
+            Some(singleton) => module::method_names(singleton, false, |visibility| visibility != Visibility::Private),
+            None => vec!(),
+        },
+        Value::Class(class) => {
+            let metaclass = interpreter.metaclass(class);
+            module::method_names(&metaclass, false, |visibility| visibility != Visibility::Private)
+        },
+        _ => vec!(),
+    };
+
+    Ok(Value::array(names.iter().map(|name| Value::symbol(name)).collect()))
+}
+
+fn define_singleton_method(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let singleton = Value::Class(interpreter.singleton_class(receiver)?);
+    interpreter.call_method(&singleton, "define_method", args, block)
+}
+
+fn main_visibility(interpreter: &mut Interpreter, args: &[Value], visibility: Visibility) -> EvalResult {
+    let object = interpreter.classes.object.clone();
+    module::set_visibility(interpreter, &object, args, visibility)
+}
+
+fn boolean_to_s(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::string(if receiver.is_truthy() { "true" } else { "false" }))
+}
+
+fn and(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(receiver.is_truthy() && args[0].is_truthy()))
+}
+
+fn or(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(receiver.is_truthy() || args[0].is_truthy()))
+}
+
+fn xor(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    Ok(Value::Bool(receiver.is_truthy() != args[0].is_truthy()))
+}
@@ -0,0 +1,234 @@
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ array, check_arity, conversion_error, expect_integer, expect_string, numeric };
+use crate::eval::value::{ Encoding, RProc, Value };
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How many times a directive applies: what it does by default, a number, or `*` for all.
+#[derive(Clone, Copy)]
+enum Count {
+    Default,
+    Exactly(usize),
+    All,
+}
+
+/// `Array#pack`: the elements as a binary string, laid out by a template of directives such
+/// as `C*`, `n2`, `a10` or `m0`. Spaces and `#` comments in the template are ignored.
+pub fn pack(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let template: Vec<char> = expect_string(interpreter, &args[0])?.chars().collect();
+    let mut items = array::elements(receiver).into_iter();
+    let mut packed: Vec<u8> = vec!();
+    // like Ruby: US-ASCII while only base64 was written, UTF-8 if `U` came first
+    let mut encoding = Some(Encoding::UsAscii);
+
+    let mut position = 0;
+    while position < template.len() {
+        let directive = template[position];
+        position += 1;
+        if directive.is_whitespace() { continue; }
+        if directive == '#' {
+            while position < template.len() && template[position] != '\n' { position += 1; }
+            continue;
+        }
+
+        let mut native = false;
+        let mut big_endian = false;
+        while let Some(&modifier) = template.get(position).filter(|c| matches!(c, '_' | '!' | '<' | '>')) {
+            if !"sSiIlLqQjJ".contains(directive) {
+                let message = format!("'{}' allowed only after types sSiIlLqQjJ", modifier);
+                return Err(interpreter.error("ArgumentError", &message));
+            }
+            match modifier {
+                '>' => big_endian = true,
+                '<' => big_endian = false,
+                _ => native = true,
+            }
+            position += 1;
+        }
+        let count = if template.get(position) == Some(&'*') {
+            position += 1;
+            Count::All
+        } else {
+            let digits: String = template[position..].iter().take_while(|c| c.is_ascii_digit()).collect();
+            position += digits.len();
+            digits.parse().map_or(Count::Default, Count::Exactly)
+        };
+
+        encoding = match directive {
+            'U' if encoding == Some(Encoding::UsAscii) => Some(Encoding::Utf8),
+            'U' | 'm' => encoding,
+            _ => None,
+        };
+        let width = match directive {
+            'C' | 'c' => Some(1),
+            'S' | 's' | 'n' | 'v' => Some(2),
+            'L' | 'l' if native => Some(8),
+            'L' | 'l' | 'I' | 'i' | 'N' | 'V' => Some(4),
+            'Q' | 'q' | 'J' | 'j' => Some(8),
+            _ => None,
+        };
+        if let Some(width) = width {
+            let big_endian = big_endian || matches!(directive, 'n' | 'N');
+            for item in take(interpreter, &mut items, count)?.iter() {
+                let value = expect_integer(interpreter, item)?;
+                let bytes = if big_endian { value.to_be_bytes()[8 - width..].to_vec() } else { value.to_le_bytes()[..width].to_vec() };
+                packed.extend(bytes);
+            }
+            continue;
+        }
+
+        match directive {
+            'e' | 'E' | 'g' | 'G' | 'f' | 'F' | 'd' | 'D' => {
+                for item in take(interpreter, &mut items, count)?.iter() {
+                    let value = match item {
+                        Value::Integer(_) | Value::Bignum(_) | Value::Float(_) => numeric::float_of(item),
+                        item => return Err(conversion_error(interpreter, item, "Float")),
+                    };
+                    packed.extend(match directive {
+                        'e' | 'f' | 'F' => (value as f32).to_le_bytes().to_vec(),
+                        'g' => (value as f32).to_be_bytes().to_vec(),
+                        'G' => value.to_be_bytes().to_vec(),
+                        _ => value.to_le_bytes().to_vec(),
+                    });
+                }
+            },
+            'a' | 'A' | 'Z' => {
+                let bytes = next_string(interpreter, &mut items)?;
+                let padding = if directive == 'A' { b' ' } else { 0 };
+                match count {
+                    Count::All if directive == 'Z' => {
+                        packed.extend(bytes);
+                        packed.push(0);
+                    },
+                    Count::All => packed.extend(bytes),
+                    Count::Default | Count::Exactly(_) => {
+                        let width = if let Count::Exactly(width) = count { width } else { 1 };
+                        let mut field = bytes;
+                        field.resize(width, padding);
+                        packed.extend(field);
+                    },
+                }
+            },
+            'B' | 'b' => {
+                let bits = next_string(interpreter, &mut items)?;
+                let length = match count { Count::All => bits.len(), Count::Exactly(length) => length.min(bits.len()), Count::Default => 1.min(bits.len()) };
+                for chunk in bits[..length].chunks(8) {
+                    let mut byte = 0u8;
+                    for (index, bit) in chunk.iter().enumerate() {
+                        if bit & 1 == 1 {
+                            byte |= if directive == 'B' { 0x80 >> index } else { 1 << index };
+                        }
+                    }
+                    packed.push(byte);
+                }
+            },
+            'H' | 'h' => {
+                let digits = next_string(interpreter, &mut items)?;
+                let length = match count { Count::All => digits.len(), Count::Exactly(length) => length.min(digits.len()), Count::Default => 1.min(digits.len()) };
+                for chunk in digits[..length].chunks(2) {
+                    let nibble = |digit: u8| if digit.is_ascii_alphabetic() { (digit & 7) + 9 } else { digit & 15 };
+                    let (first, second) = (nibble(chunk[0]), chunk.get(1).map_or(0, |digit| nibble(*digit)));
+                    packed.push(if directive == 'H' { first << 4 | second } else { second << 4 | first });
+                }
+            },
+            'U' => {
+                for item in take(interpreter, &mut items, count)?.iter() {
+                    let code = expect_integer(interpreter, item)?;
+                    match u32::try_from(code).ok().and_then(char::from_u32) {
+                        Some(c) => packed.extend(c.to_string().into_bytes()),
+                        None => return Err(interpreter.error("RangeError", "pack(U): value out of range")),
+                    }
+                }
+            },
+            'w' => {
+                for item in take(interpreter, &mut items, count)?.iter() {
+                    let value = expect_integer(interpreter, item)?;
+                    if value < 0 { return Err(interpreter.error("ArgumentError", "can't compress negative numbers")); }
+                    let mut groups = vec!((value & 0x7f) as u8);
+                    let mut rest = value >> 7;
+                    while rest > 0 {
+                        groups.push((rest & 0x7f) as u8 | 0x80);
+                        rest >>= 7;
+                    }
+                    packed.extend(groups.into_iter().rev());
+                }
+            },
+            'm' => {
+                let bytes = next_string(interpreter, &mut items)?;
+                let line = match count {
+                    Count::Exactly(0) => None,
+                    Count::Exactly(length) if length > 2 => Some(length / 3 * 3),
+                    _ => Some(45),
+                };
+                packed.extend(base64(&bytes, line));
+            },
+            'x' => {
+                let length = match count { Count::All => 0, Count::Exactly(length) => length, Count::Default => 1 };
+                packed.resize(packed.len() + length, 0);
+            },
+            'X' => {
+                let length = match count { Count::All => 0, Count::Exactly(length) => length, Count::Default => 1 };
+                if length > packed.len() { return Err(interpreter.error("ArgumentError", "X outside of string")); }
+                packed.truncate(packed.len() - length);
+            },
+            '@' => {
+                let offset = match count { Count::All => packed.len(), Count::Exactly(offset) => offset, Count::Default => 1 };
+                packed.resize(offset, 0);
+            },
+            unknown => {
+                let message = format!("unknown pack directive '{}' in '{}'", unknown, template.iter().collect::<String>());
+                return Err(interpreter.error("ArgumentError", &message));
+            },
+        }
+    }
+
+    Ok(Value::bytes(packed, encoding.unwrap_or(Encoding::Binary)))
+}
+
+/// The elements a numeric directive consumes: one by default, all that are left for `*`.
+fn take(interpreter: &Interpreter, items: &mut impl Iterator<Item = Value>, count: Count) -> Result<Vec<Value>, Control> {
+    let wanted = match count {
+        Count::All => return Ok(items.collect()),
+        Count::Exactly(count) => count,
+        Count::Default => 1,
+    };
+    let taken: Vec<Value> = items.take(wanted).collect();
+    if taken.len() < wanted { return Err(interpreter.error("ArgumentError", "too few arguments")); }
+    Ok(taken)
+}
+
+fn next_string(interpreter: &Interpreter, items: &mut impl Iterator<Item = Value>) -> Result<Vec<u8>, Control> {
+    match items.next() {
+        Some(Value::String(string)) => Ok(string.bytes.borrow().clone()),
+        Some(other) => Err(conversion_error(interpreter, &other, "String")),
+        None => Err(interpreter.error("ArgumentError", "too few arguments")),
+    }
+}
+
+/// Base64 with a newline after every `line` bytes of input and at the end, or no newlines.
+fn base64(bytes: &[u8], line: Option<usize>) -> Vec<u8> {
+    let mut encoded = vec!();
+    let lines: Vec<&[u8]> = match line {
+        Some(line) => bytes.chunks(line).collect(),
+        None => vec!(bytes),
+    };
+    for chunk in lines.into_iter() {
+        for group in chunk.chunks(3) {
+            let n = (group[0] as u32) << 16 | (*group.get(1).unwrap_or(&0) as u32) << 8 | *group.get(2).unwrap_or(&0) as u32;
+            for index in 0..4 {
+                if index <= group.len() {
+                    encoded.push(BASE64[(n >> (18 - 6 * index) & 63) as usize]);
+                } else {
+                    encoded.push(b'=');
+                }
+            }
+        }
+        if line.is_some() { encoded.push(b'\n'); }
+    }
+
+    encoded
+}
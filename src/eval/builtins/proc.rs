@@ -0,0 +1,81 @@
+use std::rc::Rc;
+
+use crate::ast::node::NodeKind;
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::value::{ ProcBody, RProc, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let proc_class = interpreter.classes.proc_class.clone();
+    proc_class.define_builtin("call", call);
+    proc_class.define_builtin("()", call);
+    proc_class.define_builtin("[]", call);
+    proc_class.define_builtin("===", call);
+    proc_class.define_builtin("yield", call);
+    proc_class.define_builtin("lambda?", |_, r, _, _| Ok(Value::Bool(proc_of(r).lambda)));
+    proc_class.define_builtin("arity", arity);
+    proc_class.define_builtin("inspect", inspect);
+    proc_class.define_builtin("to_s", inspect);
+    proc_class.define_builtin("to_proc", |_, r, _, _| Ok(r.clone()));
+    interpreter.metaclass(&proc_class).define_builtin("new", |interpreter, _, _, block| {
+        let block = expect_block(interpreter, block, "tried to create Proc object without a block")?;
+        Ok(Value::Proc(block.clone()))
+    });
+
+    let kernel = interpreter.classes.kernel.clone();
+    kernel.define_private_builtin("block_given?", |interpreter, _, _, _| {
+        let call = interpreter.frame().context.method.clone();
+        Ok(Value::Bool(call.is_some_and(|call| call.block.is_some())))
+    });
+    kernel.define_private_builtin("proc", |interpreter, _, _, block| {
+        let block = expect_block(interpreter, block, "tried to create Proc object without a block")?;
+        Ok(Value::Proc(block.clone()))
+    });
+    kernel.define_private_builtin("lambda", lambda);
+}
+
+pub fn expect_block<'a>(interpreter: &Interpreter, block: Option<&'a Rc<RProc>>, message: &str) -> Result<&'a Rc<RProc>, Control> {
+    block.ok_or_else(|| interpreter.error("ArgumentError", message))
+}
+
+/// Makes a lambda from the block, which checks its arguments like a method does.
+fn lambda(interpreter: &mut Interpreter, _receiver: &Value, _args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let block = expect_block(interpreter, block, "tried to create Proc object without a block")?;
+    Ok(Value::Proc(Rc::new(RProc {
+        body:    block.body.clone(),
+        scope:   block.scope.clone(),
+        context: block.context.clone(),
+        lambda:  true,
+    })))
+}
+
+fn proc_of(value: &Value) -> Rc<RProc> {
+    match value {
+        Value::Proc(proc) => proc.clone(),
+        _ => unreachable!("a Proc method called on {:?}", value),
+    }
+}
+
+fn call(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    interpreter.call_proc_with_block(&proc_of(receiver), args, block)
+}
+
+/// The number of required arguments, negated and less one when there are optional ones too.
+fn arity(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let proc = proc_of(receiver);
+    let params = match &proc.body {
+        ProcBody::Block(ast, node) => match &ast[*node].kind {
+            NodeKind::Block(params, _) | NodeKind::Lambda(params, _) => params,
+            _ => unreachable!("a proc is made from a block or a lambda"),
+        },
+        ProcBody::Native(_) => return Ok(Value::Integer(-1)),
+    };
+    let required = (params.required.len() + params.post.len() + params.numbered + usize::from(params.it)) as i64;
+    let variable = params.rest.is_some() || (proc.lambda && !params.optional.is_empty());
+
+    Ok(Value::Integer(if variable { -required - 1 } else { required }))
+}
+
+fn inspect(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let lambda = if proc_of(receiver).lambda { " (lambda)" } else { "" };
+    Ok(Value::string(&format!("#<Proc:0x{:016x}{}>", receiver.address(), lambda)))
+}
@@ -0,0 +1,495 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{ Hash, Hasher };
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::bigint::BigInt;
+use crate::eval::builtins::{ check_arity, enumerable, eql, equals, expect_integer, numeric };
+use crate::eval::builtins::enumerable::compare;
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::value::{ RProc, RRange, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let range = interpreter.classes.range.clone();
+    range.define_builtin("begin", |_, r, _, _| Ok(with(r, |range| range.start.clone())));
+    range.define_builtin("end", |_, r, _, _| Ok(with(r, |range| range.end.clone())));
+    range.define_builtin("first", first);
+    range.define_builtin("last", last);
+    range.define_builtin("min", |i, r, a, b| extreme(i, r, a, b, Ordering::Less));
+    range.define_builtin("max", |i, r, a, b| extreme(i, r, a, b, Ordering::Greater));
+    range.define_builtin("exclude_end?", |_, r, _, _| Ok(Value::Bool(with(r, |range| range.exclusive))));
+    range.define_builtin("==", equal);
+    range.define_builtin("eql?", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        Ok(Value::Bool(eql(r, &a[0])))
+    });
+    range.define_builtin("hash", hash);
+    range.define_builtin("each", each);
+    range.define_builtin("step", step);
+    range.define_builtin("%", step);
+    range.define_builtin("include?", include);
+    range.define_builtin("member?", include);
+    range.define_builtin("cover?", cover);
+    range.define_builtin("===", cover);
+    range.define_builtin("to_a", to_a);
+    range.define_builtin("entries", to_a);
+    range.define_builtin("size", size);
+    range.define_builtin("count", count);
+    range.define_builtin("sum", sum);
+    range.define_builtin("inspect", |i, r, _, _| describe(i, r, Interpreter::inspect));
+    range.define_builtin("to_s", |i, r, _, _| describe(i, r, Interpreter::to_s));
+}
+
+fn with<T>(value: &Value, f: impl FnOnce(&RRange) -> T) -> T {
+    match value {
+        Value::Range(range) => f(range),
+        _ => unreachable!("a Range method called on {:?}", value),
+    }
+}
+
+fn ends(value: &Value) -> (Value, Value, bool) {
+    with(value, |range| (range.start.clone(), range.end.clone(), range.exclusive))
+}
+
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let (a, b) = match (receiver, &args[0]) {
+        (Value::Range(a), Value::Range(b)) => (a.clone(), b.clone()),
+        _ => return Ok(Value::Bool(false)),
+    };
+
+    let equal = a.exclusive == b.exclusive && equals(interpreter, &a.start, &b.start)? && equals(interpreter, &a.end, &b.end)?;
+    Ok(Value::Bool(equal))
+}
+
+/// Combines the hashes of the ends and whether the end is excluded, so ranges that are `eql?`
+/// hash the same.
+fn hash(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let (start, end, exclusive) = with(receiver, |range| (range.start.clone(), range.end.clone(), range.exclusive));
+    let mut hasher = DefaultHasher::new();
+    exclusive.hash(&mut hasher);
+    for value in [start, end].iter() {
+        match interpreter.call_method(value, "hash", &[], None)? {
+            Value::Integer(hash) => hash.hash(&mut hasher),
+            other => other.address().hash(&mut hasher),
+        }
+    }
+
+    Ok(Value::Integer(hasher.finish() as i64))
+}
+
+fn iteration_error(interpreter: &Interpreter, start: &Value) -> Control {
+    let message = format!("can't iterate from {}", interpreter.class_of(start).name());
+    interpreter.error("TypeError", &message)
+}
+
+/// The last integer in a range of integers ending at `end`, or `None` for no end at all.
+fn integer_end(interpreter: &Interpreter, end: &Value, exclusive: bool) -> Result<Option<i64>, Control> {
+    let last = match end {
+        Value::Nil => return Ok(None),
+        Value::Float(end) if end.is_infinite() && *end > 0.0 => return Ok(None),
+        Value::Float(end) if exclusive && end.fract() == 0.0 => *end as i64 - 1,
+        Value::Float(end) => end.floor() as i64,
+        end => {
+            let end = expect_integer(interpreter, end)?;
+            if exclusive { end.saturating_sub(1) } else { end }
+        },
+    };
+    Ok(Some(last))
+}
+
+/// Hands each element of the range to `f` until it returns `false`: integers counting up,
+/// strings by `succ` the way `String#upto` goes, and anything else with a `succ` until it
+/// passes the end. Without an end it goes on for as long as `f` wants.
+fn iterate(
+    interpreter: &mut Interpreter,
+    receiver: &Value,
+    mut f: impl FnMut(&mut Interpreter, Value) -> Result<bool, Control>,
+) -> Result<(), Control> {
+    let (start, end, exclusive) = ends(receiver);
+    match (&start, &end) {
+        (Value::Integer(start), Value::Integer(_) | Value::Float(_) | Value::Nil) => {
+            let last = integer_end(interpreter, &end, exclusive)?.unwrap_or(i64::MAX);
+            for value in *start..=last {
+                if !f(interpreter, Value::Integer(value))? { break; }
+            }
+            Ok(())
+        },
+        (Value::String(_), Value::String(_)) => each_string(interpreter, &start, &end, exclusive, f),
+        (Value::Nil | Value::Float(_), _) => Err(iteration_error(interpreter, &start)),
+        _ if interpreter.lookup_class(&start).find_method("succ").is_none() => Err(iteration_error(interpreter, &start)),
+        (_, Value::Nil) => {
+            let mut value = start.clone();
+            while f(interpreter, value.clone())? {
+                value = interpreter.call_method(&value, "succ", &[], None)?;
+            }
+            Ok(())
+        },
+        _ => {
+            let mut value = start.clone();
+            loop {
+                let ordering = compare(interpreter, &value, &end)?;
+                if ordering == Ordering::Greater || (exclusive && ordering == Ordering::Equal) { break; }
+                if !f(interpreter, value.clone())? || ordering == Ordering::Equal { break; }
+                value = interpreter.call_method(&value, "succ", &[], None)?;
+            }
+            Ok(())
+        },
+    }
+}
+
+/// `String#upto`: single characters go through the characters between them, digits count
+/// keeping the width of the start, and anything else follows `succ` until it reaches the end
+/// or grows longer than it.
+fn each_string(
+    interpreter: &mut Interpreter,
+    start: &Value,
+    end: &Value,
+    exclusive: bool,
+    mut f: impl FnMut(&mut Interpreter, Value) -> Result<bool, Control>,
+) -> Result<(), Control> {
+    let bytes = |value: &Value| match value {
+        Value::String(string) => string.bytes.borrow().clone(),
+        _ => unreachable!("both ends are strings"),
+    };
+    let (first, last) = (bytes(start), bytes(end));
+
+    if let ([a], [b]) = (&first[..], &last[..]) {
+        if a.is_ascii() && b.is_ascii() {
+            let end = if exclusive { *b } else { b + 1 };
+            for c in *a..end {
+                if !f(interpreter, Value::string(&(c as char).to_string()))? { break; }
+            }
+            return Ok(());
+        }
+    }
+
+    let digits = |bytes: &[u8]| !bytes.is_empty() && bytes.iter().all(u8::is_ascii_digit);
+    if digits(&first) && digits(&last) {
+        let parse = |bytes: &[u8]| String::from_utf8_lossy(bytes).parse::<i64>().ok();
+        if let (Some(a), Some(b)) = (parse(&first), parse(&last)) {
+            let b = if exclusive { b - 1 } else { b };
+            for number in a..=b {
+                let text = format!("{:0width$}", number, width = first.len());
+                if !f(interpreter, Value::string(&text))? { break; }
+            }
+            return Ok(());
+        }
+    }
+
+    if first > last || (exclusive && first == last) { return Ok(()); }
+    let mut value = start.clone();
+    loop {
+        let current = bytes(&value);
+        if exclusive && current == last { break; }
+        if !f(interpreter, value.clone())? || current == last { break; }
+        value = interpreter.call_method(&value, "succ", &[], None)?;
+        let length = bytes(&value).len();
+        if length > last.len() || length == 0 { break; }
+    }
+    Ok(())
+}
+
+fn each(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each", args)),
+    };
+    iterate(interpreter, receiver, |interpreter, value| {
+        interpreter.call_proc(block, &[value])?;
+        Ok(true)
+    })?;
+    Ok(receiver.clone())
+}
+
+/// Every `n`th element. Numeric ranges add the step instead of counting elements, and with a
+/// float anywhere the values are worked out as `start + i * step` so errors don't add up.
+fn step(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let amount = args.first().cloned().unwrap_or(Value::Integer(1));
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "step", args)),
+    };
+    if !matches!(amount, Value::Integer(_) | Value::Float(_)) {
+        let message = format!("no implicit conversion of {} into Integer", interpreter.class_of(&amount).name());
+        return Err(interpreter.error("TypeError", &message));
+    }
+    match numeric::float_of(&amount).partial_cmp(&0.0) {
+        Some(Ordering::Less) => return Err(interpreter.error("ArgumentError", "step can't be negative")),
+        Some(Ordering::Equal) => return Err(interpreter.error("ArgumentError", "step can't be 0")),
+        _ => (),
+    }
+
+    let (start, end, exclusive) = ends(receiver);
+    match (&start, &end, &amount) {
+        (Value::Integer(start), Value::Integer(_) | Value::Nil, Value::Integer(amount)) => {
+            let last = integer_end(interpreter, &end, exclusive)?.unwrap_or(i64::MAX);
+            let mut value = *start;
+            while value <= last {
+                interpreter.call_proc(block, &[Value::Integer(value)])?;
+                value = match value.checked_add(*amount) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        },
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_) | Value::Nil, _) => {
+            let (start, unit) = (numeric::float_of(&start), numeric::float_of(&amount));
+            let count = if end.is_nil() { f64::INFINITY } else { float_step_size(start, numeric::float_of(&end), unit, exclusive) };
+            let end = if end.is_nil() { f64::INFINITY } else { numeric::float_of(&end) };
+            let mut index = 0.0;
+            while index < count {
+                let value = (index * unit + start).min(end);
+                interpreter.call_proc(block, &[Value::Float(value)])?;
+                index += 1.0;
+            }
+        },
+        _ => {
+            let every = expect_integer(interpreter, &amount)?;
+            let mut index = 0;
+            iterate(interpreter, receiver, |interpreter, value| {
+                if index % every == 0 { interpreter.call_proc(block, &[value])?; }
+                index += 1;
+                Ok(true)
+            })?;
+        },
+    }
+    Ok(receiver.clone())
+}
+
+/// How many steps of `unit` fit between the ends, allowing for rounding error.
+fn float_step_size(start: f64, end: f64, unit: f64, exclusive: bool) -> f64 {
+    let n = (end - start) / unit;
+    let err = ((start.abs() + end.abs() + (end - start).abs()) / unit.abs() * f64::EPSILON).min(0.5);
+    if unit.is_infinite() { return if start <= end { 1.0 } else { 0.0 }; }
+
+    let n = if exclusive {
+        if n <= 0.0 { return 0.0; }
+        let n = if n < 1.0 { 0.0 } else { (n - err).floor() };
+        if (n + 1.0) * unit + start < end { n + 1.0 } else { n }
+    } else {
+        if n < 0.0 { return 0.0; }
+        (n + err).floor()
+    };
+    n + 1.0
+}
+
+/// Whether the value lies between the ends, compared with `<=>`. A missing end is unbounded.
+fn covers(interpreter: &mut Interpreter, receiver: &Value, value: &Value) -> Result<bool, Control> {
+    let (start, end, exclusive) = ends(receiver);
+    if !start.is_nil() {
+        match interpreter.call_method(&start, "<=>", slice::from_ref(value), None)? {
+            Value::Integer(ordering) if ordering <= 0 => (),
+            _ => return Ok(false),
+        }
+    }
+    if !end.is_nil() {
+        match interpreter.call_method(value, "<=>", &[end], None)? {
+            Value::Integer(ordering) if ordering < 0 || (ordering == 0 && !exclusive) => (),
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+/// `cover?` and `===`: whether a value, or every value of another range, lies within this one.
+fn cover(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let other = match &args[0] {
+        Value::Range(other) => other.clone(),
+        value => return Ok(Value::Bool(covers(interpreter, receiver, value)?)),
+    };
+
+    let (_, end, exclusive) = ends(receiver);
+    let starts = match &other.start {
+        Value::Nil => with(receiver, |range| range.start.is_nil()),
+        start => covers(interpreter, receiver, start)?,
+    };
+    if !starts { return Ok(Value::Bool(false)); }
+    let ends = match (&other.end, &end) {
+        (_, Value::Nil) => true,
+        (Value::Nil, _) => false,
+        (other_end, _) => match compare(interpreter, other_end, &end)? {
+            Ordering::Less => true,
+            Ordering::Equal => !exclusive || other.exclusive,
+            Ordering::Greater => false,
+        },
+    };
+    if !ends || other.start.is_nil() || other.end.is_nil() { return Ok(Value::Bool(ends)); }
+
+    let empty = match compare(interpreter, &other.start, &other.end)? {
+        Ordering::Greater => true,
+        Ordering::Equal => other.exclusive,
+        Ordering::Less => false,
+    };
+    Ok(Value::Bool(!empty))
+}
+
+/// Numeric ranges are checked like `cover?`; other ranges go through their elements.
+fn include(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let (start, end, _) = ends(receiver);
+    let numeric = |value: &Value| matches!(value, Value::Integer(_) | Value::Bignum(_) | Value::Float(_));
+    if numeric(&start) || numeric(&end) { return Ok(Value::Bool(covers(interpreter, receiver, &args[0])?)); }
+
+    match (&start, &end) {
+        (Value::String(_), Value::Nil) | (Value::Nil, Value::String(_)) => {
+            Err(interpreter.error("TypeError", "cannot determine inclusion in beginless/endless ranges"))
+        },
+        _ => enumerable::include(interpreter, receiver, args, block),
+    }
+}
+
+fn to_a(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let (start, end, exclusive) = ends(receiver);
+    if end.is_nil() { return Err(interpreter.error("RangeError", "cannot convert endless range to an array")); }
+    if let (Value::Integer(start), Value::Integer(end)) = (&start, &end) {
+        let elements = if exclusive { (*start..*end).map(Value::Integer).collect() } else { (*start..=*end).map(Value::Integer).collect() };
+        return Ok(Value::array(elements));
+    }
+
+    let mut elements = vec!();
+    iterate(interpreter, receiver, |_, value| {
+        elements.push(value);
+        Ok(true)
+    })?;
+    Ok(Value::array(elements))
+}
+
+/// The start, or the first `n` elements.
+fn first(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (start, _, _) = ends(receiver);
+    if start.is_nil() { return Err(interpreter.error("RangeError", "cannot get the first element of beginless range")); }
+    if args.is_empty() { return Ok(start); }
+
+    if expect_integer(interpreter, &args[0])? < 0 {
+        return Err(interpreter.error("ArgumentError", "negative array size (or size too big)"));
+    }
+    enumerable::first(interpreter, receiver, args, block)
+}
+
+/// The end, or the last `n` elements.
+fn last(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (_, end, _) = ends(receiver);
+    if end.is_nil() { return Err(interpreter.error("RangeError", "cannot get the last element of endless range")); }
+    if args.is_empty() { return Ok(end); }
+
+    let count = expect_integer(interpreter, &args[0])?;
+    if count < 0 { return Err(interpreter.error("ArgumentError", "negative array size")); }
+    let elements = match to_a(interpreter, receiver, &[], None)? {
+        Value::Array(array) => array.elements.borrow().clone(),
+        _ => unreachable!("to_a makes an array"),
+    };
+    let skipped = elements.len().saturating_sub(count as usize);
+    Ok(Value::array(elements[skipped..].to_vec()))
+}
+
+/// `min` and `max` read the ends when they can, and go through the elements for a block or a
+/// count.
+fn extreme(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, wanted: Ordering) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (start, end, exclusive) = ends(receiver);
+    let minimum = wanted == Ordering::Less;
+    if (if minimum { &start } else { &end }).is_nil() {
+        let message = if minimum { "cannot get the minimum of beginless range" } else { "cannot get the maximum of endless range" };
+        return Err(interpreter.error("RangeError", message));
+    }
+    if block.is_some() || !args.is_empty() { return enumerable::extreme(interpreter, receiver, args, block, wanted); }
+    if !minimum && exclusive {
+        if !matches!(end, Value::Integer(_) | Value::Bignum(_)) {
+            return Err(interpreter.error("TypeError", "cannot exclude non Integer end value"));
+        }
+        if !matches!(start, Value::Integer(_) | Value::Bignum(_) | Value::Nil) {
+            return Err(interpreter.error("TypeError", "cannot exclude end value with non Integer begin value"));
+        }
+    }
+    if !start.is_nil() && !end.is_nil() {
+        match compare(interpreter, &start, &end)? {
+            Ordering::Greater => return Ok(Value::Nil),
+            Ordering::Equal if exclusive => return Ok(Value::Nil),
+            _ => (),
+        }
+    }
+
+    match (minimum, exclusive) {
+        (true, _) => Ok(start),
+        (false, false) => Ok(end),
+        (false, true) => interpreter.call_method(&end, "-", &[Value::Integer(1)], None),
+    }
+}
+
+/// The number of elements of a range of integers, which is infinite without an end. Other
+/// ranges don't know theirs.
+fn size(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 0)?;
+    let (start, end, exclusive) = ends(receiver);
+    match (&start, &end) {
+        (Value::Integer(_), Value::Nil) => Ok(Value::Float(f64::INFINITY)),
+        (Value::Integer(start), Value::Integer(_) | Value::Float(_)) => match integer_end(interpreter, &end, exclusive)? {
+            Some(last) => Ok(Value::Integer((last as i128 - *start as i128 + 1).max(0) as i64)),
+            None => Ok(Value::Float(f64::INFINITY)),
+        },
+        (Value::Nil | Value::Float(_), Value::Integer(_) | Value::Float(_) | Value::Nil) => Err(iteration_error(interpreter, &start)),
+        _ => Ok(Value::Nil),
+    }
+}
+
+fn count(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let (start, end, _) = ends(receiver);
+    if args.is_empty() && block.is_none() && (matches!(start, Value::Integer(_)) || end.is_nil()) {
+        if end.is_nil() || start.is_nil() { return Ok(Value::Float(f64::INFINITY)); }
+        return size(interpreter, receiver, args, block);
+    }
+    enumerable::count(interpreter, receiver, args, block)
+}
+
+/// The sum of a range of integers is worked out rather than added up, however big the ends.
+fn sum(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let (start, end, exclusive) = ends(receiver);
+    let initial = args.first().cloned().unwrap_or(Value::Integer(0));
+    let integer = |value: &Value| matches!(value, Value::Integer(_) | Value::Bignum(_));
+    if block.is_some() || !integer(&start) || !integer(&end) || !integer(&initial) {
+        return enumerable::sum(interpreter, receiver, args, block);
+    }
+    let one = BigInt::from_i64(1);
+    let first = numeric::big_of(&start);
+    let last = if exclusive { numeric::big_of(&end).sub(&one) } else { numeric::big_of(&end) };
+    if last < first { return Ok(initial); }
+
+    let count = last.sub(&first).add(&one);
+    let total = Value::integer(first.add(&last).mul(&count).div_rem(&BigInt::from_i64(2)).0);
+    interpreter.call_method(&initial, "+", &[total], None)
+}
+
+/// The start and length a range picks out of something `length` long, as `[]` reads it: ends
+/// may count from the back, a missing end runs to the end, and `None` means the start is out
+/// of bounds.
+pub fn span(interpreter: &Interpreter, range: &RRange, length: i64) -> Result<Option<(i64, i64)>, Control> {
+    let start = if range.start.is_nil() { 0 } else { expect_integer(interpreter, &range.start)? };
+    let end = if range.end.is_nil() { -1 } else { expect_integer(interpreter, &range.end)? };
+    let start = if start < 0 { start + length } else { start };
+    if start < 0 || start > length { return Ok(None); }
+    let end = if end < 0 { end + length } else { end };
+    let end = if range.exclusive && !range.end.is_nil() { end } else { end + 1 };
+
+    Ok(Some((start, (end - start).clamp(0, length - start))))
+}
+
+/// `1..5`, `1...`, `..5`: a `nil` end is left out, unless both are `nil`.
+fn describe(interpreter: &mut Interpreter, receiver: &Value, show: fn(&mut Interpreter, &Value) -> Result<String, Control>) -> EvalResult {
+    let (start, end, exclusive) = ends(receiver);
+    let both_nil = start.is_nil() && end.is_nil();
+    let start = if start.is_nil() && !both_nil { String::new() } else { show(interpreter, &start)? };
+    let end = if end.is_nil() && !both_nil { String::new() } else { show(interpreter, &end)? };
+
+    Ok(Value::string(&format!("{}{}{}", start, if exclusive { "..." } else { ".." }, end)))
+}
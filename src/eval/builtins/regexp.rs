@@ -0,0 +1,496 @@
+use std::rc::Rc;
+use std::slice;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ array, check_arity, conversion_error, expect_integer, string };
+use crate::eval::class::RClass;
+use crate::eval::regexp::{ Captures, Options, Regex };
+use crate::eval::value::{ RProc, Value };
+
+pub fn define(interpreter: &mut Interpreter) {
+    let object = interpreter.classes.object.clone();
+    let regexp = RClass::new("Regexp", Some(object.clone()));
+    interpreter.set_constant("Regexp", Value::Class(regexp.clone()));
+    regexp.set_constant("IGNORECASE", Value::Integer(Options::IGNORECASE));
+    regexp.set_constant("EXTENDED", Value::Integer(Options::EXTENDED));
+    regexp.set_constant("MULTILINE", Value::Integer(Options::MULTILINE));
+
+    let metaclass = interpreter.metaclass(&regexp);
+    metaclass.define_builtin("new", new);
+    metaclass.define_builtin("compile", new);
+    metaclass.define_builtin("escape", escape);
+    metaclass.define_builtin("quote", escape);
+    metaclass.define_builtin("union", union);
+    metaclass.define_builtin("last_match", last_match);
+
+    regexp.define_builtin("source", |i, r, _, _| Ok(i.instance_variable(r, "source")));
+    regexp.define_builtin("options", |i, r, _, _| Ok(i.instance_variable(r, "options")));
+    regexp.define_builtin("casefold?", |i, r, _, _| Ok(Value::Bool(options_of(i, r).ignore_case)));
+    regexp.define_builtin("names", names);
+    regexp.define_builtin("to_s", to_s);
+    regexp.define_builtin("inspect", inspect);
+    regexp.define_builtin("==", equal);
+    regexp.define_builtin("eql?", equal);
+    regexp.define_builtin("match", match_method);
+    regexp.define_builtin("match?", is_match);
+    regexp.define_builtin("=~", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        match_operator(i, r, &a[0])
+    });
+    regexp.define_builtin("===", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        if !matches!(a[0], Value::String(_) | Value::Symbol(_)) { return Ok(Value::Bool(false)); }
+        Ok(Value::Bool(match_operator(i, r, &a[0])?.is_truthy()))
+    });
+
+    let match_data = RClass::new("MatchData", Some(object));
+    interpreter.set_constant("MatchData", Value::Class(match_data.clone()));
+    match_data.define_builtin("[]", group);
+    match_data.define_builtin("captures", |i, r, _, _| Ok(Value::array(groups(i, r)[1..].to_vec())));
+    match_data.define_builtin("to_a", |i, r, _, _| Ok(Value::array(groups(i, r))));
+    match_data.define_builtin("named_captures", named_captures);
+    match_data.define_builtin("names", |i, r, _, _| {
+        let regexp = i.instance_variable(r, "regexp");
+        names(i, &regexp, &[], None)
+    });
+    match_data.define_builtin("values_at", values_at);
+    match_data.define_builtin("size", |i, r, _, _| Ok(Value::Integer(groups(i, r).len() as i64)));
+    match_data.define_builtin("length", |i, r, _, _| Ok(Value::Integer(groups(i, r).len() as i64)));
+    match_data.define_builtin("to_s", |i, r, _, _| Ok(groups(i, r)[0].clone()));
+    match_data.define_builtin("pre_match", |i, r, _, _| Ok(pre_match(i, r)));
+    match_data.define_builtin("post_match", |i, r, _, _| Ok(post_match(i, r)));
+    match_data.define_builtin("begin", |i, r, a, _| offset(i, r, a, |(start, _)| start));
+    match_data.define_builtin("end", |i, r, a, _| offset(i, r, a, |(_, end)| end));
+    match_data.define_builtin("string", |i, r, _, _| Ok(i.instance_variable(r, "string")));
+    match_data.define_builtin("regexp", |i, r, _, _| Ok(i.instance_variable(r, "regexp")));
+    match_data.define_builtin("inspect", match_data_inspect);
+
+    interpreter.classes.nil.define_builtin("=~", |_, _, _, _| Ok(Value::Nil));
+    interpreter.classes.kernel.define_builtin("!~", |interpreter, receiver, args, _| {
+        check_arity(interpreter, args, 1, 1)?;
+        let matched = interpreter.call_method(receiver, "=~", args, None)?;
+        Ok(Value::Bool(!matched.is_truthy()))
+    });
+}
+
+/// A new `Regexp`, or a `RegexpError` if `source` isn't a valid pattern.
+pub fn regexp(interpreter: &mut Interpreter, source: &str, options: Options) -> EvalResult {
+    compile(interpreter, source, options)?;
+    let class = match interpreter.constant("Regexp") {
+        Some(Value::Class(class)) => class,
+        _ => unreachable!("Regexp is a builtin class"),
+    };
+    let regexp = Value::object(class);
+    if let Value::Object(object) = &regexp {
+        object.set("source", Value::string(source));
+        object.set("options", Value::Integer(options.bits()));
+    }
+
+    Ok(regexp)
+}
+
+/// The compiled pattern, which is kept so each regexp is only compiled once.
+fn compile(interpreter: &mut Interpreter, source: &str, options: Options) -> Result<Rc<Regex>, Control> {
+    let key = (source.to_string(), options.bits());
+    if let Some(regex) = interpreter.regexps.get(&key) {
+        return Ok(regex.clone());
+    }
+    let regex = match Regex::new(source, options) {
+        Ok(regex) => Rc::new(regex),
+        Err(message) => return Err(interpreter.error("RegexpError", &format!("{}: /{}/", message, source))),
+    };
+    interpreter.regexps.insert(key, regex.clone());
+
+    Ok(regex)
+}
+
+pub fn is_regexp(interpreter: &Interpreter, value: &Value) -> bool {
+    matches!(value, Value::Object(_)) && interpreter.is_a(value, "Regexp")
+}
+
+fn source_of(interpreter: &Interpreter, regexp: &Value) -> String {
+    match interpreter.instance_variable(regexp, "source") {
+        Value::String(source) => source.text(),
+        _ => String::new(),
+    }
+}
+
+fn options_of(interpreter: &Interpreter, regexp: &Value) -> Options {
+    match interpreter.instance_variable(regexp, "options") {
+        Value::Integer(bits) => Options::from_bits(bits),
+        _ => Options::default(),
+    }
+}
+
+fn compiled(interpreter: &mut Interpreter, regexp: &Value) -> Result<Rc<Regex>, Control> {
+    let (source, options) = (source_of(interpreter, regexp), options_of(interpreter, regexp));
+    compile(interpreter, &source, options)
+}
+
+/// The text of a string to be matched against, which has to be valid in its encoding.
+pub fn text_of(interpreter: &Interpreter, value: &Value) -> Result<String, Control> {
+    let string = match value {
+        Value::String(string) => string,
+        Value::Symbol(name) => return Ok(name.to_string()),
+        value => return Err(conversion_error(interpreter, value, "String")),
+    };
+    match String::from_utf8(string.bytes.borrow().clone()) {
+        Ok(text) => Ok(text),
+        Err(_) => Err(interpreter.error("ArgumentError", &format!("invalid byte sequence in {}", string.encoding.get().name()))),
+    }
+}
+
+/// Searches `text` from the byte offset `start`, giving the `MatchData` of the first match.
+/// `$~` is left alone.
+pub fn search(interpreter: &mut Interpreter, regexp: &Value, text: &str, start: usize) -> Result<Option<Value>, Control> {
+    let regex = compiled(interpreter, regexp)?;
+    Ok(regex.search(text, start).map(|captures| match_data(interpreter, regexp, text, &captures)))
+}
+
+fn match_data(interpreter: &Interpreter, regexp: &Value, text: &str, captures: &Captures) -> Value {
+    let class = match interpreter.constant("MatchData") {
+        Some(Value::Class(class)) => class,
+        _ => unreachable!("MatchData is a builtin class"),
+    };
+    let offsets = captures.iter().map(|range| match range {
+        Some((start, end)) => Value::array(vec!(Value::Integer(*start as i64), Value::Integer(*end as i64))),
+        None => Value::Nil,
+    }).collect();
+    let string = Value::string(text);
+    if let Value::String(string) = &string { string.frozen.set(true); }
+
+    let match_data = Value::object(class);
+    if let Value::Object(object) = &match_data {
+        object.set("string", string);
+        object.set("regexp", regexp.clone());
+        object.set("offsets", Value::array(offsets));
+    }
+    match_data
+}
+
+/// The byte ranges of the groups, the whole match first.
+pub fn ranges(interpreter: &Interpreter, match_data: &Value) -> Vec<Option<(usize, usize)>> {
+    let offsets = match interpreter.instance_variable(match_data, "offsets") {
+        Value::Array(_) => array::elements(&interpreter.instance_variable(match_data, "offsets")),
+        _ => return vec!(),
+    };
+    offsets.iter().map(|offset| match offset {
+        Value::Array(_) => match array::elements(offset).as_slice() {
+            [Value::Integer(start), Value::Integer(end)] => Some((*start as usize, *end as usize)),
+            _ => None,
+        },
+        _ => None,
+    }).collect()
+}
+
+fn matched_text(interpreter: &Interpreter, match_data: &Value) -> String {
+    match interpreter.instance_variable(match_data, "string") {
+        Value::String(string) => string.text(),
+        _ => String::new(),
+    }
+}
+
+/// The matched strings, or `nil` for groups that didn't take part.
+fn groups(interpreter: &Interpreter, match_data: &Value) -> Vec<Value> {
+    let text = matched_text(interpreter, match_data);
+    ranges(interpreter, match_data).iter().map(|range| match range {
+        Some((start, end)) => Value::string(&text[*start..*end]),
+        None => Value::Nil,
+    }).collect()
+}
+
+fn pre_match(interpreter: &Interpreter, match_data: &Value) -> Value {
+    let text = matched_text(interpreter, match_data);
+    let start = ranges(interpreter, match_data).first().copied().flatten().map_or(0, |(start, _)| start);
+    Value::string(&text[..start])
+}
+
+fn post_match(interpreter: &Interpreter, match_data: &Value) -> Value {
+    let text = matched_text(interpreter, match_data);
+    let end = ranges(interpreter, match_data).first().copied().flatten().map_or(0, |(_, end)| end);
+    Value::string(&text[end..])
+}
+
+/// `$~` and the globals that read it: `$&`, `` $` ``, `$'`, `$+` and `$1` onwards.
+pub fn special_global(interpreter: &Interpreter, name: &str) -> Option<Value> {
+    // `$0` is the program name rather than a group
+    let digits = name.strip_prefix('$').filter(|digits| !digits.starts_with('0') && !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()));
+    if digits.is_none() && !matches!(name, "$&" | "$`" | "$'" | "$+") {
+        return None;
+    }
+    let match_data = interpreter.global("$~");
+    if match_data.is_nil() { return Some(Value::Nil); }
+
+    Some(match name {
+        "$`" => pre_match(interpreter, &match_data),
+        "$'" => post_match(interpreter, &match_data),
+        "$+" => groups(interpreter, &match_data).into_iter().skip(1).rev().find(|group| !group.is_nil()).unwrap_or(Value::Nil),
+        "$&" => groups(interpreter, &match_data)[0].clone(),
+        _ => {
+            let index = digits.and_then(|digits| digits.parse::<usize>().ok()).unwrap_or(usize::MAX);
+            groups(interpreter, &match_data).get(index).cloned().unwrap_or(Value::Nil)
+        },
+    })
+}
+
+/// The character index of `byte` in `text`.
+pub fn char_index(text: &str, byte: usize) -> usize {
+    text[..byte].chars().count()
+}
+
+/// The byte offset of the character at `index`, which may count from the end.
+pub fn byte_offset(text: &str, index: i64) -> Option<usize> {
+    let length = text.chars().count() as i64;
+    let index = if index < 0 { index + length } else { index };
+    if index < 0 || index > length { return None; }
+    Some(text.char_indices().nth(index as usize).map_or(text.len(), |(offset, _)| offset))
+}
+
+/// `regexp =~ string`: the character index of the first match, setting `$~`.
+fn match_operator(interpreter: &mut Interpreter, regexp: &Value, target: &Value) -> EvalResult {
+    if target.is_nil() {
+        interpreter.set_global("$~", Value::Nil);
+        return Ok(Value::Nil);
+    }
+    let text = text_of(interpreter, target)?;
+    let found = search(interpreter, regexp, &text, 0)?;
+    interpreter.set_global("$~", found.clone().unwrap_or(Value::Nil));
+
+    Ok(match found {
+        Some(found) => {
+            let start = ranges(interpreter, &found)[0].map_or(0, |(start, _)| start);
+            Value::Integer(char_index(&text, start) as i64)
+        },
+        None => Value::Nil,
+    })
+}
+
+/// `Regexp.new(pattern, options)`, where the options are an integer of flag bits, a string of
+/// flag letters or any true value for `IGNORECASE`.
+fn new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    if is_regexp(interpreter, &args[0]) {
+        let (source, options) = (source_of(interpreter, &args[0]), options_of(interpreter, &args[0]));
+        return regexp(interpreter, &source, options);
+    }
+    let source = super::expect_string(interpreter, &args[0])?;
+    let options = match args.get(1) {
+        None | Some(Value::Nil) | Some(Value::Bool(false)) => Options::default(),
+        Some(Value::Integer(bits)) => Options::from_bits(*bits),
+        Some(Value::String(flags)) => match Options::from_flags(&flags.text()) {
+            Ok(options) => options,
+            Err(flag) => return Err(interpreter.error("ArgumentError", &format!("unknown regexp option: {}", flag))),
+        },
+        Some(_) => Options { ignore_case: true, ..Options::default() },
+    };
+
+    regexp(interpreter, &source, options)
+}
+
+fn escape(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let text = match &args[0] {
+        Value::Symbol(name) => name.to_string(),
+        other => super::expect_string(interpreter, other)?,
+    };
+    Ok(Value::string(&Regex::escape(&text)))
+}
+
+/// A regexp matching any of the patterns: strings match literally, regexps keep their options.
+fn union(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let patterns = match args {
+        [Value::Array(_)] => array::elements(&args[0]),
+        args => args.to_vec(),
+    };
+    if let [pattern] = patterns.as_slice() {
+        if is_regexp(interpreter, pattern) { return Ok(pattern.clone()); }
+    }
+    let mut sources = vec!();
+    for pattern in patterns.iter() {
+        if is_regexp(interpreter, pattern) {
+            sources.push(interpreter.to_s(pattern)?);
+        } else {
+            sources.push(Regex::escape(&super::expect_string(interpreter, pattern)?));
+        }
+    }
+    let source = if sources.is_empty() { String::from("(?!)") } else { sources.join("|") };
+
+    regexp(interpreter, &source, Options::default())
+}
+
+fn last_match(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 1)?;
+    let match_data = interpreter.global("$~");
+    match args.first() {
+        Some(index) if !match_data.is_nil() => group(interpreter, &match_data, slice::from_ref(index), None),
+        _ => Ok(match_data),
+    }
+}
+
+fn names(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let regex = compiled(interpreter, receiver)?;
+    let mut names: Vec<Value> = vec!();
+    for (name, _) in regex.names.iter() {
+        if !names.iter().any(|known| matches!(known, Value::String(known) if known.text() == *name)) {
+            names.push(Value::string(name));
+        }
+    }
+    Ok(Value::array(names))
+}
+
+/// `(?mi-x:source)`, the form a regexp takes when embedded in another.
+fn to_s(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let options = options_of(interpreter, receiver);
+    let off = Options { ignore_case: !options.ignore_case, extended: !options.extended, multiline: !options.multiline };
+    let off = if off == Options::default() { String::new() } else { format!("-{}", off) };
+    Ok(Value::string(&format!("(?{}{}:{})", options, off, source_of(interpreter, receiver))))
+}
+
+/// `/source/flags`, with any unescaped slash in the source escaped.
+fn inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut source = String::new();
+    let mut escaped = false;
+    for c in source_of(interpreter, receiver).chars() {
+        if c == '/' && !escaped { source.push('\\'); }
+        escaped = c == '\\' && !escaped;
+        source.push(c);
+    }
+    Ok(Value::string(&format!("/{}/{}", source, options_of(interpreter, receiver))))
+}
+
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let other = &args[0];
+    let equal = is_regexp(interpreter, other)
+        && source_of(interpreter, receiver) == source_of(interpreter, other)
+        && options_of(interpreter, receiver) == options_of(interpreter, other);
+    Ok(Value::Bool(equal))
+}
+
+/// `match(string, pos = 0)`: the `MatchData` of the first match at or after `pos`, which is
+/// also stored in `$~` and passed to the block if there is one.
+fn match_method(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    if args[0].is_nil() {
+        interpreter.set_global("$~", Value::Nil);
+        return Ok(Value::Nil);
+    }
+    let text = text_of(interpreter, &args[0])?;
+    let start = match args.get(1) {
+        Some(position) => match byte_offset(&text, expect_integer(interpreter, position)?) {
+            Some(start) => start,
+            None => {
+                interpreter.set_global("$~", Value::Nil);
+                return Ok(Value::Nil);
+            },
+        },
+        None => 0,
+    };
+    let found = search(interpreter, receiver, &text, start)?.unwrap_or(Value::Nil);
+    interpreter.set_global("$~", found.clone());
+    match block {
+        Some(block) if !found.is_nil() => interpreter.call_proc(block, &[found]),
+        _ => Ok(found),
+    }
+}
+
+/// Like `match`, but without setting `$~`.
+fn is_match(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    if args[0].is_nil() { return Ok(Value::Bool(false)); }
+    let text = text_of(interpreter, &args[0])?;
+    let start = match args.get(1) {
+        Some(position) => match byte_offset(&text, expect_integer(interpreter, position)?) {
+            Some(start) => start,
+            None => return Ok(Value::Bool(false)),
+        },
+        None => 0,
+    };
+    let regex = compiled(interpreter, receiver)?;
+    Ok(Value::Bool(regex.search(&text, start).is_some()))
+}
+
+/// The number of a group given by index or name.
+pub fn group_index(interpreter: &mut Interpreter, match_data: &Value, key: &Value) -> Result<Option<usize>, Control> {
+    let name = match key {
+        Value::String(name) => name.text(),
+        Value::Symbol(name) => name.to_string(),
+        key => {
+            let index = expect_integer(interpreter, key)?;
+            let count = ranges(interpreter, match_data).len() as i64;
+            let index = if index < 0 { index + count } else { index };
+            return Ok(if index < 0 { None } else { Some(index as usize) });
+        },
+    };
+    let regexp = interpreter.instance_variable(match_data, "regexp");
+    let regex = compiled(interpreter, &regexp)?;
+    // with a repeated name, the last group that matched wins
+    let ranges = ranges(interpreter, match_data);
+    let mut numbers = regex.names.iter().filter(|(group, _)| *group == name).map(|(_, index)| *index).collect::<Vec<_>>();
+    if numbers.is_empty() {
+        return Err(interpreter.error("IndexError", &format!("undefined group name reference: {}", name)));
+    }
+    numbers.sort_unstable();
+    Ok(numbers.iter().rev().find(|index| ranges.get(**index).copied().flatten().is_some()).or(numbers.last()).copied())
+}
+
+fn group(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let groups = groups(interpreter, receiver);
+    if args.len() == 2 || matches!(args[0], Value::Range(_)) {
+        return interpreter.call_method(&Value::array(groups), "[]", args, None);
+    }
+    let index = group_index(interpreter, receiver, &args[0])?;
+    Ok(index.and_then(|index| groups.get(index).cloned()).unwrap_or(Value::Nil))
+}
+
+fn values_at(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut values = vec!();
+    for key in args.iter() {
+        values.push(group(interpreter, receiver, slice::from_ref(key), None)?);
+    }
+    Ok(Value::array(values))
+}
+
+fn named_captures(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let regexp = interpreter.instance_variable(receiver, "regexp");
+    let regex = compiled(interpreter, &regexp)?;
+    let captures = Value::hash();
+    for (name, _) in regex.names.iter() {
+        let value = group(interpreter, receiver, &[Value::string(name)], None)?;
+        super::hash::insert(interpreter, &captures, Value::string(name), value)?;
+    }
+    Ok(captures)
+}
+
+/// The character offset where a group starts or ends, or `nil` if it didn't match.
+fn offset(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], end: fn((usize, usize)) -> usize) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let index = group_index(interpreter, receiver, &args[0])?;
+    let ranges = ranges(interpreter, receiver);
+    let range = match index {
+        Some(index) if index < ranges.len() => ranges[index],
+        _ => {
+            let message = format!("index {} out of matches", interpreter.inspect(&args[0])?);
+            return Err(interpreter.error("IndexError", &message));
+        },
+    };
+    let text = matched_text(interpreter, receiver);
+    Ok(range.map_or(Value::Nil, |range| Value::Integer(char_index(&text, end(range)) as i64)))
+}
+
+/// `#<MatchData "whole" 1:"group" name:"group">`
+fn match_data_inspect(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let regexp = interpreter.instance_variable(receiver, "regexp");
+    let regex = compiled(interpreter, &regexp)?;
+    let mut parts = vec!();
+    for (index, group) in groups(interpreter, receiver).iter().enumerate() {
+        let value = match group {
+            Value::String(group) => string::inspect(&group.text()),
+            _ => String::from("nil"),
+        };
+        let label = regex.names.iter().find(|(_, number)| *number == index).map_or(index.to_string(), |(name, _)| name.clone());
+        parts.push(if index == 0 { value } else { format!("{}:{}", label, value) });
+    }
+    Ok(Value::string(&format!("#<MatchData {}>", parts.join(" "))))
+}
@@ -0,0 +1,1370 @@
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
+use std::hash::{ Hash, Hasher };
+use std::rc::Rc;
+
+use crate::eval::{ Control, EvalResult, Interpreter };
+use crate::eval::builtins::{ array, check_arity, conversion_error, encoding, expect_integer, format, numeric, range, regexp };
+use crate::eval::builtins::enumerator::enumerator_for;
+use crate::eval::regexp::{ Options, Regex };
+use crate::eval::value::{ Encoding, RProc, RString, Value };
+
+/// Changes the contents of a string; the copying and in-place (`!`) forms of a method share one.
+type Transform = fn(&mut Interpreter, &Value, &[Value]) -> Result<Vec<u8>, Control>;
+
+pub fn define(interpreter: &mut Interpreter) {
+    let string = interpreter.classes.string.clone();
+    interpreter.metaclass(&string).define_builtin("new", new);
+    string.define_builtin("+", add);
+    string.define_builtin("*", multiply);
+    string.define_builtin("%", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let values = match &a[0] {
+            Value::Array(_) => array::elements(&a[0]),
+            value => vec!(value.clone()),
+        };
+        Ok(Value::string(&format::format(i, &contents(r), &values)?))
+    });
+    string.define_builtin("<<", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        append(i, r, &a[0])
+    });
+    string.define_builtin("concat", concat);
+    string.define_builtin("prepend", prepend);
+    string.define_builtin("==", equal);
+    string.define_builtin("===", equal);
+    string.define_builtin("eql?", equal);
+    string.define_builtin("hash", hash);
+    string.define_builtin("<=>", compare);
+    string.define_builtin("<", |i, r, a, _| comparison(i, r, a, |o| o < 0));
+    string.define_builtin("<=", |i, r, a, _| comparison(i, r, a, |o| o <= 0));
+    string.define_builtin(">", |i, r, a, _| comparison(i, r, a, |o| o > 0));
+    string.define_builtin(">=", |i, r, a, _| comparison(i, r, a, |o| o >= 0));
+    string.define_builtin("casecmp", |i, r, a, _| casecmp(i, r, a).map(|o| o.map_or(Value::Nil, Value::Integer)));
+    string.define_builtin("casecmp?", |i, r, a, _| casecmp(i, r, a).map(|o| o.map_or(Value::Nil, |o| Value::Bool(o == 0))));
+    string.define_builtin("=~", match_operator);
+    string.define_builtin("match", |i, r, a, b| matching(i, r, a, b, "match"));
+    string.define_builtin("match?", |i, r, a, b| matching(i, r, a, b, "match?"));
+
+    string.define_builtin("length", length);
+    string.define_builtin("size", length);
+    string.define_builtin("bytesize", |_, r, _, _| Ok(Value::Integer(rstring(r).bytes.borrow().len() as i64)));
+    string.define_builtin("empty?", |_, r, _, _| Ok(Value::Bool(rstring(r).bytes.borrow().is_empty())));
+    string.define_builtin("[]", index);
+    string.define_builtin("slice", index);
+    string.define_builtin("[]=", set_index);
+    string.define_builtin("getbyte", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let bytes = bytes(r);
+        let index = expect_integer(i, &a[0])?;
+        let index = if index < 0 { index + bytes.len() as i64 } else { index };
+        Ok(usize::try_from(index).ok().and_then(|index| bytes.get(index)).map_or(Value::Nil, |byte| Value::Integer(*byte as i64)))
+    });
+    string.define_builtin("index", index_of);
+    string.define_builtin("rindex", rindex_of);
+    string.define_builtin("include?", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let other = expect_rstring(i, &a[0])?.bytes.borrow().clone();
+        Ok(Value::Bool(find(&bytes(r), &other, 0).is_some()))
+    });
+    string.define_builtin("start_with?", start_with);
+    string.define_builtin("end_with?", |_, r, a, _| {
+        let bytes = bytes(r);
+        Ok(Value::Bool(a.iter().any(|suffix| matches!(suffix, Value::String(suffix) if bytes.ends_with(&suffix.bytes.borrow())))))
+    });
+
+    string.define_builtin("sub", |i, r, a, b| copy_or_same(r, substitute(i, r, a, b, false)?));
+    string.define_builtin("gsub", |i, r, a, b| copy_or_same(r, substitute(i, r, a, b, true)?));
+    string.define_builtin("sub!", |i, r, a, b| {
+        modifiable(i, r)?;
+        replace_or_nil(r, substitute(i, r, a, b, false)?)
+    });
+    string.define_builtin("gsub!", |i, r, a, b| {
+        modifiable(i, r)?;
+        replace_or_nil(r, substitute(i, r, a, b, true)?)
+    });
+    string.define_builtin("scan", scan);
+    string.define_builtin("split", split);
+
+    string.define_builtin("strip", |i, r, a, _| copied(i, r, a, strip));
+    string.define_builtin("strip!", |i, r, a, _| in_place(i, r, a, strip));
+    string.define_builtin("lstrip", |i, r, a, _| copied(i, r, a, lstrip));
+    string.define_builtin("lstrip!", |i, r, a, _| in_place(i, r, a, lstrip));
+    string.define_builtin("rstrip", |i, r, a, _| copied(i, r, a, rstrip));
+    string.define_builtin("rstrip!", |i, r, a, _| in_place(i, r, a, rstrip));
+    string.define_builtin("chop", |i, r, a, _| copied(i, r, a, chop));
+    string.define_builtin("chop!", |i, r, a, _| in_place(i, r, a, chop));
+    string.define_builtin("chomp", |i, r, a, _| copied(i, r, a, chomp));
+    string.define_builtin("chomp!", |i, r, a, _| in_place(i, r, a, chomp));
+    string.define_builtin("upcase", |i, r, a, _| copied(i, r, a, upcase));
+    string.define_builtin("upcase!", |i, r, a, _| in_place(i, r, a, upcase));
+    string.define_builtin("downcase", |i, r, a, _| copied(i, r, a, downcase));
+    string.define_builtin("downcase!", |i, r, a, _| in_place(i, r, a, downcase));
+    string.define_builtin("capitalize", |i, r, a, _| copied(i, r, a, capitalize));
+    string.define_builtin("capitalize!", |i, r, a, _| in_place(i, r, a, capitalize));
+    string.define_builtin("swapcase", |i, r, a, _| copied(i, r, a, swapcase));
+    string.define_builtin("swapcase!", |i, r, a, _| in_place(i, r, a, swapcase));
+    string.define_builtin("reverse", |i, r, a, _| copied(i, r, a, reverse));
+    string.define_builtin("reverse!", |i, r, a, _| in_place(i, r, a, reverse));
+    string.define_builtin("squeeze", |i, r, a, _| copied(i, r, a, squeeze));
+    string.define_builtin("squeeze!", |i, r, a, _| in_place(i, r, a, squeeze));
+    string.define_builtin("delete", |i, r, a, _| copied(i, r, a, delete));
+    string.define_builtin("delete!", |i, r, a, _| in_place(i, r, a, delete));
+    string.define_builtin("tr", |i, r, a, _| copied(i, r, a, translate));
+    string.define_builtin("tr!", |i, r, a, _| in_place(i, r, a, translate));
+    string.define_builtin("delete_prefix", |i, r, a, _| copied(i, r, a, delete_prefix));
+    string.define_builtin("delete_prefix!", |i, r, a, _| in_place(i, r, a, delete_prefix));
+    string.define_builtin("delete_suffix", |i, r, a, _| copied(i, r, a, delete_suffix));
+    string.define_builtin("delete_suffix!", |i, r, a, _| in_place(i, r, a, delete_suffix));
+    string.define_builtin("succ", |i, r, a, _| copied(i, r, a, successor));
+    string.define_builtin("succ!", |i, r, a, _| in_place(i, r, a, successor));
+    string.define_builtin("next", |i, r, a, _| copied(i, r, a, successor));
+    string.define_builtin("next!", |i, r, a, _| in_place(i, r, a, successor));
+    string.define_builtin("center", |i, r, a, _| justify(i, r, a, Justify::Center));
+    string.define_builtin("ljust", |i, r, a, _| justify(i, r, a, Justify::Left));
+    string.define_builtin("rjust", |i, r, a, _| justify(i, r, a, Justify::Right));
+    string.define_builtin("count", |i, r, a, _| {
+        let sets = char_sets(i, a, 1)?;
+        Ok(Value::Integer(contents(r).chars().filter(|c| sets.iter().all(|set| set.contains(*c))).count() as i64))
+    });
+
+    string.define_builtin("replace", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let string = modifiable(i, r)?;
+        let other = expect_rstring(i, &a[0])?;
+        let (bytes, encoding) = (other.bytes.borrow().clone(), other.encoding.get());
+        *string.bytes.borrow_mut() = bytes;
+        string.encoding.set(encoding);
+        Ok(r.clone())
+    });
+    string.define_builtin("clear", |i, r, _, _| {
+        modifiable(i, r)?.bytes.borrow_mut().clear();
+        Ok(r.clone())
+    });
+    string.define_builtin("insert", insert);
+
+    string.define_builtin("chars", |_, r, _, _| Ok(Value::array(chars(r))));
+    string.define_builtin("each_char", each_char);
+    string.define_builtin("bytes", |_, r, _, _| Ok(Value::array(bytes(r).iter().map(|byte| Value::Integer(*byte as i64)).collect())));
+    string.define_builtin("each_byte", |i, r, a, b| {
+        let block = match b {
+            Some(block) => block,
+            None => return Ok(enumerator_for(i, r, "each_byte", a)),
+        };
+        for byte in bytes(r) {
+            i.call_proc(block, &[Value::Integer(byte as i64)])?;
+        }
+        Ok(r.clone())
+    });
+    string.define_builtin("lines", |i, r, a, _| Ok(Value::array(lines(i, r, a)?)));
+    string.define_builtin("each_line", |i, r, a, b| {
+        let lines = lines(i, r, a)?;
+        let block = match b {
+            Some(block) => block,
+            None => return Ok(enumerator_for(i, r, "each_line", a)),
+        };
+        for line in lines {
+            i.call_proc(block, &[line])?;
+        }
+        Ok(r.clone())
+    });
+    string.define_builtin("ord", ord);
+    string.define_builtin("chr", |_, r, _, _| Ok(chars(r).first().cloned().unwrap_or_else(|| Value::bytes(vec!(), rstring(r).encoding.get()))));
+
+    string.define_builtin("dup", |_, r, _, _| Ok(duplicate(r)));
+    string.define_builtin("+@", |_, r, _, _| Ok(if rstring(r).frozen.get() { duplicate(r) } else { r.clone() }));
+    string.define_builtin("-@", |_, r, _, _| Ok(frozen_copy(r)));
+    string.define_builtin("dedup", |_, r, _, _| Ok(frozen_copy(r)));
+
+    string.define_builtin("encoding", |i, r, _, _| Ok(encoding::value_of(i, rstring(r).encoding.get())));
+    string.define_builtin("force_encoding", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        let encoding = encoding::expect_encoding(i, &a[0])?;
+        modifiable(i, r)?.encoding.set(encoding);
+        Ok(r.clone())
+    });
+    string.define_builtin("valid_encoding?", |_, r, _, _| Ok(Value::Bool(is_valid(rstring(r)))));
+    string.define_builtin("ascii_only?", |_, r, _, _| Ok(Value::Bool(bytes(r).is_ascii())));
+    string.define_builtin("b", |_, r, _, _| Ok(Value::bytes(bytes(r), Encoding::Binary)));
+    string.define_builtin("encode", encode);
+
+    string.define_builtin("to_s", |_, r, _, _| Ok(r.clone()));
+    string.define_builtin("to_str", |_, r, _, _| Ok(r.clone()));
+    string.define_builtin("to_sym", |_, r, _, _| Ok(Value::symbol(&contents(r))));
+    string.define_builtin("intern", |_, r, _, _| Ok(Value::symbol(&contents(r))));
+    string.define_builtin("to_i", |i, r, a, _| {
+        check_arity(i, a, 0, 1)?;
+        let base = match a.first() {
+            Some(base) => expect_integer(i, base)?,
+            None => 10,
+        };
+        if base == 1 || !(0..=36).contains(&base) {
+            return Err(i.error("ArgumentError", &format!("invalid radix {}", base)));
+        }
+        Ok(to_integer(&contents(r), base as u32))
+    });
+    string.define_builtin("hex", |_, r, _, _| Ok(to_integer(&contents(r), 16)));
+    string.define_builtin("oct", |_, r, _, _| Ok(to_integer(&contents(r), 8)));
+    string.define_builtin("to_f", |_, r, _, _| Ok(Value::Float(to_float(&contents(r)))));
+    string.define_builtin("inspect", |_, r, _, _| {
+        let string = rstring(r);
+        Ok(Value::string(&inspect_bytes(&string.bytes.borrow(), string.encoding.get())))
+    });
+}
+
+
+fn rstring(value: &Value) -> &RString {
+    match value {
+        Value::String(string) => string,
+        _ => unreachable!("a String method called on {:?}", value),
+    }
+}
+
+fn bytes(value: &Value) -> Vec<u8> {
+    rstring(value).bytes.borrow().clone()
+}
+
+fn contents(value: &Value) -> String {
+    rstring(value).text()
+}
+
+/// The argument as a `String` object, or a `TypeError`.
+fn expect_rstring<'a>(interpreter: &Interpreter, value: &'a Value) -> Result<&'a RString, Control> {
+    match value {
+        Value::String(string) => Ok(string),
+        value => Err(conversion_error(interpreter, value, "String")),
+    }
+}
+
+/// The string, if it can be changed; a frozen one raises `FrozenError`.
+fn modifiable<'a>(interpreter: &mut Interpreter, value: &'a Value) -> Result<&'a RString, Control> {
+    let string = rstring(value);
+    if string.frozen.get() {
+        let message = format!("can't modify frozen String: {}", interpreter.inspect(value)?);
+        return Err(interpreter.error("FrozenError", &message));
+    }
+    Ok(string)
+}
+
+fn duplicate(value: &Value) -> Value {
+    Value::bytes(bytes(value), rstring(value).encoding.get())
+}
+
+fn frozen_copy(value: &Value) -> Value {
+    if rstring(value).frozen.get() { return value.clone(); }
+    let copy = duplicate(value);
+    rstring(&copy).frozen.set(true);
+    copy
+}
+
+/// Strings are equal when they have the same bytes and either the same encoding or nothing
+/// but ASCII, which reads the same in all of them.
+pub fn same_contents(a: &RString, b: &RString) -> bool {
+    let bytes = a.bytes.borrow();
+    *bytes == *b.bytes.borrow() && (a.encoding.get() == b.encoding.get() || bytes.is_ascii())
+}
+
+fn is_valid(string: &RString) -> bool {
+    match string.encoding.get() {
+        Encoding::Utf8 => std::str::from_utf8(&string.bytes.borrow()).is_ok(),
+        Encoding::UsAscii => string.bytes.borrow().is_ascii(),
+        Encoding::Binary => true,
+    }
+}
+
+/// The byte range of each character. A byte that isn't part of a valid UTF-8 sequence counts
+/// as a character of its own, as does every byte in the single-byte encodings.
+fn char_ranges(bytes: &[u8], encoding: Encoding) -> Vec<(usize, usize)> {
+    if encoding != Encoding::Utf8 {
+        return (0..bytes.len()).map(|offset| (offset, offset + 1)).collect();
+    }
+    let mut ranges = vec!();
+    let mut offset = 0;
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            ranges.push((offset, offset + c.len_utf8()));
+            offset += c.len_utf8();
+        }
+        for _ in chunk.invalid() {
+            ranges.push((offset, offset + 1));
+            offset += 1;
+        }
+    }
+    ranges
+}
+
+fn chars(value: &Value) -> Vec<Value> {
+    let (bytes, encoding) = (bytes(value), rstring(value).encoding.get());
+    char_ranges(&bytes, encoding).iter().map(|(start, end)| Value::bytes(bytes[*start..*end].to_vec(), encoding)).collect()
+}
+
+/// The encoding of `a` with `b` appended: a side with nothing but ASCII takes on the other's,
+/// and any other mix is an `Encoding::CompatibilityError`.
+fn compatible(interpreter: &Interpreter, a: &RString, b: &RString) -> Result<Encoding, Control> {
+    let (first, second) = (a.encoding.get(), b.encoding.get());
+    if first == second || b.bytes.borrow().is_ascii() { return Ok(first); }
+    if a.bytes.borrow().is_ascii() { return Ok(second); }
+
+    let name = |encoding: Encoding| match encoding {
+        Encoding::Binary => format!("BINARY ({})", encoding.name()),
+        encoding => encoding.name().to_string(),
+    };
+    let message = format!("incompatible character encodings: {} and {}", name(first), name(second));
+    Err(interpreter.error("Encoding::CompatibilityError", &message))
+}
+
+/// The first byte offset at or after `from` where `needle` occurs.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() { return None; }
+    if needle.is_empty() { return Some(from); }
+    haystack[from..].windows(needle.len()).position(|window| window == needle).map(|position| position + from)
+}
+
+/// A string as a double quoted literal, with quotes, backslashes and control characters
+/// escaped, and `#` escaped where it would start an interpolation.
+pub fn inspect(string: &str) -> String {
+    inspect_units(&string.chars().map(Ok).collect::<Vec<_>>(), true)
+}
+
+/// Like `inspect`, but bytes that aren't characters in the encoding are written as `\xFF`.
+fn inspect_bytes(bytes: &[u8], encoding: Encoding) -> String {
+    let units = char_ranges(bytes, encoding).iter().map(|(start, end)| match std::str::from_utf8(&bytes[*start..*end]) {
+        Ok(text) if encoding == Encoding::Utf8 || text.is_ascii() => Ok(text.chars().next().unwrap_or_default()),
+        _ => Err(bytes[*start]),
+    }).collect::<Vec<_>>();
+    inspect_units(&units, encoding == Encoding::Utf8)
+}
+
+/// Control characters without an escape of their own are written as `\u0001` in a Unicode
+/// string and as `\x01` in any other.
+fn inspect_units(units: &[Result<char, u8>], unicode: bool) -> String {
+    let mut inspected = String::from("\"");
+    for (index, unit) in units.iter().enumerate() {
+        let c = match unit {
+            Ok(c) => *c,
+            Err(byte) => {
+                inspected += &format!("\\x{:02X}", byte);
+                continue;
+            },
+        };
+        match c {
+            '"' => inspected += "\\\"",
+            '\\' => inspected += "\\\\",
+            '\n' => inspected += "\\n",
+            '\t' => inspected += "\\t",
+            '\r' => inspected += "\\r",
+            '\x1b' => inspected += "\\e",
+            '\x07' => inspected += "\\a",
+            '\x08' => inspected += "\\b",
+            '\x0b' => inspected += "\\v",
+            '\x0c' => inspected += "\\f",
+            '#' if matches!(units.get(index + 1), Some(Ok('{' | '$' | '@'))) => inspected += "\\#",
+            c if c.is_control() && unicode => inspected += &format!("\\u{:04X}", c as u32),
+            c if c.is_control() => inspected += &format!("\\x{:02X}", c as u32),
+            c => inspected.push(c),
+        }
+    }
+    inspected.push('"');
+
+    inspected
+}
+
+/// `String.new(string = "", encoding: nil)`. With nothing to copy, the new string is binary.
+fn new(interpreter: &mut Interpreter, _receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let (args, options) = match args.split_last() {
+        Some((Value::Hash(_), rest)) => (rest, args.last()),
+        _ => (args, None),
+    };
+    check_arity(interpreter, args, 0, 1)?;
+    let string = match args.first() {
+        Some(value) => {
+            let other = expect_rstring(interpreter, value)?;
+            Value::bytes(other.bytes.borrow().clone(), other.encoding.get())
+        },
+        None => Value::bytes(vec!(), Encoding::Binary),
+    };
+    if let Some(encoding) = options.and_then(|options| super::hash::get(options, &Value::symbol("encoding"))) {
+        rstring(&string).encoding.set(encoding::expect_encoding(interpreter, &encoding)?);
+    }
+
+    Ok(string)
+}
+
+fn add(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let (string, other) = (rstring(receiver), expect_rstring(interpreter, &args[0])?);
+    let encoding = compatible(interpreter, string, other)?;
+    let mut bytes = bytes(receiver);
+    bytes.extend_from_slice(&other.bytes.borrow());
+    Ok(Value::bytes(bytes, encoding))
+}
+
+fn multiply(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let times = expect_integer(interpreter, &args[0])?;
+    if times < 0 { return Err(interpreter.error("ArgumentError", "negative argument")); }
+    Ok(Value::bytes(bytes(receiver).repeat(times as usize), rstring(receiver).encoding.get()))
+}
+
+/// `<<`: appends a string, or an integer as the character with that code.
+fn append(interpreter: &mut Interpreter, receiver: &Value, other: &Value) -> EvalResult {
+    let string = modifiable(interpreter, receiver)?;
+    let (bytes, encoding) = match other {
+        Value::Integer(code) => {
+            let encoding = string.encoding.get();
+            match (encoding, u8::try_from(*code), u32::try_from(*code).ok().and_then(char::from_u32)) {
+                (Encoding::Utf8, _, Some(c)) => (c.to_string().into_bytes(), encoding),
+                (Encoding::UsAscii, Ok(byte), _) if !byte.is_ascii() => (vec!(byte), Encoding::Binary),
+                (Encoding::Binary, Ok(byte), _) | (Encoding::UsAscii, Ok(byte), _) => (vec!(byte), encoding),
+                _ => return Err(interpreter.error("RangeError", &format!("{} out of char range", code))),
+            }
+        },
+        other => {
+            let other = expect_rstring(interpreter, other)?;
+            let bytes = other.bytes.borrow().clone();
+            (bytes, compatible(interpreter, string, other)?)
+        },
+    };
+    string.bytes.borrow_mut().extend_from_slice(&bytes);
+    string.encoding.set(encoding);
+
+    Ok(receiver.clone())
+}
+
+fn concat(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    modifiable(interpreter, receiver)?;
+    // appending the string to itself appends what it held before
+    let args = args.iter().map(|arg| if arg.is_identical(receiver) { duplicate(arg) } else { arg.clone() }).collect::<Vec<_>>();
+    for arg in args.iter() {
+        append(interpreter, receiver, arg)?;
+    }
+    Ok(receiver.clone())
+}
+
+fn prepend(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let string = modifiable(interpreter, receiver)?;
+    let mut prefix = Value::bytes(vec!(), string.encoding.get());
+    for arg in args.iter() {
+        append(interpreter, &prefix, arg)?;
+    }
+    append(interpreter, &prefix, &duplicate(receiver))?;
+    prefix = frozen_copy(&prefix);
+    let prefix = rstring(&prefix);
+    *string.bytes.borrow_mut() = prefix.bytes.borrow().clone();
+    string.encoding.set(prefix.encoding.get());
+
+    Ok(receiver.clone())
+}
+
+fn insert(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 2)?;
+    let index = expect_integer(interpreter, &args[0])?;
+    let length = char_ranges(&bytes(receiver), rstring(receiver).encoding.get()).len() as i64;
+    // a negative index inserts after that character
+    let position = if index < 0 { index + length + 1 } else { index };
+    if position < 0 || position > length {
+        return Err(interpreter.error("IndexError", &format!("index {} out of string", index)));
+    }
+    splice(interpreter, receiver, &[Value::Integer(position), Value::Integer(0)], &args[1])?;
+    Ok(receiver.clone())
+}
+
+fn equal(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match &args[0] {
+        Value::String(other) => Ok(Value::Bool(same_contents(rstring(receiver), other))),
+        _ => Ok(Value::Bool(false)),
+    }
+}
+
+fn hash(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let mut hasher = DefaultHasher::new();
+    bytes(receiver).hash(&mut hasher);
+    Ok(Value::Integer(hasher.finish() as i64))
+}
+
+fn compare(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match &args[0] {
+        Value::String(other) => Ok(Value::Integer(bytes(receiver).cmp(&other.bytes.borrow()) as i64)),
+        _ => Ok(Value::Nil),
+    }
+}
+
+fn comparison(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], test: fn(i64) -> bool) -> EvalResult {
+    match compare(interpreter, receiver, args, None)? {
+        Value::Integer(ordering) => Ok(Value::Bool(test(ordering))),
+        _ => {
+            let message = format!("comparison of String with {} failed", interpreter.inspect(&args[0])?);
+            Err(interpreter.error("ArgumentError", &message))
+        }
+    }
+}
+
+/// Compares ignoring ASCII case, or `None` if the argument isn't a string.
+fn casecmp(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Option<i64>, Control> {
+    check_arity(interpreter, args, 1, 1)?;
+    let other = match &args[0] {
+        Value::String(other) => other.bytes.borrow().to_ascii_lowercase(),
+        Value::Symbol(_) => return Err(conversion_error(interpreter, &args[0], "String")),
+        _ => return Ok(None),
+    };
+    Ok(Some(bytes(receiver).to_ascii_lowercase().cmp(&other) as i64))
+}
+
+fn length(_interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    Ok(Value::Integer(char_ranges(&bytes(receiver), rstring(receiver).encoding.get()).len() as i64))
+}
+
+/// A `Regexp` to search with; a string pattern matches its text literally.
+fn pattern_of(interpreter: &mut Interpreter, value: &Value) -> EvalResult {
+    match value {
+        Value::String(string) => regexp::regexp(interpreter, &Regex::escape(&string.text()), Options::default()),
+        value if regexp::is_regexp(interpreter, value) => Ok(value.clone()),
+        value => {
+            let message = format!("wrong argument type {} (expected Regexp)", interpreter.class_of(value).name());
+            Err(interpreter.error("TypeError", &message))
+        }
+    }
+}
+
+fn match_operator(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    if let Value::String(_) = &args[0] {
+        return Err(interpreter.error("TypeError", "wrong argument type String (expected Regexp)"));
+    }
+    interpreter.call_method(&args[0], "=~", std::slice::from_ref(receiver), None)
+}
+
+/// `match` and `match?`, where a string pattern is compiled as a regexp.
+fn matching(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, method: &str) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let pattern = match &args[0] {
+        Value::String(source) => regexp::regexp(interpreter, &source.text(), Options::default())?,
+        pattern => pattern_of(interpreter, pattern)?,
+    };
+    let mut args = args.to_vec();
+    args[0] = receiver.clone();
+    interpreter.call_method(&pattern, method, &args, block)
+}
+
+/// The byte range a `[index]`, `[start, length]` or `[range]` picks out, counting characters.
+fn char_span(interpreter: &Interpreter, receiver: &Value, args: &[Value]) -> Result<Option<(usize, usize)>, Control> {
+    let ranges = char_ranges(&bytes(receiver), rstring(receiver).encoding.get());
+    let length = ranges.len() as i64;
+    let (start, count) = match args {
+        [Value::Range(range)] => match range::span(interpreter, range, length)? {
+            Some(span) => span,
+            None => return Ok(None),
+        },
+        [index] => {
+            let index = expect_integer(interpreter, index)?;
+            let index = if index < 0 { index + length } else { index };
+            if index < 0 || index >= length { return Ok(None); }
+            (index, 1)
+        },
+        [start, count] => {
+            let (start, count) = (expect_integer(interpreter, start)?, expect_integer(interpreter, count)?);
+            let start = if start < 0 { start + length } else { start };
+            if start < 0 || start > length || count < 0 { return Ok(None); }
+            (start, count.min(length - start))
+        },
+        _ => return Ok(None),
+    };
+    let offset = |index: i64| ranges.get(index as usize).map_or(ranges.last().map_or(0, |(_, end)| *end), |(start, _)| *start);
+
+    Ok(Some((offset(start), offset(start + count))))
+}
+
+/// `[index]`, `[start, length]`, `[range]`, `[regexp, capture]` or `[string]`.
+fn index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    if regexp::is_regexp(interpreter, &args[0]) {
+        let text = regexp::text_of(interpreter, receiver)?;
+        let found = regexp::search(interpreter, &args[0], &text, 0)?;
+        interpreter.set_global("$~", found.clone().unwrap_or(Value::Nil));
+        let group = args.get(1).cloned().unwrap_or(Value::Integer(0));
+        return match found {
+            Some(found) => interpreter.call_method(&found, "[]", &[group], None),
+            None => Ok(Value::Nil),
+        };
+    }
+    if let Value::String(other) = &args[0] {
+        let found = find(&bytes(receiver), &other.bytes.borrow(), 0).is_some();
+        return Ok(if found { duplicate(&args[0]) } else { Value::Nil });
+    }
+
+    Ok(match char_span(interpreter, receiver, args)? {
+        Some((start, end)) => Value::bytes(bytes(receiver)[start..end].to_vec(), rstring(receiver).encoding.get()),
+        None => Value::Nil,
+    })
+}
+
+/// `[]=` with any of the selectors `[]` takes, replacing what they pick out.
+fn set_index(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 2, 3)?;
+    let (value, selector) = args.split_last().expect("there are at least two arguments");
+    splice(interpreter, receiver, selector, value)?;
+    Ok(value.clone())
+}
+
+fn splice(interpreter: &mut Interpreter, receiver: &Value, selector: &[Value], value: &Value) -> Result<(), Control> {
+    let string = modifiable(interpreter, receiver)?;
+    let replacement = expect_rstring(interpreter, value)?;
+    let span = if regexp::is_regexp(interpreter, &selector[0]) {
+        let text = regexp::text_of(interpreter, receiver)?;
+        let found = regexp::search(interpreter, &selector[0], &text, 0)?;
+        interpreter.set_global("$~", found.clone().unwrap_or(Value::Nil));
+        let found = found.ok_or_else(|| interpreter.error("IndexError", "regexp not matched"))?;
+        let index = match selector.get(1) {
+            Some(group) => regexp::group_index(interpreter, &found, group)?.unwrap_or(usize::MAX),
+            None => 0,
+        };
+        match regexp::ranges(interpreter, &found).get(index).copied().flatten() {
+            Some(span) => span,
+            None => {
+                let message = format!("index {} out of regexp", interpreter.inspect(&selector[1])?);
+                return Err(interpreter.error("IndexError", &message));
+            },
+        }
+    } else if let Value::String(pattern) = &selector[0] {
+        let pattern = pattern.bytes.borrow().clone();
+        match find(&bytes(receiver), &pattern, 0) {
+            Some(start) => (start, start + pattern.len()),
+            None => return Err(interpreter.error("IndexError", "string not matched")),
+        }
+    } else {
+        match char_span(interpreter, receiver, selector)? {
+            Some(span) => span,
+            None if matches!(selector[0], Value::Range(_)) => {
+                let message = format!("{} out of range", interpreter.inspect(&selector[0])?);
+                return Err(interpreter.error("RangeError", &message));
+            },
+            None => {
+                let message = format!("index {} out of string", interpreter.inspect(&selector[0])?);
+                return Err(interpreter.error("IndexError", &message));
+            },
+        }
+    };
+    let encoding = compatible(interpreter, string, replacement)?;
+    let bytes = replacement.bytes.borrow().clone();
+    string.bytes.borrow_mut().splice(span.0..span.1, bytes);
+    string.encoding.set(encoding);
+
+    Ok(())
+}
+
+/// `index(pattern, start = 0)`: the character index of the first match at or after `start`.
+fn index_of(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let text = regexp::text_of(interpreter, receiver)?;
+    let start = match args.get(1) {
+        Some(start) => match regexp::byte_offset(&text, expect_integer(interpreter, start)?) {
+            Some(start) => start,
+            None => return Ok(Value::Nil),
+        },
+        None => 0,
+    };
+    let found = match &args[0] {
+        Value::String(pattern) => find(text.as_bytes(), &pattern.bytes.borrow(), start),
+        pattern => {
+            let found = regexp::search(interpreter, pattern, &text, start)?;
+            interpreter.set_global("$~", found.clone().unwrap_or(Value::Nil));
+            found.and_then(|found| regexp::ranges(interpreter, &found)[0]).map(|(start, _)| start)
+        },
+    };
+    Ok(found.map_or(Value::Nil, |found| Value::Integer(regexp::char_index(&text, found) as i64)))
+}
+
+/// `rindex(pattern, start = length)`: the character index of the last match starting at or
+/// before `start`.
+fn rindex_of(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let text = regexp::text_of(interpreter, receiver)?;
+    let start = match args.get(1) {
+        Some(start) => {
+            let start = expect_integer(interpreter, start)?;
+            let length = text.chars().count() as i64;
+            if start < -length { return Ok(Value::Nil); }
+            regexp::byte_offset(&text, start.min(length)).unwrap_or(text.len())
+        },
+        None => text.len(),
+    };
+    let pattern = pattern_of(interpreter, &args[0])?;
+    let mut found = None;
+    for position in (0..=start).rev().filter(|position| text.is_char_boundary(*position)) {
+        if let Some(matched) = regexp::search(interpreter, &pattern, &text, position)? {
+            if regexp::ranges(interpreter, &matched)[0].is_some_and(|(start, _)| start == position) {
+                found = Some((position, matched));
+                break;
+            }
+        }
+    }
+    if regexp::is_regexp(interpreter, &args[0]) {
+        interpreter.set_global("$~", found.as_ref().map_or(Value::Nil, |(_, matched)| matched.clone()));
+    }
+    Ok(found.map_or(Value::Nil, |(position, _)| Value::Integer(regexp::char_index(&text, position) as i64)))
+}
+
+fn start_with(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let bytes = bytes(receiver);
+    for prefix in args.iter() {
+        let starts = match prefix {
+            Value::String(prefix) => bytes.starts_with(&prefix.bytes.borrow()),
+            prefix if regexp::is_regexp(interpreter, prefix) => {
+                let text = regexp::text_of(interpreter, receiver)?;
+                let found = regexp::search(interpreter, prefix, &text, 0)?
+                    .filter(|found| regexp::ranges(interpreter, found)[0].is_some_and(|(start, _)| start == 0));
+                interpreter.set_global("$~", found.clone().unwrap_or(Value::Nil));
+                found.is_some()
+            },
+            prefix => return Err(conversion_error(interpreter, prefix, "String")),
+        };
+        if starts { return Ok(Value::Bool(true)); }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// `sub` and `gsub`: the text with the first or every match replaced, or `None` if nothing
+/// matched. The replacement is a string that can refer to groups, a hash of the matched text,
+/// or the block's result.
+fn substitute(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, global: bool) -> Result<Option<String>, Control> {
+    let min = if block.is_some() { 1 } else { 2 };
+    check_arity(interpreter, args, min, 2)?;
+    let pattern = pattern_of(interpreter, &args[0])?;
+    let text = regexp::text_of(interpreter, receiver)?;
+    let template = match args.get(1) {
+        Some(Value::Hash(_)) | None => None,
+        Some(template) => Some(super::expect_string(interpreter, template)?),
+    };
+
+    let mut result = String::new();
+    let (mut last, mut position) = (0, 0);
+    let mut matched = None;
+    while position <= text.len() {
+        let found = match regexp::search(interpreter, &pattern, &text, position)? {
+            Some(found) => found,
+            None => break,
+        };
+        let (start, end) = regexp::ranges(interpreter, &found)[0].expect("the whole pattern matched");
+        interpreter.set_global("$~", found.clone());
+        result.push_str(&text[last..start]);
+        let replacement = match (&template, args.get(1), block) {
+            (Some(template), _, _) => expand(interpreter, template, &found)?,
+            (None, Some(hash), _) => {
+                let value = interpreter.call_method(hash, "[]", &[Value::string(&text[start..end])], None)?;
+                interpreter.to_s(&value)?
+            },
+            (None, None, Some(block)) => {
+                let value = interpreter.call_proc(block, &[Value::string(&text[start..end])])?;
+                interpreter.to_s(&value)?
+            },
+            (None, None, None) => unreachable!("the arity check needs a replacement or a block"),
+        };
+        result.push_str(&replacement);
+        matched = Some(found);
+        last = end;
+        if !global { break; }
+
+        // an empty match moves on a character, so the next search can't find it again
+        position = if end > start { end } else {
+            match text[end..].chars().next() {
+                Some(c) => {
+                    result.push(c);
+                    last = end + c.len_utf8();
+                    last
+                },
+                None => break,
+            }
+        };
+    }
+    result.push_str(&text[last..]);
+    interpreter.set_global("$~", matched.clone().unwrap_or(Value::Nil));
+
+    Ok(matched.map(|_| result))
+}
+
+/// Fills in a replacement string's `\0` or `\&`, `\1` to `\9`, `\k<name>`, `` \` ``, `\'`
+/// and `\\` from a match.
+fn expand(interpreter: &mut Interpreter, template: &str, found: &Value) -> Result<String, Control> {
+    let mut expanded = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            expanded.push(c);
+            continue;
+        }
+        let group = match chars.next() {
+            Some(digit) if digit.is_ascii_digit() => Value::Integer(digit as i64 - '0' as i64),
+            Some('&') => Value::Integer(0),
+            Some(side @ ('`' | '\'')) => {
+                let method = if side == '`' { "pre_match" } else { "post_match" };
+                if let Value::String(text) = interpreter.call_method(found, method, &[], None)? {
+                    expanded += &text.text();
+                }
+                continue;
+            },
+            Some('\\') => {
+                expanded.push('\\');
+                continue;
+            },
+            Some('k') if chars.peek() == Some(&'<') => {
+                chars.next();
+                Value::string(&chars.by_ref().take_while(|c| *c != '>').collect::<String>())
+            },
+            Some(other) => {
+                expanded.push('\\');
+                expanded.push(other);
+                continue;
+            },
+            None => {
+                expanded.push('\\');
+                continue;
+            },
+        };
+        if let Value::String(text) = interpreter.call_method(found, "[]", &[group], None)? {
+            expanded += &text.text();
+        }
+    }
+    Ok(expanded)
+}
+
+fn copy_or_same(receiver: &Value, result: Option<String>) -> EvalResult {
+    let encoding = rstring(receiver).encoding.get();
+    Ok(match result {
+        Some(result) => Value::bytes(result.into_bytes(), encoding),
+        None => duplicate(receiver),
+    })
+}
+
+/// Gives the string new contents, or `nil` if they're no different.
+fn replace_or_nil(receiver: &Value, result: Option<impl Into<Vec<u8>>>) -> EvalResult {
+    let string = rstring(receiver);
+    match result.map(Into::into) {
+        Some(bytes) if bytes != *string.bytes.borrow() => {
+            *string.bytes.borrow_mut() = bytes;
+            Ok(receiver.clone())
+        },
+        _ => Ok(Value::Nil),
+    }
+}
+
+fn copied(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], transform: Transform) -> EvalResult {
+    Ok(Value::bytes(transform(interpreter, receiver, args)?, rstring(receiver).encoding.get()))
+}
+
+fn in_place(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], transform: Transform) -> EvalResult {
+    modifiable(interpreter, receiver)?;
+    let result = transform(interpreter, receiver, args)?;
+    replace_or_nil(receiver, Some(result))
+}
+
+/// `scan(pattern)`: every match, or its groups if the pattern has any, yielded to the block
+/// when there is one.
+fn scan(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    let pattern = pattern_of(interpreter, &args[0])?;
+    let text = regexp::text_of(interpreter, receiver)?;
+    let mut results = vec!();
+    let mut position = 0;
+    while position <= text.len() {
+        let found = match regexp::search(interpreter, &pattern, &text, position)? {
+            Some(found) => found,
+            None => break,
+        };
+        let ranges = regexp::ranges(interpreter, &found);
+        let (start, end) = ranges[0].expect("the whole pattern matched");
+        let piece = |range: &Option<(usize, usize)>| range.map_or(Value::Nil, |(start, end)| Value::string(&text[start..end]));
+        let result = if ranges.len() == 1 { piece(&ranges[0]) } else { Value::array(ranges[1..].iter().map(piece).collect()) };
+        interpreter.set_global("$~", found);
+        match block {
+            Some(block) => { interpreter.call_proc(block, &[result])?; },
+            None => results.push(result),
+        }
+        position = if end > start { end } else { end + text[end..].chars().next().map_or(1, char::len_utf8) };
+    }
+
+    Ok(if block.is_some() { receiver.clone() } else { Value::array(results) })
+}
+
+/// `split(pattern = nil, limit = 0)`. A `nil` or `" "` pattern splits on runs of whitespace
+/// and ignores leading whitespace. Groups in a regexp pattern are included in the result. A
+/// positive limit caps the number of fields, and unless there is one, trailing empty fields
+/// are dropped when the limit is zero.
+fn split(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 0, 2)?;
+    let text = regexp::text_of(interpreter, receiver)?;
+    let limit = match args.get(1) {
+        Some(limit) => expect_integer(interpreter, limit)?,
+        None => 0,
+    };
+    let encoding = rstring(receiver).encoding.get();
+    let piece = |text: &str| Value::bytes(text.as_bytes().to_vec(), encoding);
+    if text.is_empty() { return Ok(Value::array(vec!())); }
+    if limit == 1 { return Ok(Value::array(vec!(duplicate(receiver)))); }
+
+    let mut fields = vec!();
+    let mut splits = 0;
+    let awk = match args.first() {
+        None | Some(Value::Nil) => true,
+        Some(Value::String(pattern)) => *pattern.bytes.borrow() == b" ",
+        Some(_) => false,
+    };
+    if awk {
+        let is_space = |c: char| c.is_ascii_whitespace() || c == '\x0b' || c == '\0';
+        let mut rest = text.trim_start_matches(is_space);
+        while !rest.is_empty() {
+            if limit > 0 && splits == limit - 1 {
+                fields.push(piece(rest));
+                break;
+            }
+            match rest.find(is_space) {
+                Some(end) => {
+                    fields.push(piece(&rest[..end]));
+                    splits += 1;
+                    rest = rest[end..].trim_start_matches(is_space);
+                    if rest.is_empty() && limit != 0 { fields.push(piece("")); }
+                },
+                None => {
+                    fields.push(piece(rest));
+                    break;
+                },
+            }
+        }
+    } else {
+        let pattern = pattern_of(interpreter, &args[0])?;
+        let (mut start, mut position) = (0, 0);
+        while limit <= 0 || splits < limit - 1 {
+            let found = match regexp::search(interpreter, &pattern, &text, position)? {
+                Some(found) => found,
+                None => break,
+            };
+            let ranges = regexp::ranges(interpreter, &found);
+            let (match_start, match_end) = ranges[0].expect("the whole pattern matched");
+            if match_start == match_end {
+                if match_start >= text.len() { break; }
+                // an empty match can't end a field before it has a character
+                if match_start == start {
+                    position = match_start + text[match_start..].chars().next().map_or(1, char::len_utf8);
+                    continue;
+                }
+            }
+            fields.push(piece(&text[start..match_start]));
+            fields.extend(ranges[1..].iter().map(|range| range.map_or(Value::Nil, |(start, end)| piece(&text[start..end]))));
+            splits += 1;
+            start = match_end;
+            position = match_end;
+        }
+        fields.push(piece(&text[start..]));
+    }
+    if limit == 0 {
+        while matches!(fields.last(), Some(Value::String(field)) if field.bytes.borrow().is_empty()) {
+            fields.pop();
+        }
+    }
+
+    Ok(Value::array(fields))
+}
+
+/// `lines(separator = "\n", chomp: false)`: the lines, each keeping its separator unless
+/// `chomp` is set.
+fn lines(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<Value>, Control> {
+    let (args, chomp) = match args.split_last() {
+        Some((options @ Value::Hash(_), rest)) => (rest, super::hash::get(options, &Value::symbol("chomp")).is_some_and(|chomp| chomp.is_truthy())),
+        _ => (args, false),
+    };
+    check_arity(interpreter, args, 0, 1)?;
+    let separator = match args.first() {
+        Some(separator) => expect_rstring(interpreter, separator)?.bytes.borrow().clone(),
+        None => b"\n".to_vec(),
+    };
+    let (bytes, encoding) = (bytes(receiver), rstring(receiver).encoding.get());
+    let mut lines = vec!();
+    let mut start = 0;
+    while start < bytes.len() {
+        let (end, next) = match find(&bytes, &separator, start).filter(|_| !separator.is_empty()) {
+            Some(found) => (if chomp { found } else { found + separator.len() }, found + separator.len()),
+            None => (bytes.len(), bytes.len()),
+        };
+        lines.push(Value::bytes(bytes[start..end].to_vec(), encoding));
+        start = next;
+    }
+    Ok(lines)
+}
+
+fn each_char(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>) -> EvalResult {
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(enumerator_for(interpreter, receiver, "each_char", args)),
+    };
+    for c in chars(receiver) {
+        interpreter.call_proc(block, &[c])?;
+    }
+    Ok(receiver.clone())
+}
+
+fn ord(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let (bytes, encoding) = (bytes(receiver), rstring(receiver).encoding.get());
+    let (start, end) = match char_ranges(&bytes, encoding).first() {
+        Some(range) => *range,
+        None => return Err(interpreter.error("ArgumentError", "empty string")),
+    };
+    match std::str::from_utf8(&bytes[start..end]) {
+        _ if encoding != Encoding::Utf8 => Ok(Value::Integer(bytes[start] as i64)),
+        Ok(c) => Ok(Value::Integer(c.chars().next().map_or(0, |c| c as i64))),
+        Err(_) => Err(interpreter.error("ArgumentError", &format!("invalid byte sequence in {}", encoding.name()))),
+    }
+}
+
+fn is_space(byte: &u8) -> bool {
+    matches!(byte, b'\0' | b' ' | b'\t'..=b'\r')
+}
+
+fn strip(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    let stripped = Value::bytes(rstrip(interpreter, receiver, args)?, Encoding::Binary);
+    lstrip(interpreter, &stripped, args)
+}
+
+fn lstrip(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 0)?;
+    let bytes = bytes(receiver);
+    let start = bytes.iter().position(|byte| !is_space(byte)).unwrap_or(bytes.len());
+    Ok(bytes[start..].to_vec())
+}
+
+fn rstrip(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 0)?;
+    let bytes = bytes(receiver);
+    let end = bytes.iter().rposition(|byte| !is_space(byte)).map_or(0, |last| last + 1);
+    Ok(bytes[..end].to_vec())
+}
+
+fn chop(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 0)?;
+    let bytes = bytes(receiver);
+    if bytes.ends_with(b"\r\n") { return Ok(bytes[..bytes.len() - 2].to_vec()); }
+    let last = char_ranges(&bytes, rstring(receiver).encoding.get()).last().map_or(0, |(start, _)| *start);
+    Ok(bytes[..last].to_vec())
+}
+
+/// `chomp(separator = nil)`: without a separator, removes a trailing `\r\n`, `\n` or `\r`;
+/// with `""`, every trailing line break.
+fn chomp(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 1)?;
+    let mut bytes = bytes(receiver);
+    match args.first() {
+        None => {
+            if bytes.ends_with(b"\n") { bytes.pop(); }
+            if bytes.ends_with(b"\r") { bytes.pop(); }
+        },
+        Some(Value::Nil) => (),
+        Some(separator) => {
+            let separator = expect_rstring(interpreter, separator)?.bytes.borrow().clone();
+            if separator.is_empty() {
+                while bytes.ends_with(b"\n") {
+                    bytes.pop();
+                    if bytes.ends_with(b"\r") { bytes.pop(); }
+                }
+            } else if bytes.ends_with(&separator) {
+                bytes.truncate(bytes.len() - separator.len());
+            }
+        },
+    }
+    Ok(bytes)
+}
+
+/// Maps each character, given whether it's the first; only ASCII letters change in strings
+/// that aren't valid UTF-8.
+fn map_case(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], map: fn(char, bool) -> String) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 1)?;
+    let string = rstring(receiver);
+    let bytes = bytes(receiver);
+    match std::str::from_utf8(&bytes) {
+        Ok(text) if string.encoding.get() == Encoding::Utf8 => {
+            Ok(text.chars().enumerate().map(|(index, c)| map(c, index == 0)).collect::<String>().into_bytes())
+        },
+        _ => Ok(bytes.iter().enumerate().map(|(index, byte)| match byte {
+            byte if byte.is_ascii_alphabetic() => map(*byte as char, index == 0).as_bytes()[0],
+            byte => *byte,
+        }).collect()),
+    }
+}
+
+fn upcase(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    map_case(interpreter, receiver, args, |c, _| c.to_uppercase().collect())
+}
+
+fn downcase(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    map_case(interpreter, receiver, args, |c, _| c.to_lowercase().collect())
+}
+
+fn capitalize(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    map_case(interpreter, receiver, args, |c, first| if first { c.to_uppercase().collect() } else { c.to_lowercase().collect() })
+}
+
+fn swapcase(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    map_case(interpreter, receiver, args, |c, _| if c.is_uppercase() { c.to_lowercase().collect() } else { c.to_uppercase().collect() })
+}
+
+fn reverse(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 0)?;
+    let bytes = bytes(receiver);
+    let ranges = char_ranges(&bytes, rstring(receiver).encoding.get());
+    Ok(ranges.iter().rev().flat_map(|(start, end)| bytes[*start..*end].iter().copied()).collect())
+}
+
+fn delete_prefix(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 1, 1)?;
+    let (bytes, prefix) = (bytes(receiver), expect_rstring(interpreter, &args[0])?.bytes.borrow().clone());
+    Ok(bytes.strip_prefix(prefix.as_slice()).unwrap_or(&bytes).to_vec())
+}
+
+fn delete_suffix(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 1, 1)?;
+    let (bytes, suffix) = (bytes(receiver), expect_rstring(interpreter, &args[0])?.bytes.borrow().clone());
+    Ok(bytes.strip_suffix(suffix.as_slice()).unwrap_or(&bytes).to_vec())
+}
+
+/// The characters a `tr`-style specification like `"a-z"` or `"^aeiou"` stands for.
+struct CharSet {
+    negated: bool,
+    chars:   Vec<char>,
+}
+
+impl CharSet {
+    fn parse(spec: &str) -> CharSet {
+        let spec = spec.chars().collect::<Vec<_>>();
+        let negated = spec.len() > 1 && spec[0] == '^';
+        let spec = if negated { &spec[1..] } else { &spec[..] };
+        let mut chars = vec!();
+        let mut index = 0;
+        while index < spec.len() {
+            match spec.get(index..index + 3) {
+                Some([from, '-', to]) if from <= to => {
+                    chars.extend(*from..=*to);
+                    index += 3;
+                },
+                _ => {
+                    if spec[index] == '\\' && index + 1 < spec.len() { index += 1; }
+                    chars.push(spec[index]);
+                    index += 1;
+                },
+            }
+        }
+        CharSet { negated, chars }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.chars.contains(&c) != self.negated
+    }
+}
+
+/// The sets given as arguments; a character has to be in all of them.
+fn char_sets(interpreter: &mut Interpreter, args: &[Value], min: usize) -> Result<Vec<CharSet>, Control> {
+    check_arity(interpreter, args, min, usize::MAX)?;
+    args.iter().map(|spec| super::expect_string(interpreter, spec).map(|spec| CharSet::parse(&spec))).collect()
+}
+
+fn delete(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    let sets = char_sets(interpreter, args, 1)?;
+    Ok(contents(receiver).chars().filter(|c| !sets.iter().all(|set| set.contains(*c))).collect::<String>().into_bytes())
+}
+
+/// `squeeze(*sets)`: runs of the same character, or of those in the sets, become one.
+fn squeeze(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    let sets = char_sets(interpreter, args, 0)?;
+    let mut squeezed = String::new();
+    for c in contents(receiver).chars() {
+        if squeezed.ends_with(c) && sets.iter().all(|set| set.contains(c)) { continue; }
+        squeezed.push(c);
+    }
+    Ok(squeezed.into_bytes())
+}
+
+/// `tr(from, to)`: each character in `from` becomes the one at the same place in `to`, whose
+/// last character repeats as needed. An empty `to` deletes them.
+fn translate(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 2, 2)?;
+    let from = CharSet::parse(&super::expect_string(interpreter, &args[0])?);
+    let to = CharSet::parse(&super::expect_string(interpreter, &args[1])?).chars;
+    let mut translated = String::new();
+    for c in contents(receiver).chars() {
+        if !from.contains(c) {
+            translated.push(c);
+            continue;
+        }
+        let position = if from.negated { to.len() } else { from.chars.iter().position(|known| *known == c).unwrap_or(0) };
+        if let Some(replacement) = to.get(position).or(to.last()) {
+            translated.push(*replacement);
+        }
+    }
+    Ok(translated.into_bytes())
+}
+
+/// `succ`: the rightmost letter or digit is incremented, carrying into the ones to its left,
+/// so `"az"` is followed by `"ba"` and `"zz"` by `"aaa"`. Without any, the last character is.
+fn successor(interpreter: &mut Interpreter, receiver: &Value, args: &[Value]) -> Result<Vec<u8>, Control> {
+    check_arity(interpreter, args, 0, 0)?;
+    let mut chars = contents(receiver).chars().collect::<Vec<_>>();
+    if chars.is_empty() { return Ok(vec!()); }
+    if !chars.iter().any(char::is_ascii_alphanumeric) {
+        let last = chars.len() - 1;
+        chars[last] = char::from_u32(chars[last] as u32 + 1).unwrap_or(chars[last]);
+        return Ok(chars.into_iter().collect::<String>().into_bytes());
+    }
+
+    let mut index = chars.iter().rposition(char::is_ascii_alphanumeric).expect("there is a letter or digit");
+    loop {
+        let (next, carry) = match chars[index] {
+            'z' => ('a', Some('a')),
+            'Z' => ('A', Some('A')),
+            '9' => ('0', Some('1')),
+            c => (char::from_u32(c as u32 + 1).expect("an ASCII letter or digit"), None),
+        };
+        chars[index] = next;
+        let carry = match carry {
+            Some(carry) => carry,
+            None => break,
+        };
+        match chars[..index].iter().rposition(char::is_ascii_alphanumeric) {
+            Some(previous) => index = previous,
+            None => {
+                chars.insert(index, carry);
+                break;
+            },
+        }
+    }
+    Ok(chars.into_iter().collect::<String>().into_bytes())
+}
+
+#[derive(Clone, Copy)]
+enum Justify {
+    Left,
+    Right,
+    Center,
+}
+
+fn justify(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], justify: Justify) -> EvalResult {
+    check_arity(interpreter, args, 1, 2)?;
+    let width = expect_integer(interpreter, &args[0])?;
+    let padding = match args.get(1) {
+        Some(padding) => super::expect_string(interpreter, padding)?,
+        None => String::from(" "),
+    };
+    if padding.is_empty() { return Err(interpreter.error("ArgumentError", "zero width padding")); }
+
+    let text = contents(receiver);
+    let missing = (width - text.chars().count() as i64).max(0) as usize;
+    let (left, right) = match justify {
+        Justify::Left => (0, missing),
+        Justify::Right => (missing, 0),
+        Justify::Center => (missing / 2, missing - missing / 2),
+    };
+    let pad = |count: usize| padding.chars().cycle().take(count).collect::<String>();
+    Ok(Value::bytes(format!("{}{}{}", pad(left), text, pad(right)).into_bytes(), rstring(receiver).encoding.get()))
+}
+
+/// `to_i`: the integer at the start of the text, ignoring whatever follows it, or 0. Base 0
+/// takes the base from a prefix like `0x`.
+fn to_integer(text: &str, base: u32) -> Value {
+    let text = text.trim_start();
+    let bytes = text.as_bytes();
+    let mut end = if matches!(bytes.first(), Some(b'+' | b'-')) { 1 } else { 0 };
+    let prefix = bytes.get(end..end + 2).map(|prefix| prefix.to_ascii_lowercase());
+    let radix = match prefix.as_deref() {
+        Some(b"0x") if base == 16 || base == 0 => { end += 2; 16 },
+        Some(b"0b") if base == 2 || base == 0 => { end += 2; 2 },
+        Some(b"0o") if base == 8 || base == 0 => { end += 2; 8 },
+        Some(b"0d") if base == 10 || base == 0 => { end += 2; 10 },
+        _ if base == 0 && bytes.get(end) == Some(&b'0') => 8,
+        _ if base == 0 => 10,
+        _ => base,
+    };
+    let digits_start = end;
+    while let Some(byte) = bytes.get(end) {
+        let is_digit = (*byte as char).is_digit(radix);
+        let separates = *byte == b'_' && end > digits_start && bytes.get(end + 1).is_some_and(|next| (*next as char).is_digit(radix));
+        if !is_digit && !separates { break; }
+        end += 1;
+    }
+    if end == digits_start { return Value::Integer(0); }
+    let sign = if bytes[0] == b'-' { "-" } else { "" };
+    numeric::parse_integer(&format!("{}{}", sign, &text[digits_start..end]), Some(radix)).unwrap_or(Value::Integer(0))
+}
+
+/// `to_f`: the float at the start of the text, or 0.0.
+fn to_float(text: &str) -> f64 {
+    let text = text.trim_start();
+    let end = text.find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '_' | 'e' | 'E' | '+' | '-'))).unwrap_or(text.len());
+    (1..=end).rev().find_map(|end| match &text[..end] {
+        prefix if prefix.contains(['x', 'X']) => None,
+        prefix => numeric::parse_float(prefix),
+    }).unwrap_or(0.0)
+}
+
+/// `encode(to = "UTF-8", from = encoding, invalid: nil, undef: nil, replace: nil)`. Bytes that
+/// aren't valid in the source encoding raise `Encoding::InvalidByteSequenceError`, and
+/// characters the target can't hold `Encoding::UndefinedConversionError`, unless the
+/// options ask for them to be replaced.
+fn encode(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let (args, options) = match args.split_last() {
+        Some((options @ Value::Hash(_), rest)) => (rest, Some(options.clone())),
+        _ => (args, None),
+    };
+    check_arity(interpreter, args, 0, 2)?;
+    let option = |name: &str| options.as_ref().and_then(|options| super::hash::get(options, &Value::symbol(name)));
+    let to = match args.first() {
+        Some(to) => encoding::expect_encoding(interpreter, to)?,
+        None => Encoding::Utf8,
+    };
+    let from = match args.get(1) {
+        Some(from) => encoding::expect_encoding(interpreter, from)?,
+        None => rstring(receiver).encoding.get(),
+    };
+    let replace_invalid = matches!(option("invalid"), Some(Value::Symbol(mode)) if mode == "replace");
+    let replace_undefined = matches!(option("undef"), Some(Value::Symbol(mode)) if mode == "replace");
+    let replacement = match option("replace") {
+        Some(replacement) => super::expect_string(interpreter, &replacement)?,
+        None if to == Encoding::Utf8 => String::from("\u{fffd}"),
+        None => String::from("?"),
+    };
+    if from == to && !replace_invalid { return Ok(duplicate(receiver)); }
+
+    let bytes = bytes(receiver);
+    let mut encoded = vec!();
+    for (start, end) in char_ranges(&bytes, from) {
+        let unit = &bytes[start..end];
+        let c = match (from, std::str::from_utf8(unit)) {
+            (Encoding::Utf8, Ok(c)) => c.chars().next(),
+            (_, Ok(c)) if c.is_ascii() => c.chars().next(),
+            (Encoding::Binary, _) if from != to => {
+                if !replace_undefined {
+                    let message = format!("\"\\x{:02X}\" from {} to {}", unit[0], from.name(), to.name());
+                    return Err(interpreter.error("Encoding::UndefinedConversionError", &message));
+                }
+                None
+            },
+            (Encoding::Binary, _) => {
+                encoded.extend_from_slice(unit);
+                continue;
+            },
+            _ => {
+                if !replace_invalid {
+                    let message = format!("\"\\x{:02X}\" on {}", unit[0], from.name());
+                    return Err(interpreter.error("Encoding::InvalidByteSequenceError", &message));
+                }
+                None
+            },
+        };
+        match c {
+            Some(c) if to == Encoding::Utf8 || c.is_ascii() => encoded.extend_from_slice(c.to_string().as_bytes()),
+            Some(c) if !replace_undefined => {
+                let message = format!("U+{:04X} from {} to {}", c as u32, from.name(), to.name());
+                return Err(interpreter.error("Encoding::UndefinedConversionError", &message));
+            },
+            _ => encoded.extend_from_slice(replacement.as_bytes()),
+        }
+    }
+
+    Ok(Value::bytes(encoded, to))
+}
@@ -0,0 +1,94 @@
+use std::rc::Rc;
+
+use crate::eval::{ EvalResult, Interpreter };
+use crate::eval::builtins::{ check_arity, string };
+use crate::eval::value::{ RProc, Value };
+
+const OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "**", "==", "===", "!=", "<=>", "<", "<=", ">", ">=", "<<", ">>", "&", "|",
+    "^", "~", "!", "=~", "!~", "[]", "[]=", "+@", "-@",
+];
+
+pub fn define(interpreter: &mut Interpreter) {
+    let symbol = interpreter.classes.symbol.clone();
+    symbol.define_builtin("to_s", |_, r, _, _| Ok(Value::string(&name(r))));
+    symbol.define_builtin("id2name", |_, r, _, _| Ok(Value::string(&name(r))));
+    symbol.define_builtin("name", |_, r, _, _| Ok(Value::string(&name(r))));
+    symbol.define_builtin("to_sym", |_, r, _, _| Ok(r.clone()));
+    symbol.define_builtin("inspect", |_, r, _, _| Ok(Value::string(&inspect(&name(r)))));
+    symbol.define_builtin("length", |_, r, _, _| Ok(Value::Integer(name(r).chars().count() as i64)));
+    symbol.define_builtin("size", |_, r, _, _| Ok(Value::Integer(name(r).chars().count() as i64)));
+    symbol.define_builtin("<=>", compare);
+    symbol.define_builtin("==", |i, r, a, _| {
+        check_arity(i, a, 1, 1)?;
+        Ok(Value::Bool(r.is_identical(&a[0])))
+    });
+    symbol.define_builtin("to_proc", to_proc);
+    symbol.define_builtin("empty?", |_, r, _, _| Ok(Value::Bool(name(r).is_empty())));
+    symbol.define_builtin("upcase", |i, r, a, b| through_string(i, r, a, b, "upcase"));
+    symbol.define_builtin("downcase", |i, r, a, b| through_string(i, r, a, b, "downcase"));
+    symbol.define_builtin("capitalize", |i, r, a, b| through_string(i, r, a, b, "capitalize"));
+    symbol.define_builtin("swapcase", |i, r, a, b| through_string(i, r, a, b, "swapcase"));
+    symbol.define_builtin("succ", |i, r, a, b| through_string(i, r, a, b, "succ"));
+    symbol.define_builtin("next", |i, r, a, b| through_string(i, r, a, b, "succ"));
+    symbol.define_builtin("[]", |i, r, a, b| i.call_method(&Value::string(&name(r)), "[]", a, b));
+    symbol.define_builtin("start_with?", |i, r, a, b| i.call_method(&Value::string(&name(r)), "start_with?", a, b));
+    symbol.define_builtin("end_with?", |i, r, a, b| i.call_method(&Value::string(&name(r)), "end_with?", a, b));
+    symbol.define_builtin("=~", |i, r, a, b| i.call_method(&Value::string(&name(r)), "=~", a, b));
+}
+
+fn name(value: &Value) -> String {
+    match value {
+        Value::Symbol(name) => name.to_string(),
+        _ => unreachable!("a Symbol method called on {:?}", value),
+    }
+}
+
+/// `:name`, quoting the name when it wouldn't read back as a symbol literal, like `:"two words"`.
+pub fn inspect(name: &str) -> String {
+    if is_plain(name) { format!(":{}", name) } else { format!(":{}", string::inspect(name)) }
+}
+
+/// Whether the symbol can be written without quotes: an identifier, constant, variable, method
+/// name with `?`, `!` or `=`, or an operator.
+pub fn is_plain(name: &str) -> bool {
+    if OPERATORS.contains(&name) { return true; }
+
+    let body = name.trim_start_matches("@@").trim_start_matches('@').trim_start_matches('$');
+    let sigil = body.len() != name.len();
+    let body = if sigil { body } else { body.trim_end_matches(['?', '!', '=']) };
+    if name.len() - body.len() > 1 && !name.starts_with("@@") { return false; }
+
+    let mut chars = body.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || !c.is_ascii() => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || !c.is_ascii())
+}
+
+/// The `String` method of the same name applied to the name, giving a symbol back.
+fn through_string(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], block: Option<&Rc<RProc>>, method: &str) -> EvalResult {
+    let result = interpreter.call_method(&Value::string(&name(receiver)), method, args, block)?;
+    interpreter.call_method(&result, "to_sym", &[], None)
+}
+
+/// A lambda calling the method on its first argument with the rest, which is what `&:name`
+/// passes as a block.
+fn to_proc(interpreter: &mut Interpreter, receiver: &Value, _args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    let method = name(receiver);
+    let proc = interpreter.native_proc(move |interpreter, args| match args.split_first() {
+        Some((receiver, rest)) => interpreter.call_public(receiver, &method, rest, None, false),
+        None => Err(interpreter.error("ArgumentError", "no receiver given")),
+    });
+    let lambda = RProc { body: proc.body.clone(), scope: proc.scope.clone(), context: proc.context.clone(), lambda: true };
+    Ok(Value::Proc(Rc::new(lambda)))
+}
+
+fn compare(interpreter: &mut Interpreter, receiver: &Value, args: &[Value], _block: Option<&Rc<RProc>>) -> EvalResult {
+    check_arity(interpreter, args, 1, 1)?;
+    match &args[0] {
+        Value::Symbol(other) => Ok(Value::Integer(name(receiver).as_str().cmp(other) as i64)),
+        _ => Ok(Value::Nil),
+    }
+}
@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::node::{ Ast, NodeId };
+use crate::eval::{ EvalResult, Interpreter };
+use crate::eval::environment::Nesting;
+use crate::eval::value::{ RProc, Value };
+
+/// A method implemented in Rust. It gets the receiver, the evaluated arguments and the block
+/// passed to the call, if any.
+pub type Builtin = fn(&mut Interpreter, &Value, &[Value], Option<&Rc<RProc>>) -> EvalResult;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Protected,
+    Private,
+}
+
+#[derive(Clone)]
+pub enum MethodBody {
+    Builtin(Builtin),
+    /// A `def` node in `ast`, with the modules it was nested in for constant lookup
+    Defined(Rc<Ast>, NodeId, Rc<Nesting>),
+    /// A block given to `define_method`
+    Proc(Rc<RProc>),
+    /// Reads the instance variable, as defined by `attr_reader`
+    AttrReader(String),
+    /// Assigns the instance variable, as defined by `attr_writer`
+    AttrWriter(String),
+}
+
+#[derive(Clone)]
+pub struct Method {
+    pub body:       MethodBody,
+    pub visibility: Visibility,
+}
+
+/// A class or module: its name, superclass, the modules mixed into it, and the methods,
+/// constants and class variables defined in it. Singleton classes are classes too, attached
+/// to the one object whose methods they hold.
+pub struct RClass {
+    /// Empty for a class that hasn't been assigned to a constant yet
+    pub name:            RefCell<String>,
+    pub superclass:      RefCell<Option<Rc<RClass>>>,
+    pub is_module:       bool,
+    /// The object this is the singleton class of
+    pub attached:        Option<Value>,
+    /// Created the first time a singleton method is defined on the class or looked up
+    pub singleton:       RefCell<Option<Rc<RClass>>>,
+    /// Most recently included first
+    pub includes:        RefCell<Vec<Rc<RClass>>>,
+    /// Most recently prepended first
+    pub prepends:        RefCell<Vec<Rc<RClass>>>,
+    pub methods:         RefCell<HashMap<String, Method>>,
+    pub constants:       RefCell<HashMap<String, Value>>,
+    pub class_variables: RefCell<HashMap<String, Value>>,
+    /// The instance variables of the class object itself, set by its class methods
+    pub ivars:           RefCell<Vec<(String, Value)>>,
+}
+
+impl RClass {
+    pub fn new(name: &str, superclass: Option<Rc<RClass>>) -> Rc<RClass> {
+        Rc::new(Self::build(name, superclass, false, None))
+    }
+
+    pub fn module(name: &str) -> Rc<RClass> {
+        Rc::new(Self::build(name, None, true, None))
+    }
+
+    /// A singleton class for `attached`, inheriting from the class it had before.
+    pub fn singleton(superclass: Rc<RClass>, attached: Value) -> Rc<RClass> {
+        Rc::new(Self::build("", Some(superclass), false, Some(attached)))
+    }
+
+    fn build(name: &str, superclass: Option<Rc<RClass>>, is_module: bool, attached: Option<Value>) -> RClass {
+        RClass {
+            name:            RefCell::new(name.to_string()),
+            superclass:      RefCell::new(superclass),
+            is_module,
+            attached,
+            singleton:       RefCell::new(None),
+            includes:        RefCell::new(vec!()),
+            prepends:        RefCell::new(vec!()),
+            methods:         RefCell::new(HashMap::new()),
+            constants:       RefCell::new(HashMap::new()),
+            class_variables: RefCell::new(HashMap::new()),
+            ivars:           RefCell::new(vec!()),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.borrow().clone()
+    }
+
+    pub fn is_singleton(&self) -> bool {
+        self.attached.is_some()
+    }
+
+    /// The name, or how Ruby shows a class without one: `#<Class:Foo>` for the singleton class
+    /// of `Foo` and `#<Class:0x...>` for an anonymous class.
+    pub fn inspect(&self) -> String {
+        let name = self.name();
+        if !name.is_empty() { return name; }
+
+        match &self.attached {
+            Some(Value::Class(class)) => format!("#<Class:{}>", class.inspect()),
+            Some(Value::Object(object)) => {
+                format!("#<Class:#<{}:0x{:016x}>>", object.class.inspect(), Rc::as_ptr(object) as usize)
+            },
+            Some(value) => format!("#<Class:{:?}>", value),
+            None => {
+                let kind = if self.is_module { "Module" } else { "Class" };
+                format!("#<{}:0x{:016x}>", kind, self as *const RClass as usize)
+            }
+        }
+    }
+
+    pub fn superclass(&self) -> Option<Rc<RClass>> {
+        self.superclass.borrow().clone()
+    }
+
+    pub fn define(&self, name: &str, method: Method) {
+        self.methods.borrow_mut().insert(name.to_string(), method);
+    }
+
+    pub fn set_constant(&self, name: &str, value: Value) {
+        self.constants.borrow_mut().insert(name.to_string(), value);
+    }
+
+    pub fn define_builtin(&self, name: &str, builtin: Builtin) {
+        self.define(name, Method { body: MethodBody::Builtin(builtin), visibility: Visibility::Public });
+    }
+
+    /// Defines a builtin that can only be called without a receiver, like `initialize`.
+    pub fn define_private_builtin(&self, name: &str, builtin: Builtin) {
+        self.define(name, Method { body: MethodBody::Builtin(builtin), visibility: Visibility::Private });
+    }
+
+    /// The classes and modules searched for a method, in order: the modules prepended to
+    /// this class, the class itself, the modules it includes, then the same for each
+    /// superclass. A module mixed in more than once is only searched at its last position,
+    /// so including a module a superclass already has doesn't change the order.
+    pub fn ancestors(self: &Rc<Self>) -> Vec<Rc<RClass>> {
+        let mut ancestors = vec!();
+        let mut class = Some(self.clone());
+        while let Some(current) = class {
+            current.push_with_modules(&mut ancestors);
+            class = current.superclass();
+        }
+
+        let mut unique: Vec<Rc<RClass>> = vec!();
+        for class in ancestors.into_iter().rev() {
+            if !unique.iter().any(|seen| Rc::ptr_eq(seen, &class)) {
+                unique.push(class);
+            }
+        }
+        unique.reverse();
+        unique
+    }
+
+    fn push_with_modules(self: &Rc<Self>, ancestors: &mut Vec<Rc<RClass>>) {
+        for module in self.prepends.borrow().iter() {
+            module.push_with_modules(ancestors);
+        }
+        ancestors.push(self.clone());
+        for module in self.includes.borrow().iter() {
+            module.push_with_modules(ancestors);
+        }
+    }
+
+    /// Finds `name` in the nearest ancestor defining it, returning the method together with
+    /// the class or module it was found in.
+    pub fn find_method(self: &Rc<Self>, name: &str) -> Option<(Method, Rc<RClass>)> {
+        self.ancestors().into_iter().find_map(|class| {
+            let method = class.methods.borrow().get(name).cloned();
+            method.map(|method| (method, class))
+        })
+    }
+
+    /// Whether this class is `other`, inherits from it or has it mixed in.
+    pub fn inherits(self: &Rc<Self>, other: &Rc<RClass>) -> bool {
+        self.ancestors().iter().any(|class| Rc::ptr_eq(class, other))
+    }
+}
@@ -0,0 +1,212 @@
+use std::cell::{ Cell, RefCell };
+use std::mem;
+use std::rc::Rc;
+use std::panic::{ self, AssertUnwindSafe };
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::thread::{ self, JoinHandle };
+
+use crate::eval::{ Control, EvalResult, Interpreter, Stacks };
+use crate::eval::value::Value;
+
+/// As much stack as the main interpreter thread needs for `MAX_CALL_DEPTH` calls.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Something handed between a coroutine's thread and the thread resuming it. Values aren't
+/// `Send`, but the two threads take turns: each one waits on a channel for as long as the
+/// other runs, so no object is ever used by both at once.
+struct Handoff<T>(T);
+
+unsafe impl Send for Handoff<Resume> {}
+unsafe impl Send for Handoff<Step> {}
+
+type Body = Box<dyn FnOnce(&mut Interpreter, Rc<Suspender>) -> EvalResult>;
+
+/// What the resuming thread tells the coroutine's thread.
+enum Resume {
+    /// Run the body, on the interpreter given
+    Start(*mut Interpreter, Body),
+    /// Go on from where it suspended, on the interpreter it started on
+    Continue,
+    /// Unwind without running anything more, as the coroutine was dropped
+    Terminate,
+}
+
+enum Step {
+    Suspend(Vec<Value>),
+    Finish(EvalResult),
+}
+
+/// The panic payload a terminated coroutine unwinds its thread with.
+struct Terminated;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Created,
+    Suspended,
+    Running,
+    Finished,
+}
+
+/// Code that can stop halfway and go on later, like the `each` behind `Enumerator#next`. It
+/// runs on a thread of its own, on interpreter stacks of its own, whenever it is resumed.
+/// Dropping one that hasn't finished unwinds its thread and waits for it to end.
+pub struct Coroutine {
+    resume: Sender<Handoff<Resume>>,
+    steps:  Receiver<Handoff<Step>>,
+    /// The body, until the first resume hands it to the thread
+    body:   RefCell<Option<Body>>,
+    /// The coroutine's stacks while it is suspended
+    stacks: RefCell<Option<Stacks>>,
+    state:  Cell<State>,
+    /// What the body returned, once it has finished
+    result: RefCell<Value>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// How the code running in a coroutine hands back control.
+pub struct Suspender {
+    resume: Receiver<Handoff<Resume>>,
+    steps:  Sender<Handoff<Step>>,
+}
+
+impl Coroutine {
+    /// A coroutine that will run `body` when it is first resumed.
+    pub fn new(body: impl FnOnce(&mut Interpreter, Rc<Suspender>) -> EvalResult + 'static) -> Coroutine {
+        let (resume, resumed) = mpsc::channel();
+        let (stepped, steps) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let suspender = Rc::new(Suspender { resume: resumed, steps: stepped });
+                let (interpreter, body) = match suspender.resume.recv() {
+                    Ok(Handoff(Resume::Start(interpreter, body))) => (interpreter, body),
+                    _ => return,
+                };
+                // SAFETY: the resuming thread waits for the next step without touching the
+                // interpreter, and a suspended coroutine waits until it is resumed again
+                let interpreter = unsafe { &mut *interpreter };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| body(interpreter, suspender.clone())));
+                match result {
+                    Ok(result) => {
+                        // the blocks it made may still hold the suspender, and drop it on the other thread
+                        let suspender = mem::ManuallyDrop::new(suspender);
+                        suspender.hand_over(Step::Finish(result));
+                    },
+                    Err(payload) if payload.is::<Terminated>() => {},
+                    Err(payload) => panic::resume_unwind(payload),
+                }
+            })
+            .expect("failed to start a thread for a coroutine");
+
+        Coroutine {
+            resume,
+            steps,
+            body:   RefCell::new(Some(Box::new(body))),
+            stacks: RefCell::new(None),
+            state:  Cell::new(State::Created),
+            result: RefCell::new(Value::Nil),
+            thread: Some(thread),
+        }
+    }
+
+    /// Runs the coroutine until it suspends itself, giving the values it suspended with, or
+    /// `None` once it has finished. What it raises is raised here.
+    pub fn resume(&self, interpreter: &mut Interpreter) -> Result<Option<Vec<Value>>, Control> {
+        let message = match self.state.get() {
+            State::Finished => return Ok(None),
+            State::Running => return Err(interpreter.error("FiberError", "attempt to resume the current fiber")),
+            State::Created => Resume::Start(interpreter as *mut Interpreter, self.body.borrow_mut().take().expect("a coroutine starts once")),
+            State::Suspended => Resume::Continue,
+        };
+
+        let stacks = self.stacks.borrow_mut().take().unwrap_or_else(|| interpreter.new_stacks());
+        let caller = interpreter.switch_stacks(stacks);
+        self.state.set(State::Running);
+        let sent = self.resume.send(Handoff(message));
+        let step = sent.ok().and_then(|_| self.steps.recv().ok()).expect("a coroutine's thread stopped");
+        let stacks = interpreter.switch_stacks(caller);
+
+        match step.0 {
+            Step::Suspend(values) => {
+                *self.stacks.borrow_mut() = Some(stacks);
+                self.state.set(State::Suspended);
+                Ok(Some(values))
+            },
+            Step::Finish(result) => {
+                self.state.set(State::Finished);
+                *self.result.borrow_mut() = result?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// What the body returned, or nil until it has finished.
+    pub fn result(&self) -> Value {
+        self.result.borrow().clone()
+    }
+}
+
+impl Drop for Coroutine {
+    fn drop(&mut self) {
+        // a running coroutine is being resumed further up this thread's stack, so can't be dropped
+        if self.state.get() == State::Running { return; }
+        if self.state.get() != State::Finished { let _ = self.resume.send(Handoff(Resume::Terminate)); }
+        // while the thread unwinds this one waits, so the objects it drops aren't in use here
+        if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+    }
+}
+
+impl Suspender {
+    /// Hands `values` to whoever resumed the coroutine, then waits to be resumed again.
+    pub fn suspend(&self, values: Vec<Value>) {
+        self.hand_over(Step::Suspend(values));
+        self.wait();
+    }
+
+    fn hand_over(&self, step: Step) {
+        if let Err(unsent) = self.steps.send(Handoff(step)) {
+            mem::forget(unsent);
+            Self::terminate();
+        }
+    }
+
+    fn wait(&self) {
+        match self.resume.recv() {
+            Ok(Handoff(Resume::Continue)) => {},
+            _ => Self::terminate(),
+        }
+    }
+
+    /// Unwinds the thread of a coroutine that was dropped without finishing, dropping what
+    /// its body holds.
+    fn terminate() -> ! {
+        panic::resume_unwind(Box::new(Terminated))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_unwind_a_coroutine_dropped_halfway() {
+        let mut interpreter = Interpreter::new();
+        let held = Rc::new(());
+        let body_held = held.clone();
+        let coroutine = Coroutine::new(move |_, suspender| {
+            let _held = body_held;
+            for i in 0..3 {
+                suspender.suspend(vec!(Value::Integer(i)));
+            }
+            Ok(Value::Nil)
+        });
+        assert!(matches!(coroutine.resume(&mut interpreter), Ok(Some(values)) if values.len() == 1));
+        assert_eq!(Rc::strong_count(&held), 2);
+
+        drop(coroutine);
+        assert_eq!(Rc::strong_count(&held), 1);
+
+        let never_started = Coroutine::new(|_, _| Ok(Value::Nil));
+        drop(never_started);
+    }
+}
@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::node::{ Ast, NodeId };
+use crate::eval::class::RClass;
+use crate::eval::value::{ RProc, Value };
+
+/// The local variables of a method body, block or the top level. A block's scope has the scope
+/// it was created in as its parent, so it can read and assign the variables around it for as
+/// long as it lives.
+pub struct Scope {
+    variables: RefCell<HashMap<String, Value>>,
+    parent:    Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new() -> Rc<Scope> {
+        Rc::new(Scope { variables: RefCell::new(HashMap::new()), parent: None })
+    }
+
+    pub fn child(parent: &Rc<Scope>) -> Rc<Scope> {
+        Rc::new(Scope { variables: RefCell::new(HashMap::new()), parent: Some(parent.clone()) })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.variables.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.get(name)),
+        }
+    }
+
+    /// Assigns to the variable in the nearest scope that has it, or creates it in this one.
+    pub fn set(&self, name: &str, value: Value) {
+        if !self.assign_existing(name, &value) {
+            self.declare(name, value);
+        }
+    }
+
+    /// Creates the variable in this scope, shadowing any outer one, as block parameters do.
+    pub fn declare(&self, name: &str, value: Value) {
+        self.variables.borrow_mut().insert(name.to_string(), value);
+    }
+
+    fn assign_existing(&self, name: &str, value: &Value) -> bool {
+        if let Some(slot) = self.variables.borrow_mut().get_mut(name) {
+            *slot = value.clone();
+            return true;
+        }
+
+        self.parent.as_ref().is_some_and(|parent| parent.assign_existing(name, value))
+    }
+
+    /// The names of the variables visible from this scope, innermost first.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.variables.borrow().keys().cloned().collect();
+        names.sort();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.names().into_iter().filter(|name| !self.variables.borrow().contains_key(name)));
+        }
+
+        names
+    }
+}
+
+/// The modules lexically enclosing a piece of code, innermost first, which is where relative
+/// constants are looked up and assigned.
+pub struct Nesting {
+    pub module: Rc<RClass>,
+    pub parent: Option<Rc<Nesting>>,
+}
+
+impl Nesting {
+    pub fn new(module: Rc<RClass>, parent: Option<Rc<Nesting>>) -> Rc<Nesting> {
+        Rc::new(Nesting { module, parent })
+    }
+}
+
+/// A running method, as `super` needs to find the next method up the ancestors and pass on
+/// the arguments and block.
+pub struct MethodCall {
+    pub name:       String,
+    /// The class or module the method was found in
+    pub owner:      Rc<RClass>,
+    /// The `def` node of a method defined in Ruby, whose parameters a bare `super` forwards
+    pub definition: Option<(Rc<Ast>, NodeId)>,
+    pub block:      Option<Rc<RProc>>,
+}
+
+/// What `self`, `def` and constants refer to in a method body, a class body or the top
+/// level. Blocks capture the context they are created in along with its local variables.
+#[derive(Clone)]
+pub struct Context {
+    pub self_value: Value,
+    /// The class a `def` adds its method to
+    pub definee:    Rc<RClass>,
+    pub nesting:    Rc<Nesting>,
+    pub method:     Option<Rc<MethodCall>>,
+    /// Identifies the method or lambda call a `return` leaves
+    pub returns_to: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_assign_to_the_scope_that_has_the_variable() {
+        let outer = Scope::new();
+        outer.set("x", Value::Integer(1));
+        let inner = Scope::child(&outer);
+        inner.set("x", Value::Integer(2));
+        inner.set("y", Value::Integer(3));
+
+        assert!(matches!(outer.get("x"), Some(Value::Integer(2))));
+        assert!(outer.get("y").is_none());
+        assert!(matches!(inner.get("y"), Some(Value::Integer(3))));
+
+        inner.declare("x", Value::Integer(4));
+        assert!(matches!(outer.get("x"), Some(Value::Integer(2))));
+        assert_eq!(inner.names(), vec!("x", "y"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::process;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use crate::eval::value::Value;
+
+const N: usize = 624;
+const M: usize = 397;
+
+/// The Mersenne Twister MT19937, seeded and drawn from the way MRI does it so that a program
+/// calling `srand` sees the same numbers it would there.
+pub struct Random {
+    state: [u32; N],
+    index: usize,
+}
+
+impl Random {
+    /// A generator seeded with the words of a seed: one word seeds it directly, more go
+    /// through `init_by_array`.
+    pub fn new(key: &[u32]) -> Random {
+        let mut random = Random { state: [0; N], index: N };
+        match key {
+            [] => random.init(0),
+            [word] => random.init(*word),
+            key => random.init_by_array(key),
+        }
+        random
+    }
+
+    fn init(&mut self, seed: u32) {
+        self.state[0] = seed;
+        for i in 1..N {
+            let previous = self.state[i - 1];
+            self.state[i] = 1_812_433_253_u32.wrapping_mul(previous ^ (previous >> 30)).wrapping_add(i as u32);
+        }
+        self.index = N;
+    }
+
+    fn init_by_array(&mut self, key: &[u32]) {
+        self.init(19_650_218);
+        let (mut i, mut j) = (1, 0);
+        for _ in 0..N.max(key.len()) {
+            let previous = self.state[i - 1];
+            self.state[i] = (self.state[i] ^ (previous ^ (previous >> 30)).wrapping_mul(1_664_525))
+                .wrapping_add(key[j])
+                .wrapping_add(j as u32);
+            i += 1;
+            j += 1;
+            if i >= N {
+                self.state[0] = self.state[N - 1];
+                i = 1;
+            }
+            if j >= key.len() { j = 0; }
+        }
+        for _ in 0..N - 1 {
+            let previous = self.state[i - 1];
+            self.state[i] = (self.state[i] ^ (previous ^ (previous >> 30)).wrapping_mul(1_566_083_941)).wrapping_sub(i as u32);
+            i += 1;
+            if i >= N {
+                self.state[0] = self.state[N - 1];
+                i = 1;
+            }
+        }
+        self.state[0] = 0x8000_0000;
+    }
+
+    fn generate(&mut self) {
+        for k in 0..N {
+            let y = (self.state[k] & 0x8000_0000) | (self.state[(k + 1) % N] & 0x7fff_ffff);
+            let mut next = self.state[(k + M) % N] ^ (y >> 1);
+            if y & 1 != 0 { next ^= 0x9908_b0df; }
+            self.state[k] = next;
+        }
+        self.index = 0;
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.index >= N { self.generate(); }
+        let mut y = self.state[self.index];
+        self.index += 1;
+
+        y ^= y >> 11;
+        y ^= (y << 7) & 0x9d2c_5680;
+        y ^= (y << 15) & 0xefc6_0000;
+        y ^ (y >> 18)
+    }
+
+    /// A float in `[0, 1)` made from 53 random bits.
+    pub fn next_f64(&mut self) -> f64 {
+        let (a, b) = (self.next_u32() >> 5, self.next_u32() >> 6);
+        (f64::from(a) * 67_108_864.0 + f64::from(b)) * (1.0 / 9_007_199_254_740_992.0)
+    }
+
+    /// An integer from 0 to `limit` inclusive. Words are drawn masked to the bits `limit`
+    /// needs, starting over whenever the value goes past it.
+    pub fn up_to(&mut self, limit: u64) -> u64 {
+        if limit == 0 { return 0; }
+        let mask = u64::MAX >> limit.leading_zeros();
+        'retry: loop {
+            let mut value = 0;
+            for word in (0..2).rev() {
+                if (mask >> (word * 32)) & 0xffff_ffff == 0 { continue; }
+                value |= u64::from(self.next_u32()) << (word * 32);
+                value &= mask;
+                if value > limit { continue 'retry; }
+            }
+            return value;
+        }
+    }
+}
+
+/// The 32-bit words of a seed's magnitude, least significant first.
+pub fn seed_words(seed: &Value) -> Vec<u32> {
+    match seed {
+        Value::Integer(seed) => {
+            let magnitude = seed.unsigned_abs();
+            if magnitude >> 32 == 0 { vec!(magnitude as u32) } else { vec!(magnitude as u32, (magnitude >> 32) as u32) }
+        },
+        Value::Bignum(seed) => seed.magnitude_limbs().to_vec(),
+        _ => vec!(0),
+    }
+}
+
+/// A seed for a program that doesn't choose one, different from run to run.
+pub fn new_seed() -> Value {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+    let mixed = (nanos as u64).rotate_left(17) ^ (u64::from(process::id()) << 32) ^ (nanos >> 64) as u64;
+    Value::Integer((mixed >> 1) as i64)
+}
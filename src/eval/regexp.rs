@@ -0,0 +1,751 @@
+use std::fmt;
+
+/// The flags after a regexp literal, with the bit values Ruby gives them in `Regexp#options`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Options {
+    pub ignore_case: bool,
+    pub extended:    bool,
+    /// `.` matches newlines too; this is Ruby's `/m`, not Perl's.
+    pub multiline:   bool,
+}
+
+impl Options {
+    pub const IGNORECASE: i64 = 1;
+    pub const EXTENDED: i64 = 2;
+    pub const MULTILINE: i64 = 4;
+
+    pub fn from_bits(bits: i64) -> Options {
+        Options {
+            ignore_case: bits & Options::IGNORECASE != 0,
+            extended:    bits & Options::EXTENDED != 0,
+            multiline:   bits & Options::MULTILINE != 0,
+        }
+    }
+
+    /// The options written as literal flags, like `"mi"`. Unknown flags are an error.
+    pub fn from_flags(flags: &str) -> Result<Options, char> {
+        let mut options = Options::default();
+        for flag in flags.chars() {
+            match flag {
+                'i' => options.ignore_case = true,
+                'x' => options.extended = true,
+                'm' => options.multiline = true,
+                // the encoding flags only matter to a byte-level engine
+                'n' | 'u' | 'e' | 's' | 'o' => {},
+                flag => return Err(flag),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn bits(self) -> i64 {
+        let mut bits = 0;
+        if self.ignore_case { bits |= Options::IGNORECASE; }
+        if self.extended { bits |= Options::EXTENDED; }
+        if self.multiline { bits |= Options::MULTILINE; }
+        bits
+    }
+}
+
+/// The flags in the order `Regexp#inspect` prints them.
+impl fmt::Display for Options {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.multiline { write!(f, "m")?; }
+        if self.ignore_case { write!(f, "i")?; }
+        if self.extended { write!(f, "x")?; }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    /// A character, and whether case is ignored
+    Char(char, bool),
+    /// `.`, and whether it matches a newline
+    Any(bool),
+    Set(CharSet, bool),
+    LineStart,
+    LineEnd,
+    TextStart,
+    TextEnd,
+    /// `\Z`, the end of the text or just before a final newline
+    TextEndBeforeNewline,
+    WordBoundary(bool),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize>, greedy: bool },
+    Backreference(usize, bool),
+    Look { node: Box<Node>, ahead: bool, negate: bool },
+}
+
+#[derive(Debug)]
+struct CharSet {
+    negated: bool,
+    items:   Vec<SetItem>,
+}
+
+#[derive(Debug)]
+enum SetItem {
+    Char(char),
+    Range(char, char),
+    /// A class like `\d` or `[:alpha:]`, negated like `\D` when the flag is set
+    Class(fn(char) -> bool, bool),
+    Set(CharSet),
+}
+
+impl CharSet {
+    fn contains(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            SetItem::Char(item) => *item == c,
+            SetItem::Range(from, to) => (*from..=*to).contains(&c),
+            SetItem::Class(class, negated) => class(c) != *negated,
+            SetItem::Set(set) => set.contains(c),
+        });
+        found != self.negated
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0b' | '\x0c')
+}
+
+/// A compiled pattern. Matching works on the characters of a `str` but reports positions
+/// as byte offsets into it.
+#[derive(Debug)]
+pub struct Regex {
+    node:      Node,
+    /// How many capture groups there are, not counting the whole match
+    pub groups: usize,
+    /// Named groups and their numbers, in the order they appear
+    pub names: Vec<(String, usize)>,
+}
+
+/// Where each group matched, the whole match first, as byte ranges.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    pub fn new(pattern: &str, options: Options) -> Result<Regex, String> {
+        let mut parser = PatternParser { chars: pattern.chars().collect(), pos: 0, groups: 0, names: vec!(), options };
+        let node = parser.parse_alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(String::from("unmatched close parenthesis"));
+        }
+
+        Ok(Regex { node, groups: parser.groups, names: parser.names })
+    }
+
+    /// The first match starting at or after the byte offset `start`.
+    pub fn search(&self, text: &str, start: usize) -> Option<Captures> {
+        let matcher = Matcher { text };
+        let mut pos = start;
+        while pos <= text.len() {
+            let mut captures = vec!(None; self.groups + 1);
+            let found = matcher.run(&self.node, pos, &mut captures, &mut |end, captures: &mut Captures| {
+                captures[0] = Some((pos, end));
+                true
+            });
+            if found { return Some(captures); }
+            match text[pos..].chars().next() {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+        }
+        None
+    }
+
+    /// The number of the group called `name`.
+    pub fn group_named(&self, name: &str) -> Option<usize> {
+        self.names.iter().rev().find(|(group, _)| group == name).map(|(_, index)| *index)
+    }
+
+    /// `text` with every character that means something in a pattern escaped.
+    pub fn escape(text: &str) -> String {
+        let mut escaped = String::new();
+        for c in text.chars() {
+            match c {
+                '.' | '*' | '?' | '+' | '^' | '$' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | '\\' | '/' | '-' | '#' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                },
+                ' ' => escaped += "\\ ",
+                '\n' => escaped += "\\n",
+                '\t' => escaped += "\\t",
+                '\r' => escaped += "\\r",
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+struct PatternParser {
+    chars:    Vec<char>,
+    pos:      usize,
+    groups:   usize,
+    names:    Vec<(String, usize)>,
+    /// The options in effect where the parser is, which `(?i)` and the like change
+    options:  Options,
+}
+
+impl PatternParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let matches = text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c));
+        if matches { self.pos += text.chars().count(); }
+        matches
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec!(self.parse_concat()?);
+        while self.eat("|") {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alternation(branches) })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec!();
+        loop {
+            if self.options.extended { self.skip_extended_space(); }
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                Some('*') | Some('+') | Some('?') => return Err(String::from("target of repeat operator is not specified")),
+                _ => {},
+            }
+            let atom = self.parse_atom()?;
+            let atom = self.parse_quantifiers(atom)?;
+            nodes.push(atom);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn skip_extended_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.pos += 1;
+            } else if c == '#' {
+                while !matches!(self.next(), Some('\n') | None) {}
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_quantifiers(&mut self, mut atom: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => { self.pos += 1; (0, None) },
+                Some('+') => { self.pos += 1; (1, None) },
+                Some('?') => { self.pos += 1; (0, Some(1)) },
+                Some('{') => match self.parse_interval() {
+                    Some(interval) => interval,
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+            let greedy = !self.eat("?");
+            // a possessive quantifier is treated as a greedy one
+            self.eat("+");
+            atom = Node::Repeat { node: Box::new(atom), min, max, greedy };
+        }
+    }
+
+    /// `{n}`, `{n,}`, `{,m}` or `{n,m}`; anything else leaves the brace to be read literally.
+    fn parse_interval(&mut self) -> Option<(usize, Option<usize>)> {
+        let close = self.chars[self.pos..].iter().position(|c| *c == '}')? + self.pos;
+        let inside: String = self.chars[self.pos + 1..close].iter().collect();
+        let number = |text: &str| if text.is_empty() { Some(None) } else { text.parse::<usize>().ok().map(Some) };
+        let interval = match inside.split_once(',') {
+            Some((min, max)) => {
+                let (min, max) = (number(min)?, number(max)?);
+                if min.is_none() && max.is_none() { return None; }
+                (min.unwrap_or(0), max)
+            },
+            None => {
+                let count = number(&inside)??;
+                (count, Some(count))
+            },
+        };
+        self.pos = close + 1;
+        Some(interval)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.next().expect("parse_concat checks for the end");
+        Ok(match c {
+            '(' => self.parse_group()?,
+            '[' => Node::Set(self.parse_set()?, self.options.ignore_case),
+            '.' => Node::Any(self.options.multiline),
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '\\' => self.parse_escape()?,
+            c => Node::Char(c, self.options.ignore_case),
+        })
+    }
+
+    /// A group after its `(`. Options set inside it, as in `(?i:...)` or `(?m)`, end with it.
+    fn parse_group(&mut self) -> Result<Node, String> {
+        let outer = self.options;
+        let node = self.parse_group_body();
+        self.options = outer;
+        node
+    }
+
+    fn parse_group_body(&mut self) -> Result<Node, String> {
+        if self.peek() == Some('?') && matches!(self.chars.get(self.pos + 1), Some('i' | 'm' | 'x' | '-')) {
+            self.pos += 1;
+            let mut on = true;
+            loop {
+                match self.next() {
+                    Some('-') if on => on = false,
+                    Some('i') => self.options.ignore_case = on,
+                    Some('m') => self.options.multiline = on,
+                    Some('x') => self.options.extended = on,
+                    // `(?i)` changes the options for the rest of the enclosing group
+                    Some(')') => return Ok(Node::Empty),
+                    Some(':') => break,
+                    _ => return Err(String::from("undefined group option")),
+                }
+            }
+        }
+        let node = if self.eat("?:") || self.eat("?>") {
+            Node::Group(Box::new(self.parse_alternation()?), None)
+        } else if self.eat("?=") || self.eat("?!") {
+            let negate = self.chars[self.pos - 1] == '!';
+            Node::Look { node: Box::new(self.parse_alternation()?), ahead: true, negate }
+        } else if self.eat("?<=") || self.eat("?<!") {
+            let negate = self.chars[self.pos - 1] == '!';
+            Node::Look { node: Box::new(self.parse_alternation()?), ahead: false, negate }
+        } else if self.eat("?<") || self.eat("?'") {
+            let close = if self.chars[self.pos - 1] == '<' { '>' } else { '\'' };
+            let name = self.read_name(close)?;
+            self.groups += 1;
+            let index = self.groups;
+            self.names.push((name, index));
+            Node::Group(Box::new(self.parse_alternation()?), Some(index))
+        } else if self.peek() == Some('?') {
+            return Err(String::from("undefined group option"));
+        } else {
+            self.groups += 1;
+            let index = self.groups;
+            Node::Group(Box::new(self.parse_alternation()?), Some(index))
+        };
+        if !self.eat(")") {
+            return Err(String::from("end pattern with unmatched parenthesis"));
+        }
+        Ok(node)
+    }
+
+    fn read_name(&mut self, close: char) -> Result<String, String> {
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == close && !name.is_empty() => return Ok(name),
+                Some(c) if is_word(c) => name.push(c),
+                _ => return Err(String::from("invalid group name")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("too short escape sequence")?;
+        Ok(match c {
+            'A' => Node::TextStart,
+            'z' => Node::TextEnd,
+            'Z' => Node::TextEndBeforeNewline,
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            '1'..='9' => {
+                let mut index = c.to_digit(10).unwrap() as usize;
+                while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+                    index = index * 10 + digit as usize;
+                    self.pos += 1;
+                }
+                Node::Backreference(index, self.options.ignore_case)
+            },
+            'k' if self.eat("<") => {
+                let name = self.read_name('>')?;
+                let index = self.names.iter().rev().find(|(group, _)| *group == name).map(|(_, index)| *index);
+                Node::Backreference(index.ok_or_else(|| format!("undefined name <{}> reference", name))?, self.options.ignore_case)
+            },
+            'p' | 'P' => Node::Set(CharSet { negated: false, items: vec!(self.property(c == 'P')?) }, self.options.ignore_case),
+            c => match self.class_escape(c) {
+                Some(item) => Node::Set(CharSet { negated: false, items: vec!(item) }, self.options.ignore_case),
+                None => Node::Char(self.char_escape(c)?, self.options.ignore_case),
+            },
+        })
+    }
+
+    /// A character property after its `\p` or `\P`, like `{L}` or `{^Alpha}`.
+    fn property(&mut self, negated: bool) -> Result<SetItem, String> {
+        if !self.eat("{") { return Err(String::from("invalid character property name <p>")); }
+        let close = self.chars[self.pos..].iter().position(|c| *c == '}').ok_or("invalid character property name <p>")?;
+        let name: String = self.chars[self.pos..self.pos + close].iter().collect();
+        self.pos += close + 1;
+        let (negated, property) = match name.strip_prefix('^') {
+            Some(property) => (!negated, property),
+            None => (negated, name.as_str()),
+        };
+        let class = character_property(property).ok_or_else(|| format!("invalid character property name {{{}}}", name))?;
+        Ok(SetItem::Class(class, negated))
+    }
+
+    /// The class a letter like `d` stands for after a backslash.
+    fn class_escape(&self, c: char) -> Option<SetItem> {
+        let class: fn(char) -> bool = match c.to_ascii_lowercase() {
+            'd' => |c| c.is_ascii_digit(),
+            'w' => |c| c.is_ascii_alphanumeric() || c == '_',
+            's' => is_space,
+            'h' => |c| c.is_ascii_hexdigit(),
+            _ => return None,
+        };
+        Some(SetItem::Class(class, c.is_ascii_uppercase()))
+    }
+
+    /// The character an escape like `\n`, `\x41` or `é` stands for.
+    fn char_escape(&mut self, c: char) -> Result<char, String> {
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            'a' => '\x07',
+            'e' => '\x1b',
+            '0' => '\0',
+            'x' => self.read_hex(1, 2)?,
+            'u' if self.eat("{") => {
+                let c = self.read_hex(1, 6)?;
+                if !self.eat("}") { return Err(String::from("invalid Unicode escape")); }
+                c
+            },
+            'u' => self.read_hex(4, 4)?,
+            c => c,
+        })
+    }
+
+    fn read_hex(&mut self, min: usize, max: usize) -> Result<char, String> {
+        let mut value = 0;
+        let mut count = 0;
+        while count < max {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(digit) => value = value * 16 + digit,
+                None => break,
+            }
+            self.pos += 1;
+            count += 1;
+        }
+        if count < min { return Err(String::from("invalid hex escape")); }
+        char::from_u32(value).ok_or_else(|| String::from("invalid Unicode range"))
+    }
+
+    /// A bracketed set after its `[`, up to and including the `]`.
+    fn parse_set(&mut self) -> Result<CharSet, String> {
+        let negated = self.eat("^");
+        let mut items = vec!();
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or("premature end of char-class")?;
+            let item = match c {
+                ']' if !first => break,
+                '[' if self.eat(":") => {
+                    let close = self.chars[self.pos..].iter().position(|c| *c == ':').ok_or("premature end of char-class")?;
+                    let name: String = self.chars[self.pos..self.pos + close].iter().collect();
+                    self.pos += close;
+                    if !self.eat(":]") { return Err(String::from("premature end of char-class")); }
+                    let (negated, name) = match name.strip_prefix('^') {
+                        Some(name) => (true, name.to_string()),
+                        None => (false, name),
+                    };
+                    SetItem::Class(posix_class(&name).ok_or_else(|| format!("invalid POSIX bracket type [:{}:]", name))?, negated)
+                },
+                '[' => SetItem::Set(self.parse_set()?),
+                '\\' => {
+                    let c = self.next().ok_or("premature end of char-class")?;
+                    match self.class_escape(c) {
+                        Some(item) => item,
+                        None if c == 'p' || c == 'P' => self.property(c == 'P')?,
+                        None if c == 'b' => SetItem::Char('\x08'),
+                        None => SetItem::Char(self.char_escape(c)?),
+                    }
+                },
+                c => SetItem::Char(c),
+            };
+            first = false;
+            // a `-` between two characters makes a range; elsewhere it's literal
+            if let SetItem::Char(from) = item {
+                if self.peek() == Some('-') && !matches!(self.chars.get(self.pos + 1), Some(']') | None) {
+                    self.pos += 1;
+                    let to = match self.next() {
+                        Some('\\') => {
+                            let c = self.next().ok_or("premature end of char-class")?;
+                            self.char_escape(c)?
+                        },
+                        Some(to) => to,
+                        None => return Err(String::from("premature end of char-class")),
+                    };
+                    if to < from { return Err(String::from("empty range in char class")); }
+                    items.push(SetItem::Range(from, to));
+                    continue;
+                }
+            }
+            items.push(item);
+        }
+        Ok(CharSet { negated, items })
+    }
+}
+
+fn posix_class(name: &str) -> Option<fn(char) -> bool> {
+    Some(match name {
+        "alpha" => char::is_alphabetic,
+        "digit" => |c| c.is_ascii_digit(),
+        "alnum" => char::is_alphanumeric,
+        "upper" => char::is_uppercase,
+        "lower" => char::is_lowercase,
+        "space" => char::is_whitespace,
+        "punct" => |c| c.is_ascii_punctuation(),
+        "xdigit" => |c| c.is_ascii_hexdigit(),
+        "word" => is_word,
+        "cntrl" => char::is_control,
+        "blank" => |c| c == ' ' || c == '\t',
+        "print" => |c| !c.is_control(),
+        "graph" => |c| !c.is_control() && !c.is_whitespace(),
+        _ => return None,
+    })
+}
+
+/// The class for a `\p{...}` property: a POSIX class name, or one of the Unicode general
+/// categories for letters, numbers and punctuation, where `N` and `Nd` both take any numeric
+/// character. Names are matched ignoring case, spaces and underscores.
+fn character_property(name: &str) -> Option<fn(char) -> bool> {
+    let name: String = name.chars().filter(|c| *c != ' ' && *c != '_').collect::<String>().to_ascii_lowercase();
+    Some(match name.as_str() {
+        "l" | "letter" => char::is_alphabetic,
+        "lu" | "uppercaseletter" => char::is_uppercase,
+        "ll" | "lowercaseletter" => char::is_lowercase,
+        "n" | "number" | "nd" | "decimalnumber" => char::is_numeric,
+        "p" | "punctuation" => |c| c.is_ascii_punctuation(),
+        "any" => |_| true,
+        "ascii" => |c| c.is_ascii(),
+        name => return posix_class(name),
+    })
+}
+
+/// Backtracking search: each node matches what it can at `pos` and hands every way it can
+/// end to the continuation `next`, until one of them lets the rest of the pattern match.
+struct Matcher<'a> {
+    text: &'a str,
+}
+
+type Continuation<'c> = &'c mut dyn FnMut(usize, &mut Captures) -> bool;
+
+impl<'a> Matcher<'a> {
+    fn char_at(&self, pos: usize) -> Option<char> {
+        self.text[pos..].chars().next()
+    }
+
+    fn char_before(&self, pos: usize) -> Option<char> {
+        self.text[..pos].chars().next_back()
+    }
+
+    fn same(a: char, b: char, ignore_case: bool) -> bool {
+        a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    fn in_set(set: &CharSet, c: char, ignore_case: bool) -> bool {
+        if set.contains(c) { return true; }
+        ignore_case && (c.to_lowercase().any(|c| set.contains(c)) || c.to_uppercase().any(|c| set.contains(c)))
+    }
+
+    /// Matches a single character with `test`.
+    fn step(&self, pos: usize, captures: &mut Captures, next: Continuation, test: impl Fn(char) -> bool) -> bool {
+        match self.char_at(pos) {
+            Some(c) if test(c) => next(pos + c.len_utf8(), captures),
+            _ => false,
+        }
+    }
+
+    fn run(&self, node: &Node, pos: usize, captures: &mut Captures, next: Continuation) -> bool {
+        match node {
+            Node::Empty => next(pos, captures),
+            Node::Char(expected, ignore_case) => self.step(pos, captures, next, |c| Matcher::same(c, *expected, *ignore_case)),
+            Node::Any(multiline) => self.step(pos, captures, next, |c| c != '\n' || *multiline),
+            Node::Set(set, ignore_case) => self.step(pos, captures, next, |c| Matcher::in_set(set, c, *ignore_case)),
+            Node::LineStart => (pos == 0 || self.text.as_bytes()[pos - 1] == b'\n') && next(pos, captures),
+            Node::LineEnd => (pos == self.text.len() || self.text.as_bytes()[pos] == b'\n') && next(pos, captures),
+            Node::TextStart => pos == 0 && next(pos, captures),
+            Node::TextEnd => pos == self.text.len() && next(pos, captures),
+            Node::TextEndBeforeNewline => {
+                let end = pos == self.text.len() || (pos + 1 == self.text.len() && self.text.ends_with('\n'));
+                end && next(pos, captures)
+            },
+            Node::WordBoundary(boundary) => {
+                let before = self.char_before(pos).is_some_and(is_word);
+                let after = self.char_at(pos).is_some_and(is_word);
+                ((before != after) == *boundary) && next(pos, captures)
+            },
+            Node::Group(node, None) => self.run(node, pos, captures, next),
+            Node::Group(node, Some(index)) => {
+                let index = *index;
+                let saved = captures[index];
+                let found = self.run(node, pos, captures, &mut |end, captures: &mut Captures| {
+                    let inner = captures[index];
+                    captures[index] = Some((pos, end));
+                    if next(end, captures) { return true; }
+                    captures[index] = inner;
+                    false
+                });
+                if !found { captures[index] = saved; }
+                found
+            },
+            Node::Concat(nodes) => self.run_sequence(nodes, pos, captures, next),
+            Node::Alternation(branches) => {
+                for branch in branches {
+                    if self.run(branch, pos, captures, next) { return true; }
+                }
+                false
+            },
+            Node::Repeat { node, min, max, greedy } => self.run_repeat(node, *min, *max, *greedy, 0, pos, captures, next),
+            Node::Backreference(index, ignore_case) => {
+                let (start, end) = match captures.get(*index).copied().flatten() {
+                    Some(range) => range,
+                    None => return false,
+                };
+                let mut at = pos;
+                for expected in self.text[start..end].chars() {
+                    match self.char_at(at) {
+                        Some(c) if Matcher::same(c, expected, *ignore_case) => at += c.len_utf8(),
+                        _ => return false,
+                    }
+                }
+                next(at, captures)
+            },
+            Node::Look { node, ahead: true, negate } => {
+                let mut inner = captures.clone();
+                let found = self.run(node, pos, &mut inner, &mut |_, _| true);
+                if found == *negate { return false; }
+                if found { *captures = inner; }
+                next(pos, captures)
+            },
+            Node::Look { node, ahead: false, negate } => {
+                let mut inner = captures.clone();
+                let found = (0..=pos).rev()
+                    .filter(|start| self.text.is_char_boundary(*start))
+                    .any(|start| self.run(node, start, &mut inner, &mut |end, _| end == pos));
+                if found == *negate { return false; }
+                if found { *captures = inner; }
+                next(pos, captures)
+            },
+        }
+    }
+
+    fn run_sequence(&self, nodes: &[Node], pos: usize, captures: &mut Captures, next: Continuation) -> bool {
+        match nodes.split_first() {
+            None => next(pos, captures),
+            Some((first, rest)) => self.run(first, pos, captures, &mut |pos, captures: &mut Captures| self.run_sequence(rest, pos, captures, next)),
+        }
+    }
+
+    /// The `count`th repetition onwards. A repetition that matches nothing once the minimum is
+    /// reached ends the loop, so `(a*)*` can't go round forever.
+    #[allow(clippy::too_many_arguments)]
+    fn run_repeat(&self, node: &Node, min: usize, max: Option<usize>, greedy: bool, count: usize, pos: usize, captures: &mut Captures, next: Continuation) -> bool {
+        let more = max.is_none_or(|max| count < max);
+        let again = |captures: &mut Captures, next: Continuation| {
+            self.run(node, pos, captures, &mut |end, captures: &mut Captures| {
+                if end == pos && count >= min { return false; }
+                self.run_repeat(node, min, max, greedy, count + 1, end, captures, next)
+            })
+        };
+        if count < min { return again(captures, next); }
+        if !greedy && next(pos, captures) { return true; }
+        if more && again(captures, next) { return true; }
+        greedy && next(pos, captures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<Vec<Option<String>>> {
+        let regex = Regex::new(pattern, Options::default()).unwrap();
+        regex.search(text, 0).map(|captures| {
+            captures.iter().map(|range| range.map(|(start, end)| text[start..end].to_string())).collect()
+        })
+    }
+
+    fn matched(pattern: &str, text: &str) -> Option<String> {
+        find(pattern, text).and_then(|captures| captures[0].clone())
+    }
+
+    #[test]
+    fn should_match_literals_classes_and_anchors() {
+        assert_eq!(matched("b+", "abbbc"), Some(String::from("bbb")));
+        assert_eq!(matched("[a-c]+\\d", "xxcab7"), Some(String::from("cab7")));
+        assert_eq!(matched("^\\w+$", "one\ntwo"), Some(String::from("one")));
+        assert_eq!(matched("\\Atwo", "one\ntwo"), None);
+        assert_eq!(matched("[^aeiou\\s]+", "aei xyz"), Some(String::from("xyz")));
+        assert_eq!(matched("[[:upper:]][[:lower:]]*", "hello World"), Some(String::from("World")));
+        assert_eq!(matched("\\bcat\\b", "concat cat"), Some(String::from("cat")));
+        assert_eq!(matched("é.", "café!"), Some(String::from("é!")));
+        assert_eq!(matched("a.c", "a\nc"), None);
+    }
+
+    #[test]
+    fn should_backtrack_through_quantifiers_and_alternation() {
+        assert_eq!(matched("a.*c", "abcbc"), Some(String::from("abcbc")));
+        assert_eq!(matched("a.*?c", "abcbc"), Some(String::from("abc")));
+        assert_eq!(matched("x{2,3}", "xxxxx"), Some(String::from("xxx")));
+        assert_eq!(matched("a{,2}b", "aaab"), Some(String::from("aab")));
+        assert_eq!(matched("cat|category", "category"), Some(String::from("cat")));
+        assert_eq!(matched("(a*)*b", "aaab"), Some(String::from("aaab")));
+        assert_eq!(matched("\\d+(?=%)", "50 75%"), Some(String::from("75")));
+        assert_eq!(matched("(?<!\\$)\\b\\d+", "$5 7"), Some(String::from("7")));
+        assert_eq!(matched("{", "a{b"), Some(String::from("{")));
+    }
+
+    #[test]
+    fn should_capture_groups() {
+        assert_eq!(find("(\\w+)@(\\w+)", "mail bob@example now"),
+            Some(vec!(Some(String::from("bob@example")), Some(String::from("bob")), Some(String::from("example")))));
+        assert_eq!(find("(a)|(b)", "b"), Some(vec!(Some(String::from("b")), None, Some(String::from("b")))));
+        assert_eq!(matched("(\\w)\\1", "abccd"), Some(String::from("cc")));
+        let regex = Regex::new("(?<year>\\d+)-(?<month>\\d+)", Options::default()).unwrap();
+        assert_eq!(regex.group_named("month"), Some(2));
+        assert_eq!(matched("(?<x>.)\\k<x>", "xyzzy"), Some(String::from("zz")));
+    }
+
+    #[test]
+    fn should_honour_options_and_reject_bad_patterns() {
+        let ignore_case = Options::from_flags("i").unwrap();
+        assert!(Regex::new("HELLO", ignore_case).unwrap().search("say hello", 0).is_some());
+        let extended = Options::from_flags("x").unwrap();
+        assert!(Regex::new("a b # letters\n c", extended).unwrap().search("abc", 0).is_some());
+        assert_eq!(Options::from_flags("mix").unwrap().to_string(), "mix");
+        assert_eq!(Options::from_flags("q"), Err('q'));
+        assert_eq!(Regex::new("(a", Options::default()).unwrap_err(), "end pattern with unmatched parenthesis");
+        assert_eq!(Regex::new("a)", Options::default()).unwrap_err(), "unmatched close parenthesis");
+        assert_eq!(Regex::new("*a", Options::default()).unwrap_err(), "target of repeat operator is not specified");
+        assert_eq!(Regex::escape("1.5 (x)"), "1\\.5\\ \\(x\\)");
+    }
+}
@@ -0,0 +1,295 @@
+use std::cell::{ Cell, RefCell };
+use std::collections::{ BTreeSet, HashMap };
+use std::fmt;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use crate::ast::node::{ Ast, NodeId };
+use crate::eval::bigint::BigInt;
+use crate::eval::builtins::numeric::float_to_s;
+use crate::eval::class::RClass;
+use crate::eval::{ EvalResult, Interpreter };
+use crate::eval::environment::{ Context, Scope };
+
+/// A Ruby object as the interpreter sees it. Immediates are stored inline; everything else is
+/// shared by reference, so cloning a `Value` never copies the object it refers to.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Integer(i64),
+    /// An `Integer` outside the range of `i64`. Results that fit are always `Integer`.
+    Bignum(Rc<BigInt>),
+    Float(f64),
+    String(Rc<RString>),
+    /// An interned name: symbols with the same name are the same object
+    Symbol(&'static str),
+    Array(Rc<RArray>),
+    Hash(Rc<RHash>),
+    Range(Rc<RRange>),
+    Proc(Rc<RProc>),
+    Object(Rc<RObject>),
+    /// A class or a module
+    Class(Rc<RClass>),
+}
+
+/// The encodings a `String` can be tagged with. The bytes aren't checked against the tag
+/// until something needs them to be valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// `ASCII-8BIT`, also called `BINARY`: raw bytes with no character structure
+    Binary,
+    UsAscii,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Binary => "ASCII-8BIT",
+            Encoding::UsAscii => "US-ASCII",
+        }
+    }
+
+    /// The encoding called `name`, ignoring case and accepting the usual aliases.
+    pub fn find(name: &str) -> Option<Encoding> {
+        match name.to_ascii_uppercase().as_str() {
+            "UTF-8" | "UTF8" | "CP65001" => Some(Encoding::Utf8),
+            "ASCII-8BIT" | "BINARY" => Some(Encoding::Binary),
+            "US-ASCII" | "ASCII" | "ANSI_X3.4-1968" | "646" => Some(Encoding::UsAscii),
+            _ => None,
+        }
+    }
+}
+
+/// A mutable buffer of bytes and the encoding they're meant to be read in.
+pub struct RString {
+    pub bytes:    RefCell<Vec<u8>>,
+    pub encoding: Cell<Encoding>,
+    pub frozen:   Cell<bool>,
+}
+
+impl RString {
+    /// The contents as text, with anything that isn't valid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+    }
+}
+
+pub struct RArray {
+    pub elements: RefCell<Vec<Value>>,
+    pub frozen:   Cell<bool>,
+}
+
+/// The entries in the order their keys were first stored. A key is found by its `hash`,
+/// which picks the bucket of entry positions whose keys are compared with `eql?`.
+pub struct RHash {
+    pub entries:      RefCell<Vec<(Value, Value)>>,
+    pub buckets:      RefCell<HashMap<i64, Vec<usize>>>,
+    pub default:      RefCell<Value>,
+    pub default_proc: RefCell<Option<Rc<RProc>>>,
+    /// Set by `compare_by_identity`: keys are then only the same when they're the same object
+    pub identity:     Cell<bool>,
+    /// How many loops over the entries are running, during which no key can be added
+    pub iterating:    Cell<usize>,
+    pub frozen:       Cell<bool>,
+}
+
+pub struct RRange {
+    pub start:     Value,
+    pub end:       Value,
+    pub exclusive: bool,
+}
+
+/// A block or lambda together with the scope and context it closes over.
+pub struct RProc {
+    pub body:    ProcBody,
+    pub scope:   Rc<Scope>,
+    pub context: Rc<Context>,
+    pub lambda:  bool,
+}
+
+pub type NativeBlock = Rc<dyn Fn(&mut Interpreter, &[Value]) -> EvalResult>;
+
+#[derive(Clone)]
+pub enum ProcBody {
+    /// A block or lambda node in `ast`, whose parameters and body stay in the tree
+    Block(Rc<Ast>, NodeId),
+    /// A block made by a builtin, like the one `Enumerable` methods pass to `each`
+    Native(NativeBlock),
+}
+
+/// An instance of a class defined in Ruby, or of one of the builtin classes without a native
+/// representation such as the exceptions. Instance variable names keep their `@`; names
+/// without it hold state that Ruby code can't see, like an exception's message.
+pub struct RObject {
+    pub class:     Rc<RClass>,
+    /// In the order they were first assigned, which is how `inspect` lists them
+    pub ivars:     RefCell<Vec<(String, Value)>>,
+    pub singleton: RefCell<Option<Rc<RClass>>>,
+    pub frozen:    Cell<bool>,
+}
+
+impl RObject {
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.ivars.borrow().iter().find(|(ivar, _)| ivar == name).map(|(_, value)| value.clone())
+    }
+
+    pub fn set(&self, name: &str, value: Value) {
+        let mut ivars = self.ivars.borrow_mut();
+        match ivars.iter_mut().find(|(ivar, _)| ivar == name) {
+            Some(slot) => slot.1 = value,
+            None => ivars.push((name.to_string(), value)),
+        }
+    }
+}
+
+/// The name of every symbol ever made. They are never freed, and are shared by the threads
+/// coroutines run on.
+static SYMBOLS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+impl Value {
+    pub fn string(value: &str) -> Value {
+        Value::bytes(value.as_bytes().to_vec(), Encoding::Utf8)
+    }
+
+    pub fn bytes(bytes: Vec<u8>, encoding: Encoding) -> Value {
+        Value::String(Rc::new(RString { bytes: RefCell::new(bytes), encoding: Cell::new(encoding), frozen: Cell::new(false) }))
+    }
+
+    pub fn symbol(name: &str) -> Value {
+        let mut symbols = SYMBOLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(interned) = symbols.get(name) { return Value::Symbol(interned); }
+
+        let interned: &'static str = Box::leak(Box::from(name));
+        symbols.insert(interned);
+        Value::Symbol(interned)
+    }
+
+    pub fn array(elements: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RArray { elements: RefCell::new(elements), frozen: Cell::new(false) }))
+    }
+
+    pub fn hash() -> Value {
+        Value::Hash(Rc::new(RHash {
+            entries:      RefCell::new(vec!()),
+            buckets:      RefCell::new(HashMap::new()),
+            default:      RefCell::new(Value::Nil),
+            default_proc: RefCell::new(None),
+            identity:     Cell::new(false),
+            iterating:    Cell::new(0),
+            frozen:       Cell::new(false),
+        }))
+    }
+
+    /// An `Integer`, kept in a machine word when it fits in one.
+    pub fn integer(value: BigInt) -> Value {
+        match value.to_i64() {
+            Some(value) => Value::Integer(value),
+            None => Value::Bignum(Rc::new(value)),
+        }
+    }
+
+    pub fn range(start: Value, end: Value, exclusive: bool) -> Value {
+        Value::Range(Rc::new(RRange { start, end, exclusive }))
+    }
+
+    pub fn object(class: Rc<RClass>) -> Value {
+        Value::Object(Rc::new(RObject { class, ivars: RefCell::new(vec!()), singleton: RefCell::new(None), frozen: Cell::new(false) }))
+    }
+
+    /// Only `nil` and `false` are false in Ruby; `0`, `""` and `[]` are all true.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    /// Whether the object can no longer be changed. Numbers, symbols, ranges, `nil`, `true`
+    /// and `false` always are.
+    pub fn is_frozen(&self) -> bool {
+        match self {
+            Value::String(string) => string.frozen.get(),
+            Value::Array(array) => array.frozen.get(),
+            Value::Hash(hash) => hash.frozen.get(),
+            Value::Object(object) => object.frozen.get(),
+            Value::Proc(_) | Value::Class(_) => false,
+            _ => true,
+        }
+    }
+
+    pub fn freeze(&self) {
+        match self {
+            Value::String(string) => string.frozen.set(true),
+            Value::Array(array) => array.frozen.set(true),
+            Value::Hash(hash) => hash.frozen.set(true),
+            Value::Object(object) => object.frozen.set(true),
+            _ => (),
+        }
+    }
+
+    /// Whether both values are the same object, which is what `equal?` tests.
+    pub fn is_identical(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Bignum(a), Value::Bignum(b)) => Rc::ptr_eq(a, b),
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Symbol(a), Value::Symbol(b)) => ptr::eq(*a, *b),
+            (Value::String(a), Value::String(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Hash(a), Value::Hash(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a), Value::Range(b)) => Rc::ptr_eq(a, b),
+            (Value::Proc(a), Value::Proc(b)) => Rc::ptr_eq(a, b),
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// A number identifying the object for as long as it lives, used by `object_id` and the
+    /// default `inspect`.
+    pub fn address(&self) -> usize {
+        match self {
+            Value::Nil => 8,
+            Value::Bool(true) => 20,
+            Value::Bool(false) => 0,
+            Value::Integer(value) => (*value as usize).wrapping_mul(2).wrapping_add(1),
+            Value::Bignum(value) => Rc::as_ptr(value) as usize,
+            Value::Float(value) => value.to_bits() as usize,
+            Value::Symbol(name) => name.as_ptr() as usize,
+            Value::String(value) => Rc::as_ptr(value) as usize,
+            Value::Array(value) => Rc::as_ptr(value) as usize,
+            Value::Hash(value) => Rc::as_ptr(value) as usize,
+            Value::Range(value) => Rc::as_ptr(value) as usize,
+            Value::Proc(value) => Rc::as_ptr(value) as usize,
+            Value::Object(value) => Rc::as_ptr(value) as usize,
+            Value::Class(value) => Rc::as_ptr(value) as usize,
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Bignum(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", float_to_s(*value)),
+            Value::String(value) => write!(f, "{:?}", value.text()),
+            Value::Symbol(name) => write!(f, ":{}", name),
+            Value::Array(value) => f.debug_list().entries(value.elements.borrow().iter()).finish(),
+            Value::Hash(value) => f.debug_map().entries(value.entries.borrow().iter().map(|(k, v)| (k.clone(), v.clone()))).finish(),
+            Value::Range(range) => write!(f, "{:?}{}{:?}", range.start, if range.exclusive { "..." } else { ".." }, range.end),
+            Value::Proc(_) => write!(f, "#<Proc>"),
+            Value::Object(object) => write!(f, "#<{}>", object.class.inspect()),
+            Value::Class(class) => write!(f, "{}", class.inspect()),
+        }
+    }
+}
@@ -0,0 +1,1029 @@
+use std::fmt;
+
+use crate::ast::node::{ Ast, NodeId, NodeKind, AssignTarget, Call, Parameters, BodyStatement };
+use crate::lexer::Lexer;
+use crate::lexer::token::Trivia;
+use crate::parser::{ Parser, ParseError, Precedence };
+use crate::program::Program;
+
+/// Lines are broken to stay within this many columns where the syntax allows it.
+pub const MAX_WIDTH: usize = 80;
+
+const INDENT: &str = "  ";
+
+#[derive(Debug)]
+pub enum FormatError {
+    /// The source doesn't parse, so there is nothing to format
+    Parse(Vec<ParseError>),
+    /// The formatted source parses into a different program. This is a bug in the formatter,
+    /// reported instead of silently changing what the code does.
+    Unstable(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Parse(errors) => {
+                let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            },
+            FormatError::Unstable(_) => write!(f, "formatting would change the meaning of the program"),
+        }
+    }
+}
+
+/// Parses and formats `source`, checking that the result parses back into the same program.
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let program = parse(source).map_err(FormatError::Parse)?;
+    let formatted = format(&program);
+    match parse(&formatted) {
+        Ok(reparsed) if reparsed.same_shape(&program) => Ok(formatted),
+        _ => Err(FormatError::Unstable(formatted)),
+    }
+}
+
+fn parse(source: &str) -> Result<Program, Vec<ParseError>> {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut parser = Parser::new(&mut lexer);
+    let (program, errors) = parser.parse_program();
+    if errors.is_empty() { Ok(program) } else { Err(errors) }
+}
+
+/// Prints `program` as idiomatic Ruby, keeping its comments and single blank lines.
+pub fn format(program: &Program) -> String {
+    Formatter::new(&program.ast, &program.trivia).format(&program.statements)
+}
+
+/// Prints an `Ast` as source. Comments and blank lines are attached to the statements around
+/// them by position: those before a statement lead it, and a comment following code on the
+/// same line trails it.
+pub struct Formatter<'a> {
+    ast:         &'a Ast,
+    trivia:      &'a [Trivia],
+    next_trivia: usize,
+    width:       usize,
+    /// Whether the node printed next stands where Ruby allows a command call, like `puts x`
+    command:     bool,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(ast: &'a Ast, trivia: &'a [Trivia]) -> Self {
+        Self { ast, trivia, next_trivia: 0, width: MAX_WIDTH, command: false }
+    }
+
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn format(mut self, statements: &[NodeId]) -> String {
+        self.statements(statements, 0, usize::MAX)
+    }
+
+    /// Prints one statement per line at `level`, with the trivia found before `limit`.
+    fn statements(&mut self, ids: &[NodeId], level: usize, limit: usize) -> String {
+        let mut out = String::new();
+        for (i, id) in ids.iter().enumerate() {
+            let span = self.ast[*id].span;
+            let next = ids.get(i + 1).map_or(limit, |next| self.ast[*next].span.start);
+            self.leading_trivia(&mut out, span.start, level, true);
+            out += &indent(level);
+            self.command = true;
+            out += &self.node(*id, level);
+
+            let mut comments = vec!();
+            while let Some(trivia) = self.trivia.get(self.next_trivia).filter(|trivia| trivia.start() < span.end) {
+                if let Trivia::Comment { text, .. } = trivia { comments.push(text.clone()); }
+                self.next_trivia += 1;
+            }
+            if let Some(comment) = self.trailing_comment(next) { comments.push(comment); }
+            for (i, comment) in comments.iter().enumerate() {
+                if i > 0 { out = format!("{}\n{}", out, indent(level)); } else { out.push(' '); }
+                out += comment;
+            }
+            out.push('\n');
+        }
+        self.leading_trivia(&mut out, limit, level, false);
+
+        out
+    }
+
+    /// Prints the comments before `limit` on lines of their own, keeping at most one blank line
+    /// between anything already printed and what follows. Blank lines at the end of a body are
+    /// dropped unless `before_statement`.
+    fn leading_trivia(&mut self, out: &mut String, limit: usize, level: usize, before_statement: bool) {
+        let mut blank = false;
+        while let Some(trivia) = self.trivia.get(self.next_trivia).filter(|trivia| trivia.start() < limit) {
+            match trivia {
+                Trivia::BlankLine(_) => blank = !out.is_empty(),
+                Trivia::Comment { text, .. } => {
+                    if blank { out.push('\n'); }
+                    blank = false;
+                    *out = format!("{}{}{}\n", out, indent(level), text);
+                },
+            }
+            self.next_trivia += 1;
+        }
+        if blank && before_statement { out.push('\n'); }
+    }
+
+    /// Takes the comment following code on the current line, if there is one before `limit`.
+    fn trailing_comment(&mut self, limit: usize) -> Option<String> {
+        match self.trivia.get(self.next_trivia) {
+            Some(Trivia::Comment { text, own_line: false, span }) if span.start < limit => {
+                self.next_trivia += 1;
+                Some(text.clone())
+            },
+            _ => None
+        }
+    }
+
+    /// Prints a node whose first line starts at the current position and whose following lines
+    /// are indented from `level`.
+    fn node(&mut self, id: NodeId, level: usize) -> String {
+        let end = self.ast[id].span.end;
+        let command = std::mem::replace(&mut self.command, false);
+        match &self.ast[id].kind {
+            NodeKind::Nil => String::from("nil"),
+            NodeKind::Boolean(value) => value.to_string(),
+            NodeKind::Integer(value) => value.to_string(),
+            NodeKind::BigInteger(digits) => digits.clone(),
+            NodeKind::Float(value) => format!("{:?}", value),
+            NodeKind::Str(value) => quote(value),
+            NodeKind::InterpolatedStr(parts) => format!("\"{}\"", self.interpolated(parts, level, escape)),
+            NodeKind::Symbol(name) => symbol(name),
+            NodeKind::Regex(source, flags) => format!("/{}/{}", source, flags),
+            NodeKind::InterpolatedRegex(parts, flags) => {
+                let source = self.interpolated(parts, level, |text| String::from_utf8_lossy(text).to_string());
+                format!("/{}/{}", source, flags)
+            },
+            NodeKind::Array(elements) => {
+                let elements = self.list(elements, level, None, 1, false, end);
+                self.bracketed("[", &elements, "]", level)
+            },
+            NodeKind::Hash(pairs) if pairs.is_empty() => String::from("{}"),
+            NodeKind::Hash(pairs) => {
+                let pairs = self.pairs(pairs, level, 2, end);
+                if pairs.broken { self.bracketed("{", &pairs, "}", level) } else { format!("{{ {} }}", pairs.texts().join(", ")) }
+            },
+            NodeKind::Range(start, end, exclusive) => {
+                let start = start.map_or(String::new(), |start| self.operand(start, level, Precedence::Range, true));
+                let end = end.map_or(String::new(), |end| self.operand(end, level, Precedence::Range, true));
+                format!("{}{}{}", start, if *exclusive { "..." } else { ".." }, end)
+            },
+            NodeKind::SelfRef => String::from("self"),
+            NodeKind::Identifier(name) |
+            NodeKind::InstanceVariable(name) |
+            NodeKind::ClassVariable(name) |
+            NodeKind::GlobalVariable(name) => name.clone(),
+            NodeKind::Constant(Some(scope), name) => format!("{}::{}", self.operand(*scope, level, Precedence::Call, false), name),
+            NodeKind::Constant(None, name) => name.clone(),
+            NodeKind::Assign(target, value) => {
+                let target = self.target(target, level);
+                format!("{} = {}", target, self.command_operand(*value, level, Precedence::AndOr))
+            },
+            NodeKind::MultipleAssign(targets, value) => {
+                let targets = self.targets(targets, level);
+                let value = match &self.ast[*value].kind {
+                    NodeKind::Array(values) if values.len() > 1 || values.iter().any(|id| matches!(self.ast[*id].kind, NodeKind::Splat(_))) => {
+                        let values: Vec<String> = values.iter().map(|id| self.operand(*id, level, Precedence::AndOr, true)).collect();
+                        values.join(", ")
+                    },
+                    _ => self.operand(*value, level, Precedence::AndOr, true),
+                };
+                format!("{} = {}", targets, value)
+            },
+            NodeKind::OpAssign(target, op, value) => {
+                let target = self.target(target, level);
+                format!("{} {}= {}", target, op, self.command_operand(*value, level, Precedence::AndOr))
+            },
+            NodeKind::Mlhs(targets) => format!("({})", self.targets(targets, level)),
+            NodeKind::Splat(Some(value)) => format!("*{}", self.operand(*value, level, Precedence::Prefix, true)),
+            NodeKind::Splat(None) => String::from("*"),
+            NodeKind::BlockPass(Some(value)) => format!("&{}", self.operand(*value, level, Precedence::Prefix, true)),
+            NodeKind::BlockPass(None) => String::from("&"),
+            NodeKind::Call(call) => self.call(call, level, end, command),
+            NodeKind::Block(params, body) => {
+                let params = self.block_params(params, level);
+                self.block(&params, body, level, end)
+            },
+            NodeKind::Lambda(params, body) => {
+                let header = if params.is_empty() { String::from("->") } else { format!("->({})", self.params(params, level)) };
+                format!("{} {}", header, self.block("", body, level, end))
+            },
+            NodeKind::Def(name, params, body) => {
+                let header = if params.is_empty() { format!("def {}", name) } else { format!("def {}({})", name, self.params(params, level)) };
+                self.body(&header, body, level, end)
+            },
+            NodeKind::SingletonDef(receiver, name, params, body) => {
+                let name = format!("{}.{}", self.node(*receiver, level), name);
+                let header = if params.is_empty() { format!("def {}", name) } else { format!("def {}({})", name, self.params(params, level)) };
+                self.body(&header, body, level, end)
+            },
+            NodeKind::Class(name, superclass, body) => {
+                let header = match superclass {
+                    Some(superclass) => format!("class {} < {}", self.node(*name, level), self.node(*superclass, level)),
+                    None => format!("class {}", self.node(*name, level)),
+                };
+                self.body(&header, body, level, end)
+            },
+            NodeKind::SingletonClass(object, body) => {
+                let header = format!("class << {}", self.node(*object, level));
+                self.body(&header, body, level, end)
+            },
+            NodeKind::Module(name, body) => {
+                let header = format!("module {}", self.node(*name, level));
+                self.body(&header, body, level, end)
+            },
+            NodeKind::Super(arguments, block) => self.super_call(arguments.as_deref(), *block, level, end),
+            NodeKind::Yield(arguments) if arguments.is_empty() => String::from("yield"),
+            NodeKind::Yield(arguments) => {
+                let arguments = self.list(arguments, level, None, 6, true, end);
+                format!("yield{}", self.bracketed("(", &arguments, ")", level))
+            },
+            NodeKind::Begin(body) => self.body("begin", body, level, end),
+            NodeKind::RescueModifier(value, rescue) => {
+                self.command = true;
+                let value = self.operand(*value, level, Precedence::RescueModifier, false);
+                format!("{} rescue {}", value, self.operand(*rescue, level, Precedence::RescueModifier, true))
+            },
+            NodeKind::Retry => String::from("retry"),
+            NodeKind::Return(Some(value)) => format!("return {}", self.operand(*value, level, Precedence::AndOr, true)),
+            NodeKind::Return(None) => String::from("return"),
+            NodeKind::Break(Some(value)) => format!("break {}", self.operand(*value, level, Precedence::AndOr, true)),
+            NodeKind::Break(None) => String::from("break"),
+            NodeKind::Next(Some(value)) => format!("next {}", self.operand(*value, level, Precedence::AndOr, true)),
+            NodeKind::Next(None) => String::from("next"),
+            NodeKind::And(left, right) => {
+                let left = self.operand(*left, level, Precedence::AndAnd, false);
+                format!("{} && {}", left, self.operand(*right, level, Precedence::AndAnd, true))
+            },
+            NodeKind::Or(left, right) => {
+                let left = self.operand(*left, level, Precedence::OrOr, false);
+                format!("{} || {}", left, self.operand(*right, level, Precedence::OrOr, true))
+            },
+            NodeKind::If(condition, consequence, alternative) => self.conditional("if", id, *condition, consequence, alternative.as_deref(), level),
+            NodeKind::Unless(condition, consequence, alternative) => {
+                self.conditional("unless", id, *condition, consequence, alternative.as_deref(), level)
+            },
+            NodeKind::While(condition, body) => self.conditional("while", id, *condition, body, None, level),
+            NodeKind::Until(condition, body) => self.conditional("until", id, *condition, body, None, level),
+            NodeKind::Case(case) => {
+                let mut out = match case.subject {
+                    Some(subject) => format!("case {}", self.node(subject, level)),
+                    None => String::from("case"),
+                };
+                let first_clause = case.when_clauses.first().map_or(end, |clause| clause.span.start);
+                if let Some(comment) = self.trailing_comment(first_clause) { out = format!("{} {}", out, comment); }
+                out.push('\n');
+                self.leading_trivia(&mut out, first_clause, level, false);
+
+                for (i, clause) in case.when_clauses.iter().enumerate() {
+                    let limit = match (case.when_clauses.get(i + 1), &case.else_clause) {
+                        (Some(next), _) => next.span.start,
+                        (None, Some(statements)) => statements.first().map_or(end, |id| self.ast[*id].span.start),
+                        (None, None) => end,
+                    };
+                    let values: Vec<String> = clause.values.iter().map(|id| self.node(*id, level)).collect();
+                    out = format!("{}{}when {}", out, indent(level), values.join(", "));
+                    out += &self.clause_statements(&clause.body, level, limit);
+                }
+                if let Some(statements) = &case.else_clause {
+                    out = format!("{}{}else", out, indent(level));
+                    out += &self.clause_statements(statements, level, end);
+                }
+
+                format!("{}{}end", out, indent(level))
+            },
+        }
+    }
+
+    /// Prints an `if`, `unless`, `while` or `until` in the form it was written in: as a
+    /// modifier, a ternary, or a block up to `end` with `elsif` for a nested `if` in its else
+    /// branch.
+    fn conditional(&mut self, keyword: &str, id: NodeId, condition: NodeId, body: &[NodeId], alternative: Option<&[NodeId]>, level: usize) -> String {
+        match (self.conditional_form(id), body, alternative) {
+            (ConditionalForm::Ternary, [consequence], Some([alternative])) => {
+                let condition = self.operand(condition, level, Precedence::Ternary, true);
+                let consequence = self.operand(*consequence, level, Precedence::Ternary, true);
+                return format!("{} ? {} : {}", condition, consequence, self.operand(*alternative, level, Precedence::Ternary, false));
+            },
+            (ConditionalForm::Modifier, [statement], None) => {
+                let statement = self.command_operand(*statement, level, Precedence::Modifier);
+                return format!("{} {} {}", statement, keyword, self.operand(condition, level, Precedence::Modifier, true));
+            },
+            _ => (),
+        }
+
+        let end = self.ast[id].span.end;
+        let else_start = alternative.and_then(|statements| statements.first()).map_or(end, |id| self.ast[*id].span.start);
+        let mut out = format!("{} {}", keyword, self.node(condition, level));
+        out += &self.clause_statements(body, level, else_start);
+        match alternative {
+            Some([nested]) if keyword == "if" && self.conditional_form(*nested) == ConditionalForm::Block &&
+                matches!(self.ast[*nested].kind, NodeKind::If(_, _, _)) => {
+                let nested = self.node(*nested, level);
+                return format!("{}{}els{}", out, indent(level), nested);
+            },
+            Some(statements) => {
+                out = format!("{}{}else", out, indent(level));
+                out += &self.clause_statements(statements, level, end);
+            },
+            None => (),
+        }
+
+        format!("{}{}end", out, indent(level))
+    }
+
+    /// Tells the forms of a conditional apart by where its condition sits in its span.
+    fn conditional_form(&self, id: NodeId) -> ConditionalForm {
+        let (condition, body) = match &self.ast[id].kind {
+            NodeKind::If(condition, body, _) | NodeKind::Unless(condition, body, _) |
+            NodeKind::While(condition, body) | NodeKind::Until(condition, body) => (*condition, body),
+            _ => return ConditionalForm::Block,
+        };
+        let start = self.ast[id].span.start;
+        let condition_start = self.ast[condition].span.start;
+        match body.first() {
+            Some(first) if self.ast[*first].span.start == start && condition_start > start => ConditionalForm::Modifier,
+            _ if condition_start == start => ConditionalForm::Ternary,
+            _ => ConditionalForm::Block,
+        }
+    }
+
+    /// Prints the `key => value` pairs of a hash ending at `limit` like the items of a list,
+    /// using `key: value` for symbol keys that allow it.
+    fn pairs(&mut self, pairs: &[(NodeId, NodeId)], level: usize, column: usize, limit: usize) -> Items {
+        let checkpoint = self.next_trivia;
+        let flat = self.pair_strings(pairs, level);
+        let width = level * INDENT.len() + column + flat.iter().map(|item| first_line(item).len() + 2).sum::<usize>();
+        let has_comments = self.next_trivia > checkpoint || self.comment_before(limit);
+        if width <= self.width && !flat.iter().any(|item| item.contains('\n')) && !has_comments {
+            return Items::flat(flat);
+        }
+
+        self.next_trivia = checkpoint;
+        let starts: Vec<usize> = pairs.iter().map(|(key, _)| self.ast[*key].span.start).collect();
+        self.broken_items(&starts, limit, |formatter, index| formatter.pair_string(pairs[index], level + 1))
+    }
+
+    fn pair_strings(&mut self, pairs: &[(NodeId, NodeId)], level: usize) -> Vec<String> {
+        pairs.iter().map(|pair| self.pair_string(*pair, level)).collect()
+    }
+
+    fn pair_string(&mut self, (key, value): (NodeId, NodeId), level: usize) -> String {
+        let key = match &self.ast[key].kind {
+            NodeKind::Symbol(name) if is_label(name) => format!("{}:", name),
+            _ => format!("{} =>", self.operand(key, level, Precedence::Modifier, true)),
+        };
+        format!("{} {}", key, self.operand(value, level, Precedence::Modifier, true))
+    }
+
+    /// Lays out items one to a line, each with the comments on the lines before it and the one
+    /// following it on its line. The comments left before `limit` end the list.
+    fn broken_items(&mut self, starts: &[usize], limit: usize, text: impl Fn(&mut Self, usize) -> String) -> Items {
+        let mut items = vec!();
+        for (index, start) in starts.iter().enumerate() {
+            let leading = self.comments_before(*start);
+            let text = text(self, index);
+            let trailing = self.trailing_comment(starts.get(index + 1).copied().unwrap_or(limit));
+            items.push(Item { leading, text, trailing });
+        }
+        let closing = self.comments_before(limit);
+
+        Items { items, broken: true, closing }
+    }
+
+    /// Whether a comment comes before `limit`.
+    fn comment_before(&self, limit: usize) -> bool {
+        self.trivia[self.next_trivia..].iter()
+            .take_while(|trivia| trivia.start() < limit)
+            .any(|trivia| matches!(trivia, Trivia::Comment { .. }))
+    }
+
+    /// Takes the comments before `limit`, passing over blank lines.
+    fn comments_before(&mut self, limit: usize) -> Vec<String> {
+        let mut comments = vec!();
+        while let Some(trivia) = self.trivia.get(self.next_trivia).filter(|trivia| trivia.start() < limit) {
+            if let Trivia::Comment { text, .. } = trivia { comments.push(text.clone()); }
+            self.next_trivia += 1;
+        }
+        comments
+    }
+
+    /// Prints a node where the parser expects an expression binding at least as tightly as
+    /// `min`, or strictly more tightly when `strict`, wrapping it in parentheses otherwise.
+    fn operand(&mut self, id: NodeId, level: usize, min: Precedence, strict: bool) -> String {
+        let precedence = self.precedence(id);
+        let text = self.node(id, level);
+        if precedence < min || (strict && precedence == min) {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    /// Prints a node like `operand` where Ruby also allows a command call, which keeps the
+    /// form it was written in.
+    fn command_operand(&mut self, id: NodeId, level: usize, min: Precedence) -> String {
+        self.command = true;
+        self.operand(id, level, min, true)
+    }
+
+    fn precedence(&self, id: NodeId) -> Precedence {
+        match &self.ast[id].kind {
+            NodeKind::Assign(_, _) | NodeKind::MultipleAssign(_, _) | NodeKind::OpAssign(_, _, _) => Precedence::Assignment,
+            NodeKind::RescueModifier(_, _) => Precedence::RescueModifier,
+            NodeKind::Return(_) | NodeKind::Break(_) | NodeKind::Next(_) => Precedence::Lowest,
+            NodeKind::Integer(value) if *value < 0 => Precedence::UnaryMinus,
+            NodeKind::BigInteger(digits) if digits.starts_with('-') => Precedence::UnaryMinus,
+            NodeKind::Float(value) if value.is_sign_negative() => Precedence::UnaryMinus,
+            NodeKind::Range(_, _, _) => Precedence::Range,
+            NodeKind::And(_, _) => Precedence::AndAnd,
+            NodeKind::Or(_, _) => Precedence::OrOr,
+            NodeKind::If(_, _, _) | NodeKind::Unless(_, _, _) | NodeKind::While(_, _) | NodeKind::Until(_, _) => {
+                match self.conditional_form(id) {
+                    ConditionalForm::Modifier => Precedence::Modifier,
+                    ConditionalForm::Ternary => Precedence::Ternary,
+                    ConditionalForm::Block => Precedence::Call,
+                }
+            },
+            NodeKind::Call(call) if is_binary(call) => binary_precedence(&call.method).unwrap_or(Precedence::Call),
+            NodeKind::Call(call) if is_unary(call) && call.method == "-@" => Precedence::UnaryMinus,
+            NodeKind::Call(call) if is_unary(call) => Precedence::Prefix,
+            _ => Precedence::Call,
+        }
+    }
+
+    /// Prints a call, whose node ends at `end`. Where a `command` is allowed, a call without a
+    /// receiver written without parentheses around its arguments is printed that way again.
+    fn call(&mut self, call: &Call, level: usize, end: usize, command: bool) -> String {
+        if is_binary(call) {
+            let precedence = binary_precedence(&call.method).unwrap_or(Precedence::Call);
+            // `**` is the one right associative operator
+            let right_associative = precedence == Precedence::Pow;
+            let left = self.operand(call.receiver.unwrap(), level, precedence, right_associative);
+            let right = self.operand(call.arguments[0], level, precedence, !right_associative);
+            return format!("{} {} {}", left, call.method, right);
+        }
+        if is_unary(call) {
+            let receiver = call.receiver.unwrap();
+            let precedence = if call.method == "-@" { Precedence::UnaryMinus } else { Precedence::Prefix };
+            let numeric = matches!(self.ast[receiver].kind, NodeKind::Integer(_) | NodeKind::BigInteger(_) | NodeKind::Float(_));
+            // `-(1)` has to keep its parentheses, or it reads back as the literal `-1`
+            let operand = if numeric && call.method == "-@" {
+                format!("({})", self.node(receiver, level))
+            } else {
+                self.operand(receiver, level, precedence, precedence == Precedence::Prefix)
+            };
+            return format!("{}{}", call.method.trim_end_matches('@'), operand);
+        }
+
+        let mut prefix = match call.receiver {
+            Some(receiver) => self.operand(receiver, level, Precedence::Call, false),
+            None => String::new(),
+        };
+        let block = call.block.filter(|block| matches!(self.ast[*block].kind, NodeKind::Block(_, _)));
+        let block_pass = call.block.filter(|_| block.is_none());
+        let arguments_end = block.map_or(end, |block| self.ast[block].span.start);
+        if call.method == "[]" && call.receiver.is_some() && call.block.is_none() {
+            let arguments = self.list(&call.arguments, level, None, prefix.len() + 1, false, end);
+            return format!("{}{}", prefix, self.bracketed("[", &arguments, "]", level));
+        }
+        if call.receiver.is_some() { prefix.push('.'); }
+        prefix += &call.method;
+
+        let mut command = command && call.receiver.is_none() && self.is_command(call, block_pass, arguments_end);
+        if !call.arguments.is_empty() || block_pass.is_some() {
+            let arguments = self.list(&call.arguments, level, block_pass, prefix.len() + 1, true, arguments_end);
+            command = command && !arguments.broken;
+            if command {
+                prefix = format!("{} {}", prefix, arguments.texts().join(", "));
+            } else {
+                prefix += &self.bracketed("(", &arguments, ")", level);
+            }
+        } else if call.receiver.is_none() && block.is_none() {
+            prefix += "()";
+        }
+        if let Some(block) = block {
+            let block = match &self.ast[block].kind {
+                // braces would take the block to the last argument of the command
+                NodeKind::Block(params, body) if command => {
+                    let params = self.block_params(params, level);
+                    self.body(&format!("do{}", params), body, level, self.ast[block].span.end)
+                },
+                _ => self.node(block, level),
+            };
+            prefix = format!("{} {}", prefix, block);
+        }
+
+        prefix
+    }
+
+    /// Whether the source left out the parentheses around the arguments of `call`, which end at
+    /// `arguments_end`, and they read back the same that way. Without them the last argument
+    /// ends the call, or is a space away from its block.
+    fn is_command(&self, call: &Call, block_pass: Option<NodeId>, arguments_end: usize) -> bool {
+        let last = match block_pass.or_else(|| call.arguments.last().copied()) {
+            Some(last) => self.ast[last].span.end,
+            None => return false,
+        };
+        let unparenthesized = if block_pass.is_none() && call.block.is_some() { last + 1 >= arguments_end } else { last == arguments_end };
+        unparenthesized && call.arguments.iter().all(|id| match self.ast[*id].kind {
+            NodeKind::Assign(_, _) | NodeKind::OpAssign(_, _, _) => true,
+            _ => self.precedence(*id) > Precedence::RescueModifier,
+        })
+    }
+
+    /// Prints `super` like a call, except that a bare `super` has no parentheses to add.
+    fn super_call(&mut self, arguments: Option<&[NodeId]>, block: Option<NodeId>, level: usize, end: usize) -> String {
+        let literal_block = block.filter(|block| matches!(self.ast[*block].kind, NodeKind::Block(_, _)));
+        let block_pass = block.filter(|_| literal_block.is_none());
+        let arguments_end = literal_block.map_or(end, |block| self.ast[block].span.start);
+        let mut prefix = String::from("super");
+        if let Some(arguments) = arguments {
+            let arguments = self.list(arguments, level, block_pass, prefix.len() + 1, true, arguments_end);
+            prefix += &self.bracketed("(", &arguments, ")", level);
+        }
+        if let Some(block) = literal_block {
+            prefix = format!("{} {}", prefix, self.node(block, level));
+        }
+
+        prefix
+    }
+
+    /// Prints the parts of an interpolated string or regexp, with the text as `text` writes it.
+    fn interpolated(&mut self, parts: &[NodeId], level: usize, text: fn(&[u8]) -> String) -> String {
+        let mut out = String::new();
+        for part in parts.iter() {
+            match &self.ast[*part].kind {
+                NodeKind::Str(value) => out += &text(value),
+                NodeKind::Begin(body) => {
+                    let statements: Vec<String> = body.statements.iter().map(|id| self.node(*id, level)).collect();
+                    out += &format!("#{{{}}}", statements.join("; "));
+                },
+                _ => out += &self.node(*part, level),
+            }
+        }
+        out
+    }
+
+    /// Prints the items of a list ending at `limit`, each on one line if the list is broken
+    /// over several lines, so the caller can decide how to join them. `column` is roughly where
+    /// the list starts. With `arguments`, a hash ending the list is printed without its braces,
+    /// with a line for each pair when broken. A list with comments inside is always broken, so
+    /// they stay with the items they're next to.
+    fn list(&mut self, ids: &[NodeId], level: usize, last: Option<NodeId>, column: usize, arguments: bool, limit: usize) -> Items {
+        let trailing_hash = ids.last().copied().filter(|id| {
+            arguments && matches!(&self.ast[*id].kind, NodeKind::Hash(pairs) if !pairs.is_empty())
+        });
+        let ids: Vec<NodeId> = ids.iter().copied().chain(last).collect();
+        let checkpoint = self.next_trivia;
+        let flat: Vec<String> = ids.iter().map(|id| self.item(*id, level, trailing_hash)).collect();
+        let width = level * INDENT.len() + column + flat.iter().map(|item| first_line(item).len() + 2).sum::<usize>();
+        let has_comments = self.next_trivia > checkpoint || self.comment_before(limit);
+        let multiline = flat.iter().rev().skip(1).any(|item| item.contains('\n'));
+        if width <= self.width && !multiline && !has_comments {
+            return Items::flat(flat);
+        }
+
+        self.next_trivia = checkpoint;
+        let mut pieces = vec!();
+        for id in ids.iter() {
+            match &self.ast[*id].kind {
+                NodeKind::Hash(pairs) if trailing_hash == Some(*id) => pieces.extend(pairs.iter().map(|pair| Piece::Pair(*pair))),
+                _ => pieces.push(Piece::Node(*id)),
+            }
+        }
+        let starts: Vec<usize> = pieces.iter().map(|piece| match piece {
+            Piece::Node(id) | Piece::Pair((id, _)) => self.ast[*id].span.start,
+        }).collect();
+        self.broken_items(&starts, limit, |formatter, index| match pieces[index] {
+            Piece::Node(id) => formatter.node(id, level + 1),
+            Piece::Pair(pair) => formatter.pair_string(pair, level + 1),
+        })
+    }
+
+    fn item(&mut self, id: NodeId, level: usize, bare_hash: Option<NodeId>) -> String {
+        match &self.ast[id].kind {
+            NodeKind::Hash(pairs) if bare_hash == Some(id) => self.pair_strings(pairs, level).join(", "),
+            _ => self.node(id, level),
+        }
+    }
+
+    fn bracketed(&self, open: &str, items: &Items, close: &str, level: usize) -> String {
+        if !items.broken { return format!("{}{}{}", open, items.texts().join(", "), close); }
+
+        let mut lines = vec!();
+        for (index, item) in items.items.iter().enumerate() {
+            lines.extend(item.leading.iter().map(|comment| format!("{}{}", indent(level + 1), comment)));
+            let mut line = format!("{}{}", indent(level + 1), item.text);
+            if index + 1 < items.items.len() { line.push(','); }
+            if let Some(comment) = &item.trailing { line = format!("{} {}", line, comment); }
+            lines.push(line);
+        }
+        lines.extend(items.closing.iter().map(|comment| format!("{}{}", indent(level + 1), comment)));
+        format!("{}\n{}\n{}{}", open, lines.join("\n"), indent(level), close)
+    }
+
+    fn target(&mut self, target: &AssignTarget, level: usize) -> String {
+        match target {
+            AssignTarget::Local(name) |
+            AssignTarget::InstanceVariable(name) |
+            AssignTarget::ClassVariable(name) |
+            AssignTarget::GlobalVariable(name) => name.clone(),
+            AssignTarget::Constant(Some(scope), name) => format!("{}::{}", self.operand(*scope, level, Precedence::Call, false), name),
+            AssignTarget::Constant(None, name) => name.clone(),
+            AssignTarget::Attribute(receiver, name) => format!("{}.{}", self.operand(*receiver, level, Precedence::Call, false), name),
+            AssignTarget::Index(receiver, arguments) => {
+                let receiver = self.operand(*receiver, level, Precedence::Call, false);
+                let arguments: Vec<String> = arguments.iter().map(|id| self.node(*id, level)).collect();
+                format!("{}[{}]", receiver, arguments.join(", "))
+            },
+            AssignTarget::Splat(Some(target)) => format!("*{}", self.target(target, level)),
+            AssignTarget::Splat(None) => String::from("*"),
+            AssignTarget::Nested(targets) => format!("({})", self.targets(targets, level)),
+        }
+    }
+
+    fn targets(&mut self, targets: &[AssignTarget], level: usize) -> String {
+        let targets: Vec<String> = targets.iter().map(|target| self.target(target, level)).collect();
+        targets.join(", ")
+    }
+
+    /// The parameters of a block between pipes, after a space, or nothing when it has none.
+    fn block_params(&mut self, params: &Parameters, level: usize) -> String {
+        if params.is_empty() { String::new() } else { format!(" |{}|", self.params(params, level)) }
+    }
+
+    fn params(&mut self, params: &Parameters, level: usize) -> String {
+        let mut strings: Vec<String> = params.required.iter().map(|target| self.target(target, level)).collect();
+        for (name, default) in params.optional.iter() {
+            strings.push(format!("{} = {}", name, self.operand(*default, level, Precedence::Assignment, true)));
+        }
+        match params.rest.as_deref() {
+            Some("*") => strings.push(String::from("*")),
+            Some(rest) => strings.push(format!("*{}", rest)),
+            None => ()
+        }
+        strings.extend(params.post.iter().map(|target| self.target(target, level)));
+        match params.block.as_deref() {
+            Some("&") => strings.push(String::from("&")),
+            Some(block) => strings.push(format!("&{}", block)),
+            None => ()
+        }
+
+        strings.join(", ")
+    }
+
+    /// Whether a block fits on one line between braces: a single simple statement, without
+    /// rescue clauses or comments.
+    fn is_inline_block(&self, body: &BodyStatement, end: usize) -> bool {
+        let simple = body.rescue_clauses.is_empty() && body.else_clause.is_none() && body.ensure_clause.is_none();
+        let comments = self.trivia[self.next_trivia..].iter()
+            .any(|trivia| matches!(trivia, Trivia::Comment { .. }) && trivia.start() < end);
+        let single = match body.statements.as_slice() {
+            [] => true,
+            [statement] => !matches!(
+                self.ast[*statement].kind,
+                NodeKind::Def(_, _, _) | NodeKind::SingletonDef(_, _, _, _) | NodeKind::Class(_, _, _) |
+                NodeKind::SingletonClass(_, _) | NodeKind::Module(_, _) | NodeKind::Begin(_)
+            ),
+            _ => false,
+        };
+
+        simple && !comments && single
+    }
+
+    /// Prints `{ params statement }` when it fits on a line, or a `do params ... end` block.
+    fn block(&mut self, params: &str, body: &BodyStatement, level: usize, end: usize) -> String {
+        if self.is_inline_block(body, end) {
+            let checkpoint = self.next_trivia;
+            let statement = body.statements.first().map(|id| self.node(*id, level));
+            let text = match statement {
+                Some(statement) => format!("{{{} {} }}", params, statement),
+                None if params.is_empty() => String::from("{}"),
+                None => format!("{{{} }}", params),
+            };
+            if !text.contains('\n') && level * INDENT.len() + text.len() <= self.width {
+                return text;
+            }
+            self.next_trivia = checkpoint;
+        }
+
+        self.body(&format!("do{}", params), body, level, end)
+    }
+
+    /// Prints `header`, then the statements and clauses of `body`, then `end`.
+    fn body(&mut self, header: &str, body: &BodyStatement, level: usize, end: usize) -> String {
+        let mut out = String::from(header);
+        if let Some(comment) = self.trailing_comment(body.statements.first().map_or(end, |id| self.ast[*id].span.start)) {
+            out = format!("{} {}", out, comment);
+        }
+        out.push('\n');
+
+        let clause_starts: Vec<usize> = body.rescue_clauses.iter().map(|clause| clause.span.start)
+            .chain(body.else_span.map(|span| span.start))
+            .chain(body.ensure_span.map(|span| span.start))
+            .chain(std::iter::once(end))
+            .collect();
+        out += &self.statements(&body.statements, level + 1, clause_starts[0]);
+
+        for (i, clause) in body.rescue_clauses.iter().enumerate() {
+            out += &indent(level);
+            out += "rescue";
+            if !clause.exception_classes.is_empty() {
+                let classes: Vec<String> = clause.exception_classes.iter().map(|id| self.node(*id, level)).collect();
+                out = format!("{} {}", out, classes.join(", "));
+            }
+            if let Some(variable) = &clause.variable { out = format!("{} => {}", out, variable); }
+            out += &self.clause_statements(&clause.body, level, clause_starts[i + 1]);
+        }
+        let mut next = body.rescue_clauses.len() + 1;
+        if let Some(statements) = &body.else_clause {
+            out = format!("{}{}else", out, indent(level));
+            out += &self.clause_statements(statements, level, clause_starts[next]);
+            next += 1;
+        }
+        if let Some(statements) = &body.ensure_clause {
+            out = format!("{}{}ensure", out, indent(level));
+            out += &self.clause_statements(statements, level, clause_starts[next]);
+        }
+
+        format!("{}{}end", out, indent(level))
+    }
+
+    /// Finishes a clause header line and prints the statements below it.
+    fn clause_statements(&mut self, statements: &[NodeId], level: usize, limit: usize) -> String {
+        let mut out = String::new();
+        if let Some(comment) = self.trailing_comment(statements.first().map_or(limit, |id| self.ast[*id].span.start)) {
+            out = format!(" {}", comment);
+        }
+        out.push('\n');
+
+        out + &self.statements(statements, level + 1, limit)
+    }
+}
+
+fn indent(level: usize) -> String {
+    INDENT.repeat(level)
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("")
+}
+
+fn is_binary(call: &Call) -> bool {
+    call.receiver.is_some() && call.arguments.len() == 1 && call.block.is_none() && binary_precedence(&call.method).is_some()
+}
+
+fn is_unary(call: &Call) -> bool {
+    call.receiver.is_some() && call.arguments.is_empty() && call.block.is_none() && ["-@", "!", "~"].contains(&call.method.as_str())
+}
+
+/// The precedence the parser gives to the operators it reads as binary method calls.
+fn binary_precedence(method: &str) -> Option<Precedence> {
+    match method {
+        "==" | "!=" | "===" | "<=>" | "=~" | "!~" => Some(Precedence::Equals),
+        "<" | ">" | "<=" | ">="                   => Some(Precedence::LessGreater),
+        "|" | "^"                                 => Some(Precedence::BitOr),
+        "&"                                       => Some(Precedence::BitAnd),
+        "<<" | ">>"                               => Some(Precedence::Shift),
+        "+" | "-"                                 => Some(Precedence::Sum),
+        "*" | "/" | "%"                           => Some(Precedence::Product),
+        "**"                                      => Some(Precedence::Pow),
+        _                                         => None,
+    }
+}
+
+/// Whether a symbol can be written as a `name:` label in a hash.
+fn is_label(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The items of a list or hash, laid out by `list` or `pairs` for `bracketed`.
+struct Items {
+    items:   Vec<Item>,
+    /// Whether they go one to a line
+    broken:  bool,
+    /// The comments after the last item of a broken list
+    closing: Vec<String>,
+}
+
+/// An item of a list, with the comments on the lines before it and the one after it on its
+/// line, which only a broken list has room for.
+struct Item {
+    leading:  Vec<String>,
+    text:     String,
+    trailing: Option<String>,
+}
+
+impl Items {
+    fn flat(texts: Vec<String>) -> Self {
+        let items = texts.into_iter().map(|text| Item { leading: vec!(), text, trailing: None }).collect();
+        Items { items, broken: false, closing: vec!() }
+    }
+
+    fn texts(&self) -> Vec<&str> {
+        self.items.iter().map(|item| item.text.as_str()).collect()
+    }
+}
+
+/// What a broken list puts on a line: a node, or one pair of a hash passed without braces.
+#[derive(Copy, Clone)]
+enum Piece {
+    Node(NodeId),
+    Pair((NodeId, NodeId)),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ConditionalForm {
+    Block,
+    Modifier,
+    Ternary,
+}
+
+/// Quotes a string with single quotes unless it needs escapes.
+fn quote(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(text) if !text.chars().any(|c| c == '\'' || c == '\\' || c.is_control()) => format!("'{}'", text),
+        _ => format!("\"{}\"", escape(value)),
+    }
+}
+
+/// Escapes the contents of a double-quoted string. Bytes that aren't valid UTF-8 are written
+/// as `\xHH`, so they read back as the same bytes.
+fn escape(value: &[u8]) -> String {
+    let mut escaped = String::new();
+    for chunk in value.utf8_chunks() {
+        let mut chars = chunk.valid().chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"'  => escaped += "\\\"",
+                '\\' => escaped += "\\\\",
+                '\n' => escaped += "\\n",
+                '\t' => escaped += "\\t",
+                '\r' => escaped += "\\r",
+                '\u{1b}' => escaped += "\\e",
+                // a digit after `\0` would be read as part of an octal escape
+                '\u{0}' if matches!(chars.peek(), Some('0'..='7')) => escaped += "\\000",
+                '\u{0}' => escaped += "\\0",
+                '#' if matches!(chars.peek(), Some('{') | Some('$') | Some('@')) => escaped += "\\#",
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped += &format!("\\x{:02X}", byte);
+        }
+    }
+
+    escaped
+}
+
+/// Prints a symbol, quoting its name when it isn't an identifier, variable or operator.
+fn symbol(name: &str) -> String {
+    let operators = ["[]=", "[]", "<=>", "===", "==", "=~", "!=", "!~", "**", "+@", "-@", "<=", ">=",
+                     "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "!", "&", "|", "^", "~"];
+    let bare = name.trim_start_matches(['@', '$']);
+    let bare = bare.strip_suffix(['?', '!', '=']).unwrap_or(bare);
+    let identifier = bare.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') &&
+        bare.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') &&
+        name.len() - bare.len() <= 2;
+    if identifier || operators.contains(&name) {
+        format!(":{}", name)
+    } else {
+        format!(":{}", quote(name.as_bytes()).replacen('\'', "\"", 2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_formats(input: &str, expected: &str) {
+        let formatted = format_source(input).unwrap_or_else(|error| panic!("{:?}", error));
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), expected, "formatting should be idempotent");
+    }
+
+    #[test]
+    fn should_normalize_spacing_and_indentation() {
+        assert_formats(
+            "x=1+2*3;y =  [1,2 ,3]\ndef add(a,b=2,*rest,&blk)\nreturn a+b\n     end\nputs(add(x ,y))",
+            "x = 1 + 2 * 3\ny = [1, 2, 3]\ndef add(a, b = 2, *rest, &blk)\n  return a + b\nend\nputs(add(x, y))\n",
+        );
+    }
+
+    #[test]
+    fn should_keep_the_parentheses_precedence_needs() {
+        assert_formats(
+            "a = (1 + 2) * 3\nb = 1 - (2 - 3)\nc = -(x + 1)\nd = (x + y).abs\ne = (x = 1) rescue nil",
+            "a = (1 + 2) * 3\nb = 1 - (2 - 3)\nc = -(x + 1)\nd = (x + y).abs\ne = (x = 1) rescue nil\n",
+        );
+    }
+
+    #[test]
+    fn should_preserve_comments_and_single_blank_lines() {
+        assert_formats(
+            "# frozen_string_literal: true\n\n\n# Adds things\ndef add(a, b) # the sum\n  # done here\n  a + b\n\n  # trailing\nend\nadd(1, 2)  #   call it",
+            "# frozen_string_literal: true\n\n# Adds things\ndef add(a, b) # the sum\n  # done here\n  a + b\n\n  # trailing\nend\nadd(1, 2) #   call it\n",
+        );
+    }
+
+    #[test]
+    fn should_print_clauses_at_the_level_of_their_body() {
+        assert_formats(
+            "begin\nrisky\nrescue Timeout, Errors::Bad => e\n# try again\nretry\nrescue\nnil\nelse\nok\nensure\ncleanup\nend",
+            "begin\n  risky\nrescue Timeout, Errors::Bad => e\n  # try again\n  retry\nrescue\n  nil\nelse\n  ok\nensure\n  cleanup\nend\n",
+        );
+    }
+
+    #[test]
+    fn should_choose_braces_or_do_for_blocks() {
+        assert_formats(
+            "items.map do |x| x * 2 end\nitems.each { |x|\n  log(x)\n  save(x)\n}\nrun(&blk)\ndouble = ->(x){x*2}\nnoop = ->{}",
+            "items.map { |x| x * 2 }\nitems.each do |x|\n  log(x)\n  save(x)\nend\nrun(&blk)\ndouble = ->(x) { x * 2 }\nnoop = -> {}\n",
+        );
+    }
+
+    #[test]
+    fn should_print_classes_modules_and_super() {
+        assert_formats(
+            "module Shapes\nclass Square<Base\ndef self.unit;new(1);end\ndef initialize(side)\nsuper ;super( side ){|x|x}\nend\nclass <<self\nattr_reader :count\nend\nend\nend",
+            "module Shapes\n  class Square < Base\n    def self.unit\n      new(1)\n    end\n    def initialize(side)\n      super\n      super(side) { |x| x }\n    end\n    class << self\n      attr_reader :count\n    end\n  end\nend\n",
+        );
+    }
+
+    #[test]
+    fn should_print_parameters_in_their_order() {
+        assert_formats(
+            "def f(a,*r,b)\nend\ndef g(a=1,b)\nend\ndef h(a, b = 2, *, c, &blk)\nend\neach { |x, *y, z| x }\nh.each_with_index { |(k,v),i| k }\n",
+            "def f(a, *r, b)\nend\ndef g(a = 1, b)\nend\ndef h(a, b = 2, *, c, &blk)\nend\neach { |x, *y, z| x }\nh.each_with_index { |(k, v), i| k }\n",
+        );
+    }
+
+    #[test]
+    fn should_keep_the_command_form_of_calls() {
+        assert_formats(
+            "attr_reader :x,:y\nputs(v)\nraise 'x' unless ok\np $! rescue nil\nx = format '%d', 1\np x += 1\nit 'works' do\nrun\nend\nlog [puts 1]\nfoo (a + b) * c, 2\n",
+            "attr_reader :x, :y\nputs(v)\nraise 'x' unless ok\np $! rescue nil\nx = format '%d', 1\np x += 1\nit 'works' do\n  run\nend\nlog [puts(1)]\nfoo (a + b) * c, 2\n",
+        );
+    }
+
+    #[test]
+    fn should_print_yield() {
+        assert_formats("def each\nyield ;yield  1,2\nyield( x )\nend", "def each\n  yield\n  yield(1, 2)\n  yield(x)\nend\n");
+    }
+
+    #[test]
+    fn should_break_long_argument_lists() {
+        let input = "configure(first_argument_name, second_argument_name, third_argument_name, fourth)";
+        assert_formats(input, "configure(\n  first_argument_name,\n  second_argument_name,\n  third_argument_name,\n  fourth\n)\n");
+    }
+
+    #[test]
+    fn should_keep_comments_inside_literals_and_argument_lists() {
+        assert_formats(
+            "h = {\n  # leading\n  a: 1,\n  b: 2, # bee\n}\ncall(1,\n  # note\n  2)\nx = [1, # one\n  2\n  # end\n]\nfoo(1, a: 2, # x\n  b: 3) { |y| y }\n",
+            "h = {\n  # leading\n  a: 1,\n  b: 2 # bee\n}\ncall(\n  1,\n  # note\n  2\n)\nx = [\n  1, # one\n  2\n  # end\n]\nfoo(\n  1,\n  a: 2, # x\n  b: 3\n) { |y| y }\n",
+        );
+    }
+
+    #[test]
+    fn should_print_assignments_and_literals() {
+        assert_formats(
+            "a , b = b , a\n*, last = list\n(x, y), z = pair\nh[k] ||= \"it's\"\nobj.name = \"tab\\there\"\nFoo::BAR = :\"odd sym\"\ncall()\n",
+            "a, b = b, a\n*, last = list\n(x, y), z = pair\nh[k] ||= \"it's\"\nobj.name = \"tab\\there\"\nFoo::BAR = :\"odd sym\"\ncall()\n",
+        );
+    }
+
+    #[test]
+    fn should_print_string_bytes_and_interpolation() {
+        assert_formats(
+            "a = \"\\xff\\101\"\nb = \"\\0\\0612\"\nputs \"x=#{x+1}, #{ a;b }\\n\"\nc = '#{raw}'\n",
+            "a = \"\\xFFA\"\nb = \"\\00012\"\nputs \"x=#{x + 1}, #{a; b}\\n\"\nc = '#{raw}'\n",
+        );
+        assert_formats("r = /a#{ n+1 }b/i\n", "r = /a#{n + 1}b/i\n");
+    }
+
+    #[test]
+    fn should_keep_the_form_of_conditionals() {
+        assert_formats(
+            "if a then b elsif c\nd\nelse e end\nputs(x) unless done\ny = ok ? 1 : (z ? 2 : 3)\nwhile n<10 do n+=1 end\ncase x\nwhen 1,2 then :low # small\nelse :high\nend",
+            "if a\n  b\nelsif c\n  d\nelse\n  e\nend\nputs(x) unless done\ny = ok ? 1 : z ? 2 : 3\nwhile n < 10\n  n += 1\nend\ncase x\nwhen 1, 2\n  :low # small\nelse\n  :high\nend\n",
+        );
+    }
+
+    #[test]
+    fn should_print_hashes_ranges_and_logic() {
+        assert_formats(
+            "h = {a: 1, 'b'=>2}\nrender(:show, {status: 200})\nr = (1..n-1)\ns = (1..)\nok = a and not b or c\nv = -2 ** 2 + (-2) ** 2",
+            "h = { a: 1, 'b' => 2 }\nrender(:show, status: 200)\nr = 1..n - 1\ns = 1..\n(ok = a) && !b || c\nv = -2 ** 2 + (-2) ** 2\n",
+        );
+    }
+
+    #[test]
+    fn should_refuse_to_format_invalid_source() {
+        assert!(matches!(format_source("x = (1"), Err(FormatError::Parse(_))));
+    }
+}
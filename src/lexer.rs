@@ -1,24 +1,47 @@
 pub mod token;
 
-use token::{ TokenType, Token, KEYWORDS };
+use token::{ TokenType, Token, Span, Trivia, KEYWORDS };
 
 pub struct Lexer {
     body:     String,
     pos:      usize,
     read_pos: usize,
-    ch:       char
+    ch:       char,
+    span:     Span,
+    trivia:   Vec<Trivia>,
+    /// The type of the token most recently returned, which decides what a `/` means
+    previous: Option<TokenType>,
+    /// Set by the parser when that token is a local variable, so `x /2` divides
+    after_local: bool,
+    /// For each `#{` still open, innermost last, the `"` or `/` closing the literal it is in
+    /// and how many braces are open inside it
+    interpolations: Vec<(char, usize)>,
+}
+
+/// Where the contents of a string or regexp literal stop.
+#[derive(PartialEq)]
+enum StringEnd {
+    Quote,
+    /// At the `#{` of an interpolation, which only double quotes allow
+    Interpolation,
+    Eof,
 }
 
 impl Lexer {
     pub fn new(body: String) -> Self {
         let mut l = Self {
-            body: body,
+            body,
             pos: 0,
             read_pos: 0,
-            ch: ' '
+            ch: ' ',
+            span: Span::default(),
+            trivia: vec!(),
+            previous: None,
+            after_local: false,
+            interpolations: vec!(),
         };
         l.read_char();
-        return l;
+        l
     }
 
     pub fn read_char(&mut self) {
@@ -38,72 +61,450 @@ impl Lexer {
         bytes[self.read_pos] as char
     }
 
+    fn peek_char_at(&self, offset: usize) -> char {
+        match self.body.as_bytes().get(self.read_pos + offset) {
+            Some(byte) => *byte as char,
+            None => '\u{0}'
+        }
+    }
+
+    /// Reads an operator followed by `=`, like `+=` or `||=`, if there is one at the current
+    /// position.
+    fn read_op_assign(&mut self) -> Option<Token> {
+        let op_len = match (self.ch, self.peek_char(), self.peek_char_at(1)) {
+            ('*', '*', '=') | ('|', '|', '=') | ('&', '&', '=') | ('<', '<', '=') | ('>', '>', '=') => 2,
+            ('+', '=', _) | ('-', '=', _) | ('*', '=', _) | ('/', '=', _) | ('%', '=', _) |
+            ('|', '=', _) | ('&', '=', _) | ('^', '=', _) => 1,
+            _ => return None
+        };
+        let pos = self.pos;
+        for _ in 0..op_len { self.read_char(); }
+
+        Some(Token::new(TokenType::OPASSIGN, self.body[pos..=self.pos].to_string()))
+    }
+
+    /// Reads the operator starting at the current character that is made of several characters,
+    /// like `<=>` or `**`, leaving the lexer on its last character.
+    fn read_compound_operator(&mut self) -> Option<Token> {
+        let operators = [
+            ("<=>", TokenType::SPACESHIP), ("===", TokenType::EQQ), ("...", TokenType::DOT3),
+            ("**", TokenType::POW), ("<=", TokenType::LTE), (">=", TokenType::GTE), ("=~", TokenType::MATCH),
+            ("!~", TokenType::NMATCH), ("<<", TokenType::LSHIFT), (">>", TokenType::RSHIFT),
+            ("&&", TokenType::ANDAND), ("||", TokenType::OROR), ("..", TokenType::DOT2),
+        ];
+        let rest = &self.body[self.pos..];
+        let (operator, token_type) = operators.iter().find(|(operator, _)| rest.starts_with(operator))?;
+        for _ in 1..operator.len() { self.read_char(); }
+
+        Some(Token::new(*token_type, operator.to_string()))
+    }
+
     pub fn read_identifier(&mut self) -> String {
         let pos = self.pos;
-        while self.is_letter(self.ch) {
+        while self.is_letter(self.ch) || self.is_digit(self.ch) {
+            self.read_char();
+        }
+        // Method names may end in a single `?` or `!`, but `foo!=bar` is still `foo != bar`
+        if (self.ch == '?' || self.ch == '!') && self.peek_char() != '=' {
+            self.read_char();
+        }
+        self.body[pos..self.pos].to_string()
+    }
+
+    /// Reads `@ivar`, `@@cvar` and `$gvar` names, including special globals like `$0` and `$!`.
+    pub fn read_variable(&mut self) -> Token {
+        let pos = self.pos;
+        let sigil = self.ch;
+        self.read_char();
+        if sigil == '@' && self.ch == '@' { self.read_char(); }
+
+        if self.is_letter(self.ch) {
+            while self.is_letter(self.ch) || self.is_digit(self.ch) { self.read_char(); }
+        } else if sigil == '$' && self.is_digit(self.ch) {
+            while self.is_digit(self.ch) { self.read_char(); }
+        } else if sigil == '$' && "!@:;,/\\_~$?<>\"&`'+*.=0".contains(self.ch) {
             self.read_char();
+        } else {
+            return Token::new(TokenType::ILLEGAL, self.body[pos..self.pos].to_string());
         }
-        return std::str::from_utf8(&self.body.as_bytes()[pos..self.pos]).unwrap().to_string();
+
+        let literal = self.body[pos..self.pos].to_string();
+        let token_type = match sigil {
+            '$' => TokenType::GVAR,
+            _ if literal.starts_with("@@") => TokenType::CVAR,
+            _ => TokenType::IVAR,
+        };
+        Token::new(token_type, literal)
     }
 
-    pub fn read_number(&mut self) -> String {
+    /// Reads an integer or a float like `1.5`, `2e10` or `1_000.0e-3`. An integer can also
+    /// have a prefix giving its base, as in `0xff`, `0b101`, `0o17` or `0d9`, which is kept in
+    /// the literal for the parser.
+    pub fn read_number(&mut self) -> Token {
         let pos = self.pos;
-        while self.is_digit(self.ch) {
+        let radix = match (self.ch, self.peek_char().to_ascii_lowercase()) {
+            ('0', 'x') => 16,
+            ('0', 'b') => 2,
+            ('0', 'o') => 8,
+            ('0', 'd') => 10,
+            _ => 0,
+        };
+        if radix > 0 && self.peek_char_at(1).is_digit(radix) {
+            self.read_char();
+            self.read_char();
+            while self.ch.is_digit(radix) || (self.ch == '_' && self.peek_char().is_digit(radix)) {
+                self.read_char();
+            }
+            return Token::new(TokenType::INT, self.body[pos..self.pos].replace('_', ""));
+        }
+        self.read_digits();
+        let mut token_type = TokenType::INT;
+        if self.ch == '.' && self.peek_char().is_ascii_digit() {
+            token_type = TokenType::FLOAT;
+            self.read_char();
+            self.read_digits();
+        }
+        let exponent = match (self.peek_char(), self.peek_char_at(1)) {
+            (digit, _) if digit.is_ascii_digit() => true,
+            ('+', digit) | ('-', digit) => digit.is_ascii_digit(),
+            _ => false
+        };
+        if (self.ch == 'e' || self.ch == 'E') && exponent {
+            token_type = TokenType::FLOAT;
+            self.read_char();
+            if self.ch == '+' || self.ch == '-' { self.read_char(); }
+            self.read_digits();
+        }
+
+        Token::new(token_type, self.body[pos..self.pos].replace('_', ""))
+    }
+
+    fn read_digits(&mut self) {
+        while self.is_digit(self.ch) || (self.ch == '_' && self.peek_char().is_ascii_digit()) {
+            self.read_char();
+        }
+    }
+
+    /// Reads a quoted string whose quote is the current character, leaving the lexer on the
+    /// closing quote.
+    pub fn read_string(&mut self, quote: char) -> String {
+        let (bytes, _) = self.read_string_contents(quote, false);
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Reads the contents of a string literal after the current character, which is its quote
+    /// or the `}` ending an interpolation, up to the closing quote, the `#{` of an
+    /// interpolation if `interpolate` is set, or the end of the source. The lexer is left on
+    /// the quote or the `#`. Escapes are read into the bytes they stand for, which needn't be
+    /// valid UTF-8.
+    fn read_string_contents(&mut self, quote: char, interpolate: bool) -> (Vec<u8>, StringEnd) {
+        let mut bytes = vec!();
+        self.read_char();
+        while self.ch != quote && self.pos < self.body.len() {
+            if interpolate && self.ch == '#' && self.peek_char() == '{' { return (bytes, StringEnd::Interpolation); }
+            if self.ch == '\\' {
+                self.read_char();
+                match (quote, self.ch) {
+                    ('"', 'n')  => bytes.push(b'\n'),
+                    ('"', 't')  => bytes.push(b'\t'),
+                    ('"', 'r')  => bytes.push(b'\r'),
+                    ('"', 's')  => bytes.push(b' '),
+                    ('"', 'e')  => bytes.push(0x1b),
+                    ('"', 'a')  => bytes.push(0x07),
+                    ('"', 'b')  => bytes.push(0x08),
+                    ('"', 'v')  => bytes.push(0x0b),
+                    ('"', 'f')  => bytes.push(0x0c),
+                    ('"', '0'..='7') => {
+                        // up to three octal digits, so `"\101"` is `"A"` and `"\0"` a NUL
+                        let mut byte = self.ch.to_digit(8).unwrap_or(0);
+                        for _ in 0..2 {
+                            match self.peek_char().to_digit(8) {
+                                Some(digit) => byte = byte * 8 + digit,
+                                None => break,
+                            }
+                            self.read_char();
+                        }
+                        bytes.push(byte as u8);
+                    },
+                    ('"', 'x') if self.peek_char().is_ascii_hexdigit() => {
+                        let mut byte = 0;
+                        for _ in 0..2 {
+                            match self.peek_char().to_digit(16) {
+                                Some(digit) => byte = byte * 16 + digit as u8,
+                                None => break,
+                            }
+                            self.read_char();
+                        }
+                        bytes.push(byte);
+                    },
+                    ('"', 'u') => {
+                        let braced = self.peek_char() == '{';
+                        if braced { self.read_char(); }
+                        let mut code = 0;
+                        let mut digits = 0;
+                        while let Some(digit) = self.peek_char().to_digit(16).filter(|_| braced || digits < 4) {
+                            code = code * 16 + digit;
+                            digits += 1;
+                            self.read_char();
+                        }
+                        if braced && self.peek_char() == '}' { self.read_char(); }
+                        let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+                        bytes.extend_from_slice(c.to_string().as_bytes());
+                    },
+                    ('"', ch)   => bytes.push(ch as u8),
+                    (_, '\\')  => bytes.push(b'\\'),
+                    (q, ch) if ch == q => bytes.push(ch as u8),
+                    (_, ch)     => { bytes.push(b'\\'); bytes.push(ch as u8) },
+                }
+            } else {
+                bytes.push(self.ch as u8);
+            }
+            self.read_char();
+        }
+        let end = if self.ch == quote { StringEnd::Quote } else { StringEnd::Eof };
+        (bytes, end)
+    }
+
+    /// Makes the token for a double-quoted string's contents read up to `end`: `tt` if they
+    /// reach the closing quote, `begins` if they stop at an interpolation, which is then
+    /// entered, and an illegal token if the source ends first.
+    fn string_token(&mut self, tt: TokenType, begins: TokenType, bytes: Vec<u8>, end: StringEnd) -> Token {
+        match end {
+            StringEnd::Quote => Token::string(tt, bytes),
+            StringEnd::Interpolation => {
+                self.read_char();
+                self.interpolations.push(('"', 0));
+                Token::string(begins, bytes)
+            },
+            // kept with its quote, as a string still open at the end of the source
+            StringEnd::Eof => Token::new(TokenType::ILLEGAL, format!("\"{}", String::from_utf8_lossy(&bytes))),
+        }
+    }
+
+    /// Reads the source of a regexp after the current character, which is its opening `/` or
+    /// the `}` ending an interpolation, up to the closing `/`, the `#{` of an interpolation or
+    /// the end of the source. Escapes are left for the regexp to read, except for `\/`, which
+    /// only stops the literal ending.
+    fn read_regex_contents(&mut self) -> (Vec<u8>, StringEnd) {
+        let mut bytes = vec!();
+        self.read_char();
+        while self.ch != '/' && self.pos < self.body.len() {
+            if self.ch == '#' && self.peek_char() == '{' { return (bytes, StringEnd::Interpolation); }
+            if self.ch == '\\' && self.peek_char() == '/' {
+                self.read_char();
+            } else if self.ch == '\\' {
+                bytes.push(b'\\');
+                self.read_char();
+            }
+            bytes.push(self.ch as u8);
+            self.read_char();
+        }
+        let end = if self.ch == '/' { StringEnd::Quote } else { StringEnd::Eof };
+        (bytes, end)
+    }
+
+    /// Makes the token for the source of a regexp read up to `end`, like `string_token`. The
+    /// token closing the regexp has the flags after it in its literal, separated by a `/`.
+    fn regex_token(&mut self, tt: TokenType, begins: TokenType, bytes: Vec<u8>, end: StringEnd) -> Token {
+        match end {
+            StringEnd::Quote => {
+                let mut token = Token::string(tt, bytes);
+                token.literal.push('/');
+                while self.peek_char().is_ascii_alphabetic() {
+                    self.read_char();
+                    token.literal.push(self.ch);
+                }
+                token
+            },
+            StringEnd::Interpolation => {
+                self.read_char();
+                self.interpolations.push(('/', 0));
+                Token::string(begins, bytes)
+            },
+            StringEnd::Eof => Token::new(TokenType::ILLEGAL, format!("/{}", String::from_utf8_lossy(&bytes))),
+        }
+    }
+
+    /// Whether a `/` here starts a regexp rather than dividing: where an expression starts, or
+    /// after a method name when spaced like an argument, as in `split /,/`. The regexp must
+    /// also close on the same line.
+    fn regex_allowed(&self) -> bool {
+        if !self.closes_on_line(b'/') { return false; }
+        match self.previous {
+            None => true,
+            Some(TokenType::IDENT) => {
+                !self.after_local && self.is_space_at(self.pos.wrapping_sub(1)) && !self.is_space_at(self.pos + 1) && self.peek_char_at(0) != '='
+            },
+            Some(TokenType::CONSTANT | TokenType::IVAR | TokenType::CVAR | TokenType::GVAR | TokenType::INT |
+                 TokenType::FLOAT | TokenType::STRING | TokenType::STRINGEND | TokenType::SYMBOL | TokenType::REGEXP |
+                 TokenType::REGEXPEND | TokenType::RPAREN |
+                 TokenType::RBRACKET | TokenType::RBRACE | TokenType::END | TokenType::SELF | TokenType::TRUE |
+                 TokenType::FALSE | TokenType::NIL) => false,
+            Some(_) => true,
+        }
+    }
+
+    fn closes_on_line(&self, delimiter: u8) -> bool {
+        let mut bytes = self.body.as_bytes()[self.read_pos.min(self.body.len())..].iter();
+        while let Some(&byte) = bytes.next() {
+            match byte {
+                b'\\' => { bytes.next(); },
+                b'\n' => return false,
+                byte if byte == delimiter => return true,
+                _ => (),
+            }
+        }
+        false
+    }
+
+    /// Reads the name of a `:symbol`, which may be an identifier, a variable, an operator or a
+    /// quoted string. Returns `None` if the colon doesn't start a symbol.
+    fn read_symbol(&mut self) -> Option<String> {
+        let next = self.peek_char();
+        if next == '"' || next == '\'' {
             self.read_char();
+            return Some(self.read_string(next));
         }
+        if next.is_ascii_alphabetic() || next == '_' || next == '@' || next == '$' {
+            self.read_char();
+            let pos = self.pos;
+            while self.ch == '@' || self.ch == '$' { self.read_char(); }
+            while self.is_letter(self.ch) || self.is_digit(self.ch) { self.read_char(); }
+            if (self.ch == '?' || self.ch == '!' || self.ch == '=') && !"=~>".contains(self.peek_char()) {
+                self.read_char();
+            }
+            let name = self.body[pos..self.pos].to_string();
+            // Leave the lexer on the last character of the symbol, like every other token
+            self.pos -= 1;
+            self.read_pos -= 1;
+            return Some(name);
+        }
+
+        let operators = ["[]=", "[]", "<=>", "===", "==", "=~", "!=", "!~", "**", "+@", "-@", "<=", ">=",
+                         "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "!", "&", "|", "^", "~"];
+        let rest = &self.body[self.read_pos..];
+        let operator = operators.iter().find(|operator| rest.starts_with(*operator))?;
+        for _ in 0..operator.len() { self.read_char(); }
 
-        return std::str::from_utf8(&self.body.as_bytes()[pos..self.pos]).unwrap().to_string();
+        Some(operator.to_string())
     }
 
     pub fn is_letter(&mut self, ch: char) -> bool {
-        'a' <= ch && ch <= 'z' || 'A' <= ch && ch <= 'Z' || ch == '_' || ch == '!' || ch == '?'
+        ch.is_ascii_alphabetic() || ch == '_'
     }
 
     pub fn is_digit(&mut self, ch: char) -> bool {
-        '0' <= ch && ch <= '9'
+        ch.is_ascii_digit()
     }
 
     pub fn lookup_ident(&mut self, ident: String) -> TokenType {
         match KEYWORDS.get(&ident[..]) {
             Some(tt) => *tt,
+            None if ident.starts_with(|c: char| c.is_ascii_uppercase()) => TokenType::CONSTANT,
             None => TokenType::IDENT
         }
     }
 
     pub fn skip_whitespace(&mut self) {
-        while self.pos_at_whitespace() {
-            self.read_char()
+        loop {
+            if self.pos_at_whitespace() {
+                self.read_char()
+            } else if self.ch == '\\' && self.peek_char() == '\n' {
+                self.read_char();
+                self.read_char();
+            } else if self.ch == '#' {
+                let start = self.pos;
+                let own_line = self.line_is_blank_before(start);
+                while self.ch != '\n' && self.pos < self.body.len() {
+                    self.read_char();
+                }
+                let text = self.body[start..self.pos].trim_end().to_string();
+                self.trivia.push(Trivia::Comment { span: Span::new(start, self.pos), text, own_line });
+            } else {
+                break;
+            }
         }
     }
 
+    /// Whether the line holding the byte offset `pos` has only whitespace before it.
+    fn line_is_blank_before(&self, pos: usize) -> bool {
+        self.body.as_bytes()[..pos].iter().rev()
+            .take_while(|byte| **byte != b'\n')
+            .all(|byte| byte.is_ascii_whitespace())
+    }
+
+    pub fn source(&self) -> &str {
+        &self.body
+    }
+
+    /// The comments and blank lines skipped so far.
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
+    }
+
     fn pos_at_whitespace(&mut self) -> bool {
         self.ch == ' ' || self.ch == '\t'
     }
-}
 
-impl Iterator for Lexer {
-    type Item = Token;
+    /// The span of the token most recently returned by `next`.
+    pub fn span(&self) -> Span {
+        self.span
+    }
 
-    fn next(&mut self) -> Option<Token> {
-        if self.pos == self.body.len() {
-            self.pos += 1;
+    /// Tells the lexer whether the identifier just returned names a local variable, so a `/`
+    /// after it divides instead of starting a regexp, as in `x /2`.
+    pub fn set_after_local(&mut self, after_local: bool) {
+        self.after_local = after_local;
+    }
+
+    /// Whether the source has whitespace (or nothing at all) at the byte offset `pos`.
+    pub fn is_space_at(&self, pos: usize) -> bool {
+        match self.body.as_bytes().get(pos) {
+            Some(byte) => byte.is_ascii_whitespace(),
+            None => true
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        // An unterminated string or regexp can run past the end, which still gets its EOF
+        if self.pos >= self.body.len() {
+            if self.previous == Some(TokenType::EOF) { return None; }
+            self.pos = self.body.len() + 1;
             return Some(Token::new(TokenType::EOF, "\u{0}".to_string()));
         }
-        if self.pos >= self.body.len() { return None; }
 
-        self.skip_whitespace();
+        let starts_regex = self.ch == '/' && self.regex_allowed();
+        if let Some(tok) = self.read_op_assign().filter(|_| !starts_regex) {
+            self.read_char();
+            return Some(tok);
+        }
+        if let Some(tok) = self.read_compound_operator() {
+            self.read_char();
+            return Some(tok);
+        }
 
         let tok = match self.ch {
             '=' => {
                 if self.peek_char() == '=' {
                     self.read_char();
                     Token::new(TokenType::EQ,        "==".to_string())
+                } else if self.peek_char() == '>' {
+                    self.read_char();
+                    Token::new(TokenType::HASHROCKET, "=>".to_string())
                 } else {
                     Token::new(TokenType::ASSIGN,    self.ch.to_string())
                 }
             },
             '+' => Token::new(TokenType::PLUS,      self.ch.to_string()),
-            '-' => Token::new(TokenType::MINUS,     self.ch.to_string()),
+            '-' => {
+                if self.peek_char() == '>' {
+                    self.read_char();
+                    Token::new(TokenType::LAMBDA,    "->".to_string())
+                } else {
+                    Token::new(TokenType::MINUS,     self.ch.to_string())
+                }
+            },
+            '&' => Token::new(TokenType::AMPERSAND, self.ch.to_string()),
             '!' => {
                 if self.peek_char() == '=' {
                     let mut ch = String::from(self.ch);
@@ -114,31 +515,103 @@ impl Iterator for Lexer {
                     Token::new(TokenType::BANG,      self.ch.to_string())
                 }
             }
+            '/' if starts_regex => {
+                let (bytes, end) = self.read_regex_contents();
+                self.regex_token(TokenType::REGEXP, TokenType::REGEXPBEGIN, bytes, end)
+            },
             '/' => Token::new(TokenType::SLASH,     self.ch.to_string()),
+            '%' => Token::new(TokenType::PERCENT,   self.ch.to_string()),
+            '^' => Token::new(TokenType::CARET,     self.ch.to_string()),
+            '~' => Token::new(TokenType::TILDE,     self.ch.to_string()),
+            '?' => Token::new(TokenType::QUESTION,  self.ch.to_string()),
             '*' => Token::new(TokenType::ASTERISK,  self.ch.to_string()),
             '<' => Token::new(TokenType::LT,        self.ch.to_string()),
             '>' => Token::new(TokenType::GT,        self.ch.to_string()),
             ';' => Token::new(TokenType::SEMICOLON, self.ch.to_string()),
             ',' => Token::new(TokenType::COMMA,     self.ch.to_string()),
+            '.' => Token::new(TokenType::DOT,       self.ch.to_string()),
+            '|' => Token::new(TokenType::PIPE,      self.ch.to_string()),
+            ':' => {
+                if self.peek_char() == ':' {
+                    self.read_char();
+                    Token::new(TokenType::COLONCOLON, "::".to_string())
+                } else if let Some(name) = self.read_symbol() {
+                    Token::new(TokenType::SYMBOL,    name)
+                } else {
+                    Token::new(TokenType::COLON,     self.ch.to_string())
+                }
+            },
             '(' => Token::new(TokenType::LPAREN,    self.ch.to_string()),
             ')' => Token::new(TokenType::RPAREN,    self.ch.to_string()),
-            '{' => Token::new(TokenType::LBRACE,    self.ch.to_string()),
-            '}' => Token::new(TokenType::RBRACE,    self.ch.to_string()),
-            '\n' => Token::new(TokenType::NEWLINE,  self.ch.to_string()),
+            '{' => {
+                if let Some((_, depth)) = self.interpolations.last_mut() { *depth += 1; }
+                Token::new(TokenType::LBRACE,    self.ch.to_string())
+            },
+            // the `}` closing an interpolation carries on with the string or regexp
+            '}' if self.interpolations.last() == Some(&('"', 0)) => {
+                self.interpolations.pop();
+                let (bytes, end) = self.read_string_contents('"', true);
+                self.string_token(TokenType::STRINGEND, TokenType::STRINGMID, bytes, end)
+            },
+            '}' if self.interpolations.last() == Some(&('/', 0)) => {
+                self.interpolations.pop();
+                let (bytes, end) = self.read_regex_contents();
+                self.regex_token(TokenType::REGEXPEND, TokenType::STRINGMID, bytes, end)
+            },
+            '}' => {
+                if let Some((_, depth)) = self.interpolations.last_mut() { *depth -= 1; }
+                Token::new(TokenType::RBRACE,    self.ch.to_string())
+            },
+            '[' => Token::new(TokenType::LBRACKET,  self.ch.to_string()),
+            ']' => Token::new(TokenType::RBRACKET,  self.ch.to_string()),
+            '@' | '$' => return Some(self.read_variable()),
+            '"' => {
+                let (bytes, end) = self.read_string_contents('"', true);
+                self.string_token(TokenType::STRING, TokenType::STRINGBEGIN, bytes, end)
+            },
+            '\'' => {
+                let (bytes, end) = self.read_string_contents('\'', false);
+                // a string still open at the end of the source is kept with its quote
+                if end == StringEnd::Eof { return Some(Token::new(TokenType::ILLEGAL, format!("'{}", String::from_utf8_lossy(&bytes)))); }
+                Token::string(TokenType::STRING, bytes)
+            },
+            '\n' => {
+                if self.line_is_blank_before(self.pos) { self.trivia.push(Trivia::BlankLine(self.pos)); }
+                Token::new(TokenType::NEWLINE,  self.ch.to_string())
+            },
             '\r' => Token::new(TokenType::NEWLINE,  self.ch.to_string()),
             _ => {
                 if self.is_letter(self.ch) {
                     let literal = self.read_identifier();
+                    // `name: value` in a hash or argument list, but not `Name::Const`
+                    if self.ch == ':' && self.peek_char() != ':' && !literal.ends_with('?') {
+                        self.read_char();
+                        return Some(Token::new(TokenType::LABEL, literal));
+                    }
                     return Some(Token::new(self.lookup_ident(literal.clone()), literal));
                 } else if self.is_digit(self.ch) {
-                    return Some(Token::new(TokenType::INT, self.read_number()));
+                    return Some(self.read_number());
                 } else {
                     Token::new(TokenType::ILLEGAL, self.ch.to_string())
                 }
             }
         };
         self.read_char();
-        return Some(tok);
+        Some(tok)
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.skip_whitespace();
+        let start = self.pos.min(self.body.len());
+        let token = self.next_token();
+        self.span = Span::new(start, self.pos.min(self.body.len()));
+        self.previous = token.as_ref().map(|token| token.token_type);
+
+        token
     }
 }
 
@@ -175,6 +648,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::single_match)]
     fn it_can_parse_multiple_lines() {
         let input = "five = 5
 ten = 10
@@ -341,4 +815,179 @@ if (5 < 10) {
             }
         }
     }
+
+    #[test]
+    fn it_can_lex_exception_handling() {
+        let input = "begin # try it
+  raise Net::Error, \"can't \\\"connect\\\"\"
+rescue Timeout, IOError => e
+  retry if ok?
+else
+  'done'
+ensure
+  nil
+end";
+
+        let expected_tokens = vec![
+            Token::new(TokenType::BEGIN,      "begin".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::IDENT,      "raise".to_string()),
+            Token::new(TokenType::CONSTANT,   "Net".to_string()),
+            Token::new(TokenType::COLONCOLON, "::".to_string()),
+            Token::new(TokenType::CONSTANT,   "Error".to_string()),
+            Token::new(TokenType::COMMA,      ",".to_string()),
+            Token::new(TokenType::STRING,     "can't \"connect\"".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::RESCUE,     "rescue".to_string()),
+            Token::new(TokenType::CONSTANT,   "Timeout".to_string()),
+            Token::new(TokenType::COMMA,      ",".to_string()),
+            Token::new(TokenType::CONSTANT,   "IOError".to_string()),
+            Token::new(TokenType::HASHROCKET, "=>".to_string()),
+            Token::new(TokenType::IDENT,      "e".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::RETRY,      "retry".to_string()),
+            Token::new(TokenType::IF,         "if".to_string()),
+            Token::new(TokenType::IDENT,      "ok?".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::ELSE,       "else".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::STRING,     "done".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::ENSURE,     "ensure".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::NIL,        "nil".to_string()),
+            Token::new(TokenType::NEWLINE,    "\n".to_string()),
+            Token::new(TokenType::END,        "end".to_string()),
+            Token::new(TokenType::EOF,        "\u{0}".to_string()),
+        ];
+
+        let mut lexer = Lexer::new(input.to_string());
+        for t in expected_tokens {
+            let tok = lexer.next().unwrap_or_else(|| panic!("expected {:?}, got None", t));
+            assert_eq!(tok.token_type, t.token_type);
+            assert_eq!(tok.literal, t.literal);
+        }
+    }
+
+    #[test]
+    fn it_can_lex_op_assign() {
+        let input = "a += 1; b -= c ||= d &&= e *= f /= 2";
+
+        let expected_tokens = vec![
+            (TokenType::IDENT, "a"), (TokenType::OPASSIGN, "+="), (TokenType::INT, "1"),
+            (TokenType::SEMICOLON, ";"), (TokenType::IDENT, "b"), (TokenType::OPASSIGN, "-="),
+            (TokenType::IDENT, "c"), (TokenType::OPASSIGN, "||="), (TokenType::IDENT, "d"),
+            (TokenType::OPASSIGN, "&&="), (TokenType::IDENT, "e"), (TokenType::OPASSIGN, "*="),
+            (TokenType::IDENT, "f"), (TokenType::OPASSIGN, "/="), (TokenType::INT, "2"),
+            (TokenType::EOF, "\u{0}"),
+        ];
+
+        let mut lexer = Lexer::new(input.to_string());
+        for (token_type, literal) in expected_tokens {
+            let tok = lexer.next().unwrap();
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn it_can_lex_string_bytes_and_interpolation() {
+        let input = "\"\\xff\\101\" \"a#{ {b: 1}[:b] }c#{\"}\"}\" / 2";
+
+        let expected_tokens = vec![
+            (TokenType::STRING, vec!(0xff, b'A')), (TokenType::STRINGBEGIN, b"a".to_vec()),
+            (TokenType::LBRACE, vec!()), (TokenType::LABEL, vec!()), (TokenType::INT, vec!()),
+            (TokenType::RBRACE, vec!()), (TokenType::LBRACKET, vec!()), (TokenType::SYMBOL, vec!()),
+            (TokenType::RBRACKET, vec!()), (TokenType::STRINGMID, b"c".to_vec()), (TokenType::STRING, b"}".to_vec()),
+            (TokenType::STRINGEND, vec!()), (TokenType::SLASH, vec!()), (TokenType::INT, vec!()),
+            (TokenType::EOF, vec!()),
+        ];
+
+        let mut lexer = Lexer::new(input.to_string());
+        for (token_type, bytes) in expected_tokens {
+            let tok = lexer.next().unwrap();
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.bytes, bytes);
+        }
+    }
+
+    #[test]
+    fn it_can_lex_interpolated_regexps() {
+        let input = "/a#{n}b#{ {}.size }/i";
+
+        let expected_tokens = vec![
+            (TokenType::REGEXPBEGIN, "a"), (TokenType::IDENT, "n"), (TokenType::STRINGMID, "b"),
+            (TokenType::LBRACE, "{"), (TokenType::RBRACE, "}"), (TokenType::DOT, "."), (TokenType::IDENT, "size"),
+            (TokenType::REGEXPEND, "/i"), (TokenType::EOF, "\u{0}"),
+        ];
+
+        let mut lexer = Lexer::new(input.to_string());
+        for (token_type, literal) in expected_tokens {
+            let tok = lexer.next().unwrap();
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn it_can_lex_lambdas_and_symbols() {
+        let input = "->(x) { x }; map(&:to_s); :ok? :<=> :\"with space\" :@name b : c";
+
+        let expected_tokens = vec![
+            (TokenType::LAMBDA, "->"), (TokenType::LPAREN, "("), (TokenType::IDENT, "x"),
+            (TokenType::RPAREN, ")"), (TokenType::LBRACE, "{"), (TokenType::IDENT, "x"),
+            (TokenType::RBRACE, "}"), (TokenType::SEMICOLON, ";"), (TokenType::IDENT, "map"),
+            (TokenType::LPAREN, "("), (TokenType::AMPERSAND, "&"), (TokenType::SYMBOL, "to_s"),
+            (TokenType::RPAREN, ")"), (TokenType::SEMICOLON, ";"), (TokenType::SYMBOL, "ok?"),
+            (TokenType::SYMBOL, "<=>"), (TokenType::SYMBOL, "with space"), (TokenType::SYMBOL, "@name"),
+            (TokenType::IDENT, "b"), (TokenType::COLON, ":"), (TokenType::IDENT, "c"), (TokenType::EOF, "\u{0}"),
+        ];
+
+        let mut lexer = Lexer::new(input.to_string());
+        for (token_type, literal) in expected_tokens {
+            let tok = lexer.next().unwrap();
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn it_can_lex_operators_floats_and_labels() {
+        let input = "a <=> b === c ** 2 && x || y .. z ... w
+1.5e3 2e-2 3.0 1..2 f(key: v) n %= 3 m **= 2 p <<= 1 q != r !~ s >= t <= u ^ ~v ? 1 : 2
+while until unless elsif case when break next self and or not";
+        let types: Vec<TokenType> = Lexer::new(input.to_string()).map(|token| token.token_type).collect();
+
+        assert_eq!(types, vec!(
+            TokenType::IDENT, TokenType::SPACESHIP, TokenType::IDENT, TokenType::EQQ, TokenType::IDENT,
+            TokenType::POW, TokenType::INT, TokenType::ANDAND, TokenType::IDENT, TokenType::OROR,
+            TokenType::IDENT, TokenType::DOT2, TokenType::IDENT, TokenType::DOT3, TokenType::IDENT,
+            TokenType::NEWLINE,
+            TokenType::FLOAT, TokenType::FLOAT, TokenType::FLOAT, TokenType::INT, TokenType::DOT2,
+            TokenType::INT, TokenType::IDENT, TokenType::LPAREN, TokenType::LABEL, TokenType::IDENT,
+            TokenType::RPAREN, TokenType::IDENT, TokenType::OPASSIGN, TokenType::INT, TokenType::IDENT,
+            TokenType::OPASSIGN, TokenType::INT, TokenType::IDENT, TokenType::OPASSIGN, TokenType::INT,
+            TokenType::IDENT, TokenType::NOTEQ, TokenType::IDENT, TokenType::NMATCH, TokenType::IDENT,
+            TokenType::GTE, TokenType::IDENT, TokenType::LTE, TokenType::IDENT, TokenType::CARET,
+            TokenType::TILDE, TokenType::IDENT, TokenType::QUESTION, TokenType::INT, TokenType::COLON,
+            TokenType::INT, TokenType::NEWLINE,
+            TokenType::WHILE, TokenType::UNTIL, TokenType::UNLESS, TokenType::ELSIF, TokenType::CASE,
+            TokenType::WHEN, TokenType::BREAK, TokenType::NEXT, TokenType::SELF, TokenType::AND,
+            TokenType::OR, TokenType::NOT, TokenType::EOF,
+        ));
+    }
+
+    #[test]
+    fn it_keeps_comments_and_blank_lines_as_trivia() {
+        let input = "# header\nx = 1 # one\n\n  \ny = 2";
+        let mut lexer = Lexer::new(input.to_string());
+        while lexer.next().is_some() {}
+
+        assert_eq!(lexer.trivia(), &[
+            Trivia::Comment { span: Span::new(0, 8), text: "# header".to_string(), own_line: true },
+            Trivia::Comment { span: Span::new(15, 20), text: "# one".to_string(), own_line: false },
+            Trivia::BlankLine(21),
+            Trivia::BlankLine(24),
+        ]);
+    }
 }
@@ -4,17 +4,70 @@ use phf::phf_map;
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    /// The contents of a string literal, which needn't be valid UTF-8. Empty for other tokens.
+    pub bytes: Vec<u8>,
 }
 
 impl Token {
     pub fn new(tt: TokenType, lit: String) -> Self {
         Self {
             token_type: tt,
-            literal:    lit
+            literal:    lit,
+            bytes:      vec!(),
+        }
+    }
+
+    /// A string literal, or a piece of one, whose literal is its contents as text.
+    pub fn string(tt: TokenType, bytes: Vec<u8>) -> Self {
+        Self {
+            token_type: tt,
+            literal:    String::from_utf8_lossy(&bytes).into_owned(),
+            bytes,
+        }
+    }
+}
+
+/// Byte offsets `start..end` of a token in the source.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end:   usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The 1-based line and column where the span starts in `source`.
+    pub fn line_and_column(&self, source: &str) -> (usize, usize) {
+        let before = &source.as_bytes()[..self.start.min(source.len())];
+        let line = before.iter().filter(|byte| **byte == b'\n').count() + 1;
+        let line_start = before.iter().rposition(|byte| *byte == b'\n').map_or(0, |pos| pos + 1);
+
+        (line, self.start - line_start + 1)
+    }
+}
+
+/// Source text the lexer skips but tools such as the formatter need to put back.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trivia {
+    /// A `# comment`, and whether it sits on a line of its own rather than after code
+    Comment { span: Span, text: String, own_line: bool },
+    /// The byte offset of the newline ending a line with nothing but whitespace on it
+    BlankLine(usize),
+}
+
+impl Trivia {
+    pub fn start(&self) -> usize {
+        match self {
+            Trivia::Comment { span, .. } => span.start,
+            Trivia::BlankLine(pos) => *pos,
         }
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, Debug)]
 #[derive(std::cmp::PartialEq)]
 pub enum TokenType {
@@ -23,27 +76,74 @@ pub enum TokenType {
 
     // Identifiers and literals
     IDENT,
+    CONSTANT,
+    IVAR,
+    CVAR,
+    GVAR,
     INT,
+    FLOAT,
+    STRING,
+    /// `"text#{`, the start of a string with interpolated code
+    STRINGBEGIN,
+    /// `}text#{`, between two interpolations
+    STRINGMID,
+    /// `}text"`, the end of a string with interpolated code
+    STRINGEND,
+    SYMBOL,
+    /// A `/regexp/flags` literal, as the source and flags separated by its last `/`
+    REGEXP,
+    /// `/source#{`, the start of a regexp with interpolated code, continued like a string
+    REGEXPBEGIN,
+    /// `}source/flags`, the end of a regexp with interpolated code, with its flags like `REGEXP`
+    REGEXPEND,
+    LABEL,
 
     // Operators
     ASSIGN,
+    OPASSIGN,
     PLUS,
     MINUS,
     BANG,
     ASTERISK,
     SLASH,
+    PERCENT,
+    POW,
     LT,
     GT,
+    LTE,
+    GTE,
+    SPACESHIP,
+    EQQ,
+    MATCH,
+    NMATCH,
+    LSHIFT,
+    RSHIFT,
+    ANDAND,
+    OROR,
+    CARET,
+    TILDE,
+    DOT2,
+    DOT3,
+    QUESTION,
+    HASHROCKET,
+    LAMBDA,
+    AMPERSAND,
 
     // Delimiters
     COMMA,
     SEMICOLON,
+    DOT,
+    COLON,
+    COLONCOLON,
+    PIPE,
 
     // Groupings
     LPAREN,
     RPAREN,
     LBRACE,
     RBRACE,
+    LBRACKET,
+    RBRACKET,
     NEWLINE,
 
     // Keywords
@@ -52,11 +152,33 @@ pub enum TokenType {
     DO,
     TRUE,
     FALSE,
+    NIL,
     IF,
     ELSE,
     RETURN,
     EQ,
     NOTEQ,
+    BEGIN,
+    RESCUE,
+    ENSURE,
+    RETRY,
+    THEN,
+    ELSIF,
+    UNLESS,
+    WHILE,
+    UNTIL,
+    CASE,
+    WHEN,
+    BREAK,
+    NEXT,
+    SELF,
+    AND,
+    OR,
+    NOT,
+    CLASS,
+    MODULE,
+    SUPER,
+    YIELD,
 }
 
 pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
@@ -65,7 +187,29 @@ pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "do"     => TokenType::DO,
     "true"   => TokenType::TRUE,
     "false"  => TokenType::FALSE,
+    "nil"    => TokenType::NIL,
     "if"     => TokenType::IF,
     "else"   => TokenType::ELSE,
     "return" => TokenType::RETURN,
+    "begin"  => TokenType::BEGIN,
+    "rescue" => TokenType::RESCUE,
+    "ensure" => TokenType::ENSURE,
+    "retry"  => TokenType::RETRY,
+    "then"   => TokenType::THEN,
+    "elsif"  => TokenType::ELSIF,
+    "unless" => TokenType::UNLESS,
+    "while"  => TokenType::WHILE,
+    "until"  => TokenType::UNTIL,
+    "case"   => TokenType::CASE,
+    "when"   => TokenType::WHEN,
+    "break"  => TokenType::BREAK,
+    "next"   => TokenType::NEXT,
+    "self"   => TokenType::SELF,
+    "and"    => TokenType::AND,
+    "or"     => TokenType::OR,
+    "not"    => TokenType::NOT,
+    "class"  => TokenType::CLASS,
+    "module" => TokenType::MODULE,
+    "super"  => TokenType::SUPER,
+    "yield"  => TokenType::YIELD,
 };
@@ -1,4 +1,10 @@
-mod lexer;
+pub mod ast;
+pub mod eval;
+pub mod formatter;
+pub mod lexer;
+pub mod parser;
+pub mod program;
+pub mod repl;
 
 #[cfg(test)]
 mod tests {
@@ -8,7 +14,8 @@ mod tests {
     fn it_can_build_a_token() {
         let token = Token {
             token_type: TokenType::ILLEGAL,
-            literal:    "?".to_string()
+            literal:    "?".to_string(),
+            bytes:      vec!(),
         };
 
         assert_eq!(token.token_type, TokenType::ILLEGAL);
@@ -1,30 +1,293 @@
+use std::env;
+use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::{ IsTerminal, Read };
+use std::process;
+use std::thread;
 
-mod ast;
-mod lexer;
-mod parser;
-mod program;
+use rri::eval::{ Control, Interpreter };
+use rri::eval::value::Value;
+use rri::formatter::{ format_source, FormatError };
+use rri::lexer::Lexer;
+use rri::parser::Parser;
+use rri::repl::{ self, Repl };
 
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-
-const PROMPT: &str = ">> ";
+/// The stack the interpreter runs on. Evaluating the tree recurses deeply, so this is much
+/// bigger than the main thread's to let Ruby code recurse as far as `SystemStackError`
+/// allows.
+const STACK_SIZE: usize = 1024 * 1024 * 1024;
 
 fn main() {
-    println!("Welcome to Rust's Ruby Interpreter");
-    let mut running = true;
-    while running {
-        print!("{}", PROMPT);
-        io::stdout().flush();
-        let mut line = String::new();
-        std::io::stdin().read_line(&mut line).unwrap();
-        let lex = Lexer::new(line);
-        for tok in lex {
-            println!("{:?}:{}", tok.token_type, tok.literal);
-            if tok.literal == "exit".to_string() { running = false };
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("fmt") => process::exit(fmt(&args[1..])),
+        Some(arg) if arg.starts_with("--dump-ast") => process::exit(dump_ast(&args)),
+        _ => {},
+    }
+
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rri: {}", message);
+            process::exit(1);
+        },
+    };
+    let (name, source) = match options.program() {
+        Some(program) => program,
+        None => process::exit(on_big_stack(move || {
+            let mut repl = Repl::new();
+            options.configure(repl.interpreter(), "irb");
+            repl::run(repl)
+        })),
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("rri: {}: {}", name, error);
+            process::exit(1);
+        },
+    };
+
+    process::exit(on_big_stack(move || run(&name, &source, &options)));
+}
+
+/// Runs the interpreter on a thread with a `STACK_SIZE` stack, returning its exit status.
+fn on_big_stack(f: impl FnOnce() -> i32 + Send + 'static) -> i32 {
+    let runner = thread::Builder::new().stack_size(STACK_SIZE).spawn(f);
+    match runner.map(|runner| runner.join()) {
+        Ok(Ok(status)) => status,
+        Ok(Err(_)) => 1,
+        Err(error) => {
+            eprintln!("rri: {}", error);
+            1
+        },
+    }
+}
+
+/// What the command line asks for:
+/// `rri [-c] [-w] [-I DIR]... [-e CODE]... [FILE | -] [ARGUMENT]...`
+#[derive(Default)]
+struct Options {
+    /// The `-e` snippets, one line each
+    code:      Vec<String>,
+    file:      Option<String>,
+    arguments: Vec<String>,
+    load_path: Vec<String>,
+    check:     bool,
+    warnings:  bool,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--" => break,
+                "-c" => options.check = true,
+                "-w" => options.warnings = true,
+                "-e" | "-I" => {
+                    let value = args.next().ok_or_else(|| match arg.as_str() {
+                        "-e" => String::from("no code specified for -e"),
+                        _ => String::from("no directory specified for -I"),
+                    })?;
+                    options.option_value(arg, value.clone());
+                },
+                option if option.starts_with("-e") || option.starts_with("-I") => options.option_value(&option[..2], option[2..].to_string()),
+                "-" => {
+                    options.file = Some(arg.clone());
+                    break;
+                },
+                option if option.starts_with('-') => return Err(format!("invalid option {}", option)),
+                file => {
+                    options.file = Some(file.to_string());
+                    break;
+                },
+            }
+        }
+
+        let rest: Vec<String> = args.cloned().collect();
+        match (&options.file, options.code.is_empty()) {
+            (None, true) => if let Some((file, arguments)) = rest.split_first() {
+                options.file = Some(file.clone());
+                options.arguments = arguments.to_vec();
+            },
+            (Some(file), false) => {
+                options.arguments = vec!(file.clone());
+                options.arguments.extend(rest);
+                options.file = None;
+            },
+            _ => options.arguments = rest,
         }
+        Ok(options)
+    }
+
+    /// Sets up the load path, `ARGV`, `$0` and `$VERBOSE` as the options ask.
+    fn configure(&self, interpreter: &mut Interpreter, program: &str) {
+        interpreter.prepend_load_path(&self.load_path);
+        interpreter.set_arguments(program, &self.arguments);
+        if self.warnings { interpreter.set_global("$VERBOSE", Value::Bool(true)); }
+    }
+
+    fn option_value(&mut self, option: &str, value: String) {
+        if option == "-e" {
+            self.code.push(value);
+        } else {
+            self.load_path.push(fs::canonicalize(&value).map_or(value, |path| path.to_string_lossy().into_owned()));
+        }
+    }
+
+    /// The name and source of the program to run: the `-e` code, the file, or standard input
+    /// when it's `-` or when nothing was given and stdin isn't a terminal. None means the REPL.
+    fn program(&self) -> Option<(String, io::Result<String>)> {
+        if !self.code.is_empty() { return Some((String::from("-e"), Ok(self.code.join("\n")))); }
+
+        match self.file.as_deref() {
+            Some("-") => Some((String::from("-"), read_stdin())),
+            Some(file) => Some((file.to_string(), fs::read_to_string(file))),
+            None if !io::stdin().is_terminal() => Some((String::from("-"), read_stdin())),
+            None => None,
+        }
+    }
+}
+
+fn read_stdin() -> io::Result<String> {
+    let mut source = String::new();
+    io::stdin().read_to_string(&mut source)?;
+    Ok(source)
+}
+
+/// Runs a program, or with `-c` only checks that it parses. An exception nobody rescues is
+/// reported on stderr and makes the exit status 1; `exit` and `abort` choose their own.
+fn run(name: &str, source: &str, options: &Options) -> i32 {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut parser = Parser::new(&mut lexer);
+    let (mut program, errors) = parser.parse_program();
+    if !errors.is_empty() {
+        for error in errors.iter() {
+            eprintln!("{}:{}", name, error.describe(source));
+        }
+        return 1;
+    }
+    if options.check {
+        println!("Syntax OK");
+        return 0;
+    }
+    program.ast.set_file(name);
+
+    let mut interpreter = Interpreter::new();
+    options.configure(&mut interpreter, name);
+    let status = match interpreter.eval_program(program) {
+        Ok(_) => 0,
+        Err(Control::Raise(exception)) => interpreter.exit_status(&exception).unwrap_or_else(|| {
+            let _ = interpreter.flush_output();
+            eprintln!("{}", interpreter.report(&exception));
+            1
+        }),
+        Err(_) => unreachable!("eval_program only lets exceptions escape"),
+    };
+    let _ = interpreter.flush_output();
+
+    status
+}
+
+/// `rri fmt [--check] [FILE...]` rewrites each file in place, or formats stdin to stdout when
+/// no files are given. With `--check` nothing is written; the files that would change are
+/// listed and the exit status is 1.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            eprintln!("rri fmt: {}", error);
+            return 1;
+        }
+        return match format_source(&source) {
+            Ok(formatted) if check => i32::from(formatted != source),
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            },
+            Err(error) => report_format_error("-", &source, &error),
+        };
+    }
+
+    let mut status = 0;
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("rri fmt: {}: {}", file, error);
+                status = 1;
+                continue;
+            }
+        };
+        match format_source(&source) {
+            Ok(formatted) if formatted == source => (),
+            Ok(_) if check => {
+                println!("{}", file);
+                status = 1;
+            },
+            Ok(formatted) => {
+                if let Err(error) = fs::write(file, formatted) {
+                    eprintln!("rri fmt: {}: {}", file, error);
+                    status = 1;
+                }
+            },
+            Err(error) => status = report_format_error(file, &source, &error),
+        }
+    }
+
+    status
+}
+
+/// `rri --dump-ast=sexp|json [FILE]` prints the syntax tree of a file, or of stdin.
+fn dump_ast(args: &[String]) -> i32 {
+    let format = args[0].trim_start_matches("--dump-ast").trim_start_matches('=');
+    if format != "sexp" && format != "json" {
+        eprintln!("rri: --dump-ast expects sexp or json, got `{}`", format);
+        return 1;
+    }
+    let file = args.get(1).map_or("-", String::as_str);
+    let source = if file == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(file)
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("rri: {}: {}", file, error);
+            return 1;
+        }
+    };
+
+    let mut lexer = Lexer::new(source.clone());
+    let mut parser = Parser::new(&mut lexer);
+    let (program, errors) = parser.parse_program();
+    if !errors.is_empty() {
+        for error in errors.iter() {
+            eprintln!("{}:{}", file, error.describe(&source));
+        }
+        return 1;
+    }
+    if format == "sexp" { println!("{}", program.to_sexp()); } else { println!("{}", program.to_json()); }
+
+    0
+}
+
+fn report_format_error(file: &str, source: &str, error: &FormatError) -> i32 {
+    match error {
+        FormatError::Parse(errors) => {
+            for error in errors.iter() {
+                eprintln!("{}:{}", file, error.describe(source));
+            }
+        },
+        FormatError::Unstable(_) => eprintln!("rri fmt: {}: {}", file, error),
     }
 
-    println!("Goodbye!");
+    1
 }
@@ -1,14 +1,151 @@
+mod error;
+
+use std::collections::HashSet;
+use std::num::IntErrorKind;
+
 use crate::lexer::Lexer;
-use crate::lexer::token::{ Token, TokenType };
-use crate::ast::node::Node;
-use crate::ast::expressions::Expression;
+use crate::lexer::token::{ Token, TokenType, Span };
+use crate::ast::node::{ Ast, NodeId, NodeKind, AssignTarget, Call, Case, Parameters, BodyStatement, RescueClause, WhenClause };
 use crate::program::Program;
 
+pub use error::ParseError;
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub(crate) enum Precedence {
+    Lowest,
+    Modifier,       // x if y
+    AndOr,          // x and y
+    Not,            // not x
+    Assignment,     // x = y
+    RescueModifier, // x rescue y
+    Ternary,        // x ? y : z
+    Range,          // x..y
+    OrOr,           // ||
+    AndAnd,         // &&
+    Equals,         // == or <=>
+    LessGreater,    // > or <
+    BitOr,          // | or ^
+    BitAnd,         // &
+    Shift,          // << or >>
+    Sum,            // +
+    Product,        // *
+    UnaryMinus,     // -x
+    Pow,            // **
+    Prefix,         // !x or ~x
+    Call,           // x.y or X::Y
+}
+
+/// Whether a `# frozen_string_literal: true` magic comment comes before the first line of
+/// code.
+fn frozen_string_literal(source: &str) -> bool {
+    let mut frozen = false;
+    for line in source.lines().map(str::trim).take_while(|line| line.is_empty() || line.starts_with('#')) {
+        let comment = line.trim_start_matches('#').trim().trim_matches(|c| c == '-' || c == '*').trim();
+        if let Some((name, value)) = comment.split_once(':') {
+            if name.trim().replace('-', "_").eq_ignore_ascii_case("frozen_string_literal") {
+                frozen = value.trim().eq_ignore_ascii_case("true");
+            }
+        }
+    }
+    frozen
+}
+
+/// Writes the digits of a number in `radix` in decimal, however many there are.
+fn to_decimal(digits: &str, radix: u32) -> String {
+    // decimal digits, least significant first
+    let mut decimal: Vec<u32> = vec!(0);
+    for digit in digits.chars().filter_map(|c| c.to_digit(radix)) {
+        let mut carry = digit;
+        for place in decimal.iter_mut() {
+            let value = *place * radix + carry;
+            *place = value % 10;
+            carry = value / 10;
+        }
+        while carry > 0 {
+            decimal.push(carry % 10);
+            carry /= 10;
+        }
+    }
+    decimal.iter().rev().filter_map(|digit| char::from_digit(*digit, 10)).collect()
+}
+
+fn precedence_of(token_type: TokenType) -> Precedence {
+    match token_type {
+        TokenType::IF         => Precedence::Modifier,
+        TokenType::UNLESS     => Precedence::Modifier,
+        TokenType::WHILE      => Precedence::Modifier,
+        TokenType::UNTIL      => Precedence::Modifier,
+        TokenType::AND        => Precedence::AndOr,
+        TokenType::OR         => Precedence::AndOr,
+        TokenType::ASSIGN     => Precedence::Assignment,
+        TokenType::OPASSIGN   => Precedence::Assignment,
+        TokenType::RESCUE     => Precedence::RescueModifier,
+        TokenType::QUESTION   => Precedence::Ternary,
+        TokenType::DOT2       => Precedence::Range,
+        TokenType::DOT3       => Precedence::Range,
+        TokenType::OROR       => Precedence::OrOr,
+        TokenType::ANDAND     => Precedence::AndAnd,
+        TokenType::EQ         => Precedence::Equals,
+        TokenType::NOTEQ      => Precedence::Equals,
+        TokenType::EQQ        => Precedence::Equals,
+        TokenType::SPACESHIP  => Precedence::Equals,
+        TokenType::MATCH      => Precedence::Equals,
+        TokenType::NMATCH     => Precedence::Equals,
+        TokenType::LT         => Precedence::LessGreater,
+        TokenType::GT         => Precedence::LessGreater,
+        TokenType::LTE        => Precedence::LessGreater,
+        TokenType::GTE        => Precedence::LessGreater,
+        TokenType::PIPE       => Precedence::BitOr,
+        TokenType::CARET      => Precedence::BitOr,
+        TokenType::AMPERSAND  => Precedence::BitAnd,
+        TokenType::LSHIFT     => Precedence::Shift,
+        TokenType::RSHIFT     => Precedence::Shift,
+        TokenType::PLUS       => Precedence::Sum,
+        TokenType::MINUS      => Precedence::Sum,
+        TokenType::ASTERISK   => Precedence::Product,
+        TokenType::SLASH      => Precedence::Product,
+        TokenType::PERCENT    => Precedence::Product,
+        TokenType::POW        => Precedence::Pow,
+        TokenType::DOT        => Precedence::Call,
+        TokenType::COLONCOLON => Precedence::Call,
+        TokenType::LBRACKET   => Precedence::Call,
+        _                     => Precedence::Lowest,
+    }
+}
+
+/// An element of an argument or array list: a value, or a `key => value` / `key: value` pair
+/// that is gathered with its neighbours into a trailing hash.
+enum ListItem {
+    Value(NodeId),
+    Pair(NodeId, NodeId),
+}
+
+/// The local variables visible at some point in the source. Blocks can see the locals of their
+/// enclosing scope; method bodies cannot.
+struct LocalScope {
+    names:           HashSet<String>,
+    inherits:        bool,
+    explicit_params: bool,
+    numbered:        usize,
+    uses_it:         bool,
+}
+
+impl LocalScope {
+    fn new(inherits: bool) -> Self {
+        Self { names: HashSet::new(), inherits, explicit_params: false, numbered: 0, uses_it: false }
+    }
+}
+
 pub struct Parser<'a> {
     lexer: &'a mut Lexer,
     current_token: Token,
+    current_span: Span,
     peek_token: Option<Token>,
-    errors: Vec<String>,
+    peek_span: Span,
+    errors: Vec<ParseError>,
+    no_do_block: bool,
+    scopes: Vec<LocalScope>,
+    ast: Ast,
 }
 
 impl<'a> Parser<'a> {
@@ -17,133 +154,1557 @@ impl<'a> Parser<'a> {
             Some(token) => token,
             None => Token::new(TokenType::EOF, "\u{0}".to_string())
         };
+        let current_span = lexer.span();
         let peek = lexer.next();
+        let peek_span = lexer.span();
         Self {
-            lexer: lexer,
+            lexer,
             current_token: current,
+            current_span,
             peek_token: peek,
+            peek_span,
             errors: vec!(),
+            no_do_block: false,
+            scopes: vec!(LocalScope::new(false)),
+            ast: Ast::new(),
+        }
+    }
+
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+
+    /// Records an error at the current token.
+    fn error(&mut self, message: String, expected: &[TokenType]) {
+        self.errors.push(ParseError::new(message, self.current_span, expected.to_vec()));
+    }
+
+    fn peek_error(&mut self, expected_type: TokenType) {
+        let msg = match self.peek() {
+            Some(token) => format!("expected next token to be {:?}, got {:?} instead", expected_type, token.token_type),
+            None => format!("expected next token to be {:?}, got EOF instead", expected_type)
+        };
+
+        self.errors.push(ParseError::new(msg, self.peek_span, vec!(expected_type)));
+    }
+
+    fn no_prefix_parse_error(&mut self) {
+        let current = self.current();
+        if current.token_type == TokenType::ILLEGAL && current.literal.starts_with(['"', '\'']) {
+            return self.error(String::from("unterminated string meets end of file"), &[]);
+        }
+        if current.token_type == TokenType::ILLEGAL && current.literal.starts_with('/') {
+            return self.error(String::from("unterminated regexp meets end of file"), &[]);
+        }
+        let msg = format!("unexpected {:?}, expected an expression", current.token_type);
+        self.error(msg, &[]);
+    }
+
+    pub fn current(&self) -> Token {
+        self.current_token.clone()
+    }
+
+    pub fn peek(&self) -> Option<Token> {
+        self.peek_token.clone()
+    }
+
+    /// Adds a node spanning from `start` to the end of the current token.
+    fn node(&mut self, kind: NodeKind, start: usize) -> NodeId {
+        self.ast.push(kind, Span::new(start, self.current_span.end))
+    }
+
+    /// Adds a node covering just the current token.
+    fn leaf(&mut self, kind: NodeKind) -> NodeId {
+        self.ast.push(kind, self.current_span)
+    }
+
+    fn start_of(&self, id: NodeId) -> usize {
+        self.ast[id].span.start
+    }
+
+    /// Parses the whole input. A statement with a syntax error is skipped after recording the
+    /// error, so one bad line doesn't hide the errors or statements that follow it.
+    pub fn parse_program(&mut self) -> (Program, Vec<ParseError>) {
+        let statements = self.parse_statements(&[]);
+        let mut program = Program::new();
+        program.ast = std::mem::take(&mut self.ast);
+        program.ast.index_lines(self.lexer.source());
+        program.ast.set_frozen_string_literal(frozen_string_literal(self.lexer.source()));
+        program.trivia = self.lexer.trivia().to_vec();
+        for statement in statements {
+            program.push(statement);
+        }
+
+        (program, self.errors())
+    }
+
+    /// Parses statements until the current token is EOF or one of `terminators`, leaving the
+    /// terminator as the current token.
+    fn parse_statements(&mut self, terminators: &[TokenType]) -> Vec<NodeId> {
+        let mut statements = vec!();
+        loop {
+            self.skip_separators();
+            if self.cur_token_is(TokenType::EOF) || terminators.contains(&self.current().token_type) { break; }
+
+            let (depth, no_do_block) = (self.scopes.len(), self.no_do_block);
+            match self.parse_statement() {
+                Some(statement) => {
+                    statements.push(statement);
+                    self.next();
+                    if !self.at_statement_end(terminators) {
+                        if self.cur_token_is(TokenType::ILLEGAL) {
+                            self.no_prefix_parse_error();
+                        } else {
+                            let message = format!("unexpected {:?}, expecting end of statement", self.current().token_type);
+                            self.error(message, &[TokenType::NEWLINE, TokenType::SEMICOLON]);
+                        }
+                        self.synchronize(terminators);
+                    }
+                },
+                None => {
+                    self.scopes.truncate(depth);
+                    self.no_do_block = no_do_block;
+                    self.synchronize(terminators);
+                },
+            }
+        }
+
+        statements
+    }
+
+    /// Whether the current token can follow a statement: a newline, `;`, the end of the input
+    /// or one of `terminators`.
+    fn at_statement_end(&self, terminators: &[TokenType]) -> bool {
+        let token_type = self.current().token_type;
+        matches!(token_type, TokenType::NEWLINE | TokenType::SEMICOLON | TokenType::EOF) || terminators.contains(&token_type)
+    }
+
+    /// Skips the rest of a statement that failed to parse, stopping at the next newline or `;`,
+    /// or at one of `terminators` so the enclosing construct can still be closed.
+    fn synchronize(&mut self, terminators: &[TokenType]) {
+        while !self.cur_token_is(TokenType::EOF) && !self.cur_token_is(TokenType::NEWLINE) && !self.cur_token_is(TokenType::SEMICOLON) {
+            if terminators.contains(&self.current().token_type) { return; }
+            self.next();
+        }
+    }
+
+    /// Parses an expression, extending it into a multiple assignment when followed by a comma:
+    /// `a, b = b, a` or `a = 1, 2`.
+    fn parse_statement(&mut self) -> Option<NodeId> {
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if !self.peek_token_is(TokenType::COMMA) { return Some(expression); }
+
+        let start = self.start_of(expression);
+        match self.ast[expression].kind.clone() {
+            NodeKind::Assign(target, value) => {
+                let value = self.parse_rhs_list(value)?;
+                Some(self.node(NodeKind::Assign(target, value), start))
+            },
+            NodeKind::MultipleAssign(targets, value) => {
+                let value = self.parse_rhs_list(value)?;
+                Some(self.node(NodeKind::MultipleAssign(targets, value), start))
+            },
+            _ => {
+                let targets = self.parse_mlhs(expression)?;
+                if !self.expect_peek(TokenType::ASSIGN) { return None; }
+                self.next();
+                self.skip_newlines();
+                let first_value = self.parse_expression(Precedence::AndOr)?;
+                let value = self.parse_rhs_list(first_value)?;
+
+                Some(self.node(NodeKind::MultipleAssign(targets, value), start))
+            }
+        }
+    }
+
+    /// Parses the remaining comma separated values on the right of an assignment. Several
+    /// values, or a splatted one, are gathered into an array.
+    fn parse_rhs_list(&mut self, first: NodeId) -> Option<NodeId> {
+        let start = self.start_of(first);
+        let mut values = vec!(first);
+        while self.peek_token_is(TokenType::COMMA) {
+            self.next();
+            self.next();
+            self.skip_newlines();
+            values.push(self.parse_expression(Precedence::AndOr)?);
+        }
+
+        if values.len() == 1 && !matches!(self.ast[first].kind, NodeKind::Splat(_)) {
+            return Some(first);
+        }
+
+        Some(self.node(NodeKind::Array(values), start))
+    }
+
+    /// Parses the rest of a comma separated list of assignment targets, given its first item.
+    fn parse_mlhs(&mut self, first: NodeId) -> Option<Vec<AssignTarget>> {
+        let mut targets = vec!(self.parse_target(first)?);
+        while self.peek_token_is(TokenType::COMMA) {
+            self.next();
+            if self.peek_token_is(TokenType::ASSIGN) || self.peek_token_is(TokenType::RPAREN) {
+                targets.push(AssignTarget::Splat(None));
+                break;
+            }
+            self.next();
+            let target = self.parse_expression(Precedence::Assignment)?;
+            targets.push(self.parse_target(target)?);
+        }
+
+        Some(targets)
+    }
+
+    /// Converts an expression parsed on the left of `=` into the target it assigns to.
+    fn parse_target(&mut self, expression: NodeId) -> Option<AssignTarget> {
+        let target = match self.ast[expression].kind.clone() {
+            NodeKind::Identifier(name) => {
+                self.declare_local(&name);
+                AssignTarget::Local(name)
+            },
+            NodeKind::InstanceVariable(name) => AssignTarget::InstanceVariable(name),
+            NodeKind::ClassVariable(name) => AssignTarget::ClassVariable(name),
+            NodeKind::GlobalVariable(name) => AssignTarget::GlobalVariable(name),
+            NodeKind::Constant(scope, name) => AssignTarget::Constant(scope, name),
+            NodeKind::Call(Call { receiver: Some(receiver), method, arguments, block: None }) if method == "[]" => {
+                AssignTarget::Index(receiver, arguments)
+            },
+            NodeKind::Call(Call { receiver: Some(receiver), method, arguments, block: None })
+                if arguments.is_empty() && method.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_') => {
+                AssignTarget::Attribute(receiver, method)
+            },
+            NodeKind::Splat(Some(expression)) => AssignTarget::Splat(Some(Box::new(self.parse_target(expression)?))),
+            NodeKind::Splat(None) => AssignTarget::Splat(None),
+            NodeKind::Mlhs(targets) => AssignTarget::Nested(targets),
+            _ => {
+                self.error(format!("cannot assign to {}", self.ast.to_s(expression).trim_end()), &[]);
+                return None;
+            }
+        };
+
+        Some(target)
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<NodeId> {
+        let mut left = match self.current().token_type {
+            TokenType::IDENT    => self.parse_ident_expression(),
+            // `Integer("42")` calls the method named like the class
+            TokenType::CONSTANT if self.peek_token_is(TokenType::LPAREN) && !self.peek_is_spaced() => self.parse_call(None),
+            TokenType::CONSTANT => Some(self.leaf(NodeKind::Constant(None, self.current().literal))),
+            TokenType::IVAR     => Some(self.leaf(NodeKind::InstanceVariable(self.current().literal))),
+            TokenType::CVAR     => Some(self.leaf(NodeKind::ClassVariable(self.current().literal))),
+            TokenType::GVAR     => Some(self.leaf(NodeKind::GlobalVariable(self.current().literal))),
+            TokenType::INT      => self.parse_integer(false),
+            TokenType::FLOAT    => self.parse_float(false),
+            TokenType::STRING   => Some(self.leaf(NodeKind::Str(self.current().bytes))),
+            TokenType::STRINGBEGIN => self.parse_interpolated_string(),
+            TokenType::SYMBOL   => Some(self.leaf(NodeKind::Symbol(self.current().literal))),
+            TokenType::REGEXP   => self.parse_regex(),
+            TokenType::REGEXPBEGIN => self.parse_interpolated_regex(),
+            TokenType::NIL      => Some(self.leaf(NodeKind::Nil)),
+            TokenType::TRUE     => Some(self.leaf(NodeKind::Boolean(true))),
+            TokenType::FALSE    => Some(self.leaf(NodeKind::Boolean(false))),
+            TokenType::SELF     => Some(self.leaf(NodeKind::SelfRef)),
+            TokenType::BANG     => self.parse_prefix_expression("!", Precedence::Prefix),
+            TokenType::TILDE    => self.parse_prefix_expression("~", Precedence::Prefix),
+            TokenType::NOT      => self.parse_prefix_expression("!", Precedence::Not),
+            TokenType::MINUS    => self.parse_minus_expression(),
+            TokenType::PLUS     => self.parse_prefix_expression("+@", Precedence::UnaryMinus),
+            TokenType::DOT2 | TokenType::DOT3 => self.parse_range(None),
+            TokenType::LPAREN   => self.parse_grouped_expression(),
+            TokenType::LBRACKET => self.parse_array_literal(),
+            TokenType::LBRACE   => self.parse_hash_literal(),
+            TokenType::ASTERISK => self.parse_splat(),
+            TokenType::AMPERSAND => self.parse_block_pass(),
+            TokenType::LAMBDA   => self.parse_lambda(),
+            TokenType::RETURN   => self.parse_jump_expression(NodeKind::Return),
+            TokenType::BREAK    => self.parse_jump_expression(NodeKind::Break),
+            TokenType::NEXT     => self.parse_jump_expression(NodeKind::Next),
+            TokenType::DEF      => self.parse_def_expression(),
+            TokenType::CLASS    => self.parse_class_expression(),
+            TokenType::MODULE   => self.parse_module_expression(),
+            TokenType::SUPER    => self.parse_super(),
+            TokenType::YIELD    => self.parse_yield(),
+            TokenType::BEGIN    => self.parse_begin_expression(),
+            TokenType::RETRY    => Some(self.leaf(NodeKind::Retry)),
+            TokenType::IF | TokenType::UNLESS => self.parse_if_expression(),
+            TokenType::WHILE | TokenType::UNTIL => self.parse_while_expression(),
+            TokenType::CASE     => self.parse_case_expression(),
+            _ => {
+                self.no_prefix_parse_error();
+                None
+            }
+        }?;
+
+        while precedence < self.peek_precedence() {
+            self.next();
+            left = match self.current().token_type {
+                TokenType::ASSIGN     => self.parse_assign_expression(left),
+                TokenType::OPASSIGN   => self.parse_op_assign_expression(left),
+                TokenType::RESCUE     => self.parse_rescue_modifier(left),
+                TokenType::IF | TokenType::UNLESS | TokenType::WHILE | TokenType::UNTIL => self.parse_modifier(left),
+                TokenType::AND | TokenType::OR | TokenType::ANDAND | TokenType::OROR => self.parse_logical_expression(left),
+                TokenType::QUESTION   => self.parse_ternary_expression(left),
+                TokenType::DOT2 | TokenType::DOT3 => self.parse_range(Some(left)),
+                TokenType::DOT        => self.parse_method_call(left),
+                TokenType::COLONCOLON => self.parse_scoped_constant(left),
+                TokenType::LBRACKET   => self.parse_index_expression(left),
+                _                     => self.parse_infix_expression(left),
+            }?;
+        }
+
+        Some(left)
+    }
+
+    fn parse_ident_expression(&mut self) -> Option<NodeId> {
+        let token = self.current();
+        let paren_call = self.peek_token_is(TokenType::LPAREN) && !self.peek_is_spaced();
+        let local = self.is_local(&token.literal);
+        if paren_call || (!local && (self.peek_is_command_argument() || self.peek_is_block())) {
+            return self.parse_call(None);
+        }
+        if !local {
+            self.use_implicit_parameter(&token.literal);
+        }
+
+        Some(self.leaf(NodeKind::Identifier(token.literal)))
+    }
+
+    fn parse_assign_expression(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        let assign_start = self.current_span.start;
+        let target = match self.ast[left].kind.clone() {
+            NodeKind::Mlhs(targets) => AssignTarget::Nested(targets),
+            NodeKind::Splat(_) => AssignTarget::Nested(vec!(self.parse_target(left)?)),
+            _ => self.parse_target(left)?,
+        };
+
+        self.next();
+        self.skip_newlines();
+        let mut right_expr = self.parse_expression(Precedence::AndOr)?;
+        if let NodeKind::Splat(_) = self.ast[right_expr].kind {
+            right_expr = self.node(NodeKind::Array(vec!(right_expr)), assign_start);
+        }
+
+        match target {
+            AssignTarget::Nested(targets) => Some(self.node(NodeKind::MultipleAssign(targets, right_expr), start)),
+            target => Some(self.node(NodeKind::Assign(target, right_expr), start)),
+        }
+    }
+
+    /// Parses `target op= value` into a single node, so the receiver and index arguments of an
+    /// attribute or index target are only evaluated once.
+    fn parse_op_assign_expression(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        let token = self.current();
+        let op = token.literal.trim_end_matches('=').to_string();
+        let target = match self.parse_target(left)? {
+            target @ (AssignTarget::Splat(_) | AssignTarget::Nested(_)) => {
+                self.error(format!("cannot use {} on {}", token.literal, self.ast.target_to_s(&target)), &[]);
+                return None;
+            },
+            target => target,
+        };
+
+        self.next();
+        self.skip_newlines();
+        let value = self.parse_expression(Precedence::AndOr)?;
+
+        Some(self.node(NodeKind::OpAssign(target, op, value), start))
+    }
+
+    /// Parses an integer literal, in the base its prefix gives: `0x`, `0b`, `0o` or just `0`
+    /// for octal, and `0d` or none for decimal.
+    fn parse_integer(&mut self, negative: bool) -> Option<NodeId> {
+        let current = self.current();
+        let literal = if negative { format!("-{}", current.literal) } else { current.literal };
+        let (sign, unsigned) = literal.split_at(if negative { 1 } else { 0 });
+        let (radix, digits) = match unsigned.get(..2).map(|prefix| prefix.to_ascii_lowercase()).as_deref() {
+            Some("0x") => (16, &unsigned[2..]),
+            Some("0b") => (2, &unsigned[2..]),
+            Some("0o") => (8, &unsigned[2..]),
+            Some("0d") => (10, &unsigned[2..]),
+            _ if unsigned.len() > 1 && unsigned.starts_with('0') => (8, &unsigned[1..]),
+            _ => (10, unsigned),
+        };
+        match i64::from_str_radix(&format!("{}{}", sign, digits), radix) {
+            Ok(value) => Some(self.leaf(NodeKind::Integer(value))),
+            // Ruby integers have no upper bound, so a literal that only overflows is kept in decimal
+            Err(error) if matches!(error.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+                Some(self.leaf(NodeKind::BigInteger(format!("{}{}", sign, to_decimal(digits, radix)))))
+            },
+            Err(_) => {
+                self.error(format!("could not parse {} as an integer", literal), &[]);
+                None
+            }
+        }
+    }
+
+    fn parse_float(&mut self, negative: bool) -> Option<NodeId> {
+        let current = self.current();
+        match current.literal.parse::<f64>() {
+            Ok(value) => Some(self.leaf(NodeKind::Float(if negative { -value } else { value }))),
+            Err(_) => {
+                self.error(format!("could not parse {} as a float", current.literal), &[]);
+                None
+            }
+        }
+    }
+
+    fn parse_regex(&mut self) -> Option<NodeId> {
+        let literal = self.current().literal;
+        let (source, flags) = literal.rsplit_once('/').expect("the lexer separates the flags with a slash");
+        let flags = self.regex_flags(flags)?;
+        Some(self.leaf(NodeKind::Regex(source.to_string(), flags)))
+    }
+
+    /// Checks the flags after a regexp, dropping the `o` that makes no difference here.
+    fn regex_flags(&mut self, flags: &str) -> Option<String> {
+        if let Some(flag) = flags.chars().find(|flag| !"imxo".contains(*flag)) {
+            self.error(format!("unknown regexp option - {}", flag), &[]);
+            return None;
+        }
+        Some(flags.chars().filter(|flag| *flag != 'o').collect())
+    }
+
+    /// Parses `return`, `break` or `next`, with or without a value.
+    fn parse_jump_expression(&mut self, kind: fn(Option<NodeId>) -> NodeKind) -> Option<NodeId> {
+        let start = self.current_span.start;
+        if !self.peek_starts_expression() {
+            return Some(self.leaf(kind(None)));
+        }
+        self.next();
+
+        // `return 1, 2` returns an array, as a multiple assignment's values make one
+        let first = self.parse_expression(Precedence::AndOr)?;
+        let value = self.parse_rhs_list(first)?;
+        Some(self.node(kind(Some(value)), start))
+    }
+
+    fn parse_prefix_expression(&mut self, method: &str, precedence: Precedence) -> Option<NodeId> {
+        let start = self.current_span.start;
+        self.next();
+        let right = self.parse_expression(precedence)?;
+
+        Some(self.node(NodeKind::Call(Call {
+            receiver: Some(right),
+            method: method.to_string(),
+            arguments: vec!(),
+            block: None,
+        }), start))
+    }
+
+    /// A `-` directly in front of a number is part of the literal, except that `-2 ** 2` is
+    /// still `-(2 ** 2)`. Anything else is negated with `-@`.
+    fn parse_minus_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let numeric = self.peek_token_is(TokenType::INT) || self.peek_token_is(TokenType::FLOAT);
+        if !numeric || self.peek_is_spaced() {
+            return self.parse_prefix_expression("-@", Precedence::UnaryMinus);
+        }
+
+        self.next();
+        let float = self.cur_token_is(TokenType::FLOAT);
+        if !self.peek_token_is(TokenType::POW) {
+            let literal = if float { self.parse_float(true) } else { self.parse_integer(true) }?;
+            self.ast[literal].span.start = start;
+            return Some(literal);
+        }
+
+        let base = if float { self.parse_float(false) } else { self.parse_integer(false) }?;
+        self.next();
+        let power = self.parse_infix_expression(base)?;
+        Some(self.node(NodeKind::Call(Call {
+            receiver: Some(power),
+            method: String::from("-@"),
+            arguments: vec!(),
+            block: None,
+        }), start))
+    }
+
+    fn parse_infix_expression(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        let token = self.current();
+        // `**` is right associative: `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+        let precedence = match token.token_type {
+            TokenType::POW => Precedence::UnaryMinus,
+            token_type => precedence_of(token_type),
+        };
+        self.next();
+        self.skip_newlines();
+        let right = self.parse_expression(precedence)?;
+
+        Some(self.node(NodeKind::Call(Call {
+            receiver: Some(left),
+            method: token.literal,
+            arguments: vec!(right),
+            block: None,
+        }), start))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        self.next();
+        self.skip_newlines();
+        let no_do_block = std::mem::replace(&mut self.no_do_block, false);
+        let mut expr = self.parse_expression(Precedence::Lowest)?;
+        let mlhs = if self.peek_token_is(TokenType::COMMA) {
+            Some(self.parse_mlhs(expr)?)
+        } else {
+            None
+        };
+        self.no_do_block = no_do_block;
+        self.skip_peek_newlines();
+        if !self.expect_peek(TokenType::RPAREN) { return None; }
+        if let Some(targets) = mlhs {
+            expr = self.node(NodeKind::Mlhs(targets), start);
+        }
+
+        Some(expr)
+    }
+
+    fn parse_array_literal(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let elements = self.parse_expression_list(TokenType::RBRACKET)?;
+
+        Some(self.node(NodeKind::Array(elements), start))
+    }
+
+    fn parse_index_expression(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        let arguments = self.parse_expression_list(TokenType::RBRACKET)?;
+
+        Some(self.node(NodeKind::Call(Call {
+            receiver: Some(left),
+            method: String::from("[]"),
+            arguments,
+            block: None,
+        }), start))
+    }
+
+    /// Parses `&blk` or `&:sym`, or a bare `&` when nothing follows.
+    fn parse_block_pass(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        if !self.peek_starts_expression() {
+            return Some(self.leaf(NodeKind::BlockPass(None)));
+        }
+        self.next();
+        let expr = self.parse_expression(Precedence::Prefix)?;
+
+        Some(self.node(NodeKind::BlockPass(Some(expr)), start))
+    }
+
+    /// Parses `->(params) { body }`, `-> params do body end` or `-> { body }`.
+    fn parse_lambda(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        self.push_scope(true);
+        let params = if self.peek_token_is(TokenType::LPAREN) {
+            self.next();
+            self.next();
+            let params = self.parse_parameters(&[TokenType::RPAREN])?;
+            self.next();
+            params
+        } else {
+            self.next();
+            self.parse_parameters(&[TokenType::LBRACE, TokenType::DO])?
+        };
+        if let Some(scope) = self.scopes.last_mut() { scope.explicit_params = !params.is_empty(); }
+
+        if !self.cur_token_is(TokenType::LBRACE) && !self.cur_token_is(TokenType::DO) {
+            let msg = format!("expected `{{` or `do` to start lambda body, got {:?} instead", self.current().token_type);
+            self.error(msg, &[TokenType::LBRACE, TokenType::DO]);
+            return None;
+        }
+        let (params, body) = self.parse_block_body(self.current().token_type, params)?;
+
+        Some(self.node(NodeKind::Lambda(params, body), start))
+    }
+
+    /// Parses `*expr`, or a bare `*` when nothing that could be splatted follows.
+    fn parse_splat(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        if !self.peek_starts_expression() {
+            return Some(self.leaf(NodeKind::Splat(None)));
+        }
+        self.next();
+        let expr = self.parse_expression(Precedence::Prefix)?;
+
+        Some(self.node(NodeKind::Splat(Some(expr)), start))
+    }
+
+    fn parse_rescue_modifier(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        self.next();
+        let rescue = self.parse_expression(Precedence::RescueModifier)?;
+
+        Some(self.node(NodeKind::RescueModifier(left, rescue), start))
+    }
+
+    /// Parses `expr if cond` and the `unless`, `while` and `until` modifiers.
+    fn parse_modifier(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        let keyword = self.current().token_type;
+        self.next();
+        let condition = self.parse_expression(Precedence::Modifier)?;
+        let kind = match keyword {
+            TokenType::IF     => NodeKind::If(condition, vec!(left), None),
+            TokenType::UNLESS => NodeKind::Unless(condition, vec!(left), None),
+            TokenType::WHILE  => NodeKind::While(condition, vec!(left)),
+            _                 => NodeKind::Until(condition, vec!(left)),
+        };
+
+        Some(self.node(kind, start))
+    }
+
+    fn parse_logical_expression(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        let token_type = self.current().token_type;
+        self.next();
+        self.skip_newlines();
+        let right = self.parse_expression(precedence_of(token_type))?;
+        let kind = match token_type {
+            TokenType::AND | TokenType::ANDAND => NodeKind::And(left, right),
+            _ => NodeKind::Or(left, right),
+        };
+
+        Some(self.node(kind, start))
+    }
+
+    fn parse_ternary_expression(&mut self, condition: NodeId) -> Option<NodeId> {
+        let start = self.start_of(condition);
+        self.next();
+        self.skip_newlines();
+        let consequence = self.parse_expression(Precedence::Ternary)?;
+        self.skip_peek_newlines();
+        if !self.expect_peek(TokenType::COLON) { return None; }
+        self.next();
+        self.skip_newlines();
+        let alternative = self.parse_expression(Precedence::RescueModifier)?;
+
+        Some(self.node(NodeKind::If(condition, vec!(consequence), Some(vec!(alternative))), start))
+    }
+
+    /// Parses `a..b` or `a...b` from the operator, where either end may be missing: `(1..)` and
+    /// `..5` are endless and beginless ranges.
+    fn parse_range(&mut self, left: Option<NodeId>) -> Option<NodeId> {
+        let start = match left {
+            Some(left) => self.start_of(left),
+            None => self.current_span.start,
+        };
+        let exclusive = self.cur_token_is(TokenType::DOT3);
+        if !self.peek_starts_expression() {
+            return Some(self.node(NodeKind::Range(left, None, exclusive), start));
+        }
+        self.next();
+        let right = self.parse_expression(Precedence::Range)?;
+
+        Some(self.node(NodeKind::Range(left, Some(right), exclusive), start))
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let no_do_block = std::mem::replace(&mut self.no_do_block, false);
+        let mut pairs = vec!();
+        self.next();
+        self.skip_newlines();
+        while !self.cur_token_is(TokenType::RBRACE) {
+            match self.parse_list_item(Precedence::Lowest)? {
+                ListItem::Pair(key, value) => pairs.push((key, value)),
+                ListItem::Value(_) => {
+                    self.peek_error(TokenType::HASHROCKET);
+                    return None;
+                }
+            }
+            self.next();
+            self.skip_newlines();
+            if self.cur_token_is(TokenType::COMMA) {
+                self.next();
+                self.skip_newlines();
+            } else if !self.cur_token_is(TokenType::RBRACE) {
+                let msg = format!("expected `,` or RBRACE in hash, got {:?} instead", self.current().token_type);
+                self.error(msg, &[TokenType::COMMA, TokenType::RBRACE]);
+                return None;
+            }
+        }
+        self.no_do_block = no_do_block;
+
+        Some(self.node(NodeKind::Hash(pairs), start))
+    }
+
+    /// Parses a list element starting at the current token. A `key: value` label or an
+    /// expression followed by `=>` makes a hash pair.
+    fn parse_list_item(&mut self, precedence: Precedence) -> Option<ListItem> {
+        let key = if self.cur_token_is(TokenType::LABEL) {
+            let span = Span::new(self.current_span.start, self.current_span.end + 1);
+            self.ast.push(NodeKind::Symbol(self.current().literal), span)
+        } else {
+            let value = self.parse_expression(precedence)?;
+            if !self.peek_token_is(TokenType::HASHROCKET) { return Some(ListItem::Value(value)); }
+            self.next();
+            value
+        };
+        self.next();
+        self.skip_newlines();
+        let value = self.parse_expression(precedence)?;
+
+        Some(ListItem::Pair(key, value))
+    }
+
+    /// Parses `if`/`unless` up to its `end`. An `elsif` becomes a nested `If` in the else branch,
+    /// which shares the final `end`.
+    fn parse_if_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let keyword = self.current().token_type;
+        let condition = self.parse_condition()?;
+        let consequence = self.parse_statements(&[TokenType::ELSIF, TokenType::ELSE, TokenType::END]);
+        let alternative = match self.current().token_type {
+            TokenType::ELSIF => Some(vec!(self.parse_if_expression()?)),
+            TokenType::ELSE => {
+                self.next();
+                Some(self.parse_statements(&[TokenType::END]))
+            },
+            _ => None
+        };
+        if !self.cur_token_is(TokenType::END) {
+            self.error(format!("expected `end` to close `if`, got {:?} instead", self.current().token_type), &[TokenType::END]);
+            return None;
+        }
+
+        let kind = match keyword {
+            TokenType::UNLESS => NodeKind::Unless(condition, consequence, alternative),
+            _ => NodeKind::If(condition, consequence, alternative),
+        };
+        Some(self.node(kind, start))
+    }
+
+    fn parse_while_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let keyword = self.current().token_type;
+        let condition = self.parse_condition()?;
+        let body = self.parse_statements(&[TokenType::END]);
+        if !self.cur_token_is(TokenType::END) {
+            self.error(format!("expected `end` to close loop, got {:?} instead", self.current().token_type), &[TokenType::END]);
+            return None;
+        }
+
+        let kind = match keyword {
+            TokenType::UNTIL => NodeKind::Until(condition, body),
+            _ => NodeKind::While(condition, body),
+        };
+        Some(self.node(kind, start))
+    }
+
+    /// Parses the condition after `if`, `unless`, `elsif`, `while` or `until`, and moves past the
+    /// `then` or `do` that may follow it. A `do` there never starts a block.
+    fn parse_condition(&mut self) -> Option<NodeId> {
+        self.next();
+        let no_do_block = std::mem::replace(&mut self.no_do_block, true);
+        let condition = self.parse_expression(Precedence::Lowest);
+        self.no_do_block = no_do_block;
+        let condition = condition?;
+        self.next();
+        if self.cur_token_is(TokenType::THEN) || self.cur_token_is(TokenType::DO) { self.next(); }
+
+        Some(condition)
+    }
+
+    fn parse_case_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let subject = if self.peek_is_end_of_line() {
+            None
+        } else {
+            self.next();
+            Some(self.parse_expression(Precedence::Lowest)?)
+        };
+        self.next();
+        self.skip_separators();
+
+        let mut when_clauses = vec!();
+        while self.cur_token_is(TokenType::WHEN) {
+            let clause_start = self.current_span.start;
+            let mut values = vec!();
+            loop {
+                self.next();
+                self.skip_newlines();
+                values.push(self.parse_expression(Precedence::Lowest)?);
+                if !self.peek_token_is(TokenType::COMMA) { break; }
+                self.next();
+            }
+            let span = Span::new(clause_start, self.current_span.end);
+            self.next();
+            if self.cur_token_is(TokenType::THEN) { self.next(); }
+            let body = self.parse_statements(&[TokenType::WHEN, TokenType::ELSE, TokenType::END]);
+            when_clauses.push(WhenClause { span, values, body });
+        }
+        if when_clauses.is_empty() {
+            self.error(format!("expected `when` after `case`, got {:?} instead", self.current().token_type), &[TokenType::WHEN]);
+            return None;
+        }
+        let else_clause = if self.cur_token_is(TokenType::ELSE) {
+            self.next();
+            Some(self.parse_statements(&[TokenType::END]))
+        } else {
+            None
+        };
+        if !self.cur_token_is(TokenType::END) {
+            self.error(format!("expected `end` to close `case`, got {:?} instead", self.current().token_type), &[TokenType::END]);
+            return None;
+        }
+
+        Some(self.node(NodeKind::Case(Case { subject, when_clauses, else_clause }), start))
+    }
+
+    fn parse_scoped_constant(&mut self, left: NodeId) -> Option<NodeId> {
+        let start = self.start_of(left);
+        if !self.expect_peek(TokenType::CONSTANT) { return None; }
+
+        Some(self.node(NodeKind::Constant(Some(left), self.current().literal), start))
+    }
+
+    fn parse_method_call(&mut self, receiver: NodeId) -> Option<NodeId> {
+        self.next();
+        let token = self.current();
+        if !self.is_method_name(&token) {
+            self.error(format!("expected a method name after `.`, got {:?} instead", token.token_type), &[TokenType::IDENT, TokenType::CONSTANT]);
+            return None;
+        }
+
+        self.parse_call(Some(receiver))
+    }
+
+    /// Parses the arguments and block of a call whose method name is the current token.
+    fn parse_call(&mut self, receiver: Option<NodeId>) -> Option<NodeId> {
+        let start = match receiver {
+            Some(receiver) => self.start_of(receiver),
+            None => self.current_span.start,
+        };
+        let method = self.current().literal;
+        let mut arguments = if self.peek_token_is(TokenType::LPAREN) && !self.peek_is_spaced() {
+            self.next();
+            self.parse_expression_list(TokenType::RPAREN)?
+        } else if self.peek_is_command_argument() {
+            self.parse_command_arguments()?
+        } else {
+            vec!()
+        };
+
+        let block = self.parse_block_argument(&mut arguments)?;
+
+        Some(self.node(NodeKind::Call(Call {
+            receiver,
+            method,
+            arguments,
+            block,
+        }), start))
+    }
+
+    /// Takes a trailing `&block` out of the arguments of a call, or parses the literal block
+    /// following them.
+    fn parse_block_argument(&mut self, arguments: &mut Vec<NodeId>) -> Option<Option<NodeId>> {
+        let block_pass = match arguments.last() {
+            Some(&last) if matches!(self.ast[last].kind, NodeKind::BlockPass(_)) => arguments.pop(),
+            _ => None
+        };
+        if !self.peek_is_block() { return Some(block_pass); }
+
+        self.next();
+        if block_pass.is_some() {
+            self.error(String::from("both block arg and actual block given"), &[]);
+            return None;
+        }
+        Some(Some(self.parse_block()?))
+    }
+
+    fn parse_yield(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let arguments = if self.peek_token_is(TokenType::LPAREN) && !self.peek_is_spaced() {
+            self.next();
+            self.parse_expression_list(TokenType::RPAREN)?
+        } else if self.peek_is_command_argument() {
+            self.parse_command_arguments()?
+        } else {
+            vec!()
+        };
+
+        Some(self.node(NodeKind::Yield(arguments), start))
+    }
+
+    /// Parses `super`, `super(args)` or `super args`, each with an optional block. Only a bare
+    /// `super` passes on the arguments of the current method.
+    fn parse_super(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let mut arguments = if self.peek_token_is(TokenType::LPAREN) && !self.peek_is_spaced() {
+            self.next();
+            Some(self.parse_expression_list(TokenType::RPAREN)?)
+        } else if self.peek_is_command_argument() {
+            Some(self.parse_command_arguments()?)
+        } else {
+            None
+        };
+        let block = match arguments.as_mut() {
+            Some(arguments) => self.parse_block_argument(arguments)?,
+            None => self.parse_block_argument(&mut vec!())?,
+        };
+
+        Some(self.node(NodeKind::Super(arguments, block), start))
+    }
+
+    /// Parses comma separated expressions from the current opening token up to `closing`,
+    /// allowing newlines and a trailing comma.
+    fn parse_expression_list(&mut self, closing: TokenType) -> Option<Vec<NodeId>> {
+        let no_do_block = std::mem::replace(&mut self.no_do_block, false);
+        let mut expressions = vec!();
+        let mut pairs = vec!();
+        self.next();
+        self.skip_newlines();
+        while !self.cur_token_is(closing) {
+            match self.parse_list_item(Precedence::Lowest)? {
+                ListItem::Value(value) => {
+                    self.push_hash(&mut expressions, &mut pairs);
+                    expressions.push(value);
+                },
+                ListItem::Pair(key, value) => pairs.push((key, value)),
+            }
+            self.next();
+            self.skip_newlines();
+            if self.cur_token_is(TokenType::COMMA) {
+                self.next();
+                self.skip_newlines();
+            } else if !self.cur_token_is(closing) {
+                let msg = format!("expected `,` or {:?} in list, got {:?} instead", closing, self.current().token_type);
+                self.error(msg, &[TokenType::COMMA, closing]);
+                return None;
+            }
+        }
+        self.push_hash(&mut expressions, &mut pairs);
+        self.no_do_block = no_do_block;
+
+        Some(expressions)
+    }
+
+    /// Gathers the `key => value` pairs collected so far in a list into a hash argument.
+    fn push_hash(&mut self, expressions: &mut Vec<NodeId>, pairs: &mut Vec<(NodeId, NodeId)>) {
+        if let (Some((first, _)), Some((_, last))) = (pairs.first(), pairs.last()) {
+            let span = Span::new(self.start_of(*first), self.ast[*last].span.end);
+            expressions.push(self.ast.push(NodeKind::Hash(std::mem::take(pairs)), span));
+        }
+    }
+
+    /// Parses the arguments of a call without parentheses, like `raise Error, "message"`. A
+    /// trailing `do` block belongs to the outer call, not to the last argument.
+    fn parse_command_arguments(&mut self) -> Option<Vec<NodeId>> {
+        let no_do_block = std::mem::replace(&mut self.no_do_block, true);
+        let mut arguments = vec!();
+        let mut pairs = vec!();
+        loop {
+            self.next();
+            match self.parse_list_item(Precedence::RescueModifier)? {
+                ListItem::Value(mut value) => {
+                    // an argument can assign, as in `p x = 2`, with the rescue modifier of the
+                    // statement still applying to the whole call
+                    if self.peek_token_is(TokenType::ASSIGN) || self.peek_token_is(TokenType::OPASSIGN) {
+                        self.next();
+                        value = match self.current().token_type {
+                            TokenType::ASSIGN => self.parse_assign_expression(value),
+                            _ => self.parse_op_assign_expression(value),
+                        }?;
+                    }
+                    self.push_hash(&mut arguments, &mut pairs);
+                    arguments.push(value);
+                },
+                ListItem::Pair(key, value) => pairs.push((key, value)),
+            }
+            if !self.peek_token_is(TokenType::COMMA) { break; }
+
+            self.next();
+            self.skip_peek_newlines();
+        }
+        self.push_hash(&mut arguments, &mut pairs);
+        self.no_do_block = no_do_block;
+
+        Some(arguments)
+    }
+
+    /// Parses `do |params| ... end` or `{ |params| ... }` starting at the opening token.
+    fn parse_block(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let opening = self.current().token_type;
+        self.push_scope(true);
+        let params = if self.peek_token_is(TokenType::PIPE) {
+            self.next();
+            self.next();
+            let params = self.parse_parameters(&[TokenType::PIPE])?;
+            if let Some(scope) = self.scopes.last_mut() { scope.explicit_params = true; }
+            params
+        } else if self.peek_token_is(TokenType::OROR) {
+            self.next();
+            if let Some(scope) = self.scopes.last_mut() { scope.explicit_params = true; }
+            Parameters::default()
+        } else {
+            Parameters::default()
+        };
+        let (params, body) = self.parse_block_body(opening, params)?;
+
+        Some(self.node(NodeKind::Block(params, body), start))
+    }
+
+    /// Parses the body of a block or lambda opened by `do` or `{` whose scope has already been
+    /// pushed, from the token before its first statement to the closing `end` or `}`. Returns the
+    /// parameters completed with any numbered or `it` parameters the body used.
+    fn parse_block_body(&mut self, opening: TokenType, mut params: Parameters) -> Option<(Parameters, BodyStatement)> {
+        let closing = if opening == TokenType::DO { TokenType::END } else { TokenType::RBRACE };
+        let no_do_block = std::mem::replace(&mut self.no_do_block, false);
+        self.next();
+
+        let body = if closing == TokenType::END {
+            self.parse_body_statement()?
+        } else {
+            BodyStatement { statements: self.parse_statements(&[TokenType::RBRACE]), ..BodyStatement::default() }
+        };
+        if !self.cur_token_is(closing) {
+            self.error(format!("expected {:?} to close block, got {:?} instead", closing, self.current().token_type), &[closing]);
+            return None;
+        }
+        self.no_do_block = no_do_block;
+        if let Some(scope) = self.scopes.pop() {
+            params.numbered = scope.numbered;
+            params.it = scope.uses_it;
+        }
+
+        Some((params, body))
+    }
+
+    fn parse_def_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        self.next();
+        let receiver = match self.current().token_type {
+            TokenType::SELF | TokenType::IDENT | TokenType::CONSTANT if self.peek_token_is(TokenType::DOT) => {
+                let receiver = match self.current().token_type {
+                    TokenType::SELF => self.leaf(NodeKind::SelfRef),
+                    TokenType::IDENT => self.leaf(NodeKind::Identifier(self.current().literal)),
+                    _ => self.leaf(NodeKind::Constant(None, self.current().literal)),
+                };
+                self.next();
+                self.next();
+                Some(receiver)
+            },
+            _ => None,
+        };
+        let name = self.parse_method_name()?;
+
+        self.push_scope(false);
+        let params = if self.peek_token_is(TokenType::LPAREN) {
+            self.next();
+            self.next();
+            self.parse_parameters(&[TokenType::RPAREN])?
+        } else if self.peek_token_is(TokenType::IDENT) || self.peek_token_is(TokenType::ASTERISK) || self.peek_token_is(TokenType::AMPERSAND) {
+            self.next();
+            self.parse_parameters(&[TokenType::NEWLINE, TokenType::SEMICOLON])?
+        } else {
+            Parameters::default()
+        };
+        self.next();
+
+        let body = self.parse_body_statement()?;
+        self.scopes.pop();
+        if !self.cur_token_is(TokenType::END) {
+            self.error(format!("expected `end` to close `def {}`, got {:?} instead", name, self.current().token_type), &[TokenType::END]);
+            return None;
+        }
+
+        match receiver {
+            Some(receiver) => Some(self.node(NodeKind::SingletonDef(receiver, name, params, body), start)),
+            None => Some(self.node(NodeKind::Def(name, params, body), start)),
+        }
+    }
+
+    /// Parses `class Name < Superclass ... end`, or `class << object ... end` opening the
+    /// singleton class of an object.
+    fn parse_class_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        if self.peek_token_is(TokenType::LSHIFT) {
+            self.next();
+            self.next();
+            let object = self.parse_expression(Precedence::Lowest)?;
+            let body = self.parse_module_body("class <<")?;
+            return Some(self.node(NodeKind::SingletonClass(object, body), start));
+        }
+
+        self.next();
+        let name = self.parse_module_name("class")?;
+        let superclass = if self.peek_token_is(TokenType::LT) {
+            self.next();
+            self.next();
+            Some(self.parse_expression(Precedence::Lowest)?)
+        } else {
+            None
+        };
+        let body = self.parse_module_body(&format!("class {}", self.ast.to_s(name)))?;
+
+        Some(self.node(NodeKind::Class(name, superclass, body), start))
+    }
+
+    fn parse_module_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        self.next();
+        let name = self.parse_module_name("module")?;
+        let body = self.parse_module_body(&format!("module {}", self.ast.to_s(name)))?;
+
+        Some(self.node(NodeKind::Module(name, body), start))
+    }
+
+    /// Parses the constant, possibly scoped like `Outer::Inner`, naming a class or module.
+    fn parse_module_name(&mut self, keyword: &str) -> Option<NodeId> {
+        let name = if self.cur_token_is(TokenType::CONSTANT) { self.parse_expression(Precedence::LessGreater) } else { None };
+        match name {
+            Some(name) if matches!(self.ast[name].kind, NodeKind::Constant(_, _)) => Some(name),
+            _ => {
+                self.error(format!("expected a constant to name the {}, got {:?} instead", keyword, self.current().token_type), &[TokenType::CONSTANT]);
+                None
+            }
+        }
+    }
+
+    /// Parses the body of a class or module in a scope of its own, up to the closing `end`.
+    fn parse_module_body(&mut self, opening: &str) -> Option<BodyStatement> {
+        self.push_scope(false);
+        self.next();
+        let body = self.parse_body_statement()?;
+        self.scopes.pop();
+        if !self.cur_token_is(TokenType::END) {
+            self.error(format!("expected `end` to close `{}`, got {:?} instead", opening, self.current().token_type), &[TokenType::END]);
+            return None;
+        }
+
+        Some(body)
+    }
+
+    /// Reads the name in `def name`, which may be a setter like `name=` or an operator like `<=>`,
+    /// `[]=` or `-@`.
+    fn parse_method_name(&mut self) -> Option<String> {
+        let token = self.current();
+        let mut name = token.literal.clone();
+        match token.token_type {
+            TokenType::IDENT if self.peek_token_is(TokenType::ASSIGN) && !self.peek_is_spaced() => {
+                self.next();
+                name.push('=');
+            },
+            TokenType::LBRACKET => {
+                if !self.expect_peek(TokenType::RBRACKET) { return None; }
+                name = String::from("[]");
+                if self.peek_token_is(TokenType::ASSIGN) && !self.peek_is_spaced() {
+                    self.next();
+                    name.push('=');
+                }
+            },
+            TokenType::PLUS | TokenType::MINUS | TokenType::BANG | TokenType::TILDE
+                if self.peek().is_some_and(|peek| peek.literal == "@") && !self.peek_is_spaced() => {
+                self.next();
+                name.push('@');
+            },
+            TokenType::PLUS | TokenType::MINUS | TokenType::ASTERISK | TokenType::SLASH | TokenType::PERCENT |
+            TokenType::POW | TokenType::LT | TokenType::GT | TokenType::LTE | TokenType::GTE |
+            TokenType::SPACESHIP | TokenType::EQ | TokenType::EQQ | TokenType::NOTEQ | TokenType::MATCH |
+            TokenType::NMATCH | TokenType::LSHIFT | TokenType::RSHIFT | TokenType::AMPERSAND |
+            TokenType::PIPE | TokenType::CARET | TokenType::TILDE | TokenType::BANG => (),
+            _ if self.is_method_name(&token) => (),
+            _ => {
+                let msg = format!("expected a method name after `def`, got {:?} instead", token.token_type);
+                self.error(msg, &[TokenType::IDENT, TokenType::CONSTANT]);
+                return None;
+            }
+        }
+
+        Some(name)
+    }
+
+    /// Parses a parameter list starting at its first token and ending on one of `closing`.
+    /// A list in parentheses or pipes may take several lines. The parameters are separated by
+    /// commas, each name is used once, and they come in the order `Parameters` keeps them in.
+    /// A block's required parameters can be `(a, b)` patterns destructuring their argument.
+    fn parse_parameters(&mut self, closing: &[TokenType]) -> Option<Parameters> {
+        let mut params = Parameters::default();
+        let mut names = vec!();
+        let bracketed = closing.contains(&TokenType::RPAREN) || closing.contains(&TokenType::PIPE);
+        if bracketed { self.skip_newlines(); }
+        while !closing.contains(&self.current().token_type) {
+            if params.block.is_some() {
+                self.error(String::from("no parameter can follow the block parameter"), closing);
+                return None;
+            }
+            match self.current().token_type {
+                TokenType::IDENT if self.peek_token_is(TokenType::ASSIGN) => {
+                    if params.rest.is_some() || !params.post.is_empty() {
+                        self.error(String::from("optional parameters must come before the rest parameter and the required ones after them"), &[]);
+                        return None;
+                    }
+                    let name = self.current().literal;
+                    self.declare_parameter(&mut names, &name)?;
+                    self.next();
+                    self.next();
+                    // A block's `|a = 1|` ends at the pipe rather than reading it as `1 | ...`
+                    let precedence = if closing.contains(&TokenType::PIPE) { Precedence::BitOr } else { Precedence::Assignment };
+                    let default = self.parse_expression(precedence)?;
+                    params.optional.push((name, default));
+                },
+                TokenType::IDENT | TokenType::LPAREN => {
+                    let target = if self.cur_token_is(TokenType::LPAREN) && closing.contains(&TokenType::PIPE) {
+                        self.parse_parameter_pattern(&mut names)?
+                    } else if self.cur_token_is(TokenType::IDENT) {
+                        self.declare_parameter(&mut names, &self.current().literal)?;
+                        AssignTarget::Local(self.current().literal)
+                    } else {
+                        self.error(String::from("unexpected LPAREN in parameter list"), &[TokenType::IDENT]);
+                        return None;
+                    };
+                    if params.optional.is_empty() && params.rest.is_none() {
+                        params.required.push(target);
+                    } else {
+                        params.post.push(target);
+                    }
+                },
+                TokenType::ASTERISK => {
+                    if params.rest.is_some() || !params.post.is_empty() {
+                        self.error(String::from("a parameter list can only have one rest parameter, before the required ones after it"), &[]);
+                        return None;
+                    }
+                    if self.peek_token_is(TokenType::IDENT) {
+                        self.next();
+                        self.declare_parameter(&mut names, &self.current().literal)?;
+                        params.rest = Some(self.current().literal);
+                    } else {
+                        params.rest = Some(String::from("*"));
+                    }
+                },
+                TokenType::AMPERSAND => {
+                    if self.peek_token_is(TokenType::IDENT) {
+                        self.next();
+                        self.declare_parameter(&mut names, &self.current().literal)?;
+                        params.block = Some(self.current().literal);
+                    } else {
+                        params.block = Some(String::from("&"));
+                    }
+                },
+                _ => {
+                    let mut expected = vec!(TokenType::IDENT, TokenType::ASTERISK, TokenType::AMPERSAND);
+                    expected.extend_from_slice(closing);
+                    self.error(format!("unexpected {:?} in parameter list", self.current().token_type), &expected);
+                    return None;
+                }
+            }
+            self.next();
+            if bracketed { self.skip_newlines(); }
+            if self.cur_token_is(TokenType::COMMA) {
+                self.next();
+                self.skip_newlines();
+            } else if !closing.contains(&self.current().token_type) {
+                let mut expected = vec!(TokenType::COMMA);
+                expected.extend_from_slice(closing);
+                self.error(format!("expected `,` between parameters, got {:?} instead", self.current().token_type), &expected);
+                return None;
+            }
+        }
+
+        Some(params)
+    }
+
+    /// Parses a `(a, *b, (c, d))` pattern in a block's parameters, from its opening to its
+    /// closing parenthesis.
+    fn parse_parameter_pattern(&mut self, names: &mut Vec<String>) -> Option<AssignTarget> {
+        self.next();
+        self.skip_newlines();
+        let mut targets = vec!();
+        while !self.cur_token_is(TokenType::RPAREN) {
+            let splat = targets.iter().any(|target| matches!(target, AssignTarget::Splat(_)));
+            let target = match self.current().token_type {
+                TokenType::IDENT => {
+                    self.declare_parameter(names, &self.current().literal)?;
+                    AssignTarget::Local(self.current().literal)
+                },
+                TokenType::ASTERISK if !splat && self.peek_token_is(TokenType::IDENT) => {
+                    self.next();
+                    self.declare_parameter(names, &self.current().literal)?;
+                    AssignTarget::Splat(Some(Box::new(AssignTarget::Local(self.current().literal))))
+                },
+                TokenType::ASTERISK if !splat => AssignTarget::Splat(None),
+                TokenType::LPAREN => self.parse_parameter_pattern(names)?,
+                _ => {
+                    let expected = [TokenType::IDENT, TokenType::ASTERISK, TokenType::LPAREN, TokenType::RPAREN];
+                    self.error(format!("unexpected {:?} in parameter list", self.current().token_type), &expected);
+                    return None;
+                }
+            };
+            targets.push(target);
+            self.next();
+            self.skip_newlines();
+            if self.cur_token_is(TokenType::COMMA) {
+                self.next();
+                self.skip_newlines();
+            } else if !self.cur_token_is(TokenType::RPAREN) {
+                self.error(format!("expected `,` between parameters, got {:?} instead", self.current().token_type), &[TokenType::COMMA, TokenType::RPAREN]);
+                return None;
+            }
+        }
+
+        Some(AssignTarget::Nested(targets))
+    }
+
+    /// Declares a parameter as a local, unless the list already has one by that name. Names
+    /// starting with `_` can be used for several parameters that are ignored.
+    fn declare_parameter(&mut self, names: &mut Vec<String>, name: &str) -> Option<()> {
+        if !name.starts_with('_') && names.iter().any(|other| other == name) {
+            self.error(String::from("duplicated argument name"), &[]);
+            return None;
+        }
+        names.push(name.to_string());
+        self.declare_local(name);
+        Some(())
+    }
+
+    /// Parses a string with interpolated code, from its `"text#{` to its `}text"`.
+    fn parse_interpolated_string(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let parts = self.parse_interpolation(TokenType::STRINGEND, "string")?;
+        Some(self.node(NodeKind::InterpolatedStr(parts), start))
+    }
+
+    /// Parses a regexp with interpolated code, from its `/source#{` to its `}source/flags`.
+    fn parse_interpolated_regex(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        let parts = self.parse_interpolation(TokenType::REGEXPEND, "regexp")?;
+        let literal = self.current().literal;
+        let (_, flags) = literal.rsplit_once('/').expect("the lexer separates the flags with a slash");
+        let flags = self.regex_flags(flags)?;
+        Some(self.node(NodeKind::InterpolatedRegex(parts, flags), start))
+    }
+
+    /// Parses the parts of a string or regexp with interpolated code, up to the token that
+    /// ends it. The text between becomes `Str` nodes, and the code in each `#{}` a `Begin`
+    /// node, which may hold several statements or none.
+    fn parse_interpolation(&mut self, end: TokenType, literal: &str) -> Option<Vec<NodeId>> {
+        let mut parts = vec!();
+        loop {
+            let text = self.current().bytes;
+            if !text.is_empty() { parts.push(self.leaf(NodeKind::Str(text))); }
+            if self.cur_token_is(end) { break; }
+
+            let code_start = self.current_span.end;
+            let errors = self.errors.len();
+            self.next();
+            let statements = self.parse_statements(&[TokenType::STRINGMID, end]);
+            if self.cur_token_is(TokenType::EOF) {
+                // the rest of the literal may already have been found unterminated
+                if self.errors.len() == errors { self.error(format!("unterminated {} meets end of file", literal), &[]); }
+                return None;
+            }
+            let body = BodyStatement { statements, ..BodyStatement::default() };
+            parts.push(self.ast.push(NodeKind::Begin(body), Span::new(code_start, self.current_span.start)));
+        }
+
+        Some(parts)
+    }
+
+    fn parse_begin_expression(&mut self) -> Option<NodeId> {
+        let start = self.current_span.start;
+        self.next();
+        let body = self.parse_body_statement()?;
+        if !self.cur_token_is(TokenType::END) {
+            self.error(format!("expected `end` to close `begin`, got {:?} instead", self.current().token_type), &[TokenType::END]);
+            return None;
+        }
+
+        Some(self.node(NodeKind::Begin(body), start))
+    }
+
+    /// Parses statements followed by any `rescue`, `else` and `ensure` clauses, stopping on the
+    /// closing `end`.
+    fn parse_body_statement(&mut self) -> Option<BodyStatement> {
+        let clause_ends = [TokenType::RESCUE, TokenType::ELSE, TokenType::ENSURE, TokenType::END];
+        let mut body = BodyStatement {
+            statements: self.parse_statements(&clause_ends),
+            ..BodyStatement::default()
+        };
+
+        while self.cur_token_is(TokenType::RESCUE) {
+            let start = self.current_span.start;
+            let mut exception_classes = vec!();
+            let mut variable = None;
+            while !self.peek_is_end_of_line() && !self.peek_token_is(TokenType::THEN) && !self.peek_token_is(TokenType::HASHROCKET) {
+                self.next();
+                exception_classes.push(self.parse_expression(Precedence::Modifier)?);
+                if self.peek_token_is(TokenType::COMMA) {
+                    self.next();
+                    self.skip_peek_newlines();
+                }
+            }
+            if self.peek_token_is(TokenType::HASHROCKET) {
+                self.next();
+                if !self.expect_peek(TokenType::IDENT) { return None; }
+                self.declare_local(&self.current().literal);
+                variable = Some(self.current().literal);
+            }
+            let span = Span::new(start, self.current_span.end);
+            if self.peek_token_is(TokenType::THEN) { self.next(); }
+            self.next();
+
+            let body_statements = self.parse_statements(&clause_ends);
+            body.rescue_clauses.push(RescueClause { span, exception_classes, variable, body: body_statements });
+        }
+        if self.cur_token_is(TokenType::ELSE) {
+            body.else_span = Some(self.current_span);
+            self.next();
+            body.else_clause = Some(self.parse_statements(&[TokenType::ENSURE, TokenType::END]));
+        }
+        if self.cur_token_is(TokenType::ENSURE) {
+            body.ensure_span = Some(self.current_span);
+            self.next();
+            body.ensure_clause = Some(self.parse_statements(&[TokenType::END]));
         }
-    }
 
-    pub fn errors(&self) -> Vec<String> {
-        self.errors.clone()
+        Some(body)
     }
 
-    fn peek_error(&mut self, expected_type: TokenType) {
-        let msg = match self.peek() {
-            Some(token) => format!("expected next token to be {:?}, got {:?} instead", expected_type, token.token_type),
-            None => format!("expected next tokent to be {:?}, got EOF instead", expected_type)
-        };
-
-        self.errors.push(msg.clone());
+    fn is_method_name(&self, token: &Token) -> bool {
+        match token.token_type {
+            TokenType::IDENT | TokenType::CONSTANT => true,
+            _ => token.literal.starts_with(|c: char| c.is_ascii_lowercase()),
+        }
     }
 
-    pub fn current(&self) -> Token {
-        self.current_token.clone()
+    fn push_scope(&mut self, inherits: bool) {
+        self.scopes.push(LocalScope::new(inherits));
     }
 
-    pub fn peek(&self) -> Option<Token> {
-        self.peek_token.clone()
+    /// Records a use of `_1`..`_9` or `it` inside a block without explicit parameters, returning
+    /// whether `name` refers to one of the block's implicit parameters.
+    fn use_implicit_parameter(&mut self, name: &str) -> bool {
+        let scope = match self.scopes.last_mut() {
+            Some(scope) if scope.inherits && !scope.explicit_params => scope,
+            _ => return false
+        };
+        if name == "it" {
+            scope.uses_it = true;
+            return true;
+        }
+
+        let digit = name.strip_prefix('_').and_then(|digit| digit.parse::<usize>().ok());
+        match digit {
+            Some(n) if name.len() == 2 && n > 0 => {
+                scope.numbered = scope.numbered.max(n);
+                true
+            },
+            _ => false
+        }
     }
 
-    pub fn parse_program(&mut self) -> (Program, Vec<String>) {
-        let mut program = Program::new();
-        while !self.is_eof() {
-            let expr = self.parse_expression();
-            match expr {
-                Some(expression) => program.push(expression),
-                None => ()
-            }
-            self.next();
+    /// Treats the names as local variables assigned before the source starts, as the locals
+    /// of earlier lines are in the REPL.
+    pub fn declare_locals(&mut self, names: &[String]) {
+        for name in names {
+            self.declare_local(name);
         }
+    }
 
-        println!("finished parsing program. found {} errors.", self.errors().len());
-        return (program, self.errors());
+    fn declare_local(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.names.insert(name.to_string());
+        }
     }
 
-    fn parse_expression(&mut self) -> Option<Expression<'a>> {
-        match self.current().token_type {
-            TokenType::IDENT  => self.parse_ident_expression(),
-            TokenType::INT    => self.parse_integer(),
-            TokenType::RETURN => self.parse_return_expression(),
-            _ => None
+    fn is_local(&self, name: &str) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if scope.names.contains(name) { return true; }
+            if !scope.inherits { break; }
         }
+
+        false
     }
 
-    fn parse_ident_expression(&mut self) -> Option<Expression<'a>> {
+    /// Whether the next token can start the first argument of a call without parentheses.
+    /// Like Ruby, `foo [1]` and `foo -1` pass an argument while `foo[1]` and `foo - 1` don't.
+    fn peek_is_command_argument(&self) -> bool {
         match self.peek() {
-            Some(ref peek_token) => {
-                match peek_token.token_type {
-                    TokenType::ASSIGN => self.parse_assign_expression(),
-                    TokenType::SEMICOLON => self.create_ident_expression(),
-                    TokenType::EOF => self.create_ident_expression(),
-                    _ => None
-                }
+            Some(token) => match token.token_type {
+                TokenType::IDENT | TokenType::CONSTANT | TokenType::IVAR | TokenType::CVAR |
+                TokenType::GVAR | TokenType::INT | TokenType::FLOAT | TokenType::STRING | TokenType::STRINGBEGIN | TokenType::SYMBOL |
+                TokenType::REGEXP | TokenType::REGEXPBEGIN | TokenType::LABEL | TokenType::NIL | TokenType::TRUE | TokenType::FALSE | TokenType::SELF |
+                TokenType::LAMBDA | TokenType::TILDE | TokenType::DEF | TokenType::YIELD | TokenType::SUPER | TokenType::CASE |
+                TokenType::BEGIN => true,
+                TokenType::LPAREN | TokenType::LBRACKET | TokenType::BANG => self.peek_is_spaced(),
+                TokenType::MINUS | TokenType::PLUS | TokenType::ASTERISK | TokenType::AMPERSAND => {
+                    self.peek_is_spaced() && !self.lexer.is_space_at(self.peek_span.end)
+                },
+                _ => false
             },
-            None => None
+            None => false
         }
-
     }
 
-    fn parse_assign_expression(&mut self) -> Option<Expression<'a>> {
-        if !self.cur_token_is(TokenType::IDENT) { return None; }
-
-        let name = Node::Identifier(self.current().clone(), self.current().literal.clone());
-
-        if !self.expect_peek(TokenType::ASSIGN) { return None; }
-
-        self.next();
-        match self.parse_expression() {
-            Some(right_expr) => Some(Expression::Assign(self.current().clone(), name, Box::new(right_expr))),
-            None => None
+    fn peek_starts_expression(&self) -> bool {
+        match self.peek() {
+            Some(token) => matches!(token.token_type,
+                TokenType::IDENT | TokenType::CONSTANT | TokenType::IVAR | TokenType::CVAR |
+                TokenType::GVAR | TokenType::INT | TokenType::FLOAT | TokenType::STRING | TokenType::STRINGBEGIN | TokenType::SYMBOL |
+                TokenType::REGEXP | TokenType::REGEXPBEGIN | TokenType::NIL | TokenType::TRUE | TokenType::FALSE | TokenType::SELF | TokenType::BANG |
+                TokenType::TILDE | TokenType::NOT | TokenType::MINUS | TokenType::PLUS | TokenType::ASTERISK | TokenType::LPAREN |
+                TokenType::LBRACKET | TokenType::LBRACE | TokenType::LAMBDA | TokenType::DEF | TokenType::BEGIN |
+                TokenType::RETRY | TokenType::RETURN | TokenType::BREAK | TokenType::NEXT | TokenType::CASE |
+                TokenType::SUPER | TokenType::YIELD
+            ),
+            None => false
         }
     }
 
-    fn create_ident_expression(&mut self) -> Option<Expression<'a>> {
-        if !self.cur_token_is(TokenType::IDENT) { return None; }
+    fn peek_is_spaced(&self) -> bool {
+        self.peek_span.start > self.current_span.end
+    }
 
-        let ident = Some(Expression::Identifier(self.current().clone(), Node::Identifier(self.current().clone(), self.current().literal.clone())));
-        self.next();
-        return ident;
+    fn peek_is_block(&self) -> bool {
+        self.peek_token_is(TokenType::LBRACE) || (!self.no_do_block && self.peek_token_is(TokenType::DO))
     }
 
-    fn parse_integer(&mut self) -> Option<Expression<'a>> {
-        let current = self.current().clone();
-        while !self.is_end_of_expression() { self.next(); }
+    fn peek_is_end_of_line(&self) -> bool {
+        self.peek_token_is(TokenType::NEWLINE) || self.peek_token_is(TokenType::SEMICOLON) || self.peek_token_is(TokenType::EOF) || self.peek().is_none()
+    }
 
-        Some(Expression::Value(current, 0))
+    fn peek_precedence(&self) -> Precedence {
+        match self.peek() {
+            Some(token) => precedence_of(token.token_type),
+            None => Precedence::Lowest
+        }
     }
 
-    fn parse_return_expression(&mut self) -> Option<Expression<'a>> {
-        let return_token = self.current().clone();
-        self.next();
+    fn skip_separators(&mut self) {
+        while self.cur_token_is(TokenType::NEWLINE) || self.cur_token_is(TokenType::SEMICOLON) {
+            self.next();
+        }
+    }
 
-        match self.parse_expression() {
-            Some(right_expr) => Some(Expression::Return(return_token, Box::new(right_expr))),
-            None => None
+    fn skip_newlines(&mut self) {
+        while self.cur_token_is(TokenType::NEWLINE) {
+            self.next();
         }
     }
 
-    fn is_end_of_expression(&mut self) -> bool {
-        self.cur_token_is(TokenType::SEMICOLON) || self.cur_token_is(TokenType::NEWLINE) || self.is_eof()
+    fn skip_peek_newlines(&mut self) {
+        while self.peek_token_is(TokenType::NEWLINE) {
+            self.next();
+        }
     }
 
-    fn cur_token_is(&mut self, expected_type: TokenType) -> bool {
+    fn cur_token_is(&self, expected_type: TokenType) -> bool {
         self.current().token_type == expected_type
     }
 
     fn expect_peek(&mut self, expected_type: TokenType) -> bool {
         if self.peek_token_is(expected_type) {
             self.next();
-            return true;
+            true
         } else {
             self.peek_error(expected_type);
-            return false;
+            false
         }
     }
 
-    fn peek_token_is(&mut self, expected_type: TokenType) -> bool {
+    fn peek_token_is(&self, expected_type: TokenType) -> bool {
         match self.peek() {
             Some(token) => {
                 token.token_type == expected_type
@@ -151,10 +1712,6 @@ impl<'a> Parser<'a> {
             None => false
         }
     }
-
-    fn is_eof(&mut self) -> bool {
-        self.peek().is_none() || self.peek().unwrap().token_type == TokenType::EOF
-    }
 }
 
 impl<'a> Iterator for Parser<'a> {
@@ -165,7 +1722,11 @@ impl<'a> Iterator for Parser<'a> {
         match result.clone() {
             Some(token) => {
                 self.current_token = token;
+                self.current_span = self.peek_span;
+                let local = self.cur_token_is(TokenType::IDENT) && self.is_local(&self.current_token.literal);
+                self.lexer.set_after_local(local);
                 self.peek_token = self.lexer.next();
+                self.peek_span = self.lexer.span();
                 result
             },
             None => None
@@ -228,4 +1789,703 @@ mod test {
         assert_eq!(parser.current().token_type, TokenType::EOF);
         assert!(parser.peek().is_none());
     }
+
+    fn parse(input: &str) -> Program {
+        let mut lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+        assert!(errors.is_empty(), "parser errors: {:?}", errors);
+
+        program
+    }
+
+    fn statements_to_s(program: &Program) -> Vec<String> {
+        program.statements.iter().map(|id| program.ast.to_s(*id)).collect()
+    }
+
+    #[test]
+    fn should_parse_begin_with_all_clauses() {
+        let program = parse("begin
+  risky 1, 2
+rescue TypeA, Errors::TypeB => e
+  retry
+rescue
+  log e
+else
+  celebrate
+ensure
+  cleanup
+end");
+
+        assert_eq!(program.statements.len(), 1);
+        let ast = &program.ast;
+        let body = match &program.statement(0).kind {
+            NodeKind::Begin(body) => body,
+            kind => panic!("expected Begin, got {:?}", kind),
+        };
+        assert_eq!(body.statements.len(), 1);
+        assert_eq!(body.rescue_clauses.len(), 2);
+
+        let first = &body.rescue_clauses[0];
+        assert_eq!(first.exception_classes.len(), 2);
+        assert_eq!(ast.to_s(first.exception_classes[1]), "Errors::TypeB");
+        assert_eq!(first.variable, Some("e".to_string()));
+        assert!(matches!(ast[first.body[0]].kind, NodeKind::Retry));
+
+        let second = &body.rescue_clauses[1];
+        assert!(second.exception_classes.is_empty());
+        assert_eq!(second.variable, None);
+        assert_eq!(ast.to_s(second.body[0]), "log(e)");
+
+        assert_eq!(ast.to_s(body.else_clause.as_ref().unwrap()[0]), "celebrate");
+        assert_eq!(ast.to_s(body.ensure_clause.as_ref().unwrap()[0]), "cleanup");
+    }
+
+    #[test]
+    fn should_parse_rescue_modifier_inside_assignment() {
+        let program = parse("x = risky rescue nil");
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statement(0).kind {
+            NodeKind::Assign(AssignTarget::Local(name), value) => {
+                assert_eq!(name, "x");
+                match &program.ast[*value].kind {
+                    NodeKind::RescueModifier(expr, rescue) => {
+                        assert_eq!(program.ast.to_s(*expr), "risky");
+                        assert!(matches!(program.ast[*rescue].kind, NodeKind::Nil));
+                    },
+                    kind => panic!("expected RescueModifier, got {:?}", kind),
+                }
+            },
+            kind => panic!("expected Assign, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_parse_command_call_before_rescue_modifier() {
+        let program = parse("raise ArgumentError, 'bad' rescue nil");
+
+        match &program.statement(0).kind {
+            NodeKind::RescueModifier(expr, _) => assert_eq!(program.ast.to_s(*expr), "raise(ArgumentError, \"bad\")"),
+            kind => panic!("expected RescueModifier, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_parse_assignments_in_command_arguments() {
+        let program = parse("x = 1\np x += 1\nputs y ||= \"d\"\np a[0] = 5, z = 2\np w = 3 rescue nil");
+
+        assert_eq!(statements_to_s(&program)[1..], [
+            "p(x += 1\n)",
+            "puts(y ||= \"d\"\n)",
+            "p(a[0] = 5\n, z = 2\n)",
+            "p(w = 3 rescue nil\n)",
+        ]);
+    }
+
+    #[test]
+    fn should_parse_implicit_begin_in_def() {
+        let program = parse("def fetch(url, retries = 3, *rest)
+  get url
+rescue Timeout => e
+  retry
+ensure
+  close
+end");
+
+        match &program.statement(0).kind {
+            NodeKind::Def(name, params, body) => {
+                assert_eq!(name, "fetch");
+                assert_eq!(program.ast.params_to_s(params), "url, retries = 3, *rest");
+                assert_eq!(body.statements.len(), 1);
+                assert_eq!(body.rescue_clauses.len(), 1);
+                assert!(body.ensure_clause.is_some());
+            },
+            kind => panic!("expected Def, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_parse_parameter_lists_over_several_lines() {
+        let program = parse("def f(
+  a,
+  b = 1
+)
+  a
+end
+g = ->(
+  x
+) { x }
+each do |k,
+  v|
+end");
+
+        assert_eq!(statements_to_s(&program), vec!("def f(a, b = 1)\na\nend\n", "g = ->(x) {\nx\n}\n", "each do |k, v|\nend"));
+    }
+
+    #[test]
+    fn should_keep_required_parameters_after_optional_ones_and_the_rest_apart() {
+        let program = parse("def f(a, *r, b)\nend\ndef g(a = 1, b)\nend\ndef h(a, b = 2, *r, c, d, &blk)\nend");
+
+        let params: Vec<(Vec<String>, Vec<String>)> = program.statements.iter().map(|id| match &program.ast[*id].kind {
+            NodeKind::Def(_, params, _) => {
+                let names = |targets: &[AssignTarget]| targets.iter().map(|target| program.ast.target_to_s(target)).collect();
+                (names(&params.required), names(&params.post))
+            },
+            kind => panic!("expected Def, got {:?}", kind),
+        }).collect();
+        assert_eq!(params, vec!(
+            (vec!("a".to_string()), vec!("b".to_string())),
+            (vec!(), vec!("b".to_string())),
+            (vec!("a".to_string()), vec!("c".to_string(), "d".to_string())),
+        ));
+        assert_eq!(statements_to_s(&program), vec!("def f(a, *r, b)\nend\n", "def g(a = 1, b)\nend\n", "def h(a, b = 2, *r, c, d, &blk)\nend\n"));
+    }
+
+    #[test]
+    fn should_parse_implicit_begin_in_do_block() {
+        let program = parse("items.each do |item|
+  process item
+rescue
+  skip
+end");
+
+        let call = match &program.statement(0).kind {
+            NodeKind::Call(call) => call,
+            kind => panic!("expected Call, got {:?}", kind),
+        };
+        assert_eq!(call.method, "each");
+        match call.block.map(|block| &program.ast[block].kind) {
+            Some(NodeKind::Block(params, body)) => {
+                assert_eq!(params.required, vec!(AssignTarget::Local("item".to_string())));
+                assert_eq!(body.statements.len(), 1);
+                assert_eq!(body.rescue_clauses.len(), 1);
+            },
+            block => panic!("expected Block, got {:?}", block),
+        }
+    }
+
+    #[test]
+    fn should_attach_do_block_to_the_outer_command_call() {
+        let program = parse("wrap inner do
+  work
+end");
+
+        match &program.statement(0).kind {
+            NodeKind::Call(call) => {
+                assert_eq!(call.method, "wrap");
+                assert!(call.block.is_some());
+                assert_eq!(program.ast.to_s(call.arguments[0]), "inner");
+            },
+            kind => panic!("expected Call, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_parse_operators_as_method_calls() {
+        let program = parse("1 + 2 * 3 == -x");
+
+        assert_eq!(program.to_s(), "1.+(2.*(3)).==(x.-@)");
+    }
+
+    #[test]
+    fn should_parse_multiple_assignment() {
+        let program = parse("a, b = b, a
+first, *rest = list
+(x, y), z = [[1, 2], 3]
+head, = items
+*, last = items");
+
+        let expected = vec!(
+            ("a, b", "[b, a]"),
+            ("first, *rest", "list"),
+            ("(x, y), z", "[[1, 2], 3]"),
+            ("head, *", "items"),
+            ("*, last", "items"),
+        );
+        assert_eq!(program.statements.len(), expected.len());
+        for (id, (targets, value)) in program.statements.iter().zip(expected) {
+            match &program.ast[*id].kind {
+                NodeKind::MultipleAssign(lhs, rhs) => {
+                    let lhs: Vec<String> = lhs.iter().map(|target| program.ast.target_to_s(target)).collect();
+                    assert_eq!(lhs.join(", "), targets);
+                    assert_eq!(program.ast.to_s(*rhs), value);
+                },
+                kind => panic!("expected MultipleAssign, got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn should_gather_splats_and_value_lists_into_arrays() {
+        let program = parse("a = *range
+b = 1, 2");
+
+        assert_eq!(program.to_s(), "a = [*range]\nb = [1, 2]\n");
+    }
+
+    #[test]
+    fn should_gather_several_jump_values_into_an_array() {
+        let program = parse("def m\n  return 1, 2\nend\nloop { break 1, *rest }\nlist.map { next :a, :b }");
+        let source = program.to_s();
+
+        assert!(source.contains("return [1, 2]"), "{}", source);
+        assert!(source.contains("break [1, *rest]"), "{}", source);
+        assert!(source.contains("next [:a, :b]"), "{}", source);
+    }
+
+    #[test]
+    fn should_parse_assignment_targets() {
+        let program = parse("obj.name = x
+h[key, 2] = x
+Foo::BAR = 1
+@ivar = 2
+@@cvar = 3
+$global = 4");
+
+        let targets: Vec<String> = program.statements.iter().map(|id| match &program.ast[*id].kind {
+            NodeKind::Assign(target, _) => format!("{:?}", target).split('(').next().unwrap().to_string(),
+            kind => panic!("expected Assign, got {:?}", kind),
+        }).collect();
+        assert_eq!(targets, vec!("Attribute", "Index", "Constant", "InstanceVariable", "ClassVariable", "GlobalVariable"));
+        assert_eq!(program.to_s(), "obj.name = x\nh[key, 2] = x\nFoo::BAR = 1\n@ivar = 2\n@@cvar = 3\n$global = 4\n");
+    }
+
+    #[test]
+    fn should_use_known_locals_to_disambiguate_arguments() {
+        let program = parse("puts [1]
+x = [1]
+x [0]
+foo -1
+x -1");
+
+        assert_eq!(statements_to_s(&program), vec!("puts([1])", "x = [1]\n", "x[0]", "foo(-1)", "x.-(1)"));
+    }
+
+    #[test]
+    fn should_parse_op_assign_without_duplicating_the_target() {
+        let program = parse("total += 1
+@count -= 2
+$seen ||= 3
+LIMIT &&= 4
+obj.count += 1
+h[expensive_key] ||= compute
+h[a, b] *= 2");
+
+        let expected = vec!(
+            ("Local(\"total\")", "+", "1"),
+            ("InstanceVariable(\"@count\")", "-", "2"),
+            ("GlobalVariable(\"$seen\")", "||", "3"),
+            ("Constant(None, \"LIMIT\")", "&&", "4"),
+            ("obj.count", "+", "1"),
+            ("h[expensive_key]", "||", "compute"),
+            ("h[a, b]", "*", "2"),
+        );
+        assert_eq!(program.statements.len(), expected.len());
+        for (id, (target, op, value)) in program.statements.iter().zip(expected) {
+            match &program.ast[*id].kind {
+                NodeKind::OpAssign(lhs, operator, rhs) => {
+                    match lhs {
+                        AssignTarget::Attribute(_, _) | AssignTarget::Index(_, _) => assert_eq!(program.ast.target_to_s(lhs), target),
+                        _ => assert_eq!(format!("{:?}", lhs), target),
+                    }
+                    assert_eq!(operator, op);
+                    assert_eq!(program.ast.to_s(*rhs), value);
+                },
+                kind => panic!("expected OpAssign, got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn should_declare_locals_assigned_with_op_assign() {
+        let program = parse("memo ||= []
+memo [0]");
+
+        assert_eq!(program.ast.to_s(program.statements[1]), "memo[0]");
+    }
+
+    #[test]
+    fn should_reject_op_assign_to_multiple_targets() {
+        let mut lexer = Lexer::new("*a += 1".to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (_, errors) = parser.parse_program();
+
+        let messages: Vec<String> = errors.iter().map(|error| error.message.clone()).collect();
+        assert_eq!(messages, vec!("cannot use += on *a"));
+    }
+
+    #[test]
+    fn should_parse_stabby_lambdas() {
+        let program = parse("double = ->(x, y = 2) { x * y }
+noop = -> { }
+each = -> item, &blk do
+  blk.call item
+rescue
+  nil
+end");
+
+        let lambdas: Vec<(String, usize, usize)> = program.statements.iter().map(|id| match &program.ast[*id].kind {
+            NodeKind::Assign(_, value) => match &program.ast[*value].kind {
+                NodeKind::Lambda(params, body) => (program.ast.params_to_s(params), body.statements.len(), body.rescue_clauses.len()),
+                kind => panic!("expected Lambda, got {:?}", kind),
+            },
+            kind => panic!("expected Assign, got {:?}", kind),
+        }).collect();
+        assert_eq!(lambdas, vec!(
+            ("x, y = 2".to_string(), 1, 0),
+            ("".to_string(), 0, 0),
+            ("item, &blk".to_string(), 1, 1),
+        ));
+    }
+
+    #[test]
+    fn should_parse_lambda_and_proc_calls_with_blocks() {
+        let program = parse("lambda { |x| x }
+proc do end");
+
+        for (id, name) in program.statements.iter().zip(vec!("lambda", "proc")) {
+            match &program.ast[*id].kind {
+                NodeKind::Call(call) => {
+                    assert_eq!(call.method, name);
+                    assert!(matches!(call.block.map(|block| &program.ast[block].kind), Some(NodeKind::Block(_, _))));
+                },
+                kind => panic!("expected Call, got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn should_record_numbered_and_it_block_parameters() {
+        let program = parse("pairs.map { _1 + _2 }
+names.each { puts it }
+names.each { |name| it }
+-> { _1 }");
+
+        let params: Vec<(usize, bool)> = program.statements.iter().map(|id| match &program.ast[*id].kind {
+            NodeKind::Call(Call { block: Some(block), .. }) => match &program.ast[*block].kind {
+                NodeKind::Block(params, _) => (params.numbered, params.it),
+                kind => panic!("expected Block, got {:?}", kind),
+            },
+            NodeKind::Lambda(params, _) => (params.numbered, params.it),
+            kind => panic!("expected a block, got {:?}", kind),
+        }).collect();
+        assert_eq!(params, vec!((2, false), (0, true), (0, false), (1, false)));
+    }
+
+    #[test]
+    fn should_parse_block_pass_arguments() {
+        let program = parse("names.map(&:to_s)
+items.each(&blk)
+run 1, &callback
+def forward(*, &)
+  target(*, &)
+end");
+
+        let blocks: Vec<String> = program.statements.iter().take(3).map(|id| match &program.ast[*id].kind {
+            NodeKind::Call(call) => program.ast.to_s(call.block.unwrap()),
+            kind => panic!("expected Call, got {:?}", kind),
+        }).collect();
+        assert_eq!(blocks, vec!("&:to_s", "&blk", "&callback"));
+        match &program.statement(3).kind {
+            NodeKind::Def(_, params, body) => {
+                assert_eq!(program.ast.params_to_s(params), "*, &");
+                assert_eq!(program.ast.to_s(body.statements[0]), "target(*) &");
+            },
+            kind => panic!("expected Def, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_parse_classes_and_modules() {
+        let program = parse("module Shapes
+  class Square < Base
+    def self.unit
+      new(1)
+    end
+
+    def area
+      side * side
+    end
+  end
+end
+class << self
+  attr_reader :count
+end
+class Outer::Inner; end");
+
+        assert_eq!(statements_to_s(&program), vec!(
+            "module Shapes\nclass Square < Base\ndef self.unit()\nnew(1)\nend\ndef area()\nside.*(side)\nend\nend\nend\n",
+            "class << self\nattr_reader(:count)\nend\n",
+            "class Outer::Inner\nend\n",
+        ));
+    }
+
+    #[test]
+    fn should_parse_patterns_in_block_parameters() {
+        let program = parse("h.each_with_index { |(k, v), i| }\neach { |a, (b, *c, (d, e))| d }");
+
+        assert_eq!(statements_to_s(&program), vec!("h.each_with_index do |(k, v), i|\nend", "each do |a, (b, *c, (d, e))|\nd\nend"));
+    }
+
+    #[test]
+    fn should_reject_malformed_parameter_lists() {
+        for (input, expected) in [
+            ("def f(a b)\nend", "expected `,` between parameters, got IDENT instead"),
+            ("def f(a, a)\nend", "duplicated argument name"),
+            ("def f(*a, *b)\nend", "a parameter list can only have one rest parameter, before the required ones after it"),
+            ("def f(&b, c)\nend", "no parameter can follow the block parameter"),
+            ("def f(*r, a = 1)\nend", "optional parameters must come before the rest parameter and the required ones after them"),
+            ("each { |x, x| }", "duplicated argument name"),
+            ("each { |(a, a)| }", "duplicated argument name"),
+            ("each { |(*a, *b)| }", "unexpected ASTERISK in parameter list"),
+            ("def f((a, b))\nend", "unexpected LPAREN in parameter list"),
+        ] {
+            let mut lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(&mut lexer);
+            let (_, errors) = parser.parse_program();
+            assert_eq!(errors[0].message, expected, "{}", input);
+        }
+        parse("each { |_, _| }\ndef g(_a, _a)\nend");
+    }
+
+    #[test]
+    fn should_reject_class_names_that_are_not_constants() {
+        for (input, expected) in [
+            ("class foo\nend", "expected a constant to name the class, got IDENT instead"),
+            ("module M\n  def x\n  end\n", "expected `end` to close `module M`, got EOF instead"),
+        ] {
+            let mut lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(&mut lexer);
+            let (_, errors) = parser.parse_program();
+            assert_eq!(errors[0].message, expected);
+        }
+    }
+
+    #[test]
+    fn should_keep_integer_literals_beyond_i64() {
+        let program = parse("123456789012345678901234567890\n-9223372036854775809\nInteger(\"42\")");
+
+        assert_eq!(program.statement(0).kind, NodeKind::BigInteger(String::from("123456789012345678901234567890")));
+        assert_eq!(program.statement(1).kind, NodeKind::BigInteger(String::from("-9223372036854775809")));
+        assert_eq!(program.ast.to_s(program.statements[2]), "Integer(\"42\")");
+    }
+
+    #[test]
+    fn should_parse_integer_literals_in_other_bases() {
+        let program = parse("[0xff, 0B101, 0o17, 017, 0d19, 0xf_0, -0b11]\n0x1_0000_0000_0000_0000");
+
+        assert_eq!(statements_to_s(&program), ["[255, 5, 15, 15, 19, 240, -3]", "18446744073709551616"]);
+        let mut lexer = Lexer::new(String::from("p 08"));
+        let mut parser = Parser::new(&mut lexer);
+        let (_, errors) = parser.parse_program();
+        assert_eq!(errors[0].message, "could not parse 08 as an integer");
+    }
+
+    #[test]
+    fn should_parse_interpolated_strings() {
+        let program = parse("\"\\xff\"\n\"a#{x = 1; x + 1}b#{}\"\n\"#{\"in#{2}\"}\"");
+
+        assert_eq!(program.statement(0).kind, NodeKind::Str(vec!(0xff)));
+        assert_eq!(statements_to_s(&program)[1..], ["\"a#{x = 1; x.+(1)}b#{}\"", "\"#{\"in#{2}\"}\""]);
+        match &program.statement(1).kind {
+            NodeKind::InterpolatedStr(parts) => assert_eq!(parts.len(), 4),
+            kind => panic!("expected an interpolated string, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_report_unterminated_interpolated_strings() {
+        for input in ["p \"a#{1", "p \"a#{1}b", "p \"#{\"a"] {
+            let mut lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(&mut lexer);
+            let (_, errors) = parser.parse_program();
+            let messages: Vec<String> = errors.iter().map(|error| error.message.clone()).collect();
+            assert_eq!(messages, ["unterminated string meets end of file"], "{}", input);
+        }
+    }
+
+    #[test]
+    fn should_parse_super_with_and_without_arguments() {
+        let program = parse("def initialize(name, *rest)
+  super
+  super()
+  super name, 1
+  super(name) { |x| x }
+  super(&blk)
+end");
+
+        let supers: Vec<(Option<usize>, bool)> = match &program.statement(0).kind {
+            NodeKind::Def(_, _, body) => body.statements.iter().map(|id| match &program.ast[*id].kind {
+                NodeKind::Super(arguments, block) => (arguments.as_ref().map(Vec::len), block.is_some()),
+                kind => panic!("expected Super, got {:?}", kind),
+            }).collect(),
+            kind => panic!("expected Def, got {:?}", kind),
+        };
+        assert_eq!(supers, vec!((None, false), (Some(0), false), (Some(2), false), (Some(1), true), (Some(0), true)));
+    }
+
+    #[test]
+    fn should_parse_yield_with_and_without_arguments() {
+        let program = parse("def each\n  yield\n  yield 1, x\n  yield(2) + 1\n  yield [3]\nend");
+
+        let statements: Vec<String> = match &program.statement(0).kind {
+            NodeKind::Def(_, _, body) => body.statements.iter().map(|id| program.ast.to_s(*id)).collect(),
+            kind => panic!("expected Def, got {:?}", kind),
+        };
+        assert_eq!(statements, vec!("yield()", "yield(1, x)", "yield(2).+(1)", "yield([3])"));
+    }
+
+    #[test]
+    fn should_recover_from_errors_at_the_next_statement() {
+        let input = "x = 1
+y = (2
+z = 3
+w = )
+def broken(a, 1)
+  v = 4
+end
+u = 5";
+        let mut lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+
+        assert_eq!(statements_to_s(&program), vec!("x = 1\n", "z = 3\n", "v = 4\n", "u = 5\n"));
+
+        let described: Vec<String> = errors.iter().map(|error| error.describe(input)).collect();
+        assert_eq!(described, vec!(
+            "3:1: expected next token to be RPAREN, got IDENT instead",
+            "4:5: unexpected RPAREN, expected an expression",
+            "5:15: unexpected INT in parameter list",
+            "7:1: unexpected END, expected an expression",
+        ));
+        assert_eq!(errors[0].expected, vec!(TokenType::RPAREN));
+        assert_eq!(errors[0].span, Span::new(13, 14));
+        assert_eq!(errors[2].expected, vec!(TokenType::IDENT, TokenType::ASTERISK, TokenType::AMPERSAND, TokenType::RPAREN));
+    }
+
+    #[test]
+    fn should_require_a_separator_between_statements() {
+        let described = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(&mut lexer);
+            let (_, errors) = parser.parse_program();
+            errors.iter().map(|error| error.describe(input)).collect::<Vec<String>>()
+        };
+
+        assert_eq!(described("x = 1 2\ny = 3"), vec!("1:7: unexpected INT, expecting end of statement"));
+        assert_eq!(described("p :a :b"), vec!("1:6: unexpected SYMBOL, expecting end of statement"));
+        assert_eq!(described("def f\n  g 1 'a'\nend"), vec!("2:7: unexpected STRING, expecting end of statement"));
+        assert!(described("a = 1; b = 2\n[a].each { |x| p x }\np a if b").is_empty());
+
+        assert_eq!(statements_to_s(&parse("p ~5")), vec!("p(5.~)"));
+        assert_eq!(statements_to_s(&parse("private def secret\nend")).len(), 1);
+    }
+
+    #[test]
+    fn should_keep_parsing_a_body_after_a_bad_statement() {
+        let mut lexer = Lexer::new("def ok\n  a = (\n  b = 2\nend\nc = 3".to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.statements.len(), 2);
+        match &program.statement(0).kind {
+            NodeKind::Def(name, _, body) => {
+                assert_eq!(name, "ok");
+                assert_eq!(body.statements.len(), 0);
+            },
+            kind => panic!("expected Def, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_span_compound_nodes_from_their_first_token() {
+        let input = "list[0] ||= -> { 1 }\nFoo::Bar.new(x) rescue nil";
+        let program = parse(input);
+        let source = |id: NodeId| {
+            let span = program.ast[id].span;
+            &input[span.start..span.end]
+        };
+
+        assert_eq!(source(program.statements[0]), "list[0] ||= -> { 1 }");
+        assert_eq!(source(program.statements[1]), "Foo::Bar.new(x) rescue nil");
+        match &program.statement(1).kind {
+            NodeKind::RescueModifier(call, _) => assert_eq!(source(*call), "Foo::Bar.new(x)"),
+            kind => panic!("expected RescueModifier, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_follow_ruby_operator_precedence() {
+        let program = parse("a || b && c == d
+x = y and z
+1 + 2 * 3 ** 2 ** 2 % 4
+-2 ** 2
+-2.abs
+not a == b
+a < b | c & d << 1
+1..n - 1");
+
+        assert_eq!(statements_to_s(&program), vec!(
+            "(a || (b && c.==(d)))",
+            "(x = y\n && z)",
+            "1.+(2.*(3.**(2.**(2))).%(4))",
+            "2.**(2).-@",
+            "-2.abs",
+            "a.==(b).!",
+            "a.<(b.|(c.&(d.<<(1))))",
+            "(1..n.-(1))",
+        ));
+    }
+
+    #[test]
+    fn should_parse_conditionals_and_loops() {
+        let program = parse("if a then b elsif c
+  d
+else
+  e
+end
+puts x unless done
+n += 1 while n < 10
+until ready do wait end
+value = ok ? 1 : 2
+case x
+when 1, 2 then :low
+when 3
+  :mid
+else
+  :high
+end
+loop do break 1 if a; next end");
+
+        assert_eq!(statements_to_s(&program), vec!(
+            "if a\nb\nelse\nif c\nd\nelse\ne\nend\nend\n",
+            "unless done\nputs(x)\nend\n",
+            "while n.<(10)\nn += 1\nend\n",
+            "until ready\nwait\nend\n",
+            "value = if ok\n1\nelse\n2\nend\n\n",
+            "case x\nwhen 1, 2\n:low\nwhen 3\n:mid\nelse\n:high\nend\n",
+            "loop do\nif a\nbreak 1\nend\nnext\nend",
+        ));
+    }
+
+    #[test]
+    fn should_parse_hashes_and_ranges() {
+        let program = parse("h = { a: 1, \"b\" => 2, if: 3 }
+render :show, status: 200, layout: false
+f(1, key: :v)
+(1..)
+(..5)
+(0...n)");
+
+        assert_eq!(statements_to_s(&program), vec!(
+            "h = {:a => 1, \"b\" => 2, :if => 3}\n",
+            "render(:show, {:status => 200, :layout => false})",
+            "f(1, {:key => :v})",
+            "(1..)",
+            "(..5)",
+            "(0...n)",
+        ));
+    }
 }
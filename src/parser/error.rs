@@ -0,0 +1,30 @@
+use std::fmt;
+
+use crate::lexer::token::{ Span, TokenType };
+
+/// A syntax error, located at the token that caused it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message:  String,
+    pub span:     Span,
+    /// The tokens that would have been accepted instead, when the parser knows them
+    pub expected: Vec<TokenType>,
+}
+
+impl ParseError {
+    pub fn new(message: String, span: Span, expected: Vec<TokenType>) -> Self {
+        Self { message, span, expected }
+    }
+
+    /// Formats the error as `line:column: message` for the source it was found in.
+    pub fn describe(&self, source: &str) -> String {
+        let (line, column) = self.span.line_and_column(source);
+        format!("{}:{}: {}", line, column, self.message)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -1,35 +1,67 @@
-use crate::ast::node::Node;
-use crate::ast::expressions::Expression;
-use crate::lexer::token::{ Token, TokenType };
+use crate::ast::dump::{ self, Dumper };
+use crate::ast::node::{ Ast, Node, NodeId };
+use crate::lexer::token::Trivia;
+
+/// A parsed program: the arena owning every node, the ids of its top level statements, and the
+/// comments and blank lines found between them.
+pub struct Program {
+    pub ast:        Ast,
+    pub statements: Vec<NodeId>,
+    pub trivia:     Vec<Trivia>,
+}
 
-pub struct Program<'a> {
-    pub expressions: Vec<Expression<'a>>
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<'a> Program<'a> {
-    pub fn new() -> Program<'a> {
-        Program { expressions: vec!() }
+impl Program {
+    pub fn new() -> Program {
+        Program { ast: Ast::new(), statements: vec!(), trivia: vec!() }
+    }
+
+    pub fn push(&mut self, statement: NodeId) {
+        self.statements.push(statement);
+    }
+
+    /// The node of the top level statement at `index`.
+    pub fn statement(&self, index: usize) -> &Node {
+        &self.ast[self.statements[index]]
+    }
+
+    /// Whether both programs have the same statements, ignoring spans, ids and trivia.
+    pub fn same_shape(&self, other: &Program) -> bool {
+        self.ast.same_list(&self.statements, &other.ast, &other.statements)
     }
 
-    pub fn push(&mut self, expression: Expression<'a>) {
-        self.expressions.push(expression);
+    /// The tree as an S-expression, with the byte span of every node.
+    pub fn to_sexp(&self) -> String {
+        dump::sexp(&Dumper::new(&self.ast).program(&self.statements))
+    }
+
+    /// The tree and its comments as JSON, with the byte span of every node.
+    pub fn to_json(&self) -> String {
+        dump::json_with_comments(&Dumper::new(&self.ast).program(&self.statements), &self.trivia)
     }
 
     pub fn to_s(&self) -> String {
         let mut string = String::from("");
 
-        for expr in self.expressions.iter() {
-            string = string + &expr.to_s();
+        for statement in self.statements.iter() {
+            string += &self.ast.to_s(*statement);
         }
 
-        return string;
+        string
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::lexer::Lexer;
-    use crate::parser::Parser;
+    use crate::lexer::token::Span;
+    use crate::parser::{ Parser, ParseError };
+    use crate::ast::node::{ AssignTarget, NodeKind };
     use super::*;
 
     #[test]
@@ -41,10 +73,10 @@ mod test {
         let (program, errors) = parser.parse_program();
         check_parser_errors(errors);
 
-        assert_eq!(program.expressions.len(), 3);
-        test_assign_expression(program.expressions[0].clone(), "x".to_string());
-        test_assign_expression(program.expressions[1].clone(), "y".to_string());
-        test_assign_expression(program.expressions[2].clone(), "foobar".to_string());
+        assert_eq!(program.statements.len(), 3);
+        test_assign_expression(program.statement(0), "x".to_string());
+        test_assign_expression(program.statement(1), "y".to_string());
+        test_assign_expression(program.statement(2), "foobar".to_string());
     }
 
     #[test]
@@ -60,14 +92,16 @@ mod test {
         let (program, errors) = parser.parse_program();
         check_parser_errors(errors);
 
-        assert_eq!(program.expressions.len(), 3);
-        for expr in program.expressions.iter() {
-            println!("Checking expression {:?}", expr);
-            match expr {
-                Expression::Return(token, _) => assert_eq!(token.literal, "return"),
-                Expression::Assign(_, _, _) => panic!("expected Return, got Assign"),
-                Expression::Value(_, _) => panic!("expected Return, got Value"),
-                Expression::Identifier(_, _) => panic!("expected Assign, got Identifier"),
+        assert_eq!(program.statements.len(), 3);
+        for id in program.statements.iter() {
+            let node = &program.ast[*id];
+            println!("Checking expression {:?}", node);
+            match node.kind {
+                NodeKind::Return(_) => assert!(input[node.span.start..].starts_with("return")),
+                NodeKind::Assign(_, _) => panic!("expected Return, got Assign"),
+                NodeKind::Integer(_) => panic!("expected Return, got Integer"),
+                NodeKind::Identifier(_) => panic!("expected Assign, got Identifier"),
+                _ => panic!("expected Return, got {:?}", node),
             }
         }
     }
@@ -75,38 +109,68 @@ mod test {
     #[test]
     fn should_print_a_program() {
         let mut program = Program::new();
-        let assign_expression = Expression::Assign(
-            Token::new(TokenType::ASSIGN, "=".to_string()),
-            Node::Identifier(
-                Token::new(TokenType::IDENT, "my_var".to_string()),
-                "my_var".to_string()
-            ),
-            Box::new(Expression::Value(
-                Token::new(TokenType::IDENT, "another_var".to_string()),
-                5
-            ))
-
+        let value = program.ast.push(NodeKind::Identifier("another_var".to_string()), Span::default());
+        let assign_expression = program.ast.push(
+            NodeKind::Assign(AssignTarget::Local("my_var".to_string()), value),
+            Span::default()
         );
         program.push(assign_expression);
         assert_eq!(program.to_s(), "my_var = another_var\n");
     }
 
-    fn test_assign_expression(e: Expression, expected_name: String) {
-        match e {
-            Expression::Assign(_, identifier, _) => {
+    #[test]
+    fn should_span_the_source_of_each_node() {
+        let input = "total = items.sum + 1\nputs(total)";
+        let mut lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+        check_parser_errors(errors);
+
+        let sources: Vec<&str> = program.statements.iter()
+            .map(|id| program.ast[*id].span)
+            .map(|span| &input[span.start..span.end])
+            .collect();
+        assert_eq!(sources, vec!("total = items.sum + 1", "puts(total)"));
+
+        match &program.statement(0).kind {
+            NodeKind::Assign(_, value) => {
+                let span = program.ast[*value].span;
+                assert_eq!(&input[span.start..span.end], "items.sum + 1");
+            },
+            kind => panic!("expected Assign, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn should_number_nodes_densely() {
+        let mut lexer = Lexer::new("a = [1, 2]\nb = a".to_string());
+        let mut parser = Parser::new(&mut lexer);
+        let (program, errors) = parser.parse_program();
+        check_parser_errors(errors);
+
+        for (index, node) in program.ast.nodes().enumerate() {
+            assert_eq!(node.id.index(), index);
+        }
+        assert!(program.statements.iter().all(|id| id.index() < program.ast.len()));
+    }
+
+    fn test_assign_expression(node: &Node, expected_name: String) {
+        match &node.kind {
+            NodeKind::Assign(identifier, _) => {
                 match identifier {
-                    Node::Identifier(_, name) => assert_eq!(name, expected_name),
-                    _ => panic!("Right expression type, wrong Node type")
+                    AssignTarget::Local(name) => assert_eq!(*name, expected_name),
+                    _ => panic!("Right expression type, wrong target type")
                 }
             },
-            Expression::Value(_, _) => panic!("expected Assign, got Value"),
-            Expression::Return(_, _) => panic!("expected Assign, got Return"),
-            Expression::Identifier(_, _) => panic!("expected Assign, got Identifier"),
+            NodeKind::Integer(_) => panic!("expected Assign, got Integer"),
+            NodeKind::Return(_) => panic!("expected Assign, got Return"),
+            NodeKind::Identifier(_) => panic!("expected Assign, got Identifier"),
+            _ => panic!("expected Assign, got {:?}", node),
         }
     }
 
-    fn check_parser_errors(errors: Vec<String>) {
-        if errors.len() == 0 { return; }
+    fn check_parser_errors(errors: Vec<ParseError>) {
+        if errors.is_empty() { return; }
 
         println!("parser has {} errors", errors.len());
         for msg in errors.iter() {
@@ -120,14 +184,9 @@ mod test {
         let mut lexer = Lexer::new(String::from("foobar;"));
         let mut parser = Parser::new(&mut lexer);
         let (program, _errors) = parser.parse_program();
-        assert_eq!(program.expressions.len(), 1);
-        match &program.expressions[0] {
-            Expression::Identifier(_token, node) => {
-                match node {
-                    Node::Identifier(_token, name) => assert_eq!(name, "foobar"),
-                    _ => panic!("Wrong Node type in Identifier expression")
-                }
-            },
+        assert_eq!(program.statements.len(), 1);
+        match &program.statement(0).kind {
+            NodeKind::Identifier(name) => assert_eq!(name, "foobar"),
             _ => panic!("Wrong expression type")
         }
     }
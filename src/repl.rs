@@ -0,0 +1,249 @@
+pub mod completion;
+pub mod history;
+mod editor;
+
+use crate::eval::{ Control, Interpreter };
+use crate::eval::value::Value;
+use crate::lexer::Lexer;
+use crate::parser::{ Parser, ParseError };
+use completion::Completion;
+use editor::{ Editor, Input };
+use history::History;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = "?> ";
+
+/// The name the REPL's input goes by in backtraces and syntax errors.
+const FILE: &str = "(rri)";
+
+/// What became of a line given to the REPL.
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    /// The input so far is incomplete, like an open `def` or string, and needs more lines
+    More,
+    /// There was nothing to run
+    Nothing,
+    /// The input ran; this is the `=> inspect` of its value
+    Value(String),
+    /// The input didn't parse or raised an exception, described here
+    Error(String),
+    /// The input called `exit` or `abort`, asking for this exit status
+    Exit(i32),
+}
+
+/// An irb-like session: each complete input runs at the top level of the same interpreter, so
+/// locals, methods and classes carry over, and `_` holds the last value.
+pub struct Repl {
+    interpreter: Interpreter,
+    /// The lines of an input that isn't complete yet
+    buffer:      String,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl { interpreter: Interpreter::new(), buffer: String::new() }
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// `>> `, or `?> ` while an input continues over several lines.
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT }
+    }
+
+    /// Drops the lines of an unfinished input, as Ctrl-C does.
+    pub fn discard(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Adds a line to the input, and runs the input once it's complete.
+    pub fn feed(&mut self, line: &str) -> Step {
+        self.buffer.push_str(line);
+        if !self.buffer.ends_with('\n') { self.buffer.push('\n'); }
+        let source = self.buffer.clone();
+
+        let mut lexer = Lexer::new(source.clone());
+        let mut parser = Parser::new(&mut lexer);
+        parser.declare_locals(&self.interpreter.top_level_locals());
+        let (mut program, errors) = parser.parse_program();
+        if is_incomplete(&source, &errors) { return Step::More; }
+
+        self.buffer.clear();
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors.iter().map(|error| format!("{}:{}", FILE, error.describe(&source))).collect();
+            return Step::Error(messages.join("\n"));
+        }
+        if program.statements.is_empty() { return Step::Nothing; }
+        program.ast.set_file(FILE);
+
+        Interpreter::clear_interrupt();
+        let result = self.interpreter.eval_program(program);
+        let _ = self.interpreter.flush_output();
+        let value = match result {
+            Ok(value) => value,
+            Err(Control::Raise(exception)) => return self.failure(&exception),
+            Err(_) => unreachable!("eval_program only lets exceptions escape"),
+        };
+        self.interpreter.set_top_level_local("_", value.clone());
+        match self.interpreter.inspect(&value) {
+            Ok(inspected) => Step::Value(format!("=> {}", inspected)),
+            Err(Control::Raise(exception)) => self.failure(&exception),
+            Err(_) => unreachable!("inspect only lets exceptions escape"),
+        }
+    }
+
+    /// The completions of the word at the end of the text, from what the session has defined.
+    pub fn complete(&mut self, text: &str) -> Completion {
+        completion::complete(&mut self.interpreter, text)
+    }
+
+    fn failure(&mut self, exception: &Value) -> Step {
+        match self.interpreter.exit_status(exception) {
+            Some(status) => Step::Exit(status),
+            None => Step::Error(self.interpreter.report(exception)),
+        }
+    }
+}
+
+/// Whether the input stopped before it was complete: its first syntax error is at the very
+/// end, like a missing `end` or `)`, or is a string that never closed.
+pub fn is_incomplete(source: &str, errors: &[ParseError]) -> bool {
+    match errors.first() {
+        Some(error) => error.span.start >= source.trim_end().len() || error.message.starts_with("unterminated"),
+        None => false,
+    }
+}
+
+/// Runs a REPL on the terminal until Ctrl-D or `exit`, returning the exit status. Lines
+/// entered are kept in the history file from `History::default_path`.
+pub fn run(mut repl: Repl) -> i32 {
+    signals::catch_interrupts();
+    let history = History::default_path().map_or_else(History::new, History::load);
+    let mut editor = Editor::new(history);
+    loop {
+        let prompt = repl.prompt();
+        let line = match editor.read_line(prompt, &mut |text| repl.complete(text)) {
+            Ok(Input::Line(line)) => line,
+            Ok(Input::Interrupted) => {
+                Interpreter::clear_interrupt();
+                repl.discard();
+                println!();
+                continue;
+            },
+            Ok(Input::End) => {
+                println!();
+                return 0;
+            },
+            Err(error) => {
+                eprintln!("rri: {}", error);
+                return 1;
+            },
+        };
+
+        match repl.feed(&line) {
+            Step::More | Step::Nothing => {},
+            Step::Value(value) => println!("{}", value),
+            Step::Error(message) => eprintln!("{}", message),
+            Step::Exit(status) => return status,
+        }
+    }
+}
+
+/// Ctrl-C raises `Interrupt` in the running code, or interrupts reading a line, instead of
+/// ending the process.
+#[cfg(unix)]
+mod signals {
+    use std::os::raw::c_int;
+
+    use crate::eval::Interpreter;
+
+    const SIGINT: c_int = 2;
+
+    extern "C" {
+        fn signal(signal: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn siginterrupt(signal: c_int, interrupt: c_int) -> c_int;
+    }
+
+    extern "C" fn on_interrupt(_signal: c_int) {
+        Interpreter::interrupt();
+    }
+
+    pub fn catch_interrupts() {
+        // SAFETY: the handler only stores to an atomic, which is safe in a signal handler
+        unsafe {
+            signal(SIGINT, on_interrupt);
+            siginterrupt(SIGINT, 1);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn catch_interrupts() {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Feeds each line in turn, returning what each step came to.
+    fn session(lines: &[&str]) -> Vec<Step> {
+        let mut repl = Repl::new();
+        repl.interpreter().set_output(Box::new(std::io::sink()));
+        lines.iter().map(|line| repl.feed(line)).collect()
+    }
+
+    fn value(inspected: &str) -> Step {
+        Step::Value(format!("=> {}", inspected))
+    }
+
+    #[test]
+    fn should_keep_the_top_level_between_inputs() {
+        assert_eq!(session(&["x = 5", "def double(n); n * 2; end", "double(x)"]), [value("5"), value(":double"), value("10")]);
+        assert_eq!(
+            session(&["x = 5", "x -1", "class Point; end", "Point.new.class", "_", "[1, 2].map { |n| n + x }"]),
+            [value("5"), value("4"), value("nil"), value("Point"), value("Point"), value("[6, 7]")],
+        );
+        assert_eq!(session(&["", "# a comment", "  "]), [Step::Nothing, Step::Nothing, Step::Nothing]);
+    }
+
+    #[test]
+    fn should_continue_incomplete_input() {
+        assert_eq!(
+            session(&["def add(a, b)", "a + b", "end", "add(1,", "2)", "[1, 2].each do |n|", "end"]),
+            [Step::More, Step::More, value(":add"), Step::More, value("3"), Step::More, value("[1, 2]")],
+        );
+        assert_eq!(session(&["s = 'a", "b'", "{ a: 1,", "b: 2 }"]), [Step::More, value("\"a\\nb\""), Step::More, value("{a: 1, b: 2}")]);
+
+        let mut repl = Repl::new();
+        assert_eq!(repl.prompt(), ">> ");
+        assert_eq!(repl.feed("if true"), Step::More);
+        assert_eq!(repl.prompt(), "?> ");
+        repl.discard();
+        assert_eq!(repl.feed("1"), value("1"));
+    }
+
+    #[test]
+    fn should_report_errors_and_carry_on() {
+        let steps = session(&["x = )", "1 / 0", "end", "x = 1", "x"]);
+        assert_eq!(steps[0], Step::Error(String::from("(rri):1:5: unexpected RPAREN, expected an expression")));
+        assert_eq!(steps[1], Step::Error(String::from("(rri):1:in '<main>': divided by 0 (ZeroDivisionError)")));
+        assert!(matches!(steps[2], Step::Error(_)));
+        assert_eq!(steps[3..], [value("1"), value("1")]);
+    }
+
+    #[test]
+    fn should_exit_with_the_status_asked_for() {
+        assert_eq!(session(&["exit"]), [Step::Exit(0)]);
+        assert_eq!(session(&["begin", "exit 3", "ensure", "1", "end"])[4], Step::Exit(3));
+        assert_eq!(session(&["abort"]), [Step::Exit(1)]);
+    }
+}
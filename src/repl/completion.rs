@@ -0,0 +1,163 @@
+use crate::eval::Interpreter;
+use crate::eval::value::Value;
+use crate::lexer::Lexer;
+use crate::lexer::token::KEYWORDS;
+use crate::parser::Parser;
+
+/// The words the word before the cursor could be completed to.
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+    /// Where the word starts, in chars from the start of the line
+    pub start:      usize,
+    /// Every word it could become, sorted
+    pub candidates: Vec<String>,
+}
+
+/// Completes the word the text ends with: to a constant after `Module::`, to a method after
+/// `receiver.`, and otherwise to a local variable, keyword, constant, or method callable at
+/// the top level.
+///
+/// The receiver is evaluated to find its methods only when that can't run any code: when
+/// it's a local, constant, instance or global variable, or a literal.
+pub fn complete(interpreter: &mut Interpreter, text: &str) -> Completion {
+    let chars: Vec<char> = text.chars().collect();
+    let mut start = chars.len();
+    while start > 0 && is_word(chars[start - 1]) { start -= 1; }
+    let word: String = chars[start..].iter().collect();
+    let before: String = chars[..start].iter().collect();
+    if word.starts_with(|c: char| c.is_ascii_digit()) { return Completion { start, candidates: vec!() }; }
+
+    let names = if let Some(receiver) = before.strip_suffix("::") {
+        match receiver_value(interpreter, receiver) {
+            Some(Value::Class(module)) => module.constants.borrow().keys().cloned().collect(),
+            _ => vec!(),
+        }
+    } else if let Some(receiver) = before.strip_suffix('.') {
+        match receiver_value(interpreter, receiver) {
+            // `1..e` is a range
+            _ if receiver.ends_with('.') => vec!(),
+            Some(value) => interpreter.method_names(&value, false),
+            None => vec!(),
+        }
+    } else {
+        let mut names = interpreter.top_level_locals();
+        names.extend(KEYWORDS.keys().map(|keyword| keyword.to_string()));
+        names.extend(interpreter.classes.object.constants.borrow().keys().cloned());
+        let main = interpreter.main().clone();
+        names.extend(interpreter.method_names(&main, true));
+        names
+    };
+
+    let mut candidates: Vec<String> = names.into_iter()
+        .filter(|name| name.starts_with(&word) && name.starts_with(is_word))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    Completion { start, candidates }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The value of the expression the text ends with, if it's one that is safe to evaluate.
+fn receiver_value(interpreter: &mut Interpreter, text: &str) -> Option<Value> {
+    let receiver = receiver(text)?;
+    let first = receiver.chars().next()?;
+    if first.is_lowercase() || first == '_' {
+        let is_literal = matches!(receiver, "self" | "nil" | "true" | "false");
+        if !is_literal && !interpreter.top_level_locals().iter().any(|local| local == receiver) { return None; }
+    }
+    // `Module::name` calls a method
+    if receiver.split("::").skip(1).any(|name| !name.starts_with(char::is_uppercase)) { return None; }
+    if receiver.ends_with(']') { return Some(Value::array(vec!())); }
+    // `"#{code}"` and `/#{code}/` run the code
+    if receiver.starts_with(['"', '/']) && receiver.contains("#{") { return None; }
+
+    let mut lexer = Lexer::new(receiver.to_string());
+    let mut parser = Parser::new(&mut lexer);
+    parser.declare_locals(&interpreter.top_level_locals());
+    let (program, errors) = parser.parse_program();
+    if !errors.is_empty() { return None; }
+    interpreter.eval_program(program).ok()
+}
+
+/// The expression at the end of the text that a receiver can be taken from: a quoted string,
+/// an array literal, or a run of names and `::`, which may start with `@`, `$` or `:`.
+fn receiver(text: &str) -> Option<&str> {
+    let last = text.chars().last()?;
+    let start = match last {
+        '"' | '\'' => text[..text.len() - 1].rfind(last)?,
+        ']' => {
+            let mut depth = 0;
+            let (start, _) = text.char_indices().rev().find(|(_, c)| {
+                match c {
+                    ']' => depth += 1,
+                    '[' => depth -= 1,
+                    _ => {},
+                }
+                depth == 0
+            })?;
+            // `list[0]` indexes rather than making an array
+            if text[..start].ends_with(|c: char| is_word(c) || c == ']' || c == ')') { return None; }
+            start
+        },
+        _ => {
+            let start = text.rfind(|c: char| !(is_word(c) || c == ':')).map_or(0, |i| i + 1);
+            let sigils = text[..start].len() - text[..start].trim_end_matches(['@', '$']).len();
+            start - sigils
+        },
+    };
+    Some(&text[start..]).filter(|receiver| !receiver.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candidates(interpreter: &mut Interpreter, text: &str) -> Vec<String> {
+        complete(interpreter, text).candidates
+    }
+
+    #[test]
+    fn should_complete_locals_keywords_and_constants() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_top_level_local("value", Value::Integer(1));
+        interpreter.set_top_level_local("values", Value::Integer(2));
+
+        assert_eq!(complete(&mut interpreter, "x = val"), Completion { start: 4, candidates: vec!(String::from("value"), String::from("values")) });
+        assert_eq!(candidates(&mut interpreter, "whi"), ["while"]);
+        assert_eq!(candidates(&mut interpreter, "Comparab"), ["Comparable"]);
+        assert_eq!(candidates(&mut interpreter, "Float::INF"), ["INFINITY"]);
+        assert!(candidates(&mut interpreter, "pu").contains(&String::from("puts")));
+        assert!(candidates(&mut interpreter, "12").is_empty());
+    }
+
+    #[test]
+    fn should_complete_methods_of_the_receiver() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_top_level_local("name", Value::string("rri"));
+
+        let methods = candidates(&mut interpreter, "name.up");
+        assert!(methods.contains(&String::from("upcase")));
+        assert!(methods.iter().all(|method| method.starts_with("up")));
+        assert_eq!(complete(&mut interpreter, "3.ti").start, 2);
+        assert_eq!(candidates(&mut interpreter, "3.ti"), ["times"]);
+        assert_eq!(candidates(&mut interpreter, "[1, [2]].fir"), ["first"]);
+        assert_eq!(candidates(&mut interpreter, "'a.b'.upcas"), candidates(&mut interpreter, "name.upcas"));
+        assert_eq!(candidates(&mut interpreter, "Comparable.instance_meth"), ["instance_methods"]);
+        assert!(!candidates(&mut interpreter, "name.").iter().any(|method| method == "+"));
+    }
+
+    #[test]
+    fn should_not_run_code_to_find_a_receiver() {
+        let mut interpreter = Interpreter::new();
+        assert!(candidates(&mut interpreter, "exit.to").is_empty());
+        assert!(candidates(&mut interpreter, "Missing.to").is_empty());
+        assert!(candidates(&mut interpreter, "(1..2).to").is_empty());
+        assert!(candidates(&mut interpreter, "1..to").is_empty());
+        assert!(candidates(&mut interpreter, "Integer::sqrt.to").is_empty());
+        assert!(candidates(&mut interpreter, "ARGV[0].fir").is_empty());
+        assert!(candidates(&mut interpreter, "\"#{exit}\".up").is_empty());
+    }
+}
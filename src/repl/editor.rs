@@ -0,0 +1,611 @@
+use std::io::{ self, Read, Write };
+
+use super::completion::Completion;
+use super::history::History;
+
+/// A line read from the terminal.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Line(String),
+    /// Ctrl-C was pressed while reading
+    Interrupted,
+    /// Ctrl-D on an empty line, or the end of the input
+    End,
+}
+
+/// A key pressed, decoded from the bytes the terminal sends for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Home,
+    End,
+    Up,
+    Down,
+    KillToEnd,
+    KillToStart,
+    KillWord,
+    ClearScreen,
+    /// Ctrl-C
+    Interrupt,
+    /// Ctrl-D
+    EndOfInput,
+    Unknown,
+}
+
+/// Reads the next byte, or `None` at the end of the input.
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Reads the next key, understanding the Emacs-style control keys and the escape sequences
+/// xterm and the Linux console send for arrows, Home, End and Delete.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let byte = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let key = match byte {
+        1 => Key::Home,
+        2 => Key::Left,
+        3 => Key::Interrupt,
+        4 => Key::EndOfInput,
+        5 => Key::End,
+        6 => Key::Right,
+        8 | 127 => Key::Backspace,
+        9 => Key::Tab,
+        b'\n' | b'\r' => Key::Enter,
+        11 => Key::KillToEnd,
+        12 => Key::ClearScreen,
+        14 => Key::Down,
+        16 => Key::Up,
+        21 => Key::KillToStart,
+        23 => Key::KillWord,
+        27 => read_escape(input)?,
+        byte if byte < b' ' => Key::Unknown,
+        byte => read_char(input, byte)?,
+    };
+    Ok(Some(key))
+}
+
+/// The key for an escape sequence, whose `ESC` has been read.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    let key = match read_byte(input)? {
+        Some(b'b') => Key::WordLeft,
+        Some(b'f') => Key::WordRight,
+        Some(b'O') => match read_byte(input)? {
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown,
+        },
+        Some(b'[') => {
+            // parameters, then the final byte that names the sequence
+            let mut parameters = String::new();
+            let last = loop {
+                match read_byte(input)? {
+                    Some(byte @ 0x40..=0x7e) => break byte,
+                    Some(byte) => parameters.push(byte as char),
+                    None => return Ok(Key::Unknown),
+                }
+            };
+            match (parameters.as_str(), last) {
+                ("", b'A') => Key::Up,
+                ("", b'B') => Key::Down,
+                ("", b'C') => Key::Right,
+                ("", b'D') => Key::Left,
+                ("1;5", b'C') | ("1;3", b'C') => Key::WordRight,
+                ("1;5", b'D') | ("1;3", b'D') => Key::WordLeft,
+                ("", b'H') | ("1", b'~') | ("7", b'~') => Key::Home,
+                ("", b'F') | ("4", b'~') | ("8", b'~') => Key::End,
+                ("3", b'~') => Key::Delete,
+                _ => Key::Unknown,
+            }
+        },
+        _ => Key::Unknown,
+    };
+    Ok(key)
+}
+
+/// The char a UTF-8 sequence starting with `first` encodes.
+fn read_char(input: &mut impl Read, first: u8) -> io::Result<Key> {
+    let length = match first.leading_ones() {
+        0 => 1,
+        count @ 2..=4 => count as usize,
+        _ => return Ok(Key::Unknown),
+    };
+    let mut bytes = vec!(first);
+    while bytes.len() < length {
+        match read_byte(input)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(match std::str::from_utf8(&bytes).ok().and_then(|text| text.chars().next()) {
+        Some(c) => Key::Char(c),
+        None => Key::Unknown,
+    })
+}
+
+/// The line being edited, with the cursor counted in chars.
+#[derive(Debug, Default)]
+struct Line {
+    chars:  Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn before_cursor(&self) -> String {
+        self.chars[..self.cursor].iter().collect()
+    }
+
+    /// Replaces the text, with the cursor at its end.
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.chars.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() { self.chars.remove(self.cursor); }
+    }
+
+    /// Where the word before the cursor starts, skipping the spaces or punctuation after it.
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && !is_word(self.chars[start - 1]) { start -= 1; }
+        while start > 0 && is_word(self.chars[start - 1]) { start -= 1; }
+        start
+    }
+
+    /// Where the word after the cursor ends.
+    fn word_end(&self) -> usize {
+        let mut end = self.cursor;
+        while end < self.chars.len() && !is_word(self.chars[end]) { end += 1; }
+        while end < self.chars.len() && is_word(self.chars[end]) { end += 1; }
+        end
+    }
+
+    fn kill_word(&mut self) {
+        let start = self.word_start();
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn kill_to_start(&mut self) {
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    fn kill_to_end(&mut self) {
+        self.chars.truncate(self.cursor);
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Reads lines from the terminal with the cursor keys, Emacs-style editing keys, the history
+/// on Up and Down, and completion on Tab. Where standard input or output isn't a terminal,
+/// lines are read as they come instead.
+pub struct Editor {
+    history: History,
+}
+
+impl Editor {
+    pub fn new(history: History) -> Self {
+        Editor { history }
+    }
+
+    /// Shows the prompt and reads a line, which goes into the history. `complete` gives the
+    /// completions of the text before the cursor.
+    pub fn read_line(&mut self, prompt: &str, complete: &mut dyn FnMut(&str) -> Completion) -> io::Result<Input> {
+        let input = match terminal::RawMode::enable() {
+            Some(_raw_mode) => {
+                let stdin = io::stdin();
+                let stdout = io::stdout();
+                self.edit(&mut stdin.lock(), &mut stdout.lock(), prompt, terminal::width(), complete)
+            },
+            None => {
+                print!("{}", prompt);
+                io::stdout().flush()?;
+                read_plain_line()
+            },
+        }?;
+        if let Input::Line(line) = &input { self.history.add(line); }
+        Ok(input)
+    }
+
+    /// Edits a line on a terminal in raw mode `width` columns wide, until Enter, Ctrl-C or
+    /// Ctrl-D.
+    fn edit(
+        &mut self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        prompt: &str,
+        width: usize,
+        complete: &mut dyn FnMut(&str) -> Completion,
+    ) -> io::Result<Input> {
+        let mut line = Line::default();
+        // the history entry shown, and what was typed before going back through the history
+        let mut shown = self.history.len();
+        let mut typed = String::new();
+
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        loop {
+            let key = match read_key(input)? {
+                Some(key) => key,
+                None if line.chars.is_empty() => return Ok(Input::End),
+                None => return Ok(Input::Line(line.text())),
+            };
+            match key {
+                Key::Enter => {
+                    write!(output, "\r\n")?;
+                    output.flush()?;
+                    return Ok(Input::Line(line.text()));
+                },
+                Key::Interrupt => {
+                    write!(output, "^C")?;
+                    output.flush()?;
+                    return Ok(Input::Interrupted);
+                },
+                Key::EndOfInput if line.chars.is_empty() => return Ok(Input::End),
+                Key::EndOfInput | Key::Delete => line.delete(),
+                Key::Char(c) => line.insert(c.encode_utf8(&mut [0; 4])),
+                Key::Backspace => line.backspace(),
+                Key::Left => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                Key::WordLeft => line.cursor = line.word_start(),
+                Key::WordRight => line.cursor = line.word_end(),
+                Key::Home => line.cursor = 0,
+                Key::End => line.cursor = line.chars.len(),
+                Key::KillToEnd => line.kill_to_end(),
+                Key::KillToStart => line.kill_to_start(),
+                Key::KillWord => line.kill_word(),
+                Key::Up if shown > 0 => {
+                    if shown == self.history.len() { typed = line.text(); }
+                    shown -= 1;
+                    line.set(self.history.get(shown).unwrap_or_default());
+                },
+                Key::Down if shown < self.history.len() => {
+                    shown += 1;
+                    line.set(self.history.get(shown).unwrap_or(&typed));
+                },
+                Key::Tab => complete_word(&mut line, output, width, complete)?,
+                Key::ClearScreen => write!(output, "\x1b[H\x1b[2J")?,
+                Key::Up | Key::Down | Key::Unknown => {},
+            }
+            refresh(output, prompt, &line, width)?;
+        }
+    }
+}
+
+/// Completes the word before the cursor as far as all its completions agree, listing them
+/// when that adds nothing, or rings the bell if there are none.
+fn complete_word(line: &mut Line, output: &mut impl Write, width: usize, complete: &mut dyn FnMut(&str) -> Completion) -> io::Result<()> {
+    let before = line.before_cursor();
+    let Completion { start, candidates } = complete(&before);
+    let word: String = before.chars().skip(start).collect();
+    let common = match candidates.first() {
+        Some(first) => candidates.iter().fold(first.as_str(), |common, candidate| common_prefix(common, candidate)),
+        None => return write!(output, "\x07"),
+    };
+    if common.len() > word.len() {
+        line.insert(&common[word.len()..]);
+    } else if candidates.len() > 1 {
+        let column = candidates.iter().map(|candidate| candidate.chars().count()).max().unwrap_or(0) + 2;
+        let columns = (width / column).max(1);
+        write!(output, "\r\n")?;
+        for row in candidates.chunks(columns) {
+            let cells: Vec<String> = row.iter().map(|candidate| format!("{:1$}", candidate, column)).collect();
+            write!(output, "{}\r\n", cells.concat().trim_end())?;
+        }
+    }
+    Ok(())
+}
+
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let length = a.char_indices().zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or_else(|| a.len().min(b.len()), |((i, _), _)| i);
+    &a[..length]
+}
+
+/// Redraws the prompt and line, scrolling the line sideways when it's wider than the
+/// terminal so the cursor stays in view.
+fn refresh(output: &mut impl Write, prompt: &str, line: &Line, width: usize) -> io::Result<()> {
+    let prompt_width = prompt.chars().count();
+    let room = width.saturating_sub(prompt_width + 1).max(1);
+    let first = line.cursor.saturating_sub(room);
+    let last = (first + room).min(line.chars.len());
+    let visible: String = line.chars[first..last].iter().collect();
+
+    write!(output, "\r{}{}\x1b[K\r", prompt, visible)?;
+    let column = prompt_width + line.cursor - first;
+    if column > 0 { write!(output, "\x1b[{}C", column)?; }
+    output.flush()
+}
+
+/// Reads a line a byte at a time, so that Ctrl-C, which interrupts the read, is noticed.
+fn read_plain_line() -> io::Result<Input> {
+    let mut line = vec!();
+    let mut byte = [0];
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    loop {
+        match stdin.read(&mut byte) {
+            Ok(0) if line.is_empty() => return Ok(Input::End),
+            Ok(0) => break,
+            Ok(_) => {
+                line.push(byte[0]);
+                if byte[0] == b'\n' { break; }
+            },
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => return Ok(Input::Interrupted),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(Input::Line(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Raw mode through termios, where the layout of `struct termios` and the flag values are
+/// known: keys reach the editor as they're pressed, without echo, and Ctrl-C arrives as a
+/// byte rather than a signal.
+#[cfg(any(
+    all(target_os = "linux", any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64", target_arch = "arm")),
+    target_os = "macos",
+))]
+mod terminal {
+    use std::io::{ self, IsTerminal };
+    use std::mem;
+    use std::os::raw::{ c_int, c_ulong };
+
+    #[cfg(target_os = "linux")]
+    mod sys {
+        use std::os::raw::{ c_uint, c_ulong };
+
+        pub type Flags = c_uint;
+        pub const NCCS: usize = 32;
+        pub const ICRNL: Flags = 0o400;
+        pub const IXON: Flags = 0o2000;
+        pub const ISIG: Flags = 0o1;
+        pub const ICANON: Flags = 0o2;
+        pub const ECHO: Flags = 0o10;
+        pub const IEXTEN: Flags = 0o100000;
+        pub const VTIME: usize = 5;
+        pub const VMIN: usize = 6;
+        pub const TIOCGWINSZ: c_ulong = 0x5413;
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct Termios {
+            pub iflag:  Flags,
+            pub oflag:  Flags,
+            pub cflag:  Flags,
+            pub lflag:  Flags,
+            pub line:   u8,
+            pub cc:     [u8; NCCS],
+            pub ispeed: c_uint,
+            pub ospeed: c_uint,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use std::os::raw::c_ulong;
+
+        pub type Flags = c_ulong;
+        pub const NCCS: usize = 20;
+        pub const ICRNL: Flags = 0x100;
+        pub const IXON: Flags = 0x200;
+        pub const ISIG: Flags = 0x80;
+        pub const ICANON: Flags = 0x100;
+        pub const ECHO: Flags = 0x8;
+        pub const IEXTEN: Flags = 0x400;
+        pub const VMIN: usize = 16;
+        pub const VTIME: usize = 17;
+        pub const TIOCGWINSZ: c_ulong = 0x40087468;
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct Termios {
+            pub iflag:  Flags,
+            pub oflag:  Flags,
+            pub cflag:  Flags,
+            pub lflag:  Flags,
+            pub cc:     [u8; NCCS],
+            pub ispeed: c_ulong,
+            pub ospeed: c_ulong,
+        }
+    }
+
+    use sys::*;
+
+    const STDIN: c_int = 0;
+    const STDOUT: c_int = 1;
+    const TCSANOW: c_int = 0;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct WindowSize {
+        rows:    u16,
+        columns: u16,
+        x:       u16,
+        y:       u16,
+    }
+
+    extern "C" {
+        fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        fn tcsetattr(fd: c_int, actions: c_int, termios: *const Termios) -> c_int;
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    /// Keeps the terminal in raw mode, putting back the settings it had when dropped.
+    pub struct RawMode {
+        original: Termios,
+    }
+
+    impl RawMode {
+        /// Puts the terminal in raw mode, if standard input and output are both a terminal.
+        pub fn enable() -> Option<RawMode> {
+            if !io::stdin().is_terminal() || !io::stdout().is_terminal() { return None; }
+
+            // SAFETY: Termios is plain integers, for which zero is valid, and tcgetattr fills it in
+            let mut original: Termios = unsafe { mem::zeroed() };
+            if unsafe { tcgetattr(STDIN, &mut original) } != 0 { return None; }
+
+            let mut raw = original;
+            raw.iflag &= !(ICRNL | IXON);
+            raw.lflag &= !(ECHO | ICANON | ISIG | IEXTEN);
+            raw.cc[VMIN] = 1;
+            raw.cc[VTIME] = 0;
+            // SAFETY: raw is a valid Termios that outlives the call
+            if unsafe { tcsetattr(STDIN, TCSANOW, &raw) } != 0 { return None; }
+            Some(RawMode { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: the settings were read by tcgetattr
+            unsafe { tcsetattr(STDIN, TCSANOW, &self.original) };
+        }
+    }
+
+    /// How many columns the terminal has, 80 if it won't say.
+    pub fn width() -> usize {
+        let mut size = WindowSize::default();
+        // SAFETY: TIOCGWINSZ writes a struct winsize, which WindowSize lays out
+        match unsafe { ioctl(STDOUT, TIOCGWINSZ, &mut size as *mut WindowSize) } {
+            0 if size.columns > 0 => size.columns as usize,
+            _ => 80,
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64", target_arch = "arm")),
+    target_os = "macos",
+)))]
+mod terminal {
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<RawMode> {
+            None
+        }
+    }
+
+    pub fn width() -> usize {
+        80
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Edits a line from the bytes typed, completing from `words`, returning what was read
+    /// and what was drawn.
+    fn type_keys(entries: &[&str], words: &[&str], keys: &[u8]) -> (Input, String) {
+        let mut history = History::new();
+        for entry in entries {
+            history.add(entry);
+        }
+        let mut editor = Editor::new(history);
+        let mut complete = |text: &str| {
+            let start = text.rfind(|c| !is_word(c)).map_or(0, |i| i + 1);
+            let word = &text[start..];
+            let candidates = words.iter().filter(|candidate| candidate.starts_with(word)).map(|candidate| candidate.to_string()).collect();
+            Completion { start: text[..start].chars().count(), candidates }
+        };
+        let mut output = vec!();
+        let input = editor.edit(&mut &keys[..], &mut output, ">> ", 80, &mut complete).unwrap();
+        (input, String::from_utf8(output).unwrap())
+    }
+
+    fn line(text: &str) -> Input {
+        Input::Line(String::from(text))
+    }
+
+    #[test]
+    fn should_edit_the_line() {
+        assert_eq!(type_keys(&[], &[], b"1 + 2\r").0, line("1 + 2"));
+        assert_eq!(type_keys(&[], &[], b"12\x1b[D\x1b[D3\x05\x7f4\r").0, line("314"));
+        assert_eq!(type_keys(&[], &[], b"abc\x01\x1b[3~\x04x\r").0, line("xc"));
+        assert_eq!(type_keys(&[], &[], b"foo bar baz\x17\x17qux\r").0, line("foo qux"));
+        assert_eq!(type_keys(&[], &[], b"foo bar\x1bb\x0b\x1b[H\x1b[1;5C!\x15x\r").0, line("x "));
+        assert_eq!(type_keys(&[], &[], "héllo\x02\x02\x02\x7fe\r".as_bytes()).0, line("hello"));
+        assert_eq!(type_keys(&[], &[], b"x\x1b[Z\r").0, line("x"));
+    }
+
+    #[test]
+    fn should_stop_on_ctrl_c_and_ctrl_d() {
+        assert_eq!(type_keys(&[], &[], b"1 +\x03"), (Input::Interrupted, String::from(">> \r>> 1\x1b[K\r\x1b[4C\r>> 1 \x1b[K\r\x1b[5C\r>> 1 +\x1b[K\r\x1b[6C^C")));
+        assert_eq!(type_keys(&[], &[], b"\x04").0, Input::End);
+        assert_eq!(type_keys(&[], &[], b"").0, Input::End);
+        assert_eq!(type_keys(&[], &[], b"x").0, line("x"));
+    }
+
+    #[test]
+    fn should_go_through_the_history() {
+        let history = ["first", "second"];
+        assert_eq!(type_keys(&history, &[], b"\x1b[A\r").0, line("second"));
+        assert_eq!(type_keys(&history, &[], b"\x1b[A\x1b[A\x1b[A!\r").0, line("first!"));
+        assert_eq!(type_keys(&history, &[], b"typed\x10\x10\x0e\x0e\r").0, line("typed"));
+        assert_eq!(type_keys(&history, &[], b"\x1b[A\x1b[B\x1b[B\r").0, line(""));
+    }
+
+    #[test]
+    fn should_complete_the_word_before_the_cursor() {
+        let words = ["upcase", "upcase!", "upto", "reverse"];
+        assert_eq!(type_keys(&[], &words, b"s.rev\t\r").0, line("s.reverse"));
+        assert_eq!(type_keys(&[], &words, b"a u\tc\t\r").0, line("a upcase"));
+        assert_eq!(type_keys(&[], &words, b"x y\x01\tz\r").0, line("zx y"));
+
+        let (input, drawn) = type_keys(&[], &words, b"up\t\r");
+        assert_eq!(input, line("up"));
+        assert!(drawn.contains("\r\nupcase   upcase!  upto\r\n"));
+        assert!(type_keys(&[], &words, b"zz\t\r").1.contains('\x07'));
+    }
+
+    #[test]
+    fn should_scroll_a_long_line_to_keep_the_cursor_in_view() {
+        let keys = format!("{}\r", "x".repeat(100));
+        let (_, drawn) = type_keys(&[], &[], keys.as_bytes());
+        let last = drawn.rsplit("\r>> ").next().unwrap();
+        assert_eq!(last, format!("{}\x1b[K\r\x1b[79C\r\n", "x".repeat(76)));
+    }
+}
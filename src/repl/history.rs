@@ -0,0 +1,123 @@
+use std::env;
+use std::fs::{ self, OpenOptions };
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+
+/// How many lines the history keeps.
+const LIMIT: usize = 1000;
+
+/// The lines entered in the REPL, oldest first. With a file they outlast the session: each
+/// line is appended as it's entered, so sessions running side by side all keep theirs.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+    path:    Option<PathBuf>,
+}
+
+impl History {
+    /// A history kept only in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `$RRI_HISTORY`, or `.rri_history` in the home directory.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("RRI_HISTORY") { return Some(PathBuf::from(path)); }
+        env::var_os("HOME").map(|home| Path::new(&home).join(".rri_history"))
+    }
+
+    /// Reads the history kept in a file, which needn't exist yet. A file grown past `LIMIT`
+    /// lines is cut down to the last of them.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(text) => text.lines().map(String::from).collect(),
+            Err(_) => vec!(),
+        };
+        let mut history = History { entries, path: Some(path) };
+        if history.entries.len() > LIMIT {
+            history.entries.drain(..history.entries.len() - LIMIT);
+            // the history still works in memory if the file can't be written
+            let _ = history.rewrite();
+        }
+        history
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Adds an entered line, unless it's blank or repeats the line before.
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) { return; }
+
+        self.entries.push(line.to_string());
+        if self.entries.len() > LIMIT { self.entries.remove(0); }
+        if let Some(path) = &self.path {
+            let _ = OpenOptions::new().create(true).append(true).open(path).and_then(|mut file| writeln!(file, "{}", line));
+        }
+    }
+
+    fn rewrite(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut text = self.entries.join("\n");
+        text.push('\n');
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rri-history-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn should_keep_entered_lines_across_sessions() {
+        let path = scratch("sessions");
+        let mut history = History::load(path.clone());
+        assert!(history.is_empty());
+        for line in ["x = 1\n", "", "  ", "x + 1", "x + 1", "x"] {
+            history.add(line);
+        }
+        assert_eq!((0..history.len()).map(|i| history.get(i).unwrap()).collect::<Vec<_>>(), ["x = 1", "x + 1", "x"]);
+
+        let history = History::load(path.clone());
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(0), Some("x = 1"));
+        assert_eq!(history.get(3), None);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn should_keep_only_the_latest_lines() {
+        let path = scratch("limit");
+        let lines: Vec<String> = (0..LIMIT + 5).map(|i| i.to_string()).collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let mut history = History::load(path.clone());
+        assert_eq!(history.len(), LIMIT);
+        assert_eq!(history.get(0), Some("5"));
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), LIMIT);
+
+        history.add("next");
+        assert_eq!(history.len(), LIMIT);
+        assert_eq!(history.get(LIMIT - 1), Some("next"));
+        let _ = fs::remove_file(path);
+    }
+}